@@ -0,0 +1,312 @@
+// Benchmarks for the CPU and GPU cost of recording and submitting frames, run against a headless
+// device so no window is needed. Skipped when no adapter is available, e.g. on CI without a GPU.
+//
+//     cargo bench --bench frame
+
+use std::time::Duration;
+
+use cgmath::prelude::*;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use wgpu::util::DeviceExt;
+
+use learn_wgpu::{
+    camera::DepthMode,
+    model::{Instance, InstanceRaw, ModelVertex, Vertex},
+    pass,
+    renderer,
+    texture::Texture,
+};
+
+const SIZE:         u32                 = 512;
+const COLOR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+const DEPTH_MODE:   DepthMode           = DepthMode::ReverseZ;
+
+const SHADER: &str = r#"
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) normal: vec3<f32>,
+}
+
+@vertex
+fn vs_main(
+    @location(0) position: vec3<f32>,
+    @location(2) normal: vec3<f32>,
+    instance: InstanceInput,
+) -> VertexOutput {
+    let model = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+
+    var out: VertexOutput;
+
+    // Scaled down to keep every instance on screen without a camera
+    let world = model * vec4<f32>(position, 1.0);
+    out.clip_position = vec4<f32>(world.xy * 0.02, 0.5, 1.0);
+    out.normal        = normal;
+
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(in.normal * 0.5 + 0.5, 1.0);
+}
+"#;
+
+// A headless device with an offscreen target and a cube to draw
+struct Bench {
+    device:      wgpu::Device,
+    queue:       wgpu::Queue,
+    view:        wgpu::TextureView,
+    depth:       Texture,
+    pipeline:    wgpu::RenderPipeline,
+    vertices:    wgpu::Buffer,
+    indices:     wgpu::Buffer,
+    index_count: u32,
+}
+
+impl Bench {
+    fn new() -> Option<Self> {
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let adapter  = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference:       wgpu::PowerPreference::default(),
+            compatible_surface:     None,
+            force_fallback_adapter: false,
+        }))?;
+
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                features: wgpu::Features::empty(),
+                limits:   wgpu::Limits::default(),
+                label:    Some("Bench Device"),
+            },
+            None,
+        )).ok()?;
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label:           Some("Bench Target"),
+            size:            wgpu::Extent3d { width: SIZE, height: SIZE, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count:    1,
+            dimension:       wgpu::TextureDimension::D2,
+            format:          COLOR_FORMAT,
+            usage:           wgpu::TextureUsages::RENDER_ATTACHMENT,
+        });
+
+        let view  = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let depth = Texture::create_depth_texture(&device, SIZE, SIZE, "bench_depth_texture");
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Bench Shader"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Bench Pipeline Layout"),
+            bind_group_layouts:   &[],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = renderer::create_render_pipeline(
+            &device,
+            &pipeline_layout,
+            COLOR_FORMAT,
+            Some(renderer::depth_state(Texture::DEPTH_FORMAT, DEPTH_MODE.compare(), true)),
+            &[ModelVertex::desc(), InstanceRaw::layout()],
+            &shader,
+            "Bench Pipeline",
+        );
+
+        let (cube_vertices, cube_indices) = cube();
+
+        let vertices = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label:    Some("Bench Vertex Buffer"),
+            contents: bytemuck::cast_slice(&cube_vertices),
+            usage:    wgpu::BufferUsages::VERTEX,
+        });
+
+        let indices = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label:    Some("Bench Index Buffer"),
+            contents: bytemuck::cast_slice(&cube_indices),
+            usage:    wgpu::BufferUsages::INDEX,
+        });
+
+        Some(Self {
+            device,
+            queue,
+            view,
+            depth,
+            pipeline,
+            vertices,
+            indices,
+            index_count: cube_indices.len() as u32,
+        })
+    }
+
+    fn create_instance_buffer(&self, count: u32) -> wgpu::Buffer {
+        let per_row = (count as f32).sqrt().ceil() as u32;
+        let data    = (0..count)
+            .map(|i| Instance {
+                position: cgmath::Vector3::new((i % per_row) as f32 * 2.0 - per_row as f32, (i / per_row) as f32 * 2.0 - per_row as f32, 0.0),
+                rotation: cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_y(), cgmath::Deg(i as f32)),
+                scale:    cgmath::Vector3::new(1.0, 1.0, 1.0),
+            }.to_raw())
+            .collect::<Vec<_>>();
+
+        self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label:    Some("Bench Instance Buffer"),
+            contents: bytemuck::cast_slice(&data),
+            usage:    wgpu::BufferUsages::VERTEX,
+        })
+    }
+
+    // Records `draws` draw calls of `instances` instances each into one pass
+    fn encode(&self, instance_buffer: &wgpu::Buffer, draws: u32, instances: u32) -> wgpu::CommandBuffer {
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Bench Encoder"),
+        });
+
+        {
+            let mut render_pass = pass::begin_main_pass(&mut encoder, &self.view, &self.depth.view, pass::CLEAR_COLOR, DEPTH_MODE);
+
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_vertex_buffer(0, self.vertices.slice(..));
+            render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+            render_pass.set_index_buffer(self.indices.slice(..), wgpu::IndexFormat::Uint32);
+
+            for _ in 0..draws {
+                render_pass.draw_indexed(0..self.index_count, 0, 0..instances);
+            }
+        }
+
+        encoder.finish()
+    }
+
+    // Submits and blocks until the GPU is done, so the measurement covers the GPU work too
+    fn submit_and_wait(&self, commands: wgpu::CommandBuffer) {
+        self.queue.submit(std::iter::once(commands));
+        self.device.poll(wgpu::Maintain::Wait);
+    }
+}
+
+fn cube() -> (Vec<ModelVertex>, Vec<u32>) {
+    let faces = [
+        cgmath::Vector3::unit_x(),
+        -cgmath::Vector3::unit_x(),
+        cgmath::Vector3::unit_y(),
+        -cgmath::Vector3::unit_y(),
+        cgmath::Vector3::unit_z(),
+        -cgmath::Vector3::unit_z(),
+    ];
+
+    let mut vertices = Vec::new();
+    let mut indices  = Vec::new();
+
+    for normal in faces {
+        // Two axes spanning the face, ordered so its triangles wind counter-clockwise from outside
+        let u = cgmath::Vector3::new(normal.y, normal.z, normal.x);
+        let v = normal.cross(u);
+        let base = vertices.len() as u32;
+
+        for (s, t) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+            let position = normal + u * s + v * t;
+
+            vertices.push(ModelVertex {
+                position:        [position.x * 0.5, position.y * 0.5, position.z * 0.5],
+                tex_coords:      [(s + 1.0) * 0.5, (t + 1.0) * 0.5],
+                normal:          normal.into(),
+                color:           [1.0; 4],
+                lightmap_coords: [(s + 1.0) * 0.5, (t + 1.0) * 0.5],
+            });
+        }
+
+        indices.extend([base, base + 1, base + 2, base, base + 2, base + 3]);
+    }
+
+    (vertices, indices)
+}
+
+fn command_encoding(c: &mut Criterion, bench: &Bench) {
+    let instance_buffer = bench.create_instance_buffer(1);
+    let mut group       = c.benchmark_group("command_encoding");
+
+    for draws in [1, 100, 1_000, 10_000] {
+        group.throughput(Throughput::Elements(draws as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(draws), &draws, |b, &draws| {
+            b.iter(|| bench.encode(&instance_buffer, draws, 1));
+        });
+    }
+
+    group.finish();
+}
+
+fn buffer_upload(c: &mut Criterion, bench: &Bench) {
+    let mut group = c.benchmark_group("buffer_upload");
+
+    for size in [64 << 10, 1 << 20, 16 << 20] {
+        let buffer = bench.device.create_buffer(&wgpu::BufferDescriptor {
+            label:              Some("Bench Upload Buffer"),
+            size:               size as wgpu::BufferAddress,
+            usage:              wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let data = vec![0u8; size];
+
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, _| {
+            b.iter(|| {
+                bench.queue.write_buffer(&buffer, 0, &data);
+                bench.queue.submit(std::iter::empty());
+                bench.device.poll(wgpu::Maintain::Wait);
+            });
+        });
+    }
+
+    group.finish();
+}
+
+fn instanced_draw(c: &mut Criterion, bench: &Bench) {
+    let mut group = c.benchmark_group("instanced_draw");
+
+    for instances in [1, 100, 10_000, 100_000] {
+        let instance_buffer = bench.create_instance_buffer(instances);
+
+        group.throughput(Throughput::Elements(instances as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(instances), &instances, |b, &instances| {
+            b.iter(|| bench.submit_and_wait(bench.encode(&instance_buffer, 1, instances)));
+        });
+    }
+
+    group.finish();
+}
+
+fn frame_benches(c: &mut Criterion) {
+    let bench = match Bench::new() {
+        Some(bench) => bench,
+        None => {
+            eprintln!("No GPU adapter available, skipping frame benchmarks");
+            return;
+        }
+    };
+
+    command_encoding(c, &bench);
+    buffer_upload(c, &bench);
+    instanced_draw(c, &bench);
+}
+
+criterion_group! {
+    name    = benches;
+    config  = Criterion::default().measurement_time(Duration::from_secs(5));
+    targets = frame_benches
+}
+criterion_main!(benches);
@@ -0,0 +1,113 @@
+// Shows what the default audio input hears, its bands as bars with a spectrogram above them. Run
+// with `--features audio`, and on a loopback or monitor device to see music that's playing.
+//
+// Up and Down scale the input's gain and Left and Right how fast the bands fall back.
+
+use std::time::Duration;
+
+use learn_wgpu::{
+    app::SetupFuture,
+    audio::{self, AudioInput},
+    input::Input,
+    pass::{Drawable, RenderLayer, RenderLayers},
+    renderer::{self, Frame, GpuContext},
+    run_app,
+    App,
+};
+use winit::event::*;
+
+const TWEAK_STEP: f32 = 1.25;
+
+struct Spectrum {
+    audio:    AudioInput,
+    pipeline: wgpu::RenderPipeline,
+    // Since the last frame, which the bands fall back by
+    dt:       Duration,
+}
+
+impl Spectrum {
+    fn new(ctx: &GpuContext) -> anyhow::Result<Self> {
+        let audio  = AudioInput::new(ctx)?;
+        let device = &ctx.device;
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Spectrum Shader"),
+            source: wgpu::ShaderSource::Wgsl((audio::shader_header(0) + include_str!("audio_visualizer.wgsl")).into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Spectrum Pipeline Layout"),
+            bind_group_layouts:   &[&audio.layout],
+            push_constant_ranges: &[],
+        });
+
+        // The UI layer has no depth attachment
+        let pipeline = renderer::create_render_pipeline(
+            device,
+            &pipeline_layout,
+            ctx.config.format,
+            None,
+            &[],
+            &shader,
+            "Spectrum Pipeline",
+        );
+
+        Ok(Self {
+            audio,
+            pipeline,
+            dt: Duration::ZERO,
+        })
+    }
+}
+
+impl Drawable for Spectrum {
+    fn draw<'a>(&'a self, _layer: RenderLayer, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.audio.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+impl App for Spectrum {
+    fn setup(ctx: &mut GpuContext) -> SetupFuture<'_, Self> {
+        Box::pin(async move { Self::new(ctx).expect("Couldn't capture audio") })
+    }
+
+    fn input(&mut self, _ctx: &GpuContext, event: &WindowEvent) -> bool {
+        if let WindowEvent::KeyboardInput {
+            input: KeyboardInput {
+                state: ElementState::Pressed,
+                virtual_keycode: Some(keycode),
+                ..
+            },
+            ..
+        } = event {
+            match keycode {
+                VirtualKeyCode::Up    => self.audio.gain *= TWEAK_STEP,
+                VirtualKeyCode::Down  => self.audio.gain /= TWEAK_STEP,
+                VirtualKeyCode::Right => self.audio.release *= TWEAK_STEP,
+                VirtualKeyCode::Left  => self.audio.release /= TWEAK_STEP,
+                _ => return false,
+            }
+
+            log::info!("Gain {:.2}, release {:.2} a second", self.audio.gain, self.audio.release);
+
+            return true;
+        }
+
+        false
+    }
+
+    fn update(&mut self, dt: Duration, _input: &Input) {
+        self.dt = dt;
+    }
+
+    fn render<'a>(&'a mut self, frame: &mut Frame, layers: &mut RenderLayers<'a>) {
+        self.audio.update(&frame.ctx.queue, self.dt);
+        layers.add(RenderLayer::Ui, &*self);
+    }
+}
+
+fn main() {
+    pollster::block_on(run_app::<Spectrum>());
+}
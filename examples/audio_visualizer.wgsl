@@ -0,0 +1,52 @@
+// The audio input's bands as bars along the bottom of the screen and a spectrogram scrolling up
+// above them, over a background that pulses with the bass. Follows `audio::shader_header`.
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // From the bottom left corner at 0.0 to the top right at 1.0
+    @location(0) uv:                  vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // One triangle covering the screen
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+    out.uv            = uv;
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+
+    return out;
+}
+
+// Low bands red through to high bands blue
+fn band_color(t: f32) -> vec3<f32> {
+    return clamp(vec3<f32>(1.5 - t * 2.0, 1.0 - abs(t - 0.5) * 2.0, t * 2.0 - 0.5), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let count = 64u;
+    let band  = min(u32(in.uv.x * f32(count)), count - 1u);
+    let t     = f32(band) / f32(count - 1u);
+
+    var color = vec3<f32>(0.02, 0.02, 0.05) * (1.0 + audio.bass * 6.0);
+
+    if (in.uv.y < 0.4) {
+        // Bars with a gap between them
+        let height = audio_band(band) * 0.4;
+        let gap    = fract(in.uv.x * f32(count)) > 0.85;
+
+        if (in.uv.y < height && !gap) {
+            color = band_color(t) * (0.4 + in.uv.y / 0.4 * 0.6);
+        }
+    } else {
+        // Newest at the bottom, oldest at the top
+        let rows = u32(textureDimensions(audio_history).y);
+        let ago  = u32((in.uv.y - 0.4) / 0.6 * f32(rows));
+
+        color += band_color(t) * audio_band_history(band, min(ago, rows - 1u));
+    }
+
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,137 @@
+// Filters an image on the GPU and shows it beside the original. Pass the image's path, or the
+// cube's texture is used.
+//
+// 1 to 5 add or remove a blur, sharpening, Sobel edges, grayscale and a green chroma key, which
+// run in the order they were added and Backspace clears. Dragging with the left mouse button
+// moves the split between the original on the left and the result on the right, and S saves the
+// result to filtered.png.
+
+use std::time::Duration;
+
+use learn_wgpu::{
+    app::SetupFuture,
+    image_filters::{ImageFilter, ImageFilters, SplitView},
+    input::Input,
+    pass::{RenderLayer, RenderLayers},
+    renderer::{Frame, GpuContext},
+    run_app,
+    texture::Texture,
+    App,
+};
+use winit::event::*;
+
+const DEFAULT_IMAGE: &str = "res/cube-diffuse.jpg";
+const OUTPUT_FILE:   &str = "filtered.png";
+
+struct ImageFilterApp {
+    original: Texture,
+    filters:  ImageFilters,
+    view:     SplitView,
+    chain:    Vec<ImageFilter>,
+    // The chain has changed since it last ran
+    dirty:    bool,
+    // Where the cursor is across the window while the split is dragged
+    drag:     Option<f32>,
+}
+
+impl ImageFilterApp {
+    fn new(ctx: &GpuContext) -> anyhow::Result<Self> {
+        let path  = std::env::args().nth(1).unwrap_or_else(|| DEFAULT_IMAGE.to_string());
+        let image = image::open(&path)?;
+
+        log::info!("Filtering {} ({}x{})", path, image.width(), image.height());
+
+        Ok(Self {
+            original: Texture::from_image(&ctx.device, &ctx.queue, &image, Some("Original Image"))?,
+            filters:  ImageFilters::new(ctx, image.width(), image.height()),
+            view:     SplitView::new(ctx),
+            chain:    Vec::new(),
+            dirty:    true,
+            drag:     None,
+        })
+    }
+
+    // Adds `filter` to the end of the chain, or takes out the one of its kind already in it
+    fn toggle(&mut self, filter: ImageFilter) {
+        let kind = std::mem::discriminant(&filter);
+
+        match self.chain.iter().position(|f| std::mem::discriminant(f) == kind) {
+            Some(i) => { self.chain.remove(i); }
+            None    => self.chain.push(filter),
+        }
+
+        log::info!("Filters: {:?}", self.chain);
+        self.dirty = true;
+    }
+
+    fn save(&self, ctx: &GpuContext) -> anyhow::Result<()> {
+        self.filters.read_image(ctx)?.save(OUTPUT_FILE)?;
+        Ok(())
+    }
+}
+
+impl App for ImageFilterApp {
+    fn setup(ctx: &mut GpuContext) -> SetupFuture<'_, Self> {
+        Box::pin(async move { Self::new(ctx).expect("Couldn't load the image") })
+    }
+
+    fn input(&mut self, ctx: &GpuContext, event: &WindowEvent) -> bool {
+        if let WindowEvent::KeyboardInput {
+            input: KeyboardInput {
+                state: ElementState::Pressed,
+                virtual_keycode: Some(keycode),
+                ..
+            },
+            ..
+        } = event {
+            match keycode {
+                VirtualKeyCode::Key1 => self.toggle(ImageFilter::GaussianBlur { sigma: 4.0 }),
+                VirtualKeyCode::Key2 => self.toggle(ImageFilter::Sharpen { amount: 1.0 }),
+                VirtualKeyCode::Key3 => self.toggle(ImageFilter::Sobel { amount: 1.0 }),
+                VirtualKeyCode::Key4 => self.toggle(ImageFilter::Grayscale { amount: 1.0 }),
+                VirtualKeyCode::Key5 => self.toggle(ImageFilter::ChromaKey { key: [0.0, 1.0, 0.0], threshold: 0.1, softness: 0.1 }),
+                VirtualKeyCode::Back => {
+                    self.chain.clear();
+                    self.dirty = true;
+                }
+                VirtualKeyCode::S => match self.save(ctx) {
+                    Ok(()) => log::info!("Saved {}", OUTPUT_FILE),
+                    Err(e) => log::error!("Couldn't save {}: {}", OUTPUT_FILE, e),
+                },
+                _ => return false,
+            }
+
+            return true;
+        }
+
+        false
+    }
+
+    fn update(&mut self, _dt: Duration, input: &Input) {
+        self.drag = input.is_button_held(MouseButton::Left).then(|| input.cursor_position().x as f32);
+    }
+
+    fn render<'a>(&'a mut self, frame: &mut Frame, layers: &mut RenderLayers<'a>) {
+        if let Some(x) = self.drag {
+            // The image is fitted to the window the same way the view's shader does it
+            let (width, height) = self.filters.size();
+            let screen          = (frame.ctx.size.width as f32, frame.ctx.size.height as f32);
+            let scale           = (screen.0 / width as f32).min(screen.1 / height as f32);
+            let left            = (screen.0 - width as f32 * scale) * 0.5;
+
+            self.view.split = (x - left) / (width as f32 * scale);
+        }
+
+        if self.dirty {
+            self.filters.run(frame.ctx, &mut frame.encoder, &self.original.view, &self.chain);
+            self.dirty = false;
+        }
+
+        self.view.prepare(frame.ctx, &self.original.view, &self.filters);
+        layers.add(RenderLayer::Ui, &self.view);
+    }
+}
+
+fn main() {
+    pollster::block_on(run_app::<ImageFilterApp>());
+}
@@ -0,0 +1,119 @@
+// A plasma pattern played like an instrument, its speed, zoom, colors and warping driven from a
+// MIDI controller or OSC. Pass a mapping file, or examples/live_control.txt is used, and run with
+// `--features live_control`. Control messages that come in are logged at debug level, which helps
+// when writing a mapping for a new controller.
+
+use std::time::Duration;
+
+use learn_wgpu::{
+    app::SetupFuture,
+    bind_group,
+    input::Input,
+    live_control::{ControlMapping, LiveControl},
+    pass::{Drawable, RenderLayer, RenderLayers},
+    renderer::{self, Frame, GpuContext},
+    run_app,
+    App,
+};
+use wgpu::util::DeviceExt;
+
+const DEFAULT_MAPPING: &str = "examples/live_control.txt";
+
+struct LiveControlApp {
+    control:    LiveControl,
+    pipeline:   wgpu::RenderPipeline,
+    pattern:    wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    // Advanced by the speed parameter each frame, rather than in the shader, so changing speed
+    // doesn't make the pattern jump
+    time:       f32,
+    dt:         Duration,
+}
+
+impl LiveControlApp {
+    fn new(ctx: &GpuContext) -> anyhow::Result<Self> {
+        let path        = std::env::args().nth(1).unwrap_or_else(|| DEFAULT_MAPPING.to_string());
+        let mut control = LiveControl::new(ctx, ControlMapping::from_str(&std::fs::read_to_string(&path)?)?)?;
+        let device      = &ctx.device;
+
+        // Visible before anything's been touched
+        control.set("brightness", 1.0);
+        control.set("speed", 1.0);
+
+        let pattern = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label:    Some("Pattern Buffer"),
+            contents: bytemuck::cast_slice(&[0.0f32; 4]),
+            usage:    wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT)
+            .build(device, "pattern_bind_group_layout");
+
+        let bind_group = bind_group::BindGroupBuilder::new(&layout)
+            .uniform(&pattern)
+            .build(device, "pattern_bind_group");
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Pattern Shader"),
+            source: wgpu::ShaderSource::Wgsl((control.shader_header(0) + include_str!("live_control.wgsl")).into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pattern Pipeline Layout"),
+            bind_group_layouts:   &[&control.layout, &layout],
+            push_constant_ranges: &[],
+        });
+
+        // The UI layer has no depth attachment
+        let pipeline = renderer::create_render_pipeline(
+            device,
+            &pipeline_layout,
+            ctx.config.format,
+            None,
+            &[],
+            &shader,
+            "Pattern Pipeline",
+        );
+
+        Ok(Self {
+            control,
+            pipeline,
+            pattern,
+            bind_group,
+            time: 0.0,
+            dt:   Duration::ZERO,
+        })
+    }
+}
+
+impl Drawable for LiveControlApp {
+    fn draw<'a>(&'a self, _layer: RenderLayer, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.control.bind_group, &[]);
+        render_pass.set_bind_group(1, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+impl App for LiveControlApp {
+    fn setup(ctx: &mut GpuContext) -> SetupFuture<'_, Self> {
+        Box::pin(async move { Self::new(ctx).expect("Couldn't set up live control") })
+    }
+
+    fn update(&mut self, dt: Duration, _input: &Input) {
+        self.dt = dt;
+    }
+
+    fn render<'a>(&'a mut self, frame: &mut Frame, layers: &mut RenderLayers<'a>) {
+        self.control.update(&frame.ctx.queue, self.dt);
+        self.time += self.dt.as_secs_f32() * self.control.value("speed").unwrap_or(1.0);
+
+        frame.ctx.queue.write_buffer(&self.pattern, 0, bytemuck::cast_slice(&[self.time, frame.ctx.aspect(), 0.0, 0.0]));
+        layers.add(RenderLayer::Ui, &*self);
+    }
+}
+
+fn main() {
+    pollster::block_on(run_app::<LiveControlApp>());
+}
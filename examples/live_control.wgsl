@@ -0,0 +1,44 @@
+// A plasma pattern whose look comes from live controls, through the `param_*()` helpers
+// `LiveControl::shader_header` puts before this
+
+struct Pattern {
+    // Seconds, sped up or slowed down by the speed parameter
+    time:   f32,
+    aspect: f32,
+}
+
+@group(1) @binding(0)
+var<uniform> pattern: Pattern;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv:                  vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // One triangle covering the screen
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+    out.uv            = (uv * 2.0 - 1.0) * vec2<f32>(pattern.aspect, 1.0);
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let t = pattern.time;
+    var p = in.uv * param_zoom();
+
+    // Bends the pattern around itself
+    p += param_warp() * vec2<f32>(sin(p.y + t), cos(p.x - t));
+
+    let v = sin(p.x + t) + sin(p.y * 1.3 - t) + sin(length(p) * 1.7 + t * 0.5);
+    let h = param_hue() + v * 0.15;
+
+    let color = 0.5 + 0.5 * cos(6.28318 * (vec3<f32>(h) + vec3<f32>(0.0, 0.33, 0.67)));
+
+    return vec4<f32>(color * param_brightness(), 1.0);
+}
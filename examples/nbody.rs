@@ -0,0 +1,162 @@
+// Two galaxies colliding, every star pulled by every other on the GPU. Logs how many pulls a
+// second it manages, as a measure of the GPU's compute throughput.
+//
+// Dragging with the left mouse button orbits the camera and scrolling zooms. Space pauses and R
+// starts over. Up and Down scale gravity, Left and Right the time step, and PageUp and PageDown
+// the steps a frame. 1 to 4 start over with 4096, 16384, 32768 or 65536 bodies.
+
+use std::time::Duration;
+
+use cgmath::prelude::*;
+use learn_wgpu::{
+    app::SetupFuture,
+    camera::Camera,
+    input::Input,
+    nbody::{NBody, NBodySettings},
+    pass::{RenderLayer, RenderLayers},
+    renderer::{Frame, GpuContext},
+    run_app,
+    App,
+};
+use winit::event::*;
+
+const ORBIT_SENSITIVITY: f32 = 0.005; // radians per pixel
+const ZOOM_STEP:         f32 = 0.9;
+const TWEAK_STEP:        f32 = 1.25;
+
+struct NBodyApp {
+    nbody:    NBody,
+    camera:   Camera,
+    // Of the camera around the middle of the galaxies
+    yaw:      f32,
+    pitch:    f32,
+    distance: f32,
+    paused:   bool,
+    steps:    u32,
+    // Steps and time since throughput was last logged
+    counted:  u64,
+    elapsed:  f32,
+}
+
+impl NBodyApp {
+    fn new(ctx: &GpuContext) -> Self {
+        Self {
+            nbody:    NBody::new(ctx, NBodySettings::default()),
+            camera:   Camera {
+                eye:        cgmath::Point3::new(0.0, 0.0, 1.0),
+                target:     cgmath::Point3::origin(),
+                up:         cgmath::Vector3::unit_y(),
+                aspect:     ctx.aspect(),
+                fovy:       45.0,
+                znear:      0.5,
+                zfar:       2000.0,
+                depth_mode: ctx.depth_mode,
+            },
+            yaw:      0.0,
+            pitch:    0.6,
+            distance: 220.0,
+            paused:   false,
+            steps:    1,
+            counted:  0,
+            elapsed:  0.0,
+        }
+    }
+
+    fn restart(&mut self, ctx: &GpuContext, count: u32) {
+        self.nbody = NBody::new(ctx, NBodySettings { count, ..self.nbody.settings });
+        log::info!("{} bodies", count);
+    }
+}
+
+impl App for NBodyApp {
+    fn setup(ctx: &mut GpuContext) -> SetupFuture<'_, Self> {
+        Box::pin(async move { Self::new(ctx) })
+    }
+
+    fn input(&mut self, ctx: &GpuContext, event: &WindowEvent) -> bool {
+        if let WindowEvent::KeyboardInput {
+            input: KeyboardInput {
+                state: ElementState::Pressed,
+                virtual_keycode: Some(keycode),
+                ..
+            },
+            ..
+        } = event {
+            match keycode {
+                VirtualKeyCode::Up       => self.nbody.settings.gravity *= TWEAK_STEP,
+                VirtualKeyCode::Down     => self.nbody.settings.gravity /= TWEAK_STEP,
+                VirtualKeyCode::Right    => self.nbody.settings.time_step *= TWEAK_STEP,
+                VirtualKeyCode::Left     => self.nbody.settings.time_step /= TWEAK_STEP,
+                VirtualKeyCode::Space    => self.paused = !self.paused,
+                VirtualKeyCode::R        => self.nbody.reset(&ctx.queue),
+                VirtualKeyCode::PageUp   => self.steps += 1,
+                VirtualKeyCode::PageDown => self.steps = (self.steps - 1).max(1),
+                VirtualKeyCode::Key1     => self.restart(ctx, 4096),
+                VirtualKeyCode::Key2     => self.restart(ctx, 16384),
+                VirtualKeyCode::Key3     => self.restart(ctx, 32768),
+                VirtualKeyCode::Key4     => self.restart(ctx, 65536),
+                _ => return false,
+            }
+
+            let settings = &self.nbody.settings;
+
+            log::info!(
+                "Gravity {:.3}, time step {:.4}, {} steps a frame{}",
+                settings.gravity,
+                settings.time_step,
+                self.steps,
+                if self.paused { ", paused" } else { "" },
+            );
+
+            return true;
+        }
+
+        false
+    }
+
+    fn resize(&mut self, ctx: &GpuContext) {
+        self.camera.aspect = ctx.aspect();
+    }
+
+    fn update(&mut self, dt: Duration, input: &Input) {
+        if input.is_button_held(MouseButton::Left) {
+            let (dx, dy) = input.cursor_delta();
+
+            self.yaw  -= dx as f32 * ORBIT_SENSITIVITY;
+            self.pitch = (self.pitch + dy as f32 * ORBIT_SENSITIVITY).clamp(-1.5, 1.5);
+        }
+
+        self.distance *= ZOOM_STEP.powf(input.scroll_delta());
+
+        let offset = cgmath::Vector3::new(self.yaw.sin() * self.pitch.cos(), self.pitch.sin(), self.yaw.cos() * self.pitch.cos());
+        self.camera.eye = self.camera.target + offset * self.distance;
+
+        if self.paused {
+            return;
+        }
+
+        self.counted += self.steps as u64;
+        self.elapsed += dt.as_secs_f32();
+
+        if self.elapsed >= 1.0 {
+            let count = self.nbody.count() as f64;
+
+            log::info!("{:.2} billion interactions a second", self.counted as f64 * count * count / self.elapsed as f64 / 1e9);
+            self.counted = 0;
+            self.elapsed = 0.0;
+        }
+    }
+
+    fn render<'a>(&'a mut self, frame: &mut Frame, layers: &mut RenderLayers<'a>) {
+        if !self.paused {
+            frame.compute("N-Body Step", |ctx, encoder| self.nbody.step(ctx, encoder, self.steps));
+        }
+
+        self.nbody.prepare(&frame.ctx.queue, &self.camera);
+        layers.add(RenderLayer::WorldTransparent, &self.nbody);
+    }
+}
+
+fn main() {
+    pollster::block_on(run_app::<NBodyApp>());
+}
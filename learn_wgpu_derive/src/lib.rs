@@ -0,0 +1,361 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, spanned::Spanned, Data, DeriveInput, Expr, Fields, Lit, Type};
+
+// Generates `ATTRIBUTES` and `layout()` for a `#[repr(C)]` vertex struct.
+//
+// Each field tagged `#[location(n)]` becomes a vertex attribute whose format is inferred from the
+// field type. Matrices (`[[f32; 4]; 4]`) take one location per column. Add `#[vertex(instance)]`
+// to the struct to step the buffer per instance.
+//
+// Packed fields name their format with `#[format(..)]`, e.g. `#[format(Unorm8x4)] color: [u8; 4]`
+// or `#[format(Float16x2)] uv: [u16; 2]`, which has to store the same number of components of
+// the same size as the field.
+#[proc_macro_derive(VertexLayout, attributes(location, vertex, format))]
+pub fn derive_vertex_layout(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e)     => e.to_compile_error().into(),
+    }
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name          = &input.ident;
+    let mut step_mode = quote!(::wgpu::VertexStepMode::Vertex);
+
+    for attr in &input.attrs {
+        if attr.path.is_ident("vertex") {
+            let mode: syn::Ident = attr.parse_args()?;
+
+            step_mode = match mode.to_string().as_str() {
+                "vertex"   => quote!(::wgpu::VertexStepMode::Vertex),
+                "instance" => quote!(::wgpu::VertexStepMode::Instance),
+                _          => return Err(syn::Error::new(mode.span(), "expected `vertex` or `instance`")),
+            };
+        }
+    }
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(syn::Error::new(input.span(), "VertexLayout requires named fields")),
+        },
+        _ => return Err(syn::Error::new(input.span(), "VertexLayout can only be derived for structs")),
+    };
+
+    let mut attributes = Vec::new();
+    let mut offset     = quote!(0usize);
+
+    for field in fields {
+        let ty = &field.ty;
+
+        let mut location = None;
+        let mut packed   = None;
+
+        for attr in &field.attrs {
+            if attr.path.is_ident("location") {
+                let lit: syn::LitInt = attr.parse_args()?;
+                location = Some(lit.base10_parse::<u32>()?);
+            } else if attr.path.is_ident("format") {
+                packed = Some(attr.parse_args::<syn::Ident>()?);
+            }
+        }
+
+        if let Some(location) = location {
+            let (format, columns) = match packed {
+                Some(format) => (packed_format(format, ty)?, 1),
+                None         => vertex_format(ty)?,
+            };
+
+            for column in 0..columns {
+                let shader_location = location + column;
+
+                attributes.push(quote! {
+                    ::wgpu::VertexAttribute {
+                        offset:          (#offset + #column as usize * (::std::mem::size_of::<#ty>() / #columns as usize)) as ::wgpu::BufferAddress,
+                        shader_location: #shader_location,
+                        format:          ::wgpu::VertexFormat::#format,
+                    }
+                });
+            }
+        }
+
+        offset = quote!(#offset + ::std::mem::size_of::<#ty>());
+    }
+
+    let count = attributes.len();
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics #name #ty_generics #where_clause {
+            pub const ATTRIBUTES: [::wgpu::VertexAttribute; #count] = [
+                #(#attributes),*
+            ];
+
+            pub fn layout<'a>() -> ::wgpu::VertexBufferLayout<'a> {
+                ::wgpu::VertexBufferLayout {
+                    array_stride: ::std::mem::size_of::<Self>() as ::wgpu::BufferAddress,
+                    step_mode:    #step_mode,
+                    attributes:   &Self::ATTRIBUTES,
+                }
+            }
+        }
+    })
+}
+
+// Implements `crate::uniform::Uniform` for a `#[repr(C)]` struct shared with WGSL, after checking
+// at compile time that every field sits where WGSL's layout rules put it and that the struct is
+// padded out to its alignment. Only works inside the crate defining that trait.
+//
+// The rules are those of the uniform address space by default, where arrays and nested structs
+// are aligned to 16 bytes. `#[uniform(storage)]` on the struct relaxes them to the storage
+// address space's. Fields whose names start with an underscore are padding that WGSL never
+// sees, so they're only counted towards the offsets.
+#[proc_macro_derive(Uniform, attributes(uniform))]
+pub fn derive_uniform(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match expand_uniform(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e)     => e.to_compile_error().into(),
+    }
+}
+
+fn expand_uniform(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name        = &input.ident;
+    let mut storage = false;
+
+    for attr in &input.attrs {
+        if attr.path.is_ident("uniform") {
+            let space: syn::Ident = attr.parse_args()?;
+
+            storage = match space.to_string().as_str() {
+                "uniform" => false,
+                "storage" => true,
+                _         => return Err(syn::Error::new(space.span(), "expected `uniform` or `storage`")),
+            };
+        }
+    }
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(syn::Error::new(input.span(), "Uniform requires named fields")),
+        },
+        _ => return Err(syn::Error::new(input.span(), "Uniform can only be derived for structs")),
+    };
+
+    let mut checks = Vec::new();
+    let mut aligns = Vec::new();
+    let mut offset = quote!(0usize);
+
+    for field in fields {
+        let ty    = &field.ty;
+        let ident = field.ident.as_ref().unwrap();
+
+        if !ident.to_string().starts_with('_') {
+            let align   = wgsl_align(ty, storage)?;
+            let message = format!("`{}.{}` isn't where WGSL expects it, pad the fields before it", name, ident);
+
+            checks.push(quote! {
+                assert!((#offset) % (#align) == 0, #message);
+            });
+            aligns.push(align);
+        }
+
+        offset = quote!(#offset + ::std::mem::size_of::<#ty>());
+    }
+
+    let message = format!("`{}` has to be padded out to a multiple of its alignment", name);
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics crate::uniform::Uniform for #name #ty_generics #where_clause {
+            const ALIGN: usize = {
+                let mut align = 4usize;
+                #(
+                    if #aligns > align {
+                        align = #aligns;
+                    }
+                )*
+                align
+            };
+        }
+
+        const _: () = {
+            #(#checks)*
+            assert!(::std::mem::size_of::<#name>() % <#name as crate::uniform::Uniform>::ALIGN == 0, #message);
+        };
+    })
+}
+
+// The alignment WGSL gives a field, as an expression since nested structs only know theirs once
+// they're compiled
+fn wgsl_align(ty: &Type, storage: bool) -> syn::Result<TokenStream2> {
+    // Arrays and structs nested in uniforms start on 16 bytes
+    let nested = |align: TokenStream2| if storage {
+        align
+    } else {
+        quote! {{
+            let align = #align;
+            if align > 16 { align } else { 16 }
+        }}
+    };
+
+    match ty {
+        Type::Array(array) => {
+            // Only needed to tell vectors and small matrices apart, so constants are fine elsewhere
+            let len = array_len(&array.len).ok();
+
+            match &*array.elem {
+                // Matrices are columns of vectors, aligned like one column. Three component
+                // columns take 16 bytes in WGSL but 12 in Rust, so there's no way to match them.
+                Type::Array(column) => {
+                    let rows = array_len(&column.len)?;
+
+                    if !is_wgsl_scalar(&column.elem) {
+                        return Err(syn::Error::new(ty.span(), "only arrays of scalars can be nested"));
+                    }
+
+                    match (len, rows) {
+                        (_, 3)            => Err(syn::Error::new(ty.span(), "vec3 columns are 16 bytes in WGSL, use [[f32; 4]; N] and ignore w")),
+                        (Some(2..=4), 2)  => Ok(quote!(8usize)),
+                        (_, 4)            => Ok(quote!(16usize)),
+                        (_, 2) if storage => Ok(quote!(8usize)),
+                        _                 => Err(syn::Error::new(ty.span(), "array elements in uniforms have to be 16 bytes apart")),
+                    }
+                }
+                elem => match (is_wgsl_scalar(elem), len) {
+                    (true, Some(2))      => Ok(quote!(8usize)),
+                    (true, Some(3 | 4))  => Ok(quote!(16usize)),
+                    (true, _) if storage => Ok(quote!(4usize)),
+                    (true, _)            => Err(syn::Error::new(ty.span(), "array elements in uniforms have to be 16 bytes apart, use [[T; 4]; N]")),
+                    (false, _)           => {
+                        let stride = format!("`{}` has to be padded out to 16 bytes to be an array element", quote!(#elem));
+
+                        Ok(nested(quote! {{
+                            assert!(#storage || ::std::mem::size_of::<#elem>() % 16 == 0, #stride);
+                            <#elem as crate::uniform::Uniform>::ALIGN
+                        }}))
+                    }
+                },
+            }
+        }
+        _ if is_wgsl_scalar(ty) => Ok(quote!(4usize)),
+        _                       => Ok(nested(quote!(<#ty as crate::uniform::Uniform>::ALIGN))),
+    }
+}
+
+// The 32 bit types WGSL has, the only scalars it can share
+fn is_wgsl_scalar(ty: &Type) -> bool {
+    if let Type::Path(path) = ty {
+        if let Some(ident) = path.path.get_ident() {
+            return matches!(ident.to_string().as_str(), "f32" | "u32" | "i32");
+        }
+    }
+
+    false
+}
+
+// Returns the attribute format for a field and how many consecutive locations it occupies
+fn vertex_format(ty: &Type) -> syn::Result<(syn::Ident, u32)> {
+    match ty {
+        Type::Array(array) => {
+            let len = array_len(&array.len)?;
+
+            if let Type::Array(_) = &*array.elem {
+                // Matrix: one attribute per column
+                let (column, nested) = vertex_format(&array.elem)?;
+
+                if nested != 1 {
+                    return Err(syn::Error::new(ty.span(), "arrays nested more than two deep aren't supported"));
+                }
+
+                return Ok((column, len));
+            }
+
+            let (scalar, bits) = scalar_format(&array.elem)?;
+
+            match (len, bits) {
+                (2 | 4, _) | (3, 32 | 64) => Ok((format_ident!("{}x{}", scalar, len), 1)),
+                (3, _) => Err(syn::Error::new(array.len.span(), "8 and 16 bit vectors must have 2 or 4 components")),
+                _      => Err(syn::Error::new(array.len.span(), "vectors must have 2, 3, or 4 components")),
+            }
+        }
+        _ => match scalar_format(ty)? {
+            (scalar, 32 | 64) => Ok((format_ident!("{}", scalar), 1)),
+            _ => Err(syn::Error::new(ty.span(), "8 and 16 bit attributes must have 2 or 4 components")),
+        },
+    }
+}
+
+// The scalar's format and size in bits
+fn scalar_format(ty: &Type) -> syn::Result<(&'static str, u32)> {
+    if let Type::Path(path) = ty {
+        if let Some(ident) = path.path.get_ident() {
+            match ident.to_string().as_str() {
+                "f32" => return Ok(("Float32", 32)),
+                "f64" => return Ok(("Float64", 64)),
+                "u32" => return Ok(("Uint32", 32)),
+                "i32" => return Ok(("Sint32", 32)),
+                "u16" => return Ok(("Uint16", 16)),
+                "i16" => return Ok(("Sint16", 16)),
+                "u8"  => return Ok(("Uint8", 8)),
+                "i8"  => return Ok(("Sint8", 8)),
+                _     => {}
+            }
+        }
+    }
+
+    Err(syn::Error::new(ty.span(), "unsupported vertex attribute type"))
+}
+
+// Checks a `#[format(..)]` against the field storing it. Normalized formats need an integer field
+// of the same signedness, and half floats are stored as `u16` bits.
+fn packed_format(format: syn::Ident, ty: &Type) -> syn::Result<syn::Ident> {
+    let name = format.to_string();
+
+    let (kind, rest)  = ["Unorm", "Snorm", "Uint", "Sint", "Float"]
+        .iter()
+        .find_map(|kind| name.strip_prefix(kind).map(|rest| (*kind, rest)))
+        .ok_or_else(|| syn::Error::new(format.span(), "unknown vertex format"))?;
+    let (bits, count) = match rest.split_once('x') {
+        Some((bits, count)) => (bits.parse::<u32>(), count.parse::<u32>()),
+        None                => (rest.parse::<u32>(), Ok(1)),
+    };
+    let (bits, count) = match (bits, count) {
+        (Ok(bits), Ok(count)) => (bits, count),
+        _ => return Err(syn::Error::new(format.span(), "unknown vertex format")),
+    };
+
+    let (elem, len) = match ty {
+        Type::Array(array) => (&*array.elem, array_len(&array.len)?),
+        _                  => (ty, 1),
+    };
+    let (scalar, scalar_bits) = scalar_format(elem)?;
+
+    let storage = match kind {
+        "Unorm" | "Uint" => scalar.starts_with("Uint"),
+        "Snorm" | "Sint" => scalar.starts_with("Sint"),
+        _                => scalar == "Float32" && bits == 32 || scalar == "Float64" && bits == 64 || scalar == "Uint16" && bits == 16,
+    };
+
+    if !storage || bits != scalar_bits || count != len {
+        return Err(syn::Error::new(ty.span(), format!("field can't hold a `{}` attribute", name)));
+    }
+
+    Ok(format)
+}
+
+fn array_len(len: &Expr) -> syn::Result<u32> {
+    if let Expr::Lit(expr) = len {
+        if let Lit::Int(lit) = &expr.lit {
+            return lit.base10_parse();
+        }
+    }
+
+    Err(syn::Error::new(len.span(), "array length must be an integer literal"))
+}
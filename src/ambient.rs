@@ -0,0 +1,142 @@
+// Ambient light from the environment, so surfaces facing away from every light still pick up the
+// sky's color. The environment is projected onto second order spherical harmonics and convolved
+// with a cosine lobe, leaving nine colors that give the diffuse light reaching any normal. They're
+// uploaded with the lights and evaluated by `ambient_lighting` in lights.wgsl.
+
+use cgmath::prelude::*;
+
+// Normalization of each basis function, in the order below. Also in lights.wgsl.
+const BASIS_SCALE: [f32; 9] = [
+    0.282_095,
+    0.488_603, 0.488_603, 0.488_603,
+    1.092_548, 1.092_548, 0.315_392, 1.092_548, 0.546_274,
+];
+
+// The cosine lobe's convolution for each band, divided by pi so the result is ready to multiply
+// albedo with
+const BAND_FACTOR: [f32; 9] = [
+    1.0,
+    2.0 / 3.0, 2.0 / 3.0, 2.0 / 3.0,
+    0.25, 0.25, 0.25, 0.25, 0.25,
+];
+
+// Steps in elevation when projecting an environment, with twice as many around
+const PROJECTION_STEPS: u32 = 32;
+
+// Sky colors straight up, at the horizon and straight down, blended by elevation
+#[derive(Debug, Copy, Clone)]
+pub struct SkyGradient {
+    pub zenith:  [f32; 3],
+    pub horizon: [f32; 3],
+    pub ground:  [f32; 3],
+}
+
+impl SkyGradient {
+    // The scene has no sky of its own, only the color the frame is cleared to. That's taken as the
+    // horizon, a little brighter overhead and darker underfoot, as if bounced off the ground.
+    pub fn from_clear_color(color: wgpu::Color) -> Self {
+        let horizon = [color.r as f32, color.g as f32, color.b as f32];
+
+        Self {
+            zenith:  horizon.map(|c| c * 1.4),
+            horizon,
+            ground:  horizon.map(|c| c * 0.4),
+        }
+    }
+
+    pub fn radiance(&self, direction: cgmath::Vector3<f32>) -> [f32; 3] {
+        let y = direction.normalize().y;
+        // Square root so most of the change is close to the horizon
+        let (far, t) = if y >= 0.0 { (self.zenith, y.sqrt()) } else { (self.ground, (-y).sqrt()) };
+
+        [0, 1, 2].map(|i| self.horizon[i] + (far[i] - self.horizon[i]) * t)
+    }
+
+    pub fn ambient(&self) -> SphericalHarmonics {
+        SphericalHarmonics::from_radiance(|direction| self.radiance(direction))
+    }
+}
+
+// Nine colors, already convolved for diffuse lighting. A constant environment of radiance 1.0
+// lights every normal by 1.0.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SphericalHarmonics {
+    pub coefficients: [[f32; 3]; 9],
+}
+
+impl SphericalHarmonics {
+    // The same light from every direction
+    pub fn uniform(color: [f32; 3]) -> Self {
+        let mut coefficients = [[0.0; 3]; 9];
+
+        coefficients[0] = color.map(|c| c / BASIS_SCALE[0]);
+
+        Self { coefficients }
+    }
+
+    // Integrates `radiance` over the sphere on an elevation and azimuth grid
+    pub fn from_radiance(radiance: impl Fn(cgmath::Vector3<f32>) -> [f32; 3]) -> Self {
+        let mut coefficients = [[0.0; 3]; 9];
+
+        let theta_steps = PROJECTION_STEPS;
+        let phi_steps   = PROJECTION_STEPS * 2;
+        let d_theta     = std::f32::consts::PI / theta_steps as f32;
+        let d_phi       = std::f32::consts::TAU / phi_steps as f32;
+
+        for i in 0..theta_steps {
+            let theta = (i as f32 + 0.5) * d_theta;
+            // Solid angle of each cell in this row
+            let weight = theta.sin() * d_theta * d_phi;
+
+            for j in 0..phi_steps {
+                let phi       = (j as f32 + 0.5) * d_phi;
+                let direction = cgmath::Vector3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin());
+                let color     = radiance(direction);
+
+                for (coefficient, basis) in coefficients.iter_mut().zip(basis(direction)) {
+                    for (c, channel) in coefficient.iter_mut().zip(color) {
+                        *c += channel * basis * weight;
+                    }
+                }
+            }
+        }
+
+        for (coefficient, factor) in coefficients.iter_mut().zip(BAND_FACTOR) {
+            *coefficient = coefficient.map(|c| c * factor);
+        }
+
+        Self { coefficients }
+    }
+
+    pub fn scaled(&self, scale: f32) -> Self {
+        Self { coefficients: self.coefficients.map(|coefficient| coefficient.map(|c| c * scale)) }
+    }
+
+    pub fn to_raw(&self) -> [[f32; 4]; 9] {
+        self.coefficients.map(|[r, g, b]| [r, g, b, 0.0])
+    }
+}
+
+impl Default for SphericalHarmonics {
+    // White light everywhere, which leaves albedo as it is
+    fn default() -> Self {
+        Self::uniform([1.0; 3])
+    }
+}
+
+// The real spherical harmonics up to the second band, for a unit direction
+fn basis(d: cgmath::Vector3<f32>) -> [f32; 9] {
+    let unscaled = [
+        1.0,
+        d.y, d.z, d.x,
+        d.x * d.y, d.y * d.z, 3.0 * d.z * d.z - 1.0, d.x * d.z, d.x * d.x - d.y * d.y,
+    ];
+
+    let mut basis = [0.0; 9];
+
+    for ((b, u), scale) in basis.iter_mut().zip(unscaled).zip(BASIS_SCALE) {
+        *b = u * scale;
+    }
+
+    basis
+}
@@ -0,0 +1,271 @@
+// Statistics about the finished frame, gathered by compute passes and read back without stalling:
+// the darkest, brightest and average luminance of the scene and a histogram of how far its pixels
+// are from the camera. Results arrive a frame or two late, which is fine for debugging and for
+// anything that adapts slowly, like picking a focus distance or a quality level.
+//
+// Runs as a post effect that only reads the scene, so add it after the effects whose result it
+// should see. Needs compute shaders, so it isn't available on WebGL.
+
+use std::cell::RefCell;
+
+use crate::{
+    bind_group,
+    camera::{Camera, DepthMode},
+    compute::{self, AsyncReadback},
+    crash_report,
+    pass::PostEffect,
+    renderer::GpuContext,
+    uniform::{Uniform, UniformBuffer},
+};
+
+// Also in analysis_depth.wgsl
+pub const DEPTH_BINS: usize = 64;
+
+// Fixed point scale of the luminance sum, also in analysis_luminance.wgsl
+const LUMINANCE_SCALE: f32 = 256.0;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct AnalysisParams {
+    near:        f32,
+    far:         f32,
+    reverse_z:   u32,
+    srgb_target: u32,
+}
+
+// As the shaders leave it, see analysis_luminance.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct AnalysisRaw {
+    min_inverted:  u32,
+    max_luminance: u32,
+    luminance_sum: u32,
+    pixels:        u32,
+    covered:       u32,
+    depth_bins:    [u32; DEPTH_BINS],
+}
+
+// Linear luminance of the scene's pixels. The average saturates at 1.0 like the scene target does.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LuminanceStats {
+    pub min:     f32,
+    pub max:     f32,
+    pub average: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DepthHistogram {
+    // Pixels in each bin, nearest first. Bins get exponentially thicker from `near` to `far`.
+    pub bins:     Vec<u32>,
+    pub near:     f32,
+    pub far:      f32,
+    // Share of the frame something was drawn to, the rest is background
+    pub coverage: f32,
+}
+
+impl DepthHistogram {
+    // Distances in front of the camera where `bin` starts and ends
+    pub fn bin_range(&self, bin: usize) -> (f32, f32) {
+        let edge = |i: usize| self.near * (self.far / self.near).powf(i as f32 / self.bins.len() as f32);
+
+        (edge(bin), edge(bin + 1))
+    }
+
+    // Distance within which `fraction` of the covered pixels lie, to the end of a bin. None when
+    // nothing was drawn.
+    pub fn percentile(&self, fraction: f32) -> Option<f32> {
+        let total = self.bins.iter().sum::<u32>();
+
+        if total == 0 {
+            return None;
+        }
+
+        let wanted  = (fraction.clamp(0.0, 1.0) * total as f32).ceil().max(1.0) as u32;
+        let mut sum = 0;
+
+        for (bin, &count) in self.bins.iter().enumerate() {
+            sum += count;
+
+            if sum >= wanted {
+                return Some(self.bin_range(bin).1);
+            }
+        }
+
+        Some(self.far)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnalysisResults {
+    pub luminance: LuminanceStats,
+    pub depth:     DepthHistogram,
+}
+
+impl AnalysisResults {
+    // None when the frame had no pixels, like a minimized window
+    fn from_raw(raw: &AnalysisRaw, near: f32, far: f32) -> Option<Self> {
+        if raw.pixels == 0 {
+            return None;
+        }
+
+        let luminance = LuminanceStats {
+            min:     f32::from_bits(!raw.min_inverted),
+            max:     f32::from_bits(raw.max_luminance),
+            average: raw.luminance_sum as f32 / LUMINANCE_SCALE / raw.pixels as f32,
+        };
+
+        let depth = DepthHistogram {
+            bins:     raw.depth_bins.to_vec(),
+            near,
+            far,
+            coverage: (raw.covered as f32 / raw.pixels as f32).min(1.0),
+        };
+
+        Some(Self { luminance, depth })
+    }
+}
+
+pub struct SceneAnalysis {
+    params:             UniformBuffer<AnalysisParams>,
+    luminance_pipeline: wgpu::ComputePipeline,
+    luminance_layout:   wgpu::BindGroupLayout,
+    depth_pipeline:     wgpu::ComputePipeline,
+    depth_layout:       wgpu::BindGroupLayout,
+    // Made in `prepare`, since the depth texture is replaced on resize
+    depth_group:        Option<wgpu::BindGroup>,
+    depth_size:         (u32, u32),
+    size:               (u32, u32),
+    // Cleared and filled again every frame
+    analysis:           wgpu::Buffer,
+    // Copied into while applying, which only borrows
+    readback:           RefCell<AsyncReadback>,
+    // The camera's range when the results were read, taken to be the range they were drawn with
+    range:              (f32, f32),
+    results:            Option<AnalysisResults>,
+    srgb_target:        bool,
+}
+
+impl SceneAnalysis {
+    pub fn new(ctx: &GpuContext) -> Self {
+        let device = &ctx.device;
+        let size   = std::mem::size_of::<AnalysisRaw>() as wgpu::BufferAddress;
+
+        let luminance_layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::COMPUTE)
+            .unfilterable_texture(wgpu::ShaderStages::COMPUTE, wgpu::TextureViewDimension::D2)
+            .storage(wgpu::ShaderStages::COMPUTE, false)
+            .build(device, "analysis_luminance_bind_group_layout");
+
+        let depth_layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::COMPUTE)
+            .depth_texture(wgpu::ShaderStages::COMPUTE)
+            .storage(wgpu::ShaderStages::COMPUTE, false)
+            .build(device, "analysis_depth_bind_group_layout");
+
+        let analysis = device.create_buffer(&wgpu::BufferDescriptor {
+            label:              Some("Scene Analysis Buffer"),
+            size,
+            usage:              wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            params:             UniformBuffer::new(device, "Scene Analysis Params Buffer"),
+            luminance_pipeline: compute::create_pipeline(device, &luminance_layout, include_str!("analysis_luminance.wgsl"), "Scene Analysis Luminance"),
+            luminance_layout,
+            depth_pipeline:     compute::create_pipeline(device, &depth_layout, include_str!("analysis_depth.wgsl"), "Scene Analysis Depth"),
+            depth_layout,
+            depth_group:        None,
+            depth_size:         (0, 0),
+            size:               (0, 0),
+            analysis,
+            readback:           RefCell::new(AsyncReadback::new(device, size, "Scene Analysis Readback Buffer")),
+            range:              (0.1, 100.0),
+            results:            None,
+            srgb_target:        ctx.config.format.describe().srgb,
+        }
+    }
+
+    // Called once a frame before the effect is added, with the camera the scene is drawn from.
+    // Picks up results that have arrived since the last frame.
+    pub fn prepare(&mut self, ctx: &GpuContext, camera: &Camera) {
+        let readback = self.readback.get_mut();
+
+        // Last frame's copy has been submitted by now
+        readback.submitted();
+
+        if let Some(raw) = readback.try_read::<AnalysisRaw>(&ctx.device) {
+            if let Some(results) = AnalysisResults::from_raw(&raw[0], self.range.0, self.range.1) {
+                self.results = Some(results);
+            }
+        }
+
+        let size        = ctx.render_size();
+        self.size       = (size.width, size.height);
+        self.depth_size = (ctx.size.width, ctx.size.height);
+        self.range      = (camera.znear, camera.zfar);
+
+        self.params.write(&ctx.queue, &AnalysisParams {
+            near:        camera.znear,
+            far:         camera.zfar,
+            reverse_z:   (ctx.depth_mode == DepthMode::ReverseZ) as u32,
+            srgb_target: self.srgb_target as u32,
+        });
+
+        self.depth_group = Some(
+            bind_group::BindGroupBuilder::new(&self.depth_layout)
+                .uniform(self.params.buffer())
+                .texture(&ctx.depth_texture.view)
+                .storage(&self.analysis)
+                .build(&ctx.device, "analysis_depth_bind_group")
+        );
+    }
+
+    // The latest results, from a frame or two ago. None until the first arrive.
+    pub fn results(&self) -> Option<&AnalysisResults> {
+        self.results.as_ref()
+    }
+}
+
+impl PostEffect for SceneAnalysis {
+    fn apply(
+        &self,
+        device:  &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        input:   &wgpu::TextureView,
+        _output: &wgpu::TextureView,
+    ) {
+        let depth_group = match &self.depth_group {
+            Some(group) => group,
+            None        => return,
+        };
+
+        let luminance_group = bind_group::BindGroupBuilder::new(&self.luminance_layout)
+            .uniform(self.params.buffer())
+            .texture(input)
+            .storage(&self.analysis)
+            .build(device, "analysis_luminance_bind_group");
+
+        encoder.clear_buffer(&self.analysis, 0, None);
+
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Scene Analysis Pass"),
+            });
+
+            crash_report::set_compute_pipeline(&mut compute_pass, &self.luminance_pipeline, "Scene Analysis Luminance");
+            compute_pass.set_bind_group(0, &luminance_group, &[]);
+            compute_pass.dispatch_workgroups(compute::workgroups(self.size.0), compute::workgroups(self.size.1), 1);
+
+            crash_report::set_compute_pipeline(&mut compute_pass, &self.depth_pipeline, "Scene Analysis Depth");
+            compute_pass.set_bind_group(0, depth_group, &[]);
+            compute_pass.dispatch_workgroups(compute::workgroups(self.depth_size.0), compute::workgroups(self.depth_size.1), 1);
+        }
+
+        self.readback.borrow_mut().copy(encoder, &self.analysis);
+    }
+
+    fn writes_output(&self) -> bool {
+        false
+    }
+}
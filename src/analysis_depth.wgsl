@@ -0,0 +1,75 @@
+// Sorts the pixels of the depth buffer into 64 bins by their distance in front of the camera.
+// Bins get exponentially thicker like the light cluster slices, so nearby detail isn't squeezed
+// into the first few. Pixels nothing was drawn to are left out.
+
+struct AnalysisParams {
+    near:        f32,
+    far:         f32,
+    reverse_z:   u32,
+    srgb_target: u32,
+}
+
+// Also in analysis_luminance.wgsl
+struct Analysis {
+    min_inverted:  atomic<u32>,
+    max_luminance: atomic<u32>,
+    luminance_sum: atomic<u32>,
+    pixels:        atomic<u32>,
+    covered:       atomic<u32>,
+    depth_bins:    array<atomic<u32>, 64>,
+}
+
+@group(0) @binding(0)
+var<uniform> params: AnalysisParams;
+@group(0) @binding(1)
+var t_depth: texture_depth_2d;
+@group(0) @binding(2)
+var<storage, read_write> analysis: Analysis;
+
+// One bin for each of the 64 invocations in a workgroup
+var<workgroup> local_bins: array<atomic<u32>, 64>;
+
+// Distance in front of the camera from a depth buffer value
+fn linear_depth(depth: f32) -> f32 {
+    let near = params.near;
+    let far  = params.far;
+
+    if (params.reverse_z != 0u) {
+        return near * far / (near + depth * (far - near));
+    }
+
+    return near * far / (far - depth * (far - near));
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_main(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(local_invocation_index) index: u32,
+) {
+    let size  = vec2<i32>(textureDimensions(t_depth));
+    let coord = vec2<i32>(id.xy);
+
+    atomicStore(&local_bins[index], 0u);
+
+    workgroupBarrier();
+
+    if (coord.x < size.x && coord.y < size.y) {
+        let depth   = textureLoad(t_depth, coord, 0);
+        let cleared = select(depth >= 1.0, depth <= 0.0, params.reverse_z != 0u);
+
+        if (!cleared) {
+            let t = log(linear_depth(depth) / params.near) / log(params.far / params.near);
+
+            atomicAdd(&local_bins[u32(clamp(t, 0.0, 0.999) * 64.0)], 1u);
+        }
+    }
+
+    workgroupBarrier();
+
+    let count = atomicLoad(&local_bins[index]);
+
+    if (count > 0u) {
+        atomicAdd(&analysis.depth_bins[index], count);
+        atomicAdd(&analysis.covered, count);
+    }
+}
@@ -0,0 +1,85 @@
+// Finds the darkest, brightest and average luminance of an image. Each workgroup reduces its own
+// pixels in shared memory first so only one atomic per statistic reaches the storage buffer.
+
+struct AnalysisParams {
+    near:        f32,
+    far:         f32,
+    reverse_z:   u32,
+    srgb_target: u32,
+}
+
+// Also in analysis_depth.wgsl. Luminance is kept as float bits, which sort like the floats as long
+// as they aren't negative. The minimum is inverted so the cleared buffer is a valid start.
+struct Analysis {
+    min_inverted:  atomic<u32>,
+    max_luminance: atomic<u32>,
+    // Fixed point, 256 to a luminance of 1.0
+    luminance_sum: atomic<u32>,
+    pixels:        atomic<u32>,
+    covered:       atomic<u32>,
+    depth_bins:    array<atomic<u32>, 64>,
+}
+
+@group(0) @binding(0)
+var<uniform> params: AnalysisParams;
+@group(0) @binding(1)
+var src: texture_2d<f32>;
+@group(0) @binding(2)
+var<storage, read_write> analysis: Analysis;
+
+var<workgroup> local_min:    atomic<u32>;
+var<workgroup> local_max:    atomic<u32>;
+var<workgroup> local_sum:    atomic<u32>;
+var<workgroup> local_pixels: atomic<u32>;
+
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+    return select(pow((c + 0.055) / 1.055, vec3<f32>(2.4)), c / 12.92, c <= vec3<f32>(0.04045));
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_main(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(local_invocation_index) index: u32,
+) {
+    let size  = vec2<i32>(textureDimensions(src));
+    let coord = vec2<i32>(id.xy);
+
+    if (index == 0u) {
+        atomicStore(&local_min, 0xffffffffu);
+        atomicStore(&local_max, 0u);
+        atomicStore(&local_sum, 0u);
+        atomicStore(&local_pixels, 0u);
+    }
+
+    workgroupBarrier();
+
+    if (coord.x < size.x && coord.y < size.y) {
+        // Loading an sRGB view already gives linear color, anything else holds the encoded values
+        var color = textureLoad(src, coord, 0).rgb;
+
+        if (params.srgb_target == 0u) {
+            color = srgb_to_linear(color);
+        }
+
+        let luminance = max(dot(color, vec3<f32>(0.2126, 0.7152, 0.0722)), 0.0);
+        let bits      = bitcast<u32>(luminance);
+
+        atomicMin(&local_min, bits);
+        atomicMax(&local_max, bits);
+        atomicAdd(&local_sum, u32(min(luminance, 1.0) * 256.0 + 0.5));
+        atomicAdd(&local_pixels, 1u);
+    }
+
+    workgroupBarrier();
+
+    if (index == 0u) {
+        let pixels = atomicLoad(&local_pixels);
+
+        if (pixels > 0u) {
+            atomicMax(&analysis.min_inverted, ~atomicLoad(&local_min));
+            atomicMax(&analysis.max_luminance, atomicLoad(&local_max));
+            atomicAdd(&analysis.luminance_sum, atomicLoad(&local_sum));
+            atomicAdd(&analysis.pixels, pixels);
+        }
+    }
+}
@@ -0,0 +1,245 @@
+// Property animation: curves of values over time, each bound to a material parameter, a spot
+// light's intensity or color, or part of an instance's transform. An `Animator` samples its tracks
+// during update and writes the values straight into the scene, so nothing else needs to know
+// they're animated. Lights and instances are uploaded every frame anyway, and materials upload
+// whatever changed in one go before rendering.
+
+use cgmath::prelude::*;
+
+use crate::{camera_path::Easing, light::SpotLight, model::{Instance, Material}};
+
+// Values a curve can blend between
+pub trait Animatable: Copy {
+    fn interpolate(&self, other: &Self, t: f32) -> Self;
+}
+
+impl Animatable for f32 {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl<const N: usize> Animatable for [f32; N] {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        let mut result = *self;
+
+        for (value, other) in result.iter_mut().zip(other) {
+            *value += (other - *value) * t;
+        }
+
+        result
+    }
+}
+
+impl Animatable for cgmath::Vector3<f32> {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        self.lerp(*other, t)
+    }
+}
+
+impl Animatable for cgmath::Quaternion<f32> {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        self.slerp(*other, t)
+    }
+}
+
+// What a curve does past its last key
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Repeat {
+    // Holds the last value
+    #[default]
+    Once,
+    Loop,
+    // Plays backwards to the start, then forwards again
+    PingPong,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Keyframe<T> {
+    // Seconds from the start of the curve
+    pub time:   f32,
+    pub value:  T,
+    // How the segment from this key to the next one is paced
+    pub easing: Easing,
+}
+
+#[derive(Debug, Clone)]
+pub struct Curve<T> {
+    pub repeat: Repeat,
+    keys:       Vec<Keyframe<T>>,
+}
+
+impl<T: Animatable> Curve<T> {
+    pub fn new(repeat: Repeat) -> Self {
+        Self { repeat, keys: Vec::new() }
+    }
+
+    // From `from` to `to` over `duration` seconds
+    pub fn tween(from: T, to: T, duration: f32, easing: Easing, repeat: Repeat) -> Self {
+        let mut curve = Self::new(repeat);
+
+        curve.add_key(Keyframe { time: 0.0, value: from, easing });
+        curve.add_key(Keyframe { time: duration, value: to, easing });
+        curve
+    }
+
+    // Keys can be added in any order, they're kept sorted by time
+    pub fn add_key(&mut self, key: Keyframe<T>) -> &mut Self {
+        let index = self.keys.partition_point(|k| k.time <= key.time);
+
+        self.keys.insert(index, key);
+        self
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.keys.last().map_or(0.0, |key| key.time)
+    }
+
+    // The value at `time`, held at the first key before the curve starts. None without keys.
+    pub fn sample(&self, time: f32) -> Option<T> {
+        let first    = self.keys.first()?;
+        let duration = self.duration();
+
+        let time = match self.repeat {
+            _ if duration <= 0.0 || time <= 0.0 => time,
+            Repeat::Once                        => time.min(duration),
+            Repeat::Loop                        => time % duration,
+            Repeat::PingPong                    => {
+                let t = time % (duration * 2.0);
+
+                if t > duration { duration * 2.0 - t } else { t }
+            }
+        };
+
+        if time <= first.time {
+            return Some(first.value);
+        }
+
+        let i = self.keys.partition_point(|k| k.time <= time);
+
+        if i == self.keys.len() {
+            return Some(self.keys[i - 1].value);
+        }
+
+        let from   = &self.keys[i - 1];
+        let to     = &self.keys[i];
+        let length = to.time - from.time;
+        let t      = if length > 0.0 { from.easing.apply((time - from.time) / length) } else { 1.0 };
+
+        Some(from.value.interpolate(&to.value, t))
+    }
+}
+
+// A curve and the property it drives. Indices that don't exist in the scene are skipped.
+#[derive(Debug, Clone)]
+pub enum Track {
+    MaterialTint     { material: usize, curve: Curve<[f32; 4]> },
+    // Blinking lights and flickering screens
+    MaterialEmissive { material: usize, curve: Curve<f32> },
+    LightIntensity   { light: usize,    curve: Curve<f32> },
+    LightColor       { light: usize,    curve: Curve<[f32; 3]> },
+    Position         { instance: usize, curve: Curve<cgmath::Vector3<f32>> },
+    Rotation         { instance: usize, curve: Curve<cgmath::Quaternion<f32>> },
+    Scale            { instance: usize, curve: Curve<cgmath::Vector3<f32>> },
+}
+
+// Everything tracks can write to
+pub struct AnimationTargets<'a> {
+    pub materials: &'a mut [Material],
+    pub lights:    &'a mut [SpotLight],
+    pub instances: &'a mut [Instance],
+}
+
+pub struct Animator {
+    pub tracks:  Vec<Track>,
+    pub speed:   f32,
+    pub playing: bool,
+    time:        f32,
+}
+
+impl Default for Animator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Animator {
+    pub fn new() -> Self {
+        Self {
+            tracks:  Vec::new(),
+            speed:   1.0,
+            playing: false,
+            time:    0.0,
+        }
+    }
+
+    pub fn add(&mut self, track: Track) -> &mut Self {
+        self.tracks.push(track);
+        self
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn seek(&mut self, time: f32) {
+        self.time = time.max(0.0);
+    }
+
+    pub fn update(&mut self, dt: std::time::Duration) {
+        if self.playing {
+            self.time += dt.as_secs_f32() * self.speed;
+        }
+    }
+
+    // Writes every track's value at the current time into `targets`. Returns whether any instance
+    // moved, since their bounds then need updating.
+    pub fn apply(&self, targets: &mut AnimationTargets) -> bool {
+        let mut moved = false;
+
+        for track in &self.tracks {
+            match track {
+                Track::MaterialTint { material, curve } => {
+                    if let (Some(material), Some(tint)) = (targets.materials.get_mut(*material), curve.sample(self.time)) {
+                        material.params.tint = tint;
+                    }
+                }
+                Track::MaterialEmissive { material, curve } => {
+                    if let (Some(material), Some(intensity)) = (targets.materials.get_mut(*material), curve.sample(self.time)) {
+                        material.params.emissive_intensity = intensity;
+                    }
+                }
+                Track::LightIntensity { light, curve } => {
+                    if let (Some(light), Some(intensity)) = (targets.lights.get_mut(*light), curve.sample(self.time)) {
+                        light.intensity = intensity;
+                    }
+                }
+                Track::LightColor { light, curve } => {
+                    if let (Some(light), Some(color)) = (targets.lights.get_mut(*light), curve.sample(self.time)) {
+                        light.color = color;
+                    }
+                }
+                Track::Position { instance, curve } => {
+                    if let (Some(instance), Some(position)) = (targets.instances.get_mut(*instance), curve.sample(self.time)) {
+                        instance.position = position;
+                        moved = true;
+                    }
+                }
+                Track::Rotation { instance, curve } => {
+                    if let (Some(instance), Some(rotation)) = (targets.instances.get_mut(*instance), curve.sample(self.time)) {
+                        instance.rotation = rotation;
+                        moved = true;
+                    }
+                }
+                Track::Scale { instance, curve } => {
+                    if let (Some(instance), Some(scale)) = (targets.instances.get_mut(*instance), curve.sample(self.time)) {
+                        instance.scale = scale;
+                        moved = true;
+                    }
+                }
+            }
+        }
+
+        moved
+    }
+}
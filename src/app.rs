@@ -0,0 +1,331 @@
+use std::{cell::RefCell, future::Future, pin::Pin, rc::Rc, time::Duration};
+
+use winit::{
+    event::*,
+    event_loop::{ControlFlow, EventLoop},
+    window::WindowBuilder,
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::replay::{ReplayEvent, ReplayMode};
+use crate::{
+    input::Input,
+    pass::{self, RenderLayers},
+    plugin::Plugins,
+    renderer::{Frame, GpuContext, RendererOptions},
+};
+
+// `setup` is async because loading resources is async on the web
+pub type SetupFuture<'a, A> = Pin<Box<dyn Future<Output = A> + 'a>>;
+
+// User code built on the crate. `run_app` owns the window, GPU context and event loop and calls
+// into this, so apps never touch the event loop themselves.
+pub trait App: Sized + 'static {
+    fn renderer_options() -> RendererOptions {
+        RendererOptions::default()
+    }
+
+    // Registers the plugins to run alongside the app. Called before `setup`.
+    fn plugins(_plugins: &mut Plugins) {}
+
+    // Builds the app once the GPU context exists, since most app state is GPU resources
+    fn setup(ctx: &mut GpuContext) -> SetupFuture<'_, Self>;
+
+    // Return true if the event was consumed so the runner doesn't handle it as well
+    fn input(&mut self, _ctx: &GpuContext, _event: &WindowEvent) -> bool {
+        false
+    }
+
+    // Called after the surface has been reconfigured for the new size, and whenever dynamic
+    // resolution changes the render size
+    fn resize(&mut self, _ctx: &GpuContext) {}
+
+    fn update(&mut self, dt: Duration, input: &Input);
+
+    // Record uploads or extra passes into `frame` and register draws into `layers`.
+    // The runner executes the layers once the app and plugins have registered theirs.
+    fn render<'a>(&'a mut self, frame: &mut Frame, layers: &mut RenderLayers<'a>);
+
+    // What's shown in the headset with `RendererOptions::vr`, through OpenXR natively or WebXR in
+    // the browser. Apps without one only show in the window.
+    fn xr_scene(&mut self) -> Option<&mut dyn crate::stereo::XrScene> {
+        None
+    }
+}
+
+pub async fn run_app<A: App>() {
+    // Toggle logger based on WASM or desktop
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "wasm32")] {
+            std::panic::set_hook(Box::new(console_error_panic_hook::hook));
+            console_log::init_with_level(log::Level::Warn).expect("Couldn't initialize logger");
+        } else {
+            env_logger::init();
+        }
+    }
+
+    // Window setup
+    let event_loop = EventLoop::new();
+    let window     = WindowBuilder::new().build(&event_loop).unwrap();
+
+    // Add a canvas to the HTML document
+    #[cfg(target_arch = "wasm32")]
+    {
+        use winit::dpi::PhysicalSize;
+        use winit::platform::web::WindowExtWebSys;
+
+        window.set_inner_size(PhysicalSize::new(450, 400));
+
+        web_sys::window()
+            .and_then(|win| win.document())
+            .and_then(|doc| {
+                let dst    = doc.get_element_by_id("wasm-example")?;
+                let canvas = web_sys::Element::from(window.canvas());
+
+                dst.append_child(&canvas).ok()?;
+
+                Some(())
+            })
+            .expect("Couldn't append canvas to document body");
+    }
+
+    // GpuContext::new and App::setup use async code, so wait to finish
+    #[cfg(feature = "vr")]
+    let (mut ctx, mut xr) = crate::xr::create_context(window, A::renderer_options()).await;
+    #[cfg(not(feature = "vr"))]
+    let mut ctx     = GpuContext::new(window, A::renderer_options()).await;
+    let mut plugins = Plugins::new();
+
+    A::plugins(&mut plugins);
+    plugins.setup(&mut ctx);
+
+    #[cfg_attr(target_arch = "wasm32", allow(unused_mut))]
+    let mut input = Input::new();
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let replay = ReplayMode::from_env(input.seed(), ctx.size);
+
+    // Played back in a window the size it was recorded in, with the same seed
+    #[cfg(not(target_arch = "wasm32"))]
+    if let ReplayMode::Replaying(recording) = &replay {
+        input.set_seed(recording.seed());
+        ctx.window().set_inner_size(recording.window_size());
+    }
+
+    let app    = A::setup(&mut ctx).await;
+    let shared = Rc::new(RefCell::new(Runner {
+        ctx,
+        app,
+        plugins,
+        input,
+        last_frame: instant::Instant::now(),
+        #[cfg(not(target_arch = "wasm32"))]
+        replay,
+        #[cfg(all(target_arch = "wasm32", feature = "webxr"))]
+        web_xr:     crate::webxr::WebXr::new(),
+    }));
+
+    // Event loop
+    event_loop.run(move |event, _, control_flow| {
+        // Browsers only start a headset session from a click or key press
+        #[cfg(all(target_arch = "wasm32", feature = "webxr"))]
+        crate::webxr::handle_event(&shared, &event);
+
+        let runner = &mut *shared.borrow_mut();
+
+        match event {
+            Event::WindowEvent {
+                ref event,
+                window_id,
+            } if window_id == runner.ctx.window().id() => {
+                // Live input is recorded as it's handled, or ignored while a recording plays
+                #[cfg(not(target_arch = "wasm32"))]
+                if let Some(recorded) = ReplayEvent::from_window_event(event) {
+                    if !runner.replay.live_event(&recorded) {
+                        return;
+                    }
+                }
+
+                if runner.window_event(event) {
+                    *control_flow = ControlFlow::Exit;
+                }
+            }
+            Event::DeviceEvent { ref event, .. } => {
+                #[cfg(not(target_arch = "wasm32"))]
+                if let Some(recorded) = ReplayEvent::from_device_event(event) {
+                    if !runner.replay.live_event(&recorded) {
+                        return;
+                    }
+                }
+
+                runner.input.process_device_event(event);
+            }
+            Event::RedrawRequested(window_id) if window_id == runner.ctx.window().id() => {
+                // A presenting WebXR session's frames take the place of the window's
+                #[cfg(all(target_arch = "wasm32", feature = "webxr"))]
+                if runner.web_xr.is_presenting() {
+                    return;
+                }
+
+                runner.update();
+
+                let Runner { ctx, app, plugins, input, .. } = runner;
+
+                // The headset paces frames while there is one, and the window mirrors the app after it
+                #[cfg(feature = "vr")]
+                if let Some(session) = &mut xr {
+                    if !session.update(ctx, app) {
+                        *control_flow = ControlFlow::Exit;
+                    }
+                }
+
+                // The frame borrows the context, so finish it before handling errors that need `&mut ctx`
+                let result = match ctx.begin_frame() {
+                    Ok(mut frame) => {
+                        let mut layers = RenderLayers::new();
+                        let depth_view = frame.depth_view();
+
+                        layers.set_timer(ctx.pass_timer());
+
+                        app.render(&mut frame, &mut layers);
+                        plugins.render(&mut frame, &mut layers);
+
+                        // The GPU gets going on compute work while the passes are recorded
+                        frame.submit_compute();
+
+                        // Drawing straight to the surface saves a copy when there's nothing to scale, filter or capture
+                        if layers.has_effects() || layers.has_captures() || ctx.render_size() != ctx.size {
+                            layers.execute_offscreen(&ctx.device, &mut frame.encoder, &ctx.scene_target, &frame.view, depth_view, pass::CLEAR_COLOR, ctx.depth_mode);
+                        } else {
+                            layers.execute(&mut frame.encoder, &frame.view, depth_view, pass::CLEAR_COLOR, ctx.depth_mode);
+                        }
+
+                        frame.present();
+                        Ok(())
+                    }
+                    Err(e) => Err(e),
+                };
+
+                match result {
+                    Ok(_) => {},
+                    // Reconfigure the surface if lost
+                    Err(wgpu::SurfaceError::Lost) => {
+                        let size = ctx.size;
+                        ctx.resize(size);
+                        app.resize(ctx);
+                    }
+                    // The system is out of memory--quit
+                    Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
+                    // All other errors (Outdated, Timeout) should be resolved by the next frame
+                    Err(e) => eprintln!("{:?}", e),
+                }
+
+                input.end_frame();
+            }
+            Event::MainEventsCleared => {
+                // RedrawRequested will only trigger once unless we manually retrigger it
+                runner.ctx.window().request_redraw();
+            }
+            _ => {}
+        }
+    });
+}
+
+// What the event loop drives. It's shared because WebXR's frames come from the browser's headset
+// session rather than the event loop.
+pub(crate) struct Runner<A: App> {
+    pub(crate) ctx:     GpuContext,
+    pub(crate) app:     A,
+    pub(crate) plugins: Plugins,
+    pub(crate) input:   Input,
+    last_frame:         instant::Instant,
+    #[cfg(not(target_arch = "wasm32"))]
+    replay:             ReplayMode,
+    #[cfg(all(target_arch = "wasm32", feature = "webxr"))]
+    pub(crate) web_xr:  crate::webxr::WebXr,
+}
+
+impl<A: App> Runner<A> {
+    // Handles a window event from the event loop or a recording. Returns true to exit.
+    pub(crate) fn window_event(&mut self, event: &WindowEvent) -> bool {
+        let Runner { ctx, app, plugins, input, .. } = self;
+
+        input.process_event(event);
+
+        if let WindowEvent::Focused(false) = event {
+            // Don't keep the cursor trapped in a window the user has switched away from
+            ctx.set_cursor_grab(false);
+        }
+
+        if !plugins.input(ctx, event) && !app.input(ctx, event) {
+            match event {
+                WindowEvent::CloseRequested | WindowEvent::KeyboardInput {
+                    input: KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::Escape),
+                        ..
+                    },
+                    ..
+                } => return true,
+                WindowEvent::KeyboardInput {
+                    input: KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::Tab),
+                        ..
+                    },
+                    ..
+                } => ctx.set_cursor_grab(!ctx.is_cursor_grabbed()),
+                WindowEvent::Resized(physical_size) => {
+                    ctx.resize(*physical_size);
+                    app.resize(ctx);
+                }
+                WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                    ctx.resize(**new_inner_size); // dereference it bc it's &&mut
+                    app.resize(ctx);
+                }
+                _ => {}
+            }
+        }
+
+        false
+    }
+
+    // Steps the plugins and app by the time since the last frame, or the recorded time when
+    // replaying
+    pub(crate) fn update(&mut self) {
+        let now     = instant::Instant::now();
+        let elapsed = now - self.last_frame;
+
+        self.last_frame = now;
+
+        // A recording steps by its own times, and its events are handled just before the update
+        // they came before. It ends before whatever quit it, so there's never an exit to act on.
+        #[cfg(not(target_arch = "wasm32"))]
+        let dt = {
+            let (dt, events) = self.replay.next_frame(elapsed);
+
+            for event in &events {
+                if let Some(event) = event.to_window_event() {
+                    self.window_event(&event);
+                }
+
+                if let Some(event) = event.to_device_event() {
+                    self.input.process_device_event(&event);
+                }
+            }
+
+            dt
+        };
+        #[cfg(target_arch = "wasm32")]
+        let dt = elapsed;
+
+        self.input.set_cursor_grabbed(self.ctx.is_cursor_grabbed());
+
+        if self.ctx.update_render_scale(dt) {
+            self.app.resize(&self.ctx);
+        }
+
+        self.plugins.update(dt, &self.input);
+        self.app.update(dt, &self.input);
+    }
+}
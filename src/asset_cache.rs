@@ -0,0 +1,202 @@
+// An on-disk cache of imported assets, so startups after the first skip the slow parts of loading:
+// optimizing meshes with meshopt and decoding, and with `bc_compress` transcoding, textures. Each
+// entry is a file named after a hash of what it was made from, so editing a source file just
+// misses, and old entries are left until `clear`.
+//
+// Entries are a small header then the data exactly as it's uploaded, little endian:
+//
+//   mesh:    magic, version, vertex count, index count, `ModelVertex`es, u32 indices
+//   texture: magic, version, format, width, height, byte count, bytes
+//
+// Anything that doesn't read back as expected counts as a miss and is rebuilt. The cache lives in
+// `asset_cache/` in the build directory, or wherever `LEARN_WGPU_ASSET_CACHE` says, and setting
+// that to nothing turns it off. Native only.
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use crate::model::ModelVertex;
+
+const MESH_MAGIC:    &[u8; 4] = b"LWMS";
+const TEXTURE_MAGIC: &[u8; 4] = b"LWTX";
+// Bumped whenever the layout of an entry, or how one's made, changes
+const VERSION:       u32 = 1;
+
+// Texture formats are stored as their place in this list
+const FORMATS: [wgpu::TextureFormat; 4] = [
+    wgpu::TextureFormat::Rgba8Unorm,
+    wgpu::TextureFormat::Rgba8UnormSrgb,
+    wgpu::TextureFormat::Bc7RgbaUnorm,
+    wgpu::TextureFormat::Bc7RgbaUnormSrgb,
+];
+
+// A texture's single mip as `Texture::from_data` takes it
+pub struct CachedTexture {
+    pub format: wgpu::TextureFormat,
+    pub width:  u32,
+    pub height: u32,
+    pub data:   Vec<u8>,
+}
+
+pub struct AssetCache {
+    dir: PathBuf,
+}
+
+impl AssetCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    // The cache the loaders in resources.rs use, None when it's turned off
+    pub fn from_env() -> Option<Self> {
+        match std::env::var_os("LEARN_WGPU_ASSET_CACHE") {
+            Some(dir) if dir.is_empty() => None,
+            Some(dir)                   => Some(Self::new(dir)),
+            None                        => Some(Self::new(std::path::Path::new(env!("OUT_DIR")).join("asset_cache"))),
+        }
+    }
+
+    pub fn load_mesh(&self, key: u64) -> Option<(Vec<ModelVertex>, Vec<u32>)> {
+        let data       = std::fs::read(self.path(key, "mesh")).ok()?;
+        let mut reader = Reader::new(&data, MESH_MAGIC)?;
+
+        let vertex_count = reader.u32()? as usize;
+        let index_count  = reader.u32()? as usize;
+
+        let vertices = reader.bytes(vertex_count * std::mem::size_of::<ModelVertex>())?
+            .chunks_exact(std::mem::size_of::<ModelVertex>())
+            .map(bytemuck::pod_read_unaligned)
+            .collect();
+        let indices  = reader.bytes(index_count * 4)?
+            .chunks_exact(4)
+            .map(bytemuck::pod_read_unaligned)
+            .collect();
+
+        Some((vertices, indices))
+    }
+
+    pub fn store_mesh(&self, key: u64, vertices: &[ModelVertex], indices: &[u32]) {
+        let mut data = header(MESH_MAGIC);
+
+        data.extend_from_slice(&(vertices.len() as u32).to_le_bytes());
+        data.extend_from_slice(&(indices.len() as u32).to_le_bytes());
+        data.extend_from_slice(bytemuck::cast_slice(vertices));
+        data.extend_from_slice(bytemuck::cast_slice(indices));
+
+        self.write(key, "mesh", &data);
+    }
+
+    pub fn load_texture(&self, key: u64) -> Option<CachedTexture> {
+        let data       = std::fs::read(self.path(key, "texture")).ok()?;
+        let mut reader = Reader::new(&data, TEXTURE_MAGIC)?;
+
+        let format = *FORMATS.get(reader.u32()? as usize)?;
+        let width  = reader.u32()?;
+        let height = reader.u32()?;
+        let length = reader.u32()? as usize;
+
+        Some(CachedTexture { format, width, height, data: reader.bytes(length)?.to_vec() })
+    }
+
+    // Textures in formats the cache doesn't know aren't stored
+    pub fn store_texture(&self, key: u64, texture: &CachedTexture) {
+        let format = match FORMATS.iter().position(|&format| format == texture.format) {
+            Some(format) => format as u32,
+            None         => return,
+        };
+
+        let mut data = header(TEXTURE_MAGIC);
+
+        data.extend_from_slice(&format.to_le_bytes());
+        data.extend_from_slice(&texture.width.to_le_bytes());
+        data.extend_from_slice(&texture.height.to_le_bytes());
+        data.extend_from_slice(&(texture.data.len() as u32).to_le_bytes());
+        data.extend_from_slice(&texture.data);
+
+        self.write(key, "texture", &data);
+    }
+
+    // Deletes every entry
+    pub fn clear(&self) -> Result<()> {
+        if self.dir.exists() {
+            std::fs::remove_dir_all(&self.dir)?;
+        }
+
+        Ok(())
+    }
+
+    fn path(&self, key: u64, extension: &str) -> PathBuf {
+        self.dir.join(format!("{:016x}.{}", key, extension))
+    }
+
+    // Written beside the entry and then renamed over it, so another instance starting up at the
+    // same time never reads half a file. A cache that can't be written is only slower, so failures
+    // are just logged.
+    fn write(&self, key: u64, extension: &str, data: &[u8]) {
+        let path      = self.path(key, extension);
+        let temporary = path.with_extension(format!("{}.{}.tmp", extension, std::process::id()));
+
+        let written = std::fs::create_dir_all(&self.dir)
+            .and_then(|_| std::fs::write(&temporary, data))
+            .and_then(|_| std::fs::rename(&temporary, &path));
+
+        if let Err(e) = written {
+            log::warn!("Couldn't write {} to the asset cache: {}", path.display(), e);
+            let _ = std::fs::remove_file(&temporary);
+        }
+    }
+}
+
+// Identifies an entry by everything that went into making it. `kind` should say how the sources
+// were processed, e.g. the texture format and whether the device could take BC7, since the same
+// source makes different entries otherwise. FNV-1a, which is stable between builds, unlike std's
+// hasher.
+pub fn key(kind: &str, sources: &[&[u8]]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+
+    for part in std::iter::once(kind.as_bytes()).chain(sources.iter().copied()) {
+        // Lengths first, so moving bytes from one part to the next changes the key
+        for &byte in (part.len() as u64).to_le_bytes().iter().chain(part) {
+            hash ^= byte as u64;
+            hash  = hash.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    hash
+}
+
+fn header(magic: &[u8; 4]) -> Vec<u8> {
+    let mut data = magic.to_vec();
+
+    data.extend_from_slice(&VERSION.to_le_bytes());
+    data
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    // None unless `data` starts with `magic` and this version
+    fn new(data: &'a [u8], magic: &[u8; 4]) -> Option<Self> {
+        let mut reader = Self { data: data.strip_prefix(&magic[..])? };
+
+        (reader.u32()? == VERSION).then_some(reader)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+
+    fn bytes(&mut self, count: usize) -> Option<&'a [u8]> {
+        if count > self.data.len() {
+            return None;
+        }
+
+        let (bytes, rest) = self.data.split_at(count);
+
+        self.data = rest;
+        Some(bytes)
+    }
+}
@@ -0,0 +1,258 @@
+// Sound from the default input device, like a microphone or a loopback of what's playing, broken
+// into frequency bands for shaders and particles to move to. Samples arrive on cpal's thread and
+// pile up in a shared buffer, and each `update` takes the latest of them through an FFT, sums the
+// bins into log spaced bands and smooths them so they rise with the music and fall back gently.
+//
+// Shaders get the bands as a uniform along with a texture of the last frames' bands, a
+// spectrogram, through `shader_header` and `bind_group`. Native only, behind the `audio` feature.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+
+use crate::{
+    bind_group,
+    renderer::GpuContext,
+    uniform::{Uniform, UniformBuffer},
+};
+
+// Samples each FFT covers, about 40 ms at 48 kHz
+const FFT_SIZE: usize = 2048;
+
+// Also in audio.wgsl
+pub const BAND_COUNT: usize = 64;
+
+// Rows of the history texture, so frames of history
+const HISTORY: u32 = 128;
+
+// Range the bands are spread over, in Hz
+const LOWEST:  f32 = 30.0;
+const HIGHEST: f32 = 16000.0;
+
+// Bands at or under this level read as silent and at or over 0 dB as full
+const FLOOR_DB: f32 = -70.0;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct AudioUniform {
+    bands:    [[f32; 4]; BAND_COUNT / 4],
+    bass:     f32,
+    mid:      f32,
+    treble:   f32,
+    level:    f32,
+    row:      u32,
+    _padding: [u32; 3],
+}
+
+pub struct AudioInput {
+    // How much a band falls a second once the sound behind it stops, as a share of full
+    pub release:    f32,
+    // Scales the bands before they're clamped, for quiet inputs
+    pub gain:       f32,
+    pub layout:     wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+    bands:          [f32; BAND_COUNT],
+    samples:        Arc<Mutex<VecDeque<f32>>>,
+    sample_rate:    f32,
+    fft:            Arc<dyn Fft<f32>>,
+    window:         Vec<f32>,
+    uniform:        UniformBuffer<AudioUniform>,
+    history:        wgpu::Texture,
+    row:            u32,
+    // Dropping it stops capture
+    _stream:        cpal::Stream,
+}
+
+impl AudioInput {
+    pub fn new(ctx: &GpuContext) -> anyhow::Result<Self> {
+        let device = cpal::default_host()
+            .default_input_device()
+            .ok_or_else(|| anyhow!("There's no audio input device"))?;
+        let config = device.default_input_config()?;
+
+        log::info!("Capturing audio from {} at {} Hz", device.name().unwrap_or_default(), config.sample_rate().0);
+
+        let samples  = Arc::new(Mutex::new(VecDeque::with_capacity(FFT_SIZE * 2)));
+        let channels = config.channels() as usize;
+        let format   = config.sample_format();
+        let config   = config.config();
+
+        let stream = match format {
+            cpal::SampleFormat::F32 => build_stream(&device, &config, channels, samples.clone(), |s: f32| s)?,
+            cpal::SampleFormat::I16 => build_stream(&device, &config, channels, samples.clone(), |s: i16| s as f32 / i16::MAX as f32)?,
+            cpal::SampleFormat::U16 => build_stream(&device, &config, channels, samples.clone(), |s: u16| s as f32 / u16::MAX as f32 * 2.0 - 1.0)?,
+        };
+
+        stream.play()?;
+
+        let gpu = &ctx.device;
+
+        let history = gpu.create_texture(&wgpu::TextureDescriptor {
+            label:           Some("Audio History Texture"),
+            size:            wgpu::Extent3d { width: BAND_COUNT as u32, height: HISTORY, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count:    1,
+            dimension:       wgpu::TextureDimension::D2,
+            format:          wgpu::TextureFormat::R32Float,
+            usage:           wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+
+        let uniform = UniformBuffer::new(gpu, "Audio Uniform Buffer");
+        let stages  = wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE;
+
+        let layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(stages)
+            .unfilterable_texture(stages, wgpu::TextureViewDimension::D2)
+            .build(gpu, "audio_bind_group_layout");
+
+        let bind_group = bind_group::BindGroupBuilder::new(&layout)
+            .uniform(uniform.buffer())
+            .texture(&history.create_view(&wgpu::TextureViewDescriptor::default()))
+            .build(gpu, "audio_bind_group");
+
+        // Hann, so the ends of each slice of samples don't smear across the bins
+        let window = (0..FFT_SIZE)
+            .map(|i| 0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / (FFT_SIZE - 1) as f32).cos())
+            .collect();
+
+        Ok(Self {
+            release:     1.5,
+            gain:        1.0,
+            layout,
+            bind_group,
+            bands:       [0.0; BAND_COUNT],
+            samples,
+            sample_rate: config.sample_rate.0 as f32,
+            fft:         FftPlanner::new().plan_fft_forward(FFT_SIZE),
+            window,
+            uniform,
+            history,
+            row:         0,
+            _stream:     stream,
+        })
+    }
+
+    // Each band's loudness from 0.0 to 1.0, for reacting to sound on the CPU
+    pub fn bands(&self) -> &[f32; BAND_COUNT] {
+        &self.bands
+    }
+
+    // Analyzes the latest samples and uploads the bands
+    pub fn update(&mut self, queue: &wgpu::Queue, dt: Duration) {
+        let mut buffer: Vec<Complex<f32>> = {
+            let samples = self.samples.lock().unwrap();
+            let skip    = samples.len().saturating_sub(FFT_SIZE);
+
+            samples.iter().skip(skip).map(|&s| Complex::new(s, 0.0)).collect()
+        };
+
+        // Not enough yet, so the start is silent
+        while buffer.len() < FFT_SIZE {
+            buffer.insert(0, Complex::new(0.0, 0.0));
+        }
+
+        for (sample, weight) in buffer.iter_mut().zip(&self.window) {
+            *sample *= weight;
+        }
+
+        self.fft.process(&mut buffer);
+
+        let bin_width = self.sample_rate / FFT_SIZE as f32;
+        let highest   = HIGHEST.min(self.sample_rate * 0.5);
+        let fall      = self.release * dt.as_secs_f32();
+
+        for (band, level) in self.bands.iter_mut().enumerate() {
+            let frequency = |edge: f32| LOWEST * (highest / LOWEST).powf(edge / BAND_COUNT as f32);
+
+            let first = (frequency(band as f32) / bin_width) as usize;
+            let last  = ((frequency(band as f32 + 1.0) / bin_width) as usize).max(first + 1).min(FFT_SIZE / 2);
+
+            // The loudest bin in the band, scaled so a full scale sine reads 0 dB through the window
+            let peak = buffer[first.min(last - 1)..last].iter().map(|c| c.norm()).fold(0.0, f32::max) * 4.0 / FFT_SIZE as f32;
+            let db   = 20.0 * (peak * self.gain).max(1e-6).log10();
+            let new  = ((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0);
+
+            // Jumps up straight away but falls back slowly
+            *level = new.max(*level - fall);
+        }
+
+        let average = |range: std::ops::Range<usize>| {
+            let count = range.len() as f32;
+            self.bands[range].iter().sum::<f32>() / count
+        };
+
+        let mut uniform = AudioUniform {
+            bands:    bytemuck::Zeroable::zeroed(),
+            bass:     average(0..BAND_COUNT / 8),
+            mid:      average(BAND_COUNT / 8..BAND_COUNT / 2),
+            treble:   average(BAND_COUNT / 2..BAND_COUNT),
+            level:    average(0..BAND_COUNT),
+            row:      self.row,
+            _padding: [0; 3],
+        };
+
+        for (i, level) in self.bands.iter().enumerate() {
+            uniform.bands[i / 4][i % 4] = *level;
+        }
+
+        self.uniform.write(queue, &uniform);
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                aspect:    wgpu::TextureAspect::All,
+                texture:   &self.history,
+                mip_level: 0,
+                origin:    wgpu::Origin3d { x: 0, y: self.row, z: 0 },
+            },
+            bytemuck::cast_slice(&self.bands),
+            wgpu::ImageDataLayout {
+                offset:         0,
+                bytes_per_row:  std::num::NonZeroU32::new((BAND_COUNT * std::mem::size_of::<f32>()) as u32),
+                rows_per_image: None,
+            },
+            wgpu::Extent3d { width: BAND_COUNT as u32, height: 1, depth_or_array_layers: 1 },
+        );
+
+        self.row = (self.row + 1) % HISTORY;
+    }
+}
+
+// Declares the audio uniform and history at `group` and helpers for reading them, to go before
+// the source of a shader that uses `AudioInput::bind_group` there
+pub fn shader_header(group: u32) -> String {
+    include_str!("audio.wgsl").replace("AUDIO_GROUP", &group.to_string())
+}
+
+// Keeps the last second or so of samples, mixed down to mono
+fn build_stream<T: cpal::Sample + 'static>(
+    device:   &cpal::Device,
+    config:   &cpal::StreamConfig,
+    channels: usize,
+    samples:  Arc<Mutex<VecDeque<f32>>>,
+    convert:  fn(T) -> f32,
+) -> anyhow::Result<cpal::Stream> {
+    let keep = FFT_SIZE * 16;
+
+    let stream = device.build_input_stream(
+        config,
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            let mut samples = samples.lock().unwrap();
+
+            for frame in data.chunks(channels.max(1)) {
+                samples.push_back(frame.iter().map(|&s| convert(s)).sum::<f32>() / frame.len() as f32);
+            }
+
+            let excess = samples.len().saturating_sub(keep);
+            samples.drain(..excess);
+        },
+        |e| log::warn!("Audio capture failed: {}", e),
+    )?;
+
+    Ok(stream)
+}
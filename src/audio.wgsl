@@ -0,0 +1,32 @@
+// Frequency bands of captured audio, see audio.rs. Prepended to shaders that react to sound by
+// `audio::shader_header`, which fills in AUDIO_GROUP.
+
+struct AudioUniform {
+    // Loudness of each of 64 bands from 0.0 to 1.0, low to high and log spaced, so band `i` is
+    // `bands[i / 4][i % 4]`
+    bands:  array<vec4<f32>, 16>,
+    // Averages over the low, middle and high bands, and over all of them
+    bass:   f32,
+    mid:    f32,
+    treble: f32,
+    level:  f32,
+    // The row of `audio_history` written last
+    row:    u32,
+}
+
+@group(AUDIO_GROUP) @binding(0)
+var<uniform> audio: AudioUniform;
+// The bands over the last frames, one row a frame, wrapping around to the top
+@group(AUDIO_GROUP) @binding(1)
+var audio_history: texture_2d<f32>;
+
+fn audio_band(i: u32) -> f32 {
+    return audio.bands[i / 4u][i % 4u];
+}
+
+// Band `i` as it was `ago` frames back, for up to the texture's height
+fn audio_band_history(i: u32, ago: u32) -> f32 {
+    let rows = u32(textureDimensions(audio_history).y);
+
+    return textureLoad(audio_history, vec2<i32>(i32(i), i32((audio.row + rows - ago % rows) % rows)), 0).r;
+}
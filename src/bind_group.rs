@@ -0,0 +1,240 @@
+// Fluent helpers for bind group layouts and bind groups.
+//
+// Bindings are numbered in the order they're added, so a `BindGroupBuilder` filled in the same
+// order as its `BindGroupLayoutBuilder` lines up automatically. A layout builder's type lists what
+// its entries hold, front to back, e.g. `(kind::Texture, (kind::Sampler, ()))`. A bind group
+// started with `BindGroupBuilder::checked` against it only has a method for the kind of resource
+// the next entry holds, and only has `build` once every entry has one, so a uniform in a texture's
+// slot or a missing resource doesn't compile. One started with `new` takes any resources, and
+// leaves checking them to wgpu.
+
+use std::marker::PhantomData;
+
+// What a binding holds. Every kind of texture, storage textures too, is bound as a view, and both
+// kinds of sampler as a sampler.
+pub mod kind {
+    pub struct Uniform;
+    pub struct Storage;
+    pub struct Texture;
+    pub struct Sampler;
+}
+
+// Appends `T` to a list of kinds
+pub trait Push<T> {
+    type Output;
+}
+
+impl<T> Push<T> for () {
+    type Output = (T, ());
+}
+
+impl<T, Head, Tail: Push<T>> Push<T> for (Head, Tail) {
+    type Output = (Head, Tail::Output);
+}
+
+pub struct BindGroupLayoutBuilder<K = ()> {
+    entries: Vec<wgpu::BindGroupLayoutEntry>,
+    kinds:   PhantomData<K>,
+}
+
+impl BindGroupLayoutBuilder {
+    pub fn new() -> Self {
+        Self { entries: Vec::new(), kinds: PhantomData }
+    }
+}
+
+impl Default for BindGroupLayoutBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K> BindGroupLayoutBuilder<K> {
+    pub fn uniform(self, visibility: wgpu::ShaderStages) -> BindGroupLayoutBuilder<K::Output> where K: Push<kind::Uniform> {
+        self.push(visibility, wgpu::BindingType::Buffer {
+            ty:                 wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size:   None,
+        })
+    }
+
+    pub fn storage(self, visibility: wgpu::ShaderStages, read_only: bool) -> BindGroupLayoutBuilder<K::Output> where K: Push<kind::Storage> {
+        self.push(visibility, wgpu::BindingType::Buffer {
+            ty:                 wgpu::BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size:   None,
+        })
+    }
+
+    pub fn texture(self, visibility: wgpu::ShaderStages, view_dimension: wgpu::TextureViewDimension) -> BindGroupLayoutBuilder<K::Output> where K: Push<kind::Texture> {
+        self.push(visibility, wgpu::BindingType::Texture {
+            multisampled:   false,
+            view_dimension,
+            sample_type:    wgpu::TextureSampleType::Float { filterable: true },
+        })
+    }
+
+    // For formats like R32Float that can only be read with `textureLoad`
+    pub fn unfilterable_texture(self, visibility: wgpu::ShaderStages, view_dimension: wgpu::TextureViewDimension) -> BindGroupLayoutBuilder<K::Output> where K: Push<kind::Texture> {
+        self.push(visibility, wgpu::BindingType::Texture {
+            multisampled:   false,
+            view_dimension,
+            sample_type:    wgpu::TextureSampleType::Float { filterable: false },
+        })
+    }
+
+    pub fn storage_texture(
+        self,
+        visibility:     wgpu::ShaderStages,
+        format:         wgpu::TextureFormat,
+        access:         wgpu::StorageTextureAccess,
+        view_dimension: wgpu::TextureViewDimension,
+    ) -> BindGroupLayoutBuilder<K::Output> where K: Push<kind::Texture> {
+        self.push(visibility, wgpu::BindingType::StorageTexture {
+            access,
+            format,
+            view_dimension,
+        })
+    }
+
+    pub fn depth_texture(self, visibility: wgpu::ShaderStages) -> BindGroupLayoutBuilder<K::Output> where K: Push<kind::Texture> {
+        self.push(visibility, wgpu::BindingType::Texture {
+            multisampled:   false,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            sample_type:    wgpu::TextureSampleType::Depth,
+        })
+    }
+
+    pub fn sampler(self, visibility: wgpu::ShaderStages) -> BindGroupLayoutBuilder<K::Output> where K: Push<kind::Sampler> {
+        self.push(visibility, wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering))
+    }
+
+    pub fn comparison_sampler(self, visibility: wgpu::ShaderStages) -> BindGroupLayoutBuilder<K::Output> where K: Push<kind::Sampler> {
+        self.push(visibility, wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison))
+    }
+
+    pub fn entries(&self) -> &[wgpu::BindGroupLayoutEntry] {
+        &self.entries
+    }
+
+    pub fn build(&self, device: &wgpu::Device, label: &str) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &self.entries,
+            label:   Some(label),
+        })
+    }
+
+    fn push<T>(mut self, visibility: wgpu::ShaderStages, ty: wgpu::BindingType) -> BindGroupLayoutBuilder<K::Output> where K: Push<T> {
+        self.entries.push(wgpu::BindGroupLayoutEntry {
+            binding: self.entries.len() as u32,
+            visibility,
+            ty,
+            count: None,
+        });
+
+        BindGroupLayoutBuilder { entries: self.entries, kinds: PhantomData }
+    }
+}
+
+// A bind group's kinds for one started with `new`, which aren't checked
+pub struct Unchecked;
+
+pub struct BindGroupBuilder<'a, K = Unchecked> {
+    layout:  &'a wgpu::BindGroupLayout,
+    entries: Vec<wgpu::BindGroupEntry<'a>>,
+    // The kinds still to be bound
+    kinds:   PhantomData<K>,
+}
+
+impl<'a> BindGroupBuilder<'a> {
+    pub fn new(layout: &'a wgpu::BindGroupLayout) -> Self {
+        Self {
+            layout,
+            entries: Vec::new(),
+            kinds:   PhantomData,
+        }
+    }
+
+    pub fn uniform(self, buffer: &'a wgpu::Buffer) -> Self {
+        self.push(buffer.as_entire_binding())
+    }
+
+    pub fn storage(self, buffer: &'a wgpu::Buffer) -> Self {
+        self.push(buffer.as_entire_binding())
+    }
+
+    pub fn texture(self, view: &'a wgpu::TextureView) -> Self {
+        self.push(wgpu::BindingResource::TextureView(view))
+    }
+
+    pub fn sampler(self, sampler: &'a wgpu::Sampler) -> Self {
+        self.push(wgpu::BindingResource::Sampler(sampler))
+    }
+
+    pub fn build(self, device: &wgpu::Device, label: &str) -> wgpu::BindGroup {
+        self.create(device, label)
+    }
+}
+
+impl<'a, K> BindGroupBuilder<'a, K> {
+    // `layout` is built from `builder`, whose entries the resources are checked against
+    pub fn checked(layout: &'a wgpu::BindGroupLayout, _builder: &BindGroupLayoutBuilder<K>) -> Self {
+        Self {
+            layout,
+            entries: Vec::new(),
+            kinds:   PhantomData,
+        }
+    }
+
+    fn push<Rest>(mut self, resource: wgpu::BindingResource<'a>) -> BindGroupBuilder<'a, Rest> {
+        self.entries.push(wgpu::BindGroupEntry {
+            binding: self.entries.len() as u32,
+            resource,
+        });
+
+        BindGroupBuilder {
+            layout:  self.layout,
+            entries: self.entries,
+            kinds:   PhantomData,
+        }
+    }
+
+    fn create(self, device: &wgpu::Device, label: &str) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout:  self.layout,
+            entries: &self.entries,
+            label:   Some(label),
+        })
+    }
+}
+
+impl<'a, Rest> BindGroupBuilder<'a, (kind::Uniform, Rest)> {
+    pub fn uniform(self, buffer: &'a wgpu::Buffer) -> BindGroupBuilder<'a, Rest> {
+        self.push(buffer.as_entire_binding())
+    }
+}
+
+impl<'a, Rest> BindGroupBuilder<'a, (kind::Storage, Rest)> {
+    pub fn storage(self, buffer: &'a wgpu::Buffer) -> BindGroupBuilder<'a, Rest> {
+        self.push(buffer.as_entire_binding())
+    }
+}
+
+impl<'a, Rest> BindGroupBuilder<'a, (kind::Texture, Rest)> {
+    pub fn texture(self, view: &'a wgpu::TextureView) -> BindGroupBuilder<'a, Rest> {
+        self.push(wgpu::BindingResource::TextureView(view))
+    }
+}
+
+impl<'a, Rest> BindGroupBuilder<'a, (kind::Sampler, Rest)> {
+    pub fn sampler(self, sampler: &'a wgpu::Sampler) -> BindGroupBuilder<'a, Rest> {
+        self.push(wgpu::BindingResource::Sampler(sampler))
+    }
+}
+
+// Every entry has its resource
+impl<'a> BindGroupBuilder<'a, ()> {
+    pub fn build(self, device: &wgpu::Device, label: &str) -> wgpu::BindGroup {
+        self.create(device, label)
+    }
+}
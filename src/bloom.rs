@@ -0,0 +1,239 @@
+// Post effect where bright parts of the scene bleed light into their surroundings. A bright pass
+// keeps what's over the threshold at half resolution, that's downsampled into a chain of smaller
+// copies which are each blurred, and all of them are added back over the scene.
+//
+// The scene target has the surface's format, so anything brighter than 1.0, like emissive
+// materials, saturates there. The threshold sits just under 1.0 by default so that whatever
+// saturated blooms and little else does.
+//
+// Needs compute shaders, so it isn't available on WebGL.
+
+use crate::{
+    bind_group,
+    compute::{self, Blur, BlurKernel, MipChain, TextureFilters},
+    crash_report,
+    pass::PostEffect,
+    renderer::GpuContext,
+    uniform::{Uniform, UniformBuffer},
+};
+
+const CHAIN_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct BloomParams {
+    threshold:   f32,
+    knee:        f32,
+    intensity:   f32,
+    levels:      u32,
+    srgb_target: u32,
+    _padding:    [u32; 3],
+}
+
+pub struct Bloom {
+    // Brightness where blooming is at full strength, in linear color
+    pub threshold:      f32,
+    // How far under the threshold it starts fading in
+    pub knee:           f32,
+    pub intensity:      f32,
+    // Levels of the chain, each half the size of the one before
+    pub levels:         u32,
+    params:             UniformBuffer<BloomParams>,
+    filters:            TextureFilters,
+    blur:               Blur,
+    bright_pipeline:    wgpu::ComputePipeline,
+    bright_layout:      wgpu::BindGroupLayout,
+    composite_pipeline: wgpu::RenderPipeline,
+    composite_layout:   wgpu::BindGroupLayout,
+    sampler:            wgpu::Sampler,
+    // Made in `prepare` once the scene's size is known
+    chain:              Option<MipChain>,
+    // Holds the horizontal half of each level's blur
+    scratch:            Option<MipChain>,
+    // What `levels` was when the chain was made, since small scenes get fewer
+    chain_levels:       u32,
+    srgb_target:        bool,
+}
+
+impl Bloom {
+    pub fn new(ctx: &GpuContext) -> Self {
+        let device = &ctx.device;
+
+        let bright_layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::COMPUTE)
+            .unfilterable_texture(wgpu::ShaderStages::COMPUTE, wgpu::TextureViewDimension::D2)
+            .storage_texture(wgpu::ShaderStages::COMPUTE, CHAIN_FORMAT, wgpu::StorageTextureAccess::WriteOnly, wgpu::TextureViewDimension::D2)
+            .build(device, "bloom_bright_bind_group_layout");
+
+        let bright_pipeline = compute::create_pipeline(device, &bright_layout, include_str!("bloom_bright.wgsl"), "Bloom Bright Pass");
+
+        let composite_layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::FRAGMENT)
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .sampler(wgpu::ShaderStages::FRAGMENT)
+            .build(device, "bloom_bind_group_layout");
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Bloom Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("bloom.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Bloom Pipeline Layout"),
+            bind_group_layouts:   &[&composite_layout],
+            push_constant_ranges: &[],
+        });
+
+        let composite_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label:    Some("Bloom Pipeline"),
+            layout:   Some(&pipeline_layout),
+            vertex:   wgpu::VertexState {
+                module:      &shader,
+                entry_point: "vs_main",
+                buffers:     &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module:      &shader,
+                entry_point: "fs_main",
+                targets:     &[Some(wgpu::ColorTargetState {
+                    format:     ctx.config.format,
+                    blend:      Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive:     wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample:   wgpu::MultisampleState::default(),
+            multiview:     None,
+        });
+
+        // Bilinear filtering smooths the smaller levels as they're stretched back up
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label:          Some("Bloom Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter:     wgpu::FilterMode::Linear,
+            min_filter:     wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            threshold:    0.95,
+            knee:         0.2,
+            intensity:    0.8,
+            levels:       5,
+            params:       UniformBuffer::new(device, "Bloom Params Buffer"),
+            filters:      TextureFilters::new(device, CHAIN_FORMAT),
+            blur:         Blur::new(device, &ctx.queue, BlurKernel::gaussian(2.0)),
+            bright_pipeline,
+            bright_layout,
+            composite_pipeline,
+            composite_layout,
+            sampler,
+            chain:        None,
+            scratch:      None,
+            chain_levels: 0,
+            srgb_target:  ctx.config.format.describe().srgb,
+        }
+    }
+
+    // Called once a frame before the effect is added. Remakes the chain when the scene's size or
+    // the number of levels changes.
+    pub fn prepare(&mut self, ctx: &GpuContext) {
+        let size  = ctx.render_size();
+        let half  = ((size.width / 2).max(1), (size.height / 2).max(1));
+        let stale = self.chain.as_ref().is_none_or(|chain| chain.size != half || self.chain_levels != self.levels);
+
+        if stale {
+            self.chain        = Some(MipChain::new(&ctx.device, CHAIN_FORMAT, half.0, half.1, self.levels, "Bloom Chain"));
+            self.scratch      = Some(MipChain::new(&ctx.device, CHAIN_FORMAT, half.0, half.1, self.levels, "Bloom Scratch"));
+            self.chain_levels = self.levels;
+        }
+
+        // The chain is capped for small scenes, so it has the final say on how many levels there are
+        let levels = self.chain.as_ref().map_or(0, |chain| chain.level_count() as u32);
+
+        self.params.write(&ctx.queue, &BloomParams {
+            threshold:   self.threshold,
+            knee:        self.knee.max(f32::EPSILON),
+            intensity:   self.intensity,
+            levels,
+            srgb_target: self.srgb_target as u32,
+            _padding:    [0; 3],
+        });
+    }
+
+    // The blurred bright pass with its smaller levels as mips, filled in while the effect is
+    // applied. None until the first `prepare`.
+    pub fn bright_pass(&self) -> Option<&wgpu::TextureView> {
+        self.chain.as_ref().map(|chain| &chain.view)
+    }
+}
+
+impl PostEffect for Bloom {
+    fn apply(
+        &self,
+        device:  &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        input:   &wgpu::TextureView,
+        output:  &wgpu::TextureView,
+    ) {
+        let (chain, scratch) = match (&self.chain, &self.scratch) {
+            (Some(chain), Some(scratch)) => (chain, scratch),
+            _                            => return,
+        };
+
+        let bright_group = bind_group::BindGroupBuilder::new(&self.bright_layout)
+            .uniform(self.params.buffer())
+            .texture(input)
+            .texture(chain.level(0))
+            .build(device, "bloom_bright_bind_group");
+
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Bloom Bright Pass"),
+            });
+
+            let (width, height) = chain.level_size(0);
+
+            crash_report::set_compute_pipeline(&mut compute_pass, &self.bright_pipeline, "Bloom Bright Pass");
+            compute_pass.set_bind_group(0, &bright_group, &[]);
+            compute_pass.dispatch_workgroups(compute::workgroups(width), compute::workgroups(height), 1);
+        }
+
+        // Each level is downsampled from the one above before it's blurred, so the blurs compound
+        // and the smallest levels spread furthest
+        for level in 0..chain.level_count() {
+            if level > 0 {
+                self.filters.downsample(device, encoder, chain.level(level - 1), chain.level(level), chain.level_size(level));
+            }
+
+            self.filters.blur(device, encoder, &self.blur, chain.level(level), scratch.level(level), chain.level(level), chain.level_size(level));
+        }
+
+        let composite_group = bind_group::BindGroupBuilder::new(&self.composite_layout)
+            .uniform(self.params.buffer())
+            .texture(input)
+            .texture(&chain.view)
+            .sampler(&self.sampler)
+            .build(device, "bloom_bind_group");
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Bloom Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view:           output,
+                resolve_target: None,
+                ops:  wgpu::Operations {
+                    load:  wgpu::LoadOp::Load,
+                    store: true
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        crash_report::set_pipeline(&mut render_pass, &self.composite_pipeline, "Bloom Pipeline");
+        render_pass.set_bind_group(0, &composite_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
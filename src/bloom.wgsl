@@ -0,0 +1,70 @@
+// Adds the blurred bloom levels over the scene
+
+struct BloomParams {
+    threshold:   f32,
+    knee:        f32,
+    intensity:   f32,
+    levels:      u32,
+    srgb_target: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: BloomParams;
+@group(0) @binding(1)
+var t_scene: texture_2d<f32>;
+@group(0) @binding(2)
+var t_bloom: texture_2d<f32>;
+@group(0) @binding(3)
+var s_bloom: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv:                  vec2<f32>,
+}
+
+// One triangle that covers the screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+
+    out.uv            = uv;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+
+    return out;
+}
+
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+    return select(pow((c + 0.055) / 1.055, vec3<f32>(2.4)), c / 12.92, c <= vec3<f32>(0.04045));
+}
+
+fn linear_to_srgb(c: vec3<f32>) -> vec3<f32> {
+    return select(1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055, c * 12.92, c <= vec3<f32>(0.0031308));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let scene = textureLoad(t_scene, vec2<i32>(in.clip_position.xy), 0);
+
+    var color = scene.rgb;
+
+    if (params.srgb_target == 0u) {
+        color = srgb_to_linear(color);
+    }
+
+    // Smaller levels spread wider, so summing them gives a tight core with a long tail
+    var bloom = vec3<f32>(0.0);
+
+    for (var level = 0u; level < params.levels; level = level + 1u) {
+        bloom = bloom + textureSampleLevel(t_bloom, s_bloom, in.uv, f32(level)).rgb;
+    }
+
+    color = color + bloom * params.intensity / f32(max(params.levels, 1u));
+
+    if (params.srgb_target == 0u) {
+        color = linear_to_srgb(color);
+    }
+
+    return vec4<f32>(color, scene.a);
+}
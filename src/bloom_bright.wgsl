@@ -0,0 +1,49 @@
+// Keeps the parts of the scene bright enough to bloom, at half resolution
+
+struct BloomParams {
+    threshold:   f32,
+    knee:        f32,
+    intensity:   f32,
+    levels:      u32,
+    srgb_target: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: BloomParams;
+@group(0) @binding(1)
+var src: texture_2d<f32>;
+@group(0) @binding(2)
+var dst: texture_storage_2d<rgba16float, write>;
+
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+    return select(pow((c + 0.055) / 1.055, vec3<f32>(2.4)), c / 12.92, c <= vec3<f32>(0.04045));
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let dst_size = vec2<i32>(textureDimensions(dst));
+    let src_max  = vec2<i32>(textureDimensions(src)) - 1;
+    let coord    = vec2<i32>(id.xy);
+
+    if (coord.x >= dst_size.x || coord.y >= dst_size.y) {
+        return;
+    }
+
+    let start = coord * 2;
+
+    var color = (textureLoad(src, min(start, src_max), 0).rgb
+        + textureLoad(src, min(start + vec2<i32>(1, 0), src_max), 0).rgb
+        + textureLoad(src, min(start + vec2<i32>(0, 1), src_max), 0).rgb
+        + textureLoad(src, min(start + vec2<i32>(1, 1), src_max), 0).rgb) * 0.25;
+
+    // The target encodes to sRGB on write, so otherwise the scene is still encoded
+    if (params.srgb_target == 0u) {
+        color = srgb_to_linear(color);
+    }
+
+    // Fades in over the knee below the threshold rather than cutting off hard
+    let brightness = max(color.r, max(color.g, color.b));
+    let weight     = smoothstep(params.threshold - params.knee, params.threshold, brightness);
+
+    textureStore(dst, coord, vec4<f32>(color * weight, 1.0));
+}
@@ -0,0 +1,544 @@
+use cgmath::prelude::*;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub min: cgmath::Point3<f32>,
+    pub max: cgmath::Point3<f32>,
+}
+
+impl Aabb {
+    // Inverted so that any union with it yields the other box
+    pub const EMPTY: Aabb = Aabb {
+        min: cgmath::Point3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+        max: cgmath::Point3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+    };
+
+    pub fn from_points<I: IntoIterator<Item = cgmath::Point3<f32>>>(points: I) -> Self {
+        points.into_iter().fold(Self::EMPTY, |aabb, p| aabb.union_point(p))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: cgmath::Point3::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y), self.min.z.min(other.min.z)),
+            max: cgmath::Point3::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y), self.max.z.max(other.max.z)),
+        }
+    }
+
+    pub fn union_point(&self, p: cgmath::Point3<f32>) -> Aabb {
+        self.union(&Aabb { min: p, max: p })
+    }
+
+    pub fn center(&self) -> cgmath::Point3<f32> {
+        self.min.midpoint(self.max)
+    }
+
+    pub fn extents(&self) -> cgmath::Vector3<f32> {
+        self.max - self.min
+    }
+
+    pub fn corners(&self) -> [cgmath::Point3<f32>; 8] {
+        let (a, b) = (self.min, self.max);
+
+        [
+            cgmath::Point3::new(a.x, a.y, a.z),
+            cgmath::Point3::new(b.x, a.y, a.z),
+            cgmath::Point3::new(a.x, b.y, a.z),
+            cgmath::Point3::new(b.x, b.y, a.z),
+            cgmath::Point3::new(a.x, a.y, b.z),
+            cgmath::Point3::new(b.x, a.y, b.z),
+            cgmath::Point3::new(a.x, b.y, b.z),
+            cgmath::Point3::new(b.x, b.y, b.z),
+        ]
+    }
+
+    // Box around the transformed corners, so it stays axis-aligned in the new space
+    pub fn transform(&self, matrix: &cgmath::Matrix4<f32>) -> Aabb {
+        Aabb::from_points(self.corners().iter().map(|c| matrix.transform_point(*c)))
+    }
+
+    pub fn bounding_sphere(&self) -> Sphere {
+        Sphere {
+            center: self.center(),
+            radius: self.extents().magnitude() / 2.0,
+        }
+    }
+}
+
+// A box in a space of its own, like a mesh's bounds under its instance's transform
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Obb {
+    pub bounds:    Aabb,
+    pub transform: cgmath::Matrix4<f32>,
+}
+
+impl Obb {
+    pub fn new(bounds: Aabb, transform: cgmath::Matrix4<f32>) -> Self {
+        Self { bounds, transform }
+    }
+
+    // Axis-aligned box around it, for the hierarchy and culling
+    pub fn world_bounds(&self) -> Aabb {
+        self.bounds.transform(&self.transform)
+    }
+}
+
+impl From<Aabb> for Obb {
+    fn from(bounds: Aabb) -> Self {
+        Self::new(bounds, cgmath::Matrix4::identity())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Sphere {
+    pub center: cgmath::Point3<f32>,
+    pub radius: f32,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Ray {
+    pub origin:    cgmath::Point3<f32>,
+    pub direction: cgmath::Vector3<f32>,
+}
+
+impl Ray {
+    // Distance along the ray to where it enters the box (0.0 if it starts inside)
+    pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<f32> {
+        self.intersect_aabb_normal(aabb).map(|(t, _)| t)
+    }
+
+    // Like `intersect_aabb`, along with the normal of the face the ray enters through. Starting
+    // inside, there's no face, so it's the normal facing back along the ray.
+    pub fn intersect_aabb_normal(&self, aabb: &Aabb) -> Option<(f32, cgmath::Vector3<f32>)> {
+        let mut t_min  = 0.0_f32;
+        let mut t_max  = f32::INFINITY;
+        let mut normal = -self.direction.normalize();
+
+        for axis in 0..3 {
+            let inv = 1.0 / self.direction[axis];
+            let t0  = (aabb.min[axis] - self.origin[axis]) * inv;
+            let t1  = (aabb.max[axis] - self.origin[axis]) * inv;
+
+            if t0.min(t1) > t_min {
+                t_min        = t0.min(t1);
+                normal       = cgmath::Vector3::zero();
+                normal[axis] = -self.direction[axis].signum();
+            }
+
+            t_max = t_max.min(t0.max(t1));
+        }
+
+        if t_min <= t_max {
+            Some((t_min, normal))
+        } else {
+            None
+        }
+    }
+
+    // Like `intersect_aabb_normal` for a box that's been moved, turned or scaled. The distance is
+    // still along this ray, and the normal is the face's in world space. Boxes scaled down to
+    // nothing can't be hit.
+    pub fn intersect_obb_normal(&self, obb: &Obb) -> Option<(f32, cgmath::Vector3<f32>)> {
+        let to_local = obb.transform.invert()?;
+        let local    = Ray {
+            origin:    to_local.transform_point(self.origin),
+            direction: to_local.transform_vector(self.direction),
+        };
+
+        let (t, normal) = local.intersect_aabb_normal(&obb.bounds)?;
+
+        if t > 0.0 {
+            // Normals go back through the inverse transpose
+            Some((t, to_local.transpose().transform_vector(normal).normalize()))
+        } else {
+            Some((t, -self.direction.normalize()))
+        }
+    }
+
+    pub fn intersect_sphere(&self, sphere: &Sphere) -> Option<f32> {
+        let to_center = sphere.center - self.origin;
+        let t         = to_center.dot(self.direction);
+        let dist2     = to_center.magnitude2() - t * t;
+        let r2        = sphere.radius * sphere.radius;
+
+        if dist2 > r2 {
+            return None;
+        }
+
+        let half_chord = (r2 - dist2).sqrt();
+
+        if t + half_chord < 0.0 {
+            None
+        } else {
+            Some((t - half_chord).max(0.0))
+        }
+    }
+}
+
+// Where a ray query landed
+#[derive(Debug, Copy, Clone)]
+pub struct Hit {
+    // Index of the item that was hit, as given to `Bvh::build` or `Bvh::build_oriented`
+    pub entity:   usize,
+    // Along the ray, in units of its direction's length
+    pub distance: f32,
+    pub position: cgmath::Point3<f32>,
+    pub normal:   cgmath::Vector3<f32>,
+}
+
+// Six planes facing into the view volume, stored as (normal, distance)
+#[derive(Debug, Copy, Clone)]
+pub struct Frustum {
+    planes: [cgmath::Vector4<f32>; 6],
+}
+
+impl Frustum {
+    // Works for both depth modes since wgpu clip space is 0 <= z <= w either way
+    pub fn from_view_proj(m: &cgmath::Matrix4<f32>) -> Self {
+        let (r0, r1, r2, r3) = (m.row(0), m.row(1), m.row(2), m.row(3));
+
+        let planes = [r3 + r0, r3 - r0, r3 + r1, r3 - r1, r2, r3 - r2]
+            .map(|p| p / p.truncate().magnitude());
+
+        Self { planes }
+    }
+
+    pub fn planes(&self) -> [cgmath::Vector4<f32>; 6] {
+        self.planes
+    }
+
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the plane normal
+            let p = cgmath::Vector3::new(
+                if plane.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+                if plane.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+                if plane.z >= 0.0 { aabb.max.z } else { aabb.min.z },
+            );
+
+            plane.truncate().dot(p) + plane.w >= 0.0
+        })
+    }
+
+    pub fn intersects_sphere(&self, sphere: &Sphere) -> bool {
+        self.planes.iter().all(|plane| {
+            plane.truncate().dot(sphere.center.to_vec()) + plane.w >= -sphere.radius
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct BvhNode {
+    bounds: Aabb,
+    // Leaves hold an item, inner nodes hold their children's node indices
+    item:   Option<usize>,
+    left:   usize,
+    right:  usize,
+}
+
+// Bounding volume hierarchy over scene objects, identified by the index they were added with.
+// Nodes are axis-aligned boxes, which only narrow down the items a ray could hit; rays are then
+// cast against each item's own box.
+#[derive(Debug, Clone, Default)]
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    items: Vec<Obb>,
+}
+
+impl Bvh {
+    pub fn build(items: &[Aabb]) -> Self {
+        Self::build_oriented(&items.iter().copied().map(Obb::from).collect::<Vec<_>>())
+    }
+
+    // For items that are turned or scaled, so rays hit them where they really are rather than
+    // anywhere in the axis-aligned boxes around them
+    pub fn build_oriented(items: &[Obb]) -> Self {
+        let mut entries = items.iter().enumerate().map(|(i, obb)| (obb.world_bounds(), i)).collect::<Vec<_>>();
+        let mut bvh     = Self { nodes: Vec::with_capacity(items.len() * 2), items: items.to_vec() };
+
+        if !entries.is_empty() {
+            bvh.build_node(&mut entries);
+        }
+
+        bvh
+    }
+
+    fn build_node(&mut self, entries: &mut [(Aabb, usize)]) -> usize {
+        let bounds = entries.iter().fold(Aabb::EMPTY, |acc, (aabb, _)| acc.union(aabb));
+        let index  = self.nodes.len();
+
+        self.nodes.push(BvhNode { bounds, item: None, left: 0, right: 0 });
+
+        if entries.len() == 1 {
+            self.nodes[index].item = Some(entries[0].1);
+            return index;
+        }
+
+        // Median split along the longest axis
+        let extents = bounds.extents();
+        let axis    = if extents.x >= extents.y && extents.x >= extents.z {
+            0
+        } else if extents.y >= extents.z {
+            1
+        } else {
+            2
+        };
+
+        // Total order, so boxes made of NaNs by a degenerate transform sort to an end rather than panic
+        entries.sort_by(|a, b| a.0.center()[axis].total_cmp(&b.0.center()[axis]));
+
+        let (left, right) = entries.split_at_mut(entries.len() / 2);
+        let left          = self.build_node(left);
+        let right         = self.build_node(right);
+
+        self.nodes[index].left  = left;
+        self.nodes[index].right = right;
+
+        index
+    }
+
+    // Closest item the ray hits, and how far along the ray it is
+    pub fn raycast(&self, ray: &Ray) -> Option<(usize, f32)> {
+        self.raycast_hit(ray).map(|hit| (hit.entity, hit.distance))
+    }
+
+    // Like `raycast`, with where on the item the ray landed and the normal of the face there
+    pub fn raycast_hit(&self, ray: &Ray) -> Option<Hit> {
+        let mut best: Option<Hit> = None;
+        let mut stack             = Vec::new();
+
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+
+            let t = match ray.intersect_aabb(&node.bounds) {
+                Some(t) => t,
+                None    => continue,
+            };
+
+            // Nothing inside can be closer than where the ray enters the node
+            if best.is_some_and(|best| t >= best.distance) {
+                continue;
+            }
+
+            match node.item {
+                Some(item) => {
+                    let closer = ray.intersect_obb_normal(&self.items[item])
+                        .filter(|(distance, _)| best.is_none_or(|best| *distance < best.distance));
+
+                    if let Some((distance, normal)) = closer {
+                        best = Some(Hit {
+                            entity:   item,
+                            distance,
+                            position: ray.origin + ray.direction * distance,
+                            normal,
+                        });
+                    }
+                }
+                None       => {
+                    stack.push(node.left);
+                    stack.push(node.right);
+                }
+            }
+        }
+
+        best
+    }
+
+    pub fn query_frustum(&self, frustum: &Frustum, out: &mut Vec<usize>) {
+        let mut stack = Vec::new();
+
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+
+            if !frustum.intersects_aabb(&node.bounds) {
+                continue;
+            }
+
+            match node.item {
+                Some(item) => out.push(item),
+                None       => {
+                    stack.push(node.left);
+                    stack.push(node.right);
+                }
+            }
+        }
+    }
+
+    pub fn node_bounds(&self) -> impl Iterator<Item = &Aabb> {
+        self.nodes.iter().map(|node| &node.bounds)
+    }
+
+    // Every node as (bounds, leaf item, left child, right child), the root first, for traversing
+    // the hierarchy somewhere else like the GPU
+    pub fn nodes(&self) -> impl Iterator<Item = (&Aabb, Option<usize>, usize, usize)> {
+        self.nodes.iter().map(|node| (&node.bounds, node.item, node.left, node.right))
+    }
+
+    // The items as they were built, by index
+    pub fn items(&self) -> &[Obb] {
+        &self.items
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cube(x: f32, y: f32, z: f32) -> Aabb {
+        Aabb {
+            min: cgmath::Point3::new(x - 0.5, y - 0.5, z - 0.5),
+            max: cgmath::Point3::new(x + 0.5, y + 0.5, z + 0.5),
+        }
+    }
+
+    // A row of unit cubes along x, two apart
+    fn row() -> Vec<Aabb> {
+        (0..5).map(|i| cube(i as f32 * 2.0, 0.0, 0.0)).collect()
+    }
+
+    fn ray(origin: (f32, f32, f32), direction: (f32, f32, f32)) -> Ray {
+        Ray {
+            origin:    origin.into(),
+            direction: direction.into(),
+        }
+    }
+
+    #[test]
+    fn build_covers_every_item() {
+        let items = row();
+        let bvh   = Bvh::build(&items);
+
+        let mut leaves = bvh.nodes().filter_map(|(_, item, _, _)| item).collect::<Vec<_>>();
+        leaves.sort();
+
+        assert_eq!(leaves, vec![0, 1, 2, 3, 4]);
+        assert_eq!(bvh.node_bounds().count(), 2 * items.len() - 1);
+        assert_eq!(*bvh.node_bounds().next().unwrap(), items.iter().fold(Aabb::EMPTY, |acc, aabb| acc.union(aabb)));
+    }
+
+    #[test]
+    fn build_handles_no_items_and_nan_bounds() {
+        assert!(Bvh::build(&[]).raycast(&ray((0.0, 0.0, -5.0), (0.0, 0.0, 1.0))).is_none());
+
+        let nan = Aabb {
+            min: cgmath::Point3::new(f32::NAN, f32::NAN, f32::NAN),
+            max: cgmath::Point3::new(f32::NAN, f32::NAN, f32::NAN),
+        };
+        let mut items = row();
+        items.insert(2, nan);
+
+        let bvh = Bvh::build(&items);
+
+        assert_eq!(bvh.nodes().filter_map(|(_, item, _, _)| item).count(), items.len());
+    }
+
+    #[test]
+    fn raycast_finds_the_closest_item() {
+        let bvh = Bvh::build(&row());
+
+        // Down the row from the far end, through every cube
+        let (item, t) = bvh.raycast(&ray((20.0, 0.0, 0.0), (-1.0, 0.0, 0.0))).unwrap();
+
+        assert_eq!(item, 4);
+        assert!((t - 11.5).abs() < 1e-5);
+
+        // Straight down onto the middle one
+        let hit = bvh.raycast_hit(&ray((4.0, 10.0, 0.0), (0.0, -1.0, 0.0))).unwrap();
+
+        assert_eq!(hit.entity, 2);
+        assert!((hit.distance - 9.5).abs() < 1e-5);
+        assert!((hit.position - cgmath::Point3::new(4.0, 0.5, 0.0)).magnitude() < 1e-5);
+        assert!((hit.normal - cgmath::Vector3::unit_y()).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn raycast_misses_between_and_behind_items() {
+        let bvh = Bvh::build(&row());
+
+        // Through the gap between the first two cubes
+        assert!(bvh.raycast(&ray((1.0, 10.0, 0.0), (0.0, -1.0, 0.0))).is_none());
+        // Pointing away from the row
+        assert!(bvh.raycast(&ray((-5.0, 0.0, 0.0), (-1.0, 0.0, 0.0))).is_none());
+    }
+
+    #[test]
+    fn raycast_hits_turned_items_where_they_are() {
+        // A unit cube turned 45 degrees about y, so its corners reach out to the sides of the
+        // box around it
+        let turned = Obb::new(cube(0.0, 0.0, 0.0), cgmath::Matrix4::from_angle_y(cgmath::Deg(45.0)));
+        let bvh    = Bvh::build_oriented(&[turned]);
+
+        // Through the corner of the box around it, alongside the face the cube turns that way
+        assert!(bvh.raycast(&ray((-5.0, 0.0, 6.2), (1.0, 0.0, -1.0))).is_none());
+
+        // Onto the face turned towards +x +z
+        let hit    = bvh.raycast_hit(&ray((5.0, 0.0, 5.0), (-1.0, 0.0, -1.0))).unwrap();
+        let facing = cgmath::Vector3::new(1.0, 0.0, 1.0).normalize();
+
+        assert_eq!(hit.entity, 0);
+        assert!((hit.position.to_vec().dot(facing) - 0.5).abs() < 1e-5);
+        assert!((hit.normal - facing).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn raycast_normals_follow_uneven_scales() {
+        // Stretched along x and turned, so the faces' normals aren't the turned axes scaled
+        let transform = cgmath::Matrix4::from_angle_z(cgmath::Deg(30.0)) * cgmath::Matrix4::from_nonuniform_scale(4.0, 1.0, 1.0);
+        let bvh       = Bvh::build_oriented(&[Obb::new(cube(0.0, 0.0, 0.0), transform)]);
+        let up        = (cgmath::Matrix4::from_angle_z(cgmath::Deg(30.0)) * cgmath::Vector4::unit_y()).truncate();
+
+        let hit = bvh.raycast_hit(&ray((up.x * 5.0, up.y * 5.0, 0.0), (-up.x, -up.y, 0.0))).unwrap();
+
+        assert!((hit.distance - 4.5).abs() < 1e-4);
+        assert!((hit.normal - up).magnitude() < 1e-5);
+
+        // Flattened to nothing, there's nothing to hit
+        let flat = Obb::new(cube(0.0, 0.0, 0.0), cgmath::Matrix4::from_nonuniform_scale(1.0, 0.0, 1.0));
+
+        assert!(Bvh::build_oriented(&[flat]).raycast(&ray((0.0, 5.0, 0.0), (0.0, -1.0, 0.0))).is_none());
+    }
+
+    #[test]
+    fn frustum_query_keeps_items_in_view() {
+        let bvh  = Bvh::build(&row());
+        let view = cgmath::Matrix4::look_at_rh(
+            cgmath::Point3::new(-10.0, 0.0, 0.0),
+            cgmath::Point3::new(0.0, 0.0, 0.0),
+            cgmath::Vector3::unit_y(),
+        );
+
+        // Far enough for the first two cubes, which are the only ones in front of the far plane
+        let proj    = cgmath::perspective(cgmath::Deg(45.0), 1.0, 0.1, 12.0);
+        let frustum = Frustum::from_view_proj(&(proj * view));
+
+        let mut visible = Vec::new();
+        bvh.query_frustum(&frustum, &mut visible);
+        visible.sort();
+
+        assert_eq!(visible, vec![0, 1]);
+
+        // Looking the other way, none of them
+        let behind = cgmath::Matrix4::look_at_rh(
+            cgmath::Point3::new(-10.0, 0.0, 0.0),
+            cgmath::Point3::new(-20.0, 0.0, 0.0),
+            cgmath::Vector3::unit_y(),
+        );
+        let frustum = Frustum::from_view_proj(&(proj * behind));
+
+        visible.clear();
+        bvh.query_frustum(&frustum, &mut visible);
+
+        assert!(visible.is_empty());
+    }
+}
@@ -0,0 +1,38 @@
+// The split sum's scale and bias on F0 for GGX, with n.v across and roughness down
+
+fn geometry_schlick_ggx(n_dot: f32, roughness: f32) -> f32 {
+    // Image based lighting's k, not the one for punctual lights
+    let k = roughness * roughness / 2.0;
+
+    return n_dot / (n_dot * (1.0 - k) + k);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let n_dot_v   = max(in.uv.x, 0.001);
+    let roughness = in.uv.y;
+    let v         = vec3<f32>(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+    let n         = vec3<f32>(0.0, 0.0, 1.0);
+
+    var scale = 0.0;
+    var bias  = 0.0;
+
+    for (var i = 0u; i < params.sample_count; i = i + 1u) {
+        let h       = importance_sample_ggx(hammersley(i, params.sample_count), n, roughness);
+        let l       = normalize(2.0 * dot(v, h) * h - v);
+        let n_dot_l = max(l.z, 0.0);
+        let n_dot_h = max(h.z, 0.0);
+        let v_dot_h = max(dot(v, h), 0.0);
+
+        if (n_dot_l > 0.0) {
+            let g       = geometry_schlick_ggx(n_dot_v, roughness) * geometry_schlick_ggx(n_dot_l, roughness);
+            let g_vis   = g * v_dot_h / (n_dot_h * n_dot_v);
+            let fresnel = pow(1.0 - v_dot_h, 5.0);
+
+            scale = scale + (1.0 - fresnel) * g_vis;
+            bias  = bias + fresnel * g_vis;
+        }
+    }
+
+    return vec4<f32>(scale, bias, 0.0, 1.0) / vec4<f32>(f32(params.sample_count), f32(params.sample_count), 1.0, 1.0);
+}
@@ -0,0 +1,344 @@
+use cgmath::prelude::*;
+use winit::event::*;
+
+use crate::{bounds::Ray, uniform::Uniform};
+
+// Translates scene from OpenGL's coordinate system to WGPU's
+#[rustfmt::skip]
+pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
+);
+
+// How depth is distributed in the depth buffer
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DepthMode {
+    // 0.0 at the near plane, 1.0 at the far plane
+    Standard,
+    // 1.0 at the near plane, 0.0 at the far plane. Float precision is densest near 0.0, which
+    // cancels out the perspective divide's bunching of depth near the camera.
+    ReverseZ,
+}
+
+impl DepthMode {
+    pub fn near_depth(&self) -> f32 {
+        match self {
+            DepthMode::Standard => 0.0,
+            DepthMode::ReverseZ => 1.0,
+        }
+    }
+
+    pub fn far_depth(&self) -> f32 {
+        match self {
+            DepthMode::Standard => 1.0,
+            DepthMode::ReverseZ => 0.0,
+        }
+    }
+
+    // Value the depth buffer is cleared to
+    pub fn clear_depth(&self) -> f32 {
+        self.far_depth()
+    }
+
+    // Passes fragments that are closer than what's in the depth buffer
+    pub fn compare(&self) -> wgpu::CompareFunction {
+        match self {
+            DepthMode::Standard => wgpu::CompareFunction::Less,
+            DepthMode::ReverseZ => wgpu::CompareFunction::GreaterEqual,
+        }
+    }
+
+    // Like `compare`, but also passes fragments at the same depth, e.g. over a depth prepass
+    pub fn compare_or_equal(&self) -> wgpu::CompareFunction {
+        match self {
+            DepthMode::Standard => wgpu::CompareFunction::LessEqual,
+            DepthMode::ReverseZ => wgpu::CompareFunction::GreaterEqual,
+        }
+    }
+
+    // `bias` with its signs set so it moves depth towards the camera
+    pub fn bias_toward_camera(&self, bias: wgpu::DepthBiasState) -> wgpu::DepthBiasState {
+        let sign = match self {
+            DepthMode::Standard => -1,
+            DepthMode::ReverseZ => 1,
+        };
+
+        wgpu::DepthBiasState {
+            constant:    bias.constant.abs() * sign,
+            slope_scale: bias.slope_scale.abs() * sign as f32,
+            clamp:       bias.clamp.abs() * sign as f32,
+        }
+    }
+
+    pub fn perspective(&self, fovy: cgmath::Deg<f32>, aspect: f32, znear: f32, zfar: f32) -> cgmath::Matrix4<f32> {
+        match self {
+            DepthMode::Standard => OPENGL_TO_WGPU_MATRIX * cgmath::perspective(fovy, aspect, znear, zfar),
+            DepthMode::ReverseZ => {
+                let f = 1.0 / (cgmath::Rad::from(fovy).0 / 2.0).tan();
+                let a = znear / (zfar - znear);
+                let b = zfar * znear / (zfar - znear);
+
+                // Maps -znear to 1.0 and -zfar to 0.0, directly in wgpu's clip space
+                cgmath::Matrix4::new(
+                    f / aspect, 0.0, 0.0,  0.0,
+                    0.0,        f,   0.0,  0.0,
+                    0.0,        0.0, a,   -1.0,
+                    0.0,        0.0, b,    0.0,
+                )
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Camera {
+    pub eye:        cgmath::Point3<f32>,
+    pub target:     cgmath::Point3<f32>,
+    pub up:         cgmath::Vector3<f32>,
+    pub aspect:     f32,
+    pub fovy:       f32,
+    pub znear:      f32,
+    pub zfar:       f32,
+    pub depth_mode: DepthMode,
+}
+
+impl Camera {
+    pub fn build_view_projections_matrix(&self) -> cgmath::Matrix4<f32> {
+        let view = cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up);
+        let proj = self.depth_mode.perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
+
+        proj * view
+    }
+
+    // Like `build_view_projections_matrix`, but with the near plane swapped for `plane`, given in
+    // world space and keeping what's on its positive side, so the rasterizer clips everything
+    // between the camera and the plane. The far plane is tilted to match, which costs depth
+    // precision the more the plane leans away from the usual near plane. Eric Lengyel's oblique
+    // frustum, for both depth modes.
+    pub fn build_oblique_view_projection(&self, plane: cgmath::Vector4<f32>) -> cgmath::Matrix4<f32> {
+        let view     = cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up);
+        let mut proj = self.depth_mode.perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
+
+        let (inverse_view, inverse_proj) = match (view.invert(), proj.invert()) {
+            (Some(view), Some(proj)) => (view, proj),
+            _                        => return proj * view,
+        };
+
+        // Planes move to view space by the inverse transpose of what moves points
+        let plane = inverse_view.transpose() * plane;
+
+        // The far corner of the frustum on the plane's side, which the tilted far plane passes through
+        let corner = inverse_proj * cgmath::Vector4::new(plane.x.signum(), plane.y.signum(), self.depth_mode.far_depth(), 1.0);
+        let w_row  = proj.row(3);
+        let near   = plane * (w_row.dot(corner) / plane.dot(corner));
+
+        // Clip space keeps 0 <= z <= w, with the near plane at z = 0, or at z = w with reverse-Z
+        let z_row = match self.depth_mode {
+            DepthMode::Standard => near,
+            DepthMode::ReverseZ => w_row - near,
+        };
+
+        proj.x.z = z_row.x;
+        proj.y.z = z_row.y;
+        proj.z.z = z_row.z;
+        proj.w.z = z_row.w;
+
+        proj * view
+    }
+
+    // Ray from the eye through a pixel, with (0, 0) at the top left of the window
+    pub fn screen_ray(&self, x: f64, y: f64, width: u32, height: u32) -> Option<Ray> {
+        let inverse = self.build_view_projections_matrix().invert()?;
+        let x       = (2.0 * x / width as f64 - 1.0) as f32;
+        let y       = (1.0 - 2.0 * y / height as f64) as f32;
+        let near    = inverse * cgmath::Vector4::new(x, y, self.depth_mode.near_depth(), 1.0);
+        let far     = inverse * cgmath::Vector4::new(x, y, self.depth_mode.far_depth(), 1.0);
+        let origin  = near.truncate() / near.w;
+
+        Some(Ray {
+            origin:    cgmath::Point3::from_vec(origin),
+            direction: (far.truncate() / far.w - origin).normalize(),
+        })
+    }
+}
+
+// A plane every point is in front of
+const NO_CLIP_PLANE: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+pub struct CameraUniform {
+    // We can't use cgmath with bytemuck directly so we have to convert the Matrix4 into a 4x4 f32 array
+    view_proj:     [ [f32; 4]; 4],
+    // Padded to a vec4 for WGSL's uniform layout
+    view_position: [f32; 4],
+    // Fragments where dot(clip_plane, position) < 0 are discarded, e.g. those behind a mirror
+    clip_plane:    [f32; 4],
+}
+
+impl Default for CameraUniform {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CameraUniform {
+    pub fn new() -> Self {
+        use cgmath::SquareMatrix;
+
+        Self {
+            view_proj:     cgmath::Matrix4::identity().into(),
+            view_position: [0.0; 4],
+            clip_plane:    NO_CLIP_PLANE,
+        }
+    }
+
+    pub fn update_view_proj(&mut self, camera: &Camera) {
+        self.view_position = camera.eye.to_homogeneous().into();
+        self.view_proj     = camera.build_view_projections_matrix().into();
+    }
+
+    pub fn set_clip_plane(&mut self, plane: Option<cgmath::Vector4<f32>>) {
+        self.clip_plane = plane.map_or(NO_CLIP_PLANE, Into::into);
+    }
+
+    // A view through a mirror or portal: `plane` becomes the near plane, see
+    // `Camera::build_oblique_view_projection`, and fragments behind it are discarded as well
+    pub fn update_view_proj_clipped(&mut self, camera: &Camera, plane: cgmath::Vector4<f32>) {
+        self.view_position = camera.eye.to_homogeneous().into();
+        self.view_proj     = camera.build_oblique_view_projection(plane).into();
+        self.clip_plane    = plane.into();
+    }
+}
+
+const MOUSE_SENSITIVITY: f32 = 0.2; // degrees per pixel
+
+pub struct CameraController {
+    speed:               f32,
+    is_up_pressed:       bool,
+    is_down_pressed:     bool,
+    is_forward_pressed:  bool,
+    is_backward_pressed: bool,
+    is_left_pressed:     bool,
+    is_right_pressed:    bool,
+    rotate_horizontal:   f32,
+    rotate_vertical:     f32,
+}
+
+impl CameraController {
+    pub fn new(speed: f32) -> Self {
+        Self {
+            speed,
+            is_up_pressed:       false,
+            is_down_pressed:     false,
+            is_forward_pressed:  false,
+            is_backward_pressed: false,
+            is_left_pressed:     false,
+            is_right_pressed:    false,
+            rotate_horizontal:   0.0,
+            rotate_vertical:     0.0,
+        }
+    }
+
+    pub fn process_events(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state,
+                    virtual_keycode: Some(keycode),
+                    ..
+                },
+                ..
+            } => {
+                let is_pressed = *state == ElementState::Pressed;
+
+                match keycode {
+                    VirtualKeyCode::Space => {
+                        self.is_up_pressed = is_pressed;
+                        true
+                    }
+                    VirtualKeyCode::LShift => {
+                        self.is_down_pressed = is_pressed;
+                        true
+                    }
+                    VirtualKeyCode::W | VirtualKeyCode::Up => {
+                        self.is_forward_pressed = is_pressed;
+                        true
+                    }
+                    VirtualKeyCode::A | VirtualKeyCode::Left => {
+                        self.is_left_pressed = is_pressed;
+                        true
+                    }
+                    VirtualKeyCode::S | VirtualKeyCode::Down => {
+                        self.is_backward_pressed = is_pressed;
+                        true
+                    }
+                    VirtualKeyCode::D | VirtualKeyCode::Right => {
+                        self.is_right_pressed = is_pressed;
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            _ => false
+        }
+    }
+
+    // Mouse-look, fed from raw mouse motion while the cursor is grabbed
+    pub fn process_mouse(&mut self, dx: f64, dy: f64) {
+        self.rotate_horizontal += dx as f32;
+        self.rotate_vertical   += dy as f32;
+    }
+
+    pub fn update_camera(&mut self, camera: &mut Camera) {
+        let forward      = camera.target - camera.eye;
+        let forward_norm = forward.normalize();
+        let forward_mag  = forward.magnitude();
+
+        // Prevents glitching when camera gets too close to the center of the screen
+        if self.is_forward_pressed && forward_mag > self.speed {
+            camera.eye += forward_norm * self.speed;
+        }
+        if self.is_backward_pressed {
+            camera.eye -= forward_norm * self.speed;
+        }
+
+        let right = forward_norm.cross(camera.up);
+
+        // Redo radius calculation incase the forward/backward is pressed
+        let forward     = camera.target - camera.eye;
+        let forward_mag = forward.magnitude();
+
+        if self.is_right_pressed {
+            // Rescale the distance between the target and eye so that it doesn't change.
+            // Therefore the eye still lies on the circle made by the target and eye.
+            camera.eye = camera.target - (forward + right * self.speed).normalize() * forward_mag;
+        }
+        if self.is_left_pressed {
+            camera.eye = camera.target - (forward - right * self.speed).normalize() * forward_mag;
+        }
+
+        // Orbit the eye around the target
+        if self.rotate_horizontal != 0.0 || self.rotate_vertical != 0.0 {
+            let offset = camera.eye - camera.target;
+            let right  = offset.cross(camera.up).normalize();
+            let yaw    = cgmath::Quaternion::from_axis_angle(camera.up, cgmath::Deg(-self.rotate_horizontal * MOUSE_SENSITIVITY));
+            let pitch  = cgmath::Quaternion::from_axis_angle(right, cgmath::Deg(self.rotate_vertical * MOUSE_SENSITIVITY));
+
+            let rotated = (yaw * pitch).rotate_vector(offset);
+
+            // Stop short of the poles, where `look_at` flips
+            let offset = if rotated.normalize().dot(camera.up).abs() < 0.99 {
+                rotated
+            } else {
+                yaw.rotate_vector(offset)
+            };
+
+            camera.eye             = camera.target + offset;
+            self.rotate_horizontal = 0.0;
+            self.rotate_vertical   = 0.0;
+        }
+    }
+}
@@ -0,0 +1,12 @@
+// The camera every lit shader is drawn with, see `CameraUniform` in camera.rs. Prepended ahead
+// of `lights.wgsl`, whose contact shadows march through the camera's view.
+
+struct CameraUniform {
+    view_proj:     mat4x4<f32>,
+    view_position: vec4<f32>,
+    clip_plane:    vec4<f32>,
+}
+
+@group(1) @binding(0)
+var<uniform> camera: CameraUniform;
+
@@ -0,0 +1,234 @@
+// Smoothing and effects layered on top of whatever moves the camera: damped following of a moving
+// target, eased field of view changes, and screen shake. Each works on a `Camera` after the
+// controller or a camera path has had its turn, so they combine with either.
+
+use std::time::Duration;
+
+use cgmath::prelude::*;
+
+use crate::{camera::Camera, camera_path::Easing};
+
+// Moves `current` towards `target` like a critically damped spring, arriving in about
+// `smooth_time` seconds without overshooting. `velocity` carries over between calls.
+pub fn smooth_damp(
+    current:     cgmath::Vector3<f32>,
+    target:      cgmath::Vector3<f32>,
+    velocity:    &mut cgmath::Vector3<f32>,
+    smooth_time: f32,
+    dt:          f32,
+) -> cgmath::Vector3<f32> {
+    if smooth_time <= 0.0 {
+        *velocity = cgmath::Vector3::zero();
+        return target;
+    }
+
+    let omega = 2.0 / smooth_time;
+    let x     = omega * dt;
+    // A cheap and stable approximation of e^-x
+    let decay = 1.0 / (1.0 + x + 0.48 * x * x + 0.235 * x * x * x);
+
+    let change = current - target;
+    let temp   = (*velocity + change * omega) * dt;
+
+    *velocity = (*velocity - temp * omega) * decay;
+
+    let result = target + (change + temp) * decay;
+
+    // Landing past the target would overshoot, so stop on it instead
+    if (target - current).dot(result - target) > 0.0 {
+        *velocity = cgmath::Vector3::zero();
+        return target;
+    }
+
+    result
+}
+
+// Keeps a moving target in view
+pub struct CameraFollow {
+    // Seconds to catch up with the target, longer is lazier
+    pub smooth_time: f32,
+    eye_velocity:    cgmath::Vector3<f32>,
+    target_velocity: cgmath::Vector3<f32>,
+}
+
+impl CameraFollow {
+    pub fn new(smooth_time: f32) -> Self {
+        Self {
+            smooth_time,
+            eye_velocity:    cgmath::Vector3::zero(),
+            target_velocity: cgmath::Vector3::zero(),
+        }
+    }
+
+    // Moves the point the camera looks at towards `position`, taking the eye along at the same
+    // offset. The controller can still orbit and zoom around the point as it moves.
+    pub fn track(&mut self, camera: &mut Camera, position: cgmath::Point3<f32>, dt: Duration) {
+        let offset = camera.eye - camera.target;
+        let target = smooth_damp(camera.target.to_vec(), position.to_vec(), &mut self.target_velocity, self.smooth_time, dt.as_secs_f32());
+
+        camera.target = cgmath::Point3::from_vec(target);
+        camera.eye    = camera.target + offset;
+    }
+
+    // Chases a transform from `offset` in its own space, e.g. behind and above a vehicle, so the
+    // camera swings around as it turns
+    pub fn chase(
+        &mut self,
+        camera:   &mut Camera,
+        position: cgmath::Point3<f32>,
+        rotation: cgmath::Quaternion<f32>,
+        offset:   cgmath::Vector3<f32>,
+        dt:       Duration,
+    ) {
+        let dt  = dt.as_secs_f32();
+        let eye = position + rotation.rotate_vector(offset);
+
+        camera.eye    = cgmath::Point3::from_vec(smooth_damp(camera.eye.to_vec(), eye.to_vec(), &mut self.eye_velocity, self.smooth_time, dt));
+        camera.target = cgmath::Point3::from_vec(smooth_damp(camera.target.to_vec(), position.to_vec(), &mut self.target_velocity, self.smooth_time, dt));
+    }
+
+    // Forgets the speed built up, e.g. after the camera has been moved some other way
+    pub fn reset(&mut self) {
+        self.eye_velocity    = cgmath::Vector3::zero();
+        self.target_velocity = cgmath::Vector3::zero();
+    }
+}
+
+// Eases the field of view from one value to another over time
+pub struct FovTransition {
+    pub easing: Easing,
+    from:       f32,
+    to:         f32,
+    duration:   f32,
+    elapsed:    f32,
+}
+
+impl FovTransition {
+    pub fn new(easing: Easing) -> Self {
+        Self {
+            easing,
+            from:     0.0,
+            to:       0.0,
+            duration: 0.0,
+            elapsed:  0.0,
+        }
+    }
+
+    // Starts from wherever the camera's field of view is now, so transitions can interrupt each other
+    pub fn start(&mut self, camera: &Camera, fovy: f32, duration: f32) {
+        self.from     = camera.fovy;
+        self.to       = fovy;
+        self.duration = duration;
+        self.elapsed  = 0.0;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.elapsed < self.duration
+    }
+
+    // Where the current transition ends up, even if it hasn't got there yet
+    pub fn destination(&self) -> f32 {
+        self.to
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, dt: Duration) {
+        if !self.is_active() {
+            return;
+        }
+
+        self.elapsed = (self.elapsed + dt.as_secs_f32()).min(self.duration);
+
+        let t = self.easing.apply(self.elapsed / self.duration);
+
+        camera.fovy = self.from + (self.to - self.from) * t;
+    }
+}
+
+// Trauma-based shake: hits add trauma, which wears off over time, and the shake grows with its
+// square so small knocks stay subtle while big ones are violent. The motion is smooth noise
+// rather than random jumps, so it reads as the camera being jolted rather than the image tearing.
+pub struct CameraShake {
+    // Degrees of yaw, pitch and roll at full trauma
+    pub max_angle:  f32,
+    // World units the eye moves at full trauma
+    pub max_offset: f32,
+    // How fast the shake wobbles, roughly in shakes per second
+    pub frequency:  f32,
+    // Trauma lost per second
+    pub decay:      f32,
+    trauma:         f32,
+    time:           f32,
+}
+
+impl Default for CameraShake {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CameraShake {
+    pub fn new() -> Self {
+        Self {
+            max_angle:  4.0,
+            max_offset: 0.15,
+            frequency:  12.0,
+            decay:      1.2,
+            trauma:     0.0,
+            time:       0.0,
+        }
+    }
+
+    // Trauma is kept between 0.0 and 1.0
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).clamp(0.0, 1.0);
+    }
+
+    pub fn trauma(&self) -> f32 {
+        self.trauma
+    }
+
+    pub fn update(&mut self, dt: Duration) {
+        let dt = dt.as_secs_f32();
+
+        self.time  += dt;
+        self.trauma = (self.trauma - self.decay * dt).max(0.0);
+    }
+
+    // A copy of `camera` knocked about by the shake, to render with. The camera itself is left
+    // alone, so the shake never builds up in it or fights whatever is moving it.
+    pub fn shaken(&self, camera: &Camera) -> Camera {
+        let mut shaken = camera.clone();
+        let shake      = self.trauma * self.trauma;
+
+        if shake <= 0.0 {
+            return shaken;
+        }
+
+        let t       = self.time * self.frequency;
+        let forward = (camera.target - camera.eye).normalize();
+        let right   = forward.cross(camera.up).normalize();
+        let up      = right.cross(forward);
+
+        let yaw   = cgmath::Quaternion::from_axis_angle(up, cgmath::Deg(self.max_angle * shake * noise(t, 0.0)));
+        let pitch = cgmath::Quaternion::from_axis_angle(right, cgmath::Deg(self.max_angle * shake * noise(t, 1.0)));
+        let roll  = cgmath::Quaternion::from_axis_angle(forward, cgmath::Deg(self.max_angle * shake * noise(t, 2.0)));
+        let turn  = yaw * pitch * roll;
+
+        let offset = (right * noise(t, 3.0) + up * noise(t, 4.0)) * self.max_offset * shake;
+
+        shaken.eye    = camera.eye + offset;
+        shaken.target = shaken.eye + turn.rotate_vector(camera.target - camera.eye);
+        shaken.up     = turn.rotate_vector(camera.up);
+
+        shaken
+    }
+}
+
+// Smooth noise from -1.0 to 1.0, a different curve for each `seed`
+fn noise(t: f32, seed: f32) -> f32 {
+    let a = (t + seed * 17.0).sin();
+    let b = (t * 2.3 + seed * 31.0).sin() * 0.5;
+    let c = (t * 4.7 + seed * 53.0).sin() * 0.25;
+
+    (a + b + c) / 1.75
+}
@@ -0,0 +1,198 @@
+// Keyframed camera paths for fly-throughs. The eye and the point it looks at each follow a
+// Catmull-Rom spline through the keys, with easing applied segment by segment.
+//
+// Playback normally follows the frame time, but can step a fixed amount per frame instead so
+// that every run lands on exactly the same camera for the same frame number, which is what
+// recording a video frame by frame needs.
+
+use crate::{
+    camera::Camera,
+    curves::{catmull_rom, Curve, CurveKind},
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Easing {
+    #[default]
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+
+        match self {
+            Easing::Linear    => t,
+            Easing::EaseIn    => t * t,
+            Easing::EaseOut   => t * (2.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct CameraKey {
+    // Seconds from the start of the path
+    pub time:   f32,
+    pub eye:    cgmath::Point3<f32>,
+    pub target: cgmath::Point3<f32>,
+    // How the segment from this key to the next one is paced
+    pub easing: Easing,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CameraPath {
+    keys: Vec<CameraKey>,
+}
+
+impl CameraPath {
+    pub fn new() -> Self {
+        Self { keys: Vec::new() }
+    }
+
+    // Keys can be added in any order, they're kept sorted by time
+    pub fn add_key(&mut self, key: CameraKey) -> &mut Self {
+        let index = self.keys.partition_point(|k| k.time <= key.time);
+
+        self.keys.insert(index, key);
+        self
+    }
+
+    pub fn keys(&self) -> &[CameraKey] {
+        &self.keys
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.keys.last().map_or(0.0, |key| key.time)
+    }
+
+    // The spline the eye follows, for drawing the track. Easing only changes the pace along it.
+    pub fn eye_curve(&self) -> Curve {
+        Curve::new(CurveKind::CatmullRom, self.keys.iter().map(|key| key.eye).collect())
+    }
+
+    // The eye and target at `time`, held at the first and last keys outside the path
+    pub fn sample(&self, time: f32) -> Option<(cgmath::Point3<f32>, cgmath::Point3<f32>)> {
+        let first = self.keys.first()?;
+        let last  = self.keys.last()?;
+
+        if time <= first.time {
+            return Some((first.eye, first.target));
+        }
+        if time >= last.time {
+            return Some((last.eye, last.target));
+        }
+
+        // The segment `time` falls in runs from key `i` to `i + 1`
+        let i      = self.keys.partition_point(|k| k.time <= time) - 1;
+        let from   = &self.keys[i];
+        let to     = &self.keys[i + 1];
+        let length = to.time - from.time;
+        let t      = if length > 0.0 { from.easing.apply((time - from.time) / length) } else { 1.0 };
+
+        // The ends repeat their key, so the path starts and stops along the first and last segments
+        let before = &self.keys[i.saturating_sub(1)];
+        let after  = &self.keys[(i + 2).min(self.keys.len() - 1)];
+
+        Some((
+            catmull_rom(before.eye, from.eye, to.eye, after.eye, t),
+            catmull_rom(before.target, from.target, to.target, after.target, t),
+        ))
+    }
+}
+
+// Plays a path back onto a camera, with play, pause and scrubbing
+pub struct CameraPathPlayer {
+    pub path:    CameraPath,
+    pub looping: bool,
+    time:        f32,
+    playing:     bool,
+    // Seconds advanced each update in deterministic mode, regardless of the frame time
+    fixed_step:  Option<f32>,
+    frame:       u32,
+}
+
+impl CameraPathPlayer {
+    pub fn new(path: CameraPath) -> Self {
+        Self {
+            path,
+            looping:    false,
+            time:       0.0,
+            playing:    false,
+            fixed_step: None,
+            frame:      0,
+        }
+    }
+
+    pub fn play(&mut self) {
+        // Playing a finished path starts it over
+        if self.is_finished() {
+            self.seek(0.0);
+        }
+
+        self.playing = true;
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    // Updates taken since the last seek, which in deterministic mode numbers the frames
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !self.looping && self.time >= self.path.duration()
+    }
+
+    // Jumps to `time`, playing or not
+    pub fn seek(&mut self, time: f32) {
+        self.time  = time.clamp(0.0, self.path.duration());
+        self.frame = 0;
+    }
+
+    // Steps `1.0 / fps` seconds per update from now on, or follows the frame time again with None
+    pub fn set_deterministic(&mut self, fps: Option<u32>) {
+        self.fixed_step = fps.map(|fps| 1.0 / fps.max(1) as f32);
+    }
+
+    // Advances playback. Stops at the end unless looping.
+    pub fn update(&mut self, dt: std::time::Duration) {
+        if !self.playing {
+            return;
+        }
+
+        let duration = self.path.duration();
+
+        self.time  += self.fixed_step.unwrap_or(dt.as_secs_f32());
+        self.frame += 1;
+
+        if self.time >= duration {
+            if self.looping && duration > 0.0 {
+                self.time %= duration;
+            } else {
+                self.time    = duration;
+                self.playing = false;
+            }
+        }
+    }
+
+    // Moves `camera` to where the path is at the current time. Leaves it alone if the path is empty.
+    pub fn apply(&self, camera: &mut Camera) {
+        if let Some((eye, target)) = self.path.sample(self.time) {
+            camera.eye    = eye;
+            camera.target = target;
+        }
+    }
+}
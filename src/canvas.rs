@@ -0,0 +1,445 @@
+// Filled and stroked 2D shapes: circles, rounded rectangles, arbitrary paths and the simpler
+// parts of SVG path data. Shapes are turned into triangles by lyon's tessellators as they're
+// drawn, so curves come out smooth at any size without a shader of their own.
+//
+// A canvas is either the screen, in pixels from its top left, drawn over everything in the UI
+// layer, or a plane in the world, drawn among the scene's transparent geometry. Like `WideLines`,
+// shapes are queued during the frame and uploaded by `prepare`. Colors are linear with straight
+// alpha.
+
+use learn_wgpu_derive::VertexLayout;
+use lyon::{
+    math::{point, Box2D, Point},
+    path::{builder::BorderRadii, path::Builder, Path, Winding},
+    tessellation::{
+        BuffersBuilder, FillOptions, FillTessellator, FillVertex, StrokeOptions, StrokeTessellator, StrokeVertex,
+        VertexBuffers,
+    },
+};
+
+use crate::{
+    bind_group,
+    camera::Camera,
+    crash_report,
+    pass::{Drawable, RenderLayer},
+    renderer::{self, GpuContext},
+    texture::Texture,
+    uniform::{Uniform, UniformBuffer},
+};
+
+const INITIAL_VERTICES: usize = 4096;
+const INITIAL_INDICES:  usize = 8192;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CanvasSpace {
+    // Pixels from the top left of the window, y down
+    Screen,
+    // Canvas units on the plane `transform` places the canvas's x and y axes on, y up
+    World { transform: cgmath::Matrix4<f32> },
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+struct CanvasVertex {
+    #[location(0)]
+    position: [f32; 2],
+    #[location(1)]
+    color:    [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct CanvasUniform {
+    transform: [[f32; 4]; 4],
+}
+
+pub struct Canvas2D {
+    // How far flattened curves may stray from the true shape, in canvas units
+    pub tolerance:      f32,
+    space:              CanvasSpace,
+    pipeline:           wgpu::RenderPipeline,
+    uniform:            UniformBuffer<CanvasUniform>,
+    bind_group:         wgpu::BindGroup,
+    vertex_buffer:      wgpu::Buffer,
+    index_buffer:       wgpu::Buffer,
+    vertex_capacity:    usize,
+    index_capacity:     usize,
+    geometry:           VertexBuffers<CanvasVertex, u32>,
+    index_count:        u32,
+    fill_tessellator:   FillTessellator,
+    stroke_tessellator: StrokeTessellator,
+}
+
+impl Canvas2D {
+    pub fn new(ctx: &GpuContext, space: CanvasSpace) -> Self {
+        let device  = &ctx.device;
+        let uniform = UniformBuffer::new(device, "Canvas Uniform Buffer");
+
+        let layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::VERTEX)
+            .build(device, "canvas_bind_group_layout");
+
+        let bind_group = bind_group::BindGroupBuilder::new(&layout)
+            .uniform(uniform.buffer())
+            .build(device, "canvas_bind_group");
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Canvas Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("canvas.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Canvas Pipeline Layout"),
+            bind_group_layouts:   &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        // On the screen shapes draw over the finished frame. In the world they're tested against
+        // the scene's depth without adding to it, like the rest of the transparent geometry.
+        let depth_stencil = match space {
+            CanvasSpace::Screen       => None,
+            CanvasSpace::World { .. } => Some(renderer::depth_state(Texture::DEPTH_FORMAT, ctx.depth_mode.compare(), false)),
+        };
+
+        // Tessellated triangles wind either way, and world canvases are seen from both sides
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label:    Some("Canvas Pipeline"),
+            layout:   Some(&pipeline_layout),
+            vertex:   wgpu::VertexState {
+                module:      &shader,
+                entry_point: "vs_main",
+                buffers:     &[CanvasVertex::layout()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module:      &shader,
+                entry_point: "fs_main",
+                targets:     &[Some(wgpu::ColorTargetState {
+                    format:     ctx.config.format,
+                    blend:      Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive:     wgpu::PrimitiveState::default(),
+            depth_stencil,
+            multisample:   wgpu::MultisampleState::default(),
+            multiview:     None,
+        });
+
+        Self {
+            tolerance:          0.1,
+            space,
+            pipeline,
+            uniform,
+            bind_group,
+            vertex_buffer:      create_buffer(device, INITIAL_VERTICES * std::mem::size_of::<CanvasVertex>(), wgpu::BufferUsages::VERTEX, "Canvas Vertex Buffer"),
+            index_buffer:       create_buffer(device, INITIAL_INDICES * std::mem::size_of::<u32>(), wgpu::BufferUsages::INDEX, "Canvas Index Buffer"),
+            vertex_capacity:    INITIAL_VERTICES,
+            index_capacity:     INITIAL_INDICES,
+            geometry:           VertexBuffers::new(),
+            index_count:        0,
+            fill_tessellator:   FillTessellator::new(),
+            stroke_tessellator: StrokeTessellator::new(),
+        }
+    }
+
+    // The layer the canvas draws in, to add it to
+    pub fn layer(&self) -> RenderLayer {
+        match self.space {
+            CanvasSpace::Screen       => RenderLayer::Ui,
+            CanvasSpace::World { .. } => RenderLayer::WorldTransparent,
+        }
+    }
+
+    // Moves a world canvas. Screen canvases stay where they are.
+    pub fn set_transform(&mut self, transform: cgmath::Matrix4<f32>) {
+        if let CanvasSpace::World { transform: current } = &mut self.space {
+            *current = transform;
+        }
+    }
+
+    pub fn fill(&mut self, path: &Path, color: [f32; 4]) {
+        let options = FillOptions::default().with_tolerance(self.tolerance);
+        let mut builder = BuffersBuilder::new(&mut self.geometry, |vertex: FillVertex| CanvasVertex {
+            position: vertex.position().to_array(),
+            color,
+        });
+
+        if let Err(e) = self.fill_tessellator.tessellate_path(path, &options, &mut builder) {
+            log::warn!("Couldn't fill canvas path: {:?}", e);
+        }
+    }
+
+    // Outlines `path` with a line `width` canvas units wide, centered on it
+    pub fn stroke(&mut self, path: &Path, width: f32, color: [f32; 4]) {
+        let options = StrokeOptions::default().with_line_width(width).with_tolerance(self.tolerance);
+        let mut builder = BuffersBuilder::new(&mut self.geometry, |vertex: StrokeVertex| CanvasVertex {
+            position: vertex.position().to_array(),
+            color,
+        });
+
+        if let Err(e) = self.stroke_tessellator.tessellate_path(path, &options, &mut builder) {
+            log::warn!("Couldn't stroke canvas path: {:?}", e);
+        }
+    }
+
+    pub fn fill_circle(&mut self, center: [f32; 2], radius: f32, color: [f32; 4]) {
+        self.fill(&circle(center, radius), color);
+    }
+
+    pub fn stroke_circle(&mut self, center: [f32; 2], radius: f32, width: f32, color: [f32; 4]) {
+        self.stroke(&circle(center, radius), width, color);
+    }
+
+    pub fn fill_rounded_rect(&mut self, min: [f32; 2], max: [f32; 2], radius: f32, color: [f32; 4]) {
+        self.fill(&rounded_rect(min, max, radius), color);
+    }
+
+    pub fn stroke_rounded_rect(&mut self, min: [f32; 2], max: [f32; 2], radius: f32, width: f32, color: [f32; 4]) {
+        self.stroke(&rounded_rect(min, max, radius), width, color);
+    }
+
+    // Uploads the shapes drawn since the last call, growing the buffers if needed. `camera` places
+    // world canvases and is ignored on the screen.
+    pub fn prepare(&mut self, ctx: &GpuContext, camera: &Camera) {
+        let transform = match self.space {
+            // Pixels to clip space, flipping y so it runs down the screen
+            CanvasSpace::Screen => {
+                let (width, height) = (ctx.config.width as f32, ctx.config.height as f32);
+
+                cgmath::Matrix4::from_translation(cgmath::Vector3::new(-1.0, 1.0, 0.0))
+                    * cgmath::Matrix4::from_nonuniform_scale(2.0 / width, -2.0 / height, 1.0)
+            }
+            CanvasSpace::World { transform } => camera.build_view_projections_matrix() * transform,
+        };
+
+        self.uniform.write(&ctx.queue, &CanvasUniform { transform: transform.into() });
+
+        let VertexBuffers { vertices, indices } = &mut self.geometry;
+
+        if vertices.len() > self.vertex_capacity {
+            self.vertex_capacity = vertices.len().next_power_of_two();
+            self.vertex_buffer   = create_buffer(&ctx.device, self.vertex_capacity * std::mem::size_of::<CanvasVertex>(), wgpu::BufferUsages::VERTEX, "Canvas Vertex Buffer");
+        }
+        if indices.len() > self.index_capacity {
+            self.index_capacity = indices.len().next_power_of_two();
+            self.index_buffer   = create_buffer(&ctx.device, self.index_capacity * std::mem::size_of::<u32>(), wgpu::BufferUsages::INDEX, "Canvas Index Buffer");
+        }
+
+        ctx.queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(vertices));
+        ctx.queue.write_buffer(&self.index_buffer, 0, bytemuck::cast_slice(indices));
+
+        self.index_count = indices.len() as u32;
+
+        vertices.clear();
+        indices.clear();
+    }
+}
+
+fn create_buffer(device: &wgpu::Device, size: usize, usage: wgpu::BufferUsages, label: &str) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label:              Some(label),
+        size:               size as wgpu::BufferAddress,
+        usage:              usage | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+impl Drawable for Canvas2D {
+    fn draw<'a>(&'a self, layer: RenderLayer, render_pass: &mut wgpu::RenderPass<'a>) {
+        if layer != self.layer() || self.index_count == 0 {
+            return;
+        }
+
+        crash_report::set_pipeline(render_pass, &self.pipeline, "Canvas Pipeline");
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.draw_indexed(0..self.index_count, 0, 0..1);
+    }
+}
+
+pub fn circle(center: [f32; 2], radius: f32) -> Path {
+    let mut builder = Path::builder();
+    builder.add_circle(point(center[0], center[1]), radius, Winding::Positive);
+    builder.build()
+}
+
+pub fn rounded_rect(min: [f32; 2], max: [f32; 2], radius: f32) -> Path {
+    let mut builder = Path::builder();
+    builder.add_rounded_rectangle(
+        &Box2D::new(point(min[0], min[1]), point(max[0], max[1])),
+        &BorderRadii::new(radius),
+        Winding::Positive,
+    );
+    builder.build()
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum SvgToken {
+    Command(char),
+    Number(f32),
+}
+
+// A path from SVG path data, as in a `<path d="...">`. Supports moves, lines, horizontal and
+// vertical lines, cubic and quadratic curves and closing, both absolute and relative, with
+// repeated arguments continuing the last command. Arcs and the smooth curve shorthands aren't.
+pub fn svg_path(data: &str) -> anyhow::Result<Path> {
+    let tokens = svg_tokens(data)?;
+    let mut at = 0;
+
+    let number = |at: &mut usize| match tokens.get(*at) {
+        Some(SvgToken::Number(n)) => {
+            *at += 1;
+            Ok(*n)
+        }
+        _ => Err(anyhow::anyhow!("Expected a number in SVG path data at token {}", *at)),
+    };
+
+    let mut builder = Path::builder();
+    let mut command = None;
+    let mut current = point(0.0, 0.0);
+    let mut start   = current;
+    let mut open    = false;
+
+    while at < tokens.len() {
+        if let SvgToken::Command(c) = tokens[at] {
+            command = Some(c);
+            at += 1;
+        }
+
+        let c = match command {
+            Some(c) => c,
+            None    => anyhow::bail!("SVG path data has numbers without a command at token {}", at),
+        };
+
+        let origin = if c.is_ascii_lowercase() { current.to_vector() } else { lyon::math::vector(0.0, 0.0) };
+
+        match c.to_ascii_uppercase() {
+            'M' => {
+                let to = point(number(&mut at)?, number(&mut at)?) + origin;
+
+                if open {
+                    builder.end(false);
+                }
+                builder.begin(to);
+
+                open    = true;
+                current = to;
+                start   = to;
+                // Pairs after the first are lines
+                command = Some(if c == 'm' { 'l' } else { 'L' });
+            }
+            'L' => {
+                let to = point(number(&mut at)?, number(&mut at)?) + origin;
+
+                begin_if_closed(&mut builder, &mut open, current);
+                builder.line_to(to);
+                current = to;
+            }
+            'H' => {
+                let to = point(number(&mut at)? + origin.x, current.y);
+
+                begin_if_closed(&mut builder, &mut open, current);
+                builder.line_to(to);
+                current = to;
+            }
+            'V' => {
+                let to = point(current.x, number(&mut at)? + origin.y);
+
+                begin_if_closed(&mut builder, &mut open, current);
+                builder.line_to(to);
+                current = to;
+            }
+            'C' => {
+                let ctrl1 = point(number(&mut at)?, number(&mut at)?) + origin;
+                let ctrl2 = point(number(&mut at)?, number(&mut at)?) + origin;
+                let to    = point(number(&mut at)?, number(&mut at)?) + origin;
+
+                begin_if_closed(&mut builder, &mut open, current);
+                builder.cubic_bezier_to(ctrl1, ctrl2, to);
+                current = to;
+            }
+            'Q' => {
+                let ctrl = point(number(&mut at)?, number(&mut at)?) + origin;
+                let to   = point(number(&mut at)?, number(&mut at)?) + origin;
+
+                begin_if_closed(&mut builder, &mut open, current);
+                builder.quadratic_bezier_to(ctrl, to);
+                current = to;
+            }
+            'Z' => {
+                if open {
+                    builder.end(true);
+                    open = false;
+                }
+
+                current = start;
+                // Takes no arguments, so whatever follows has to be a new command
+                command = None;
+            }
+            other => anyhow::bail!("Unsupported SVG path command '{}'", other),
+        }
+    }
+
+    if open {
+        builder.end(false);
+    }
+
+    Ok(builder.build())
+}
+
+// Drawing on after a close starts a new subpath where the last one started
+fn begin_if_closed(builder: &mut Builder, open: &mut bool, at: Point) {
+    if !*open {
+        builder.begin(at);
+        *open = true;
+    }
+}
+
+fn svg_tokens(data: &str) -> anyhow::Result<Vec<SvgToken>> {
+    let mut tokens = Vec::new();
+    let mut chars  = data.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() || c == ',' {
+            chars.next();
+            continue;
+        }
+
+        if c.is_ascii_alphabetic() && c != 'e' && c != 'E' {
+            tokens.push(SvgToken::Command(c));
+            chars.next();
+            continue;
+        }
+
+        // Numbers needn't be separated, so one runs until a character that can't continue it:
+        // a sign that doesn't follow an exponent, or a second decimal point
+        let mut end      = start;
+        let mut seen_dot = false;
+        let mut previous = None;
+
+        while let Some(&(i, c)) = chars.peek() {
+            let continues = c.is_ascii_digit()
+                || (c == '.' && !seen_dot)
+                || ((c == '-' || c == '+') && (i == start || matches!(previous, Some('e' | 'E'))))
+                || ((c == 'e' || c == 'E') && i != start);
+
+            if !continues {
+                break;
+            }
+
+            seen_dot |= c == '.';
+            previous  = Some(c);
+            end       = i + c.len_utf8();
+            chars.next();
+        }
+
+        if end == start {
+            anyhow::bail!("Unexpected '{}' in SVG path data", c);
+        }
+
+        tokens.push(SvgToken::Number(data[start..end].parse()?));
+    }
+
+    Ok(tokens)
+}
@@ -0,0 +1,36 @@
+// Filled and stroked 2D shapes, see canvas.rs. Vertices are in the canvas's own units, which
+// `transform` takes straight to clip space, whether the canvas is the screen or a plane in the
+// world.
+
+struct CanvasUniform {
+    transform: mat4x4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> canvas: CanvasUniform;
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    // Straight alpha
+    @location(1) color:    vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color:               vec4<f32>,
+}
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+
+    out.color         = in.color;
+    out.clip_position = canvas.transform * vec4<f32>(in.position, 0.0, 1.0);
+
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
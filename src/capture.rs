@@ -0,0 +1,70 @@
+// Frame captures with RenderDoc's in-application API. Only does anything when the app was
+// launched from RenderDoc, or has it injected, since that's what loads the library.
+
+use renderdoc::{RenderDoc, V110};
+use winit::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
+
+use crate::{plugin::Plugin, renderer::GpuContext};
+
+const CAPTURE_KEY: VirtualKeyCode = VirtualKeyCode::F12;
+
+// Captures the next frame when F12 is pressed
+pub struct RenderDocCapture {
+    renderdoc: Option<RenderDoc<V110>>,
+}
+
+impl RenderDocCapture {
+    pub fn new() -> Self {
+        let renderdoc = match RenderDoc::new() {
+            Ok(renderdoc) => Some(renderdoc),
+            Err(e) => {
+                log::info!("RenderDoc isn't attached, captures are disabled: {}", e);
+                None
+            }
+        };
+
+        Self { renderdoc }
+    }
+}
+
+impl Default for RenderDocCapture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Plugin for RenderDocCapture {
+    fn name(&self) -> &'static str {
+        "RenderDoc Capture"
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.renderdoc.is_some()
+    }
+
+    fn setup(&mut self, _ctx: &mut GpuContext) {
+        if let Some(renderdoc) = &mut self.renderdoc {
+            // Its own capture keys would clash with the app's, so captures only come from here
+            renderdoc.set_capture_keys::<renderdoc::InputButton>(&[]);
+            renderdoc.set_log_file_path_template("captures/learn_wgpu");
+        }
+    }
+
+    fn input(&mut self, _ctx: &GpuContext, event: &WindowEvent) -> bool {
+        match (event, &mut self.renderdoc) {
+            (WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(CAPTURE_KEY),
+                    ..
+                },
+                ..
+            }, Some(renderdoc)) => {
+                renderdoc.trigger_capture();
+                log::info!("Capturing the next frame with RenderDoc");
+                true
+            }
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,278 @@
+// Post effect that adjusts exposure and white balance, then grades the image through a 3D lookup
+// table, so the final look can be tuned without touching shaders
+
+use anyhow::*;
+use image::GenericImageView;
+
+use crate::{bind_group, crash_report, pass::PostEffect, renderer::GpuContext, texture::Texture, uniform::Uniform};
+
+// Size of the LUT used until one is loaded, which leaves colors as they are
+const IDENTITY_SIZE: u32 = 16;
+
+// A size x size x size grid of output colors as RGBA8, red varying fastest, then green, then blue
+pub struct Lut {
+    pub size: u32,
+    pub data: Vec<u8>,
+}
+
+impl Lut {
+    pub fn identity(size: u32) -> Self {
+        let max  = (size - 1) as f32;
+        let data = (0..size * size * size)
+            .flat_map(|i| {
+                let (r, g, b) = (i % size, i / size % size, i / (size * size));
+
+                [r, g, b].map(|c| (c as f32 / max * 255.0).round() as u8).into_iter().chain([255])
+            })
+            .collect();
+
+        Self { size, data }
+    }
+
+    // The usual strip layout: `size` slices of size x size side by side, with red across each
+    // slice, green down it and blue increasing from slice to slice
+    pub fn from_strip_image(bytes: &[u8]) -> Result<Self> {
+        let img           = image::load_from_memory(bytes)?;
+        let (width, size) = img.dimensions();
+
+        if width != size * size {
+            bail!("Expected a {}x{} strip for a LUT of size {}, found {}x{}", size * size, size, size, width, size);
+        }
+
+        let rgba = img.to_rgba8();
+        let data = (0..size * size * size)
+            .flat_map(|i| {
+                let (r, g, b) = (i % size, i / size % size, i / (size * size));
+
+                rgba.get_pixel(b * size + r, g).0
+            })
+            .collect();
+
+        Ok(Self { size, data })
+    }
+
+    // Adobe's .cube text format. Only 3D tables over the default 0-1 domain are supported.
+    pub fn from_cube(text: &str) -> Result<Self> {
+        let mut size = None;
+        let mut data = Vec::new();
+
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut words = line.split_whitespace();
+            let keyword   = words.next().unwrap_or_default();
+
+            let floats = |words: std::str::SplitWhitespace| -> Result<Vec<f32>> {
+                let values = words.map(str::parse::<f32>).collect::<Result<Vec<_>, _>>()?;
+
+                if values.len() != 3 {
+                    bail!("Expected 3 values in `{}`", line);
+                }
+                Ok(values)
+            };
+
+            match keyword {
+                "TITLE"       => {}
+                "LUT_1D_SIZE" => bail!("1D LUTs aren't supported"),
+                "LUT_3D_SIZE" => {
+                    size = Some(words.next().ok_or_else(|| anyhow!("Missing LUT_3D_SIZE value"))?.parse::<u32>()?);
+                }
+                "DOMAIN_MIN" | "DOMAIN_MAX" => {
+                    let default = if keyword == "DOMAIN_MIN" { 0.0 } else { 1.0 };
+
+                    if floats(words)?.iter().any(|&v| v != default) {
+                        bail!("Only the default 0-1 domain is supported");
+                    }
+                }
+                _ => {
+                    let rgb = floats(line.split_whitespace())?;
+
+                    data.extend(rgb.iter().map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8));
+                    data.push(255);
+                }
+            }
+        }
+
+        let size = size.ok_or_else(|| anyhow!("Missing LUT_3D_SIZE"))?;
+
+        if size < 2 || data.len() != (size * size * size * 4) as usize {
+            bail!("Expected {} entries for a LUT of size {}, found {}", size * size * size, size, data.len() / 4);
+        }
+
+        Ok(Self { size, data })
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct GradingParams {
+    exposure:    f32,
+    temperature: f32,
+    tint:        f32,
+    lut_size:    f32,
+    srgb_target: u32,
+    _padding:    [u32; 3],
+}
+
+pub struct ColorGrading {
+    // In stops, so 1.0 doubles the brightness
+    pub exposure:    f32,
+    // -1.0 (cool) to 1.0 (warm)
+    pub temperature: f32,
+    // -1.0 (green) to 1.0 (magenta)
+    pub tint:        f32,
+    pipeline:        wgpu::RenderPipeline,
+    layout:          wgpu::BindGroupLayout,
+    params:          wgpu::Buffer,
+    lut:             Texture,
+    lut_size:        u32,
+    identity:        bool,
+    srgb_target:     bool,
+}
+
+impl ColorGrading {
+    pub fn new(ctx: &GpuContext) -> Self {
+        let device = &ctx.device;
+
+        let layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::FRAGMENT)
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D3)
+            .sampler(wgpu::ShaderStages::FRAGMENT)
+            .build(device, "color_grading_bind_group_layout");
+
+        let params = device.create_buffer(&wgpu::BufferDescriptor {
+            label:              Some("Color Grading Params Buffer"),
+            size:               std::mem::size_of::<GradingParams>() as wgpu::BufferAddress,
+            usage:              wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Color Grading Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("color_grading.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Color Grading Pipeline Layout"),
+            bind_group_layouts:   &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label:    Some("Color Grading Pipeline"),
+            layout:   Some(&pipeline_layout),
+            vertex:   wgpu::VertexState {
+                module:      &shader,
+                entry_point: "vs_main",
+                buffers:     &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module:      &shader,
+                entry_point: "fs_main",
+                targets:     &[Some(wgpu::ColorTargetState {
+                    format:     ctx.config.format,
+                    blend:      Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive:     wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample:   wgpu::MultisampleState::default(),
+            multiview:     None,
+        });
+
+        let identity = Lut::identity(IDENTITY_SIZE);
+
+        Self {
+            exposure:    0.0,
+            temperature: 0.0,
+            tint:        0.0,
+            pipeline,
+            layout,
+            params,
+            lut:         create_lut_texture(device, &ctx.queue, &identity),
+            lut_size:    identity.size,
+            identity:    true,
+            srgb_target: ctx.config.format.describe().srgb,
+        }
+    }
+
+    pub fn set_lut(&mut self, ctx: &GpuContext, lut: &Lut) {
+        self.lut      = create_lut_texture(&ctx.device, &ctx.queue, lut);
+        self.lut_size = lut.size;
+        self.identity = false;
+    }
+
+    // True when applying the effect wouldn't change the image, so it can be left out
+    pub fn is_neutral(&self) -> bool {
+        self.identity && self.exposure == 0.0 && self.temperature == 0.0 && self.tint == 0.0
+    }
+
+    pub fn prepare(&self, queue: &wgpu::Queue) {
+        queue.write_buffer(&self.params, 0, bytemuck::cast_slice(&[GradingParams {
+            exposure:    self.exposure,
+            temperature: self.temperature,
+            tint:        self.tint,
+            lut_size:    self.lut_size as f32,
+            srgb_target: self.srgb_target as u32,
+            _padding:    [0; 3],
+        }]));
+    }
+}
+
+impl PostEffect for ColorGrading {
+    fn apply(
+        &self,
+        device:  &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        input:   &wgpu::TextureView,
+        output:  &wgpu::TextureView,
+    ) {
+        let bind_group = bind_group::BindGroupBuilder::new(&self.layout)
+            .uniform(&self.params)
+            .texture(input)
+            .texture(&self.lut.view)
+            .sampler(&self.lut.sampler)
+            .build(device, "color_grading_bind_group");
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Color Grading Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view:           output,
+                resolve_target: None,
+                ops:  wgpu::Operations {
+                    load:  wgpu::LoadOp::Load,
+                    store: true
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        crash_report::set_pipeline(&mut render_pass, &self.pipeline, "Color Grading Pipeline");
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+// Not sRGB: LUT entries are looked up and returned as display-encoded values. Trilinear filtering
+// blends between the grid points.
+fn create_lut_texture(device: &wgpu::Device, queue: &wgpu::Queue, lut: &Lut) -> Texture {
+    let size = wgpu::Extent3d {
+        width:                 lut.size,
+        height:                lut.size,
+        depth_or_array_layers: lut.size,
+    };
+
+    Texture::from_volume(
+        device,
+        queue,
+        &lut.data,
+        size,
+        wgpu::TextureFormat::Rgba8Unorm,
+        wgpu::AddressMode::ClampToEdge,
+        Some("Color Grading LUT"),
+    )
+}
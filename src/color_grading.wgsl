@@ -0,0 +1,81 @@
+// Exposure and white balance in linear space, then a 3D LUT over display-encoded colors
+
+struct GradingParams {
+    exposure:    f32,
+    temperature: f32,
+    tint:        f32,
+    lut_size:    f32,
+    // The target encodes to sRGB on write, so the input was linear and the output must be too
+    srgb_target: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: GradingParams;
+@group(0) @binding(1)
+var t_scene: texture_2d<f32>;
+@group(0) @binding(2)
+var t_lut: texture_3d<f32>;
+@group(0) @binding(3)
+var s_lut: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+}
+
+// One triangle that covers the screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+
+    return out;
+}
+
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+    return select(pow((c + 0.055) / 1.055, vec3<f32>(2.4)), c / 12.92, c <= vec3<f32>(0.04045));
+}
+
+fn linear_to_srgb(c: vec3<f32>) -> vec3<f32> {
+    return select(1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055, c * 12.92, c <= vec3<f32>(0.0031308));
+}
+
+// Warmth trades blue for red and tint trades green for magenta. Scaled back to the same luminance
+// so only the hue shifts.
+fn white_balance(color: vec3<f32>) -> vec3<f32> {
+    let balance = vec3<f32>(
+        1.0 + 0.2 * params.temperature,
+        1.0 - 0.2 * params.tint,
+        1.0 - 0.2 * params.temperature,
+    );
+    let luminance = dot(balance, vec3<f32>(0.2126, 0.7152, 0.0722));
+
+    return color * balance / luminance;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let scene = textureLoad(t_scene, vec2<i32>(in.clip_position.xy), 0);
+
+    var color = scene.rgb;
+
+    if (params.srgb_target == 0u) {
+        color = srgb_to_linear(color);
+    }
+
+    color = white_balance(color * exp2(params.exposure));
+
+    // Sample at texel centers so the ends of the range land on the first and last entries
+    let encoded = linear_to_srgb(clamp(color, vec3<f32>(0.0), vec3<f32>(1.0)));
+    let coords  = encoded * (params.lut_size - 1.0) / params.lut_size + 0.5 / params.lut_size;
+
+    var graded = textureSampleLevel(t_lut, s_lut, coords, 0.0).rgb;
+
+    if (params.srgb_target != 0u) {
+        graded = srgb_to_linear(graded);
+    }
+
+    return vec4<f32>(graded, scene.a);
+}
@@ -0,0 +1,513 @@
+// Reusable compute passes for processing textures: downsampling and mip generation, separable
+// blurs and a luminance histogram, plus reading results back without stalling. None of this is
+// available on WebGL, which has no compute shaders.
+//
+// Filters read with `textureLoad` and write through storage textures, so the format they write is
+// fixed when the pipelines are built. Shaders name it as STORAGE_FORMAT, which is substituted.
+
+use std::sync::mpsc;
+
+use crate::{bind_group, crash_report, uniform::Uniform};
+
+const WORKGROUP_SIZE: u32 = 8;
+
+// Widest blur in texels either side of the center, also in compute_blur.wgsl
+pub const MAX_BLUR_RADIUS: u32 = 16;
+
+// Also in luminance_histogram.wgsl and luminance_average.wgsl
+pub const HISTOGRAM_BINS: u64 = 256;
+
+pub fn create_pipeline(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, source: &str, label: &str) -> wgpu::ComputePipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label:  Some(label),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(label),
+        bind_group_layouts:   &[layout],
+        push_constant_ranges: &[],
+    });
+
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label:       Some(label),
+        layout:      Some(&pipeline_layout),
+        module:      &shader,
+        entry_point: "cs_main",
+    })
+}
+
+// Workgroups needed to cover `size` texels with 8x8 groups
+pub fn workgroups(size: u32) -> u32 {
+    size.div_ceil(WORKGROUP_SIZE)
+}
+
+// The WGSL name of a format that can be written as a storage texture on every backend
+fn storage_format_name(format: wgpu::TextureFormat) -> Option<&'static str> {
+    match format {
+        wgpu::TextureFormat::Rgba8Unorm  => Some("rgba8unorm"),
+        wgpu::TextureFormat::Rgba16Float => Some("rgba16float"),
+        wgpu::TextureFormat::Rgba32Float => Some("rgba32float"),
+        wgpu::TextureFormat::R32Float    => Some("r32float"),
+        _                                => None,
+    }
+}
+
+// A texture with a view per mip level, like the levels of a bloom chain or a texture to mip
+pub struct MipChain {
+    pub texture: wgpu::Texture,
+    pub view:    wgpu::TextureView,
+    pub size:    (u32, u32),
+    levels:      Vec<wgpu::TextureView>,
+}
+
+impl MipChain {
+    // `levels` is capped at a full chain down to 1x1
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, width: u32, height: u32, levels: u32, label: &str) -> Self {
+        let levels = levels.clamp(1, 32 - width.max(height).leading_zeros());
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label:           Some(label),
+            size:            wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+            mip_level_count: levels,
+            sample_count:    1,
+            dimension:       wgpu::TextureDimension::D2,
+            format,
+            usage:           wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST,
+        });
+
+        let views = (0..levels)
+            .map(|level| texture.create_view(&wgpu::TextureViewDescriptor {
+                label:           Some(label),
+                base_mip_level:  level,
+                mip_level_count: std::num::NonZeroU32::new(1),
+                ..Default::default()
+            }))
+            .collect();
+
+        Self {
+            view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            texture,
+            size: (width, height),
+            levels: views,
+        }
+    }
+
+    pub fn level(&self, level: usize) -> &wgpu::TextureView {
+        &self.levels[level]
+    }
+
+    pub fn level_count(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn level_size(&self, level: usize) -> (u32, u32) {
+        ((self.size.0 >> level).max(1), (self.size.1 >> level).max(1))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BlurKernel {
+    // Equal weights across 2 * radius + 1 texels
+    Box { radius: u32 },
+    // Weights from a normal distribution, cut off at `radius`
+    Gaussian { radius: u32, sigma: f32 },
+}
+
+impl BlurKernel {
+    // A Gaussian cut off at three standard deviations, where the weights are negligible
+    pub fn gaussian(sigma: f32) -> Self {
+        BlurKernel::Gaussian { radius: (sigma * 3.0).ceil() as u32, sigma }
+    }
+
+    fn weights(&self) -> Vec<f32> {
+        let weights = match *self {
+            BlurKernel::Box { radius } => {
+                vec![1.0; radius.min(MAX_BLUR_RADIUS) as usize + 1]
+            }
+            BlurKernel::Gaussian { radius, sigma } => {
+                let sigma = sigma.max(f32::EPSILON);
+
+                (0..=radius.min(MAX_BLUR_RADIUS))
+                    .map(|i| (-((i * i) as f32) / (2.0 * sigma * sigma)).exp())
+                    .collect()
+            }
+        };
+
+        // The center is counted once and every other weight twice, once on each side
+        let total = weights[0] + 2.0 * weights[1..].iter().sum::<f32>();
+
+        weights.iter().map(|w| w / total).collect()
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct BlurParams {
+    radius:   u32,
+    _padding: [u32; 3],
+    // Weight of the texel `i` away from the center is `weights[i / 4][i % 4]`
+    weights:  [[f32; 4]; (MAX_BLUR_RADIUS as usize + 4) / 4],
+}
+
+// The kernel of a blur. Each blur keeps its own so several can run in a frame.
+pub struct Blur {
+    params: wgpu::Buffer,
+}
+
+impl Blur {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, kernel: BlurKernel) -> Self {
+        let params = device.create_buffer(&wgpu::BufferDescriptor {
+            label:              Some("Blur Params Buffer"),
+            size:               std::mem::size_of::<BlurParams>() as wgpu::BufferAddress,
+            usage:              wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let blur = Self { params };
+        blur.set_kernel(queue, kernel);
+        blur
+    }
+
+    pub fn set_kernel(&self, queue: &wgpu::Queue, kernel: BlurKernel) {
+        let weights = kernel.weights();
+
+        let mut params = BlurParams {
+            radius:   weights.len() as u32 - 1,
+            _padding: [0; 3],
+            weights:  bytemuck::Zeroable::zeroed(),
+        };
+
+        for (i, weight) in weights.iter().enumerate() {
+            params.weights[i / 4][i % 4] = *weight;
+        }
+
+        queue.write_buffer(&self.params, 0, bytemuck::cast_slice(&[params]));
+    }
+}
+
+// Downsampling and blur pipelines for one storage format
+pub struct TextureFilters {
+    pub format:          wgpu::TextureFormat,
+    downsample_pipeline: wgpu::ComputePipeline,
+    downsample_layout:   wgpu::BindGroupLayout,
+    horizontal_pipeline: wgpu::ComputePipeline,
+    vertical_pipeline:   wgpu::ComputePipeline,
+    blur_layout:         wgpu::BindGroupLayout,
+}
+
+impl TextureFilters {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let name = storage_format_name(format)
+            .unwrap_or_else(|| panic!("{:?} can't be written by compute filters", format));
+
+        let downsample_layout = bind_group::BindGroupLayoutBuilder::new()
+            .unfilterable_texture(wgpu::ShaderStages::COMPUTE, wgpu::TextureViewDimension::D2)
+            .storage_texture(wgpu::ShaderStages::COMPUTE, format, wgpu::StorageTextureAccess::WriteOnly, wgpu::TextureViewDimension::D2)
+            .build(device, "downsample_bind_group_layout");
+
+        let blur_layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::COMPUTE)
+            .unfilterable_texture(wgpu::ShaderStages::COMPUTE, wgpu::TextureViewDimension::D2)
+            .storage_texture(wgpu::ShaderStages::COMPUTE, format, wgpu::StorageTextureAccess::WriteOnly, wgpu::TextureViewDimension::D2)
+            .build(device, "blur_bind_group_layout");
+
+        let downsample_source = include_str!("compute_downsample.wgsl").replace("STORAGE_FORMAT", name);
+        let blur_source       = include_str!("compute_blur.wgsl").replace("STORAGE_FORMAT", name);
+
+        // The blur direction is a constant in the shader so one kernel buffer serves both passes
+        let downsample_pipeline = create_pipeline(device, &downsample_layout, &downsample_source, "Downsample");
+        let horizontal_pipeline = create_pipeline(device, &blur_layout, &blur_source.replace("BLUR_AXIS", "vec2<i32>(1, 0)"), "Horizontal Blur");
+        let vertical_pipeline   = create_pipeline(device, &blur_layout, &blur_source.replace("BLUR_AXIS", "vec2<i32>(0, 1)"), "Vertical Blur");
+
+        Self {
+            format,
+            downsample_pipeline,
+            downsample_layout,
+            horizontal_pipeline,
+            vertical_pipeline,
+            blur_layout,
+        }
+    }
+
+    // Box filters `src` into `dst`, which is usually half its size
+    pub fn downsample(
+        &self,
+        device:   &wgpu::Device,
+        encoder:  &mut wgpu::CommandEncoder,
+        src:      &wgpu::TextureView,
+        dst:      &wgpu::TextureView,
+        dst_size: (u32, u32),
+    ) {
+        let bind_group = bind_group::BindGroupBuilder::new(&self.downsample_layout)
+            .texture(src)
+            .texture(dst)
+            .build(device, "downsample_bind_group");
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Downsample Pass"),
+        });
+
+        crash_report::set_compute_pipeline(&mut compute_pass, &self.downsample_pipeline, "Downsample");
+        compute_pass.set_bind_group(0, &bind_group, &[]);
+        compute_pass.dispatch_workgroups(workgroups(dst_size.0), workgroups(dst_size.1), 1);
+    }
+
+    // Fills every level of `chain` below the first from the level above it
+    pub fn generate_mips(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, chain: &MipChain) {
+        for level in 1..chain.level_count() {
+            self.downsample(device, encoder, chain.level(level - 1), chain.level(level), chain.level_size(level));
+        }
+    }
+
+    // Fills the first level of `chain` from `src` and each level after from the one before, for
+    // effects like bloom that work on successively smaller copies of the scene
+    pub fn downsample_chain(
+        &self,
+        device:  &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        src:     &wgpu::TextureView,
+        chain:   &MipChain,
+    ) {
+        self.downsample(device, encoder, src, chain.level(0), chain.level_size(0));
+        self.generate_mips(device, encoder, chain);
+    }
+
+    // Blurs `src` into `dst` as a horizontal then a vertical pass through `scratch`. All three
+    // are `size` and `scratch` and `dst` have to be in this filter's format. `src` and `dst` can
+    // be the same texture.
+    #[allow(clippy::too_many_arguments)]
+    pub fn blur(
+        &self,
+        device:  &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        blur:    &Blur,
+        src:     &wgpu::TextureView,
+        scratch: &wgpu::TextureView,
+        dst:     &wgpu::TextureView,
+        size:    (u32, u32),
+    ) {
+        let create_bind_group = |input, output| bind_group::BindGroupBuilder::new(&self.blur_layout)
+            .uniform(&blur.params)
+            .texture(input)
+            .texture(output)
+            .build(device, "blur_bind_group");
+
+        let horizontal = create_bind_group(src, scratch);
+        let vertical   = create_bind_group(scratch, dst);
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Blur Pass"),
+        });
+
+        crash_report::set_compute_pipeline(&mut compute_pass, &self.horizontal_pipeline, "Horizontal Blur");
+        compute_pass.set_bind_group(0, &horizontal, &[]);
+        compute_pass.dispatch_workgroups(workgroups(size.0), workgroups(size.1), 1);
+
+        crash_report::set_compute_pipeline(&mut compute_pass, &self.vertical_pipeline, "Vertical Blur");
+        compute_pass.set_bind_group(0, &vertical, &[]);
+        compute_pass.dispatch_workgroups(workgroups(size.0), workgroups(size.1), 1);
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct HistogramParams {
+    min_log_luminance: f32,
+    log_range:         f32,
+    _padding:          [u32; 2],
+}
+
+// Counts pixels into bins by log2 luminance, then reduces the bins to the average luminance of
+// the image. Black pixels land in the first bin and are left out of the average.
+pub struct LuminanceHistogram {
+    // Range of log2 luminance the bins cover. Anything outside is clamped into the end bins.
+    pub min_log_luminance: f32,
+    pub max_log_luminance: f32,
+    // The bins as `array<u32, 256>`, cleared again by the reduction
+    pub bins:              wgpu::Buffer,
+    // The average luminance as a single f32, for later passes to read
+    pub average:           wgpu::Buffer,
+    params:                wgpu::Buffer,
+    histogram_pipeline:    wgpu::ComputePipeline,
+    histogram_layout:      wgpu::BindGroupLayout,
+    average_pipeline:      wgpu::ComputePipeline,
+    average_group:         wgpu::BindGroup,
+}
+
+impl LuminanceHistogram {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let histogram_layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::COMPUTE)
+            .unfilterable_texture(wgpu::ShaderStages::COMPUTE, wgpu::TextureViewDimension::D2)
+            .storage(wgpu::ShaderStages::COMPUTE, false)
+            .build(device, "luminance_histogram_bind_group_layout");
+
+        let average_layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::COMPUTE)
+            .storage(wgpu::ShaderStages::COMPUTE, false)
+            .storage(wgpu::ShaderStages::COMPUTE, false)
+            .build(device, "luminance_average_bind_group_layout");
+
+        let params = device.create_buffer(&wgpu::BufferDescriptor {
+            label:              Some("Luminance Histogram Params Buffer"),
+            size:               std::mem::size_of::<HistogramParams>() as wgpu::BufferAddress,
+            usage:              wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        // Zeroed on creation, after which the reduction leaves them cleared for the next frame
+        let bins = device.create_buffer(&wgpu::BufferDescriptor {
+            label:              Some("Luminance Histogram Buffer"),
+            size:               HISTOGRAM_BINS * std::mem::size_of::<u32>() as wgpu::BufferAddress,
+            usage:              wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let average = device.create_buffer(&wgpu::BufferDescriptor {
+            label:              Some("Average Luminance Buffer"),
+            size:               std::mem::size_of::<f32>() as wgpu::BufferAddress,
+            usage:              wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let average_group = bind_group::BindGroupBuilder::new(&average_layout)
+            .uniform(&params)
+            .storage(&bins)
+            .storage(&average)
+            .build(device, "luminance_average_bind_group");
+
+        let histogram_pipeline = create_pipeline(device, &histogram_layout, include_str!("luminance_histogram.wgsl"), "Luminance Histogram");
+        let average_pipeline   = create_pipeline(device, &average_layout, include_str!("luminance_average.wgsl"), "Luminance Average");
+
+        Self {
+            min_log_luminance: -8.0,
+            max_log_luminance: 4.0,
+            bins,
+            average,
+            params,
+            histogram_pipeline,
+            histogram_layout,
+            average_pipeline,
+            average_group,
+        }
+    }
+
+    pub fn prepare(&self, queue: &wgpu::Queue) {
+        queue.write_buffer(&self.params, 0, bytemuck::cast_slice(&[HistogramParams {
+            min_log_luminance: self.min_log_luminance,
+            log_range:         (self.max_log_luminance - self.min_log_luminance).max(f32::EPSILON),
+            _padding:          [0; 2],
+        }]));
+    }
+
+    // Records the passes that bin `src`, which is `size`, and average the bins into `average`
+    pub fn build(
+        &self,
+        device:  &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        src:     &wgpu::TextureView,
+        size:    (u32, u32),
+    ) {
+        let histogram_group = bind_group::BindGroupBuilder::new(&self.histogram_layout)
+            .uniform(&self.params)
+            .texture(src)
+            .storage(&self.bins)
+            .build(device, "luminance_histogram_bind_group");
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Luminance Histogram Pass"),
+        });
+
+        crash_report::set_compute_pipeline(&mut compute_pass, &self.histogram_pipeline, "Luminance Histogram");
+        compute_pass.set_bind_group(0, &histogram_group, &[]);
+        compute_pass.dispatch_workgroups(workgroups(size.0), workgroups(size.1), 1);
+
+        crash_report::set_compute_pipeline(&mut compute_pass, &self.average_pipeline, "Luminance Average");
+        compute_pass.set_bind_group(0, &self.average_group, &[]);
+        compute_pass.dispatch_workgroups(1, 1, 1);
+    }
+}
+
+enum ReadbackState {
+    Idle,
+    // Copied into by an encoder that hasn't been submitted yet
+    Copied,
+    Mapping(mpsc::Receiver<Result<(), wgpu::BufferAsyncError>>),
+}
+
+// Copies a GPU buffer back without waiting on it, so results turn up a frame or more after they
+// were computed. Copies asked for while an earlier one is still on its way are skipped.
+pub struct AsyncReadback {
+    buffer: wgpu::Buffer,
+    size:   wgpu::BufferAddress,
+    state:  ReadbackState,
+}
+
+impl AsyncReadback {
+    pub fn new(device: &wgpu::Device, size: wgpu::BufferAddress, label: &str) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label:              Some(label),
+            size,
+            usage:              wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Self { buffer, size, state: ReadbackState::Idle }
+    }
+
+    // Records a copy of the start of `source` unless one is already on its way. Returns whether
+    // it did, in which case `submitted` must follow once `encoder` is submitted.
+    pub fn copy(&mut self, encoder: &mut wgpu::CommandEncoder, source: &wgpu::Buffer) -> bool {
+        if !matches!(self.state, ReadbackState::Idle) {
+            return false;
+        }
+
+        encoder.copy_buffer_to_buffer(source, 0, &self.buffer, 0, self.size);
+        self.state = ReadbackState::Copied;
+        true
+    }
+
+    // Starts mapping the copy. It can't be asked for before the copy is submitted.
+    pub fn submitted(&mut self) {
+        if let ReadbackState::Copied = self.state {
+            let (sender, receiver) = mpsc::channel();
+
+            self.buffer.slice(..self.size).map_async(wgpu::MapMode::Read, move |result| {
+                // Nobody's waiting if the readback was dropped first
+                let _ = sender.send(result);
+            });
+            self.state = ReadbackState::Mapping(receiver);
+        }
+    }
+
+    // The copy's contents once they've arrived. Polls the device but never blocks.
+    pub fn try_read<T: bytemuck::Pod>(&mut self, device: &wgpu::Device) -> Option<Vec<T>> {
+        let result = match &self.state {
+            ReadbackState::Mapping(receiver) => {
+                device.poll(wgpu::Maintain::Poll);
+                receiver.try_recv()
+            }
+            _ => return None,
+        };
+
+        match result {
+            Ok(Ok(())) => {}
+            Err(mpsc::TryRecvError::Empty) => return None,
+            // The copy is lost, so the next one can go ahead
+            Ok(Err(_)) | Err(mpsc::TryRecvError::Disconnected) => {
+                self.state = ReadbackState::Idle;
+                return None;
+            }
+        }
+
+        let data = bytemuck::cast_slice::<u8, T>(&self.buffer.slice(..self.size).get_mapped_range()).to_vec();
+
+        self.buffer.unmap();
+        self.state = ReadbackState::Idle;
+
+        Some(data)
+    }
+}
@@ -0,0 +1,42 @@
+// One direction of a separable blur. STORAGE_FORMAT and BLUR_AXIS are filled in by
+// `TextureFilters`, giving a pipeline per direction.
+
+struct BlurParams {
+    radius:  u32,
+    // Weight of the texel `i` away from the center is `weights[i / 4][i % 4]`
+    weights: array<vec4<f32>, 5>,
+}
+
+@group(0) @binding(0)
+var<uniform> params: BlurParams;
+@group(0) @binding(1)
+var src: texture_2d<f32>;
+@group(0) @binding(2)
+var dst: texture_storage_2d<STORAGE_FORMAT, write>;
+
+fn weight(i: u32) -> f32 {
+    return params.weights[i / 4u][i % 4u];
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size  = vec2<i32>(textureDimensions(dst));
+    let coord = vec2<i32>(id.xy);
+
+    if (coord.x >= size.x || coord.y >= size.y) {
+        return;
+    }
+
+    let axis = BLUR_AXIS;
+    var color = textureLoad(src, coord, 0) * weight(0u);
+
+    // Clamped to the edge so the border doesn't darken
+    for (var i = 1u; i <= params.radius; i = i + 1u) {
+        let offset = axis * i32(i);
+
+        color = color + textureLoad(src, clamp(coord + offset, vec2<i32>(0), size - 1), 0) * weight(i);
+        color = color + textureLoad(src, clamp(coord - offset, vec2<i32>(0), size - 1), 0) * weight(i);
+    }
+
+    textureStore(dst, coord, color);
+}
@@ -0,0 +1,27 @@
+// Halves a texture with a box filter. STORAGE_FORMAT is filled in by `TextureFilters`.
+
+@group(0) @binding(0)
+var src: texture_2d<f32>;
+@group(0) @binding(1)
+var dst: texture_storage_2d<STORAGE_FORMAT, write>;
+
+@compute @workgroup_size(8, 8)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let dst_size = vec2<i32>(textureDimensions(dst));
+    let src_max  = vec2<i32>(textureDimensions(src)) - 1;
+    let coord    = vec2<i32>(id.xy);
+
+    if (coord.x >= dst_size.x || coord.y >= dst_size.y) {
+        return;
+    }
+
+    let start = coord * 2;
+
+    // Clamped so levels with an odd size repeat their last row or column
+    let color = textureLoad(src, min(start, src_max), 0)
+        + textureLoad(src, min(start + vec2<i32>(1, 0), src_max), 0)
+        + textureLoad(src, min(start + vec2<i32>(0, 1), src_max), 0)
+        + textureLoad(src, min(start + vec2<i32>(1, 1), src_max), 0);
+
+    textureStore(dst, coord, color * 0.25);
+}
@@ -0,0 +1,80 @@
+// The per-pixel and 3x3 filters of `ImageFilters`, picked by `params.mode`. Colors are linear.
+
+struct FilterParams {
+    // The color chroma keying removes
+    key:       vec3<f32>,
+    // 0 copies, 1 sharpens, 2 finds edges, 3 desaturates and 4 chroma keys
+    mode:      u32,
+    amount:    f32,
+    threshold: f32,
+    softness:  f32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: FilterParams;
+@group(0) @binding(1)
+var src: texture_2d<f32>;
+@group(0) @binding(2)
+var dst: texture_storage_2d<rgba32float, write>;
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+// Clamped to the edge so borders filter like their neighbors
+fn load(coord: vec2<i32>, size: vec2<i32>) -> vec4<f32> {
+    return textureLoad(src, clamp(coord, vec2<i32>(0), size - 1), 0);
+}
+
+// Blue and red difference, which stay the same as a color gets lighter or darker
+fn chroma(color: vec3<f32>) -> vec2<f32> {
+    let y = luminance(color);
+
+    return vec2<f32>((color.b - y) * 0.5389, (color.r - y) * 0.6350);
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size  = vec2<i32>(textureDimensions(dst));
+    let coord = vec2<i32>(id.xy);
+
+    if (coord.x >= size.x || coord.y >= size.y) {
+        return;
+    }
+
+    let center = load(coord, size);
+    var result = center;
+
+    switch (params.mode) {
+        case 1u: {
+            let neighbors = load(coord + vec2<i32>(1, 0), size) + load(coord - vec2<i32>(1, 0), size)
+                + load(coord + vec2<i32>(0, 1), size) + load(coord - vec2<i32>(0, 1), size);
+
+            result = vec4<f32>(max(center.rgb * (1.0 + 4.0 * params.amount) - neighbors.rgb * params.amount, vec3<f32>(0.0)), center.a);
+        }
+        case 2u: {
+            var l: array<f32, 9>;
+
+            for (var i = 0; i < 9; i = i + 1) {
+                l[i] = luminance(load(coord + vec2<i32>(i % 3 - 1, i / 3 - 1), size).rgb);
+            }
+
+            let gx = (l[2] + 2.0 * l[5] + l[8]) - (l[0] + 2.0 * l[3] + l[6]);
+            let gy = (l[6] + 2.0 * l[7] + l[8]) - (l[0] + 2.0 * l[1] + l[2]);
+
+            result = vec4<f32>(vec3<f32>(length(vec2<f32>(gx, gy)) * params.amount), center.a);
+        }
+        case 3u: {
+            result = vec4<f32>(mix(center.rgb, vec3<f32>(luminance(center.rgb)), params.amount), center.a);
+        }
+        case 4u: {
+            let distance = length(chroma(center.rgb) - chroma(params.key));
+            let alpha    = smoothstep(params.threshold, params.threshold + params.softness, distance);
+
+            result = vec4<f32>(center.rgb, center.a * alpha);
+        }
+        default: {}
+    }
+
+    textureStore(dst, coord, result);
+}
@@ -0,0 +1,135 @@
+// Contact shadows: short rays marched from each lit point towards the light through the depth
+// buffer, catching the shadows right where objects meet the ground that shadow maps are too coarse
+// or too biased to show. The depth prepass is copied just before the opaque layer so the lighting
+// can read it, and `spot_incoming` in lights.wgsl takes the darker of the march and the shadow map
+// for spot lights that cast shadows.
+//
+// Only what's on screen casts them, so they're kept short. Views other than the one the copy was
+// taken from, like mirrors, are told apart by their depth not matching and go without.
+
+use crate::{
+    bind_group,
+    crash_report,
+    pass::SceneCapture,
+    renderer::{self, GpuContext},
+    texture::Texture,
+};
+
+#[derive(Debug, Copy, Clone)]
+pub struct ContactShadowSettings {
+    // World units marched towards the light
+    pub length:    f32,
+    // How far behind the depth buffer a ray can pass and still be blocked, so thin things don't
+    // shadow everything behind them
+    pub thickness: f32,
+    pub steps:     u32,
+}
+
+impl Default for ContactShadowSettings {
+    fn default() -> Self {
+        Self {
+            length:    0.5,
+            thickness: 0.3,
+            steps:     16,
+        }
+    }
+}
+
+pub struct ContactShadows {
+    pipeline: wgpu::RenderPipeline,
+    layout:   wgpu::BindGroupLayout,
+    // Made in `resize`, the depth buffer's size
+    depth:    Texture,
+}
+
+impl ContactShadows {
+    pub fn new(ctx: &GpuContext) -> Self {
+        let device = &ctx.device;
+
+        let layout = bind_group::BindGroupLayoutBuilder::new()
+            .depth_texture(wgpu::ShaderStages::FRAGMENT)
+            .build(device, "contact_shadows_bind_group_layout");
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Depth Copy Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("depth_copy.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Depth Copy Pipeline Layout"),
+            bind_group_layouts:   &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label:    Some("Depth Copy Pipeline"),
+            layout:   Some(&pipeline_layout),
+            vertex:   wgpu::VertexState {
+                module:      &shader,
+                entry_point: "vs_main",
+                buffers:     &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module:      &shader,
+                entry_point: "fs_main",
+                targets:     &[],
+            }),
+            primitive:     wgpu::PrimitiveState::default(),
+            depth_stencil: Some(renderer::depth_state(Texture::DEPTH_FORMAT, wgpu::CompareFunction::Always, true)),
+            multisample:   wgpu::MultisampleState::default(),
+            multiview:     None,
+        });
+
+        Self {
+            pipeline,
+            layout,
+            depth: create_depth(ctx),
+        }
+    }
+
+    // Matches the copy to the depth buffer, after which it has to be handed to the lights again
+    // with `Lights::set_scene_depth`
+    pub fn resize(&mut self, ctx: &GpuContext) {
+        self.depth = create_depth(ctx);
+    }
+
+    // The copy, for `Lights::set_scene_depth`
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.depth.view
+    }
+}
+
+impl SceneCapture for ContactShadows {
+    fn capture(
+        &self,
+        device:  &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        _scene:  &wgpu::TextureView,
+        depth:   &wgpu::TextureView,
+    ) {
+        let depth_group = bind_group::BindGroupBuilder::new(&self.layout)
+            .texture(depth)
+            .build(device, "contact_shadows_bind_group");
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Depth Copy Pass"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view:        &self.depth.view,
+                depth_ops:   Some(wgpu::Operations {
+                    load:  wgpu::LoadOp::Load,
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+
+        crash_report::set_pipeline(&mut render_pass, &self.pipeline, "Depth Copy Pipeline");
+        render_pass.set_bind_group(0, &depth_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+fn create_depth(ctx: &GpuContext) -> Texture {
+    Texture::create_depth_texture(&ctx.device, ctx.config.width, ctx.config.height, "contact_shadows_depth_texture")
+}
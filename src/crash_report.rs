@@ -0,0 +1,216 @@
+// Writes down what the renderer was doing when it fell over, to make bug reports actionable. Once
+// `install`ed by the GPU context, a panic or a wgpu validation error writes a report with the
+// adapter, the renderer's settings, the passes and draws recorded just before and the pipeline
+// they last set, ending with the panic message and backtrace. It goes to
+// `RendererOptions::crash_report` natively and to the browser console on the web.
+//
+// Like gpu_stats.rs, the adapter and settings are process wide, since the panic hook has nothing
+// else to reach them through. Apps can add settings of their own with `set_setting`. What's
+// recorded is kept by the thread recording it instead, so marking every draw doesn't take a lock.
+// wgpu reports validation errors on the thread that made the call, so the panicking thread's
+// record is the one that matters.
+
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    fmt::{Display, Write},
+    sync::{Mutex, Once},
+};
+
+use crate::renderer::{GpuContext, RendererOptions};
+
+// How many markers are kept for the report
+const RECENT_MARKERS: usize = 48;
+
+#[cfg(not(target_arch = "wasm32"))]
+const DEFAULT_PATH: &str = "crash_report.txt";
+
+static STATE: Mutex<ReportState> = Mutex::new(ReportState {
+    adapter:  None,
+    settings: Vec::new(),
+    #[cfg(not(target_arch = "wasm32"))]
+    path:     None,
+});
+
+static INSTALL_HOOK: Once = Once::new();
+
+thread_local! {
+    static RECENT: RefCell<Recent> = RefCell::new(Recent::default());
+}
+
+struct ReportState {
+    adapter:  Option<wgpu::AdapterInfo>,
+    // In the order they were first set
+    settings: Vec<(String, String)>,
+    #[cfg(not(target_arch = "wasm32"))]
+    path:     Option<std::path::PathBuf>,
+}
+
+#[derive(Default)]
+struct Recent {
+    // Oldest first, up to RECENT_MARKERS
+    markers:  VecDeque<String>,
+    // Set since the last marker, empty for none
+    pipeline: String,
+}
+
+fn state() -> std::sync::MutexGuard<'static, ReportState> {
+    // Reporting is most useful after something went wrong, so it carries on past a poisoned lock
+    STATE.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// With this thread's record, unless it's gone or already borrowed by whatever panicked
+fn with_recent<T>(f: impl FnOnce(&mut Recent) -> T) -> Option<T> {
+    RECENT.try_with(|recent| recent.try_borrow_mut().ok().map(|mut recent| f(&mut recent)))
+        .ok()
+        .flatten()
+}
+
+// Reports panics and `ctx`'s validation errors from here on. Called by `GpuContext::from_parts`.
+pub(crate) fn install(ctx: &GpuContext, options: &RendererOptions) {
+    {
+        let mut state = state();
+
+        state.adapter = Some(ctx.adapter_info.clone());
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            state.path = Some(
+                options.crash_report
+                    .map(std::path::PathBuf::from)
+                    .or_else(|| std::env::var_os("LEARN_WGPU_CRASH_REPORT").map(std::path::PathBuf::from))
+                    .unwrap_or_else(|| std::path::PathBuf::from(DEFAULT_PATH))
+            );
+        }
+    }
+
+    set_setting("Renderer options", format!("{:?}", options));
+    set_setting("Surface format", format!("{:?}", ctx.config.format));
+    set_setting("Present mode", format!("{:?}", ctx.config.present_mode));
+    set_setting("Window size", format!("{}x{}", ctx.size.width, ctx.size.height));
+    set_setting("Features", format!("{:?}", ctx.device.features()));
+
+    // Panics like wgpu's own handler, so the hook reports the error along with where it was made
+    ctx.device.on_uncaptured_error(|error| panic!("Uncaptured wgpu error: {}", error));
+
+    INSTALL_HOOK.call_once(|| {
+        // Whatever was there before still runs after, like the console hook on the web
+        let previous = std::panic::take_hook();
+
+        std::panic::set_hook(Box::new(move |info| {
+            write_report(&info.to_string());
+            previous(info);
+        }));
+    });
+}
+
+// Shows `value` under `name` in reports, replacing what was set before
+pub fn set_setting(name: &str, value: impl Display) {
+    let mut state = state();
+    let value     = value.to_string();
+
+    match state.settings.iter_mut().find(|(n, _)| n == name) {
+        Some((_, v)) => *v = value,
+        None         => state.settings.push((name.to_string(), value)),
+    }
+}
+
+// Called for each frame, layer pass, effect, capture and drawable as it's recorded, with the label
+// of the debug group it's wrapped in, and for each batch of compute work with its label
+pub(crate) fn marker(label: &str) {
+    with_recent(|recent| {
+        // Markers are pushed for every draw, so the oldest entry's string is reused once full
+        let mut marker = if recent.markers.len() >= RECENT_MARKERS {
+            recent.markers.pop_front().unwrap_or_default()
+        } else {
+            String::new()
+        };
+
+        marker.clear();
+        marker.push_str(label);
+        recent.markers.push_back(marker);
+        recent.pipeline.clear();
+    });
+}
+
+// Sets `pipeline` on `render_pass`, noting `label` as the pipeline drawing. wgpu doesn't hand
+// pipelines' labels back, so it's the one the pipeline was made with.
+pub fn set_pipeline<'a>(render_pass: &mut wgpu::RenderPass<'a>, pipeline: &'a wgpu::RenderPipeline, label: &str) {
+    render_pass.set_pipeline(pipeline);
+    note_pipeline(label);
+}
+
+// `set_pipeline` for compute passes, so an error in a dispatch names its pipeline rather than the
+// last one drawn with
+pub fn set_compute_pipeline<'a>(compute_pass: &mut wgpu::ComputePass<'a>, pipeline: &'a wgpu::ComputePipeline, label: &str) {
+    compute_pass.set_pipeline(pipeline);
+    note_pipeline(label);
+}
+
+fn note_pipeline(label: &str) {
+    with_recent(|recent| {
+        recent.pipeline.clear();
+        recent.pipeline.push_str(label);
+    });
+}
+
+// Everything a report holds, with `reason` for what happened
+pub fn report(reason: &str) -> String {
+    let state      = state();
+    let mut report = String::new();
+
+    // Writing to a String can't fail
+    let _ = writeln!(report, "learn_wgpu crash report\n\n{}", reason);
+
+    let _ = match &state.adapter {
+        Some(adapter) => writeln!(
+            report,
+            "\nAdapter: {} ({:?}, {:?}), vendor {:#06x}, device {:#06x}, driver {} {}",
+            adapter.name, adapter.backend, adapter.device_type, adapter.vendor, adapter.device, adapter.driver, adapter.driver_info,
+        ),
+        None => writeln!(report, "\nAdapter: none yet"),
+    };
+
+    let _ = writeln!(report, "\nSettings:");
+    for (name, value) in &state.settings {
+        let _ = writeln!(report, "  {}: {}", name, value);
+    }
+
+    drop(state);
+
+    // Recorded on this thread
+    with_recent(|recent| {
+        let _ = writeln!(report, "\nLast recorded, oldest first:");
+        for label in &recent.markers {
+            let _ = writeln!(report, "  {}", label);
+        }
+
+        let _ = match recent.pipeline.as_str() {
+            ""       => writeln!(report, "\nPipeline set since the last of those: none"),
+            pipeline => writeln!(report, "\nPipeline set since the last of those: {}", pipeline),
+        };
+    });
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let _ = writeln!(report, "\nBacktrace:\n{}", std::backtrace::Backtrace::force_capture());
+
+    report
+}
+
+fn write_report(reason: &str) {
+    let report = report(reason);
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let path = state().path.clone().unwrap_or_else(|| std::path::PathBuf::from(DEFAULT_PATH));
+
+        match std::fs::write(&path, report) {
+            Ok(()) => eprintln!("Wrote a crash report to {}", path.display()),
+            Err(e) => eprintln!("Couldn't write a crash report to {}: {}", path.display(), e),
+        }
+    }
+
+    // console_log sends errors to the browser console
+    #[cfg(target_arch = "wasm32")]
+    log::error!("{}", report);
+}
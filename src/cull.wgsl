@@ -0,0 +1,142 @@
+// GPU-driven culling. Each invocation tests one instance against the view frustum and the Hi-Z
+// pyramid, appending the survivors to `visible` and counting them into the indirect draw args.
+
+struct CullParams {
+    // Last frame's camera, which is what the pyramid was rendered with
+    prev_view_proj: mat4x4<f32>,
+    frustum:        array<vec4<f32>, 6>,
+    hiz_size:       vec2<f32>,
+    hiz_mips:       u32,
+    instance_count: u32,
+    occlusion:      u32,
+    reverse_z:      u32,
+}
+
+struct CullInstance {
+    model:      mat4x4<f32>,
+    bounds_min: vec4<f32>,
+    bounds_max: vec4<f32>,
+}
+
+struct DrawArgs {
+    index_count:    u32,
+    instance_count: atomic<u32>,
+    first_index:    u32,
+    base_vertex:    i32,
+    first_instance: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: CullParams;
+@group(0) @binding(1)
+var<storage, read> instances: array<CullInstance>;
+@group(0) @binding(2)
+var<storage, read_write> visible: array<mat4x4<f32>>;
+@group(0) @binding(3)
+var<storage, read_write> args: array<DrawArgs>;
+@group(0) @binding(4)
+var hiz: texture_2d<f32>;
+
+fn in_frustum(bounds_min: vec3<f32>, bounds_max: vec3<f32>) -> bool {
+    for (var i = 0; i < 6; i = i + 1) {
+        let plane = params.frustum[i];
+        // The corner furthest along the plane normal
+        let p = select(bounds_min, bounds_max, plane.xyz >= vec3<f32>(0.0));
+
+        if (dot(plane.xyz, p) + plane.w < 0.0) {
+            return false;
+        }
+    }
+    return true;
+}
+
+fn is_closer(a: f32, b: f32) -> bool {
+    if (params.reverse_z != 0u) {
+        return a > b;
+    }
+    return a < b;
+}
+
+fn is_occluded(bounds_min: vec3<f32>, bounds_max: vec3<f32>) -> bool {
+    var uv_min  = vec2<f32>(1.0);
+    var uv_max  = vec2<f32>(0.0);
+    var nearest = 1.0 - f32(params.reverse_z);
+
+    for (var i = 0u; i < 8u; i = i + 1u) {
+        let corner = select(bounds_min, bounds_max, vec3<bool>((i & 1u) != 0u, (i & 2u) != 0u, (i & 4u) != 0u));
+        let clip   = params.prev_view_proj * vec4<f32>(corner, 1.0);
+
+        // Crosses the near plane, so it can't be safely projected
+        if (clip.w <= 0.0) {
+            return false;
+        }
+
+        let ndc = clip.xyz / clip.w;
+        let uv  = ndc.xy * vec2<f32>(0.5, -0.5) + 0.5;
+
+        uv_min = min(uv_min, uv);
+        uv_max = max(uv_max, uv);
+
+        if (is_closer(ndc.z, nearest)) {
+            nearest = ndc.z;
+        }
+    }
+
+    uv_min = clamp(uv_min, vec2<f32>(0.0), vec2<f32>(1.0));
+    uv_max = clamp(uv_max, vec2<f32>(0.0), vec2<f32>(1.0));
+
+    // Pick the level where the box covers at most 2x2 texels
+    let extent = (uv_max - uv_min) * params.hiz_size;
+    let level  = clamp(i32(ceil(log2(max(max(extent.x, extent.y), 1.0)))), 0, i32(params.hiz_mips) - 1);
+    let size   = vec2<i32>(textureDimensions(hiz, level));
+    let lo     = clamp(vec2<i32>(uv_min * vec2<f32>(size)), vec2<i32>(0), size - 1);
+    let hi     = clamp(vec2<i32>(uv_max * vec2<f32>(size)), vec2<i32>(0), size - 1);
+
+    let d0 = textureLoad(hiz, vec2<i32>(lo.x, lo.y), level).r;
+    let d1 = textureLoad(hiz, vec2<i32>(hi.x, lo.y), level).r;
+    let d2 = textureLoad(hiz, vec2<i32>(lo.x, hi.y), level).r;
+    let d3 = textureLoad(hiz, vec2<i32>(hi.x, hi.y), level).r;
+
+    var farthest = d0;
+    if (is_closer(farthest, d1)) { farthest = d1; }
+    if (is_closer(farthest, d2)) { farthest = d2; }
+    if (is_closer(farthest, d3)) { farthest = d3; }
+
+    // Hidden if even the nearest point of the box is behind everything drawn over it
+    return is_closer(farthest, nearest);
+}
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+
+    if (index >= params.instance_count) {
+        return;
+    }
+
+    let instance   = instances[index];
+    let bounds_min = instance.bounds_min.xyz;
+    let bounds_max = instance.bounds_max.xyz;
+
+    if (!in_frustum(bounds_min, bounds_max)) {
+        return;
+    }
+
+    if (params.occlusion != 0u && is_occluded(bounds_min, bounds_max)) {
+        return;
+    }
+
+    let slot = atomicAdd(&args[0].instance_count, 1u);
+
+    visible[slot] = instance.model;
+}
+
+// Every mesh of the model draws the same instances, so copy the count to the other meshes' args
+@compute @workgroup_size(1)
+fn cs_finalize() {
+    let count = atomicLoad(&args[0].instance_count);
+
+    for (var i = 1u; i < arrayLength(&args); i = i + 1u) {
+        atomicStore(&args[i].instance_count, count);
+    }
+}
@@ -0,0 +1,272 @@
+// Smooth curves through or near a list of points, for paths, roads, rails and camera tracks.
+// Bezier curves pass through every third point with the two between pulling them into shape,
+// Catmull-Rom splines pass through every point, and B-splines only pass near them but are the
+// smoothest of the three. All three are made of cubic pieces, which are kept in Bezier form so
+// they can be evaluated and cut up the same way.
+//
+// Curves are flattened into points by splitting each piece in half until both halves are close
+// enough to straight. How close is measured in world units, or in pixels once projected onto the
+// screen, so that curves get finer up close and coarser in the distance.
+
+use cgmath::prelude::*;
+
+// Deepest a piece is split, which is 2^12 segments for one piece at most
+const MAX_DEPTH: u32 = 12;
+
+// Pieces crossing the near plane have no flatness on screen, and are split this deep instead
+const NEAR_PLANE_DEPTH: u32 = 6;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CurveKind {
+    // Pieces run from every third point to the next, with the two between as control points
+    Bezier,
+    CatmullRom,
+    BSpline,
+}
+
+// How far the flattened points may stray from the curve
+#[derive(Debug, Copy, Clone)]
+pub enum Tolerance {
+    // In world units
+    World(f32),
+    // In pixels, after projecting with `view_proj` onto a target `viewport` pixels in size
+    Screen {
+        view_proj: cgmath::Matrix4<f32>,
+        viewport:  (f32, f32),
+        pixels:    f32,
+    },
+}
+
+// One cubic Bezier piece of a curve
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CubicBezier {
+    pub points: [cgmath::Point3<f32>; 4],
+}
+
+impl CubicBezier {
+    pub fn point(&self, t: f32) -> cgmath::Point3<f32> {
+        let [p0, p1, p2, p3] = self.points.map(|p| p.to_vec());
+        let u = 1.0 - t;
+
+        cgmath::Point3::from_vec(p0 * (u * u * u) + p1 * (3.0 * u * u * t) + p2 * (3.0 * u * t * t) + p3 * (t * t * t))
+    }
+
+    // Not normalized, and zero where control points coincide with the ends
+    pub fn derivative(&self, t: f32) -> cgmath::Vector3<f32> {
+        let [p0, p1, p2, p3] = self.points;
+        let u = 1.0 - t;
+
+        (p1 - p0) * (3.0 * u * u) + (p2 - p1) * (6.0 * u * t) + (p3 - p2) * (3.0 * t * t)
+    }
+
+    // The two halves either side of the middle, by de Casteljau's construction
+    pub fn split(&self) -> (CubicBezier, CubicBezier) {
+        let [p0, p1, p2, p3] = self.points;
+
+        let a   = p0.midpoint(p1);
+        let b   = p1.midpoint(p2);
+        let c   = p2.midpoint(p3);
+        let ab  = a.midpoint(b);
+        let bc  = b.midpoint(c);
+        let mid = ab.midpoint(bc);
+
+        (CubicBezier { points: [p0, a, ab, mid] }, CubicBezier { points: [mid, bc, c, p3] })
+    }
+
+    // Adds the points after the first along the piece to `out`
+    fn flatten(&self, tolerance: &Tolerance, depth: u32, out: &mut Vec<cgmath::Point3<f32>>) {
+        if depth >= MAX_DEPTH || self.is_flat(tolerance, depth) {
+            out.push(self.points[3]);
+            return;
+        }
+
+        let (first, second) = self.split();
+
+        first.flatten(tolerance, depth + 1, out);
+        second.flatten(tolerance, depth + 1, out);
+    }
+
+    // Whether the control points are within tolerance of the line between the ends, which bounds
+    // how far the piece strays from it
+    fn is_flat(&self, tolerance: &Tolerance, depth: u32) -> bool {
+        match *tolerance {
+            Tolerance::World(distance) => {
+                let [p0, p1, p2, p3] = self.points;
+
+                distance_to_line(p1, p0, p3).max(distance_to_line(p2, p0, p3)) <= distance
+            }
+            Tolerance::Screen { view_proj, viewport, pixels } => {
+                let clip = self.points.map(|p| view_proj * p.to_homogeneous());
+
+                if clip.iter().all(|c| c.w <= 0.0) {
+                    return true;
+                }
+                if clip.iter().any(|c| c.w <= 0.0) {
+                    return depth >= NEAR_PLANE_DEPTH;
+                }
+
+                let [s0, s1, s2, s3] = clip.map(|c| cgmath::Point3::new(c.x / c.w * viewport.0 * 0.5, c.y / c.w * viewport.1 * 0.5, 0.0));
+
+                distance_to_line(s1, s0, s3).max(distance_to_line(s2, s0, s3)) <= pixels
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Curve {
+    pub kind:   CurveKind,
+    pub points: Vec<cgmath::Point3<f32>>,
+    // Joins the last point back to the first. Ignored for Bezier curves, which can repeat their
+    // first point at the end instead.
+    pub closed: bool,
+}
+
+impl Curve {
+    pub fn new(kind: CurveKind, points: Vec<cgmath::Point3<f32>>) -> Self {
+        Self { kind, points, closed: false }
+    }
+
+    pub fn closed(mut self) -> Self {
+        self.closed = true;
+        self
+    }
+
+    // The curve's cubic pieces, in order. Open Catmull-Rom splines and B-splines repeat their end
+    // points so the curve reaches all the way to them.
+    pub fn pieces(&self) -> Vec<CubicBezier> {
+        let points = &self.points;
+        let count  = points.len();
+
+        if count < 2 {
+            return Vec::new();
+        }
+
+        // Surrounding points of piece `i`, wrapping or clamped at the ends
+        let around = |i: usize| -> [cgmath::Point3<f32>; 4] {
+            let at = |offset: isize| {
+                let index = i as isize + offset;
+
+                if self.closed {
+                    points[index.rem_euclid(count as isize) as usize]
+                } else {
+                    points[index.clamp(0, count as isize - 1) as usize]
+                }
+            };
+
+            [at(-1), at(0), at(1), at(2)]
+        };
+
+        let spans = if self.closed { count } else { count - 1 };
+
+        match self.kind {
+            CurveKind::Bezier => points
+                .windows(4)
+                .step_by(3)
+                .map(|window| CubicBezier { points: [window[0], window[1], window[2], window[3]] })
+                .collect(),
+            CurveKind::CatmullRom => (0..spans)
+                .map(|i| {
+                    let [p0, p1, p2, p3] = around(i);
+
+                    CubicBezier { points: [p1, p1 + (p2 - p0) / 6.0, p2 - (p3 - p1) / 6.0, p2] }
+                })
+                .collect(),
+            CurveKind::BSpline => (0..spans)
+                .map(|i| {
+                    let [p0, p1, p2, p3] = around(i).map(|p| p.to_vec());
+
+                    CubicBezier {
+                        points: [
+                            (p0 + p1 * 4.0 + p2) / 6.0,
+                            (p1 * 2.0 + p2) / 3.0,
+                            (p1 + p2 * 2.0) / 3.0,
+                            (p1 + p2 * 4.0 + p3) / 6.0,
+                        ].map(cgmath::Point3::from_vec),
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    // The point `t` of the way along the pieces, from 0.0 at the start to 1.0 at the end. Pieces
+    // each get an equal share whatever their length.
+    pub fn point(&self, t: f32) -> Option<cgmath::Point3<f32>> {
+        let pieces = self.pieces();
+        let (i, t) = piece_at(pieces.len(), t)?;
+
+        Some(pieces[i].point(t))
+    }
+
+    // The direction the curve runs at `t`, like `point`
+    pub fn tangent(&self, t: f32) -> Option<cgmath::Vector3<f32>> {
+        let pieces = self.pieces();
+        let (i, t) = piece_at(pieces.len(), t)?;
+
+        let derivative = pieces[i].derivative(t);
+
+        (derivative.magnitude2() > 0.0).then(|| derivative.normalize())
+    }
+
+    // Points along the curve, from its start to its end, close enough together to draw as straight
+    // segments
+    pub fn flatten(&self, tolerance: &Tolerance) -> Vec<cgmath::Point3<f32>> {
+        let pieces = self.pieces();
+        let mut points = Vec::new();
+
+        if let Some(first) = pieces.first() {
+            points.push(first.points[0]);
+        }
+
+        for piece in &pieces {
+            piece.flatten(tolerance, 0, &mut points);
+        }
+
+        points
+    }
+}
+
+// A point on the Catmull-Rom spline from `p1` to `p2`, `t` of the way along
+pub fn catmull_rom(
+    p0: cgmath::Point3<f32>,
+    p1: cgmath::Point3<f32>,
+    p2: cgmath::Point3<f32>,
+    p3: cgmath::Point3<f32>,
+    t:  f32,
+) -> cgmath::Point3<f32> {
+    let (p0, p1, p2, p3) = (p0.to_vec(), p1.to_vec(), p2.to_vec(), p3.to_vec());
+    let t2 = t * t;
+    let t3 = t2 * t;
+
+    cgmath::Point3::from_vec(
+        (p1 * 2.0
+            + (p2 - p0) * t
+            + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+            + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3) * 0.5
+    )
+}
+
+// Which of `count` pieces `t` falls in, and how far along it
+fn piece_at(count: usize, t: f32) -> Option<(usize, f32)> {
+    if count == 0 {
+        return None;
+    }
+
+    let scaled = t.clamp(0.0, 1.0) * count as f32;
+    let i      = (scaled as usize).min(count - 1);
+
+    Some((i, scaled - i as f32))
+}
+
+fn distance_to_line(p: cgmath::Point3<f32>, a: cgmath::Point3<f32>, b: cgmath::Point3<f32>) -> f32 {
+    let line   = b - a;
+    let length = line.magnitude2();
+
+    if length <= f32::EPSILON {
+        return p.distance(a);
+    }
+
+    let t = ((p - a).dot(line) / length).clamp(0.0, 1.0);
+
+    p.distance(a + line * t)
+}
@@ -0,0 +1,384 @@
+// Immediate-mode line drawing for visualizing bounds, rays and the like. Lines are queued during
+// the frame, uploaded by `prepare` and drawn in the debug layer. What's worth drawing is sorted
+// into categories that can be switched on and off separately, which callers check before
+// queueing their lines. Lines queued in overlay mode are drawn over everything, for handles that
+// have to stay visible inside or behind the object they belong to.
+
+use cgmath::prelude::*;
+use learn_wgpu_derive::VertexLayout;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    bind_group,
+    bounds::Aabb,
+    camera::Camera,
+    crash_report,
+    pass::{Drawable, RenderLayer},
+    renderer::{self, GpuContext},
+    texture,
+};
+
+const INITIAL_CAPACITY: usize = 1024;
+
+// Segments in each circle of spheres and cones
+const CIRCLE_SEGMENTS: u32 = 32;
+
+// Pairs of corner indices, see `Aabb::corners` for the ordering
+const BOX_EDGES: [(usize, usize); 12] = [
+    (0, 1), (2, 3), (4, 5), (6, 7),
+    (0, 2), (1, 3), (4, 6), (5, 7),
+    (0, 4), (1, 5), (2, 6), (3, 7),
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DebugCategory {
+    // Bounding volume hierarchy nodes
+    Bounds,
+    // Camera frusta
+    Frusta,
+    // What each shadow map covers
+    Shadows,
+    // Light ranges and cones
+    Lights,
+    // The view frustum cut into the cells a clustered light list would use
+    Clusters,
+}
+
+impl DebugCategory {
+    pub const ALL: [DebugCategory; 5] = [
+        DebugCategory::Bounds,
+        DebugCategory::Frusta,
+        DebugCategory::Shadows,
+        DebugCategory::Lights,
+        DebugCategory::Clusters,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DebugCategory::Bounds   => "Bounds",
+            DebugCategory::Frusta   => "Frusta",
+            DebugCategory::Shadows  => "Shadow bounds",
+            DebugCategory::Lights   => "Light volumes",
+            DebugCategory::Clusters => "Cluster grid",
+        }
+    }
+
+    fn bit(&self) -> u32 {
+        1 << *self as u32
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+pub struct DebugVertex {
+    #[location(0)]
+    pub position: [f32; 3],
+    #[location(1)]
+    pub color:    [f32; 3],
+}
+
+pub struct DebugDraw {
+    pipeline:          wgpu::RenderPipeline,
+    overlay_pipeline:  wgpu::RenderPipeline,
+    camera_buffer:     wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
+    vertex_buffer:     wgpu::Buffer,
+    capacity:          usize,
+    vertices:          Vec<DebugVertex>,
+    overlay_vertices:  Vec<DebugVertex>,
+    vertex_count:      u32,
+    overlay_count:     u32,
+    // Whether lines are queued in `overlay_vertices`
+    overlay:           bool,
+    // One bit per `DebugCategory`
+    enabled:           u32,
+}
+
+impl DebugDraw {
+    pub fn new(ctx: &GpuContext) -> Self {
+        let device = &ctx.device;
+
+        let camera_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label:    Some("Debug Camera Buffer"),
+                contents: bytemuck::cast_slice(&[[[0.0_f32; 4]; 4]]),
+                usage:    wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        let camera_bind_group_layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::VERTEX)
+            .build(device, "debug_camera_bind_group_layout");
+
+        let camera_bind_group = bind_group::BindGroupBuilder::new(&camera_bind_group_layout)
+            .uniform(&camera_buffer)
+            .build(device, "debug_camera_bind_group");
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Debug Line Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("debug_lines.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Debug Line Pipeline Layout"),
+            bind_group_layouts:   &[&camera_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let create_pipeline = |compare, label| device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label:    Some(label),
+            layout:   Some(&pipeline_layout),
+            vertex:   wgpu::VertexState {
+                module:      &shader,
+                entry_point: "vs_main",
+                buffers:     &[DebugVertex::layout()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module:      &shader,
+                entry_point: "fs_main",
+                targets:     &[Some(wgpu::ColorTargetState {
+                    format:     ctx.config.format,
+                    blend:      Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology:           wgpu::PrimitiveTopology::LineList,
+                strip_index_format: None,
+                front_face:         wgpu::FrontFace::Ccw,
+                cull_mode:          None,
+                polygon_mode:       wgpu::PolygonMode::Fill,
+                unclipped_depth:    false,
+                conservative:       false,
+            },
+            depth_stencil: Some(renderer::depth_state(texture::Texture::DEPTH_FORMAT, compare, false)),
+            multisample:   wgpu::MultisampleState {
+                count: 1,
+                mask:  !0,
+                alpha_to_coverage_enabled: false
+            },
+            multiview: None,
+        });
+
+        // Lines are hidden behind geometry but don't occlude anything themselves. Overlay lines
+        // aren't hidden either.
+        let pipeline         = create_pipeline(ctx.depth_mode.compare(), "Debug Line Pipeline");
+        let overlay_pipeline = create_pipeline(wgpu::CompareFunction::Always, "Debug Overlay Pipeline");
+
+        Self {
+            pipeline,
+            overlay_pipeline,
+            camera_buffer,
+            camera_bind_group,
+            vertex_buffer: create_vertex_buffer(device, INITIAL_CAPACITY),
+            capacity:      INITIAL_CAPACITY,
+            vertices:         Vec::new(),
+            overlay_vertices: Vec::new(),
+            vertex_count:     0,
+            overlay_count:    0,
+            overlay:          false,
+            enabled:          0,
+        }
+    }
+
+    pub fn is_enabled(&self, category: DebugCategory) -> bool {
+        self.enabled & category.bit() != 0
+    }
+
+    pub fn set_enabled(&mut self, category: DebugCategory, enabled: bool) {
+        if enabled {
+            self.enabled |= category.bit();
+        } else {
+            self.enabled &= !category.bit();
+        }
+    }
+
+    // Returns whether the category is now enabled
+    pub fn toggle(&mut self, category: DebugCategory) -> bool {
+        self.enabled ^= category.bit();
+        self.is_enabled(category)
+    }
+
+    // Lines queued from now on are drawn over everything while `overlay` is set
+    pub fn set_overlay(&mut self, overlay: bool) {
+        self.overlay = overlay;
+    }
+
+    pub fn line(&mut self, a: cgmath::Point3<f32>, b: cgmath::Point3<f32>, color: [f32; 3]) {
+        let vertices = if self.overlay { &mut self.overlay_vertices } else { &mut self.vertices };
+
+        vertices.push(DebugVertex { position: a.into(), color });
+        vertices.push(DebugVertex { position: b.into(), color });
+    }
+
+    pub fn aabb(&mut self, aabb: &Aabb, color: [f32; 3]) {
+        self.corners(&aabb.corners(), color);
+    }
+
+    // The volume `view_proj` maps into clip space, e.g. a camera's or a shadow map's. Either depth
+    // mode works, since reverse-Z only swaps which end is near.
+    pub fn frustum(&mut self, view_proj: &cgmath::Matrix4<f32>, color: [f32; 3]) {
+        let inverse = match view_proj.invert() {
+            Some(inverse) => inverse,
+            None          => return,
+        };
+
+        let corner = |x: f32, y: f32, z: f32| cgmath::Point3::from_homogeneous(inverse * cgmath::Vector4::new(x, y, z, 1.0));
+
+        self.corners(&[
+            corner(-1.0, -1.0, 0.0), corner(1.0, -1.0, 0.0), corner(-1.0, 1.0, 0.0), corner(1.0, 1.0, 0.0),
+            corner(-1.0, -1.0, 1.0), corner(1.0, -1.0, 1.0), corner(-1.0, 1.0, 1.0), corner(1.0, 1.0, 1.0),
+        ], color);
+    }
+
+    pub fn circle(&mut self, center: cgmath::Point3<f32>, normal: cgmath::Vector3<f32>, radius: f32, color: [f32; 3]) {
+        let normal  = normal.normalize();
+        let helper  = if normal.y.abs() > 0.99 { cgmath::Vector3::unit_x() } else { cgmath::Vector3::unit_y() };
+        let tangent = normal.cross(helper).normalize() * radius;
+        let other   = normal.cross(tangent);
+
+        let point = |i: u32| {
+            let angle = i as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+
+            center + tangent * angle.cos() + other * angle.sin()
+        };
+
+        for i in 0..CIRCLE_SEGMENTS {
+            self.line(point(i), point(i + 1), color);
+        }
+    }
+
+    // Three circles around the axes, which is enough to judge a light's reach
+    pub fn sphere(&mut self, center: cgmath::Point3<f32>, radius: f32, color: [f32; 3]) {
+        self.circle(center, cgmath::Vector3::unit_x(), radius, color);
+        self.circle(center, cgmath::Vector3::unit_y(), radius, color);
+        self.circle(center, cgmath::Vector3::unit_z(), radius, color);
+    }
+
+    // A spot light's cone, `angle` being the half-angle from `direction`, closed off where it
+    // reaches `range`
+    pub fn cone(
+        &mut self,
+        apex:      cgmath::Point3<f32>,
+        direction: cgmath::Vector3<f32>,
+        angle:     cgmath::Deg<f32>,
+        range:     f32,
+        color:     [f32; 3],
+    ) {
+        let direction = direction.normalize();
+        let angle     = cgmath::Rad::from(angle).0;
+        let center    = apex + direction * range * angle.cos();
+        let radius    = range * angle.sin();
+
+        self.circle(center, direction, radius, color);
+
+        let helper  = if direction.y.abs() > 0.99 { cgmath::Vector3::unit_x() } else { cgmath::Vector3::unit_y() };
+        let tangent = direction.cross(helper).normalize() * radius;
+        let other   = direction.cross(tangent);
+
+        for edge in [tangent, -tangent, other, -other] {
+            self.line(apex, center + edge, color);
+        }
+    }
+
+    // `camera`'s frustum cut into `cells` in x, y and depth. Depth slices get exponentially
+    // thicker, so cells stay roughly cube-shaped as they shrink on screen.
+    pub fn cluster_grid(&mut self, camera: &Camera, cells: [u32; 3], color: [f32; 3]) {
+        let view = match cgmath::Matrix4::look_at_rh(camera.eye, camera.target, camera.up).invert() {
+            Some(view) => view,
+            None       => return,
+        };
+
+        let [x_cells, y_cells, z_cells] = cells.map(|n| n.max(1));
+        let tan_y = (cgmath::Rad::from(cgmath::Deg(camera.fovy)).0 / 2.0).tan();
+        let tan_x = tan_y * camera.aspect;
+
+        let depth = |k: u32| camera.znear * (camera.zfar / camera.znear).powf(k as f32 / z_cells as f32);
+        // A point on the slice `d` in front of the camera, `u` and `v` running from 0.0 to 1.0 across it
+        let point = |u: f32, v: f32, d: f32| view.transform_point(cgmath::Point3::new(
+            (u * 2.0 - 1.0) * tan_x * d,
+            (v * 2.0 - 1.0) * tan_y * d,
+            -d,
+        ));
+
+        for k in 0..=z_cells {
+            let d = depth(k);
+
+            for i in 0..=x_cells {
+                let u = i as f32 / x_cells as f32;
+                self.line(point(u, 0.0, d), point(u, 1.0, d), color);
+            }
+            for j in 0..=y_cells {
+                let v = j as f32 / y_cells as f32;
+                self.line(point(0.0, v, d), point(1.0, v, d), color);
+            }
+        }
+
+        let (near, far) = (depth(0), depth(z_cells));
+
+        for i in 0..=x_cells {
+            for j in 0..=y_cells {
+                let (u, v) = (i as f32 / x_cells as f32, j as f32 / y_cells as f32);
+                self.line(point(u, v, near), point(u, v, far), color);
+            }
+        }
+    }
+
+    // The edges of a box given by its corners, in `Aabb::corners` order
+    fn corners(&mut self, corners: &[cgmath::Point3<f32>; 8], color: [f32; 3]) {
+        for (a, b) in BOX_EDGES {
+            self.line(corners[a], corners[b], color);
+        }
+    }
+
+    // Uploads the lines queued since the last call, growing the vertex buffer if needed
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, view_proj: cgmath::Matrix4<f32>) {
+        let view_proj: [[f32; 4]; 4] = view_proj.into();
+
+        queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[view_proj]));
+
+        // Overlay lines follow the others in the same buffer
+        self.vertex_count  = self.vertices.len() as u32;
+        self.overlay_count = self.overlay_vertices.len() as u32;
+        self.vertices.append(&mut self.overlay_vertices);
+
+        if self.vertices.len() > self.capacity {
+            self.capacity      = self.vertices.len().next_power_of_two();
+            self.vertex_buffer = create_vertex_buffer(device, self.capacity);
+        }
+
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&self.vertices));
+
+        self.vertices.clear();
+    }
+}
+
+fn create_vertex_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label:              Some("Debug Line Vertex Buffer"),
+        size:               (capacity * std::mem::size_of::<DebugVertex>()) as wgpu::BufferAddress,
+        usage:              wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+impl Drawable for DebugDraw {
+    fn draw<'a>(&'a self, layer: RenderLayer, render_pass: &mut wgpu::RenderPass<'a>) {
+        if layer != RenderLayer::Debug || self.vertex_count + self.overlay_count == 0 {
+            return;
+        }
+
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+
+        if self.vertex_count > 0 {
+            crash_report::set_pipeline(render_pass, &self.pipeline, "Debug Line Pipeline");
+            render_pass.draw(0..self.vertex_count, 0..1);
+        }
+
+        if self.overlay_count > 0 {
+            crash_report::set_pipeline(render_pass, &self.overlay_pipeline, "Debug Overlay Pipeline");
+            render_pass.draw(self.vertex_count..self.vertex_count + self.overlay_count, 0..1);
+        }
+    }
+}
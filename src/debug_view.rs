@@ -0,0 +1,183 @@
+// Full-screen views of what the passes write and read, for telling which one a wrong picture
+// comes from. `DebugView` is cycled through in order. Normals, albedo, roughness and specular, and
+// contact shadows are what the shading models would have lit with, so the lights' uniform carries
+// the mode and `debug_surface` in lights.wgsl outputs it in place of the lit color. Depth and the
+// shadow atlas are drawn over the frame by `DebugViewer`, and velocity and the cluster heatmap by
+// the buffers that already have views of their own.
+//
+// Contact shadows are the only screen-space occlusion there is, so they stand in for SSAO. They
+// need the depth prepass, and are white without it.
+
+use crate::{
+    bind_group,
+    camera::{Camera, DepthMode},
+    crash_report,
+    pass::{Drawable, RenderLayer},
+    renderer::{self, GpuContext},
+    uniform::{Uniform, UniformBuffer},
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DebugView {
+    Off,
+    Depth,
+    Normals,
+    Albedo,
+    // Roughness in red and specular in green
+    RoughnessSpecular,
+    Velocity,
+    ShadowAtlas,
+    ContactShadows,
+    LightClusters,
+}
+
+impl DebugView {
+    pub const ALL: [DebugView; 9] = [
+        DebugView::Off,
+        DebugView::Depth,
+        DebugView::Normals,
+        DebugView::Albedo,
+        DebugView::RoughnessSpecular,
+        DebugView::Velocity,
+        DebugView::ShadowAtlas,
+        DebugView::ContactShadows,
+        DebugView::LightClusters,
+    ];
+
+    // The view after this one, wrapping back to `Off`
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&view| view == self).unwrap_or(0);
+
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DebugView::Off               => "Off",
+            DebugView::Depth             => "Depth",
+            DebugView::Normals           => "Normals",
+            DebugView::Albedo            => "Albedo",
+            DebugView::RoughnessSpecular => "Roughness and specular",
+            DebugView::Velocity          => "Velocity",
+            DebugView::ShadowAtlas       => "Shadow atlas",
+            DebugView::ContactShadows    => "Contact shadows",
+            DebugView::LightClusters     => "Light clusters",
+        }
+    }
+
+    // What `debug_surface` in lights.wgsl shows for this view, zero for lighting as usual
+    pub fn surface_mode(self) -> u32 {
+        match self {
+            DebugView::Normals           => 1,
+            DebugView::Albedo            => 2,
+            DebugView::RoughnessSpecular => 3,
+            DebugView::ContactShadows    => 4,
+            _                            => 0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct DebugViewUniform {
+    // Non-zero to turn depth into distance from the camera, zero to show it as stored
+    linearize:  u32,
+    reverse_z:  u32,
+    near:       f32,
+    far:        f32,
+    full_depth: f32,
+    _padding:   [f32; 3],
+}
+
+// Draws a depth texture over the frame, either the scene's as distance from the camera or one
+// like the shadow atlas as it's stored
+pub struct DebugViewer {
+    // Distance in world units that the depth view shows at full brightness
+    pub full_depth: f32,
+    pipeline:       wgpu::RenderPipeline,
+    layout:         wgpu::BindGroupLayout,
+    uniform:        UniformBuffer<DebugViewUniform>,
+    // Made in `prepare`, since the depth texture is replaced on resize
+    bind_group:     Option<wgpu::BindGroup>,
+}
+
+impl DebugViewer {
+    pub fn new(ctx: &GpuContext) -> Self {
+        let device = &ctx.device;
+
+        let layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::FRAGMENT)
+            .depth_texture(wgpu::ShaderStages::FRAGMENT)
+            .build(device, "debug_view_bind_group_layout");
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Debug View Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("debug_view.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Debug View Pipeline Layout"),
+            bind_group_layouts:   &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        // Drawn over everything in the UI layer, which has no depth attachment to get in the way
+        // of reading the depth buffer
+        let pipeline = renderer::create_render_pipeline(
+            device,
+            &pipeline_layout,
+            ctx.config.format,
+            None,
+            &[],
+            &shader,
+            "Debug View Pipeline",
+        );
+
+        Self {
+            full_depth: 50.0,
+            pipeline,
+            layout,
+            uniform:    UniformBuffer::new(device, "Debug View Buffer"),
+            bind_group: None,
+        }
+    }
+
+    // Shows the depth buffer `camera` drew into as distance from it
+    pub fn prepare_depth(&mut self, ctx: &GpuContext, camera: &Camera) {
+        self.prepare(ctx, &ctx.depth_texture.view, Some(camera));
+    }
+
+    // Shows `depth` as it's stored, e.g. `Lights::shadow_atlas_view`
+    pub fn prepare_raw(&mut self, ctx: &GpuContext, depth: &wgpu::TextureView) {
+        self.prepare(ctx, depth, None);
+    }
+
+    fn prepare(&mut self, ctx: &GpuContext, depth: &wgpu::TextureView, camera: Option<&Camera>) {
+        self.uniform.write(&ctx.queue, &DebugViewUniform {
+            linearize:  camera.is_some() as u32,
+            reverse_z:  camera.is_some_and(|camera| camera.depth_mode == DepthMode::ReverseZ) as u32,
+            near:       camera.map_or(0.0, |camera| camera.znear),
+            far:        camera.map_or(1.0, |camera| camera.zfar),
+            full_depth: self.full_depth.max(f32::EPSILON),
+            _padding:   [0.0; 3],
+        });
+
+        // Remade every frame it's shown, since the texture it shows can change from one to the next
+        self.bind_group = Some(
+            bind_group::BindGroupBuilder::new(&self.layout)
+                .uniform(self.uniform.buffer())
+                .texture(depth)
+                .build(&ctx.device, "debug_view_bind_group")
+        );
+    }
+}
+
+impl Drawable for DebugViewer {
+    fn draw<'a>(&'a self, _layer: RenderLayer, render_pass: &mut wgpu::RenderPass<'a>) {
+        if let Some(bind_group) = &self.bind_group {
+            crash_report::set_pipeline(render_pass, &self.pipeline, "Debug View Pipeline");
+            render_pass.set_bind_group(0, bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+}
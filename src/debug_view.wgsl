@@ -0,0 +1,60 @@
+// Shows a depth texture over the screen, see debug_view.rs. The scene's depth is turned back into
+// distance from the camera, brighter further away, and anything else is shown as it's stored.
+
+struct DebugView {
+    linearize:  u32,
+    reverse_z:  u32,
+    near:       f32,
+    far:        f32,
+    // Distance in world units that shows at full brightness
+    full_depth: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> debug: DebugView;
+@group(0) @binding(1)
+var t_depth: texture_depth_2d;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv:                  vec2<f32>,
+}
+
+// One triangle that covers the screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+
+    out.uv            = uv;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Loaded rather than sampled, since depth can't be filtered and may be smaller than the window
+    let size  = vec2<f32>(textureDimensions(t_depth));
+    let coord = min(vec2<i32>(in.uv * size), vec2<i32>(size) - 1);
+    let depth = textureLoad(t_depth, coord, 0);
+
+    if (debug.linearize == 0u) {
+        return vec4<f32>(vec3<f32>(depth), 1.0);
+    }
+
+    let near = debug.near;
+    let far  = debug.far;
+
+    // Inverts the projections in camera.rs
+    var distance: f32;
+
+    if (debug.reverse_z != 0u) {
+        distance = near * far / (near + depth * (far - near));
+    } else {
+        distance = near * far / (far - depth * (far - near));
+    }
+
+    return vec4<f32>(vec3<f32>(clamp(distance / debug.full_depth, 0.0, 1.0)), 1.0);
+}
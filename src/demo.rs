@@ -0,0 +1,2597 @@
+use std::{cell::Cell, ops::Range, time::Duration};
+
+use anyhow::Context;
+use cgmath::prelude::*;
+use wgpu::util::DeviceExt;
+use winit::event::*;
+
+use crate::{
+    ambient::{SkyGradient, SphericalHarmonics},
+    analysis::{AnalysisResults, SceneAnalysis},
+    animation::{AnimationTargets, Animator, Curve, Keyframe, Repeat, Track},
+    app::{App, SetupFuture},
+    bind_group,
+    bloom::Bloom,
+    bounds::{Aabb, Bvh, Frustum, Hit, Obb, Ray},
+    camera::{Camera, CameraController, CameraUniform},
+    camera_motion::{CameraFollow, CameraShake, FovTransition},
+    camera_path::{CameraKey, CameraPath, CameraPathPlayer, Easing},
+    canvas::{self, Canvas2D, CanvasSpace},
+    color_grading::ColorGrading,
+    contact_shadows::{ContactShadowSettings, ContactShadows},
+    crash_report,
+    curves::{self, CurveKind, Tolerance},
+    debug_draw::{DebugCategory, DebugDraw},
+    debug_view::{DebugView, DebugViewer},
+    dof::DepthOfField,
+    edges::{EdgeCaster, EdgeDetection, EdgeMaskRaw, MASK_SELECTED, MASK_STYLIZED},
+    editor,
+    exposure::AutoExposure,
+    foliage::{Foliage, FoliageArea, FoliageKind},
+    fur::Fur,
+    gpu_cull::{CullInstance, GpuCuller},
+    input::Input,
+    lens_flare::{Glare, GlareSource, LensFlare},
+    light::{Lights, RectLight, SpotLight},
+    light_clusters::{ClusterStats, LightClusters},
+    light_probes::ProbeGrid,
+    lod::{LodChain, LodSelector},
+    material_array::MaterialArray,
+    minimap::{self, Minimap},
+    model::{self, DrawModel, Instance, InstanceRaw, LayeredInstanceRaw, Vertex},
+    motion_blur::MotionBlur,
+    ocean::{Ocean, OceanSettings, Spectrum},
+    outline::OutlinePass,
+    pass::{self, Drawable, RenderLayer, RenderLayers},
+    planet::{Planet, PlanetSettings},
+    plugin::Plugins,
+    portal::{Portal, PortalFrame},
+    reflect,
+    reflection::{Mirror, ReflectionProbe},
+    renderer::{self, Frame, GpuContext, RendererOptions},
+    resolution::DynamicResolution,
+    resources,
+    retro::RetroFilter,
+    scene::Scene,
+    shading::{AlphaMode, CullMode, DepthBias, MaterialFeatures, ShadingModel, ShadingPipelines},
+    shadow::{ShadowCaster, ShadowQuality},
+    split_screen::{self, InputSource, KeyBindings, Player, SplitScreen},
+    ssr::ScreenSpaceReflections,
+    texture,
+    thumbnail::ThumbnailRenderer,
+    transmission::Transmission,
+    ui::{Anchor, Ui, UiId, UiKind, UiNode, UiScale},
+    uniform::UniformBuffer,
+    velocity::{MotionCaster, VelocityBuffer},
+    voxel::{Block, BlockFaces, VoxelWorld, AIR},
+    weather::{Precipitation, Weather},
+    wide_lines::{LineWidth, WideLines},
+};
+#[cfg(feature = "egui")]
+use crate::{egui_overlay::EguiOverlay, gpu_stats};
+
+const CAMERA_SPEED: f32 = 0.2;
+
+// 60 fps
+const TARGET_FRAME_TIME: Duration = Duration::from_micros(16_667);
+
+const NUM_INSTANCES_PER_ROW: u32 = 10;
+
+const BOUNDS_COLOR:        [f32; 3] = [1.0, 0.8, 0.0];
+const FRUSTUM_COLOR:       [f32; 3] = [0.0, 1.0, 1.0];
+const SHADOW_BOUNDS_COLOR: [f32; 3] = [1.0, 0.0, 1.0];
+const CLUSTER_COLOR:       [f32; 3] = [0.3, 0.3, 0.8];
+// Cells of the cluster grid in x, y and depth
+const CLUSTER_CELLS:       [u32; 3] = [16, 9, 24];
+
+const OUTLINE_COLOR:     [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+const OUTLINE_THRESHOLD: f32      = 0.002;
+
+const MIRROR_HEIGHT:    f32 = -2.0;
+const MIRROR_HALF_SIZE: f32 = 20.0;
+const PROBE_SIZE:       u32 = 256;
+
+// (fraction of triangles kept, screen size it's used below) for each simplified level of detail
+#[cfg(feature = "meshopt")]
+const LOD_LEVELS: [(f32, f32); 2] = [(0.5, 0.1), (0.2, 0.04)];
+
+// Grass around the grid, on flat ground just under the cubes
+const FOLIAGE_GROUND:    f32 = -1.0;
+const FOLIAGE_HALF_SIZE: f32 = 18.0;
+const FOLIAGE_DENSITY:   f32 = 40.0;
+
+// Block terrain past the far side of the cubes, with blocks dug and placed up to `VOXEL_REACH`
+// away. Each block texture is a square of noise in one color.
+const VOXEL_ORIGIN:       [f32; 3] = [-24.0, -6.0, 24.0];
+const VOXEL_EXTENT:       [i32; 2] = [48, 32];
+const VOXEL_TEXTURE_SIZE: u32      = 16;
+const VOXEL_REACH:        f32      = 20.0;
+const VOXEL_GRASS:        Block    = 1;
+const VOXEL_DIRT:         Block    = 2;
+const VOXEL_STONE:        Block    = 3;
+
+// A small planet off to the side, small enough to fly around within the far plane
+const PLANET_CENTER: [f32; 3] = [60.0, 10.0, -60.0];
+const PLANET_RADIUS: f32      = 20.0;
+const PLANET_HEIGHT: f32      = 1.5;
+
+// Open water on the other side, low enough that the scene stays dry
+const OCEAN_ORIGIN: [f32; 3] = [-70.0, -2.0, 0.0];
+const OCEAN_EXTENT: f32      = 96.0;
+
+// A road looping around the grid as a B-spline, and how far curves may stray from true on screen
+const ROAD_POINTS: [[f32; 3]; 6] = [
+    [ 30.0, 0.0,   0.0],
+    [ 20.0, 0.0,  30.0],
+    [-15.0, 0.0,  35.0],
+    [-35.0, 0.0,   5.0],
+    [-20.0, 0.0, -30.0],
+    [ 15.0, 0.0, -30.0],
+];
+const ROAD_WIDTH:      f32 = 1.5;
+const CURVE_TOLERANCE: f32 = 0.5;
+
+// The HUD's badge, in pixels from the top left of the window
+const HUD_STAR: &str = "M 176 35.5 L 185.9 66.1 L 218.3 66.1 L 192.2 85 L 202.1 116.5 L 176 97.6 L 149.9 116.5 L 159.8 85 L 133.7 66.1 L 166.1 66.1 Z";
+
+// Who controls each split-screen player, in the order they join
+const PLAYER_INPUTS: [InputSource; split_screen::MAX_PLAYERS] = [
+    InputSource::Keyboard(KeyBindings::WASD),
+    InputSource::Keyboard(KeyBindings::IJKL),
+    InputSource::Gamepad(0),
+    InputSource::Gamepad(1),
+];
+const PLAYER_SPEED:  f32 = 6.0;
+
+// An overhead view of the grid around the camera, in the top right corner
+// Past the far edge of the grid, looking out from its side
+const PORTAL_ENTRANCE:  [f32; 3]   = [0.0, 2.0, -20.0];
+const PORTAL_EXIT:      [f32; 3]   = [20.0, 2.0, 0.0];
+const PORTAL_HALF_SIZE: (f32, f32) = (2.0, 3.0);
+
+const MINIMAP_SIZE:   u32 = 200;
+const MINIMAP_HEIGHT: f32 = 40.0;
+
+const THUMBNAIL_SIZE: u32  = 256;
+const THUMBNAIL_FILE: &str = "thumbnail.png";
+
+// Fly-throughs are recorded one PNG per frame into this directory, at a steady frame rate however
+// long each frame takes to render
+const RECORDING_DIR: &str = "frames";
+const RECORDING_FPS: u32  = 30;
+const SCRUB_STEP:    f32  = 1.0;
+
+const ZOOM_FOVY:     f32 = 20.0;
+const ZOOM_DURATION: f32 = 0.4;
+const FOLLOW_TIME:   f32 = 0.3;
+const SHAKE_TRAUMA:  f32 = 0.5;
+
+const EXPOSURE_STEP:    f32 = 0.25;
+const TEMPERATURE_STEP: f32 = 0.1;
+
+// Brightens the ambient light from the clear color, which is too dark to light much by itself
+const SKY_AMBIENT_INTENSITY: f32 = 4.0;
+
+// Towards a sun low over the far side of the grid, which only exists as lens flare glare
+const SUN_DIRECTION: [f32; 3] = [-0.3, 0.25, -1.0];
+
+// How much of the scene shows through the cubes when they're turned to glass
+const GLASS_TRANSMITTANCE: f32 = 0.9;
+
+// Fur grown on the cubes, in world units
+const FUR_LENGTH: f32 = 0.08;
+
+// Repeats of the cubes' texture per unit when it's projected in world space
+const TRIPLANAR_SCALE: f32 = 0.5;
+
+// The generated detail layer is a grid of noise cells, and its slopes are exaggerated this much
+// for the normals
+const DETAIL_TEXTURE_SIZE: u32 = 64;
+const DETAIL_CELLS:        u32 = 8;
+const DETAIL_BUMPINESS:    f32 = 4.0;
+
+// Light probes are baked over the cubes, raised so none of them sit inside one, with small cube
+// captures that see across the whole grid
+const LIGHT_PROBE_SPACING:   f32 = 4.0;
+const LIGHT_PROBE_HEIGHT:    f32 = 1.5;
+const LIGHT_PROBE_FACE_SIZE: u32 = 32;
+const LIGHT_PROBE_RANGE:     f32 = 100.0;
+
+// Motion blur shutters to cycle through, as shares of the frame
+const SHUTTERS: [f32; 3] = [0.25, 0.5, 1.0];
+
+// Four greens, for a handheld look
+const RETRO_PALETTE: [[u8; 3]; 4] = [[15, 56, 15], [48, 98, 48], [139, 172, 15], [155, 188, 15]];
+
+// A model dropped onto the window, drawn once at the point the camera was looking at
+struct DroppedAsset {
+    model:           model::Model,
+    instance_buffer: wgpu::Buffer,
+}
+
+// The grid of textured cubes this crate started out as
+pub struct Demo {
+    pipelines:         ShadingPipelines,
+    // Same shading as `pipelines` but only passes pixels already laid down by the prepass
+    equal_pipelines:   ShadingPipelines,
+    // One for each cull mode, in `CullMode::ALL` order
+    prepass_pipelines: Vec<wgpu::RenderPipeline>,
+    // Drawn over the scene while any material uses toon shading
+    outline:           OutlinePass,
+    depth_prepass:     bool,
+    // Set while drawing a view with a clip plane. The prepass has no fragment shader to discard
+    // with, so it's skipped and the regular pipelines are used instead.
+    clipped_view:      Cell<bool>,
+    color_grading:     ColorGrading,
+    // Eye adaptation and tone mapping ahead of grading. Unavailable on the web since it needs
+    // compute shaders.
+    auto_exposure:     Option<AutoExposure>,
+    auto_exposing:     bool,
+    // Also compute only, so also unavailable on the web
+    bloom:             Option<Bloom>,
+    show_bloom:        bool,
+    // Ghosts from bloom's bright pass and glare over the sun, so only shown along with bloom
+    lens_flare:        Option<LensFlare>,
+    show_lens_flare:   bool,
+    // Copies the opaque scene for glass materials to see through, only while there are some
+    transmission:      Transmission,
+    // Copies the prepass depth for the lights to march contact shadows through
+    contact_shadows:   ContactShadows,
+    // Shells for materials with fur, drawn after the meshes they grow from
+    fur:               Fur,
+    // Lights per cell of the cluster grid, as a heatmap over the screen. Compute only as well.
+    light_clusters:    Option<LightClusters>,
+    light_heatmap:     bool,
+    // Luminance and depth statistics of the final frame, logged as they change. Compute only.
+    analysis:          Option<SceneAnalysis>,
+    analyzing:         bool,
+    logged_analysis:   Option<AnalysisResults>,
+    // The cluster stats last logged
+    logged_stats:      Option<ClusterStats>,
+    retro:             RetroFilter,
+    // Off, CRT, then CRT with `RETRO_PALETTE`
+    retro_mode:        u32,
+    texture_layout:    wgpu::BindGroupLayout,
+    // Whether the cubes have the generated detail layer
+    detailed:          bool,
+    obj_model:         model::Model,
+    dropped:           Vec<DroppedAsset>,
+    camera:            Camera,
+    // What's drawn this frame: `camera` with any shake applied
+    view:              Camera,
+    camera_uniform:    CameraUniform,
+    camera_buffer:     UniformBuffer<CameraUniform>,
+    camera_bind_group: wgpu::BindGroup,
+    camera_controller: CameraController,
+    // Keeps the instance selected in the editor in view while `following`
+    follow:            CameraFollow,
+    following:         bool,
+    zoom:              FovTransition,
+    // The field of view to zoom back out to
+    base_fovy:         f32,
+    shake:             CameraShake,
+    lights:            Lights,
+    // Ambient light from the sky, or white when off
+    sky_ambient:       SphericalHarmonics,
+    show_sky_ambient:  bool,
+    shadow_quality:    ShadowQuality,
+    instances:         Vec<Instance>,
+    instance_buffer:   wgpu::Buffer,
+    // Every instance, since shadows can fall from outside the view frustum
+    shadow_instances:  wgpu::Buffer,
+    // Every instance as it was a frame ago for motion vectors, and the matrices that take its
+    // place next frame. Moved instances are rewritten for one more frame so they settle.
+    motion_instances:  wgpu::Buffer,
+    instance_history:  Vec<InstanceRaw>,
+    instances_moved:   bool,
+    // Draws the grid with each cube's texture picked from `material_array` by instance, all in
+    // one call per mesh
+    batched:           bool,
+    batched_pipeline:  wgpu::RenderPipeline,
+    material_array:    MaterialArray,
+    layered_instances: wgpu::Buffer,
+    bvh:               Bvh,
+    // Indices of the instances inside the view frustum this frame
+    visible:           Vec<usize>,
+    // Coarser levels of `obj_model`, drawn in place of it when instances are small on screen.
+    // Not used by the batched or GPU culled paths.
+    lods:              LodChain,
+    lod_selector:      LodSelector,
+    debug_draw:        DebugDraw,
+    // Cycled with the numpad's *, showing one of the passes' inputs or outputs over the frame
+    debug_view:        DebugView,
+    debug_viewer:      DebugViewer,
+    // The road and the fly-through's track
+    wide_lines:        WideLines,
+    show_curves:       bool,
+    // 2D shapes drawn over the frame as a HUD
+    hud:               Canvas2D,
+    hud_star:          lyon::path::Path,
+    show_hud:          bool,
+    // Retained HUD, with a frame that's shown or hidden and a bar following the weather
+    status_ui:         Ui,
+    status_frame:      UiId,
+    status_meter:      UiId,
+    // The main camera as it was when its frustum or cluster grid was shown, so they can be looked
+    // at from outside
+    debug_camera:      Option<Camera>,
+    // Frustum and Hi-Z occlusion culling on the GPU, replacing the BVH query when enabled.
+    // Unavailable on the web since WebGL has no compute shaders.
+    gpu_culler:        Option<GpuCuller>,
+    gpu_culling:       bool,
+    editor:            editor::Editor,
+    // Draws the editor's inspector, and what's on the GPU while the editor's on
+    #[cfg(feature = "egui")]
+    egui:              EguiOverlay,
+    // A mirrored floor under the grid, with a probe above it for what the mirror can't see
+    mirror:            Mirror,
+    probe:             ReflectionProbe,
+    show_mirror:       bool,
+    ssr:               ScreenSpaceReflections,
+    show_ssr:          bool,
+    // Per-pixel motion since the last frame, shown over the screen when enabled
+    velocity:          VelocityBuffer,
+    show_velocity:     bool,
+    // Blurs along the velocity buffer, switching itself off when frames are slow
+    motion_blur:       MotionBlur,
+    show_motion_blur:  bool,
+    // Blurs what's out of focus, focusing on the selected instance or the middle of the screen
+    dof:               DepthOfField,
+    show_dof:          bool,
+    // Outlines the selection, and with `show_edges` every edge, with masks set per instance
+    edges:             EdgeDetection,
+    show_edges:        bool,
+    edge_masks:        wgpu::Buffer,
+    foliage:           Foliage,
+    show_foliage:      bool,
+    // Only remeshed while shown
+    voxels:            VoxelWorld,
+    show_voxels:       bool,
+    // Its quadtree is only walked while shown
+    planet:            Planet,
+    show_planet:       bool,
+    // Simulated only while shown. Unavailable on the web since it needs compute shaders.
+    ocean:             Option<Ocean>,
+    show_ocean:        bool,
+    // Clear until cycled to rain or snow
+    weather:           Weather,
+    portal:            Portal,
+    show_portal:       bool,
+    minimap:           Minimap,
+    show_minimap:      bool,
+    // Shown instead of the main view while it has players
+    split_screen:      SplitScreen,
+    probe_captured:    bool,
+    // Created the first time F10 saves a thumbnail or a fly-through is recorded
+    thumbnails:        Option<ThumbnailRenderer>,
+    // A fly-through over the grid, which takes the camera from the controller while it plays
+    cinematic:         CameraPathPlayer,
+    recording:         bool,
+    animator:          Animator,
+}
+
+impl Demo {
+    async fn new(ctx: &GpuContext) -> anyhow::Result<Self> {
+        let device = &ctx.device;
+        let queue  = &ctx.queue;
+        let config = &ctx.config;
+
+        let texture_bind_group_layout_builder = model::Material::layout_builder();
+
+        // Toon shading reads the camera position in the fragment shader
+        let camera_bind_group_layout_builder = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT);
+
+        // The layouts the scene's shaders share are built from what they declare, each binding
+        // visible to the stages any of them reads it in. Checking them against the builders the bind
+        // groups are filled in from catches layout mistakes here instead of in wgpu validation.
+        let mut material_sources = Vec::new();
+
+        for shading in ShadingModel::ALL {
+            for features in MaterialFeatures::all() {
+                material_sources.push(shading.variant_source(features)?);
+            }
+        }
+
+        material_sources.extend([Fur::shader_source(), Transmission::glass_shader_source()].map(String::from));
+
+        let mut scene_sources = material_sources.clone();
+        scene_sources.extend([
+            MaterialArray::shader_source(),
+            Foliage::shader_source(),
+            VoxelWorld::shader_source(),
+            Planet::shader_source(),
+            Weather::shader_source(),
+            Ocean::surface_shader_source(),
+        ].map(String::from));
+
+        let material_reflection = reflect::ShaderReflection::from_wgsl_shared(&material_sources, 0)
+            .context("Couldn't reflect the materials' shaders")?;
+        let camera_reflection   = reflect::ShaderReflection::from_wgsl_shared(&scene_sources, 1)
+            .context("Couldn't reflect the scene's shaders")?;
+        let lights_reflection   = reflect::ShaderReflection::from_wgsl_shared(&scene_sources, 2)
+            .context("Couldn't reflect the scene's shaders")?;
+
+        material_reflection.validate(0, texture_bind_group_layout_builder.entries())
+            .and_then(|_| camera_reflection.validate(1, camera_bind_group_layout_builder.entries()))
+            .and_then(|_| lights_reflection.validate(2, Lights::layout_builder().entries()))
+            .context("The scene's shaders don't match their layouts")?;
+
+        let texture_bind_group_layout = material_reflection.create_bind_group_layout(device, 0, "texture_bind_group_layout")?;
+
+
+        // Cameras
+
+        let camera = Camera {
+            eye:        (0.0, 1.0, 2.0).into(), // position the camera 1 unit up and 2 units back
+            target:     (0.0, 0.0, 0.0).into(), // have it look at the origin
+            up:         cgmath::Vector3::unit_y(),
+            aspect:     ctx.aspect(),
+            fovy:       45.0,
+            znear:      0.1,
+            zfar:       100.0,
+            depth_mode: ctx.depth_mode,
+        };
+
+        let mut camera_uniform = CameraUniform::new();
+        camera_uniform.update_view_proj(&camera);
+
+        let camera_buffer = UniformBuffer::with_contents(device, "Camera Buffer", &camera_uniform);
+
+        let camera_bind_group_layout = camera_reflection.create_bind_group_layout(device, 1, "camera_bind_group_layout")?;
+
+        let camera_bind_group = bind_group::BindGroupBuilder::checked(&camera_bind_group_layout, &camera_bind_group_layout_builder)
+            .uniform(camera_buffer.buffer())
+            .build(device, "camera_bind_group");
+
+        let camera_controller = CameraController::new(CAMERA_SPEED);
+
+        // Lights
+
+        let vertex_layouts = [model::PackedVertex::desc(), InstanceRaw::layout()];
+
+        let lights_layout = lights_reflection.create_bind_group_layout(device, 2, "lights_bind_group_layout")?;
+        let mut lights    = Lights::new(ctx, lights_layout, &vertex_layouts);
+
+        let sky_ambient = SkyGradient::from_clear_color(pass::CLEAR_COLOR).ambient().scaled(SKY_AMBIENT_INTENSITY);
+        lights.ambient  = sky_ambient;
+
+        let cookie = image::load_from_memory(&resources::load_binary("cube-diffuse.jpg").await?)?;
+        lights.set_cookie(queue, 0, &cookie);
+
+        // Shines the cube texture down onto the middle of the grid
+        lights.spots.push(SpotLight {
+            position:    (0.0, 8.0, 0.0).into(),
+            direction:   -cgmath::Vector3::unit_y(),
+            color:       [1.0, 0.9, 0.7],
+            intensity:   60.0,
+            range:       20.0,
+            inner_angle: cgmath::Deg(15.0),
+            outer_angle: cgmath::Deg(25.0),
+            cookie:      Some(0),
+            shadows:     false,
+        });
+
+        // Low across the grid so the cubes shadow their neighbours
+        lights.spots.push(SpotLight {
+            position:    (-18.0, 4.0, 0.0).into(),
+            direction:   cgmath::Vector3::new(1.0, -0.3, 0.0),
+            color:       [0.6, 0.7, 1.0],
+            intensity:   120.0,
+            range:       30.0,
+            inner_angle: cgmath::Deg(25.0),
+            outer_angle: cgmath::Deg(35.0),
+            cookie:      None,
+            shadows:     true,
+        });
+
+        // A warm panel beyond the far row, like a window, facing back across the grid
+        lights.rects.push(RectLight {
+            position:  (0.0, 4.0, -19.0).into(),
+            right:     cgmath::Vector3::new(4.0, 0.0, 0.0),
+            up:        cgmath::Vector3::new(0.0, 2.0, 0.0),
+            color:     [1.0, 0.85, 0.6],
+            intensity: 6.0,
+            two_sided: false,
+        });
+
+        // Instances
+        let obj_model = resources::load_model(
+            "cube.obj",
+            device,
+            queue,
+            &texture_bind_group_layout,
+        ).await?;
+
+        // Levels are simplified from the model, so without meshopt it's always drawn in full
+        let lods = LodChain {
+            #[cfg(feature = "meshopt")]
+            levels: resources::load_lod_levels("cube.obj", device, &LOD_LEVELS).await?,
+            ..LodChain::new()
+        };
+
+        const SPACE_BETWEEN: f32 = 3.0;
+
+        let instances = (0..NUM_INSTANCES_PER_ROW).flat_map(|z| {
+            (0..NUM_INSTANCES_PER_ROW).map(move |x| {
+                let x = SPACE_BETWEEN * (x as f32 - NUM_INSTANCES_PER_ROW as f32 / 2.0);
+                let z = SPACE_BETWEEN * (z as f32 - NUM_INSTANCES_PER_ROW as f32 / 2.0);
+
+                let position = cgmath::Vector3 { x, y: 0.0, z };
+
+                let rotation = if position.is_zero() {
+                    // this is needed so an onject at (0, 0, 0) won't get scaled to 0
+                    cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_z(), cgmath::Deg(0.0))
+                } else {
+                    cgmath::Quaternion::from_axis_angle(position.normalize(), cgmath::Deg(45.0))
+                };
+
+                Instance {
+                    position, rotation, scale: cgmath::Vector3::new(1.0, 1.0, 1.0),
+                }
+            })
+        }).collect::<Vec<_>>();
+
+        // Written every frame. Has room for every instance twice, since one dithering between
+        // levels of detail is drawn at both.
+        let instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label:              Some("Instance Buffer"),
+            size:               (2 * instances.len() * std::mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress,
+            usage:              wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let instance_data = instances.iter().map(Instance::to_raw).collect::<Vec<_>>();
+
+        let shadow_instances = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label:    Some("Shadow Instance Buffer"),
+                contents: bytemuck::cast_slice(&instance_data),
+                usage:    wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        let motion_instances = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label:    Some("Motion Instance Buffer"),
+                contents: bytemuck::cast_slice(&instance_data),
+                usage:    wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        let edge_masks = device.create_buffer(&wgpu::BufferDescriptor {
+            label:              Some("Edge Mask Buffer"),
+            size:               (instances.len() * std::mem::size_of::<EdgeMaskRaw>()) as wgpu::BufferAddress,
+            usage:              wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let layered_instances = device.create_buffer(&wgpu::BufferDescriptor {
+            label:              Some("Layered Instance Buffer"),
+            size:               (instances.len() * std::mem::size_of::<LayeredInstanceRaw>()) as wgpu::BufferAddress,
+            usage:              wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        // Variations on the cube texture, one per layer
+        let diffuse = image::load_from_memory(&resources::load_binary("cube-diffuse.jpg").await?)?;
+
+        let mut inverted = diffuse.clone();
+        inverted.invert();
+
+        let material_array_reflection = reflect::ShaderReflection::from_wgsl(MaterialArray::shader_source())
+            .context("Couldn't reflect the material array shader")?;
+
+        material_array_reflection.validate(0, MaterialArray::layout_builder().entries())
+            .context("The material array shader doesn't match its layout")?;
+
+        let material_array_layout = material_array_reflection.create_bind_group_layout(device, 0, "material_array_bind_group_layout")?;
+
+        let material_array = MaterialArray::new(
+            device,
+            queue,
+            &material_array_layout,
+            &[
+                ("grayscale", diffuse.grayscale()),
+                ("diffuse",   diffuse),
+                ("inverted",  inverted),
+            ],
+            "material_array",
+        )?;
+
+        // Rendering
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Shader"),
+            source: wgpu::ShaderSource::Wgsl(ShadingModel::Textured.variant_source(MaterialFeatures::default())?.into()),
+        });
+
+        let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Render Pipeline Layout"),
+            bind_group_layouts:   &[
+                &texture_bind_group_layout,
+                &camera_bind_group_layout,
+                &lights.layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let pipelines = ShadingPipelines::new(
+            device,
+            &[&texture_bind_group_layout, &camera_bind_group_layout, &lights.layout],
+            config.format,
+            renderer::depth_state(texture::Texture::DEPTH_FORMAT, ctx.depth_mode.compare(), true),
+            ctx.depth_mode,
+            &vertex_layouts,
+            1,
+            "Render Pipeline",
+        );
+
+        let transmission = Transmission::new(ctx, &texture_bind_group_layout, &camera_bind_group_layout, &lights.layout, &vertex_layouts);
+
+        let voxels = voxel_terrain(ctx, &camera_bind_group_layout, &lights.layout)?;
+        let (mut status_ui, status_frame, status_meter) = status_ui(ctx);
+        // The demo has no font, so the inspector only shows its sliders, and logs values as they change
+        let editor = editor::Editor::new(&mut status_ui, None, 12.0);
+
+        let planet = Planet::new(ctx, &camera_bind_group_layout, &lights.layout, PlanetSettings {
+            center: PLANET_CENTER.into(),
+            radius: PLANET_RADIUS,
+            height: PLANET_HEIGHT,
+            ..Default::default()
+        });
+
+        let weather = Weather::new(ctx, &camera_bind_group_layout, &lights.layout);
+
+        let ocean = if cfg!(target_arch = "wasm32") {
+            None
+        } else {
+            Some(Ocean::new(ctx, &camera_bind_group_layout, &lights.layout, OceanSettings {
+                origin: OCEAN_ORIGIN.into(),
+                extent: OCEAN_EXTENT,
+                ..Default::default()
+            }))
+        };
+
+        let fur = Fur::new(ctx, &texture_bind_group_layout, &camera_bind_group_layout, &lights.layout, &vertex_layouts);
+
+        let contact_shadows = ContactShadows::new(ctx);
+        lights.set_scene_depth(device, contact_shadows.view());
+
+        // The scene is drawn without multisampling, so foliage is alpha tested rather than using coverage
+        let foliage = Foliage::new(
+            ctx,
+            &camera_bind_group_layout,
+            &lights.layout,
+            1,
+            FoliageKind::Blades,
+            FoliageArea {
+                center:    cgmath::Vector2::new(-SPACE_BETWEEN / 2.0, -SPACE_BETWEEN / 2.0),
+                half_size: FOLIAGE_HALF_SIZE,
+                density:   FOLIAGE_DENSITY,
+            },
+            |_, _| FOLIAGE_GROUND,
+        );
+
+        let equal_pipelines = ShadingPipelines::new(
+            device,
+            &[&texture_bind_group_layout, &camera_bind_group_layout, &lights.layout],
+            config.format,
+            renderer::depth_state(texture::Texture::DEPTH_FORMAT, wgpu::CompareFunction::Equal, false),
+            ctx.depth_mode,
+            &vertex_layouts,
+            1,
+            "Depth Equal Render Pipeline",
+        );
+
+        let prepass_pipelines = CullMode::ALL.iter()
+            .map(|cull| renderer::create_depth_prepass_pipeline(
+                device,
+                &render_pipeline_layout,
+                renderer::depth_state(texture::Texture::DEPTH_FORMAT, ctx.depth_mode.compare(), true),
+                &vertex_layouts,
+                &shader,
+                renderer::RasterState { cull_mode: cull.face(), ..Default::default() },
+            ))
+            .collect();
+
+        let batched_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Material Array Shader"),
+            source: wgpu::ShaderSource::Wgsl(MaterialArray::shader_source().into()),
+        });
+
+        let batched_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Batched Pipeline Layout"),
+            bind_group_layouts:   &[
+                &material_array_layout,
+                &camera_bind_group_layout,
+                &lights.layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        // Passes equal depths so the same pipeline works with and without the depth prepass
+        let batched_pipeline = renderer::create_render_pipeline(
+            device,
+            &batched_pipeline_layout,
+            config.format,
+            Some(renderer::depth_state(texture::Texture::DEPTH_FORMAT, ctx.depth_mode.compare_or_equal(), true)),
+            &[model::PackedVertex::desc(), LayeredInstanceRaw::layout()],
+            &batched_shader,
+            "Batched Pipeline",
+        );
+
+        let bvh = build_bvh(&obj_model, &instances);
+
+        let animator = showcase_animations(&instances);
+
+        let gpu_culler = if cfg!(target_arch = "wasm32") {
+            None
+        } else {
+            let mut culler = GpuCuller::new(ctx, &obj_model, instances.len());
+            culler.set_instances(queue, &cull_instances(&obj_model, &instances));
+            Some(culler)
+        };
+
+        let auto_exposure = if cfg!(target_arch = "wasm32") {
+            None
+        } else {
+            Some(AutoExposure::new(ctx))
+        };
+
+        let bloom = if cfg!(target_arch = "wasm32") {
+            None
+        } else {
+            Some(Bloom::new(ctx))
+        };
+
+        let lens_flare = if cfg!(target_arch = "wasm32") {
+            None
+        } else {
+            let mut flare = LensFlare::new(ctx);
+
+            flare.glares.push(Glare {
+                source:    GlareSource::Directional(SUN_DIRECTION.into()),
+                color:     [1.0, 0.9, 0.7],
+                intensity: 1.5,
+                size:      0.15,
+            });
+
+            Some(flare)
+        };
+
+        let light_clusters = if cfg!(target_arch = "wasm32") {
+            None
+        } else {
+            Some(LightClusters::new(ctx, CLUSTER_CELLS))
+        };
+
+        let analysis = if cfg!(target_arch = "wasm32") {
+            None
+        } else {
+            Some(SceneAnalysis::new(ctx))
+        };
+
+        let probe  = ReflectionProbe::new(ctx, cgmath::Point3::new(0.0, 2.0, 0.0), PROBE_SIZE);
+        let mirror = Mirror::new(
+            ctx,
+            cgmath::Point3::new(0.0, MIRROR_HEIGHT, 0.0),
+            cgmath::Vector3::unit_y(),
+            MIRROR_HALF_SIZE,
+            &probe,
+        );
+
+        Ok(Self {
+            pipelines,
+            equal_pipelines,
+            prepass_pipelines,
+            outline: OutlinePass::new(ctx, OUTLINE_COLOR, OUTLINE_THRESHOLD),
+            depth_prepass: true,
+            clipped_view: Cell::new(false),
+            color_grading: ColorGrading::new(ctx),
+            auto_exposure,
+            auto_exposing: false,
+            show_bloom: bloom.is_some(),
+            bloom,
+            show_lens_flare: lens_flare.is_some(),
+            lens_flare,
+            transmission,
+            contact_shadows,
+            fur,
+            light_clusters,
+            light_heatmap: false,
+            logged_stats: None,
+            analysis,
+            analyzing: false,
+            logged_analysis: None,
+            retro: RetroFilter::new(ctx),
+            retro_mode: 0,
+            texture_layout: texture_bind_group_layout,
+            detailed: false,
+            obj_model,
+            dropped: Vec::new(),
+            base_fovy: camera.fovy,
+            view: camera.clone(),
+            camera,
+            camera_controller,
+            follow: CameraFollow::new(FOLLOW_TIME),
+            following: false,
+            zoom: FovTransition::new(Easing::EaseInOut),
+            shake: CameraShake::new(),
+            camera_buffer,
+            camera_bind_group,
+            camera_uniform,
+            lights,
+            sky_ambient,
+            show_sky_ambient: true,
+            shadow_quality: ShadowQuality::Medium,
+            instances,
+            instance_buffer,
+            shadow_instances,
+            motion_instances,
+            instance_history: instance_data,
+            instances_moved: false,
+            batched: false,
+            batched_pipeline,
+            material_array,
+            layered_instances,
+            bvh,
+            visible: Vec::new(),
+            lods,
+            lod_selector: LodSelector::new(),
+            debug_draw: DebugDraw::new(ctx),
+            debug_view: DebugView::Off,
+            debug_viewer: DebugViewer::new(ctx),
+            wide_lines: WideLines::new(ctx),
+            show_curves: false,
+            hud: Canvas2D::new(ctx, CanvasSpace::Screen),
+            hud_star: canvas::svg_path(HUD_STAR)?,
+            show_hud: false,
+            status_ui,
+            status_frame,
+            status_meter,
+            debug_camera: None,
+            gpu_culling: gpu_culler.is_some(),
+            gpu_culler,
+            editor,
+            #[cfg(feature = "egui")]
+            egui: EguiOverlay::new(ctx),
+            thumbnails: None,
+            mirror,
+            probe,
+            show_mirror: false,
+            ssr: ScreenSpaceReflections::new(ctx),
+            show_ssr: false,
+            velocity: VelocityBuffer::new(ctx, model::PackedVertex::desc()),
+            show_velocity: false,
+            motion_blur: MotionBlur::new(ctx),
+            show_motion_blur: false,
+            dof: DepthOfField::new(ctx),
+            show_dof: false,
+            edges: EdgeDetection::new(ctx, model::PackedVertex::desc()),
+            show_edges: false,
+            edge_masks,
+            foliage,
+            show_foliage: false,
+            voxels,
+            show_voxels: false,
+            planet,
+            show_planet: false,
+            ocean,
+            show_ocean: false,
+            weather,
+            portal: Portal::new(
+                ctx,
+                PortalFrame { center: PORTAL_ENTRANCE.into(), normal: cgmath::Vector3::unit_z(), up: cgmath::Vector3::unit_y() },
+                PortalFrame { center: PORTAL_EXIT.into(), normal: -cgmath::Vector3::unit_x(), up: cgmath::Vector3::unit_y() },
+                PORTAL_HALF_SIZE,
+            ),
+            show_portal: false,
+            minimap: Minimap::new(ctx, MINIMAP_SIZE, MINIMAP_SIZE),
+            show_minimap: false,
+            split_screen: SplitScreen::new(ctx),
+            probe_captured: false,
+            cinematic: CameraPathPlayer::new(fly_through()),
+            recording: false,
+            animator,
+        })
+    }
+}
+
+// Sweeps in low over the grid, circles it and pulls back up
+fn fly_through() -> CameraPath {
+    let mut path = CameraPath::new();
+
+    let keys = [
+        (0.0,  (0.0, 12.0, 30.0),   (0.0, 0.0, 0.0),   Easing::EaseIn),
+        (4.0,  (-12.0, 3.0, 14.0),  (0.0, 0.0, 0.0),   Easing::Linear),
+        (8.0,  (-16.0, 2.0, -8.0),  (0.0, 0.0, -3.0),  Easing::Linear),
+        (12.0, (6.0, 4.0, -18.0),   (0.0, -1.0, 0.0),  Easing::Linear),
+        (16.0, (16.0, 6.0, 4.0),    (0.0, 0.0, 0.0),   Easing::EaseInOut),
+        (20.0, (0.0, 20.0, 24.0),   (0.0, 0.0, 0.0),   Easing::Linear),
+    ];
+
+    for (time, eye, target, easing) in keys {
+        path.add_key(CameraKey { time, eye: eye.into(), target: target.into(), easing });
+    }
+
+    path
+}
+
+// Pulses the cube material and blinks it bright enough to bloom, flickers the cookie light and sets
+// the first cube bobbing and spinning
+fn showcase_animations(instances: &[Instance]) -> Animator {
+    let mut animator = Animator::new();
+
+    animator.add(Track::MaterialTint {
+        material: 0,
+        curve:    Curve::tween([1.0; 4], [1.0, 0.6, 0.3, 1.0], 1.5, Easing::EaseInOut, Repeat::PingPong),
+    });
+
+    let mut blink = Curve::new(Repeat::Loop);
+
+    for (time, intensity) in [(0.0, 0.0), (2.0, 0.0), (2.1, 2.0), (2.6, 2.0), (2.7, 0.0)] {
+        blink.add_key(Keyframe { time, value: intensity, easing: Easing::Linear });
+    }
+
+    animator.add(Track::MaterialEmissive { material: 0, curve: blink });
+
+    let mut flicker = Curve::new(Repeat::Loop);
+
+    for (time, intensity) in [(0.0, 60.0), (0.1, 10.0), (0.2, 60.0), (0.35, 20.0), (0.5, 60.0), (2.0, 60.0)] {
+        flicker.add_key(Keyframe { time, value: intensity, easing: Easing::Linear });
+    }
+
+    animator.add(Track::LightIntensity { light: 0, curve: flicker });
+
+    if let Some(first) = instances.first() {
+        let spin = |degrees: f32| first.rotation * cgmath::Quaternion::from_angle_y(cgmath::Deg(degrees));
+        let mut rotation = Curve::new(Repeat::Loop);
+
+        // Slerp takes the short way round, so a full turn needs keys less than half a turn apart
+        for (i, degrees) in [0.0, 120.0, 240.0, 360.0].into_iter().enumerate() {
+            rotation.add_key(Keyframe { time: i as f32, value: spin(degrees), easing: Easing::Linear });
+        }
+
+        animator
+            .add(Track::Position {
+                instance: 0,
+                curve:    Curve::tween(first.position, first.position + cgmath::Vector3::unit_y() * 1.5, 1.0, Easing::EaseInOut, Repeat::PingPong),
+            })
+            .add(Track::Rotation { instance: 0, curve: rotation });
+    }
+
+    animator
+}
+
+// The model's bounds under every instance's transform, used for culling and picking
+fn build_bvh(model: &model::Model, instances: &[Instance]) -> Bvh {
+    let bounds = model.bounds();
+    let boxes  = instances.iter()
+        .map(|instance| Obb::new(bounds, instance.to_matrix()))
+        .collect::<Vec<_>>();
+
+    Bvh::build_oriented(&boxes)
+}
+
+fn cull_instances(model: &model::Model, instances: &[Instance]) -> Vec<CullInstance> {
+    let bounds = model.bounds();
+
+    instances.iter().map(|instance| CullInstance::new(instance, &bounds)).collect()
+}
+
+// Grain for the cubes' detail layer: tiling value noise around mid grey, and normals following
+// its slopes
+fn detail_textures(ctx: &GpuContext) -> (texture::Texture, texture::Texture) {
+    let size = DETAIL_TEXTURE_SIZE as i32;
+    let cell = size / DETAIL_CELLS as i32;
+
+    // Hashed lattice values, wrapped so the texture tiles
+    let lattice = |x: i32, y: i32| {
+        let (x, y) = (x.rem_euclid(DETAIL_CELLS as i32) as u32, y.rem_euclid(DETAIL_CELLS as i32) as u32);
+        let hash   = (x.wrapping_mul(73_856_093) ^ y.wrapping_mul(19_349_663)).wrapping_mul(2_654_435_761);
+
+        (hash >> 8) as f32 / (1 << 24) as f32
+    };
+
+    let height = |x: i32, y: i32| {
+        let (x, y)   = (x.rem_euclid(size), y.rem_euclid(size));
+        let (cx, cy) = (x / cell, y / cell);
+        let smooth   = |t: f32| t * t * (3.0 - 2.0 * t);
+        let fx       = smooth((x % cell) as f32 / cell as f32);
+        let fy       = smooth((y % cell) as f32 / cell as f32);
+
+        let top    = lattice(cx, cy) * (1.0 - fx) + lattice(cx + 1, cy) * fx;
+        let bottom = lattice(cx, cy + 1) * (1.0 - fx) + lattice(cx + 1, cy + 1) * fx;
+
+        top * (1.0 - fy) + bottom * fy
+    };
+
+    let albedo = image::RgbaImage::from_fn(DETAIL_TEXTURE_SIZE, DETAIL_TEXTURE_SIZE, |x, y| {
+        let grey = ((0.5 + (height(x as i32, y as i32) - 0.5) * 0.4) * 255.0) as u8;
+
+        image::Rgba([grey, grey, grey, 255])
+    });
+
+    let normals = image::RgbaImage::from_fn(DETAIL_TEXTURE_SIZE, DETAIL_TEXTURE_SIZE, |x, y| {
+        let (x, y) = (x as i32, y as i32);
+        let dx     = (height(x + 1, y) - height(x - 1, y)) * DETAIL_BUMPINESS;
+        let dy     = (height(x, y + 1) - height(x, y - 1)) * DETAIL_BUMPINESS;
+        let normal = cgmath::Vector3::new(-dx, -dy, 1.0).normalize();
+        let encode = |c: f32| ((c * 0.5 + 0.5) * 255.0) as u8;
+
+        image::Rgba([encode(normal.x), encode(normal.y), encode(normal.z), 255])
+    });
+
+    let load = |pixels: image::RgbaImage, label: &str| {
+        texture::Texture::from_linear_image(&ctx.device, &ctx.queue, &image::DynamicImage::ImageRgba8(pixels), Some(label)).unwrap()
+    };
+
+    (load(albedo, "Detail Texture"), load(normals, "Detail Normal Texture"))
+}
+
+// Rolling grass over dirt over stone, with textures made of noise
+fn voxel_terrain(ctx: &GpuContext, camera_layout: &wgpu::BindGroupLayout, lights_layout: &wgpu::BindGroupLayout) -> anyhow::Result<VoxelWorld> {
+    let noise = |color: [u8; 3], salt: u32, top_band: Option<[u8; 3]>| {
+        image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(VOXEL_TEXTURE_SIZE, VOXEL_TEXTURE_SIZE, |x, y| {
+            let hash  = (x.wrapping_mul(73_856_093) ^ y.wrapping_mul(19_349_663) ^ salt.wrapping_mul(83_492_791)).wrapping_mul(2_654_435_761);
+            let shade = 0.8 + 0.4 * ((hash >> 8) as f32 / (1 << 24) as f32);
+
+            // Grass hangs a ragged few pixels over the top of the side texture
+            let color = match top_band {
+                Some(band) if y < VOXEL_TEXTURE_SIZE / 4 + hash % 3 => band,
+                _ => color,
+            };
+
+            let [r, g, b] = color.map(|c| (c as f32 * shade).min(255.0) as u8);
+
+            image::Rgba([r, g, b, 255])
+        }))
+    };
+
+    let grass = [80, 140, 50];
+    let dirt  = [120, 85, 55];
+    let stone = [125, 125, 130];
+
+    let mut voxels = VoxelWorld::new(
+        ctx,
+        camera_layout,
+        lights_layout,
+        &[
+            ("grass", noise(grass, 0, None)),
+            ("grass_side", noise(dirt, 1, Some(grass))),
+            ("dirt", noise(dirt, 2, None)),
+            ("stone", noise(stone, 3, None)),
+        ],
+        vec![
+            BlockFaces { top: 0, side: 1, bottom: 2 },
+            BlockFaces::all(2),
+            BlockFaces::all(3),
+        ],
+    )?;
+
+    voxels.origin = VOXEL_ORIGIN.into();
+
+    for z in 0..VOXEL_EXTENT[1] {
+        for x in 0..VOXEL_EXTENT[0] {
+            let height = 5.0 + 2.0 * (x as f32 * 0.21).sin() + 2.0 * (z as f32 * 0.17).cos();
+            let height = height.round() as i32;
+
+            for y in 0..height {
+                let block = match height - y {
+                    1     => VOXEL_GRASS,
+                    2 | 3 => VOXEL_DIRT,
+                    _     => VOXEL_STONE,
+                };
+
+                voxels.set_block(cgmath::Point3::new(x, y, z), block);
+            }
+        }
+    }
+
+    Ok(voxels)
+}
+
+// A framed weather meter in the bottom left corner, laid out for 720p and scaled with the window.
+// Returns the frame, which hides the rest along with it, and the meter's bar.
+fn status_ui(ctx: &GpuContext) -> (Ui, UiId, UiId) {
+    let mut ui = Ui::new(ctx, UiScale::Height(720.0));
+
+    // A light frame three texels wide with its corners cut, around a dark translucent middle
+    let frame = image::RgbaImage::from_fn(16, 16, |x, y| {
+        let edge = x.min(15 - x).min(y).min(15 - y);
+        let cut  = (x == 0 || x == 15) && (y == 0 || y == 15);
+
+        match edge {
+            _ if cut => image::Rgba([0, 0, 0, 0]),
+            0..=2    => image::Rgba([220, 220, 230, 255]),
+            _        => image::Rgba([10, 10, 16, 180]),
+        }
+    });
+    let frame = ui.add_image(ctx, &image::DynamicImage::ImageRgba8(frame), "status_frame_texture").unwrap();
+
+    let root = ui.add(UiNode::new(Anchor::BottomLeft, [16.0, -16.0], [240.0, 48.0], UiKind::NineSlice {
+        image:  frame,
+        border: [4.0; 4],
+        tint:   [1.0; 4],
+    }));
+    ui.add(UiNode::new(Anchor::Left, [12.0, 0.0], [216.0, 16.0], UiKind::Panel { color: [0.1, 0.1, 0.15, 1.0] }).with_parent(root));
+    let meter = ui.add(UiNode::new(Anchor::Left, [12.0, 0.0], [0.0, 16.0], UiKind::Panel { color: [0.2, 0.5, 1.0, 1.0] }).with_parent(root));
+
+    ui.node_mut(root).unwrap().visible = false;
+
+    (ui, root, meter)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Demo {
+    // Models are added as-is, images and HDRs are shown on a cube and .cube LUTs grade the image
+    // A square render of the scene from the current camera
+    fn save_thumbnail(&mut self, ctx: &GpuContext) -> anyhow::Result<()> {
+        let camera = Camera {
+            eye:        self.camera.eye,
+            target:     self.camera.target,
+            up:         self.camera.up,
+            aspect:     1.0,
+            fovy:       self.camera.fovy,
+            znear:      self.camera.znear,
+            zfar:       self.camera.zfar,
+            depth_mode: self.camera.depth_mode,
+        };
+
+        let mut thumbnails = self.thumbnails.take()
+            .unwrap_or_else(|| ThumbnailRenderer::new(ctx, THUMBNAIL_SIZE, THUMBNAIL_SIZE));
+
+        let image = thumbnails.render_to_image(ctx, &*self, &camera, (THUMBNAIL_SIZE, THUMBNAIL_SIZE));
+
+        self.thumbnails = Some(thumbnails);
+        image?.save(THUMBNAIL_FILE)?;
+
+        Ok(())
+    }
+
+    // Bakes a grid of light probes over the cubes from the scene as it's drawn now, which only has
+    // the instances visible from the current camera
+    fn bake_light_probes(&mut self, ctx: &GpuContext) -> anyhow::Result<usize> {
+        let raise  = cgmath::Vector3::unit_y() * LIGHT_PROBE_HEIGHT;
+        let bounds = Aabb::from_points(self.instances.iter().map(|instance| cgmath::Point3::from_vec(instance.position + raise)));
+
+        let mut grid = ProbeGrid::covering(&bounds, LIGHT_PROBE_SPACING);
+
+        grid.bake(ctx, &*self, LIGHT_PROBE_FACE_SIZE, LIGHT_PROBE_RANGE)?;
+
+        let count          = grid.probes.len();
+        self.lights.probes = Some(grid);
+
+        Ok(count)
+    }
+
+    // Saves the view the fly-through is at as the next frame of the recording
+    fn record_frame(&mut self, ctx: &GpuContext) -> anyhow::Result<()> {
+        let size = (ctx.size.width, ctx.size.height);
+
+        let mut thumbnails = self.thumbnails.take()
+            .unwrap_or_else(|| ThumbnailRenderer::new(ctx, size.0, size.1));
+
+        let image = thumbnails.render_to_image(ctx, &*self, &self.view, size);
+
+        self.thumbnails = Some(thumbnails);
+
+        std::fs::create_dir_all(RECORDING_DIR)?;
+        image?.save(format!("{}/frame_{:05}.png", RECORDING_DIR, self.cinematic.frame()))?;
+
+        Ok(())
+    }
+
+    fn start_recording(&mut self) {
+        self.cinematic.looping = false;
+        self.cinematic.set_deterministic(Some(RECORDING_FPS));
+        self.cinematic.seek(0.0);
+        self.cinematic.play();
+        self.recording = true;
+    }
+
+    fn stop_recording(&mut self) {
+        self.cinematic.set_deterministic(None);
+        self.recording = false;
+        log::info!("Recorded {} frames into {}/", self.cinematic.frame(), RECORDING_DIR);
+    }
+
+    async fn load_dropped_file(&mut self, ctx: &GpuContext, path: &std::path::Path) -> anyhow::Result<()> {
+        let device    = &ctx.device;
+        let queue     = &ctx.queue;
+        let file_name = path.to_str().ok_or_else(|| anyhow::anyhow!("Path isn't valid UTF-8"))?;
+        let extension = path.extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase())
+            .unwrap_or_default();
+
+        if extension == "cube" {
+            let lut = resources::load_lut(file_name).await?;
+
+            self.color_grading.set_lut(ctx, &lut);
+            log::info!("Grading with {}", path.display());
+            return Ok(());
+        }
+
+        let model = match extension.as_str() {
+            "obj" => resources::load_model(file_name, device, queue, &self.texture_layout).await?,
+            "png" | "jpg" | "jpeg" | "hdr" => {
+                let diffuse_texture = if extension == "hdr" {
+                    resources::load_hdr_texture(file_name, device, queue).await?
+                } else {
+                    resources::load_texture(file_name, device, queue).await?
+                };
+
+                let mut model = resources::load_model("cube.obj", device, queue, &self.texture_layout).await?;
+
+                model.materials = vec![model::Material::new(
+                    device,
+                    &self.texture_layout,
+                    file_name.to_string(),
+                    diffuse_texture,
+                    None,
+                    ShadingModel::Textured,
+                )];
+
+                for mesh in &mut model.meshes {
+                    mesh.material = 0;
+                }
+
+                model
+            }
+            _ => anyhow::bail!("Don't know how to load `.{}` files", extension),
+        };
+
+        let instance = Instance {
+            position: self.camera.target.to_vec(),
+            rotation: cgmath::Quaternion::one(),
+            scale:    cgmath::Vector3::new(1.0, 1.0, 1.0),
+        };
+
+        let instance_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label:    Some("Dropped Instance Buffer"),
+                contents: bytemuck::cast_slice(&[instance.to_raw()]),
+                usage:    wgpu::BufferUsages::VERTEX,
+            }
+        );
+
+        log::info!("Added {} to the scene", path.display());
+        self.dropped.push(DroppedAsset { model, instance_buffer });
+
+        Ok(())
+    }
+}
+
+impl App for Demo {
+    fn renderer_options() -> RendererOptions {
+        RendererOptions {
+            dynamic_resolution: Some(DynamicResolution::new(TARGET_FRAME_TIME)),
+            vr:                 true,
+            ..RendererOptions::default()
+        }
+    }
+
+    fn plugins(_plugins: &mut Plugins) {
+        #[cfg(feature = "renderdoc")]
+        _plugins.add(crate::capture::RenderDocCapture::new());
+
+        #[cfg(feature = "ndi")]
+        match crate::frame_share::NdiSender::new("learn_wgpu") {
+            Ok(sender) => { _plugins.add(crate::frame_share::FrameShare::new(sender)); }
+            Err(e)     => log::warn!("Not sharing frames over NDI: {}", e),
+        }
+    }
+
+    fn setup(ctx: &mut GpuContext) -> SetupFuture<'_, Self> {
+        Box::pin(async move { Self::new(ctx).await.expect("Couldn't set up the demo") })
+    }
+
+    fn xr_scene(&mut self) -> Option<&mut dyn crate::stereo::XrScene> {
+        Some(self)
+    }
+
+    fn input(&mut self, ctx: &GpuContext, event: &WindowEvent) -> bool {
+        // Clicks and typing meant for a window shouldn't reach the scene or the key bindings
+        #[cfg(feature = "egui")]
+        {
+            if self.egui.input(event) {
+                return true;
+            }
+        }
+
+        if let WindowEvent::KeyboardInput {
+            input: KeyboardInput {
+                state: ElementState::Pressed,
+                virtual_keycode: Some(keycode),
+                ..
+            },
+            ..
+        } = event {
+            match keycode {
+                VirtualKeyCode::F2 => {
+                    self.depth_prepass = !self.depth_prepass;
+                    log::info!("Depth prepass {}", if self.depth_prepass { "enabled" } else { "disabled" });
+                    return true;
+                }
+                VirtualKeyCode::F3 => {
+                    self.toggle_debug(DebugCategory::Bounds);
+                    return true;
+                }
+                VirtualKeyCode::Key1 => {
+                    self.toggle_debug(DebugCategory::Frusta);
+                    return true;
+                }
+                VirtualKeyCode::Key2 => {
+                    self.toggle_debug(DebugCategory::Shadows);
+                    return true;
+                }
+                VirtualKeyCode::Key3 => {
+                    self.toggle_debug(DebugCategory::Lights);
+                    return true;
+                }
+                VirtualKeyCode::Key4 => {
+                    self.toggle_debug(DebugCategory::Clusters);
+                    return true;
+                }
+                VirtualKeyCode::Key5 => {
+                    self.show_velocity = !self.show_velocity;
+                    self.velocity.reset();
+                    log::info!("Velocity buffer {}", if self.show_velocity { "shown" } else { "hidden" });
+                    return true;
+                }
+                VirtualKeyCode::Key6 => {
+                    self.show_motion_blur = !self.show_motion_blur;
+                    self.velocity.reset();
+                    log::info!("Motion blur {}", if self.show_motion_blur { "enabled" } else { "disabled" });
+                    return true;
+                }
+                VirtualKeyCode::Key7 => {
+                    let next = SHUTTERS.iter().position(|&shutter| shutter > self.motion_blur.shutter).unwrap_or(0);
+
+                    self.motion_blur.shutter = SHUTTERS[next];
+                    log::info!("Motion blur shutter {}", self.motion_blur.shutter);
+                    return true;
+                }
+                VirtualKeyCode::Key8 => {
+                    self.show_dof = !self.show_dof;
+                    log::info!("Depth of field {}", if self.show_dof { "enabled" } else { "disabled" });
+                    return true;
+                }
+                VirtualKeyCode::Key9 if self.show_dof => {
+                    self.dof.autofocus = !self.dof.autofocus;
+                    log::info!("Autofocus {}, focused at {:.1}", if self.dof.autofocus { "on" } else { "off" }, self.dof.focus_distance);
+                    return true;
+                }
+                VirtualKeyCode::Key0 => {
+                    self.show_edges = !self.show_edges;
+                    log::info!("Edge detection {}", if self.show_edges { "enabled" } else { "disabled" });
+                    return true;
+                }
+                VirtualKeyCode::F5 => {
+                    for material in &mut self.obj_model.materials {
+                        material.shading = match material.shading {
+                            ShadingModel::Textured   => ShadingModel::Toon,
+                            ShadingModel::Toon       => ShadingModel::Subsurface,
+                            ShadingModel::Subsurface => ShadingModel::Textured,
+                        };
+                    }
+                    log::info!("Switched shading models");
+                    return true;
+                }
+                VirtualKeyCode::Insert => {
+                    let glass = !self.obj_model.materials.iter().any(model::Material::is_transmissive);
+
+                    for material in &mut self.obj_model.materials {
+                        material.params.transmittance = if glass { GLASS_TRANSMITTANCE } else { 0.0 };
+                    }
+                    log::info!("Glass {}", if glass { "enabled" } else { "disabled" });
+                    return true;
+                }
+                VirtualKeyCode::PageDown => {
+                    let fur = !self.obj_model.materials.iter().any(model::Material::has_fur);
+
+                    for material in &mut self.obj_model.materials {
+                        material.params.fur_length = if fur { FUR_LENGTH } else { 0.0 };
+                    }
+                    log::info!("Fur {}", if fur { "enabled" } else { "disabled" });
+                    return true;
+                }
+                VirtualKeyCode::Home => {
+                    let triplanar = !self.obj_model.materials.iter().any(|material| material.params.triplanar_scale > 0.0);
+
+                    for material in &mut self.obj_model.materials {
+                        material.params.triplanar_scale = if triplanar { TRIPLANAR_SCALE } else { 0.0 };
+                    }
+                    log::info!("Triplanar mapping {}", if triplanar { "enabled" } else { "disabled" });
+                    return true;
+                }
+                VirtualKeyCode::End => {
+                    self.detailed = !self.detailed;
+
+                    for material in &mut self.obj_model.materials {
+                        let (albedo, normals) = if self.detailed {
+                            let (albedo, normals) = detail_textures(ctx);
+
+                            (Some(albedo), Some(normals))
+                        } else {
+                            (None, None)
+                        };
+
+                        material.set_detail_textures(&ctx.device, &self.texture_layout, albedo, normals);
+                    }
+                    log::info!("Detail textures {}", if self.detailed { "enabled" } else { "disabled" });
+                    return true;
+                }
+                VirtualKeyCode::F6 => {
+                    self.retro_mode = (self.retro_mode + 1) % 3;
+                    self.retro.set_palette(if self.retro_mode == 2 { &RETRO_PALETTE[..] } else { &[] });
+                    log::info!("Retro filter {}", ["off", "on", "on with palette"][self.retro_mode as usize]);
+                    return true;
+                }
+                VirtualKeyCode::F8 => {
+                    self.batched = !self.batched;
+                    log::info!("Texture array batching {}", if self.batched { "enabled" } else { "disabled" });
+                    return true;
+                }
+                VirtualKeyCode::F7 => {
+                    self.shadow_quality = self.shadow_quality.next();
+                    self.lights.shadow_settings = self.shadow_quality.settings();
+                    log::info!("Shadow quality {}", self.shadow_quality.name());
+                    return true;
+                }
+                VirtualKeyCode::Minus | VirtualKeyCode::Equals => {
+                    let step = if *keycode == VirtualKeyCode::Minus { -EXPOSURE_STEP } else { EXPOSURE_STEP };
+
+                    self.color_grading.exposure += step;
+                    log::info!("Exposure {:+.2}", self.color_grading.exposure);
+                    return true;
+                }
+                VirtualKeyCode::LBracket | VirtualKeyCode::RBracket => {
+                    let step = if *keycode == VirtualKeyCode::LBracket { -TEMPERATURE_STEP } else { TEMPERATURE_STEP };
+
+                    self.color_grading.temperature = (self.color_grading.temperature + step).clamp(-1.0, 1.0);
+                    log::info!("Temperature {:+.1}", self.color_grading.temperature);
+                    return true;
+                }
+                VirtualKeyCode::V => {
+                    self.show_sky_ambient = !self.show_sky_ambient;
+                    self.lights.ambient   = if self.show_sky_ambient { self.sky_ambient } else { SphericalHarmonics::default() };
+                    log::info!("Sky ambient light {}", if self.show_sky_ambient { "enabled" } else { "disabled" });
+                    return true;
+                }
+                VirtualKeyCode::Y if self.light_clusters.is_some() => {
+                    self.light_heatmap = !self.light_heatmap;
+                    self.logged_stats  = None;
+                    log::info!("Light heatmap {}", if self.light_heatmap { "shown" } else { "hidden" });
+                    return true;
+                }
+                VirtualKeyCode::F if self.analysis.is_some() => {
+                    self.analyzing       = !self.analyzing;
+                    self.logged_analysis = None;
+                    log::info!("Scene analysis {}", if self.analyzing { "enabled" } else { "disabled" });
+                    return true;
+                }
+                VirtualKeyCode::H if self.bloom.is_some() => {
+                    self.show_bloom = !self.show_bloom;
+                    log::info!("Bloom {}", if self.show_bloom { "enabled" } else { "disabled" });
+                    return true;
+                }
+                VirtualKeyCode::Grave if self.lens_flare.is_some() => {
+                    self.show_lens_flare = !self.show_lens_flare;
+                    log::info!("Lens flare {}", if self.show_lens_flare { "enabled" } else { "disabled" });
+                    return true;
+                }
+                VirtualKeyCode::F9 if self.auto_exposure.is_some() => {
+                    self.auto_exposing = !self.auto_exposing;
+                    log::info!("Auto exposure {}", if self.auto_exposing { "enabled" } else { "disabled" });
+                    return true;
+                }
+                #[cfg(not(target_arch = "wasm32"))]
+                VirtualKeyCode::F10 => {
+                    match self.save_thumbnail(ctx) {
+                        Ok(()) => log::info!("Saved a thumbnail to {}", THUMBNAIL_FILE),
+                        Err(e) => log::warn!("Couldn't save a thumbnail: {:?}", e),
+                    }
+                    return true;
+                }
+                #[cfg(not(target_arch = "wasm32"))]
+                VirtualKeyCode::Delete => {
+                    if self.lights.probes.take().is_some() {
+                        log::info!("Light probes removed");
+                    } else {
+                        match self.bake_light_probes(ctx) {
+                            Ok(count) => log::info!("Baked {} light probes", count),
+                            Err(e)    => log::warn!("Couldn't bake light probes: {:?}", e),
+                        }
+                    }
+                    return true;
+                }
+                VirtualKeyCode::PageUp => {
+                    self.lights.contact_shadows = match self.lights.contact_shadows {
+                        Some(_) => None,
+                        None    => Some(ContactShadowSettings::default()),
+                    };
+                    log::info!("Contact shadows {}", if self.lights.contact_shadows.is_some() { "enabled" } else { "disabled" });
+                    return true;
+                }
+                VirtualKeyCode::F11 => {
+                    self.show_mirror = !self.show_mirror;
+                    log::info!("Mirror {}", if self.show_mirror { "shown" } else { "hidden" });
+                    return true;
+                }
+                VirtualKeyCode::Backslash => {
+                    self.show_ssr = !self.show_ssr;
+                    log::info!("Screen-space reflections {}", if self.show_ssr { "enabled" } else { "disabled" });
+                    return true;
+                }
+                VirtualKeyCode::Semicolon => {
+                    self.show_foliage = !self.show_foliage;
+                    log::info!("Foliage {}", if self.show_foliage { "shown" } else { "hidden" });
+                    return true;
+                }
+                VirtualKeyCode::Numpad0 => {
+                    self.show_voxels = !self.show_voxels;
+                    log::info!("Voxel terrain {}", if self.show_voxels { "shown" } else { "hidden" });
+                    return true;
+                }
+                // Digs out the block in front of the camera, or places stone on the face looked at
+                VirtualKeyCode::Numpad1 | VirtualKeyCode::Numpad2 if self.show_voxels => {
+                    let hit = self.voxels.raycast(self.camera.eye, self.camera.target - self.camera.eye, VOXEL_REACH);
+
+                    if let Some(hit) = hit {
+                        if *keycode == VirtualKeyCode::Numpad1 {
+                            self.voxels.set_block(hit.block, AIR);
+                        } else {
+                            self.voxels.set_block(hit.block + hit.normal, VOXEL_STONE);
+                        }
+                    }
+                    return true;
+                }
+                VirtualKeyCode::Numpad3 => {
+                    self.show_planet = !self.show_planet;
+                    log::info!("Planet {}", if self.show_planet { "shown" } else { "hidden" });
+                    return true;
+                }
+                VirtualKeyCode::Numpad4 if self.ocean.is_some() => {
+                    self.show_ocean = !self.show_ocean;
+                    log::info!("Ocean {}", if self.show_ocean { "shown" } else { "hidden" });
+                    return true;
+                }
+                VirtualKeyCode::Numpad6 => {
+                    self.weather.precipitation = match self.weather.precipitation {
+                        Precipitation::Clear => Precipitation::Rain,
+                        Precipitation::Rain  => Precipitation::Snow,
+                        Precipitation::Snow  => Precipitation::Clear,
+                    };
+                    log::info!("Weather: {:?}", self.weather.precipitation);
+                    return true;
+                }
+                VirtualKeyCode::NumpadAdd | VirtualKeyCode::NumpadSubtract => {
+                    let step = if *keycode == VirtualKeyCode::NumpadAdd { 0.1 } else { -0.1 };
+
+                    self.weather.intensity = (self.weather.intensity + step).clamp(0.0, 1.0);
+                    log::info!("Weather intensity: {:.1}", self.weather.intensity);
+                    return true;
+                }
+                VirtualKeyCode::NumpadMultiply => {
+                    self.debug_view = self.debug_view.next();
+
+                    // The heatmap needs compute shaders
+                    if self.debug_view == DebugView::LightClusters && self.light_clusters.is_none() {
+                        self.debug_view = self.debug_view.next();
+                    }
+
+                    self.lights.debug_view = self.debug_view;
+                    self.logged_stats      = None;
+                    self.velocity.reset();
+                    log::info!("Debug view: {}", self.debug_view.name());
+                    return true;
+                }
+                VirtualKeyCode::Numpad7 => {
+                    self.show_curves = !self.show_curves;
+                    log::info!("Curves {}", if self.show_curves { "shown" } else { "hidden" });
+                    return true;
+                }
+                VirtualKeyCode::Numpad8 => {
+                    self.show_hud = !self.show_hud;
+                    log::info!("HUD {}", if self.show_hud { "shown" } else { "hidden" });
+                    return true;
+                }
+                VirtualKeyCode::Numpad9 => {
+                    if let Some(frame) = self.status_ui.node_mut(self.status_frame) {
+                        frame.visible = !frame.visible;
+                        log::info!("Status {}", if frame.visible { "shown" } else { "hidden" });
+                    }
+                    return true;
+                }
+                VirtualKeyCode::Numpad5 => {
+                    if let Some(ocean) = &mut self.ocean {
+                        ocean.settings.spectrum = match ocean.settings.spectrum {
+                            Spectrum::Phillips => Spectrum::Jonswap,
+                            Spectrum::Jonswap  => Spectrum::Phillips,
+                        };
+                        log::info!("Ocean spectrum: {:?}", ocean.settings.spectrum);
+                    }
+                    return true;
+                }
+                VirtualKeyCode::Slash => {
+                    if self.cinematic.is_playing() {
+                        self.cinematic.pause();
+                    } else {
+                        self.cinematic.play();
+                    }
+                    log::info!("Fly-through {}", if self.cinematic.is_playing() { "playing" } else { "paused" });
+                    return true;
+                }
+                VirtualKeyCode::Comma | VirtualKeyCode::Period if !self.recording => {
+                    let step = if *keycode == VirtualKeyCode::Comma { -SCRUB_STEP } else { SCRUB_STEP };
+
+                    self.cinematic.seek(self.cinematic.time() + step);
+                    self.cinematic.apply(&mut self.camera);
+                    log::info!("Fly-through at {:.1}s", self.cinematic.time());
+                    return true;
+                }
+                #[cfg(not(target_arch = "wasm32"))]
+                VirtualKeyCode::Apostrophe if !self.recording => {
+                    self.start_recording();
+                    log::info!("Recording the fly-through at {} fps", RECORDING_FPS);
+                    return true;
+                }
+                VirtualKeyCode::N => {
+                    self.set_player_count(match self.split_screen.players.len() {
+                        0                                  => 2,
+                        n if n < split_screen::MAX_PLAYERS => n + 1,
+                        _                                  => 0,
+                    });
+                    return true;
+                }
+                VirtualKeyCode::P => {
+                    self.show_portal = !self.show_portal;
+                    log::info!("Portal {}", if self.show_portal { "shown" } else { "hidden" });
+                    return true;
+                }
+                VirtualKeyCode::B => {
+                    self.animator.playing = !self.animator.playing;
+                    log::info!("Animations {}", if self.animator.playing { "playing" } else { "paused" });
+                    return true;
+                }
+                VirtualKeyCode::M => {
+                    self.show_minimap = !self.show_minimap;
+                    log::info!("Minimap {}", if self.show_minimap { "shown" } else { "hidden" });
+                    return true;
+                }
+                VirtualKeyCode::Z => {
+                    let fovy = if self.zoom.destination() == ZOOM_FOVY { self.base_fovy } else { ZOOM_FOVY };
+
+                    self.zoom.start(&self.camera, fovy, ZOOM_DURATION);
+                    return true;
+                }
+                VirtualKeyCode::X => {
+                    self.shake.add_trauma(SHAKE_TRAUMA);
+                    return true;
+                }
+                VirtualKeyCode::C => {
+                    self.following = !self.following;
+                    self.follow.reset();
+                    log::info!("Following the selection {}", if self.following { "enabled" } else { "disabled" });
+                    return true;
+                }
+                VirtualKeyCode::F4 if self.gpu_culler.is_some() && self.split_screen.players.is_empty() => {
+                    self.gpu_culling = !self.gpu_culling;
+                    log::info!("GPU culling {}", if self.gpu_culling { "enabled" } else { "disabled" });
+                    return true;
+                }
+                _ => {}
+            }
+        }
+
+        // Files can only be dropped onto native windows
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            if let WindowEvent::DroppedFile(path) = event {
+                if let Err(e) = pollster::block_on(self.load_dropped_file(ctx, path)) {
+                    log::warn!("Couldn't load {}: {:?}", path.display(), e);
+                }
+                return true;
+            }
+        }
+
+        let split = !self.split_screen.players.is_empty();
+
+        self.editor.process_events(event, &self.view, ctx.size, &mut self.instances, &mut self.obj_model.materials, &self.bvh, split)
+            || self.camera_controller.process_events(event)
+    }
+
+    fn resize(&mut self, ctx: &GpuContext) {
+        self.camera.aspect = ctx.aspect();
+        self.split_screen.resize(ctx);
+        self.transmission.resize(ctx);
+        self.contact_shadows.resize(ctx);
+        self.lights.set_scene_depth(&ctx.device, self.contact_shadows.view());
+
+        if let Some(culler) = &mut self.gpu_culler {
+            culler.resize(ctx);
+        }
+    }
+
+    fn update(&mut self, dt: Duration, input: &Input) {
+        self.motion_blur.update(dt);
+
+        if input.is_cursor_grabbed() {
+            let (dx, dy) = input.mouse_motion();
+            self.camera_controller.process_mouse(dx, dy);
+        }
+
+        let split = !self.split_screen.players.is_empty();
+
+        // Split-screen players move their own cameras. Otherwise the fly-through takes over the
+        // camera while it plays, and the controller has it the rest of the time.
+        if split {
+            self.split_screen.update(input, dt);
+        } else if self.cinematic.is_playing() {
+            self.cinematic.update(dt);
+            self.cinematic.apply(&mut self.camera);
+        } else {
+            self.camera_controller.update_camera(&mut self.camera);
+        }
+
+        if self.following {
+            if let Some(selected) = self.editor.selected {
+                self.follow.track(&mut self.camera, cgmath::Point3::from_vec(self.instances[selected].position), dt);
+            }
+        }
+
+        self.zoom.update(&mut self.camera, dt);
+        self.shake.update(dt);
+
+        self.view = self.shake.shaken(&self.camera);
+        self.camera_uniform.update_view_proj(&self.view);
+        self.foliage.update(dt);
+
+        if let Some(ocean) = &mut self.ocean {
+            ocean.update(dt);
+        }
+
+        self.weather.update(dt);
+        self.lights.wetness = self.weather.wetness();
+
+        // Clouds dim the sky's light while it rains or snows
+        if self.show_sky_ambient {
+            self.lights.ambient = self.sky_ambient.scaled(self.weather.sky_light());
+        }
+
+        self.animator.update(dt);
+
+        if self.animator.playing {
+            let moved = self.animator.apply(&mut AnimationTargets {
+                materials: &mut self.obj_model.materials,
+                lights:    &mut self.lights.spots,
+                instances: &mut self.instances,
+            });
+
+            // Animated instances are handled the same as ones dragged in the editor
+            self.editor.dirty |= moved;
+        }
+
+        // Rebuild the hierarchy around any instances moved in the editor or by animations
+        if self.editor.dirty {
+            self.bvh = build_bvh(&self.obj_model, &self.instances);
+        }
+
+        self.visible.clear();
+
+        // The players' views are drawn from the same instances, so none are culled for them
+        if split {
+            self.visible.extend(0..self.instances.len());
+        } else if !self.gpu_culling {
+            let frustum = Frustum::from_view_proj(&self.view.build_view_projections_matrix());
+
+            self.bvh.query_frustum(&frustum, &mut self.visible);
+            self.visible.sort_unstable();
+        }
+
+        if !self.lods.levels.is_empty() {
+            let bounds = self.obj_model.bounds();
+
+            self.lod_selector.update(&self.lods, &self.view, &bounds, &self.instances, &self.visible, dt.as_secs_f32());
+        }
+
+        if self.show_dof && self.dof.autofocus && !split {
+            if let Some(distance) = self.focus_distance() {
+                self.dof.focus_towards(distance, dt);
+            }
+        }
+
+        self.draw_debug();
+    }
+
+    fn render<'a>(&'a mut self, frame: &mut Frame, layers: &mut RenderLayers<'a>) {
+        let device = &frame.ctx.device;
+        let queue  = &frame.ctx.queue;
+
+        // Ahead of the uploads below, so what's changed in a window shows this frame
+        #[cfg(feature = "egui")]
+        {
+            let (editor, instances, materials) = (&mut self.editor, &mut self.instances, &mut self.obj_model.materials);
+
+            self.egui.run(frame.ctx, |ctx| {
+                editor.show(ctx, instances, materials);
+
+                if editor.enabled {
+                    gpu_stats::show(ctx);
+                }
+            });
+        }
+
+        // Changed materials go up together, ahead of every view that draws them, along with any
+        // pipelines they need that haven't been built yet
+        for material in &mut self.obj_model.materials {
+            material.upload(queue);
+            self.pipelines.prepare(device, material.pipeline_key());
+            self.equal_pipelines.prepare(device, material.pipeline_key());
+        }
+        for asset in &mut self.dropped {
+            for material in &mut asset.model.materials {
+                material.upload(queue);
+                self.pipelines.prepare(device, material.pipeline_key());
+                self.equal_pipelines.prepare(device, material.pipeline_key());
+            }
+        }
+
+        // Reflections submit their own views, which overwrite the camera buffer, so they go first.
+        // The instance buffer still holds last frame's, so the probe waits for one to exist.
+        if (self.show_mirror || self.show_ssr) && !self.probe_captured && !self.visible.is_empty() {
+            self.probe.capture(frame.ctx, &*self);
+            self.probe_captured = true;
+        }
+
+        if self.show_mirror {
+            self.mirror.render_reflection(frame.ctx, &*self, &self.view);
+        }
+
+        if self.show_portal {
+            self.portal.render(frame.ctx, &*self, &self.view);
+        }
+
+        if !self.split_screen.players.is_empty() {
+            self.split_screen.render(frame.ctx, &*self);
+        }
+
+        if self.show_minimap {
+            let overhead = minimap::overhead_camera(&self.view, MINIMAP_HEIGHT, self.minimap.aspect());
+
+            self.minimap.render(frame.ctx, &*self, &overhead);
+        }
+
+        let heatmap = self.light_heatmap || self.debug_view == DebugView::LightClusters;
+
+        if let Some(clusters) = &mut self.light_clusters {
+            if heatmap {
+                clusters.update(frame.ctx, &self.view, &self.lights.spots);
+
+                // Only logged again when the hot spot changes
+                if let Some(stats) = clusters.stats() {
+                    let changed = self.logged_stats.is_none_or(|logged| logged.max != stats.max || logged.hottest != stats.hottest);
+
+                    if changed {
+                        log::info!(
+                            "Light clusters: up to {} lights in cell {:?}, {:.1} on average over {} of {} cells",
+                            stats.max, stats.hottest, stats.average, stats.occupied, stats.cells,
+                        );
+                        self.logged_stats = Some(stats);
+                    }
+                }
+            }
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.recording {
+            if let Err(e) = self.record_frame(frame.ctx) {
+                log::warn!("Couldn't record a frame: {:?}", e);
+                self.cinematic.pause();
+            }
+
+            if !self.cinematic.is_playing() {
+                self.stop_recording();
+            }
+        }
+
+        self.clipped_view.set(false);
+        self.camera_buffer.write(queue, &self.camera_uniform);
+        self.lights.prepare(queue, self.view.eye);
+
+        if self.editor.dirty || self.instances_moved {
+            let instance_data = self.instances.iter().map(Instance::to_raw).collect::<Vec<_>>();
+
+            // Last frame's matrices become the previous ones
+            queue.write_buffer(&self.shadow_instances, 0, bytemuck::cast_slice(&instance_data));
+            queue.write_buffer(&self.motion_instances, 0, bytemuck::cast_slice(&self.instance_history));
+
+            self.instance_history = instance_data;
+        }
+
+        self.instances_moved = self.editor.dirty;
+
+        if let Some(culler) = &mut self.gpu_culler {
+            if self.editor.dirty {
+                culler.set_instances(queue, &cull_instances(&self.obj_model, &self.instances));
+            }
+
+            if self.gpu_culling {
+                let view_proj = self.view.build_view_projections_matrix();
+
+                frame.compute("GPU Culling", |ctx, encoder| culler.cull(ctx, encoder, view_proj));
+            }
+        }
+
+        self.editor.dirty = false;
+
+        // Only the instances that survived culling are uploaded and drawn
+        if self.lods.levels.is_empty() {
+            let instance_data = self.visible.iter()
+                .map(|&i| self.instances[i].to_raw())
+                .collect::<Vec<_>>();
+
+            queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&instance_data));
+        } else {
+            queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(self.lod_selector.instances()));
+        }
+
+        if self.batched {
+            let layers       = self.material_array.layer_count();
+            let layered_data = self.visible.iter()
+                .map(|&i| self.instances[i].to_layered_raw(i as u32 % layers))
+                .collect::<Vec<_>>();
+
+            queue.write_buffer(&self.layered_instances, 0, bytemuck::cast_slice(&layered_data));
+        }
+
+        self.debug_draw.prepare(device, queue, self.view.build_view_projections_matrix());
+        frame.ctx.pass_timer().time(&mut frame.encoder, "Shadows", |encoder| self.lights.render_shadows(encoder, &*self));
+
+        match self.debug_view {
+            DebugView::Depth       => self.debug_viewer.prepare_depth(frame.ctx, &self.view),
+            DebugView::ShadowAtlas => self.debug_viewer.prepare_raw(frame.ctx, self.lights.shadow_atlas_view()),
+            _                      => {}
+        }
+
+        let motion_blur   = self.show_motion_blur && self.motion_blur.active();
+        let show_velocity = self.show_velocity || self.debug_view == DebugView::Velocity;
+
+        // Only for the main view, the players' views would each need their own
+        if (show_velocity || motion_blur) && self.split_screen.players.is_empty() {
+            self.velocity.prepare(frame.ctx, &self.view);
+            self.velocity.render(&mut frame.encoder, &*self, frame.ctx.depth_mode);
+
+            if motion_blur {
+                self.motion_blur.prepare(frame.ctx, &self.view, &self.velocity);
+            }
+        }
+
+        let toon = self.models().any(|model| {
+            model.materials.iter().any(|material| material.shading == ShadingModel::Toon)
+        });
+
+        if toon {
+            self.outline.prepare(frame.ctx);
+        }
+
+        let selected = self.editor.selected.filter(|_| self.editor.enabled);
+
+        if (self.show_edges || selected.is_some()) && self.split_screen.players.is_empty() {
+            // Toon shaded instances get dark silhouettes of their own
+            let stylized = if toon { MASK_STYLIZED } else { 0 };
+            let masks    = (0..self.instances.len())
+                .map(|i| EdgeMaskRaw { mask: stylized | if selected == Some(i) { MASK_SELECTED } else { 0 } })
+                .collect::<Vec<_>>();
+
+            queue.write_buffer(&self.edge_masks, 0, bytemuck::cast_slice(&masks));
+
+            self.edges.all_edges = self.show_edges;
+            self.edges.prepare(frame.ctx, &self.view);
+            self.edges.render(&mut frame.encoder, &*self, frame.ctx.depth_mode);
+        }
+
+        if self.show_foliage {
+            self.foliage.prepare(queue);
+        }
+
+        if self.show_voxels {
+            self.voxels.update(&frame.ctx.device);
+        }
+
+        if self.show_planet {
+            self.planet.update(queue, &self.view);
+        }
+
+        if let Some(ocean) = &mut self.ocean {
+            if self.show_ocean {
+                frame.compute("Ocean Simulation", |ctx, encoder| ocean.simulate(ctx, encoder));
+            }
+        }
+
+        if self.weather.is_falling() {
+            self.weather.prepare(queue);
+        }
+
+        if self.show_curves {
+            let tolerance = Tolerance::Screen {
+                view_proj: self.view.build_view_projections_matrix(),
+                viewport:  (frame.ctx.scene_target.width as f32, frame.ctx.scene_target.height as f32),
+                pixels:    CURVE_TOLERANCE,
+            };
+            let road = curves::Curve::new(CurveKind::BSpline, ROAD_POINTS.iter().map(|&point| point.into()).collect()).closed();
+
+            self.wide_lines.curve(&road, &tolerance, LineWidth::World(ROAD_WIDTH), [0.25, 0.25, 0.28]);
+            self.wide_lines.curve(&self.cinematic.path.eye_curve(), &tolerance, LineWidth::Pixels(3.0), [1.0, 0.8, 0.2]);
+            self.wide_lines.prepare(frame.ctx, &self.view);
+        }
+
+        if self.show_hud {
+            let intensity = self.weather.intensity.clamp(0.0, 1.0);
+
+            self.hud.fill_rounded_rect([16.0, 16.0], [236.0, 136.0], 12.0, [0.02, 0.02, 0.03, 0.6]);
+            self.hud.stroke_rounded_rect([16.0, 16.0], [236.0, 136.0], 12.0, 2.0, [0.8, 0.8, 0.85, 0.8]);
+            self.hud.fill_circle([76.0, 76.0], 40.0, [0.1, 0.1, 0.12, 0.8]);
+            // The ring brightens with the weather's intensity
+            self.hud.stroke_circle([76.0, 76.0], 40.0, 6.0, [0.2, 0.5, 1.0, 0.3 + 0.7 * intensity]);
+            self.hud.fill(&self.hud_star, [1.0, 0.75, 0.1, 0.9]);
+            self.hud.stroke(&self.hud_star, 1.5, [0.3, 0.15, 0.0, 1.0]);
+            self.hud.prepare(frame.ctx, &self.view);
+        }
+
+        // Only touched when the weather changes, so it isn't laid out again every frame
+        let meter = 216.0 * self.weather.intensity.clamp(0.0, 1.0);
+
+        let width = self.status_ui.node(self.status_meter).map(|node| node.size[0]);
+
+        if matches!(width, Some(width) if width != meter) {
+            if let Some(node) = self.status_ui.node_mut(self.status_meter) {
+                node.size[0] = meter;
+            }
+        }
+
+        self.editor.update(&mut self.status_ui, &self.obj_model.materials);
+        self.status_ui.prepare(frame.ctx);
+
+        if self.show_ssr {
+            self.ssr.prepare(frame.ctx, &self.view, &self.probe);
+        }
+
+        if self.show_dof {
+            self.dof.prepare(frame.ctx, &self.view);
+        }
+
+        if let Some(bloom) = &mut self.bloom {
+            if self.show_bloom {
+                bloom.prepare(frame.ctx);
+
+                // Glare is tested against the main view's depth
+                if let Some(lens_flare) = &mut self.lens_flare {
+                    if self.show_lens_flare && self.split_screen.players.is_empty() {
+                        lens_flare.prepare(frame.ctx, &self.view, bloom);
+                    }
+                }
+            }
+        }
+
+        if let Some(auto_exposure) = &mut self.auto_exposure {
+            if self.auto_exposing {
+                auto_exposure.prepare(frame.ctx);
+            }
+        }
+
+        if let Some(analysis) = &mut self.analysis {
+            if self.analyzing {
+                analysis.prepare(frame.ctx, &self.view);
+
+                // Only logged again when the picture has noticeably changed
+                if let Some(results) = analysis.results() {
+                    let changed = self.logged_analysis.as_ref().is_none_or(|logged| {
+                        (logged.luminance.average - results.luminance.average).abs() > 0.05
+                            || logged.depth.percentile(0.5) != results.depth.percentile(0.5)
+                    });
+
+                    if changed {
+                        let median = results.depth.percentile(0.5).unwrap_or(0.0);
+
+                        log::info!(
+                            "Scene: luminance {:.3} to {:.3}, {:.3} on average, {:.0}% covered, half within {:.1}",
+                            results.luminance.min, results.luminance.max, results.luminance.average,
+                            results.depth.coverage * 100.0, median,
+                        );
+                        self.logged_analysis = Some(results.clone());
+                    }
+                }
+            }
+        }
+
+        let this: &'a Self = self;
+        let split          = !this.split_screen.players.is_empty();
+
+        // The players' views replace the main one, along with everything drawn into it
+        if split {
+            layers.add(RenderLayer::Background, &this.split_screen);
+        } else {
+            if this.depth_prepass {
+                layers.add(RenderLayer::DepthPrepass, this);
+            }
+
+            layers.add(RenderLayer::WorldOpaque, this);
+            layers.add(RenderLayer::Debug, &this.debug_draw);
+
+            if this.show_curves {
+                layers.add(RenderLayer::Debug, &this.wide_lines);
+            }
+
+            // Contact shadows march through the prepass depth while the opaque layer is lit
+            if this.depth_prepass && this.lights.contact_shadows.is_some() {
+                layers.add_capture(RenderLayer::WorldOpaque, &this.contact_shadows);
+            }
+
+            if this.show_mirror {
+                layers.add(RenderLayer::WorldOpaque, &this.mirror);
+            }
+
+            if this.show_portal {
+                layers.add(RenderLayer::WorldOpaque, &this.portal);
+            }
+
+            let glass = this.models().flat_map(|model| &model.materials).any(model::Material::is_transmissive);
+
+            // Rain and snow are drawn over everything opaque, along with any glass
+            if glass || this.weather.is_falling() {
+                layers.add(RenderLayer::WorldTransparent, this);
+            }
+
+            // Glass reads the opaque scene, so it's copied before the transparent layer
+            if glass {
+                layers.add_capture(RenderLayer::WorldTransparent, &this.transmission);
+            }
+
+            if toon {
+                layers.add(RenderLayer::Post, &this.outline);
+            }
+
+            if this.show_edges || (this.editor.enabled && this.editor.selected.is_some()) {
+                layers.add(RenderLayer::Post, &this.edges);
+            }
+        }
+
+        if this.show_minimap {
+            layers.add(RenderLayer::Ui, &this.minimap);
+        }
+
+        if this.show_hud {
+            layers.add(this.hud.layer(), &this.hud);
+        }
+
+        layers.add(RenderLayer::Ui, &this.status_ui);
+
+        #[cfg(feature = "egui")]
+        layers.add(RenderLayer::Ui, &this.egui);
+
+        if show_velocity && !split {
+            layers.add(RenderLayer::Ui, &this.velocity);
+        }
+
+        if let Some(clusters) = &this.light_clusters {
+            if heatmap {
+                layers.add(RenderLayer::Ui, clusters);
+            }
+        }
+
+        if matches!(this.debug_view, DebugView::Depth | DebugView::ShadowAtlas) && !split {
+            layers.add(RenderLayer::Ui, &this.debug_viewer);
+        }
+
+        // Before exposure and grading, so reflections are of the scene as lit. Traced against the
+        // main view's depth, so not over the players' views.
+        if this.show_ssr && !split {
+            layers.add_effect(&this.ssr);
+        }
+
+        // Focus blur first, so motion smears the blurred picture as a camera's would
+        if this.show_dof && !split {
+            layers.add_effect(&this.dof);
+        }
+
+        // Streaks come before bloom so they glow along with what made them
+        if this.show_motion_blur && this.motion_blur.active() && !split {
+            layers.add_effect(&this.motion_blur);
+        }
+
+        // Drops on the lens stay sharp whatever the focus or motion in the scene behind them
+        if this.weather.shows_droplets() && !split {
+            layers.add_effect(&this.weather.droplets);
+        }
+
+        // Ahead of exposure too, which would otherwise move what counts as bright
+        if let Some(bloom) = &this.bloom {
+            if this.show_bloom {
+                layers.add_effect(bloom);
+
+                if let Some(lens_flare) = &this.lens_flare {
+                    if this.show_lens_flare && !split {
+                        layers.add_effect(lens_flare);
+                    }
+                }
+            }
+        }
+
+        if let Some(auto_exposure) = &this.auto_exposure {
+            if this.auto_exposing {
+                layers.add_effect(auto_exposure);
+            }
+        }
+
+        if !this.color_grading.is_neutral() {
+            this.color_grading.prepare(queue);
+            layers.add_effect(&this.color_grading);
+        }
+
+        if this.retro_mode > 0 {
+            this.retro.prepare(queue);
+            layers.add_effect(&this.retro);
+        }
+
+        // Last, so it sees the frame as it's shown
+        if let Some(analysis) = &this.analysis {
+            if this.analyzing {
+                layers.add_effect(analysis);
+            }
+        }
+    }
+}
+
+impl Demo {
+    // How far in front of the view the selected instance is, or whatever is in the middle of the
+    // screen when nothing is selected
+    fn focus_distance(&self) -> Option<f32> {
+        let forward = (self.view.target - self.view.eye).normalize();
+
+        if let Some(selected) = self.editor.selected {
+            let offset = cgmath::Point3::from_vec(self.instances[selected].position) - self.view.eye;
+
+            return Some(offset.dot(forward)).filter(|&distance| distance > 0.0);
+        }
+
+        self.bvh.raycast_hit(&Ray { origin: self.view.eye, direction: forward }).map(|hit| hit.distance)
+    }
+
+    // Players join where the camera is, and 0 goes back to the main view
+    fn set_player_count(&mut self, count: usize) {
+        let players = &mut self.split_screen.players;
+
+        players.truncate(count);
+
+        while players.len() < count {
+            players.push(Player {
+                camera: self.camera.clone(),
+                input:  PLAYER_INPUTS[players.len()],
+                speed:  PLAYER_SPEED,
+            });
+        }
+
+        // Culling on the GPU only knows about the main camera
+        if count > 0 {
+            self.gpu_culling = false;
+        }
+
+        log::info!("{} split-screen players", count);
+    }
+
+    fn toggle_debug(&mut self, category: DebugCategory) {
+        let enabled = self.debug_draw.toggle(category);
+        let frozen  = self.debug_draw.is_enabled(DebugCategory::Frusta) || self.debug_draw.is_enabled(DebugCategory::Clusters);
+
+        if !frozen {
+            self.debug_camera = None;
+        } else if self.debug_camera.is_none() {
+            self.debug_camera = Some(self.view.clone());
+        }
+
+        log::info!("{} {}", category.name(), if enabled { "shown" } else { "hidden" });
+    }
+
+    // Queues the lines of every enabled debug category for this frame
+    fn draw_debug(&mut self) {
+        let debug = &mut self.debug_draw;
+
+        if debug.is_enabled(DebugCategory::Bounds) {
+            for aabb in self.bvh.node_bounds() {
+                debug.aabb(aabb, BOUNDS_COLOR);
+            }
+        }
+
+        if debug.is_enabled(DebugCategory::Frusta) {
+            if let Some(camera) = &self.debug_camera {
+                debug.frustum(&camera.build_view_projections_matrix(), FRUSTUM_COLOR);
+            }
+
+            if self.show_minimap {
+                let overhead = minimap::overhead_camera(&self.view, MINIMAP_HEIGHT, self.minimap.aspect());
+
+                debug.frustum(&overhead.build_view_projections_matrix(), FRUSTUM_COLOR);
+            }
+
+            if self.show_portal {
+                debug.frustum(&self.portal.virtual_camera(&self.view).build_view_projections_matrix(), FRUSTUM_COLOR);
+            }
+        }
+
+        for (i, spot) in self.lights.spots.iter().enumerate() {
+            if debug.is_enabled(DebugCategory::Shadows) && self.lights.shadow_tile(i).is_some() {
+                debug.frustum(&spot.view_proj(), SHADOW_BOUNDS_COLOR);
+            }
+
+            // The outer cone in the light's color, the inner one dimmer
+            if debug.is_enabled(DebugCategory::Lights) {
+                let dim = spot.color.map(|c| c * 0.5);
+
+                debug.cone(spot.position, spot.direction, spot.outer_angle, spot.range, spot.color);
+                debug.cone(spot.position, spot.direction, spot.inner_angle, spot.range, dim);
+            }
+        }
+
+        if debug.is_enabled(DebugCategory::Clusters) {
+            if let Some(camera) = &self.debug_camera {
+                debug.cluster_grid(camera, CLUSTER_CELLS, CLUSTER_COLOR);
+            }
+        }
+
+        self.editor.draw_gizmo(debug, &self.view, &self.instances);
+    }
+
+    fn models(&self) -> impl Iterator<Item = &model::Model> {
+        std::iter::once(&self.obj_model).chain(self.dropped.iter().map(|asset| &asset.model))
+    }
+
+    // None for materials left out of the prepass. Cutouts need their fragment shader to cut the
+    // holes the prepass would fill in, and biased depth wouldn't match what the prepass laid down.
+    // Transmissive materials are only drawn in the transparent layer, which nothing else is. Comes
+    // with the pipeline's label for crash reports.
+    fn pipeline(&self, layer: RenderLayer, material: &model::Material) -> Option<(&wgpu::RenderPipeline, &str)> {
+        let in_prepass = material.alpha != AlphaMode::Cutout && material.depth_bias == DepthBias::None;
+        let key        = material.pipeline_key();
+
+        match layer {
+            RenderLayer::WorldTransparent if material.is_transmissive()       => Some((self.transmission.pipeline(material.cull), "Glass Pipeline")),
+            RenderLayer::WorldTransparent                                     => None,
+            _ if material.is_transmissive()                                   => None,
+            RenderLayer::DepthPrepass if !in_prepass                          => None,
+            RenderLayer::DepthPrepass                                         => Some((&self.prepass_pipelines[material.cull as usize], "Depth Prepass Pipeline")),
+            _ if self.depth_prepass && !self.clipped_view.get() && in_prepass => Some((self.equal_pipelines.get(key), self.equal_pipelines.label(key))),
+            _                                                                 => Some((self.pipelines.get(key), self.pipelines.label(key))),
+        }
+    }
+
+    // Like `draw_model_instanced`, but with each mesh's pipeline picked by its material
+    fn draw_model<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        layer:       RenderLayer,
+        model:       &'a model::Model,
+        instances:   Range<u32>,
+    ) {
+        self.draw_meshes(render_pass, layer, model, &model.meshes, instances, false);
+    }
+
+    // `meshes` are `model`'s or one of its levels of detail. Dithered instances are tested against
+    // depth as usual rather than matched to the prepass.
+    fn draw_meshes<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        layer:       RenderLayer,
+        model:       &'a model::Model,
+        meshes:      &'a [model::Mesh],
+        instances:   Range<u32>,
+        dithered:    bool,
+    ) {
+        if instances.is_empty() {
+            return;
+        }
+
+        for mesh in meshes {
+            let material = &model.materials[mesh.material];
+            let pipeline = if dithered && layer == RenderLayer::WorldOpaque && !material.is_transmissive() {
+                Some((self.pipelines.get(material.pipeline_key()), self.pipelines.label(material.pipeline_key())))
+            } else {
+                self.pipeline(layer, material)
+            };
+
+            if let Some((pipeline, label)) = pipeline {
+                crash_report::set_pipeline(render_pass, pipeline, label);
+                render_pass.draw_mesh_instanced(mesh, material, instances.clone(), &self.camera_bind_group);
+            }
+
+            // Fur grows from what the opaque layer just drew, one shell at a time
+            if layer == RenderLayer::WorldOpaque && !material.is_transmissive() && material.has_fur() {
+                crash_report::set_pipeline(render_pass, self.fur.pipeline(material.cull), "Fur Pipeline");
+
+                for shell in self.fur.shells(material) {
+                    render_pass.set_bind_group(3, shell, &[]);
+                    render_pass.draw_mesh_instanced(mesh, material, instances.clone(), &self.camera_bind_group);
+                }
+            }
+        }
+    }
+}
+
+// Shares the camera buffer with the main view, which rewrites it every frame. Draws the instances
+// visible from the main camera.
+impl Scene for Demo {
+    fn render_view<'a>(
+        &'a self,
+        queue:      &wgpu::Queue,
+        camera:     &Camera,
+        clip_plane: Option<cgmath::Vector4<f32>>,
+        layers:     &mut RenderLayers<'a>,
+    ) {
+        let mut camera_uniform = CameraUniform::new();
+
+        match clip_plane {
+            Some(plane) => camera_uniform.update_view_proj_clipped(camera, plane),
+            None        => camera_uniform.update_view_proj(camera),
+        }
+
+        self.camera_buffer.write(queue, &camera_uniform);
+        self.clipped_view.set(clip_plane.is_some());
+
+        if self.depth_prepass && clip_plane.is_none() {
+            layers.add(RenderLayer::DepthPrepass, self);
+        }
+
+        layers.add(RenderLayer::WorldOpaque, self);
+    }
+
+    // Against the instances' boxes, like editor picking
+    fn raycast(&self, origin: cgmath::Point3<f32>, direction: cgmath::Vector3<f32>) -> Option<Hit> {
+        self.bvh.raycast_hit(&Ray { origin, direction })
+    }
+}
+
+// The headset stands the player where the fly camera is
+impl crate::stereo::XrScene for Demo {
+    fn xr_camera(&self) -> &Camera {
+        &self.camera
+    }
+}
+
+impl Drawable for Demo {
+    fn draw<'a>(&'a self, layer: RenderLayer, render_pass: &mut wgpu::RenderPass<'a>) {
+        let model = &self.obj_model;
+
+        // Every shading model reads the same lights, so they're bound once for the whole layer
+        render_pass.set_bind_group(2, &self.lights.bind_group, &[]);
+
+        if layer == RenderLayer::WorldTransparent {
+            render_pass.set_bind_group(3, self.transmission.bind_group(), &[]);
+        }
+
+        match &self.gpu_culler {
+            Some(culler) if self.gpu_culling => {
+                for (i, mesh) in model.meshes.iter().enumerate() {
+                    if let Some((pipeline, label)) = self.pipeline(layer, &model.materials[mesh.material]) {
+                        crash_report::set_pipeline(render_pass, pipeline, label);
+                        culler.draw_mesh(render_pass, model, i, &self.camera_bind_group);
+                    }
+                }
+            }
+            // The prepass only needs positions, so it keeps drawing the plain instances. Glass needs
+            // its own pipeline, so the transparent layer does too.
+            _ if self.batched && layer == RenderLayer::WorldOpaque => {
+                crash_report::set_pipeline(render_pass, &self.batched_pipeline, "Batched Pipeline");
+                render_pass.set_vertex_buffer(1, self.layered_instances.slice(..));
+                render_pass.set_bind_group(0, &self.material_array.bind_group, &[]);
+                render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+
+                for mesh in model.meshes.iter().filter(|mesh| !model.materials[mesh.material].is_transmissive()) {
+                    render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                    render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                    render_pass.draw_indexed(0..mesh.num_elements, 0, 0..self.visible.len() as u32);
+                }
+            }
+            _ if !self.lods.levels.is_empty() => {
+                render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+
+                for batch in self.lod_selector.batches() {
+                    let meshes = self.lods.meshes(model, batch.level);
+
+                    self.draw_meshes(render_pass, layer, model, meshes, batch.stable.clone(), false);
+
+                    // Dithering instances weren't in the prepass, which couldn't cut them out
+                    if layer != RenderLayer::DepthPrepass {
+                        self.draw_meshes(render_pass, layer, model, meshes, batch.fading.clone(), true);
+                    }
+                }
+            }
+            _ => {
+                render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+                self.draw_model(render_pass, layer, model, 0..self.visible.len() as u32);
+            }
+        }
+
+        for asset in &self.dropped {
+            render_pass.set_vertex_buffer(1, asset.instance_buffer.slice(..));
+            self.draw_model(render_pass, layer, &asset.model, 0..1);
+        }
+
+        // Not in the prepass, since alpha tested blades need their fragment shader to cut them out
+        if self.show_foliage && layer == RenderLayer::WorldOpaque {
+            self.foliage.draw(render_pass, &self.camera_bind_group);
+        }
+
+        if self.show_voxels && layer == RenderLayer::WorldOpaque {
+            self.voxels.draw(render_pass, &self.camera_bind_group);
+        }
+
+        if self.show_planet && layer == RenderLayer::WorldOpaque {
+            self.planet.draw(render_pass, &self.camera_bind_group);
+        }
+
+        if let Some(ocean) = &self.ocean {
+            if self.show_ocean && layer == RenderLayer::WorldOpaque {
+                ocean.draw(render_pass, &self.camera_bind_group);
+            }
+        }
+
+        // After any glass, which would otherwise hide the rain behind it
+        if layer == RenderLayer::WorldTransparent {
+            self.weather.draw(render_pass, &self.camera_bind_group);
+        }
+    }
+}
+
+impl ShadowCaster for Demo {
+    fn draw_shadow<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_vertex_buffer(1, self.shadow_instances.slice(..));
+        draw_depth(render_pass, &self.obj_model, 0..self.instances.len() as u32);
+
+        for asset in &self.dropped {
+            render_pass.set_vertex_buffer(1, asset.instance_buffer.slice(..));
+            draw_depth(render_pass, &asset.model, 0..1);
+        }
+    }
+}
+
+// Assets dropped into the scene don't move, so they're their own previous instances
+impl MotionCaster for Demo {
+    fn draw_motion<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_vertex_buffer(1, self.shadow_instances.slice(..));
+        render_pass.set_vertex_buffer(2, self.motion_instances.slice(..));
+        draw_depth(render_pass, &self.obj_model, 0..self.instances.len() as u32);
+
+        for asset in &self.dropped {
+            render_pass.set_vertex_buffer(1, asset.instance_buffer.slice(..));
+            render_pass.set_vertex_buffer(2, asset.instance_buffer.slice(..));
+            draw_depth(render_pass, &asset.model, 0..1);
+        }
+    }
+}
+
+// Dropped assets are never selected, so they go unmasked
+impl EdgeCaster for Demo {
+    fn draw_edges<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_vertex_buffer(1, self.shadow_instances.slice(..));
+        render_pass.set_vertex_buffer(2, self.edge_masks.slice(..));
+        draw_depth(render_pass, &self.obj_model, 0..self.instances.len() as u32);
+
+        for asset in &self.dropped {
+            render_pass.set_vertex_buffer(1, asset.instance_buffer.slice(..));
+            render_pass.set_vertex_buffer(2, self.edges.unmasked.slice(..));
+            draw_depth(render_pass, &asset.model, 0..1);
+        }
+    }
+}
+
+// Geometry only, for passes whose pipeline doesn't read materials
+fn draw_depth<'a>(render_pass: &mut wgpu::RenderPass<'a>, model: &'a model::Model, instances: Range<u32>) {
+    for mesh in &model.meshes {
+        render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.draw_indexed(0..mesh.num_elements, 0, instances.clone());
+    }
+}
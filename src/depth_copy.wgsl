@@ -0,0 +1,18 @@
+// Copies one depth buffer into another the same size by writing each pixel's depth, which works
+// where copying between depth textures doesn't
+
+@group(0) @binding(0)
+var t_depth: texture_depth_2d;
+
+// One triangle that covers the screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    return vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @builtin(frag_depth) f32 {
+    return textureLoad(t_depth, vec2<i32>(position.xy), 0);
+}
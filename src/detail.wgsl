@@ -0,0 +1,49 @@
+// A tiled detail layer over a material's own textures, so large surfaces stay crisp up close
+// without huge textures. Prepended to every shader that reads materials, after `triplanar.wgsl`
+// whose `planar_sample` wraps the tiled coordinates.
+
+@group(0) @binding(5)
+var t_detail: texture_2d<f32>;
+// Tangent space, u in red and v in green
+@group(0) @binding(6)
+var t_detail_normal: texture_2d<f32>;
+
+struct DetailLayer {
+    // Multiplies the albedo
+    albedo: vec3<f32>,
+    // The surface's normal with the detail's bumps added
+    normal: vec3<f32>,
+}
+
+// The detail at `tex_coords * scale`, fading out over the second half of `range` from `eye` so
+// it doesn't pop. `maps` has 1 set when there's a detail albedo texture and 2 for detail normals.
+fn detail_layer(
+    s:              sampler,
+    tex_coords:     vec2<f32>,
+    world_position: vec3<f32>,
+    normal:         vec3<f32>,
+    eye:            vec3<f32>,
+    scale:          f32,
+    range:          f32,
+    strength:       f32,
+    maps:           u32,
+) -> DetailLayer {
+    var layer = DetailLayer(vec3<f32>(1.0), normal);
+
+    let weight = strength * (1.0 - smoothstep(range * 0.5, range, distance(eye, world_position)));
+    let uv     = tex_coords * scale;
+
+    // 0.5 leaves the albedo as it is
+    if ((maps & 1u) != 0u) {
+        layer.albedo = mix(vec3<f32>(1.0), planar_sample(t_detail, s, uv).rgb * 2.0, weight);
+    }
+
+    if ((maps & 2u) != 0u) {
+        let bump  = planar_sample(t_detail_normal, s, uv).rg * 2.0 - 1.0;
+        let frame = tangent_frame(normal, world_position, tex_coords);
+
+        layer.normal = normalize(normal + (frame[0] * bump.x + frame[1] * bump.y) * weight);
+    }
+
+    return layer;
+}
@@ -0,0 +1,296 @@
+// Post effect that blurs what's out of focus the way a lens does, from the depth buffer. Each
+// pixel's circle of confusion grows with its distance from the focus, the scene is gathered over
+// disks at half resolution into a far and a near layer, and both are blended back over the sharp
+// scene. The near layer spreads past the edges of what's in front, so out of focus foreground
+// bleeds over things in focus behind it.
+//
+// The focus can be set directly or eased towards a distance each frame for autofocus.
+
+use std::time::Duration;
+
+use crate::{
+    bind_group,
+    camera::{Camera, DepthMode},
+    crash_report,
+    pass::PostEffect,
+    renderer::GpuContext,
+    uniform::{Uniform, UniformBuffer},
+};
+
+const HALF_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct DofParams {
+    focus_distance: f32,
+    aperture:       f32,
+    max_radius:     f32,
+    near:           f32,
+    far:            f32,
+    reverse_z:      u32,
+    samples:        u32,
+    _padding:       u32,
+}
+
+pub struct DepthOfField {
+    // Distance in front of the camera that's sharp
+    pub focus_distance: f32,
+    // How quickly blur grows away from the focus, as the share of `max_radius` reached at infinity.
+    // Wider apertures blur more.
+    pub aperture:       f32,
+    // Largest circle of confusion, in pixels at the render resolution
+    pub max_radius:     f32,
+    // Taps on each disk
+    pub samples:        u32,
+    // Whether `focus_towards` should be fed each frame
+    pub autofocus:      bool,
+    // How fast autofocus settles, roughly the share of the way covered each second
+    pub focus_speed:    f32,
+    params:             UniformBuffer<DofParams>,
+    depth_layout:       wgpu::BindGroupLayout,
+    coc_pipeline:       wgpu::RenderPipeline,
+    coc_layout:         wgpu::BindGroupLayout,
+    bokeh_pipeline:     wgpu::RenderPipeline,
+    bokeh_layout:       wgpu::BindGroupLayout,
+    composite_pipeline: wgpu::RenderPipeline,
+    composite_layout:   wgpu::BindGroupLayout,
+    sampler:            wgpu::Sampler,
+    // Made in `prepare`, since the depth texture and the scene's size change with the resolution
+    depth_group:        Option<wgpu::BindGroup>,
+    layers:             Option<HalfLayers>,
+}
+
+// The half resolution targets, remade when the scene's size changes
+struct HalfLayers {
+    half:        wgpu::TextureView,
+    far:         wgpu::TextureView,
+    near:        wgpu::TextureView,
+    size:        (u32, u32),
+    bokeh_group: wgpu::BindGroup,
+}
+
+impl DepthOfField {
+    pub fn new(ctx: &GpuContext) -> Self {
+        let device = &ctx.device;
+
+        let depth_layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::FRAGMENT)
+            .depth_texture(wgpu::ShaderStages::FRAGMENT)
+            .sampler(wgpu::ShaderStages::FRAGMENT)
+            .build(device, "dof_bind_group_layout");
+
+        let coc_layout = bind_group::BindGroupLayoutBuilder::new()
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .build(device, "dof_coc_bind_group_layout");
+
+        let bokeh_layout = bind_group::BindGroupLayoutBuilder::new()
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .build(device, "dof_bokeh_bind_group_layout");
+
+        let composite_layout = bind_group::BindGroupLayoutBuilder::new()
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .build(device, "dof_composite_bind_group_layout");
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label:          Some("Depth of Field Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter:     wgpu::FilterMode::Linear,
+            min_filter:     wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            focus_distance:     10.0,
+            aperture:           0.3,
+            max_radius:         12.0,
+            samples:            32,
+            autofocus:          true,
+            focus_speed:        4.0,
+            params:             UniformBuffer::new(device, "Depth of Field Params Buffer"),
+            coc_pipeline:       create_pipeline(device, &[&depth_layout, &coc_layout], &[HALF_FORMAT], include_str!("dof_coc.wgsl"), "Depth of Field CoC"),
+            coc_layout,
+            bokeh_pipeline:     create_pipeline(device, &[&depth_layout, &bokeh_layout], &[HALF_FORMAT, HALF_FORMAT], include_str!("dof_bokeh.wgsl"), "Depth of Field Bokeh"),
+            bokeh_layout,
+            composite_pipeline: create_pipeline(device, &[&depth_layout, &composite_layout], &[ctx.config.format], include_str!("dof.wgsl"), "Depth of Field"),
+            composite_layout,
+            depth_layout,
+            sampler,
+            depth_group:        None,
+            layers:             None,
+        }
+    }
+
+    // Eases the focus towards `distance` in front of the camera, for autofocus
+    pub fn focus_towards(&mut self, distance: f32, dt: Duration) {
+        let t = 1.0 - (-self.focus_speed * dt.as_secs_f32()).exp();
+
+        self.focus_distance += (distance - self.focus_distance) * t;
+    }
+
+    // Called once a frame before the effect is added, with the camera the scene was drawn from
+    pub fn prepare(&mut self, ctx: &GpuContext, camera: &Camera) {
+        let device = &ctx.device;
+        let size   = ctx.render_size();
+        let half   = ((size.width / 2).max(1), (size.height / 2).max(1));
+
+        if self.layers.as_ref().is_none_or(|layers| layers.size != half) {
+            let half_view = create_half_target(device, half, "Depth of Field Half");
+            let far       = create_half_target(device, half, "Depth of Field Far");
+            let near      = create_half_target(device, half, "Depth of Field Near");
+
+            let bokeh_group = bind_group::BindGroupBuilder::new(&self.bokeh_layout)
+                .texture(&half_view)
+                .build(device, "dof_bokeh_bind_group");
+
+            self.layers = Some(HalfLayers { half: half_view, far, near, size: half, bokeh_group });
+        }
+
+        self.params.write(&ctx.queue, &DofParams {
+            focus_distance: self.focus_distance.clamp(camera.znear, camera.zfar),
+            aperture:       self.aperture.clamp(0.0, 1.0),
+            max_radius:     self.max_radius.max(0.0),
+            near:           camera.znear,
+            far:            camera.zfar,
+            reverse_z:      (ctx.depth_mode == DepthMode::ReverseZ) as u32,
+            samples:        self.samples.max(1),
+            _padding:       0,
+        });
+
+        self.depth_group = Some(
+            bind_group::BindGroupBuilder::new(&self.depth_layout)
+                .uniform(self.params.buffer())
+                .texture(&ctx.depth_texture.view)
+                .sampler(&self.sampler)
+                .build(device, "dof_bind_group")
+        );
+    }
+}
+
+impl PostEffect for DepthOfField {
+    fn apply(
+        &self,
+        device:  &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        input:   &wgpu::TextureView,
+        output:  &wgpu::TextureView,
+    ) {
+        let (depth_group, layers) = match (&self.depth_group, &self.layers) {
+            (Some(depth_group), Some(layers)) => (depth_group, layers),
+            _                                 => return,
+        };
+
+        let coc_group = bind_group::BindGroupBuilder::new(&self.coc_layout)
+            .texture(input)
+            .build(device, "dof_coc_bind_group");
+
+        fullscreen_pass(encoder, &[&layers.half], "Depth of Field CoC", &self.coc_pipeline, &[depth_group, &coc_group]);
+        fullscreen_pass(encoder, &[&layers.far, &layers.near], "Depth of Field Bokeh", &self.bokeh_pipeline, &[depth_group, &layers.bokeh_group]);
+
+        let composite_group = bind_group::BindGroupBuilder::new(&self.composite_layout)
+            .texture(input)
+            .texture(&layers.far)
+            .texture(&layers.near)
+            .build(device, "dof_composite_bind_group");
+
+        fullscreen_pass(encoder, &[output], "Depth of Field", &self.composite_pipeline, &[depth_group, &composite_group]);
+    }
+}
+
+// A fullscreen pipeline writing each of `formats` without blending
+fn create_pipeline(
+    device:  &wgpu::Device,
+    layouts: &[&wgpu::BindGroupLayout],
+    formats: &[wgpu::TextureFormat],
+    source:  &str,
+    label:   &str,
+) -> wgpu::RenderPipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label:  Some(label),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(label),
+        bind_group_layouts:   layouts,
+        push_constant_ranges: &[],
+    });
+
+    let targets = formats.iter()
+        .map(|&format| Some(wgpu::ColorTargetState {
+            format,
+            blend:      Some(wgpu::BlendState::REPLACE),
+            write_mask: wgpu::ColorWrites::ALL,
+        }))
+        .collect::<Vec<_>>();
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label:    Some(label),
+        layout:   Some(&pipeline_layout),
+        vertex:   wgpu::VertexState {
+            module:      &shader,
+            entry_point: "vs_main",
+            buffers:     &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module:      &shader,
+            entry_point: "fs_main",
+            targets:     &targets,
+        }),
+        primitive:     wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample:   wgpu::MultisampleState::default(),
+        multiview:     None,
+    })
+}
+
+fn create_half_target(device: &wgpu::Device, size: (u32, u32), label: &str) -> wgpu::TextureView {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label:           Some(label),
+        size:            wgpu::Extent3d { width: size.0, height: size.1, depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count:    1,
+        dimension:       wgpu::TextureDimension::D2,
+        format:          HALF_FORMAT,
+        usage:           wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+    });
+
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+// One triangle over all of each of `views`, replacing what was there. `label` names the pipeline,
+// and the pass after it.
+fn fullscreen_pass(
+    encoder:     &mut wgpu::CommandEncoder,
+    views:       &[&wgpu::TextureView],
+    label:       &str,
+    pipeline:    &wgpu::RenderPipeline,
+    bind_groups: &[&wgpu::BindGroup],
+) {
+    let attachments = views.iter()
+        .map(|&view| Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops:  wgpu::Operations {
+                load:  wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                store: true
+            },
+        }))
+        .collect::<Vec<_>>();
+
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some(&format!("{} Pass", label)),
+        color_attachments:        &attachments,
+        depth_stencil_attachment: None,
+    });
+
+    crash_report::set_pipeline(&mut render_pass, pipeline, label);
+
+    for (index, group) in bind_groups.iter().enumerate() {
+        render_pass.set_bind_group(index as u32, group, &[]);
+    }
+
+    render_pass.draw(0..3, 0..1);
+}
@@ -0,0 +1,69 @@
+// Blends the blurred layers over the sharp scene by each pixel's own circle of confusion, with the
+// near field on top wherever it reaches
+
+struct DofParams {
+    focus_distance: f32,
+    aperture:       f32,
+    max_radius:     f32,
+    near:           f32,
+    far:            f32,
+    reverse_z:      u32,
+    samples:        u32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: DofParams;
+@group(0) @binding(1)
+var t_depth: texture_depth_2d;
+@group(0) @binding(2)
+var s_linear: sampler;
+
+@group(1) @binding(0)
+var t_scene: texture_2d<f32>;
+@group(1) @binding(1)
+var t_far: texture_2d<f32>;
+@group(1) @binding(2)
+var t_near: texture_2d<f32>;
+
+// One triangle that covers the screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    return vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
+
+// Also in dof_coc.wgsl
+fn linear_depth(depth: f32) -> f32 {
+    let near = params.near;
+    let far  = params.far;
+
+    if (params.reverse_z != 0u) {
+        return near * far / (near + depth * (far - near));
+    }
+
+    return near * far / (far - depth * (far - near));
+}
+
+// Also in dof_coc.wgsl
+fn circle_of_confusion(distance: f32) -> f32 {
+    return clamp(params.aperture * (distance - params.focus_distance) / distance, -1.0, 1.0) * params.max_radius;
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let coord = vec2<i32>(position.xy);
+    let uv    = position.xy / vec2<f32>(textureDimensions(t_scene));
+    let sharp = textureLoad(t_scene, coord, 0);
+    let coc   = circle_of_confusion(linear_depth(textureLoad(t_depth, coord, 0)));
+
+    let far  = textureSampleLevel(t_far, s_linear, uv, 0.0);
+    let near = textureSampleLevel(t_near, s_linear, uv, 0.0);
+
+    // Under a pixel or so of blur the sharp scene is the better picture
+    var color = mix(sharp.rgb, far.rgb, smoothstep(1.0, 3.0, coc));
+
+    color = mix(color, near.rgb, max(near.a, smoothstep(1.0, 3.0, -coc)));
+
+    return vec4<f32>(color, sharp.a);
+}
@@ -0,0 +1,80 @@
+// Gathers the half resolution scene over a disk into two layers: the far field, blurred only as
+// much as both the pixel and each sample allow so sharp things don't pick up the blurry background
+// behind them, and the near field, spreading out of focus foreground over whatever it's in front
+// of. The near layer's alpha is how much of the disk it covers.
+
+struct DofParams {
+    focus_distance: f32,
+    aperture:       f32,
+    max_radius:     f32,
+    near:           f32,
+    far:            f32,
+    reverse_z:      u32,
+    samples:        u32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: DofParams;
+@group(0) @binding(1)
+var t_depth: texture_depth_2d;
+@group(0) @binding(2)
+var s_linear: sampler;
+
+// Color, and the circle of confusion in half resolution pixels
+@group(1) @binding(0)
+var t_half: texture_2d<f32>;
+
+struct FragmentOutput {
+    @location(0) far:  vec4<f32>,
+    @location(1) near: vec4<f32>,
+}
+
+// One triangle that covers the screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    return vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
+
+// Whether a circle of confusion `coc` reaches `distance` away, softened over a pixel
+fn covers(coc: f32, distance: f32) -> f32 {
+    return clamp(coc - distance + 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> FragmentOutput {
+    let size   = vec2<f32>(textureDimensions(t_half));
+    let center = textureLoad(t_half, vec2<i32>(position.xy), 0);
+    let radius = params.max_radius * 0.5;
+
+    var far_sum  = vec3<f32>(0.0);
+    var far_w    = 0.0;
+    var near_sum = vec3<f32>(0.0);
+    var near_w   = 0.0;
+
+    let samples = max(params.samples, 1u);
+
+    // Golden angle spiral, which spreads any number of samples evenly over the disk
+    for (var i = 0u; i < samples; i = i + 1u) {
+        let r      = sqrt((f32(i) + 0.5) / f32(samples)) * radius;
+        let theta  = f32(i) * 2.399963;
+        let offset = vec2<f32>(cos(theta), sin(theta)) * r;
+        let s      = textureSampleLevel(t_half, s_linear, (position.xy + offset) / size, 0.0);
+
+        let far  = covers(min(max(s.a, 0.0), max(center.a, 0.0)), r);
+        let near = covers(-s.a, r);
+
+        far_sum  = far_sum + s.rgb * far;
+        far_w    = far_w + far;
+        near_sum = near_sum + s.rgb * near;
+        near_w   = near_w + near;
+    }
+
+    var out: FragmentOutput;
+
+    out.far  = vec4<f32>(select(center.rgb, far_sum / far_w, far_w > 0.0), 1.0);
+    out.near = vec4<f32>(select(center.rgb, near_sum / near_w, near_w > 0.0), near_w / f32(samples));
+
+    return out;
+}
@@ -0,0 +1,67 @@
+// Halves the scene and works out each pixel's circle of confusion, the disk a point at its depth
+// spreads over when the lens focuses elsewhere. Negative in front of the focus, positive behind.
+
+struct DofParams {
+    focus_distance: f32,
+    aperture:       f32,
+    max_radius:     f32,
+    near:           f32,
+    far:            f32,
+    reverse_z:      u32,
+    samples:        u32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: DofParams;
+@group(0) @binding(1)
+var t_depth: texture_depth_2d;
+@group(0) @binding(2)
+var s_linear: sampler;
+
+@group(1) @binding(0)
+var t_scene: texture_2d<f32>;
+
+// One triangle that covers the screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    return vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
+
+// Also in dof.wgsl
+fn linear_depth(depth: f32) -> f32 {
+    let near = params.near;
+    let far  = params.far;
+
+    if (params.reverse_z != 0u) {
+        return near * far / (near + depth * (far - near));
+    }
+
+    return near * far / (far - depth * (far - near));
+}
+
+// In full resolution pixels. Also in dof.wgsl.
+fn circle_of_confusion(distance: f32) -> f32 {
+    return clamp(params.aperture * (distance - params.focus_distance) / distance, -1.0, 1.0) * params.max_radius;
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let size  = vec2<i32>(textureDimensions(t_depth));
+    let coord = vec2<i32>(position.xy) * 2;
+
+    // Bilinear filtering averages the four pixels under this one
+    let color = textureSampleLevel(t_scene, s_linear, position.xy * 2.0 / vec2<f32>(size), 0.0);
+
+    // The nearest of the four decides, so foreground edges keep spreading over what's behind them
+    var distance = params.far;
+
+    for (var i = 0; i < 4; i = i + 1) {
+        let texel = min(coord + vec2<i32>(i & 1, i >> 1u), size - 1);
+
+        distance = min(distance, linear_depth(textureLoad(t_depth, texel, 0)));
+    }
+
+    return vec4<f32>(color.rgb, circle_of_confusion(distance) * 0.5);
+}
@@ -0,0 +1,295 @@
+// Edge detection in screen space, drawn over the scene in the post layer. Casters are drawn into a
+// small geometry buffer of their own, like the velocity buffer, holding world normals and a mask
+// per instance; Sobel filters over it and the depth then find silhouettes and creases.
+//
+// Masks are bits set per instance. Wherever one changes, the object's outline is drawn in that
+// mask's color, so the editor's selection and stylized objects each get their own silhouettes.
+// Ordinary depth and normal edges are drawn too when `all_edges` is set.
+
+use learn_wgpu_derive::VertexLayout;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    bind_group,
+    camera::{Camera, DepthMode},
+    crash_report,
+    model::InstanceRaw,
+    pass::{Drawable, RenderLayer},
+    renderer::{self, GpuContext},
+    texture::Texture,
+    uniform::{Uniform, UniformBuffer},
+};
+
+// Mask bits, which pick the colors in `mask_colors`. Also in edges.wgsl.
+pub const MASK_SELECTED: u32 = 1;
+pub const MASK_STYLIZED: u32 = 2;
+
+const GEOMETRY_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct EdgeCamera {
+    view_proj: [[f32; 4]; 4],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct EdgeParams {
+    edge_color:       [f32; 4],
+    mask_colors:      [[f32; 4]; 2],
+    depth_threshold:  f32,
+    normal_threshold: f32,
+    near:             f32,
+    far:              f32,
+    reverse_z:        u32,
+    all_edges:        u32,
+    _padding:         [u32; 2],
+}
+
+// Mask bits of an instance, bound next to its `InstanceRaw`
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+#[vertex(instance)]
+pub struct EdgeMaskRaw {
+    #[location(14)]
+    pub mask: u32,
+}
+
+// Anything drawn into the geometry buffer. The pipeline and camera at @group(0) are set beforehand,
+// and vertex buffers are laid out as in `edges_geometry.wgsl`: the mesh at 0, instances at 1 and
+// their masks at 2.
+pub trait EdgeCaster {
+    fn draw_edges<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>);
+}
+
+pub struct EdgeDetection {
+    // Color of depth and normal edges, drawn when `all_edges` is set
+    pub edge_color:       [f32; 4],
+    // Outline color for each mask bit, selection first
+    pub mask_colors:      [[f32; 4]; 2],
+    pub all_edges:        bool,
+    // Sobel response needed for an edge: depth relative to the depth at the pixel, normals absolute
+    pub depth_threshold:  f32,
+    pub normal_threshold: f32,
+    // A single clear mask, for casters drawing one instance that's never outlined
+    pub unmasked:         wgpu::Buffer,
+    geometry_pipeline:    wgpu::RenderPipeline,
+    camera:               UniformBuffer<EdgeCamera>,
+    camera_group:         wgpu::BindGroup,
+    geometry:             wgpu::TextureView,
+    depth:                Texture,
+    size:                 (u32, u32),
+    params:               UniformBuffer<EdgeParams>,
+    edge_pipeline:        wgpu::RenderPipeline,
+    edge_layout:          wgpu::BindGroupLayout,
+    edge_group:           wgpu::BindGroup,
+}
+
+impl EdgeDetection {
+    // `mesh_layout` is how the casters' meshes are stored, with the position at location 0 and the
+    // normal at 2
+    pub fn new(ctx: &GpuContext, mesh_layout: wgpu::VertexBufferLayout) -> Self {
+        let device = &ctx.device;
+        let size   = ctx.render_size();
+        let size   = (size.width.max(1), size.height.max(1));
+
+        let camera_layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::VERTEX)
+            .build(device, "edge_geometry_bind_group_layout");
+
+        let camera = UniformBuffer::new(device, "Edge Geometry Camera Buffer");
+
+        let camera_group = bind_group::BindGroupBuilder::new(&camera_layout)
+            .uniform(camera.buffer())
+            .build(device, "edge_geometry_bind_group");
+
+        let geometry_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Edge Geometry Shader"),
+            source: wgpu::ShaderSource::Wgsl(concat!(include_str!("instance.wgsl"), include_str!("edges_geometry.wgsl")).into()),
+        });
+
+        let geometry_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Edge Geometry Pipeline Layout"),
+            bind_group_layouts:   &[&camera_layout],
+            push_constant_ranges: &[],
+        });
+
+        let geometry_pipeline = renderer::create_render_pipeline(
+            device,
+            &geometry_pipeline_layout,
+            GEOMETRY_FORMAT,
+            Some(renderer::depth_state(Texture::DEPTH_FORMAT, ctx.depth_mode.compare(), true)),
+            &[mesh_layout, InstanceRaw::layout(), EdgeMaskRaw::layout()],
+            &geometry_shader,
+            "Edge Geometry Pipeline",
+        );
+
+        let edge_layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::FRAGMENT)
+            .unfilterable_texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .depth_texture(wgpu::ShaderStages::FRAGMENT)
+            .build(device, "edge_bind_group_layout");
+
+        let edge_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Edge Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("edges.wgsl").into()),
+        });
+
+        let edge_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Edge Pipeline Layout"),
+            bind_group_layouts:   &[&edge_layout],
+            push_constant_ranges: &[],
+        });
+
+        // Blended over the scene in the post layer, which has no depth attachment
+        let edge_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label:    Some("Edge Pipeline"),
+            layout:   Some(&edge_pipeline_layout),
+            vertex:   wgpu::VertexState {
+                module:      &edge_shader,
+                entry_point: "vs_main",
+                buffers:     &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module:      &edge_shader,
+                entry_point: "fs_main",
+                targets:     &[Some(wgpu::ColorTargetState {
+                    format:     ctx.config.format,
+                    blend:      Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive:     wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample:   wgpu::MultisampleState::default(),
+            multiview:     None,
+        });
+
+        let unmasked = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label:    Some("Unmasked Edge Mask Buffer"),
+                contents: bytemuck::cast_slice(&[EdgeMaskRaw { mask: 0 }]),
+                usage:    wgpu::BufferUsages::VERTEX,
+            }
+        );
+
+        let params     = UniformBuffer::new(device, "Edge Params Buffer");
+        let geometry   = create_geometry_target(device, size);
+        let depth      = Texture::create_depth_texture(device, size.0, size.1, "Edge Depth Texture");
+        let edge_group = create_edge_group(device, &edge_layout, &params, &geometry, &depth);
+
+        Self {
+            edge_color:       [0.0, 0.0, 0.0, 1.0],
+            mask_colors:      [[1.0, 0.6, 0.1, 1.0], [0.1, 0.1, 0.1, 1.0]],
+            all_edges:        false,
+            depth_threshold:  0.3,
+            normal_threshold: 1.0,
+            unmasked,
+            geometry_pipeline,
+            camera,
+            camera_group,
+            geometry,
+            depth,
+            size,
+            params,
+            edge_pipeline,
+            edge_layout,
+            edge_group,
+        }
+    }
+
+    // Called once a frame before `render`, with the camera the scene is drawn from. Follows the
+    // render resolution.
+    pub fn prepare(&mut self, ctx: &GpuContext, camera: &Camera) {
+        let size = ctx.render_size();
+        let size = (size.width.max(1), size.height.max(1));
+
+        if size != self.size {
+            self.geometry   = create_geometry_target(&ctx.device, size);
+            self.depth      = Texture::create_depth_texture(&ctx.device, size.0, size.1, "Edge Depth Texture");
+            self.edge_group = create_edge_group(&ctx.device, &self.edge_layout, &self.params, &self.geometry, &self.depth);
+            self.size       = size;
+        }
+
+        self.camera.write(&ctx.queue, &EdgeCamera {
+            view_proj: camera.build_view_projections_matrix().into(),
+        });
+
+        self.params.write(&ctx.queue, &EdgeParams {
+            edge_color:       self.edge_color,
+            mask_colors:      self.mask_colors,
+            depth_threshold:  self.depth_threshold,
+            normal_threshold: self.normal_threshold,
+            near:             camera.znear,
+            far:              camera.zfar,
+            reverse_z:        (ctx.depth_mode == DepthMode::ReverseZ) as u32,
+            all_edges:        self.all_edges as u32,
+            _padding:         [0; 2],
+        });
+    }
+
+    // Clears the geometry buffer and draws the casters into it
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, caster: &dyn EdgeCaster, depth_mode: DepthMode) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Edge Geometry Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view:           &self.geometry,
+                resolve_target: None,
+                ops:  wgpu::Operations {
+                    load:  wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: true
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view:       &self.depth.view,
+                depth_ops:  Some(wgpu::Operations {
+                    load:  wgpu::LoadOp::Clear(depth_mode.clear_depth()),
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+
+        crash_report::set_pipeline(&mut render_pass, &self.geometry_pipeline, "Edge Geometry Pipeline");
+        render_pass.set_bind_group(0, &self.camera_group, &[]);
+        caster.draw_edges(&mut render_pass);
+    }
+}
+
+impl Drawable for EdgeDetection {
+    fn draw<'a>(&'a self, layer: RenderLayer, render_pass: &mut wgpu::RenderPass<'a>) {
+        if layer == RenderLayer::Post {
+            crash_report::set_pipeline(render_pass, &self.edge_pipeline, "Edge Pipeline");
+            render_pass.set_bind_group(0, &self.edge_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+}
+
+fn create_geometry_target(device: &wgpu::Device, size: (u32, u32)) -> wgpu::TextureView {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label:           Some("Edge Geometry Buffer"),
+        size:            wgpu::Extent3d { width: size.0, height: size.1, depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count:    1,
+        dimension:       wgpu::TextureDimension::D2,
+        format:          GEOMETRY_FORMAT,
+        usage:           wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+    });
+
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+fn create_edge_group(
+    device:   &wgpu::Device,
+    layout:   &wgpu::BindGroupLayout,
+    params:   &UniformBuffer<EdgeParams>,
+    geometry: &wgpu::TextureView,
+    depth:    &Texture,
+) -> wgpu::BindGroup {
+    bind_group::BindGroupBuilder::new(layout)
+        .uniform(params.buffer())
+        .texture(geometry)
+        .texture(&depth.view)
+        .build(device, "edge_bind_group")
+}
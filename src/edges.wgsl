@@ -0,0 +1,98 @@
+// Finds edges with Sobel filters over linear depth, normals and the selection masks, and draws
+// them over the scene. Mask edges outline whole objects in their mask's color; depth and normal
+// edges are only drawn when every edge is asked for, for stylized looks.
+
+struct EdgeParams {
+    edge_color:       vec4<f32>,
+    mask_colors:      array<vec4<f32>, 2>,
+    depth_threshold:  f32,
+    normal_threshold: f32,
+    near:             f32,
+    far:              f32,
+    reverse_z:        u32,
+    all_edges:        u32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: EdgeParams;
+// World normal, and the mask in alpha
+@group(0) @binding(1)
+var t_geometry: texture_2d<f32>;
+@group(0) @binding(2)
+var t_depth: texture_depth_2d;
+
+// One triangle that covers the screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    return vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
+
+fn linear_depth(depth: f32) -> f32 {
+    let near = params.near;
+    let far  = params.far;
+
+    if (params.reverse_z != 0u) {
+        return near * far / (near + depth * (far - near));
+    }
+
+    return near * far / (far - depth * (far - near));
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let size  = vec2<i32>(textureDimensions(t_depth));
+    let coord = vec2<i32>(position.xy);
+
+    // Sobel weights for the horizontal gradient; the vertical one is the same turned on its side
+    var weights = array<f32, 9>(-1.0, 0.0, 1.0, -2.0, 0.0, 2.0, -1.0, 0.0, 1.0);
+
+    var depth_x  = 0.0;
+    var depth_y  = 0.0;
+    var normal_x = vec3<f32>(0.0);
+    var normal_y = vec3<f32>(0.0);
+    var mask_x   = vec2<f32>(0.0);
+    var mask_y   = vec2<f32>(0.0);
+
+    for (var i = 0; i < 9; i = i + 1) {
+        let offset = vec2<i32>(i % 3 - 1, i / 3 - 1);
+        let texel  = clamp(coord + offset, vec2<i32>(0), size - 1);
+        let wx     = weights[i];
+        let wy     = weights[(i % 3) * 3 + i / 3];
+
+        let depth    = linear_depth(textureLoad(t_depth, texel, 0));
+        let geometry = textureLoad(t_geometry, texel, 0);
+        let mask     = u32(geometry.a + 0.5);
+        let bits     = vec2<f32>(f32(mask & 1u), f32((mask >> 1u) & 1u));
+
+        depth_x  = depth_x + depth * wx;
+        depth_y  = depth_y + depth * wy;
+        normal_x = normal_x + geometry.xyz * wx;
+        normal_y = normal_y + geometry.xyz * wy;
+        mask_x   = mask_x + bits * wx;
+        mask_y   = mask_y + bits * wy;
+    }
+
+    // Masks first, so a selected object's outline wins over its ordinary edges
+    for (var b = 0; b < 2; b = b + 1) {
+        if (abs(mask_x[b]) + abs(mask_y[b]) > 0.0) {
+            return params.mask_colors[b];
+        }
+    }
+
+    if (params.all_edges == 0u) {
+        discard;
+    }
+
+    // Relative to the depth here, so distant edges aren't drowned out by their own depth range
+    let center      = linear_depth(textureLoad(t_depth, coord, 0));
+    let depth_edge  = length(vec2<f32>(depth_x, depth_y)) / center;
+    let normal_edge = length(normal_x) + length(normal_y);
+
+    if (depth_edge <= params.depth_threshold && normal_edge <= params.normal_threshold) {
+        discard;
+    }
+
+    return params.edge_color;
+}
@@ -0,0 +1,35 @@
+// Draws the normals and selection masks edge detection looks for changes in
+
+@group(0) @binding(0)
+var<uniform> view_proj: mat4x4<f32>;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_normal:        vec3<f32>,
+    @location(1) @interpolate(flat) mask: u32,
+}
+
+@vertex
+fn vs_main(
+    @location(0) position: vec3<f32>,
+    @location(2) normal:   vec3<f32>,
+    // From `EdgeMaskRaw`, bound next to the instance
+    @location(14) mask:    u32,
+    instance:              InstanceInput,
+) -> VertexOutput {
+    let model_matrix = instance_model_matrix(instance);
+
+    var out: VertexOutput;
+
+    out.clip_position = view_proj * model_matrix * vec4<f32>(position, 1.0);
+    out.world_normal  = normal_to_world(model_matrix, normal);
+    out.mask          = mask;
+
+    return out;
+}
+
+// The mask goes in alpha as a float, which holds small integers exactly
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(normalize(in.world_normal), f32(in.mask));
+}
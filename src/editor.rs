@@ -0,0 +1,799 @@
+// Editor mode, toggled with F1: click an instance to select it, then drag its gizmo's handles to
+// move, turn or scale it along one axis, or drag anywhere else to do so freely. G, R and T pick
+// which, and L switches the handles between world and local axes. I opens an inspector for the
+// selected instance's material. Ctrl+S saves every instance's transform to scene.txt and Ctrl+O
+// loads them back. Split-screen players move with the same letters, so while there are any only
+// F1 and the mouse reach the editor.
+//
+// With the `egui` feature the inspector is an egui window, see `Editor::show`, with a slider for
+// each value, a pick of the model's materials, the selected instance's transform and buttons to
+// save and load the scene. Without it the inspector is a panel of the crate's own `Ui`, where Up
+// and Down pick a value, Left and Right change it and Tab moves on to the model's next material,
+// with names and numbers beside the sliders when there's a font to write them in. Materials are
+// shared by every instance of a model, so editing one changes them all, and they aren't part of
+// the saved scene.
+
+use cgmath::prelude::*;
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::*,
+};
+
+use crate::{
+    bounds::{Aabb, Bvh, Ray},
+    camera::Camera,
+    debug_draw::DebugDraw,
+    model::{Instance, Material, MaterialParams},
+    ui::{Anchor, BitmapFont, Ui, UiId, UiKind, UiNode},
+};
+
+const TRANSLATE_SPEED: f32 = 0.005;
+const ROTATE_SPEED:    f32 = 0.5; // degrees per pixel
+const SCALE_SPEED:     f32 = 0.01;
+const MIN_SCALE:       f32 = 0.05;
+
+// Length of the gizmo's handles as a fraction of its distance from the camera, which keeps it
+// the same size on screen
+const GIZMO_SIZE:       f32 = 0.15;
+// How close the cursor has to be to a handle to grab it, as a fraction of the handle's length
+const GIZMO_TOLERANCE:  f32 = 0.08;
+const GIZMO_COLORS:     [[f32; 3]; 3] = [[1.0, 0.2, 0.2], [0.2, 1.0, 0.2], [0.3, 0.3, 1.0]];
+const GIZMO_HIGHLIGHT:  [f32; 3] = [1.0, 1.0, 0.0];
+
+const SCENE_FILE: &str = "scene.txt";
+
+// In the UI's reference pixels
+const INSPECTOR_ROW:        f32 = 18.0;
+const INSPECTOR_BAR:        [f32; 2] = [160.0, 10.0];
+const INSPECTOR_PADDING:    f32 = 8.0;
+const INSPECTOR_BACKGROUND: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+const INSPECTOR_TRACK:      [f32; 4] = [0.15, 0.15, 0.2, 1.0];
+const INSPECTOR_FILL:       [f32; 4] = [0.2, 0.5, 1.0, 1.0];
+const INSPECTOR_SELECTED:   [f32; 4] = [1.0, 0.8, 0.2, 1.0];
+// Presses of Left or Right from one end of a slider to the other
+const INSPECTOR_STEPS:      f32 = 50.0;
+
+// A material value the inspector edits, and the range its slider covers
+struct InspectorField {
+    name:  &'static str,
+    range: [f32; 2],
+    value: fn(&mut MaterialParams) -> &mut f32,
+}
+
+const INSPECTOR_FIELDS: [InspectorField; 12] = [
+    InspectorField { name: "Tint red",            range: [0.0, 1.0],  value: |params| &mut params.tint[0] },
+    InspectorField { name: "Tint green",          range: [0.0, 1.0],  value: |params| &mut params.tint[1] },
+    InspectorField { name: "Tint blue",           range: [0.0, 1.0],  value: |params| &mut params.tint[2] },
+    InspectorField { name: "Roughness",           range: [0.0, 1.0],  value: |params| &mut params.roughness },
+    InspectorField { name: "Specular",            range: [0.0, 1.0],  value: |params| &mut params.specular },
+    InspectorField { name: "Anisotropy",          range: [0.0, 1.0],  value: |params| &mut params.anisotropy },
+    InspectorField { name: "Clearcoat",           range: [0.0, 1.0],  value: |params| &mut params.clearcoat },
+    InspectorField { name: "Clearcoat roughness", range: [0.0, 1.0],  value: |params| &mut params.clearcoat_roughness },
+    InspectorField { name: "Emission",            range: [0.0, 10.0], value: |params| &mut params.emissive_intensity },
+    InspectorField { name: "Alpha cutoff",        range: [0.0, 1.0],  value: |params| &mut params.alpha_cutoff },
+    InspectorField { name: "Transmittance",       range: [0.0, 1.0],  value: |params| &mut params.transmittance },
+    InspectorField { name: "IOR",                 range: [1.0, 3.0],  value: |params| &mut params.ior },
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GizmoMode {
+    Translate,
+    Rotate,
+    Scale,
+}
+
+// Which axes the gizmo's handles follow. Scaling is always along the instance's own axes, since
+// that's all its scale can express.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GizmoSpace {
+    World,
+    Local,
+}
+
+// A handle being dragged, with how the instance was when the drag started
+struct GizmoDrag {
+    axis:     usize,
+    // Where on the handle the cursor grabbed it: distance along the axis for moving and scaling,
+    // the direction from the center for rotating
+    grab:     cgmath::Vector3<f32>,
+    position: cgmath::Vector3<f32>,
+    rotation: cgmath::Quaternion<f32>,
+    scale:    cgmath::Vector3<f32>,
+}
+
+// One of the inspector's rows: its slider's fill, and its text if there's a font
+struct InspectorRow {
+    fill:  UiId,
+    label: Option<UiId>,
+}
+
+// Sliders for one of a model's materials, in a panel in the top right
+pub struct MaterialInspector {
+    open:      bool,
+    // Of the model's materials, wrapped around to however many it has
+    material:  usize,
+    field:     usize,
+    // Laid out since the last change
+    shown:     bool,
+    font:      Option<BitmapFont>,
+    text_size: f32,
+    root:      UiId,
+    rows:      Vec<InspectorRow>,
+}
+
+impl MaterialInspector {
+    pub fn new(ui: &mut Ui, font: Option<BitmapFont>, text_size: f32) -> Self {
+        // Room for the longest name and its value, when they're written
+        let label_width = font.map_or(0.0, |font| {
+            ui.text_size(&font, "Clearcoat roughness 10.00", text_size)[0] + INSPECTOR_PADDING
+        });
+        let size = [
+            label_width + INSPECTOR_BAR[0] + 2.0 * INSPECTOR_PADDING,
+            INSPECTOR_FIELDS.len() as f32 * INSPECTOR_ROW + 2.0 * INSPECTOR_PADDING,
+        ];
+
+        let root = ui.add(UiNode::new(Anchor::TopRight, [-16.0, 16.0], size, UiKind::Panel { color: INSPECTOR_BACKGROUND }));
+        let rows = (0..INSPECTOR_FIELDS.len()).map(|i| {
+            let top   = INSPECTOR_PADDING + i as f32 * INSPECTOR_ROW;
+            let label = font.map(|font| ui.add(UiNode::new(Anchor::TopLeft, [INSPECTOR_PADDING, top], [0.0, 0.0], UiKind::Text {
+                font,
+                text:  String::new(),
+                size:  text_size,
+                color: [1.0; 4],
+            }).with_parent(root)));
+            let track = ui.add(UiNode::new(
+                Anchor::TopLeft,
+                [INSPECTOR_PADDING + label_width, top + (INSPECTOR_ROW - INSPECTOR_BAR[1]) / 2.0],
+                INSPECTOR_BAR,
+                UiKind::Panel { color: INSPECTOR_TRACK },
+            ).with_parent(root));
+            let fill  = ui.add(UiNode::new(Anchor::TopLeft, [0.0, 0.0], [0.0, INSPECTOR_BAR[1]], UiKind::Panel { color: INSPECTOR_FILL }).with_parent(track));
+
+            InspectorRow { fill, label }
+        }).collect();
+
+        ui.node_mut(root).unwrap().visible = false;
+
+        Self {
+            open:      false,
+            material:  0,
+            field:     0,
+            shown:     true,
+            font,
+            text_size,
+            root,
+            rows,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn set_open(&mut self, open: bool) {
+        self.open  = open;
+        self.shown = false;
+    }
+
+    // Up and Down pick a value, Left and Right change it and Tab moves on to the next material.
+    // Returns true if the event was used.
+    pub fn input(&mut self, event: &WindowEvent, materials: &mut [Material]) -> bool {
+        let keycode = match event {
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(keycode),
+                    ..
+                },
+                ..
+            } if self.open => *keycode,
+            _ => return false,
+        };
+
+        let fields = INSPECTOR_FIELDS.len();
+
+        match keycode {
+            VirtualKeyCode::Up    => self.field = (self.field + fields - 1) % fields,
+            VirtualKeyCode::Down  => self.field = (self.field + 1) % fields,
+            VirtualKeyCode::Tab   => self.material += 1,
+            VirtualKeyCode::Left  => self.step(materials, -1.0),
+            VirtualKeyCode::Right => self.step(materials, 1.0),
+            _                     => return false,
+        }
+
+        self.shown = false;
+        true
+    }
+
+    fn step(&self, materials: &mut [Material], direction: f32) {
+        if materials.is_empty() {
+            return;
+        }
+
+        let material   = &mut materials[self.material % materials.len()];
+        let field      = &INSPECTOR_FIELDS[self.field];
+        let [min, max] = field.range;
+        let value      = (field.value)(&mut material.params);
+
+        *value = (*value + direction * (max - min) / INSPECTOR_STEPS).clamp(min, max);
+        log::info!("{}: {} {:.2}", material.name, field.name, *value);
+    }
+
+    // Lays the panel out again after a change, since nothing else moves it
+    pub fn update(&mut self, ui: &mut Ui, materials: &[Material]) {
+        if self.shown {
+            return;
+        }
+
+        self.shown = true;
+
+        let material = match materials.len() {
+            0 => None,
+            n => Some(&materials[self.material % n]),
+        };
+
+        // egui draws its own
+        if let Some(root) = ui.node_mut(self.root) {
+            root.visible = self.open && material.is_some() && !cfg!(feature = "egui");
+        }
+
+        let mut params = match material {
+            Some(material) if self.open => material.params,
+            _                           => return,
+        };
+
+        for (i, (field, row)) in INSPECTOR_FIELDS.iter().zip(&self.rows).enumerate() {
+            let [min, max] = field.range;
+            let value      = *(field.value)(&mut params);
+
+            if let Some(fill) = ui.node_mut(row.fill) {
+                fill.size[0] = INSPECTOR_BAR[0] * ((value - min) / (max - min)).clamp(0.0, 1.0);
+                fill.kind    = UiKind::Panel { color: if i == self.field { INSPECTOR_SELECTED } else { INSPECTOR_FILL } };
+            }
+
+            if let (Some(label), Some(font)) = (row.label, self.font) {
+                let text     = format!("{} {:.2}", field.name, value);
+                let measured = ui.text_size(&font, &text, self.text_size);
+
+                if let Some(UiNode { kind: UiKind::Text { text: shown, .. }, size, .. }) = ui.node_mut(label) {
+                    *shown = text;
+                    *size  = measured;
+                }
+            }
+        }
+    }
+}
+
+pub struct Editor {
+    pub enabled:  bool,
+    pub selected: Option<usize>,
+    pub mode:     GizmoMode,
+    pub space:    GizmoSpace,
+    // Set whenever an instance is changed so its bounds can be recomputed
+    pub dirty:    bool,
+    cursor:       PhysicalPosition<f64>,
+    dragging:     bool,
+    ctrl_held:    bool,
+    // The gizmo axis under the cursor
+    hovered:      Option<usize>,
+    // Set while a gizmo handle is dragged, otherwise dragging moves the instance freely
+    gizmo_drag:   Option<GizmoDrag>,
+    inspector:    MaterialInspector,
+}
+
+impl Editor {
+    // The inspector is added to `ui`, and writes names and values in `font` if there is one
+    pub fn new(ui: &mut Ui, font: Option<BitmapFont>, text_size: f32) -> Self {
+        Self {
+            enabled:    false,
+            selected:   None,
+            mode:       GizmoMode::Translate,
+            space:      GizmoSpace::World,
+            dirty:      false,
+            cursor:     PhysicalPosition::new(0.0, 0.0),
+            dragging:   false,
+            ctrl_held:  false,
+            hovered:    None,
+            gizmo_drag: None,
+            inspector:  MaterialInspector::new(ui, font, text_size),
+        }
+    }
+
+    // Shows the inspector's changes, before `ui` is prepared
+    pub fn update(&mut self, ui: &mut Ui, materials: &[Material]) {
+        self.inspector.update(ui, materials);
+    }
+
+    // `split_screen` is set while there are split-screen players, whose keys the editor's letters
+    // would otherwise share
+    #[allow(clippy::too_many_arguments)]
+    pub fn process_events(
+        &mut self,
+        event:        &WindowEvent,
+        camera:       &Camera,
+        size:         PhysicalSize<u32>,
+        instances:    &mut [Instance],
+        materials:    &mut [Material],
+        bvh:          &Bvh,
+        split_screen: bool,
+    ) -> bool {
+        if let WindowEvent::ModifiersChanged(modifiers) = event {
+            self.ctrl_held = modifiers.ctrl();
+            return false;
+        }
+
+        if let WindowEvent::KeyboardInput {
+            input: KeyboardInput {
+                state: ElementState::Pressed,
+                virtual_keycode: Some(VirtualKeyCode::F1),
+                ..
+            },
+            ..
+        } = event {
+            self.enabled    = !self.enabled;
+            self.dragging   = false;
+            self.gizmo_drag = None;
+            self.inspector.set_open(false);
+            log::info!("Editor mode {}", if self.enabled { "enabled" } else { "disabled" });
+            return true;
+        }
+
+        if !self.enabled {
+            return false;
+        }
+
+        if !cfg!(feature = "egui") && self.inspector.input(event, materials) {
+            return true;
+        }
+
+        match event {
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(keycode),
+                    ..
+                },
+                ..
+            } if !split_screen => {
+                match keycode {
+                    VirtualKeyCode::G => self.mode = GizmoMode::Translate,
+                    VirtualKeyCode::R => self.mode = GizmoMode::Rotate,
+                    VirtualKeyCode::T => self.mode = GizmoMode::Scale,
+                    VirtualKeyCode::L => {
+                        self.space = match self.space {
+                            GizmoSpace::World => GizmoSpace::Local,
+                            GizmoSpace::Local => GizmoSpace::World,
+                        };
+                        log::info!("Gizmo in {:?} space", self.space);
+                    }
+                    VirtualKeyCode::I if self.selected.is_some() => self.inspector.set_open(!self.inspector.is_open()),
+                    VirtualKeyCode::S if self.ctrl_held => self.save(instances),
+                    VirtualKeyCode::O if self.ctrl_held => self.load(instances),
+                    _ => return false,
+                }
+                true
+            }
+            WindowEvent::MouseInput { state, button: MouseButton::Left, .. } => {
+                self.dragging   = *state == ElementState::Pressed;
+                self.gizmo_drag = None;
+
+                if !self.dragging {
+                    return true;
+                }
+
+                // Handles are in front of whatever else the cursor is over
+                let ray      = camera.screen_ray(self.cursor.x, self.cursor.y, size.width, size.height);
+                let selected = self.selected.filter(|&i| i < instances.len());
+
+                if let (Some(ray), Some(i)) = (ray, selected) {
+                    if let Some(axis) = self.gizmo_hit(camera, &instances[i], &ray) {
+                        self.gizmo_drag = self.grab(camera, &instances[i], &ray, axis);
+                        return true;
+                    }
+                }
+
+                self.selected = pick(camera, size, self.cursor, bvh);
+
+                // Nothing to inspect
+                if self.selected.is_none() && self.inspector.is_open() {
+                    self.inspector.set_open(false);
+                }
+
+                true
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                let dx = (position.x - self.cursor.x) as f32;
+                let dy = (position.y - self.cursor.y) as f32;
+
+                self.cursor = *position;
+
+                let instance = match self.selected {
+                    Some(i) if i < instances.len() => &mut instances[i],
+                    _                              => return false,
+                };
+                let ray = camera.screen_ray(position.x, position.y, size.width, size.height);
+
+                if !self.dragging {
+                    self.hovered = ray.and_then(|ray| self.gizmo_hit(camera, instance, &ray));
+                    return false;
+                }
+
+                match (&self.gizmo_drag, ray) {
+                    (Some(drag), Some(ray)) => self.drag_handle(camera, drag, instance, &ray),
+                    (Some(_), None)         => {}
+                    (None, _)               => self.drag(camera, instance, dx, dy),
+                }
+
+                self.dirty = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn save(&self, instances: &[Instance]) {
+        if let Err(e) = save_scene(SCENE_FILE, instances) {
+            log::warn!("Couldn't save scene: {:?}", e);
+        }
+    }
+
+    fn load(&mut self, instances: &mut [Instance]) {
+        match load_scene(SCENE_FILE, instances) {
+            Ok(()) => self.dirty = true,
+            Err(e) => log::warn!("Couldn't load scene: {:?}", e),
+        }
+    }
+
+    // Adds the inspector's window to `ctx` while it's open
+    #[cfg(feature = "egui")]
+    pub fn show(&mut self, ctx: &egui::Context, instances: &mut [Instance], materials: &mut [Material]) {
+        if !self.enabled || !self.inspector.open {
+            return;
+        }
+
+        let mut open = true;
+
+        egui::Window::new("Inspector").open(&mut open).show(ctx, |ui| {
+            if !materials.is_empty() {
+                self.inspector.material %= materials.len();
+
+                egui::ComboBox::from_label("Material")
+                    .selected_text(materials[self.inspector.material].name.as_str())
+                    .show_ui(ui, |ui| {
+                        for (i, material) in materials.iter().enumerate() {
+                            ui.selectable_value(&mut self.inspector.material, i, material.name.as_str());
+                        }
+                    });
+
+                let params = &mut materials[self.inspector.material].params;
+
+                for field in &INSPECTOR_FIELDS {
+                    let [min, max] = field.range;
+
+                    ui.add(egui::Slider::new((field.value)(params), min..=max).text(field.name));
+                }
+            }
+
+            if let Some(instance) = self.selected.and_then(|i| instances.get_mut(i)) {
+                ui.separator();
+
+                let mut changed = false;
+
+                ui.horizontal(|ui| {
+                    ui.label("Position");
+                    changed |= ui.add(egui::DragValue::new(&mut instance.position.x).speed(0.05)).changed();
+                    changed |= ui.add(egui::DragValue::new(&mut instance.position.y).speed(0.05)).changed();
+                    changed |= ui.add(egui::DragValue::new(&mut instance.position.z).speed(0.05)).changed();
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Scale");
+                    changed |= ui.add(egui::DragValue::new(&mut instance.scale.x).speed(0.01).clamp_range(MIN_SCALE..=f32::MAX)).changed();
+                    changed |= ui.add(egui::DragValue::new(&mut instance.scale.y).speed(0.01).clamp_range(MIN_SCALE..=f32::MAX)).changed();
+                    changed |= ui.add(egui::DragValue::new(&mut instance.scale.z).speed(0.01).clamp_range(MIN_SCALE..=f32::MAX)).changed();
+                });
+
+                self.dirty |= changed;
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save scene").clicked() {
+                    self.save(instances);
+                }
+                if ui.button("Load scene").clicked() {
+                    self.load(instances);
+                }
+            });
+        });
+
+        if !open {
+            self.inspector.set_open(false);
+        }
+    }
+
+    fn drag(&self, camera: &Camera, instance: &mut Instance, dx: f32, dy: f32) {
+        let forward = (camera.target - camera.eye).normalize();
+        let right   = forward.cross(camera.up).normalize();
+        let up      = right.cross(forward);
+
+        match self.mode {
+            GizmoMode::Translate => {
+                // Scale movement by distance so objects track the cursor at any depth
+                let distance = (instance.position - camera.eye.to_vec()).magnitude();
+
+                instance.position += (right * dx - up * dy) * TRANSLATE_SPEED * distance;
+            }
+            GizmoMode::Rotate => {
+                let yaw   = cgmath::Quaternion::from_axis_angle(up, cgmath::Deg(dx * ROTATE_SPEED));
+                let pitch = cgmath::Quaternion::from_axis_angle(right, cgmath::Deg(dy * ROTATE_SPEED));
+
+                instance.rotation = (yaw * pitch * instance.rotation).normalize();
+            }
+            GizmoMode::Scale => {
+                let factor = 1.0 - dy * SCALE_SPEED;
+                let scale  = instance.scale * factor;
+
+                if scale.x > MIN_SCALE && scale.y > MIN_SCALE && scale.z > MIN_SCALE {
+                    instance.scale = scale;
+                }
+            }
+        }
+    }
+
+    // The gizmo's center, handle length and axes for `instance`
+    fn gizmo(&self, camera: &Camera, instance: &Instance) -> (cgmath::Point3<f32>, f32, [cgmath::Vector3<f32>; 3]) {
+        let center = cgmath::Point3::from_vec(instance.position);
+        let length = (center - camera.eye).magnitude() * GIZMO_SIZE;
+        let local  = self.mode == GizmoMode::Scale || self.space == GizmoSpace::Local;
+
+        let axes = [cgmath::Vector3::unit_x(), cgmath::Vector3::unit_y(), cgmath::Vector3::unit_z()]
+            .map(|axis| if local { instance.rotation.rotate_vector(axis) } else { axis });
+
+        (center, length, axes)
+    }
+
+    // Draws the selected instance's gizmo over everything, its hovered or dragged handle highlighted
+    pub fn draw_gizmo(&self, debug: &mut DebugDraw, camera: &Camera, instances: &[Instance]) {
+        let instance = match self.selected {
+            Some(i) if self.enabled && i < instances.len() => &instances[i],
+            _                                              => return,
+        };
+
+        let (center, length, axes) = self.gizmo(camera, instance);
+        let active                 = self.gizmo_drag.as_ref().map(|drag| drag.axis).or(self.hovered);
+
+        debug.set_overlay(true);
+
+        for (i, axis) in axes.into_iter().enumerate() {
+            let color = if active == Some(i) { GIZMO_HIGHLIGHT } else { GIZMO_COLORS[i] };
+            let end   = center + axis * length;
+
+            match self.mode {
+                GizmoMode::Translate => {
+                    // An arrowhead of two fins, turned to face the camera unless it's looking
+                    // straight down the axis
+                    let side = axis.cross(camera.eye - end);
+                    let back = end - axis * length * 0.15;
+
+                    debug.line(center, end, color);
+
+                    if side.magnitude2() > 0.0 {
+                        let side = side.normalize() * length * 0.06;
+
+                        debug.line(end, back + side, color);
+                        debug.line(end, back - side, color);
+                    }
+                }
+                GizmoMode::Rotate => debug.circle(center, axis, length, color),
+                GizmoMode::Scale  => {
+                    let half = length * 0.05;
+
+                    debug.line(center, end, color);
+                    debug.aabb(&Aabb {
+                        min: end - cgmath::Vector3::new(half, half, half),
+                        max: end + cgmath::Vector3::new(half, half, half),
+                    }, color);
+                }
+            }
+        }
+
+        debug.set_overlay(false);
+    }
+
+    // The axis of the handle `ray` passes over, the closest one if there are several
+    fn gizmo_hit(&self, camera: &Camera, instance: &Instance, ray: &Ray) -> Option<usize> {
+        let (center, length, axes) = self.gizmo(camera, instance);
+        let tolerance              = length * GIZMO_TOLERANCE;
+        let mut best               = None::<(usize, f32)>;
+
+        for (i, axis) in axes.into_iter().enumerate() {
+            let t = match self.mode {
+                GizmoMode::Translate | GizmoMode::Scale => closest_on_axis(ray, center, axis).and_then(|(s, t)| {
+                    let gap = (ray.origin + ray.direction * t - (center + axis * s)).magnitude();
+
+                    (s >= 0.0 && s <= length + tolerance && t > 0.0 && gap < tolerance).then_some(t)
+                }),
+                GizmoMode::Rotate => plane_hit(ray, center, axis)
+                    .filter(|&t| ((ray.origin + ray.direction * t - center).magnitude() - length).abs() < tolerance),
+            };
+
+            if let Some(t) = t {
+                if best.is_none_or(|(_, best_t)| t < best_t) {
+                    best = Some((i, t));
+                }
+            }
+        }
+
+        best.map(|(i, _)| i)
+    }
+
+    fn grab(&self, camera: &Camera, instance: &Instance, ray: &Ray, axis: usize) -> Option<GizmoDrag> {
+        let (center, _, axes) = self.gizmo(camera, instance);
+
+        let grab = match self.mode {
+            GizmoMode::Translate | GizmoMode::Scale => cgmath::Vector3::new(closest_on_axis(ray, center, axes[axis])?.0, 0.0, 0.0),
+            GizmoMode::Rotate                       => ray.origin + ray.direction * plane_hit(ray, center, axes[axis])? - center,
+        };
+
+        Some(GizmoDrag {
+            axis,
+            grab,
+            position: instance.position,
+            rotation: instance.rotation,
+            scale:    instance.scale,
+        })
+    }
+
+    // Follows the cursor along the grabbed handle. The axes are those from the start of the drag,
+    // so rotating in local space doesn't turn the handle away from under the cursor.
+    fn drag_handle(&self, camera: &Camera, drag: &GizmoDrag, instance: &mut Instance, ray: &Ray) {
+        let start             = Instance { position: drag.position, rotation: drag.rotation, scale: drag.scale };
+        let (center, _, axes) = self.gizmo(camera, &start);
+        let axis              = axes[drag.axis];
+
+        match self.mode {
+            GizmoMode::Translate => {
+                if let Some((s, _)) = closest_on_axis(ray, center, axis) {
+                    instance.position = drag.position + axis * (s - drag.grab.x);
+                }
+            }
+            GizmoMode::Rotate => {
+                if let Some(t) = plane_hit(ray, center, axis) {
+                    let from  = drag.grab;
+                    let to    = ray.origin + ray.direction * t - center;
+                    let angle = cgmath::Rad(from.cross(to).dot(axis).atan2(from.dot(to)));
+
+                    instance.rotation = (cgmath::Quaternion::from_axis_angle(axis, angle) * drag.rotation).normalize();
+                }
+            }
+            GizmoMode::Scale => {
+                if let Some((s, _)) = closest_on_axis(ray, center, axis) {
+                    if drag.grab.x.abs() > f32::EPSILON {
+                        let scale = drag.scale[drag.axis] * s / drag.grab.x;
+
+                        instance.scale[drag.axis] = scale.max(MIN_SCALE);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Distances along `axis` from `origin` and along the ray to where the two lines pass closest,
+// or None when they're parallel
+fn closest_on_axis(ray: &Ray, origin: cgmath::Point3<f32>, axis: cgmath::Vector3<f32>) -> Option<(f32, f32)> {
+    let w     = ray.origin - origin;
+    let a     = ray.direction.dot(ray.direction);
+    let b     = ray.direction.dot(axis);
+    let c     = axis.dot(axis);
+    let d     = ray.direction.dot(w);
+    let e     = axis.dot(w);
+    let denom = a * c - b * b;
+
+    if denom.abs() < 1e-6 {
+        return None;
+    }
+
+    Some(((a * e - b * d) / denom, (b * e - c * d) / denom))
+}
+
+// Distance along the ray to the plane through `point` facing `normal`, if it's ahead
+fn plane_hit(ray: &Ray, point: cgmath::Point3<f32>, normal: cgmath::Vector3<f32>) -> Option<f32> {
+    let facing = ray.direction.dot(normal);
+
+    if facing.abs() < 1e-6 {
+        return None;
+    }
+
+    Some((point - ray.origin).dot(normal) / facing).filter(|&t| t > 0.0)
+}
+
+// Casts a ray from the cursor through the scene and returns the closest instance it hits
+fn pick(
+    camera: &Camera,
+    size:   PhysicalSize<u32>,
+    cursor: PhysicalPosition<f64>,
+    bvh:    &Bvh,
+) -> Option<usize> {
+    let ray = camera.screen_ray(cursor.x, cursor.y, size.width, size.height)?;
+
+    bvh.raycast(&ray).map(|(i, _)| i)
+}
+
+// Writes one instance per line as `px py pz rx ry rz rw sx sy sz`
+#[cfg(not(target_arch = "wasm32"))]
+fn save_scene(path: &str, instances: &[Instance]) -> anyhow::Result<()> {
+    use std::fmt::Write;
+
+    let mut out = String::new();
+
+    for instance in instances {
+        let p = instance.position;
+        let r = instance.rotation;
+        let s = instance.scale;
+
+        writeln!(
+            out,
+            "{} {} {} {} {} {} {} {} {} {}",
+            p.x, p.y, p.z, r.v.x, r.v.y, r.v.z, r.s, s.x, s.y, s.z
+        )?;
+    }
+
+    std::fs::write(path, out)?;
+    log::info!("Saved {} instances to {}", instances.len(), path);
+
+    Ok(())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn save_scene(_path: &str, _instances: &[Instance]) -> anyhow::Result<()> {
+    anyhow::bail!("saving scenes isn't supported on the web")
+}
+
+// Reads back what `save_scene` wrote. Instances are only moved, not added or removed, so the file
+// has to have as many as there are, and nothing changes unless all of it reads.
+#[cfg(not(target_arch = "wasm32"))]
+fn load_scene(path: &str, instances: &mut [Instance]) -> anyhow::Result<()> {
+    use anyhow::Context;
+
+    let text   = std::fs::read_to_string(path)?;
+    let loaded = text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(number, line)| parse_instance(line).with_context(|| format!("line {}", number + 1)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    if loaded.len() != instances.len() {
+        anyhow::bail!("{} has {} instances, but the scene has {}", path, loaded.len(), instances.len());
+    }
+
+    log::info!("Loaded {} instances from {}", loaded.len(), path);
+
+    for (instance, loaded) in instances.iter_mut().zip(loaded) {
+        *instance = loaded;
+    }
+
+    Ok(())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn load_scene(_path: &str, _instances: &mut [Instance]) -> anyhow::Result<()> {
+    anyhow::bail!("loading scenes isn't supported on the web")
+}
+
+// One line of `save_scene`'s
+#[cfg(not(target_arch = "wasm32"))]
+fn parse_instance(line: &str) -> anyhow::Result<Instance> {
+    let values = line.split_whitespace()
+        .map(str::parse::<f32>)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let [px, py, pz, rx, ry, rz, rw, sx, sy, sz]: [f32; 10] = values.try_into()
+        .map_err(|values: Vec<f32>| anyhow::anyhow!("expected 10 numbers, found {}", values.len()))?;
+
+    Ok(Instance {
+        position: cgmath::Vector3::new(px, py, pz),
+        rotation: cgmath::Quaternion::new(rw, rx, ry, rz).normalize(),
+        scale:    cgmath::Vector3::new(sx, sy, sz),
+    })
+}
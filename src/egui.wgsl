@@ -0,0 +1,67 @@
+// egui's meshes, see egui_overlay.rs. Positions are in points from the top left of the screen,
+// and colors are premultiplied sRGB, as egui gives them.
+
+struct EguiUniform {
+    // In points
+    screen:      vec2<f32>,
+    srgb_target: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> egui: EguiUniform;
+
+@group(1) @binding(0)
+var t_image: texture_2d<f32>;
+@group(1) @binding(1)
+var s_image: sampler;
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) uv:       vec2<f32>,
+    @location(2) color:    vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv:                  vec2<f32>,
+    @location(1) color:               vec4<f32>,
+}
+
+fn srgb_to_linear(srgb: vec3<f32>) -> vec3<f32> {
+    let cutoff = srgb < vec3<f32>(0.04045);
+    let lower  = srgb / 12.92;
+    let higher = pow((srgb + 0.055) / 1.055, vec3<f32>(2.4));
+
+    return select(higher, lower, cutoff);
+}
+
+fn linear_to_srgb(linear: vec3<f32>) -> vec3<f32> {
+    let cutoff = linear < vec3<f32>(0.0031308);
+    let lower  = linear * 12.92;
+    let higher = 1.055 * pow(linear, vec3<f32>(1.0 / 2.4)) - 0.055;
+
+    return select(higher, lower, cutoff);
+}
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+
+    out.uv            = in.uv;
+    out.color         = vec4<f32>(srgb_to_linear(in.color.rgb), in.color.a);
+    out.clip_position = vec4<f32>(in.position.x / egui.screen.x * 2.0 - 1.0, 1.0 - in.position.y / egui.screen.y * 2.0, 0.0, 1.0);
+
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Textures are sRGB, so sampling them gives linear colors too
+    let color = in.color * textureSample(t_image, s_image, in.uv);
+
+    if egui.srgb_target == 0u {
+        return vec4<f32>(linear_to_srgb(color.rgb), color.a);
+    }
+
+    return color;
+}
@@ -0,0 +1,471 @@
+// egui windows drawn over the finished frame in the UI layer, for tools that want text fields,
+// sliders and combo boxes rather than the fixed panels of ui.rs. Window events are handed to
+// `input` as they arrive, then `run` builds the frame's windows and uploads what egui tessellated
+// them into, and the overlay draws it as a `Drawable`.
+//
+// The tree only has egui's core, so this is its own small backend: winit's events are turned into
+// egui's by hand, and egui's meshes are drawn with one pipeline, a scissor rect per mesh.
+
+use std::{collections::HashMap, num::NonZeroU32, ops::Range};
+
+use winit::event::*;
+
+use crate::{
+    bind_group,
+    crash_report,
+    gpu_stats::{self, Tracked},
+    pass::{Drawable, RenderLayer},
+    renderer::GpuContext,
+    uniform::{Uniform, UniformBuffer},
+};
+
+const INITIAL_VERTICES: usize = 4096;
+const INITIAL_INDICES:  usize = 8192;
+
+// Points scrolled per line for mice that scroll in lines
+const SCROLL_LINE: f32 = 50.0;
+
+const VERTEX_LAYOUT: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
+    array_stride: std::mem::size_of::<egui::epaint::Vertex>() as wgpu::BufferAddress,
+    step_mode:    wgpu::VertexStepMode::Vertex,
+    attributes:   &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Unorm8x4],
+};
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct EguiUniform {
+    screen:      [f32; 2],
+    srgb_target: u32,
+    _padding:    u32,
+}
+
+struct EguiTexture {
+    texture:    wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+    _tracked:   Tracked,
+}
+
+// One of egui's meshes, as a range of the index buffer clipped to a rect in pixels
+struct EguiDraw {
+    texture:     egui::TextureId,
+    clip:        [u32; 4],
+    indices:     Range<u32>,
+    base_vertex: i32,
+}
+
+pub struct EguiOverlay {
+    context:          egui::Context,
+    // Events since the last `run`
+    raw_input:        egui::RawInput,
+    pixels_per_point: f32,
+    // In points
+    pointer:          egui::Pos2,
+    modifiers:        egui::Modifiers,
+    start:            instant::Instant,
+    pipeline:         wgpu::RenderPipeline,
+    texture_layout:   wgpu::BindGroupLayout,
+    uniform:          UniformBuffer<EguiUniform>,
+    bind_group:       wgpu::BindGroup,
+    srgb_target:      bool,
+    textures:         HashMap<egui::TextureId, EguiTexture>,
+    vertex_buffer:    wgpu::Buffer,
+    index_buffer:     wgpu::Buffer,
+    vertex_capacity:  usize,
+    index_capacity:   usize,
+    draws:            Vec<EguiDraw>,
+    // The target's size in pixels, which the scissor rect is put back to after drawing
+    target_size:      [u32; 2],
+}
+
+impl EguiOverlay {
+    pub fn new(ctx: &GpuContext) -> Self {
+        let device  = &ctx.device;
+        let uniform = UniformBuffer::new(device, "egui Uniform Buffer");
+
+        let layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT)
+            .build(device, "egui_bind_group_layout");
+
+        let texture_layout = bind_group::BindGroupLayoutBuilder::new()
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .sampler(wgpu::ShaderStages::FRAGMENT)
+            .build(device, "egui_texture_bind_group_layout");
+
+        let bind_group = bind_group::BindGroupBuilder::new(&layout)
+            .uniform(uniform.buffer())
+            .build(device, "egui_bind_group");
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("egui Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("egui.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label:                Some("egui Pipeline Layout"),
+            bind_group_layouts:   &[&layout, &texture_layout],
+            push_constant_ranges: &[],
+        });
+
+        // egui's colors are premultiplied. The UI layer has no depth attachment.
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label:    Some("egui Pipeline"),
+            layout:   Some(&pipeline_layout),
+            vertex:   wgpu::VertexState {
+                module:      &shader,
+                entry_point: "vs_main",
+                buffers:     &[VERTEX_LAYOUT],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module:      &shader,
+                entry_point: "fs_main",
+                targets:     &[Some(wgpu::ColorTargetState {
+                    format:     ctx.config.format,
+                    blend:      Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive:     wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample:   wgpu::MultisampleState::default(),
+            multiview:     None,
+        });
+
+        Self {
+            context:          egui::Context::default(),
+            raw_input:        egui::RawInput::default(),
+            pixels_per_point: ctx.window().scale_factor() as f32,
+            pointer:          egui::Pos2::ZERO,
+            modifiers:        egui::Modifiers::default(),
+            start:            instant::Instant::now(),
+            pipeline,
+            texture_layout,
+            uniform,
+            bind_group,
+            srgb_target:      ctx.config.format.describe().srgb,
+            textures:         HashMap::new(),
+            vertex_buffer:    create_buffer(device, "egui Vertex Buffer", wgpu::BufferUsages::VERTEX, INITIAL_VERTICES * std::mem::size_of::<egui::epaint::Vertex>()),
+            index_buffer:     create_buffer(device, "egui Index Buffer", wgpu::BufferUsages::INDEX, INITIAL_INDICES * std::mem::size_of::<u32>()),
+            vertex_capacity:  INITIAL_VERTICES,
+            index_capacity:   INITIAL_INDICES,
+            draws:            Vec::new(),
+            target_size:      [1, 1],
+        }
+    }
+
+    // Passes `event` on to egui. Returns true if egui is using the pointer or keyboard, so the
+    // event shouldn't reach the scene underneath.
+    pub fn input(&mut self, event: &WindowEvent) -> bool {
+        let pointer  = self.context.is_pointer_over_area() || self.context.wants_pointer_input();
+        let keyboard = self.context.wants_keyboard_input();
+
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.pointer = egui::pos2(position.x as f32 / self.pixels_per_point, position.y as f32 / self.pixels_per_point);
+                self.raw_input.events.push(egui::Event::PointerMoved(self.pointer));
+                pointer
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.raw_input.events.push(egui::Event::PointerGone);
+                false
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let button = match button {
+                    MouseButton::Left   => egui::PointerButton::Primary,
+                    MouseButton::Right  => egui::PointerButton::Secondary,
+                    MouseButton::Middle => egui::PointerButton::Middle,
+                    MouseButton::Other(_) => return false,
+                };
+
+                self.raw_input.events.push(egui::Event::PointerButton {
+                    pos:       self.pointer,
+                    button,
+                    pressed:   *state == ElementState::Pressed,
+                    modifiers: self.modifiers,
+                });
+                pointer
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let delta = match delta {
+                    MouseScrollDelta::LineDelta(x, y)   => egui::vec2(*x, *y) * SCROLL_LINE,
+                    MouseScrollDelta::PixelDelta(delta) => egui::vec2(delta.x as f32, delta.y as f32) / self.pixels_per_point,
+                };
+
+                self.raw_input.events.push(egui::Event::Scroll(delta));
+                pointer
+            }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = egui::Modifiers {
+                    alt:      modifiers.alt(),
+                    ctrl:     modifiers.ctrl(),
+                    shift:    modifiers.shift(),
+                    mac_cmd:  cfg!(target_os = "macos") && modifiers.logo(),
+                    command:  if cfg!(target_os = "macos") { modifiers.logo() } else { modifiers.ctrl() },
+                };
+                false
+            }
+            WindowEvent::ReceivedCharacter(character) if !character.is_control() => {
+                self.raw_input.events.push(egui::Event::Text(character.to_string()));
+                keyboard
+            }
+            WindowEvent::KeyboardInput { input: KeyboardInput { state, virtual_keycode: Some(keycode), .. }, .. } => {
+                if let Some(key) = key(*keycode) {
+                    self.raw_input.events.push(egui::Event::Key {
+                        key,
+                        pressed:   *state == ElementState::Pressed,
+                        modifiers: self.modifiers,
+                    });
+                }
+                keyboard
+            }
+            WindowEvent::Focused(focused) => {
+                self.raw_input.has_focus = *focused;
+                false
+            }
+            _ => false,
+        }
+    }
+
+    // Runs egui for a frame with the windows `show` adds, and uploads the result to draw
+    pub fn run(&mut self, ctx: &GpuContext, show: impl FnOnce(&egui::Context)) {
+        self.pixels_per_point = ctx.window().scale_factor() as f32;
+        self.target_size      = [ctx.size.width.max(1), ctx.size.height.max(1)];
+
+        let screen = egui::vec2(self.target_size[0] as f32, self.target_size[1] as f32) / self.pixels_per_point;
+
+        let mut raw_input = std::mem::take(&mut self.raw_input);
+
+        raw_input.screen_rect      = Some(egui::Rect::from_min_size(egui::Pos2::ZERO, screen));
+        raw_input.pixels_per_point = Some(self.pixels_per_point);
+        raw_input.max_texture_side = Some(ctx.device.limits().max_texture_dimension_2d as usize);
+        raw_input.time             = Some(self.start.elapsed().as_secs_f64());
+        raw_input.modifiers        = self.modifiers;
+        self.raw_input.has_focus   = raw_input.has_focus;
+
+        let output = self.context.run(raw_input, show);
+
+        for (id, delta) in &output.textures_delta.set {
+            self.set_texture(ctx, *id, delta);
+        }
+
+        let primitives = self.context.tessellate(output.shapes);
+
+        self.upload(ctx, &primitives);
+        self.uniform.write(&ctx.queue, &EguiUniform {
+            screen:      [screen.x, screen.y],
+            srgb_target: self.srgb_target as u32,
+            _padding:    0,
+        });
+
+        // Only after this frame's meshes are built, since they may still have used them
+        for id in &output.textures_delta.free {
+            self.textures.remove(id);
+        }
+    }
+
+    // Makes or patches the texture `id`
+    fn set_texture(&mut self, ctx: &GpuContext, id: egui::TextureId, delta: &egui::epaint::ImageDelta) {
+        let (size, pixels): ([usize; 2], Vec<egui::Color32>) = match &delta.image {
+            egui::ImageData::Color(image) => (image.size, image.pixels.clone()),
+            egui::ImageData::Font(image)  => (image.size, image.srgba_pixels(1.0).collect()),
+        };
+
+        let extent = wgpu::Extent3d {
+            width:                 size[0] as u32,
+            height:                size[1] as u32,
+            depth_or_array_layers: 1,
+        };
+
+        // A patch goes into the texture that's there, and a whole image replaces it
+        if delta.pos.is_none() || !self.textures.contains_key(&id) {
+            let label = format!("egui Texture {:?}", id);
+            let (texture, tracked) = gpu_stats::create_texture(&ctx.device, &wgpu::TextureDescriptor {
+                label:           Some(&label),
+                size:            extent,
+                mip_level_count: 1,
+                sample_count:    1,
+                dimension:       wgpu::TextureDimension::D2,
+                format:          wgpu::TextureFormat::Rgba8UnormSrgb,
+                usage:           wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            });
+
+            let filter = match delta.filter {
+                egui::TextureFilter::Nearest => wgpu::FilterMode::Nearest,
+                egui::TextureFilter::Linear  => wgpu::FilterMode::Linear,
+            };
+            let sampler = ctx.device.create_sampler(&wgpu::SamplerDescriptor {
+                label:          Some("egui Sampler"),
+                mag_filter:     filter,
+                min_filter:     filter,
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                ..Default::default()
+            });
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+            let bind_group = bind_group::BindGroupBuilder::new(&self.texture_layout)
+                .texture(&view)
+                .sampler(&sampler)
+                .build(&ctx.device, &label);
+
+            self.textures.insert(id, EguiTexture { texture, bind_group, _tracked: tracked });
+        }
+
+        let [x, y] = delta.pos.unwrap_or([0, 0]);
+
+        ctx.queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture:   &self.textures[&id].texture,
+                mip_level: 0,
+                origin:    wgpu::Origin3d { x: x as u32, y: y as u32, z: 0 },
+                aspect:    wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(&pixels),
+            wgpu::ImageDataLayout {
+                offset:         0,
+                bytes_per_row:  NonZeroU32::new(4 * extent.width),
+                rows_per_image: NonZeroU32::new(extent.height),
+            },
+            extent,
+        );
+    }
+
+    // Packs every mesh into the vertex and index buffers, each drawn clipped to its rect
+    fn upload(&mut self, ctx: &GpuContext, primitives: &[egui::ClippedPrimitive]) {
+        let mut vertices = Vec::new();
+        let mut indices  = Vec::new();
+
+        self.draws.clear();
+
+        for primitive in primitives {
+            let mesh = match &primitive.primitive {
+                egui::epaint::Primitive::Mesh(mesh) if !mesh.indices.is_empty() => mesh,
+                // Paint callbacks need a backend's own hooks, and nothing here adds them
+                _ => continue,
+            };
+
+            let clip = match self.clip_rect(primitive.clip_rect) {
+                Some(clip) => clip,
+                None       => continue,
+            };
+
+            let start = indices.len() as u32;
+
+            self.draws.push(EguiDraw {
+                texture:     mesh.texture_id,
+                clip,
+                indices:     start..start + mesh.indices.len() as u32,
+                base_vertex: vertices.len() as i32,
+            });
+
+            vertices.extend_from_slice(&mesh.vertices);
+            indices.extend_from_slice(&mesh.indices);
+        }
+
+        if vertices.len() > self.vertex_capacity {
+            self.vertex_capacity = vertices.len().next_power_of_two();
+            self.vertex_buffer   = create_buffer(&ctx.device, "egui Vertex Buffer", wgpu::BufferUsages::VERTEX, self.vertex_capacity * std::mem::size_of::<egui::epaint::Vertex>());
+        }
+
+        if indices.len() > self.index_capacity {
+            self.index_capacity = indices.len().next_power_of_two();
+            self.index_buffer   = create_buffer(&ctx.device, "egui Index Buffer", wgpu::BufferUsages::INDEX, self.index_capacity * std::mem::size_of::<u32>());
+        }
+
+        ctx.queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+        ctx.queue.write_buffer(&self.index_buffer, 0, bytemuck::cast_slice(&indices));
+    }
+
+    // `rect` in points as a scissor rect in pixels, or None when nothing of it is on screen
+    fn clip_rect(&self, rect: egui::Rect) -> Option<[u32; 4]> {
+        let [width, height] = self.target_size;
+
+        let left   = ((rect.min.x * self.pixels_per_point).round().max(0.0) as u32).min(width);
+        let top    = ((rect.min.y * self.pixels_per_point).round().max(0.0) as u32).min(height);
+        let right  = ((rect.max.x * self.pixels_per_point).round().max(0.0) as u32).min(width);
+        let bottom = ((rect.max.y * self.pixels_per_point).round().max(0.0) as u32).min(height);
+
+        (right > left && bottom > top).then_some([left, top, right - left, bottom - top])
+    }
+}
+
+fn create_buffer(device: &wgpu::Device, label: &str, usage: wgpu::BufferUsages, size: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label:              Some(label),
+        size:               size as wgpu::BufferAddress,
+        usage:              usage | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+// The egui key for `keycode`, for the keys egui's widgets use
+fn key(keycode: VirtualKeyCode) -> Option<egui::Key> {
+    use egui::Key;
+
+    Some(match keycode {
+        VirtualKeyCode::Down     => Key::ArrowDown,
+        VirtualKeyCode::Left     => Key::ArrowLeft,
+        VirtualKeyCode::Right    => Key::ArrowRight,
+        VirtualKeyCode::Up       => Key::ArrowUp,
+        VirtualKeyCode::Escape   => Key::Escape,
+        VirtualKeyCode::Tab      => Key::Tab,
+        VirtualKeyCode::Back     => Key::Backspace,
+        VirtualKeyCode::Return   => Key::Enter,
+        VirtualKeyCode::Space    => Key::Space,
+        VirtualKeyCode::Insert   => Key::Insert,
+        VirtualKeyCode::Delete   => Key::Delete,
+        VirtualKeyCode::Home     => Key::Home,
+        VirtualKeyCode::End      => Key::End,
+        VirtualKeyCode::PageUp   => Key::PageUp,
+        VirtualKeyCode::PageDown => Key::PageDown,
+        VirtualKeyCode::Key0     => Key::Num0,
+        VirtualKeyCode::Key1     => Key::Num1,
+        VirtualKeyCode::Key2     => Key::Num2,
+        VirtualKeyCode::Key3     => Key::Num3,
+        VirtualKeyCode::Key4     => Key::Num4,
+        VirtualKeyCode::Key5     => Key::Num5,
+        VirtualKeyCode::Key6     => Key::Num6,
+        VirtualKeyCode::Key7     => Key::Num7,
+        VirtualKeyCode::Key8     => Key::Num8,
+        VirtualKeyCode::Key9     => Key::Num9,
+        VirtualKeyCode::A        => Key::A,
+        VirtualKeyCode::C        => Key::C,
+        VirtualKeyCode::K        => Key::K,
+        VirtualKeyCode::U        => Key::U,
+        VirtualKeyCode::V        => Key::V,
+        VirtualKeyCode::W        => Key::W,
+        VirtualKeyCode::X        => Key::X,
+        VirtualKeyCode::Y        => Key::Y,
+        VirtualKeyCode::Z        => Key::Z,
+        _                        => return None,
+    })
+}
+
+impl Drawable for EguiOverlay {
+    fn draw<'a>(&'a self, layer: RenderLayer, render_pass: &mut wgpu::RenderPass<'a>) {
+        if layer != RenderLayer::Ui || self.draws.is_empty() {
+            return;
+        }
+
+        crash_report::set_pipeline(render_pass, &self.pipeline, "egui Pipeline");
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+
+        for draw in &self.draws {
+            let texture = match self.textures.get(&draw.texture) {
+                Some(texture) => texture,
+                None          => continue,
+            };
+
+            let [x, y, width, height] = draw.clip;
+
+            render_pass.set_scissor_rect(x, y, width, height);
+            render_pass.set_bind_group(1, &texture.bind_group, &[]);
+            render_pass.draw_indexed(draw.indices.clone(), draw.base_vertex, 0..1);
+            gpu_stats::record_draw(draw.indices.len() as u32, 1);
+        }
+
+        // Whatever's drawn after in the layer isn't clipped
+        render_pass.set_scissor_rect(0, 0, self.target_size[0], self.target_size[1]);
+    }
+}
@@ -0,0 +1,413 @@
+// Baking environment maps for image based lighting: projecting an equirectangular HDR panorama
+// onto a cube, convolving that into diffuse irradiance, prefiltering it for GGX specular at a
+// roughness per mip, and the split sum BRDF lookup table. Each is a standalone function taking a
+// device and queue, meant for baking ahead of time, and `CubeMap::read_face` gets the results back
+// to be saved to disk.
+//
+// Everything's drawn with render passes into each face, rather than compute, so it runs on WebGL
+// too. Cube maps are Rgba16Float, which can be both rendered to and filtered everywhere.
+
+use anyhow::Result;
+
+use crate::{
+    bind_group,
+    crash_report,
+    gpu_stats::{self, Tracked},
+    renderer,
+    texture,
+    uniform::{Uniform, UniformBuffer},
+};
+
+pub const CUBE_FORMAT:     wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+pub const BRDF_LUT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg16Float;
+
+// Also in environment.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct BakeParams {
+    face:         u32,
+    roughness:    f32,
+    sample_count: u32,
+    source_size:  f32,
+}
+
+pub struct CubeMap {
+    pub texture:   wgpu::Texture,
+    // All six faces and every mip, for sampling
+    pub view:      wgpu::TextureView,
+    pub sampler:   wgpu::Sampler,
+    pub size:      u32,
+    pub mip_count: u32,
+    pub tracked:   Tracked,
+}
+
+impl CubeMap {
+    pub fn new(device: &wgpu::Device, size: u32, mip_count: u32, label: &str) -> Self {
+        let (texture, tracked) = gpu_stats::create_texture(device, &wgpu::TextureDescriptor {
+            label:           Some(label),
+            size:            wgpu::Extent3d { width: size, height: size, depth_or_array_layers: 6 },
+            mip_level_count: mip_count,
+            sample_count:    1,
+            dimension:       wgpu::TextureDimension::D2,
+            format:          CUBE_FORMAT,
+            usage:           wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::COPY_DST,
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+
+        Self {
+            texture,
+            view,
+            sampler: linear_sampler(device),
+            size,
+            mip_count,
+            tracked,
+        }
+    }
+
+    // Texels across a face at `mip`
+    pub fn mip_size(&self, mip: u32) -> u32 {
+        (self.size >> mip).max(1)
+    }
+
+    fn face_view(&self, face: u32, mip: u32) -> wgpu::TextureView {
+        self.texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension:         Some(wgpu::TextureViewDimension::D2),
+            base_mip_level:    mip,
+            mip_level_count:   std::num::NonZeroU32::new(1),
+            base_array_layer:  face,
+            array_layer_count: std::num::NonZeroU32::new(1),
+            ..Default::default()
+        })
+    }
+
+    // One face at one mip, for saving, e.g. as OpenEXR or Radiance HDR. Faces go +X, -X, +Y, -Y,
+    // +Z then -Z. Blocks until the copy is back.
+    pub fn read_face(&self, device: &wgpu::Device, queue: &wgpu::Queue, face: u32, mip: u32) -> Result<image::Rgba32FImage> {
+        let size       = self.mip_size(mip);
+        let row_bytes  = size * 8;
+        let align      = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_row = row_bytes.div_ceil(align) * align;
+        let staging    = device.create_buffer(&wgpu::BufferDescriptor {
+            label:              Some("Cube Face Staging Buffer"),
+            size:               (padded_row * size) as wgpu::BufferAddress,
+            usage:              wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Cube Face Readback Encoder"),
+        });
+
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                aspect:    wgpu::TextureAspect::All,
+                texture:   &self.texture,
+                mip_level: mip,
+                origin:    wgpu::Origin3d { x: 0, y: 0, z: face },
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &staging,
+                layout: wgpu::ImageDataLayout {
+                    offset:         0,
+                    bytes_per_row:  std::num::NonZeroU32::new(padded_row),
+                    rows_per_image: std::num::NonZeroU32::new(size),
+                },
+            },
+            wgpu::Extent3d { width: size, height: size, depth_or_array_layers: 1 },
+        );
+
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let slice              = staging.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        slice.map_async(wgpu::MapMode::Read, move |result| sender.send(result).unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        receiver.recv()??;
+
+        // Rows are padded out to the copy alignment, which the image doesn't want
+        let mut pixels = Vec::with_capacity((size * size * 4) as usize);
+        {
+            let data = slice.get_mapped_range();
+
+            for row in data.chunks(padded_row as usize) {
+                for half in row[..row_bytes as usize].chunks(2) {
+                    pixels.push(from_f16(u16::from_le_bytes([half[0], half[1]])));
+                }
+            }
+        }
+
+        Ok(image::Rgba32FImage::from_raw(size, size, pixels).expect("Cube face is the wrong size"))
+    }
+}
+
+// A Radiance HDR image as an Rgba16Float texture, keeping its range for `equirect_to_cube`
+pub fn equirect_from_hdr(device: &wgpu::Device, queue: &wgpu::Queue, bytes: &[u8], label: &str) -> Result<texture::Texture> {
+    use wgpu::util::DeviceExt;
+
+    let hdr    = image::load_from_memory_with_format(bytes, image::ImageFormat::Hdr)?.to_rgba32f();
+    let halves = hdr.as_raw().iter().map(|&c| to_f16(c)).collect::<Vec<_>>();
+
+    let desc = wgpu::TextureDescriptor {
+        label:           Some(label),
+        size:            wgpu::Extent3d { width: hdr.width(), height: hdr.height(), depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count:    1,
+        dimension:       wgpu::TextureDimension::D2,
+        format:          CUBE_FORMAT,
+        usage:           wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+    };
+
+    let texture = device.create_texture_with_data(queue, &desc, bytemuck::cast_slice(&halves));
+    let view    = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    Ok(texture::Texture { texture, view, sampler: linear_sampler(device), tracked: Tracked::texture(&desc) })
+}
+
+// An equirectangular panorama projected onto a cube with faces `size` across. Every mip is drawn
+// straight from the panorama, so later passes can read blurrier versions.
+pub fn equirect_to_cube(device: &wgpu::Device, queue: &wgpu::Queue, equirect: &wgpu::TextureView, size: u32, mip_count: u32) -> CubeMap {
+    let cube  = CubeMap::new(device, size, mip_count, "environment_cube");
+    let baker = Baker::new(device, Some(wgpu::TextureViewDimension::D2), include_str!("environment_equirect.wgsl"), CUBE_FORMAT, "Equirect To Cube");
+
+    let sampler = linear_sampler(device);
+    let source  = baker.source(device, equirect, &sampler);
+
+    for mip in 0..mip_count {
+        baker.draw_faces(device, queue, &cube, mip, Some(&source), BakeParams {
+            face:         0,
+            roughness:    0.0,
+            sample_count: 0,
+            source_size:  0.0,
+        });
+    }
+
+    cube
+}
+
+// The diffuse light from `environment`, cosine weighted over the hemisphere around each
+// direction. It's smooth, so 32 across is plenty.
+pub fn irradiance(device: &wgpu::Device, queue: &wgpu::Queue, environment: &CubeMap, size: u32) -> CubeMap {
+    let cube   = CubeMap::new(device, size, 1, "irradiance_cube");
+    let baker  = Baker::new(device, Some(wgpu::TextureViewDimension::Cube), include_str!("environment_irradiance.wgsl"), CUBE_FORMAT, "Irradiance");
+    let source = baker.source(device, &environment.view, &environment.sampler);
+
+    baker.draw_faces(device, queue, &cube, 0, Some(&source), BakeParams {
+        face:         0,
+        roughness:    0.0,
+        sample_count: 0,
+        source_size:  environment.size as f32,
+    });
+
+    cube
+}
+
+// `environment` as GGX reflects it, with roughness going from 0.0 at the first mip to 1.0 at the
+// last. `sample_count` around 1024 is smooth.
+pub fn prefilter_specular(
+    device:       &wgpu::Device,
+    queue:        &wgpu::Queue,
+    environment:  &CubeMap,
+    size:         u32,
+    mip_count:    u32,
+    sample_count: u32,
+) -> CubeMap {
+    let cube   = CubeMap::new(device, size, mip_count, "prefiltered_cube");
+    let baker  = Baker::new(device, Some(wgpu::TextureViewDimension::Cube), include_str!("environment_prefilter.wgsl"), CUBE_FORMAT, "Specular Prefilter");
+    let source = baker.source(device, &environment.view, &environment.sampler);
+
+    for mip in 0..mip_count {
+        baker.draw_faces(device, queue, &cube, mip, Some(&source), BakeParams {
+            face:         0,
+            roughness:    mip as f32 / (mip_count - 1).max(1) as f32,
+            sample_count,
+            source_size:  environment.size as f32,
+        });
+    }
+
+    cube
+}
+
+// The split sum's scale in red and bias in green on F0, with n.v going across and roughness down.
+// The same for every environment, so it only needs baking once.
+pub fn brdf_lut(device: &wgpu::Device, queue: &wgpu::Queue, size: u32, sample_count: u32) -> texture::Texture {
+    let (texture, tracked) = gpu_stats::create_texture(device, &wgpu::TextureDescriptor {
+        label:           Some("brdf_lut"),
+        size:            wgpu::Extent3d { width: size, height: size, depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count:    1,
+        dimension:       wgpu::TextureDimension::D2,
+        format:          BRDF_LUT_FORMAT,
+        usage:           wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+    });
+
+    let view  = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let baker = Baker::new(device, None, include_str!("brdf_lut.wgsl"), BRDF_LUT_FORMAT, "BRDF LUT");
+
+    baker.draw(device, queue, &view, None, BakeParams {
+        face:         0,
+        roughness:    0.0,
+        sample_count,
+        source_size:  0.0,
+    });
+
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        mag_filter:     wgpu::FilterMode::Linear,
+        min_filter:     wgpu::FilterMode::Linear,
+        ..Default::default()
+    });
+
+    texture::Texture { texture, view, sampler, tracked }
+}
+
+// One of the passes, drawing with environment.wgsl in front of its own shader
+struct Baker {
+    pipeline:      wgpu::RenderPipeline,
+    label:         String,
+    params:        UniformBuffer<BakeParams>,
+    params_group:  wgpu::BindGroup,
+    source_layout: Option<wgpu::BindGroupLayout>,
+}
+
+impl Baker {
+    fn new(
+        device: &wgpu::Device,
+        source: Option<wgpu::TextureViewDimension>,
+        shader: &str,
+        format: wgpu::TextureFormat,
+        label:  &str,
+    ) -> Self {
+        let params_layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::FRAGMENT)
+            .build(device, "bake_params_layout");
+
+        let source_layout = source.map(|dimension| {
+            bind_group::BindGroupLayoutBuilder::new()
+                .texture(wgpu::ShaderStages::FRAGMENT, dimension)
+                .sampler(wgpu::ShaderStages::FRAGMENT)
+                .build(device, "bake_source_layout")
+        });
+
+        let params       = UniformBuffer::new(device, "bake_params");
+        let params_group = bind_group::BindGroupBuilder::new(&params_layout)
+            .uniform(params.buffer())
+            .build(device, "bake_params_group");
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some(label),
+            source: wgpu::ShaderSource::Wgsl((include_str!("environment.wgsl").to_string() + shader).into()),
+        });
+
+        let layouts = std::iter::once(&params_layout).chain(source_layout.as_ref()).collect::<Vec<_>>();
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label:                Some(label),
+            bind_group_layouts:   &layouts,
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = renderer::create_render_pipeline(device, &pipeline_layout, format, None, &[], &shader, label);
+
+        Self { pipeline, label: label.to_string(), params, params_group, source_layout }
+    }
+
+    fn source(&self, device: &wgpu::Device, view: &wgpu::TextureView, sampler: &wgpu::Sampler) -> wgpu::BindGroup {
+        bind_group::BindGroupBuilder::new(self.source_layout.as_ref().expect("Pass doesn't read a source"))
+            .texture(view)
+            .sampler(sampler)
+            .build(device, "bake_source_group")
+    }
+
+    // Each face is submitted on its own, since they all share the one params buffer
+    fn draw_faces(&self, device: &wgpu::Device, queue: &wgpu::Queue, cube: &CubeMap, mip: u32, source: Option<&wgpu::BindGroup>, params: BakeParams) {
+        for face in 0..6 {
+            self.draw(device, queue, &cube.face_view(face, mip), source, BakeParams { face, ..params });
+        }
+    }
+
+    fn draw(&self, device: &wgpu::Device, queue: &wgpu::Queue, target: &wgpu::TextureView, source: Option<&wgpu::BindGroup>, params: BakeParams) {
+        self.params.write(queue, &params);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Environment Bake Encoder"),
+        });
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Environment Bake Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view:           target,
+                    resolve_target: None,
+                    ops:            wgpu::Operations {
+                        load:  wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+
+            crash_report::set_pipeline(&mut render_pass, &self.pipeline, &self.label);
+            render_pass.set_bind_group(0, &self.params_group, &[]);
+
+            if let Some(source) = source {
+                render_pass.set_bind_group(1, source, &[]);
+            }
+
+            render_pass.draw(0..3, 0..1);
+        }
+
+        queue.submit(std::iter::once(encoder.finish()));
+    }
+}
+
+fn linear_sampler(device: &wgpu::Device) -> wgpu::Sampler {
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter:     wgpu::FilterMode::Linear,
+        min_filter:     wgpu::FilterMode::Linear,
+        mipmap_filter:  wgpu::FilterMode::Linear,
+        ..Default::default()
+    })
+}
+
+// Half floats by hand, rounding toward zero, since nothing else here needs a crate for them
+fn to_f16(value: f32) -> u16 {
+    let bits     = value.to_bits();
+    let sign     = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+    let mantissa = bits & 0x7f_ffff;
+
+    if value.is_nan() {
+        sign | 0x7e00
+    } else if exponent >= 31 {
+        sign | 0x7c00
+    } else if exponent <= 0 {
+        // Too small for a normal half, so subnormal or zero
+        if exponent < -10 { sign } else { sign | ((mantissa | 0x80_0000) >> (14 - exponent)) as u16 }
+    } else {
+        sign | (exponent as u16) << 10 | (mantissa >> 13) as u16
+    }
+}
+
+fn from_f16(bits: u16) -> f32 {
+    let sign     = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+
+    match exponent {
+        0  => sign * mantissa * 2f32.powi(-24),
+        31 => if mantissa == 0.0 { sign * f32::INFINITY } else { f32::NAN },
+        _  => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
@@ -0,0 +1,85 @@
+// Shared by the environment baking passes: a triangle covering the target, the direction each
+// texel of a cube face looks along, and GGX importance sampling
+
+struct BakeParams {
+    // The cube face being drawn, +X, -X, +Y, -Y, +Z then -Z
+    face:         u32,
+    roughness:    f32,
+    sample_count: u32,
+    // Texels across a face of the source, for picking which of its mips to sample
+    source_size:  f32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: BakeParams;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    // Texture space, y down
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // One triangle covering the target
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.uv       = vec2<f32>(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+fn face_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    let u = uv.x * 2.0 - 1.0;
+    let v = uv.y * 2.0 - 1.0;
+
+    var direction: vec3<f32>;
+    switch face {
+        case 0u: { direction = vec3<f32>(1.0, -v, -u); }
+        case 1u: { direction = vec3<f32>(-1.0, -v, u); }
+        case 2u: { direction = vec3<f32>(u, 1.0, v); }
+        case 3u: { direction = vec3<f32>(u, -1.0, -v); }
+        case 4u: { direction = vec3<f32>(u, -v, 1.0); }
+        default: { direction = vec3<f32>(-u, -v, -1.0); }
+    }
+
+    return normalize(direction);
+}
+
+// Van der Corput sequence, the bits of `i` mirrored behind the binary point
+fn radical_inverse(i: u32) -> f32 {
+    var bits = (i << 16u) | (i >> 16u);
+    bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+    bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+    bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+    bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+
+    return f32(bits) * 2.3283064365386963e-10;
+}
+
+fn hammersley(i: u32, count: u32) -> vec2<f32> {
+    return vec2<f32>(f32(i) / f32(count), radical_inverse(i));
+}
+
+// A half vector around `n`, more of them where GGX at `roughness` reflects the most
+fn importance_sample_ggx(xi: vec2<f32>, n: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let a         = roughness * roughness;
+    let phi       = 6.283185307 * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    let h         = vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+
+    let up        = select(vec3<f32>(0.0, 0.0, 1.0), vec3<f32>(1.0, 0.0, 0.0), abs(n.z) > 0.999);
+    let tangent   = normalize(cross(up, n));
+    let bitangent = cross(n, tangent);
+
+    return normalize(tangent * h.x + bitangent * h.y + n * h.z);
+}
+
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a2 = roughness * roughness * roughness * roughness;
+    let d  = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+
+    return a2 / (3.141592654 * d * d);
+}
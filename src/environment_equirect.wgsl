@@ -0,0 +1,17 @@
+// Projects an equirectangular panorama onto a cube face
+
+@group(1) @binding(0)
+var source: texture_2d<f32>;
+@group(1) @binding(1)
+var source_sampler: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let direction = face_direction(params.face, in.uv);
+    let uv        = vec2<f32>(
+        atan2(direction.z, direction.x) / 6.283185307 + 0.5,
+        acos(clamp(direction.y, -1.0, 1.0)) / 3.141592654
+    );
+
+    return vec4<f32>(textureSampleLevel(source, source_sampler, uv, 0.0).rgb, 1.0);
+}
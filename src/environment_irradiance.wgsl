@@ -0,0 +1,34 @@
+// The diffuse light arriving from the environment around each direction, weighted by the cosine
+// over the hemisphere
+
+@group(1) @binding(0)
+var source: texture_cube<f32>;
+@group(1) @binding(1)
+var source_sampler: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let n     = face_direction(params.face, in.uv);
+    let side  = select(vec3<f32>(0.0, 1.0, 0.0), vec3<f32>(1.0, 0.0, 0.0), abs(n.y) > 0.999);
+    let right = normalize(cross(side, n));
+    let up    = cross(n, right);
+
+    // Samples are spaced wider than the source's texels, so a small mip keeps them from aliasing
+    let lod   = max(log2(params.source_size / 32.0), 0.0);
+    let delta = 0.05;
+
+    var sum   = vec3<f32>(0.0);
+    var count = 0.0;
+
+    for (var phi = 0.0; phi < 6.283185307; phi = phi + delta) {
+        for (var theta = 0.0; theta < 1.570796327; theta = theta + delta) {
+            let local     = vec3<f32>(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            let direction = local.x * right + local.y * up + local.z * n;
+
+            sum   = sum + textureSampleLevel(source, source_sampler, direction, lod).rgb * cos(theta) * sin(theta);
+            count = count + 1.0;
+        }
+    }
+
+    return vec4<f32>(3.141592654 * sum / count, 1.0);
+}
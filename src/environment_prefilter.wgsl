@@ -0,0 +1,38 @@
+// The environment as GGX at `params.roughness` reflects it, taking the view to be along the normal
+// as the split sum approximation does
+
+@group(1) @binding(0)
+var source: texture_cube<f32>;
+@group(1) @binding(1)
+var source_sampler: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let n = face_direction(params.face, in.uv);
+
+    // Solid angle of one source texel
+    let texel_angle = 12.566370614 / (6.0 * params.source_size * params.source_size);
+
+    var color  = vec3<f32>(0.0);
+    var weight = 0.0;
+
+    for (var i = 0u; i < params.sample_count; i = i + 1u) {
+        let h       = importance_sample_ggx(hammersley(i, params.sample_count), n, params.roughness);
+        let n_dot_h = max(dot(n, h), 0.0);
+        let l       = normalize(2.0 * n_dot_h * h - n);
+        let n_dot_l = dot(n, l);
+
+        if (n_dot_l > 0.0) {
+            // Rarer directions stand for more of the sphere, so they read a blurrier mip, which
+            // keeps bright spots from speckling
+            let pdf          = distribution_ggx(n_dot_h, params.roughness) / 4.0 + 0.0001;
+            let sample_angle = 1.0 / (f32(params.sample_count) * pdf + 0.0001);
+            let lod          = select(max(0.5 * log2(sample_angle / texel_angle), 0.0), 0.0, params.roughness == 0.0);
+
+            color  = color + textureSampleLevel(source, source_sampler, l, lod).rgb * n_dot_l;
+            weight = weight + n_dot_l;
+        }
+    }
+
+    return vec4<f32>(color / max(weight, 0.0001), 1.0);
+}
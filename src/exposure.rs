@@ -0,0 +1,231 @@
+// Post effect that adapts exposure to the scene like an eye does, then tone maps. The average
+// luminance comes from a histogram of each frame and the exposure eases towards it over time, so
+// walking from a dark area into a bright one brightens and settles rather than jumping.
+//
+// Needs compute shaders, so it isn't available on WebGL.
+
+use wgpu::util::DeviceExt;
+
+use crate::{
+    bind_group,
+    compute::{self, LuminanceHistogram},
+    crash_report,
+    pass::PostEffect,
+    renderer::GpuContext,
+    uniform::Uniform,
+};
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct AdaptParams {
+    dt:           f32,
+    speed:        f32,
+    compensation: f32,
+    min_exposure: f32,
+    max_exposure: f32,
+    _padding:     [u32; 3],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct TonemapParams {
+    // Copied in from the adapted exposure each frame
+    exposure:    f32,
+    srgb_target: u32,
+    _padding:    [u32; 2],
+}
+
+pub struct AutoExposure {
+    // Stops added to the adapted exposure, to bias the whole image brighter or darker
+    pub compensation:     f32,
+    // How quickly exposure follows the scene, roughly the fraction of the gap closed per second
+    pub adaptation_speed: f32,
+    // Limits in stops, so a black screen doesn't blow up and a light source doesn't go dark
+    pub min_exposure:     f32,
+    pub max_exposure:     f32,
+    pub histogram:        LuminanceHistogram,
+    adapt_params:         wgpu::Buffer,
+    // The current exposure as a single f32, carried from frame to frame
+    state:                wgpu::Buffer,
+    adapt_pipeline:       wgpu::ComputePipeline,
+    adapt_group:          wgpu::BindGroup,
+    tonemap_params:       wgpu::Buffer,
+    tonemap_pipeline:     wgpu::RenderPipeline,
+    tonemap_layout:       wgpu::BindGroupLayout,
+    size:                 (u32, u32),
+    last_prepare:         Option<instant::Instant>,
+}
+
+impl AutoExposure {
+    pub fn new(ctx: &GpuContext) -> Self {
+        let device = &ctx.device;
+
+        let histogram = LuminanceHistogram::new(device);
+
+        let adapt_layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::COMPUTE)
+            .storage(wgpu::ShaderStages::COMPUTE, true)
+            .storage(wgpu::ShaderStages::COMPUTE, false)
+            .build(device, "exposure_adapt_bind_group_layout");
+
+        let adapt_params = device.create_buffer(&wgpu::BufferDescriptor {
+            label:              Some("Exposure Adapt Params Buffer"),
+            size:               std::mem::size_of::<AdaptParams>() as wgpu::BufferAddress,
+            usage:              wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        // Starts at 0 stops, the scene as rendered
+        let state = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label:    Some("Exposure State Buffer"),
+            contents: bytemuck::cast_slice(&[0.0f32]),
+            usage:    wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        });
+
+        let adapt_group = bind_group::BindGroupBuilder::new(&adapt_layout)
+            .uniform(&adapt_params)
+            .storage(&histogram.average)
+            .storage(&state)
+            .build(device, "exposure_adapt_bind_group");
+
+        let adapt_pipeline = compute::create_pipeline(device, &adapt_layout, include_str!("exposure_adapt.wgsl"), "Exposure Adapt");
+
+        let tonemap_layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::FRAGMENT)
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .build(device, "tonemap_bind_group_layout");
+
+        // The fragment shader reads exposure from a uniform rather than the storage buffer it's
+        // adapted in, since storage buffers can't be read from fragment shaders everywhere
+        let tonemap_params = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label:    Some("Tonemap Params Buffer"),
+            contents: bytemuck::cast_slice(&[TonemapParams {
+                exposure:    0.0,
+                srgb_target: ctx.config.format.describe().srgb as u32,
+                _padding:    [0; 2],
+            }]),
+            usage:    wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Tonemap Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("tonemap.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Tonemap Pipeline Layout"),
+            bind_group_layouts:   &[&tonemap_layout],
+            push_constant_ranges: &[],
+        });
+
+        let tonemap_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label:    Some("Tonemap Pipeline"),
+            layout:   Some(&pipeline_layout),
+            vertex:   wgpu::VertexState {
+                module:      &shader,
+                entry_point: "vs_main",
+                buffers:     &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module:      &shader,
+                entry_point: "fs_main",
+                targets:     &[Some(wgpu::ColorTargetState {
+                    format:     ctx.config.format,
+                    blend:      Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive:     wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample:   wgpu::MultisampleState::default(),
+            multiview:     None,
+        });
+
+        Self {
+            compensation:     0.0,
+            adaptation_speed: 1.5,
+            min_exposure:     -4.0,
+            max_exposure:     4.0,
+            histogram,
+            adapt_params,
+            state,
+            adapt_pipeline,
+            adapt_group,
+            tonemap_params,
+            tonemap_pipeline,
+            tonemap_layout,
+            size:             (1, 1),
+            last_prepare:     None,
+        }
+    }
+
+    // Called once a frame before the effect is added. Time since the last call drives adaptation,
+    // so after a pause the exposure catches up rather than snapping.
+    pub fn prepare(&mut self, ctx: &GpuContext) {
+        let now = instant::Instant::now();
+        let dt  = self.last_prepare.map_or(0.0, |last| (now - last).as_secs_f32().min(0.25));
+
+        self.last_prepare = Some(now);
+
+        let size  = ctx.render_size();
+        self.size = (size.width, size.height);
+
+        self.histogram.prepare(&ctx.queue);
+
+        ctx.queue.write_buffer(&self.adapt_params, 0, bytemuck::cast_slice(&[AdaptParams {
+            dt,
+            speed:        self.adaptation_speed,
+            compensation: self.compensation,
+            min_exposure: self.min_exposure,
+            max_exposure: self.max_exposure,
+            _padding:     [0; 3],
+        }]));
+    }
+}
+
+impl PostEffect for AutoExposure {
+    fn apply(
+        &self,
+        device:  &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        input:   &wgpu::TextureView,
+        output:  &wgpu::TextureView,
+    ) {
+        self.histogram.build(device, encoder, input, self.size);
+
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Exposure Adapt Pass"),
+            });
+
+            crash_report::set_compute_pipeline(&mut compute_pass, &self.adapt_pipeline, "Exposure Adapt");
+            compute_pass.set_bind_group(0, &self.adapt_group, &[]);
+            compute_pass.dispatch_workgroups(1, 1, 1);
+        }
+
+        // Exposure is the first field of the tonemap params
+        encoder.copy_buffer_to_buffer(&self.state, 0, &self.tonemap_params, 0, std::mem::size_of::<f32>() as wgpu::BufferAddress);
+
+        let bind_group = bind_group::BindGroupBuilder::new(&self.tonemap_layout)
+            .uniform(&self.tonemap_params)
+            .texture(input)
+            .build(device, "tonemap_bind_group");
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Tonemap Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view:           output,
+                resolve_target: None,
+                ops:  wgpu::Operations {
+                    load:  wgpu::LoadOp::Load,
+                    store: true
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        crash_report::set_pipeline(&mut render_pass, &self.tonemap_pipeline, "Tonemap Pipeline");
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
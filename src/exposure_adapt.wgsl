@@ -0,0 +1,44 @@
+// Eases the exposure towards the one that brings the average luminance to middle grey
+
+struct AdaptParams {
+    dt:           f32,
+    speed:        f32,
+    compensation: f32,
+    min_exposure: f32,
+    max_exposure: f32,
+}
+
+struct Average {
+    luminance: f32,
+}
+
+struct Exposure {
+    stops: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: AdaptParams;
+@group(0) @binding(1)
+var<storage, read> average: Average;
+@group(0) @binding(2)
+var<storage, read_write> exposure: Exposure;
+
+@compute @workgroup_size(1)
+fn cs_main() {
+    // Nothing but black to go by, so hold the current exposure
+    if (average.luminance <= 0.0) {
+        return;
+    }
+
+    let middle_grey = 0.18;
+    let target_stops = clamp(
+        log2(middle_grey / average.luminance) + params.compensation,
+        params.min_exposure,
+        params.max_exposure,
+    );
+
+    // Frame rate independent exponential smoothing
+    let t = 1.0 - exp(-params.dt * params.speed);
+
+    exposure.stops = mix(exposure.stops, target_stops, t);
+}
@@ -0,0 +1,397 @@
+// Grass and foliage scattered over the ground as instances of one small mesh: tapered blades, or
+// crossed cards cut out by an alpha texture. Wind is animated entirely in the vertex shader, and
+// instances thin out with distance so far away fields cost less.
+
+use std::time::Duration;
+
+use learn_wgpu_derive::VertexLayout;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    bind_group,
+    crash_report,
+    renderer::{self, GpuContext},
+    texture::Texture,
+    uniform::{Uniform, UniformBuffer},
+};
+
+// Segments along a blade, more bend more smoothly
+const BLADE_SEGMENTS: u32 = 3;
+
+const CARD_TEXTURE_SIZE: u32 = 64;
+const CARD_TUFT_BLADES:  u32 = 7;
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+struct FoliageVertex {
+    #[location(0)]
+    position:   [f32; 3],
+    #[location(1)]
+    tex_coords: [f32; 2],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+#[vertex(instance)]
+struct FoliageInstance {
+    #[location(2)]
+    position:  [f32; 3],
+    #[location(3)]
+    yaw:       f32,
+    #[location(4)]
+    size:      [f32; 2],
+    #[location(5)]
+    phase:     f32,
+    #[location(6)]
+    threshold: f32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct FoliageUniform {
+    base_color:        [f32; 4],
+    tip_color:         [f32; 4],
+    wind_direction:    [f32; 2],
+    wind_strength:     f32,
+    wind_frequency:    f32,
+    time:              f32,
+    fade_start:        f32,
+    fade_end:          f32,
+    alpha_to_coverage: u32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FoliageKind {
+    // Solid tapered blades, one per instance
+    Blades,
+    // Two crossed quads per instance, each showing a tuft of blades
+    Cards,
+}
+
+impl FoliageKind {
+    // Width and height of an instance before random variation
+    fn size(&self) -> [f32; 2] {
+        match self {
+            FoliageKind::Blades => [0.06, 0.5],
+            FoliageKind::Cards  => [0.8, 0.6],
+        }
+    }
+}
+
+// A square of ground to cover, with `density` instances per square unit
+#[derive(Debug, Copy, Clone)]
+pub struct FoliageArea {
+    pub center:    cgmath::Vector2<f32>,
+    pub half_size: f32,
+    pub density:   f32,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Wind {
+    // In the xz plane, normalized when uploaded
+    pub direction: cgmath::Vector2<f32>,
+    // How far tips lean, relative to their height
+    pub strength:  f32,
+    // Radians per second
+    pub frequency: f32,
+}
+
+pub struct Foliage {
+    pub wind:       Wind,
+    // Density starts falling at `fade_start` from the camera and reaches none at `fade_end`
+    pub fade_start: f32,
+    pub fade_end:   f32,
+    pub base_color: [f32; 3],
+    pub tip_color:  [f32; 3],
+    pipeline:       wgpu::RenderPipeline,
+    uniform:        UniformBuffer<FoliageUniform>,
+    bind_group:     wgpu::BindGroup,
+    vertices:       wgpu::Buffer,
+    indices:        wgpu::Buffer,
+    index_count:    u32,
+    instances:      wgpu::Buffer,
+    instance_count: u32,
+    // Whether the pipeline has alpha to coverage, which the shader sharpens alpha for
+    coverage:       bool,
+    time:           f32,
+}
+
+impl Foliage {
+    pub fn shader_source() -> &'static str {
+        concat!(include_str!("camera.wgsl"), include_str!("lights.wgsl"), include_str!("foliage.wgsl"))
+    }
+
+    // Drawn with the scene's camera at group 1 and lights at group 2, into a pass with
+    // `sample_count` samples. Alpha to coverage is only used when that's more than one, otherwise
+    // alpha is tested in the shader. `height_at` gives the ground height at an x and z.
+    pub fn new(
+        ctx:           &GpuContext,
+        camera_layout: &wgpu::BindGroupLayout,
+        lights_layout: &wgpu::BindGroupLayout,
+        sample_count:  u32,
+        kind:          FoliageKind,
+        area:          FoliageArea,
+        height_at:     impl Fn(f32, f32) -> f32,
+    ) -> Self {
+        let device = &ctx.device;
+        let queue  = &ctx.queue;
+
+        let (mesh_vertices, mesh_indices) = match kind {
+            FoliageKind::Blades => blade_mesh(),
+            FoliageKind::Cards  => card_mesh(),
+        };
+
+        let vertices = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label:    Some("Foliage Vertex Buffer"),
+            contents: bytemuck::cast_slice(&mesh_vertices),
+            usage:    wgpu::BufferUsages::VERTEX,
+        });
+
+        let indices = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label:    Some("Foliage Index Buffer"),
+            contents: bytemuck::cast_slice(&mesh_indices),
+            usage:    wgpu::BufferUsages::INDEX,
+        });
+
+        let scattered = scatter(kind, area, height_at);
+
+        let instances = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label:    Some("Foliage Instance Buffer"),
+            contents: bytemuck::cast_slice(&scattered),
+            usage:    wgpu::BufferUsages::VERTEX,
+        });
+
+        // Blades are shaped by their mesh, so they sample plain white
+        let image = match kind {
+            FoliageKind::Blades => image::RgbaImage::from_pixel(1, 1, image::Rgba([255; 4])),
+            FoliageKind::Cards  => tuft_image(),
+        };
+        let texture = Texture::from_image(device, queue, &image::DynamicImage::ImageRgba8(image), Some("Foliage Texture")).unwrap();
+
+        let uniform = UniformBuffer::new(device, "Foliage Uniform Buffer");
+
+        let layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT)
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .sampler(wgpu::ShaderStages::FRAGMENT)
+            .build(device, "foliage_bind_group_layout");
+
+        let bind_group = bind_group::BindGroupBuilder::new(&layout)
+            .uniform(uniform.buffer())
+            .texture(&texture.view)
+            .sampler(&texture.sampler)
+            .build(device, "foliage_bind_group");
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Foliage Shader"),
+            source: wgpu::ShaderSource::Wgsl(Self::shader_source().into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Foliage Pipeline Layout"),
+            bind_group_layouts:   &[&layout, camera_layout, lights_layout],
+            push_constant_ranges: &[],
+        });
+
+        let alpha_to_coverage = sample_count > 1;
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label:    Some("Foliage Pipeline"),
+            layout:   Some(&pipeline_layout),
+            vertex:   wgpu::VertexState {
+                module:      &shader,
+                entry_point: "vs_main",
+                buffers:     &[FoliageVertex::layout(), FoliageInstance::layout()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module:      &shader,
+                entry_point: "fs_main",
+                targets:     &[Some(wgpu::ColorTargetState {
+                    format:     ctx.config.format,
+                    blend:      Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            // Blades and cards are seen from both sides
+            primitive:     wgpu::PrimitiveState {
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: Some(renderer::depth_state(Texture::DEPTH_FORMAT, ctx.depth_mode.compare(), true)),
+            multisample:   wgpu::MultisampleState {
+                count: sample_count,
+                mask:  !0,
+                alpha_to_coverage_enabled: alpha_to_coverage,
+            },
+            multiview:     None,
+        });
+
+        Self {
+            wind:           Wind {
+                direction: cgmath::Vector2::new(1.0, 0.3),
+                strength:  0.3,
+                frequency: 1.5,
+            },
+            fade_start:     15.0,
+            fade_end:       30.0,
+            base_color:     [0.08, 0.22, 0.05],
+            tip_color:      [0.45, 0.65, 0.2],
+            pipeline,
+            uniform,
+            bind_group,
+            vertices,
+            indices,
+            index_count:    mesh_indices.len() as u32,
+            instances,
+            instance_count: scattered.len() as u32,
+            coverage:       alpha_to_coverage,
+            time:           0.0,
+        }
+    }
+
+    pub fn update(&mut self, dt: Duration) {
+        self.time += dt.as_secs_f32();
+    }
+
+    pub fn prepare(&self, queue: &wgpu::Queue) {
+        use cgmath::InnerSpace;
+
+        let direction = if self.wind.direction.magnitude2() > 0.0 { self.wind.direction.normalize() } else { cgmath::Vector2::unit_x() };
+
+        self.uniform.write(queue, &FoliageUniform {
+            base_color:        [self.base_color[0], self.base_color[1], self.base_color[2], 1.0],
+            tip_color:         [self.tip_color[0], self.tip_color[1], self.tip_color[2], 1.0],
+            wind_direction:    direction.into(),
+            wind_strength:     self.wind.strength,
+            wind_frequency:    self.wind.frequency,
+            time:              self.time,
+            fade_start:        self.fade_start,
+            fade_end:          self.fade_end.max(self.fade_start + 0.001),
+            alpha_to_coverage: self.coverage as u32,
+        });
+    }
+
+    // Expects the lights to be bound at group 2 already, as the scene's draws leave them
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, camera_bind_group: &'a wgpu::BindGroup) {
+        crash_report::set_pipeline(render_pass, &self.pipeline, "Foliage Pipeline");
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_bind_group(1, camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertices.slice(..));
+        render_pass.set_vertex_buffer(1, self.instances.slice(..));
+        render_pass.set_index_buffer(self.indices.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.draw_indexed(0..self.index_count, 0, 0..self.instance_count);
+    }
+}
+
+// A strip narrowing to a point, facing +z with its root at the origin
+fn blade_mesh() -> (Vec<FoliageVertex>, Vec<u32>) {
+    let mut vertices = Vec::new();
+    let mut indices  = Vec::new();
+
+    for i in 0..BLADE_SEGMENTS {
+        let t    = i as f32 / BLADE_SEGMENTS as f32;
+        let half = 0.5 * (1.0 - t);
+
+        vertices.push(FoliageVertex { position: [-half, t, 0.0], tex_coords: [0.5 - half, 1.0 - t] });
+        vertices.push(FoliageVertex { position: [ half, t, 0.0], tex_coords: [0.5 + half, 1.0 - t] });
+
+        let (left, right) = (2 * i, 2 * i + 1);
+
+        if i + 1 < BLADE_SEGMENTS {
+            indices.extend([left, right, right + 2, left, right + 2, left + 2]);
+        } else {
+            indices.extend([left, right, right + 1]);
+        }
+    }
+
+    vertices.push(FoliageVertex { position: [0.0, 1.0, 0.0], tex_coords: [0.5, 0.0] });
+
+    (vertices, indices)
+}
+
+// Two quads crossed at right angles, so a card reads as a clump from any side
+fn card_mesh() -> (Vec<FoliageVertex>, Vec<u32>) {
+    let mut vertices = Vec::new();
+    let mut indices  = Vec::new();
+
+    for across in [[1.0, 0.0], [0.0, 1.0]] {
+        let base = vertices.len() as u32;
+
+        for (s, t) in [(-0.5, 0.0), (0.5, 0.0), (0.5, 1.0), (-0.5, 1.0)] {
+            vertices.push(FoliageVertex {
+                position:   [across[0] * s, t, across[1] * s],
+                tex_coords: [s + 0.5, 1.0 - t],
+            });
+        }
+
+        indices.extend([base, base + 1, base + 2, base, base + 2, base + 3]);
+    }
+
+    (vertices, indices)
+}
+
+// A few curved blades in white, cut out by alpha, for the cards to be tinted with
+fn tuft_image() -> image::RgbaImage {
+    let blades = (0..CARD_TUFT_BLADES)
+        .map(|i| {
+            let root   = 0.2 + 0.6 * (i as f32 + 0.5) / CARD_TUFT_BLADES as f32;
+            let lean   = (hash(i, 0, 1) - 0.5) * 0.5;
+            let height = 0.6 + 0.4 * hash(i, 0, 2);
+
+            (root, lean, height)
+        })
+        .collect::<Vec<_>>();
+
+    let size = CARD_TEXTURE_SIZE as f32;
+
+    image::RgbaImage::from_fn(CARD_TEXTURE_SIZE, CARD_TEXTURE_SIZE, |x, y| {
+        let u = (x as f32 + 0.5) / size;
+        // Up from the bottom of the image
+        let h = 1.0 - (y as f32 + 0.5) / size;
+
+        let covered = blades.iter().any(|&(root, lean, height)| {
+            let t = h / height;
+
+            t < 1.0 && (u - (root + lean * t * t)).abs() < 0.04 * (1.0 - t)
+        });
+
+        image::Rgba([255, 255, 255, if covered { 255 } else { 0 }])
+    })
+}
+
+// Instances on a jittered grid over `area`, each with its own size, facing and sway
+fn scatter(kind: FoliageKind, area: FoliageArea, height_at: impl Fn(f32, f32) -> f32) -> Vec<FoliageInstance> {
+    let spacing = 1.0 / area.density.max(0.001).sqrt();
+    let cells   = (2.0 * area.half_size / spacing).ceil() as u32;
+    let [width, height] = kind.size();
+
+    (0..cells)
+        .flat_map(|z| (0..cells).map(move |x| (x, z)))
+        .map(|(x, z)| {
+            let px = area.center.x - area.half_size + (x as f32 + hash(x, z, 0)) * spacing;
+            let pz = area.center.y - area.half_size + (z as f32 + hash(x, z, 1)) * spacing;
+            let scale = 0.7 + 0.6 * hash(x, z, 2);
+
+            FoliageInstance {
+                position:  [px, height_at(px, pz), pz],
+                yaw:       hash(x, z, 3) * std::f32::consts::TAU,
+                size:      [width * scale, height * scale],
+                phase:     hash(x, z, 4) * std::f32::consts::TAU,
+                threshold: hash(x, z, 5),
+            }
+        })
+        .collect()
+}
+
+// A repeatable value in [0, 1) for a grid cell, with `salt` picking between independent values
+fn hash(x: u32, y: u32, salt: u32) -> f32 {
+    let mut h = x.wrapping_mul(0x8da6_b343) ^ y.wrapping_mul(0xd816_3841) ^ salt.wrapping_mul(0xcb1a_b31f);
+
+    h ^= h >> 16;
+    h  = h.wrapping_mul(0x7feb_352d);
+    h ^= h >> 15;
+    h  = h.wrapping_mul(0x846c_a68b);
+    h ^= h >> 16;
+
+    (h >> 8) as f32 / (1 << 24) as f32
+}
@@ -0,0 +1,120 @@
+// Instanced grass and foliage. Blades bend with the wind towards their tips and thin out with
+// distance from the camera. Gets `camera.wgsl` and `lights.wgsl` prepended.
+
+struct FoliageUniform {
+    base_color:        vec4<f32>,
+    tip_color:         vec4<f32>,
+    // Normalized, in the xz plane
+    wind_direction:    vec2<f32>,
+    wind_strength:     f32,
+    wind_frequency:    f32,
+    time:              f32,
+    // Distances over which density falls from full to none
+    fade_start:        f32,
+    fade_end:          f32,
+    alpha_to_coverage: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> foliage: FoliageUniform;
+@group(0) @binding(1)
+var t_foliage: texture_2d<f32>;
+@group(0) @binding(2)
+var s_foliage: sampler;
+
+struct VertexInput {
+    // x across the blade, y from 0.0 at the root to 1.0 at the tip, both before scaling
+    @location(0) position:   vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+}
+
+struct InstanceInput {
+    @location(2) position:  vec3<f32>,
+    @location(3) yaw:       f32,
+    @location(4) size:      vec2<f32>,
+    @location(5) phase:     f32,
+    // The instance is drawn while density at its distance is above this
+    @location(6) threshold: f32,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords:     vec2<f32>,
+    @location(1) world_normal:   vec3<f32>,
+    @location(2) world_position: vec3<f32>,
+    @location(3) color:          vec3<f32>,
+}
+
+@vertex
+fn vs_main(model: VertexInput, instance: InstanceInput) -> VertexOutput {
+    var out: VertexOutput;
+
+    let distance_to_camera = distance(camera.view_position.xz, instance.position.xz);
+    let density            = 1.0 - smoothstep(foliage.fade_start, foliage.fade_end, distance_to_camera);
+
+    out.tex_coords     = model.tex_coords;
+    out.world_normal   = vec3<f32>(0.0, 1.0, 0.0);
+    out.world_position = instance.position;
+    out.color          = foliage.base_color.rgb;
+
+    // Thinned out instances collapse outside the clip volume, so their triangles are dropped
+    if (instance.threshold >= density) {
+        out.clip_position = vec4<f32>(0.0, 0.0, -2.0, 1.0);
+        return out;
+    }
+
+    // What's left widens to cover for what's gone
+    let width  = instance.size.x * (2.0 - density);
+    let height = instance.size.y;
+    let local  = vec3<f32>(model.position.x * width, model.position.y * height, model.position.z * width);
+
+    let c       = cos(instance.yaw);
+    let s       = sin(instance.yaw);
+    let rotated = vec3<f32>(c * local.x + s * local.z, local.y, c * local.z - s * local.x);
+    let facing  = vec3<f32>(s, 0.0, c);
+
+    // A slow sway that rolls across the field, plus a faster flutter, both growing towards the tip
+    let along   = dot(instance.position.xz, foliage.wind_direction);
+    let sway    = sin(foliage.time * foliage.wind_frequency - along * 0.3 + instance.phase);
+    let flutter = sin(foliage.time * foliage.wind_frequency * 2.7 + instance.phase * 3.0);
+    let bend    = foliage.wind_strength * (0.7 * (sway * 0.5 + 0.5) + 0.3 * flutter) * model.position.y * model.position.y;
+    let wind    = vec3<f32>(foliage.wind_direction.x, 0.0, foliage.wind_direction.y);
+
+    // Dipping the tip as it bends keeps the blade from stretching
+    let world = instance.position + rotated + wind * bend * height - vec3<f32>(0.0, 0.5 * bend * bend * height, 0.0);
+
+    out.world_normal   = facing;
+    out.world_position = world;
+    out.color          = mix(foliage.base_color.rgb, foliage.tip_color.rgb, model.position.y);
+    out.clip_position  = camera.view_proj * vec4<f32>(world, 1.0);
+
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput, @builtin(front_facing) front: bool) -> @location(0) vec4<f32> {
+    // Sampled, and alpha's derivative taken, before anything's discarded, which would leave them
+    // out of uniform control flow
+    let texel = textureSample(t_foliage, s_foliage, in.tex_coords);
+    let width = max(fwidth(texel.a), 0.0001);
+
+    if (dot(camera.clip_plane, vec4<f32>(in.world_position, 1.0)) < 0.0) {
+        discard;
+    }
+
+    var alpha = texel.a;
+
+    if (foliage.alpha_to_coverage != 0u) {
+        // Sharpened to about a pixel wide, so coverage gives a crisp but antialiased edge
+        alpha = clamp((alpha - 0.5) / width + 0.5, 0.0, 1.0);
+    } else if (alpha < 0.5) {
+        discard;
+    }
+
+    // Blades are drawn double sided, so the back face lights with the flipped normal. Tilting it
+    // towards the sky lights thin blades more evenly than their true normal would.
+    let normal = normalize(select(-in.world_normal, in.world_normal, front) + vec3<f32>(0.0, 1.5, 0.0));
+    let spot   = spot_lighting(in.world_position, normal);
+
+    return vec4<f32>(texel.rgb * in.color * (probe_lighting(in.world_position, normal) + spot), alpha);
+}
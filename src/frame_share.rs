@@ -0,0 +1,408 @@
+// Hands each rendered frame to another application, like OBS or Resolume, so the renderer can be
+// a video source. The scene is copied out after the post effects, before debug geometry and the
+// UI are drawn, so what's shared is the clean picture at the render resolution.
+//
+// Frames are read back into a ring of buffers and passed on a couple of frames later, once the
+// GPU is done with them, rather than stalling to wait. If every buffer is still in flight, a
+// frame is skipped instead. `FrameSink` takes the pixels from there. NDI is built in behind the
+// `ndi` feature and works across platforms and networks. Spout and Syphon share GPU textures
+// through DirectX and Metal interop that wgpu doesn't expose, so they'd need a sink that uploads
+// the pixels themselves; receivers for both usually take NDI as well.
+
+use std::{cell::Cell, sync::mpsc};
+
+use crate::{
+    bind_group,
+    crash_report,
+    pass::{PostEffect, RenderLayers},
+    plugin::Plugin,
+    renderer::{self, Frame, GpuContext},
+};
+
+// Frames that can be in flight between being copied and passed on
+const READBACK_BUFFERS: usize = 3;
+
+// Where shared frames go. Rows of `rgba` are `stride` bytes apart, which may be more than
+// `width * 4`, and colors are display encoded.
+pub trait FrameSink {
+    fn send(&mut self, width: u32, height: u32, stride: u32, rgba: &[u8]);
+}
+
+enum Readback {
+    Free,
+    // The copy is recorded into this frame, so it's been submitted by the next `prepare`
+    Copied,
+    Mapping(mpsc::Receiver<Result<(), wgpu::BufferAsyncError>>),
+}
+
+struct Slot {
+    buffer: wgpu::Buffer,
+    state:  Readback,
+}
+
+// The GPU side: a post effect that copies the scene into the ring, made once the context exists
+struct FrameCopy {
+    pipeline: wgpu::RenderPipeline,
+    layout:   wgpu::BindGroupLayout,
+    format:   wgpu::TextureFormat,
+    size:     winit::dpi::PhysicalSize<u32>,
+    texture:  wgpu::Texture,
+    view:     wgpu::TextureView,
+    slots:    Vec<Slot>,
+    // Which slot this frame is copied into, if any is free
+    current:  Cell<Option<usize>>,
+}
+
+impl FrameCopy {
+    fn new(ctx: &GpuContext) -> Self {
+        let device = &ctx.device;
+
+        let layout = bind_group::BindGroupLayoutBuilder::new()
+            .unfilterable_texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .build(device, "frame_share_bind_group_layout");
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Frame Share Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("frame_share.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Frame Share Pipeline Layout"),
+            bind_group_layouts:   &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        // Encoded the way the surface is, so the bytes come out display encoded either way
+        let format = if ctx.config.format.describe().srgb {
+            wgpu::TextureFormat::Rgba8UnormSrgb
+        } else {
+            wgpu::TextureFormat::Rgba8Unorm
+        };
+
+        let pipeline = renderer::create_render_pipeline(
+            device,
+            &pipeline_layout,
+            format,
+            None,
+            &[],
+            &shader,
+            "Frame Share Pipeline",
+        );
+
+        let size            = ctx.render_size();
+        let (texture, view) = create_target(device, format, size);
+
+        Self {
+            pipeline,
+            layout,
+            format,
+            size,
+            texture,
+            view,
+            slots:   create_slots(device, size),
+            current: Cell::new(None),
+        }
+    }
+
+    // Passes on the frames that have finished reading back, starts mapping the ones copied last
+    // frame and picks a slot for this one
+    fn prepare(&mut self, ctx: &GpuContext, sink: &mut dyn FrameSink) {
+        let device = &ctx.device;
+
+        if ctx.render_size() != self.size {
+            // Frames still in flight are dropped along with their buffers
+            self.size                 = ctx.render_size();
+            (self.texture, self.view) = create_target(device, self.format, self.size);
+            self.slots                = create_slots(device, self.size);
+        }
+
+        device.poll(wgpu::Maintain::Poll);
+
+        let stride = padded_row(self.size.width);
+
+        for slot in &mut self.slots {
+            let finished = match &slot.state {
+                Readback::Mapping(receiver) => match receiver.try_recv() {
+                    Ok(result)                            => Some(result),
+                    Err(mpsc::TryRecvError::Empty)        => None,
+                    Err(mpsc::TryRecvError::Disconnected) => Some(Err(wgpu::BufferAsyncError)),
+                },
+                _ => None,
+            };
+
+            match finished {
+                Some(Ok(())) => {
+                    sink.send(self.size.width, self.size.height, stride, &slot.buffer.slice(..).get_mapped_range());
+                    slot.buffer.unmap();
+                    slot.state = Readback::Free;
+                }
+                Some(Err(e)) => {
+                    log::warn!("Couldn't read back a shared frame: {}", e);
+                    slot.state = Readback::Free;
+                }
+                None => {}
+            }
+        }
+
+        for slot in &mut self.slots {
+            if let Readback::Copied = slot.state {
+                let (sender, receiver) = mpsc::channel();
+
+                slot.buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| { let _ = sender.send(result); });
+                slot.state = Readback::Mapping(receiver);
+            }
+        }
+
+        let free = self.slots.iter().position(|slot| matches!(slot.state, Readback::Free));
+
+        if let Some(i) = free {
+            self.slots[i].state = Readback::Copied;
+        } else {
+            log::debug!("Every frame share buffer is in flight, skipping a frame");
+        }
+
+        self.current.set(free);
+    }
+}
+
+impl PostEffect for FrameCopy {
+    fn apply(
+        &self,
+        device:  &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        input:   &wgpu::TextureView,
+        _output: &wgpu::TextureView,
+    ) {
+        let slot = match self.current.get() {
+            Some(slot) => &self.slots[slot],
+            None       => return,
+        };
+
+        let bind_group = bind_group::BindGroupBuilder::new(&self.layout)
+            .texture(input)
+            .build(device, "frame_share_bind_group");
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Frame Share Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view:           &self.view,
+                    resolve_target: None,
+                    ops:  wgpu::Operations {
+                        load:  wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+
+            crash_report::set_pipeline(&mut render_pass, &self.pipeline, "Frame Share Pipeline");
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                aspect:    wgpu::TextureAspect::All,
+                texture:   &self.texture,
+                mip_level: 0,
+                origin:    wgpu::Origin3d::ZERO,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &slot.buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset:         0,
+                    bytes_per_row:  std::num::NonZeroU32::new(padded_row(self.size.width)),
+                    rows_per_image: std::num::NonZeroU32::new(self.size.height),
+                },
+            },
+            wgpu::Extent3d { width: self.size.width, height: self.size.height, depth_or_array_layers: 1 },
+        );
+    }
+
+    // Only looks at the scene
+    fn writes_output(&self) -> bool {
+        false
+    }
+}
+
+// Shares every frame with `sink`. Registered after the app's effects, so it sees all of them.
+pub struct FrameShare {
+    sink: Box<dyn FrameSink>,
+    copy: Option<FrameCopy>,
+}
+
+impl FrameShare {
+    pub fn new(sink: impl FrameSink + 'static) -> Self {
+        Self { sink: Box::new(sink), copy: None }
+    }
+}
+
+impl Plugin for FrameShare {
+    fn name(&self) -> &'static str {
+        "Frame Share"
+    }
+
+    fn setup(&mut self, ctx: &mut GpuContext) {
+        self.copy = Some(FrameCopy::new(ctx));
+    }
+
+    fn render<'a>(&'a mut self, frame: &mut Frame, layers: &mut RenderLayers<'a>) {
+        if let Some(copy) = &mut self.copy {
+            copy.prepare(frame.ctx, self.sink.as_mut());
+            layers.add_effect(copy);
+        }
+    }
+}
+
+fn create_target(
+    device: &wgpu::Device,
+    format: wgpu::TextureFormat,
+    size:   winit::dpi::PhysicalSize<u32>,
+) -> (wgpu::Texture, wgpu::TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label:           Some("Frame Share Texture"),
+        size:            wgpu::Extent3d { width: size.width, height: size.height, depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count:    1,
+        dimension:       wgpu::TextureDimension::D2,
+        format,
+        usage:           wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+    });
+
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    (texture, view)
+}
+
+fn create_slots(device: &wgpu::Device, size: winit::dpi::PhysicalSize<u32>) -> Vec<Slot> {
+    (0..READBACK_BUFFERS)
+        .map(|_| Slot {
+            buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label:              Some("Frame Share Readback Buffer"),
+                size:               (padded_row(size.width) * size.height) as wgpu::BufferAddress,
+                usage:              wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            }),
+            state:  Readback::Free,
+        })
+        .collect()
+}
+
+// Bytes between rows of a read back frame, padded out to the copy alignment
+fn padded_row(width: u32) -> u32 {
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+    (width * 4).div_ceil(align) * align
+}
+
+// Sends frames as an NDI source other applications on the network can pick up. Needs the NDI
+// runtime, which ships with NDI Tools or the SDK, to link against.
+#[cfg(feature = "ndi")]
+pub struct NdiSender {
+    instance: *mut std::ffi::c_void,
+}
+
+#[cfg(feature = "ndi")]
+impl NdiSender {
+    // `name` is what receivers list the source as
+    pub fn new(name: &str) -> anyhow::Result<Self> {
+        if !unsafe { ndi::NDIlib_initialize() } {
+            anyhow::bail!("NDI isn't supported on this CPU");
+        }
+
+        let name   = std::ffi::CString::new(name)?;
+        let create = ndi::SendCreate {
+            name:        name.as_ptr(),
+            groups:      std::ptr::null(),
+            // Frames go out as they're rendered, vsync already paces them
+            clock_video: false,
+            clock_audio: false,
+        };
+        let instance = unsafe { ndi::NDIlib_send_create(&create) };
+
+        if instance.is_null() {
+            anyhow::bail!("Couldn't create an NDI source");
+        }
+
+        log::info!("Sharing frames over NDI as {}", name.to_string_lossy());
+
+        Ok(Self { instance })
+    }
+}
+
+#[cfg(feature = "ndi")]
+impl FrameSink for NdiSender {
+    fn send(&mut self, width: u32, height: u32, stride: u32, rgba: &[u8]) {
+        let frame = ndi::VideoFrame {
+            xres:                 width as i32,
+            yres:                 height as i32,
+            four_cc:              u32::from_le_bytes(*b"RGBA"),
+            frame_rate_n:         60,
+            frame_rate_d:         1,
+            picture_aspect_ratio: width as f32 / height as f32,
+            frame_format_type:    ndi::FRAME_FORMAT_PROGRESSIVE,
+            timecode:             ndi::SEND_TIMECODE_SYNTHESIZE,
+            data:                 rgba.as_ptr(),
+            line_stride_in_bytes: stride as i32,
+            metadata:             std::ptr::null(),
+            timestamp:            0,
+        };
+
+        // Copies the frame before returning
+        unsafe { ndi::NDIlib_send_send_video_v2(self.instance, &frame) };
+    }
+}
+
+#[cfg(feature = "ndi")]
+impl Drop for NdiSender {
+    fn drop(&mut self) {
+        unsafe {
+            ndi::NDIlib_send_destroy(self.instance);
+            ndi::NDIlib_destroy();
+        }
+    }
+}
+
+// The few parts of the NDI SDK's C API that sending video needs
+#[cfg(feature = "ndi")]
+mod ndi {
+    use std::ffi::{c_char, c_void};
+
+    pub const FRAME_FORMAT_PROGRESSIVE: i32 = 1;
+    pub const SEND_TIMECODE_SYNTHESIZE: i64 = i64::MAX;
+
+    #[repr(C)]
+    pub struct SendCreate {
+        pub name:        *const c_char,
+        pub groups:      *const c_char,
+        pub clock_video: bool,
+        pub clock_audio: bool,
+    }
+
+    #[repr(C)]
+    pub struct VideoFrame {
+        pub xres:                 i32,
+        pub yres:                 i32,
+        pub four_cc:              u32,
+        pub frame_rate_n:         i32,
+        pub frame_rate_d:         i32,
+        pub picture_aspect_ratio: f32,
+        pub frame_format_type:    i32,
+        pub timecode:             i64,
+        pub data:                 *const u8,
+        pub line_stride_in_bytes: i32,
+        pub metadata:             *const c_char,
+        pub timestamp:            i64,
+    }
+
+    #[cfg_attr(windows, link(name = "Processing.NDI.Lib.x64"))]
+    #[cfg_attr(not(windows), link(name = "ndi"))]
+    extern "C" {
+        pub fn NDIlib_initialize() -> bool;
+        pub fn NDIlib_destroy();
+        pub fn NDIlib_send_create(create: *const SendCreate) -> *mut c_void;
+        pub fn NDIlib_send_destroy(instance: *mut c_void);
+        pub fn NDIlib_send_send_video_v2(instance: *mut c_void, frame: *const VideoFrame);
+    }
+}
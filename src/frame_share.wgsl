@@ -0,0 +1,18 @@
+// Copies the scene into frame sharing's RGBA texture, which the surface's BGRA can't be copied
+// into directly. Both are the same size, so texels are loaded one for one.
+
+@group(0) @binding(0)
+var scene: texture_2d<f32>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    // One triangle covering the screen
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    return vec4<f32>(textureLoad(scene, vec2<i32>(position.xy), 0).rgb, 1.0);
+}
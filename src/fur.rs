@@ -0,0 +1,109 @@
+// Fur drawn as shells: the mesh is drawn again a layer at a time, each pushed further out along
+// its normals, and each layer only keeps the pixels where a strand reaches that high. Strands are
+// cells of noise over the UVs, thinning towards their tips, so stacked together the layers read as
+// fur. Shading is Kajiya-Kay's, lighting along the strands rather than across the surface.
+//
+// Shells are cut out rather than blended, so they draw in the opaque layer after the surface
+// they grow out of, in any order.
+
+use crate::{
+    bind_group,
+    renderer::{self, GpuContext},
+    shading::CullMode,
+    texture,
+    uniform::{Uniform, UniformBuffer},
+};
+
+// Materials asking for more shells get this many. Also in fur.wgsl.
+pub const MAX_SHELLS: u32 = 32;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct ShellUniform {
+    // Counting out from the surface, the first shell being 0
+    index:    u32,
+    _padding: [u32; 3],
+}
+
+pub struct Fur {
+    // One per cull mode, in `CullMode::ALL` order
+    pipelines: Vec<wgpu::RenderPipeline>,
+    // One per shell, each only ever holding its index
+    shells:    Vec<wgpu::BindGroup>,
+    _buffers:  Vec<UniformBuffer<ShellUniform>>,
+}
+
+impl Fur {
+    pub fn shader_source() -> &'static str {
+        concat!(include_str!("camera.wgsl"), include_str!("lights.wgsl"), include_str!("lod.wgsl"), include_str!("instance.wgsl"), include_str!("fur.wgsl"))
+    }
+
+    // Shells are drawn with the scene's material, camera and lights layouts at groups 0 to 2, and
+    // the scene's vertex layouts
+    pub fn new(
+        ctx:             &GpuContext,
+        material_layout: &wgpu::BindGroupLayout,
+        camera_layout:   &wgpu::BindGroupLayout,
+        lights_layout:   &wgpu::BindGroupLayout,
+        vertex_layouts:  &[wgpu::VertexBufferLayout],
+    ) -> Self {
+        let device = &ctx.device;
+
+        let shell_layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT)
+            .build(device, "fur_shell_bind_group_layout");
+
+        let buffers = (0..MAX_SHELLS)
+            .map(|index| UniformBuffer::with_contents(device, "Fur Shell Buffer", &ShellUniform { index, _padding: [0; 3] }))
+            .collect::<Vec<_>>();
+
+        let shells = buffers.iter()
+            .map(|buffer| bind_group::BindGroupBuilder::new(&shell_layout)
+                .uniform(buffer.buffer())
+                .build(device, "fur_shell_bind_group"))
+            .collect();
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Fur Shader"),
+            source: wgpu::ShaderSource::Wgsl(Self::shader_source().into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Fur Pipeline Layout"),
+            bind_group_layouts:   &[material_layout, camera_layout, lights_layout, &shell_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipelines = CullMode::ALL.iter()
+            .map(|cull| renderer::create_render_pipeline_with_raster(
+                device,
+                &pipeline_layout,
+                ctx.config.format,
+                Some(renderer::depth_state(texture::Texture::DEPTH_FORMAT, ctx.depth_mode.compare(), true)),
+                vertex_layouts,
+                &shader,
+                renderer::RasterState { cull_mode: cull.face(), ..Default::default() },
+                "Fur Pipeline",
+            ))
+            .collect();
+
+        Self {
+            pipelines,
+            shells,
+            _buffers: buffers,
+        }
+    }
+
+    pub fn pipeline(&self, cull: CullMode) -> &wgpu::RenderPipeline {
+        &self.pipelines[cull as usize]
+    }
+
+    // The shells to draw a material's fur with, for group 3 of the fur pipelines
+    pub fn shells(&self, material: &crate::model::Material) -> &[wgpu::BindGroup] {
+        if !material.has_fur() {
+            return &[];
+        }
+
+        &self.shells[..material.params.fur_shells.min(MAX_SHELLS) as usize]
+    }
+}
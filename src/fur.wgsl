@@ -0,0 +1,172 @@
+// Fur shells, see fur.rs. Takes the same vertices, instances and bind groups as shader.wgsl, plus
+// the shell being drawn at group 3.
+
+@group(0) @binding(0)
+var t_diffuse: texture_2d<f32>;
+@group(0) @binding(1)
+var s_diffuse: sampler;
+
+struct MaterialParams {
+    tint:                    vec4<f32>,
+    emissive:                vec3<f32>,
+    emissive_intensity:      f32,
+    alpha_cutoff:            f32,
+    subsurface_wrap:         f32,
+    transmission:            f32,
+    transmission_power:      f32,
+    subsurface_color:        vec3<f32>,
+    transmission_distortion: f32,
+    specular:                f32,
+    roughness:               f32,
+    anisotropy:              f32,
+    anisotropy_rotation:     f32,
+    clearcoat:               f32,
+    clearcoat_roughness:     f32,
+    transmittance:           f32,
+    ior:                     f32,
+    thickness:               f32,
+    triplanar_scale:         f32,
+    triplanar_sharpness:     f32,
+    anisotropy_map:          u32,
+    detail_scale:            f32,
+    detail_distance:         f32,
+    detail_strength:         f32,
+    detail_maps:             u32,
+    lightmap_intensity:      f32,
+    lightmap_map:            u32,
+    fur_length:              f32,
+    fur_density:             f32,
+    fur_shells:              u32,
+    fur_gravity:             f32,
+}
+
+@group(0) @binding(2)
+var<uniform> material: MaterialParams;
+
+struct FurShell {
+    index: u32,
+}
+
+@group(3) @binding(0)
+var<uniform> shell: FurShell;
+
+// How far up the strands the shell is, from just off the surface to 1.0 at the tips. MAX_SHELLS in
+// fur.rs caps the count.
+fn shell_height() -> f32 {
+    return f32(shell.index + 1u) / f32(min(material.fur_shells, 32u));
+}
+
+
+// Vertex shader
+
+struct VertexInput {
+    @location(0) position:   vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) normal:     vec3<f32>,
+    @location(3) color:      vec4<f32>,
+}
+
+struct VertexOutput {
+   @builtin(position) clip_position: vec4<f32>,
+   @location(0) tex_coords:          vec2<f32>,
+   // Along the strand at this height, which gravity bends away from the normal
+   @location(1) strand:              vec3<f32>,
+   @location(2) world_position:      vec3<f32>,
+   @location(3) fade:                f32,
+   @location(4) color:               vec4<f32>,
+}
+
+@vertex
+fn vs_main(
+   model:    VertexInput,
+   instance: InstanceInput,
+) -> VertexOutput {
+    let model_matrix = instance_model_matrix(instance);
+
+    let height  = shell_height();
+    let normal  = normalize(normal_to_world(model_matrix, model.normal));
+    let surface = (model_matrix * vec4<f32>(model.position, 1.0)).xyz;
+    let down    = vec3<f32>(0.0, -1.0, 0.0);
+
+    // Straight out from the surface, drooping quadratically so the tips bend the most
+    let position = surface + (normal * height + down * material.fur_gravity * height * height) * material.fur_length;
+
+    var out: VertexOutput;
+
+    out.tex_coords     = model.tex_coords;
+    out.color          = model.color;
+    out.fade           = instance.model_matrix_0.w;
+    out.strand         = normalize(normal + down * material.fur_gravity * 2.0 * height);
+    out.world_position = position;
+    out.clip_position  = camera.view_proj * vec4<f32>(position, 1.0);
+
+    return out;
+}
+
+
+// Fragment shader
+
+// Two values in [0, 1) for a cell
+fn fur_hash(cell: vec2<f32>) -> vec2<f32> {
+    let p = vec2<f32>(dot(cell, vec2<f32>(127.1, 311.7)), dot(cell, vec2<f32>(269.5, 183.3)));
+
+    return fract(sin(p) * 43758.5453);
+}
+
+// Kajiya-Kay highlight along `strand`, which is brightest where the half vector is across it
+fn strand_specular(strand: vec3<f32>, light_dir: vec3<f32>, view_dir: vec3<f32>, roughness: f32) -> f32 {
+    let half_dir = normalize(light_dir + view_dir);
+    let t_dot_h  = dot(strand, half_dir);
+    let exponent = clamp(2.0 / max(pow(roughness, 4.0), 0.0001) - 2.0, 1.0, 512.0);
+
+    return pow(sqrt(max(1.0 - t_dot_h * t_dot_h, 0.0)), exponent);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Sampled before anything's discarded, which would leave the sample out of uniform control flow
+    let albedo = textureSample(t_diffuse, s_diffuse, in.tex_coords) * material.tint * in.color;
+
+    if (dot(camera.clip_plane, vec4<f32>(in.world_position, 1.0)) < 0.0) {
+        discard;
+    }
+
+    if (lod_dithered_out(in.clip_position.xy, in.fade)) {
+        discard;
+    }
+
+    // Each cell of the UVs grows one strand, somewhere near its middle and up to its own length
+    let height        = shell_height();
+    let cells         = in.tex_coords * material.fur_density;
+    let random        = fur_hash(floor(cells));
+    let strand_length = mix(0.5, 1.0, random.x);
+    let center        = vec2<f32>(0.5) + (random - 0.5) * 0.4;
+
+    // Tapering from half a cell wide at the root to nothing at the tip
+    let radius = 0.5 * (1.0 - height / strand_length);
+
+    if (height > strand_length || distance(fract(cells), center) > radius) {
+        discard;
+    }
+
+    let view_dir = normalize(camera.view_position.xyz - in.world_position);
+
+    // Strands shade each other towards the roots
+    let occlusion = mix(0.3, 1.0, height);
+
+    // Lit by how far across the strand the light is rather than by a surface normal. The highlight
+    // is only there with a `specular`, as with the textured model.
+    var light = albedo.rgb * probe_lighting(in.world_position, in.strand);
+
+    for (var i = 0u; i < lights.spot_count; i = i + 1u) {
+        let spot      = lights.spots[i];
+        let light_dir = normalize(spot.position_range.xyz - in.world_position);
+        let t_dot_l   = dot(in.strand, light_dir);
+        let across    = sqrt(max(1.0 - t_dot_l * t_dot_l, 0.0));
+        let incoming  = spot_incoming(spot, in.world_position);
+
+        light = light + incoming * (albedo.rgb * across + strand_specular(in.strand, light_dir, view_dir, material.roughness) * material.specular);
+    }
+
+    return vec4<f32>(light * occlusion, 1.0);
+}
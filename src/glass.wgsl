@@ -0,0 +1,182 @@
+// Transmissive materials, drawn over a copy of the opaque scene that they refract and blur. Takes
+// the same vertices, instances and bind groups as shader.wgsl, plus the copy at group 3.
+
+// Vertex shader
+
+struct VertexInput {
+    @location(0) position:   vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) normal:     vec3<f32>,
+    @location(3) color:      vec4<f32>,
+}
+
+struct VertexOutput {
+   @builtin(position) clip_position: vec4<f32>,
+   @location(0) tex_coords:          vec2<f32>,
+   @location(1) world_normal:        vec3<f32>,
+   @location(2) world_position:      vec3<f32>,
+   @location(3) fade:                f32,
+   @location(4) color:               vec4<f32>,
+}
+
+@vertex
+fn vs_main(
+   model:    VertexInput,
+   instance: InstanceInput,
+) -> VertexOutput {
+    let model_matrix = instance_model_matrix(instance);
+
+    var out: VertexOutput;
+
+    out.tex_coords     = model.tex_coords;
+    out.color          = model.color;
+    out.fade           = instance.model_matrix_0.w;
+    out.world_normal   = normal_to_world(model_matrix, model.normal);
+    out.world_position = (model_matrix * vec4<f32>(model.position, 1.0)).xyz;
+    out.clip_position  = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
+
+    return out;
+}
+
+
+// Fragment shader
+
+@group(0) @binding(0)
+var t_diffuse: texture_2d<f32>;
+@group(0) @binding(1)
+var s_diffuse: sampler;
+
+struct MaterialParams {
+    tint:                    vec4<f32>,
+    emissive:                vec3<f32>,
+    emissive_intensity:      f32,
+    alpha_cutoff:            f32,
+    subsurface_wrap:         f32,
+    transmission:            f32,
+    transmission_power:      f32,
+    subsurface_color:        vec3<f32>,
+    transmission_distortion: f32,
+    specular:                f32,
+    roughness:               f32,
+    anisotropy:              f32,
+    anisotropy_rotation:     f32,
+    clearcoat:               f32,
+    clearcoat_roughness:     f32,
+    transmittance:           f32,
+    ior:                     f32,
+    thickness:               f32,
+    triplanar_scale:         f32,
+    triplanar_sharpness:     f32,
+    anisotropy_map:          u32,
+    detail_scale:            f32,
+    detail_distance:         f32,
+    detail_strength:         f32,
+    detail_maps:             u32,
+}
+
+@group(0) @binding(2)
+var<uniform> material: MaterialParams;
+@group(0) @binding(3)
+var t_emissive: texture_2d<f32>;
+
+// At the mesh's UVs, or projected along the world axes for triplanar materials
+fn material_texel(t: texture_2d<f32>, tex_coords: vec2<f32>, world_position: vec3<f32>, normal: vec3<f32>) -> vec4<f32> {
+    if (material.triplanar_scale > 0.0) {
+        return triplanar_sample(t, s_diffuse, world_position, normal, material.triplanar_scale, material.triplanar_sharpness);
+    }
+
+    return textureSample(t, s_diffuse, tex_coords);
+}
+
+// The material's detail layer over a surface facing `normal`
+fn material_detail(tex_coords: vec2<f32>, world_position: vec3<f32>, normal: vec3<f32>) -> DetailLayer {
+    return detail_layer(
+        s_diffuse,
+        tex_coords,
+        world_position,
+        normal,
+        camera.view_position.xyz,
+        material.detail_scale,
+        material.detail_distance,
+        material.detail_strength,
+        material.detail_maps,
+    );
+}
+
+// The opaque scene, each level half the size of the one before
+@group(3) @binding(0)
+var t_transmission: texture_2d<f32>;
+@group(3) @binding(1)
+var s_transmission: sampler;
+
+// `i` bent through a surface facing `n` going from one index of refraction into another, `eta`
+// being their ratio. Zero past total internal reflection. Written out since naga has no
+// `refract` yet.
+fn refract_ray(i: vec3<f32>, n: vec3<f32>, eta: f32) -> vec3<f32> {
+    let cos_i = dot(n, i);
+    let k     = 1.0 - eta * eta * (1.0 - cos_i * cos_i);
+
+    if (k < 0.0) {
+        return vec3<f32>(0.0);
+    }
+
+    return eta * i - (eta * cos_i + sqrt(k)) * n;
+}
+
+// What's seen through the surface. The view ray is bent by the surface and followed `thickness`
+// into it, and the scene is read where that point lands on screen. Rough glass reads a smaller,
+// blurrier level.
+fn transmitted_light(world_position: vec3<f32>, normal: vec3<f32>, view_dir: vec3<f32>) -> vec3<f32> {
+    // Zero past total internal reflection, which leaves the point where it is
+    let refracted = refract_ray(-view_dir, normal, 1.0 / max(material.ior, 1.0));
+    let exit      = camera.view_proj * vec4<f32>(world_position + refracted * material.thickness, 1.0);
+    let ndc       = exit.xy / max(exit.w, 0.0001);
+    let uv        = clamp(vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5), vec2<f32>(0.0), vec2<f32>(1.0));
+
+    // Up to the last of transmission.rs's `MAX_LEVELS`, sampling clamps to the last level of
+    // smaller copies. WebGL can't ask the texture how many it has.
+    let level = sqrt(clamp(material.roughness, 0.0, 1.0)) * 5.0;
+
+    return textureSampleLevel(t_transmission, s_transmission, uv, level).rgb;
+}
+
+@fragment
+fn fs_main(in: VertexOutput, @builtin(front_facing) front_facing: bool) -> @location(0) vec4<f32> {
+    if (dot(camera.clip_plane, vec4<f32>(in.world_position, 1.0)) < 0.0) {
+        discard;
+    }
+
+    if (lod_dithered_out(in.clip_position.xy, in.fade)) {
+        discard;
+    }
+
+    let facing   = select(-1.0, 1.0, front_facing) * normalize(in.world_normal);
+    let detail   = material_detail(in.tex_coords, in.world_position, facing);
+    let albedo   = material_texel(t_diffuse, in.tex_coords, in.world_position, in.world_normal) * material.tint * in.color * vec4<f32>(detail.albedo, 1.0);
+    let normal   = detail.normal;
+    let view_dir = normalize(camera.view_position.xyz - in.world_position);
+
+    // Light passing through is tinted by the surface's color, the rest is lit as usual
+    let diffuse = albedo.rgb * (probe_lighting(in.world_position, normal) + spot_lighting(in.world_position, normal));
+    let behind  = transmitted_light(in.world_position, normal, view_dir) * albedo.rgb;
+    let body    = mix(diffuse, behind, clamp(material.transmittance, 0.0, 1.0));
+
+    // Glass always reflects, more so at grazing angles
+    let frame = tangent_frame(normal, in.world_position, in.tex_coords);
+
+    var highlight = vec3<f32>(0.0);
+
+    for (var i = 0u; i < lights.spot_count; i = i + 1u) {
+        let spot      = lights.spots[i];
+        let light_dir = normalize(spot.position_range.xyz - in.world_position);
+
+        highlight = highlight + spot_incoming(spot, in.world_position) * anisotropic_specular(frame, light_dir, view_dir, material.roughness, 0.0);
+    }
+
+    highlight = highlight + rect_specular(in.world_position, normal, view_dir, material.roughness);
+
+    let emission = material_texel(t_emissive, in.tex_coords, in.world_position, in.world_normal).rgb * material.emissive * material.emissive_intensity;
+    let color    = body * (1.0 - fresnel(dot(normal, view_dir))) + highlight + emission;
+
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,281 @@
+// GPU-driven culling of one model's instances. The survivors are written to a buffer that's bound
+// as the instance vertex buffer and drawn with indirect draws, so the CPU never sees the count.
+
+use cgmath::prelude::*;
+
+use crate::{
+    bind_group,
+    bounds::{Aabb, Frustum},
+    camera::DepthMode,
+    crash_report,
+    hiz::HiZPyramid,
+    model::{Instance, Model},
+    renderer::GpuContext,
+    uniform::Uniform,
+};
+
+const WORKGROUP_SIZE: u32 = 64;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+#[uniform(storage)]
+pub struct CullInstance {
+    pub model:      [[f32; 4]; 4],
+    // World space, w unused
+    pub bounds_min: [f32; 4],
+    pub bounds_max: [f32; 4],
+}
+
+impl CullInstance {
+    pub fn new(instance: &Instance, model_bounds: &Aabb) -> Self {
+        let matrix = instance.to_matrix();
+        let bounds = model_bounds.transform(&matrix);
+
+        Self {
+            model:      matrix.into(),
+            bounds_min: bounds.min.to_homogeneous().into(),
+            bounds_max: bounds.max.to_homogeneous().into(),
+        }
+    }
+}
+
+// Arguments of one `draw_indexed_indirect` call
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct DrawArgs {
+    index_count:    u32,
+    instance_count: u32,
+    first_index:    u32,
+    base_vertex:    i32,
+    first_instance: u32,
+}
+
+const DRAW_ARGS_SIZE: u64 = std::mem::size_of::<DrawArgs>() as u64;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct CullParams {
+    prev_view_proj: [[f32; 4]; 4],
+    frustum:        [[f32; 4]; 6],
+    hiz_size:       [f32; 2],
+    hiz_mips:       u32,
+    instance_count: u32,
+    occlusion:      u32,
+    reverse_z:      u32,
+    _padding:       [u32; 2],
+}
+
+pub struct GpuCuller {
+    pub hiz:           HiZPyramid,
+    // Occlusion tests are on when true, frustum tests always run
+    pub occlusion:     bool,
+    cull_pipeline:     wgpu::ComputePipeline,
+    finalize_pipeline: wgpu::ComputePipeline,
+    layout:            wgpu::BindGroupLayout,
+    bind_group:        wgpu::BindGroup,
+    params_buffer:     wgpu::Buffer,
+    instance_buffer:   wgpu::Buffer,
+    visible_buffer:    wgpu::Buffer,
+    args_buffer:       wgpu::Buffer,
+    // Index counts of the model's meshes, written into the args each frame
+    index_counts:      Vec<u32>,
+    instance_count:    u32,
+    depth_mode:        DepthMode,
+    prev_view_proj:    cgmath::Matrix4<f32>,
+    // The depth buffer only holds a usable frame after one has been drawn at the current size
+    has_history:       bool,
+}
+
+impl GpuCuller {
+    pub fn new(ctx: &GpuContext, model: &Model, capacity: usize) -> Self {
+        let device = &ctx.device;
+
+        let layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::COMPUTE)
+            .storage(wgpu::ShaderStages::COMPUTE, true)
+            .storage(wgpu::ShaderStages::COMPUTE, false)
+            .storage(wgpu::ShaderStages::COMPUTE, false)
+            .unfilterable_texture(wgpu::ShaderStages::COMPUTE, wgpu::TextureViewDimension::D2)
+            .build(device, "cull_bind_group_layout");
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Cull Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("cull.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Cull Pipeline Layout"),
+            bind_group_layouts:   &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        let create_pipeline = |entry_point, label| device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label:  Some(label),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point,
+        });
+
+        let cull_pipeline     = create_pipeline("cs_main", "Cull Pipeline");
+        let finalize_pipeline = create_pipeline("cs_finalize", "Cull Finalize Pipeline");
+
+        let create_buffer = |label, size, usage| device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size,
+            usage,
+            mapped_at_creation: false,
+        });
+
+        let params_buffer   = create_buffer(
+            "Cull Params Buffer",
+            std::mem::size_of::<CullParams>() as u64,
+            wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        );
+        let instance_buffer = create_buffer(
+            "Cull Instance Buffer",
+            (capacity * std::mem::size_of::<CullInstance>()) as u64,
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        );
+        let visible_buffer  = create_buffer(
+            "Visible Instance Buffer",
+            (capacity * std::mem::size_of::<[[f32; 4]; 4]>()) as u64,
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::VERTEX,
+        );
+        let args_buffer     = create_buffer(
+            "Cull Draw Args Buffer",
+            model.meshes.len() as u64 * DRAW_ARGS_SIZE,
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::INDIRECT | wgpu::BufferUsages::COPY_DST,
+        );
+
+        let hiz        = HiZPyramid::new(ctx);
+        let bind_group = create_bind_group(device, &layout, &params_buffer, &instance_buffer, &visible_buffer, &args_buffer, &hiz);
+
+        Self {
+            hiz,
+            occlusion: true,
+            cull_pipeline,
+            finalize_pipeline,
+            layout,
+            bind_group,
+            params_buffer,
+            instance_buffer,
+            visible_buffer,
+            args_buffer,
+            index_counts:   model.meshes.iter().map(|mesh| mesh.num_elements).collect(),
+            instance_count: 0,
+            depth_mode:     ctx.depth_mode,
+            prev_view_proj: cgmath::Matrix4::identity(),
+            has_history:    false,
+        }
+    }
+
+    // The pyramid has to match the depth buffer, so it's rebuilt along with it
+    pub fn resize(&mut self, ctx: &GpuContext) {
+        self.hiz         = HiZPyramid::new(ctx);
+        self.bind_group  = create_bind_group(
+            &ctx.device,
+            &self.layout,
+            &self.params_buffer,
+            &self.instance_buffer,
+            &self.visible_buffer,
+            &self.args_buffer,
+            &self.hiz,
+        );
+        self.has_history = false;
+    }
+
+    pub fn set_instances(&mut self, queue: &wgpu::Queue, instances: &[CullInstance]) {
+        queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(instances));
+        self.instance_count = instances.len() as u32;
+    }
+
+    // Must be recorded before the frame's depth is cleared, since the pyramid is built from the
+    // previous frame's depth. Objects that come out from behind an occluder show up a frame late.
+    pub fn cull(
+        &mut self,
+        ctx:       &GpuContext,
+        encoder:   &mut wgpu::CommandEncoder,
+        view_proj: cgmath::Matrix4<f32>,
+    ) {
+        let occlusion = self.occlusion && self.has_history;
+
+        if occlusion {
+            self.hiz.build(&ctx.device, encoder, &ctx.depth_texture.view);
+        }
+
+        let args = self.index_counts.iter()
+            .map(|&index_count| DrawArgs {
+                index_count,
+                instance_count: 0,
+                first_index:    0,
+                base_vertex:    0,
+                first_instance: 0,
+            })
+            .collect::<Vec<_>>();
+
+        let params = CullParams {
+            prev_view_proj: self.prev_view_proj.into(),
+            frustum:        Frustum::from_view_proj(&view_proj).planes().map(Into::into),
+            hiz_size:       [self.hiz.size.0 as f32, self.hiz.size.1 as f32],
+            hiz_mips:       self.hiz.mip_count,
+            instance_count: self.instance_count,
+            occlusion:      occlusion as u32,
+            reverse_z:      (self.depth_mode == DepthMode::ReverseZ) as u32,
+            _padding:       [0; 2],
+        };
+
+        ctx.queue.write_buffer(&self.args_buffer, 0, bytemuck::cast_slice(&args));
+        ctx.queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[params]));
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Cull Pass"),
+        });
+
+        compute_pass.set_bind_group(0, &self.bind_group, &[]);
+        crash_report::set_compute_pipeline(&mut compute_pass, &self.cull_pipeline, "Cull Pipeline");
+        compute_pass.dispatch_workgroups(self.instance_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+        crash_report::set_compute_pipeline(&mut compute_pass, &self.finalize_pipeline, "Cull Finalize Pipeline");
+        compute_pass.dispatch_workgroups(1, 1, 1);
+
+        self.prev_view_proj = view_proj;
+        self.has_history    = true;
+    }
+
+    // Draws one mesh of `model` with the surviving instances bound to vertex slot 1. The caller
+    // sets the pipeline, so meshes can be drawn with different ones.
+    pub fn draw_mesh<'a>(
+        &'a self,
+        render_pass:       &mut wgpu::RenderPass<'a>,
+        model:             &'a Model,
+        index:             usize,
+        camera_bind_group: &'a wgpu::BindGroup,
+    ) {
+        let mesh     = &model.meshes[index];
+        let material = &model.materials[mesh.material];
+
+        render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.visible_buffer.slice(..));
+        render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.set_bind_group(0, &material.bind_group, &[]);
+        render_pass.set_bind_group(1, camera_bind_group, &[]);
+        render_pass.draw_indexed_indirect(&self.args_buffer, index as u64 * DRAW_ARGS_SIZE);
+    }
+}
+
+fn create_bind_group(
+    device:          &wgpu::Device,
+    layout:          &wgpu::BindGroupLayout,
+    params_buffer:   &wgpu::Buffer,
+    instance_buffer: &wgpu::Buffer,
+    visible_buffer:  &wgpu::Buffer,
+    args_buffer:     &wgpu::Buffer,
+    hiz:             &HiZPyramid,
+) -> wgpu::BindGroup {
+    bind_group::BindGroupBuilder::new(layout)
+        .uniform(params_buffer)
+        .storage(instance_buffer)
+        .storage(visible_buffer)
+        .storage(args_buffer)
+        .texture(&hiz.view)
+        .build(device, "cull_bind_group")
+}
@@ -0,0 +1,391 @@
+// What's on the GPU and what each frame drew, for finding out where memory and time go. Buffers
+// and textures made by the library's wrappers, `UniformBuffer`, `Texture` and the meshes
+// resources.rs loads, carry a `Tracked` that keeps them listed until they're dropped. Draws
+// through `DrawModel` are counted against the layer pass they're recorded into, and other
+// drawables can count theirs with `record_draw`. Pass times arrive from pass_timing.rs as frames
+// finish. With the `egui` feature `show` puts it all in an egui window, and otherwise `Inspector`
+// shows it in a `Ui`.
+//
+// Resources are made all over with only a device to hand, so the records are process wide rather
+// than kept by the GPU context. Anything created straight from the device isn't listed.
+
+use std::{cmp::Reverse, collections::BTreeMap, fmt::Write, sync::Mutex};
+
+use crate::{
+    pass_timing::PassTiming,
+    ui::{Anchor, BitmapFont, Ui, UiId, UiKind, UiNode},
+};
+
+// Resources listed by the inspector, the biggest first
+const INSPECTOR_RESOURCES: usize = 12;
+
+static STATS: Mutex<Stats> = Mutex::new(Stats {
+    next_id:    0,
+    resources:  BTreeMap::new(),
+    pass:       None,
+    drawing:    Vec::new(),
+    last_frame: Vec::new(),
+    timings:    Vec::new(),
+});
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ResourceKind {
+    Buffer,
+    Texture,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResourceInfo {
+    pub kind:  ResourceKind,
+    pub label: String,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassStats {
+    pub label:     &'static str,
+    pub draws:     u32,
+    pub triangles: u64,
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct MemoryTotals {
+    pub buffers:       usize,
+    pub buffer_bytes:  u64,
+    pub textures:      usize,
+    pub texture_bytes: u64,
+}
+
+struct Stats {
+    next_id:    u64,
+    resources:  BTreeMap<u64, ResourceInfo>,
+    // The pass draws are being counted against
+    pass:       Option<&'static str>,
+    drawing:    Vec<PassStats>,
+    last_frame: Vec<PassStats>,
+    // Of the latest frame timed, which may be a few behind `last_frame`
+    timings:    Vec<PassTiming>,
+}
+
+fn stats() -> std::sync::MutexGuard<'static, Stats> {
+    // Nothing panics while holding the lock, but the counts are still worth showing if it did
+    STATS.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// Keeps a resource listed until it's dropped, so it's held beside the buffer or texture it stands
+// for
+#[derive(Debug)]
+pub struct Tracked(u64);
+
+impl Tracked {
+    pub fn new(kind: ResourceKind, label: Option<&str>, bytes: u64) -> Self {
+        let mut stats = stats();
+        let id        = stats.next_id;
+
+        stats.next_id += 1;
+        stats.resources.insert(id, ResourceInfo {
+            kind,
+            label: label.unwrap_or("unlabelled").to_string(),
+            bytes,
+        });
+
+        Self(id)
+    }
+
+    pub fn buffer(label: Option<&str>, bytes: u64) -> Self {
+        Self::new(ResourceKind::Buffer, label, bytes)
+    }
+
+    // Every mip, layer and sample of the texture `desc` describes
+    pub fn texture(desc: &wgpu::TextureDescriptor) -> Self {
+        let info                        = desc.format.describe();
+        let (block_width, block_height) = (info.block_dimensions.0 as u64, info.block_dimensions.1 as u64);
+
+        let bytes = (0..desc.mip_level_count)
+            .map(|mip| {
+                let size   = desc.size.mip_level_size(mip, desc.dimension == wgpu::TextureDimension::D3);
+                let blocks = (size.width as u64).div_ceil(block_width) * (size.height as u64).div_ceil(block_height);
+
+                blocks * info.block_size as u64 * size.depth_or_array_layers as u64
+            })
+            .sum::<u64>() * desc.sample_count as u64;
+
+        Self::new(ResourceKind::Texture, desc.label, bytes)
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        stats().resources.remove(&self.0);
+    }
+}
+
+// `Device::create_texture`, listed for as long as the `Tracked` is kept
+pub fn create_texture(device: &wgpu::Device, desc: &wgpu::TextureDescriptor) -> (wgpu::Texture, Tracked) {
+    (device.create_texture(desc), Tracked::texture(desc))
+}
+
+// `DeviceExt::create_buffer_init`, listed for as long as the `Tracked` is kept
+pub fn create_buffer_init(device: &wgpu::Device, desc: &wgpu::util::BufferInitDescriptor) -> (wgpu::Buffer, Tracked) {
+    use wgpu::util::DeviceExt;
+
+    (device.create_buffer_init(desc), Tracked::buffer(desc.label, desc.contents.len() as u64))
+}
+
+// Everything listed, the biggest first
+pub fn resources() -> Vec<ResourceInfo> {
+    let mut resources = stats().resources.values().cloned().collect::<Vec<_>>();
+
+    resources.sort_by_key(|resource| Reverse(resource.bytes));
+    resources
+}
+
+pub fn totals() -> MemoryTotals {
+    stats().resources.values().fold(MemoryTotals::default(), |mut totals, resource| {
+        match resource.kind {
+            ResourceKind::Buffer => {
+                totals.buffers      += 1;
+                totals.buffer_bytes += resource.bytes;
+            }
+            ResourceKind::Texture => {
+                totals.textures      += 1;
+                totals.texture_bytes += resource.bytes;
+            }
+        }
+
+        totals
+    })
+}
+
+// Each pass the last presented frame drew anything in, in the order they ran
+pub fn passes() -> Vec<PassStats> {
+    stats().last_frame.clone()
+}
+
+// Each timed pass of the latest frame whose times have arrived, in the order they ran
+pub fn timings() -> Vec<PassTiming> {
+    stats().timings.clone()
+}
+
+pub(crate) fn record_timings(timings: Vec<PassTiming>) {
+    stats().timings = timings;
+}
+
+// Counts a draw of `vertices` as triangles against the pass being recorded. Draws outside the
+// layer passes aren't counted.
+pub fn record_draw(vertices: u32, instances: u32) {
+    let mut guard = stats();
+    let stats     = &mut *guard;
+
+    let label = match stats.pass {
+        Some(label) => label,
+        None        => return,
+    };
+
+    if !stats.drawing.iter().any(|pass| pass.label == label) {
+        stats.drawing.push(PassStats { label, draws: 0, triangles: 0 });
+    }
+
+    let pass = stats.drawing.iter_mut().find(|pass| pass.label == label).unwrap();
+
+    pass.draws     += 1;
+    pass.triangles += vertices as u64 / 3 * instances as u64;
+}
+
+pub(crate) fn begin_pass(label: &'static str) {
+    stats().pass = Some(label);
+}
+
+pub(crate) fn end_pass() {
+    stats().pass = None;
+}
+
+pub(crate) fn end_frame() {
+    let mut stats = stats();
+
+    stats.last_frame = std::mem::take(&mut stats.drawing);
+}
+
+// A panel of text in the top left listing memory totals, the passes of the last frame, what each
+// timed pass cost and the biggest resources. `update` every so often, since the text is laid out again each time.
+pub struct Inspector {
+    root: UiId,
+    text: UiId,
+}
+
+impl Inspector {
+    pub fn new(ui: &mut Ui, font: BitmapFont, text_size: f32) -> Self {
+        let root = ui.add(UiNode::new(Anchor::TopLeft, [8.0, 8.0], [0.0, 0.0], UiKind::Panel { color: [0.0, 0.0, 0.0, 0.6] }));
+        let text = ui.add(UiNode::new(Anchor::TopLeft, [6.0, 6.0], [0.0, 0.0], UiKind::Text {
+            font,
+            text:  String::new(),
+            size:  text_size,
+            color: [1.0; 4],
+        }).with_parent(root));
+
+        Self { root, text }
+    }
+
+    pub fn set_visible(&self, ui: &mut Ui, visible: bool) {
+        if let Some(root) = ui.node_mut(self.root) {
+            root.visible = visible;
+        }
+    }
+
+    pub fn is_visible(&self, ui: &Ui) -> bool {
+        ui.node(self.root).is_some_and(|root| root.visible)
+    }
+
+    pub fn update(&self, ui: &mut Ui) {
+        if !self.is_visible(ui) {
+            return;
+        }
+
+        let report = report();
+
+        let (font, text_size) = match ui.node(self.text) {
+            Some(UiNode { kind: UiKind::Text { font, size, .. }, .. }) => (*font, *size),
+            _ => return,
+        };
+
+        let size = ui.text_size(&font, &report, text_size);
+
+        if let Some(UiNode { kind: UiKind::Text { text, .. }, size: node_size, .. }) = ui.node_mut(self.text) {
+            *text      = report;
+            *node_size = size;
+        }
+
+        if let Some(root) = ui.node_mut(self.root) {
+            root.size = [size[0] + 12.0, size[1] + 12.0];
+        }
+    }
+}
+
+// The same as `Inspector`, as an egui window listing every resource rather than the biggest
+#[cfg(feature = "egui")]
+pub fn show(ctx: &egui::Context) {
+    egui::Window::new("GPU").default_pos([8.0, 8.0]).show(ctx, |ui| {
+        let totals = totals();
+
+        egui::Grid::new("gpu_totals").num_columns(3).show(ui, |ui| {
+            ui.label("Buffers");
+            ui.label(totals.buffers.to_string());
+            ui.label(format_bytes(totals.buffer_bytes));
+            ui.end_row();
+
+            ui.label("Textures");
+            ui.label(totals.textures.to_string());
+            ui.label(format_bytes(totals.texture_bytes));
+            ui.end_row();
+        });
+
+        egui::CollapsingHeader::new("Passes").default_open(true).show(ui, |ui| {
+            egui::Grid::new("gpu_passes").num_columns(3).striped(true).show(ui, |ui| {
+                ui.strong("Pass");
+                ui.strong("Draws");
+                ui.strong("Triangles");
+                ui.end_row();
+
+                for pass in passes() {
+                    ui.label(pass.label);
+                    ui.label(pass.draws.to_string());
+                    ui.label(pass.triangles.to_string());
+                    ui.end_row();
+                }
+            });
+        });
+
+        let timings = timings();
+
+        if !timings.is_empty() {
+            egui::CollapsingHeader::new("Timings").default_open(true).show(ui, |ui| {
+                egui::Grid::new("gpu_timings").num_columns(3).striped(true).show(ui, |ui| {
+                    ui.strong("Pass");
+                    ui.strong("CPU ms");
+                    ui.strong("GPU ms");
+                    ui.end_row();
+
+                    for timing in &timings {
+                        ui.label(timing.label);
+                        ui.label(format!("{:.2}", timing.cpu_ms));
+                        ui.label(timing.gpu_ms.map_or_else(|| "-".to_string(), |ms| format!("{:.2}", ms)));
+                        ui.end_row();
+                    }
+
+                    ui.strong("Total");
+                    ui.strong(format!("{:.2}", timings.iter().map(|timing| timing.cpu_ms).sum::<f32>()));
+                    ui.strong(format!("{:.2}", timings.iter().filter_map(|timing| timing.gpu_ms).sum::<f32>()));
+                    ui.end_row();
+                });
+            });
+        }
+
+        egui::CollapsingHeader::new("Resources").show(ui, |ui| {
+            egui::ScrollArea::vertical().max_height(240.0).show(ui, |ui| {
+                egui::Grid::new("gpu_resources").num_columns(3).striped(true).show(ui, |ui| {
+                    for resource in resources() {
+                        ui.label(match resource.kind {
+                            ResourceKind::Buffer  => "Buffer",
+                            ResourceKind::Texture => "Texture",
+                        });
+                        ui.label(format_bytes(resource.bytes));
+                        ui.label(resource.label);
+                        ui.end_row();
+                    }
+                });
+            });
+        });
+    });
+}
+
+fn report() -> String {
+    let totals     = totals();
+    let mut report = String::new();
+
+    let _ = writeln!(report, "Buffers  {:>5}  {}", totals.buffers, format_bytes(totals.buffer_bytes));
+    let _ = writeln!(report, "Textures {:>5}  {}", totals.textures, format_bytes(totals.texture_bytes));
+    let _ = writeln!(report);
+
+    for pass in passes() {
+        let _ = writeln!(report, "{:<24} {:>5} draws {:>9} tris", pass.label, pass.draws, pass.triangles);
+    }
+
+    let _ = writeln!(report);
+
+    let timings = timings();
+
+    if !timings.is_empty() {
+        let _ = writeln!(report, "{:<24} {:>8} {:>8}", "Pass", "CPU ms", "GPU ms");
+
+        for timing in &timings {
+            let gpu_ms = timing.gpu_ms.map_or_else(|| "-".to_string(), |ms| format!("{:.2}", ms));
+
+            let _ = writeln!(report, "{:<24} {:>8.2} {:>8}", timing.label, timing.cpu_ms, gpu_ms);
+        }
+
+        let cpu_ms = timings.iter().map(|timing| timing.cpu_ms).sum::<f32>();
+        let gpu_ms = timings.iter().filter_map(|timing| timing.gpu_ms).sum::<f32>();
+
+        let _ = writeln!(report, "{:<24} {:>8.2} {:>8.2}", "Total", cpu_ms, gpu_ms);
+        let _ = writeln!(report);
+    }
+
+    for resource in resources().into_iter().take(INSPECTOR_RESOURCES) {
+        let kind = match resource.kind {
+            ResourceKind::Buffer  => "buf",
+            ResourceKind::Texture => "tex",
+        };
+
+        let _ = writeln!(report, "{} {:>10}  {}", kind, format_bytes(resource.bytes), resource.label);
+    }
+
+    report
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        0..=1023             => format!("{} B", bytes),
+        1024..=1_048_575     => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        1_048_576..=u64::MAX => format!("{:.1} MiB", bytes as f64 / 1_048_576.0),
+    }
+}
@@ -0,0 +1,133 @@
+// Hierarchical depth pyramid used for occlusion culling. Each level stores the furthest depth of
+// the texels it covers, so a box whose nearest depth is behind a level's texels is hidden.
+
+use wgpu::util::DeviceExt;
+
+use crate::{
+    bind_group,
+    camera::DepthMode,
+    compute::{create_pipeline, workgroups},
+    crash_report,
+    renderer::GpuContext,
+};
+
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Float;
+
+pub struct HiZPyramid {
+    pub view:            wgpu::TextureView,
+    pub size:            (u32, u32),
+    pub mip_count:       u32,
+    copy_pipeline:       wgpu::ComputePipeline,
+    downsample_pipeline: wgpu::ComputePipeline,
+    copy_layout:         wgpu::BindGroupLayout,
+    // One per level below the top, reading the level above
+    downsample_groups:   Vec<wgpu::BindGroup>,
+    mip_views:           Vec<wgpu::TextureView>,
+}
+
+impl HiZPyramid {
+    pub fn new(ctx: &GpuContext) -> Self {
+        let device = &ctx.device;
+
+        let copy_layout = bind_group::BindGroupLayoutBuilder::new()
+            .depth_texture(wgpu::ShaderStages::COMPUTE)
+            .storage_texture(wgpu::ShaderStages::COMPUTE, FORMAT, wgpu::StorageTextureAccess::WriteOnly, wgpu::TextureViewDimension::D2)
+            .build(device, "hiz_copy_bind_group_layout");
+
+        let downsample_layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::COMPUTE)
+            .unfilterable_texture(wgpu::ShaderStages::COMPUTE, wgpu::TextureViewDimension::D2)
+            .storage_texture(wgpu::ShaderStages::COMPUTE, FORMAT, wgpu::StorageTextureAccess::WriteOnly, wgpu::TextureViewDimension::D2)
+            .build(device, "hiz_downsample_bind_group_layout");
+
+        let copy_pipeline       = create_pipeline(device, &copy_layout, include_str!("hiz_copy.wgsl"), "Hi-Z Copy");
+        let downsample_pipeline = create_pipeline(device, &downsample_layout, include_str!("hiz_downsample.wgsl"), "Hi-Z Downsample");
+
+        let reverse_z     = (ctx.depth_mode == DepthMode::ReverseZ) as u32;
+        let params_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label:    Some("Hi-Z Params Buffer"),
+                contents: bytemuck::cast_slice(&[reverse_z, 0, 0, 0]),
+                usage:    wgpu::BufferUsages::UNIFORM,
+            }
+        );
+
+        // Matches the depth buffer, which is smaller than the surface under dynamic resolution
+        let size                 = ctx.render_size();
+        let (texture, mip_count) = create_texture(device, size.width, size.height);
+
+        let mip_views = (0..mip_count)
+            .map(|level| texture.create_view(&wgpu::TextureViewDescriptor {
+                label:           Some("Hi-Z Mip View"),
+                base_mip_level:  level,
+                mip_level_count: std::num::NonZeroU32::new(1),
+                ..Default::default()
+            }))
+            .collect::<Vec<_>>();
+
+        let downsample_groups = (1..mip_count as usize)
+            .map(|level| bind_group::BindGroupBuilder::new(&downsample_layout)
+                .uniform(&params_buffer)
+                .texture(&mip_views[level - 1])
+                .texture(&mip_views[level])
+                .build(device, "hiz_downsample_bind_group"))
+            .collect::<Vec<_>>();
+
+        Self {
+            view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            size: (size.width, size.height),
+            mip_count,
+            copy_pipeline,
+            downsample_pipeline,
+            copy_layout,
+            downsample_groups,
+            mip_views,
+        }
+    }
+
+    // Records the passes that rebuild the pyramid from `depth`
+    pub fn build(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, depth: &wgpu::TextureView) {
+        // The depth view changes whenever the window is resized, so this group isn't cached
+        let copy_group = bind_group::BindGroupBuilder::new(&self.copy_layout)
+            .texture(depth)
+            .texture(&self.mip_views[0])
+            .build(device, "hiz_copy_bind_group");
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Hi-Z Pass"),
+        });
+
+        crash_report::set_compute_pipeline(&mut compute_pass, &self.copy_pipeline, "Hi-Z Copy");
+        compute_pass.set_bind_group(0, &copy_group, &[]);
+        compute_pass.dispatch_workgroups(workgroups(self.size.0), workgroups(self.size.1), 1);
+
+        crash_report::set_compute_pipeline(&mut compute_pass, &self.downsample_pipeline, "Hi-Z Downsample");
+
+        for (i, group) in self.downsample_groups.iter().enumerate() {
+            let level = i as u32 + 1;
+
+            compute_pass.set_bind_group(0, group, &[]);
+            compute_pass.dispatch_workgroups(
+                workgroups((self.size.0 >> level).max(1)),
+                workgroups((self.size.1 >> level).max(1)),
+                1,
+            );
+        }
+    }
+}
+
+fn create_texture(device: &wgpu::Device, width: u32, height: u32) -> (wgpu::Texture, u32) {
+    let mip_count = 32 - width.max(height).leading_zeros();
+
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label:           Some("Hi-Z Texture"),
+        size:            wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        mip_level_count: mip_count,
+        sample_count:    1,
+        dimension:       wgpu::TextureDimension::D2,
+        format:          FORMAT,
+        usage:           wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+    });
+
+    (texture, mip_count)
+}
@@ -0,0 +1,20 @@
+// Copies the depth buffer into the top level of the Hi-Z pyramid
+
+@group(0) @binding(0)
+var src: texture_depth_2d;
+@group(0) @binding(1)
+var dst: texture_storage_2d<r32float, write>;
+
+@compute @workgroup_size(8, 8)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size  = vec2<i32>(textureDimensions(dst));
+    let coord = vec2<i32>(id.xy);
+
+    if (coord.x >= size.x || coord.y >= size.y) {
+        return;
+    }
+
+    let depth = textureLoad(src, coord, 0);
+
+    textureStore(dst, coord, vec4<f32>(depth, 0.0, 0.0, 0.0));
+}
@@ -0,0 +1,52 @@
+// Builds one level of the Hi-Z pyramid from the level above it
+
+struct HiZParams {
+    reverse_z: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: HiZParams;
+@group(0) @binding(1)
+var src: texture_2d<f32>;
+@group(0) @binding(2)
+var dst: texture_storage_2d<r32float, write>;
+
+// Keeps the depth furthest from the camera so a level never hides more than the one above it
+fn farthest(a: f32, b: f32) -> f32 {
+    if (params.reverse_z != 0u) {
+        return min(a, b);
+    }
+    return max(a, b);
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let dst_size = vec2<i32>(textureDimensions(dst));
+    let src_size = vec2<i32>(textureDimensions(src));
+    let coord    = vec2<i32>(id.xy);
+
+    if (coord.x >= dst_size.x || coord.y >= dst_size.y) {
+        return;
+    }
+
+    let start = coord * 2;
+    var end   = min(start + 2, src_size);
+
+    // Odd sized levels leave a row or column that the last texel has to cover as well
+    if (coord.x == dst_size.x - 1) {
+        end.x = src_size.x;
+    }
+    if (coord.y == dst_size.y - 1) {
+        end.y = src_size.y;
+    }
+
+    var depth = textureLoad(src, start, 0).r;
+
+    for (var y = start.y; y < end.y; y = y + 1) {
+        for (var x = start.x; x < end.x; x = x + 1) {
+            depth = farthest(depth, textureLoad(src, vec2<i32>(x, y), 0).r);
+        }
+    }
+
+    textureStore(dst, coord, vec4<f32>(depth, 0.0, 0.0, 0.0));
+}
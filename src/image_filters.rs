@@ -0,0 +1,365 @@
+// Filters for still images, chained one after another as compute passes: Gaussian blur, sharpen,
+// Sobel edges, grayscale and chroma keying. The image is copied into a pair of float textures
+// that each filter reads one of and writes the other, and the last one written holds the result,
+// which can be shown or read back to save. Colors stay linear throughout, so filters behave the
+// same whatever the source's encoding.
+//
+// Needs compute shaders, so it isn't available on WebGL.
+
+use anyhow::anyhow;
+
+use crate::{
+    bind_group,
+    compute::{self, Blur, BlurKernel, TextureFilters},
+    crash_report,
+    pass::{Drawable, RenderLayer},
+    renderer::{self, GpuContext},
+    uniform::{Uniform, UniformBuffer},
+};
+
+// Float so chains don't band, and 32 bit so it's storable everywhere and easy to read back
+const WORKING_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Float;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ImageFilter {
+    GaussianBlur { sigma: f32 },
+    // Pushes each pixel away from its neighbors, by `amount` of the difference
+    Sharpen { amount: f32 },
+    // The strength of edges as brightness, scaled by `amount`
+    Sobel { amount: f32 },
+    // Mixes toward gray by `amount`, fully at 1.0
+    Grayscale { amount: f32 },
+    // Makes pixels near `key` in hue transparent. Those within `threshold` of it go fully and
+    // those within `softness` further fade out.
+    ChromaKey { key: [f32; 3], threshold: f32, softness: f32 },
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct FilterParams {
+    key:       [f32; 3],
+    mode:      u32,
+    amount:    f32,
+    threshold: f32,
+    softness:  f32,
+    _padding:  f32,
+}
+
+impl FilterParams {
+    fn new(mode: u32, amount: f32) -> Self {
+        Self { key: [0.0; 3], mode, amount, threshold: 0.0, softness: 0.0, _padding: 0.0 }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct ViewParams {
+    screen:   [f32; 2],
+    image:    [f32; 2],
+    split:    f32,
+    _padding: [f32; 3],
+}
+
+struct WorkingTexture {
+    texture: wgpu::Texture,
+    view:    wgpu::TextureView,
+}
+
+pub struct ImageFilters {
+    filters:  TextureFilters,
+    pipeline: wgpu::ComputePipeline,
+    layout:   wgpu::BindGroupLayout,
+    // Written as each pass is recorded, so one per pass in the longest chain so far
+    params:   Vec<UniformBuffer<FilterParams>>,
+    blurs:    Vec<Blur>,
+    targets:  [WorkingTexture; 2],
+    // The horizontal half of blurs
+    scratch:  WorkingTexture,
+    size:     (u32, u32),
+    // Which target holds the last result
+    output:   usize,
+}
+
+impl ImageFilters {
+    // For images `width` by `height`
+    pub fn new(ctx: &GpuContext, width: u32, height: u32) -> Self {
+        let device = &ctx.device;
+
+        let layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::COMPUTE)
+            .unfilterable_texture(wgpu::ShaderStages::COMPUTE, wgpu::TextureViewDimension::D2)
+            .storage_texture(wgpu::ShaderStages::COMPUTE, WORKING_FORMAT, wgpu::StorageTextureAccess::WriteOnly, wgpu::TextureViewDimension::D2)
+            .build(device, "image_filter_bind_group_layout");
+
+        let create_target = |label| {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label:           Some(label),
+                size:            wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+                mip_level_count: 1,
+                sample_count:    1,
+                dimension:       wgpu::TextureDimension::D2,
+                format:          WORKING_FORMAT,
+                usage:           wgpu::TextureUsages::STORAGE_BINDING
+                    | wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_SRC,
+            });
+
+            WorkingTexture { view: texture.create_view(&wgpu::TextureViewDescriptor::default()), texture }
+        };
+
+        Self {
+            filters:  TextureFilters::new(device, WORKING_FORMAT),
+            pipeline: compute::create_pipeline(device, &layout, include_str!("compute_image_filter.wgsl"), "Image Filter"),
+            layout,
+            params:   Vec::new(),
+            blurs:    Vec::new(),
+            targets:  [create_target("Image Filter Target A"), create_target("Image Filter Target B")],
+            scratch:  create_target("Image Filter Scratch"),
+            size:     (width, height),
+            output:   0,
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    // The result of the last `run`, in linear color. Read it with `textureLoad`, since 32 bit
+    // floats can't be filtered everywhere.
+    pub fn output(&self) -> &wgpu::TextureView {
+        &self.targets[self.output].view
+    }
+
+    // Records `chain` applied in order to `source`, which is the size the filters were made for
+    pub fn run(&mut self, ctx: &GpuContext, encoder: &mut wgpu::CommandEncoder, source: &wgpu::TextureView, chain: &[ImageFilter]) {
+        let device = &ctx.device;
+
+        // The copy into the first target counts as a pass
+        while self.params.len() <= chain.len() {
+            self.params.push(UniformBuffer::new(device, "Image Filter Params Buffer"));
+        }
+
+        let blur_count = chain.iter().filter(|filter| matches!(filter, ImageFilter::GaussianBlur { .. })).count();
+
+        while self.blurs.len() < blur_count {
+            self.blurs.push(Blur::new(device, &ctx.queue, BlurKernel::gaussian(1.0)));
+        }
+
+        self.params[0].write(&ctx.queue, &FilterParams::new(0, 0.0));
+        self.dispatch(device, encoder, &self.params[0], source, &self.targets[0].view);
+        self.output = 0;
+
+        let mut blurs = self.blurs.iter();
+
+        for (filter, params) in chain.iter().zip(&self.params[1..]) {
+            let input  = &self.targets[self.output].view;
+            let output = &self.targets[1 - self.output].view;
+
+            let values = match *filter {
+                ImageFilter::GaussianBlur { sigma } => {
+                    // There are always enough, counted above
+                    if let Some(blur) = blurs.next() {
+                        blur.set_kernel(&ctx.queue, BlurKernel::gaussian(sigma));
+                        self.filters.blur(device, encoder, blur, input, &self.scratch.view, output, self.size);
+                    }
+
+                    self.output = 1 - self.output;
+                    continue;
+                }
+                ImageFilter::Sharpen { amount }   => FilterParams::new(1, amount),
+                ImageFilter::Sobel { amount }     => FilterParams::new(2, amount),
+                ImageFilter::Grayscale { amount } => FilterParams::new(3, amount),
+                ImageFilter::ChromaKey { key, threshold, softness } => FilterParams {
+                    key,
+                    threshold,
+                    softness: softness.max(f32::EPSILON),
+                    ..FilterParams::new(4, 1.0)
+                },
+            };
+
+            params.write(&ctx.queue, &values);
+            self.dispatch(device, encoder, params, input, output);
+            self.output = 1 - self.output;
+        }
+    }
+
+    fn dispatch(
+        &self,
+        device:  &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        params:  &UniformBuffer<FilterParams>,
+        input:   &wgpu::TextureView,
+        output:  &wgpu::TextureView,
+    ) {
+        let bind_group = bind_group::BindGroupBuilder::new(&self.layout)
+            .uniform(params.buffer())
+            .texture(input)
+            .texture(output)
+            .build(device, "image_filter_bind_group");
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Image Filter Pass"),
+        });
+
+        crash_report::set_compute_pipeline(&mut compute_pass, &self.pipeline, "Image Filter");
+        compute_pass.set_bind_group(0, &bind_group, &[]);
+        compute_pass.dispatch_workgroups(compute::workgroups(self.size.0), compute::workgroups(self.size.1), 1);
+    }
+
+    // Reads the output back as 8 bit sRGB, waiting for the GPU to finish with it
+    pub fn read_image(&self, ctx: &GpuContext) -> anyhow::Result<image::RgbaImage> {
+        let device          = &ctx.device;
+        let (width, height) = self.size;
+        let texel           = 16;
+        let align           = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_row      = (width * texel).div_ceil(align) * align;
+
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label:              Some("Image Filter Readback Buffer"),
+            size:               (padded_row * height) as wgpu::BufferAddress,
+            usage:              wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Image Filter Readback Encoder"),
+        });
+
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                aspect:    wgpu::TextureAspect::All,
+                texture:   &self.targets[self.output].texture,
+                mip_level: 0,
+                origin:    wgpu::Origin3d::ZERO,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &readback,
+                layout: wgpu::ImageDataLayout {
+                    offset:         0,
+                    bytes_per_row:  std::num::NonZeroU32::new(padded_row),
+                    rows_per_image: std::num::NonZeroU32::new(height),
+                },
+            },
+            wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        );
+
+        ctx.queue.submit(std::iter::once(encoder.finish()));
+
+        let slice              = readback.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        slice.map_async(wgpu::MapMode::Read, move |result| sender.send(result).unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        receiver.recv()??;
+
+        // Rows are padded out to the copy alignment, which the image doesn't want
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+        {
+            let data = slice.get_mapped_range();
+
+            for row in data.chunks(padded_row as usize) {
+                let texels: &[f32] = bytemuck::cast_slice(&row[..(width * texel) as usize]);
+
+                for texel in texels.chunks(4) {
+                    pixels.extend([encode_srgb(texel[0]), encode_srgb(texel[1]), encode_srgb(texel[2]), (texel[3].clamp(0.0, 1.0) * 255.0).round() as u8]);
+                }
+            }
+        }
+        readback.unmap();
+
+        image::RgbaImage::from_raw(width, height, pixels).ok_or_else(|| anyhow!("Image filter readback was the wrong size"))
+    }
+}
+
+// Shows an image and its filtered copy side by side, split at a line that can be moved across
+// it, fitted to the screen in the UI layer
+pub struct SplitView {
+    // From 0.0 at the image's left edge, where it's all filtered, to 1.0 at its right, where it's
+    // all original
+    pub split:  f32,
+    pipeline:   wgpu::RenderPipeline,
+    layout:     wgpu::BindGroupLayout,
+    params:     UniformBuffer<ViewParams>,
+    bind_group: Option<wgpu::BindGroup>,
+}
+
+impl SplitView {
+    pub fn new(ctx: &GpuContext) -> Self {
+        let device = &ctx.device;
+
+        let layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::FRAGMENT)
+            .unfilterable_texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .unfilterable_texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .build(device, "split_view_bind_group_layout");
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Split View Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("image_filters_view.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Split View Pipeline Layout"),
+            bind_group_layouts:   &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        // The UI layer has no depth attachment
+        let pipeline = renderer::create_render_pipeline(
+            device,
+            &pipeline_layout,
+            ctx.config.format,
+            None,
+            &[],
+            &shader,
+            "Split View Pipeline",
+        );
+
+        Self {
+            split:      0.5,
+            pipeline,
+            layout,
+            params:     UniformBuffer::new(device, "Split View Params Buffer"),
+            bind_group: None,
+        }
+    }
+
+    // Shows `original` beside the output of `filters`' last run
+    pub fn prepare(&mut self, ctx: &GpuContext, original: &wgpu::TextureView, filters: &ImageFilters) {
+        let (width, height) = filters.size();
+
+        self.params.write(&ctx.queue, &ViewParams {
+            screen:   [ctx.size.width as f32, ctx.size.height as f32],
+            image:    [width as f32, height as f32],
+            split:    self.split.clamp(0.0, 1.0),
+            _padding: [0.0; 3],
+        });
+
+        // Made again each time since the output swaps between targets
+        self.bind_group = Some(bind_group::BindGroupBuilder::new(&self.layout)
+            .uniform(self.params.buffer())
+            .texture(original)
+            .texture(filters.output())
+            .build(&ctx.device, "split_view_bind_group"));
+    }
+}
+
+impl Drawable for SplitView {
+    fn draw<'a>(&'a self, layer: RenderLayer, render_pass: &mut wgpu::RenderPass<'a>) {
+        let bind_group = match &self.bind_group {
+            Some(bind_group) if layer == RenderLayer::Ui => bind_group,
+            _ => return,
+        };
+
+        crash_report::set_pipeline(render_pass, &self.pipeline, "Split View Pipeline");
+        render_pass.set_bind_group(0, bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+fn encode_srgb(linear: f32) -> u8 {
+    let c = linear.clamp(0.0, 1.0);
+    let encoded = if c <= 0.0031308 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 };
+
+    (encoded * 255.0).round() as u8
+}
@@ -0,0 +1,64 @@
+// An image fitted to the screen, the original left of the split and its filtered copy to the
+// right, over a checkerboard where they're transparent. See `SplitView` in image_filters.rs.
+
+struct ViewParams {
+    screen: vec2<f32>,
+    image:  vec2<f32>,
+    // Across the image, from 0.0 at its left edge to 1.0 at its right
+    split:  f32,
+}
+
+@group(0) @binding(0)
+var<uniform> view: ViewParams;
+@group(0) @binding(1)
+var t_original: texture_2d<f32>;
+@group(0) @binding(2)
+var t_filtered: texture_2d<f32>;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // One triangle covering the screen
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // As large as fits, centered
+    let scale  = min(view.screen.x / view.image.x, view.screen.y / view.image.y);
+    let origin = (view.screen - view.image * scale) * 0.5;
+    let pixel  = (in.clip_position.xy - origin) / scale;
+
+    if (any(pixel < vec2<f32>(0.0)) || any(pixel >= view.image)) {
+        return vec4<f32>(0.02, 0.02, 0.02, 1.0);
+    }
+
+    let split = view.split * view.image.x;
+
+    // A line a screen pixel wide marks the split
+    if (abs(pixel.x - split) * scale < 1.0) {
+        return vec4<f32>(1.0, 1.0, 1.0, 1.0);
+    }
+
+    let coord = vec2<i32>(pixel);
+    var color: vec4<f32>;
+
+    if (pixel.x < split) {
+        color = textureLoad(t_original, coord, 0);
+    } else {
+        color = textureLoad(t_filtered, coord, 0);
+    }
+
+    let cell    = vec2<i32>(in.clip_position.xy / 8.0);
+    let checker = select(0.2, 0.3, (cell.x + cell.y) % 2 == 0);
+
+    return vec4<f32>(mix(vec3<f32>(checker), color.rgb, color.a), 1.0);
+}
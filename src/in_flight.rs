@@ -0,0 +1,167 @@
+// Frames the GPU hasn't finished yet. At most FRAMES_IN_FLIGHT are submitted before
+// `GpuContext::begin_frame` waits on the oldest, so data the CPU rewrites every frame can keep a
+// copy per frame in flight in a `PerFrame`, picked by `Frame::slot`, without touching a copy the
+// GPU may still be reading. `Frame::fence` says when a frame is done on the GPU.
+//
+// Resources that stop being needed while a submitted frame might still use them go to
+// `GpuContext::retire`, which holds on to them until every frame submitted so far has finished,
+// then frees them. Buffers and textures are destroyed outright rather than left to whenever wgpu
+// gets around to it, so unloading assets gives their memory back promptly.
+//
+// `Queue::write_buffer` is already staged by wgpu, so none of this is needed for uniforms written
+// that way. It's for buffers the CPU maps, and for streaming data in without waiting on the GPU.
+
+use std::collections::VecDeque;
+
+use crate::{renderer::Frame, schedule::WorkFence, texture};
+
+pub const FRAMES_IN_FLIGHT: usize = 2;
+
+// Something the retirement queue frees once the GPU is done with it. Anything without a faster
+// way is just dropped.
+pub trait Retire: 'static {
+    fn release(self: Box<Self>) {}
+}
+
+impl Retire for wgpu::Buffer {
+    fn release(self: Box<Self>) {
+        self.destroy();
+    }
+}
+
+impl Retire for wgpu::Texture {
+    fn release(self: Box<Self>) {
+        self.destroy();
+    }
+}
+
+impl Retire for texture::Texture {
+    fn release(self: Box<Self>) {
+        self.texture.destroy();
+    }
+}
+
+impl Retire for wgpu::BindGroup {}
+impl Retire for wgpu::TextureView {}
+
+impl<T: Retire> Retire for Option<T> {
+    fn release(self: Box<Self>) {
+        if let Some(resource) = *self {
+            Box::new(resource).release();
+        }
+    }
+}
+
+impl<T: Retire> Retire for Vec<T> {
+    fn release(self: Box<Self>) {
+        for resource in *self {
+            Box::new(resource).release();
+        }
+    }
+}
+
+struct SubmittedFrame {
+    index:      u64,
+    submission: wgpu::SubmissionIndex,
+    fence:      WorkFence,
+}
+
+// Kept by the GPU context
+pub(crate) struct InFlight {
+    // Oldest first
+    frames:    VecDeque<SubmittedFrame>,
+    // Each waiting on the frame with the index alongside it
+    retired:   Vec<(u64, Box<dyn Retire>)>,
+    completed: Option<u64>,
+}
+
+impl InFlight {
+    pub(crate) fn new() -> Self {
+        Self {
+            frames:    VecDeque::new(),
+            retired:   Vec::new(),
+            completed: None,
+        }
+    }
+
+    // Waits until there's room for another frame, then lets go of what finished frames kept alive
+    pub(crate) fn begin(&mut self, device: &wgpu::Device) {
+        if self.frames.len() >= FRAMES_IN_FLIGHT {
+            if let Some(oldest) = self.frames.front() {
+                device.poll(wgpu::Maintain::WaitForSubmissionIndex(oldest.submission));
+            }
+        } else {
+            device.poll(wgpu::Maintain::Poll);
+        }
+
+        while self.frames.front().is_some_and(|frame| frame.fence.is_done()) {
+            self.completed = self.frames.pop_front().map(|frame| frame.index);
+        }
+
+        let completed = match self.completed {
+            Some(completed) => completed,
+            None            => return,
+        };
+
+        let (finished, waiting): (Vec<_>, Vec<_>) = std::mem::take(&mut self.retired)
+            .into_iter()
+            .partition(|(index, _)| *index <= completed);
+
+        self.retired = waiting;
+
+        for (_, resource) in finished {
+            resource.release();
+        }
+    }
+
+    pub(crate) fn submitted(&mut self, index: u64, submission: wgpu::SubmissionIndex, fence: WorkFence) {
+        self.frames.push_back(SubmittedFrame { index, submission, fence });
+    }
+
+    // Keeps `resource` until the frame at `index` has finished
+    pub(crate) fn retire(&mut self, index: u64, resource: Box<dyn Retire>) {
+        self.retired.push((index, resource));
+    }
+
+    pub(crate) fn completed(&self) -> Option<u64> {
+        self.completed
+    }
+}
+
+// One of something for each frame in flight, like a staging buffer that's rewritten every frame
+pub struct PerFrame<T> {
+    items: Vec<T>,
+}
+
+impl<T> PerFrame<T> {
+    pub fn new(create: impl FnMut(usize) -> T) -> Self {
+        Self {
+            items: (0..FRAMES_IN_FLIGHT).map(create).collect(),
+        }
+    }
+
+    // The copy `frame` can write without disturbing earlier frames still on the GPU
+    pub fn get(&self, frame: &Frame) -> &T {
+        &self.items[frame.slot()]
+    }
+
+    pub fn get_mut(&mut self, frame: &Frame) -> &mut T {
+        &mut self.items[frame.slot()]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.items.iter()
+    }
+}
+
+impl PerFrame<wgpu::Buffer> {
+    // A buffer of `size` bytes per frame in flight, e.g. `MAP_WRITE | COPY_SRC` for staging
+    pub fn buffers(device: &wgpu::Device, size: wgpu::BufferAddress, usage: wgpu::BufferUsages, label: &str) -> Self {
+        Self::new(|i| device.create_buffer(&wgpu::BufferDescriptor {
+            label:              Some(&format!("{} {}", label, i)),
+            size,
+            usage,
+            mapped_at_creation: false,
+        }))
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::HashSet;
+
+use winit::{
+    dpi::PhysicalPosition,
+    event::*,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Hand {
+    Left,
+    Right,
+}
+
+// A tracked headset controller, placed in the world relative to the app's `XrScene::xr_camera`
+#[derive(Debug, Copy, Clone)]
+pub struct XrController {
+    pub position:    cgmath::Point3<f32>,
+    pub orientation: cgmath::Quaternion<f32>,
+    // 0 to 1
+    pub trigger:     f32,
+    pub squeeze:     f32,
+    // -1 to 1, right and up positive
+    pub thumbstick:  (f32, f32),
+}
+
+// Snapshot of keyboard, mouse and headset controller state, updated from window events by the app
+// runner
+pub struct Input {
+    keys_held:    HashSet<VirtualKeyCode>,
+    keys_pressed: HashSet<VirtualKeyCode>,
+    buttons_held: HashSet<MouseButton>,
+    modifiers:    ModifiersState,
+    cursor:       PhysicalPosition<f64>,
+    cursor_delta: (f64, f64),
+    mouse_motion: (f64, f64),
+    scroll_delta: f32,
+    grabbed:      bool,
+    controllers:  [Option<XrController>; 2],
+    seed:         u64,
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Input {
+    pub fn new() -> Self {
+        Self {
+            keys_held:    HashSet::new(),
+            keys_pressed: HashSet::new(),
+            buttons_held: HashSet::new(),
+            modifiers:    ModifiersState::empty(),
+            cursor:       PhysicalPosition::new(0.0, 0.0),
+            cursor_delta: (0.0, 0.0),
+            mouse_motion: (0.0, 0.0),
+            scroll_delta: 0.0,
+            grabbed:      false,
+            controllers:  [None, None],
+            // Different every run, unless a recording is being replayed
+            seed:         instant::now().to_bits(),
+        }
+    }
+
+    pub fn process_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state,
+                    virtual_keycode: Some(keycode),
+                    ..
+                },
+                ..
+            } => match state {
+                ElementState::Pressed => {
+                    // Key repeat sends more Pressed events, only count the first one
+                    if self.keys_held.insert(*keycode) {
+                        self.keys_pressed.insert(*keycode);
+                    }
+                }
+                ElementState::Released => {
+                    self.keys_held.remove(keycode);
+                }
+            },
+            WindowEvent::MouseInput { state, button, .. } => match state {
+                ElementState::Pressed  => { self.buttons_held.insert(*button); }
+                ElementState::Released => { self.buttons_held.remove(button); }
+            },
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor_delta.0 += position.x - self.cursor.x;
+                self.cursor_delta.1 += position.y - self.cursor.y;
+                self.cursor          = *position;
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.scroll_delta += match delta {
+                    MouseScrollDelta::LineDelta(_, y)  => *y,
+                    MouseScrollDelta::PixelDelta(pos) => pos.y as f32 / 100.0,
+                };
+            }
+            WindowEvent::ModifiersChanged(modifiers) => self.modifiers = *modifiers,
+            WindowEvent::Focused(false) => {
+                // Releases won't arrive while unfocused, so forget everything that's held
+                self.keys_held.clear();
+                self.buttons_held.clear();
+            }
+            _ => {}
+        }
+    }
+
+    // Raw mouse movement keeps arriving while the cursor is grabbed, unlike `CursorMoved`
+    pub fn process_device_event(&mut self, event: &DeviceEvent) {
+        if let DeviceEvent::MouseMotion { delta } = event {
+            self.mouse_motion.0 += delta.0;
+            self.mouse_motion.1 += delta.1;
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    pub(crate) fn set_cursor_grabbed(&mut self, grabbed: bool) {
+        self.grabbed = grabbed;
+    }
+
+    // Set by the headset backend each frame, `None` when the controller isn't tracked
+    #[cfg(all(target_arch = "wasm32", feature = "webxr"))]
+    pub(crate) fn set_xr_controller(&mut self, hand: Hand, controller: Option<XrController>) {
+        self.controllers[hand as usize] = controller;
+    }
+
+    // Clears the per-frame state. Called by the runner after every frame.
+    pub fn end_frame(&mut self) {
+        self.keys_pressed.clear();
+        self.cursor_delta = (0.0, 0.0);
+        self.mouse_motion = (0.0, 0.0);
+        self.scroll_delta = 0.0;
+    }
+
+    pub fn is_key_held(&self, key: VirtualKeyCode) -> bool {
+        self.keys_held.contains(&key)
+    }
+
+    // True only on the frame the key went down
+    pub fn is_key_pressed(&self, key: VirtualKeyCode) -> bool {
+        self.keys_pressed.contains(&key)
+    }
+
+    pub fn is_button_held(&self, button: MouseButton) -> bool {
+        self.buttons_held.contains(&button)
+    }
+
+    pub fn modifiers(&self) -> ModifiersState {
+        self.modifiers
+    }
+
+    pub fn cursor_position(&self) -> PhysicalPosition<f64> {
+        self.cursor
+    }
+
+    pub fn cursor_delta(&self) -> (f64, f64) {
+        self.cursor_delta
+    }
+
+    pub fn scroll_delta(&self) -> f32 {
+        self.scroll_delta
+    }
+
+    pub fn mouse_motion(&self) -> (f64, f64) {
+        self.mouse_motion
+    }
+
+    pub fn is_cursor_grabbed(&self) -> bool {
+        self.grabbed
+    }
+
+    // For apps to randomize from, so replaying a recording picks the same numbers it did
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn xr_controller(&self, hand: Hand) -> Option<&XrController> {
+        self.controllers[hand as usize].as_ref()
+    }
+}
@@ -0,0 +1,32 @@
+// Instances as `InstanceRaw` in model.rs lays them out, and their transforms. Prepended to every
+// shader that draws instanced meshes, so they all read instances the same way.
+
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+};
+
+// The first column's w carries the LOD fade rather than being part of the transform, see lod.rs
+fn instance_model_matrix(instance: InstanceInput) -> mat4x4<f32> {
+    return mat4x4<f32>(
+        vec4<f32>(instance.model_matrix_0.xyz, 0.0),
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+}
+
+// `normal` in world space. Dividing it by each axis's squared scale first turns the model matrix
+// into its inverse transpose, which keeps normals perpendicular to surfaces that are scaled more
+// along one axis than the others.
+fn normal_to_world(model_matrix: mat4x4<f32>, normal: vec3<f32>) -> vec3<f32> {
+    let scale_squared = vec3<f32>(
+        dot(model_matrix[0].xyz, model_matrix[0].xyz),
+        dot(model_matrix[1].xyz, model_matrix[1].xyz),
+        dot(model_matrix[2].xyz, model_matrix[2].xyz),
+    );
+
+    return (model_matrix * vec4<f32>(normal / scale_squared, 0.0)).xyz;
+}
@@ -0,0 +1,317 @@
+// Post effect for what a camera lens does with bright light. Ghosts are reflections between the
+// lens elements: the bloom's bright pass is flipped through the middle of the screen and repeated
+// at a few scales along the way, with a halo ring around the edge. Glare sprites are starbursts
+// drawn over lights themselves, faded by how much of the light the depth buffer says is in view,
+// so they dim as something passes in front.
+//
+// Reads the bright pass bloom leaves behind, so add it after bloom. Needs compute shaders for that,
+// so it isn't available on WebGL.
+
+use cgmath::prelude::*;
+
+use crate::{
+    bind_group,
+    bloom::Bloom,
+    camera::{Camera, DepthMode},
+    crash_report,
+    pass::PostEffect,
+    renderer::GpuContext,
+    uniform::{Uniform, UniformBuffer},
+};
+
+// Also in lens_flare_glare.wgsl
+pub const MAX_GLARES: usize = 4;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum GlareSource {
+    // Infinitely far away along a direction towards the light, like the sun
+    Directional(cgmath::Vector3<f32>),
+    Point(cgmath::Point3<f32>),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Glare {
+    pub source:    GlareSource,
+    pub color:     [f32; 3],
+    pub intensity: f32,
+    // Radius of the sprite as a share of the screen's height
+    pub size:      f32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct GlareRaw {
+    // Normalized device coordinates and depth of the light, and the sprite's radius
+    position: [f32; 4],
+    // Color premultiplied by intensity
+    color:    [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct LensFlareParams {
+    ghost_intensity: f32,
+    ghost_spacing:   f32,
+    halo_radius:     f32,
+    halo_intensity:  f32,
+    ghosts:          u32,
+    glare_count:     u32,
+    reverse_z:       u32,
+    srgb_target:     u32,
+    aspect:          f32,
+    _padding:        [f32; 3],
+    glares:          [GlareRaw; MAX_GLARES],
+}
+
+pub struct LensFlare {
+    // Brightness of the ghosts relative to the bright pass
+    pub ghost_intensity: f32,
+    // Ghosts along the line through the middle of the screen, and how far apart they are as a
+    // share of it
+    pub ghosts:          u32,
+    pub ghost_spacing:   f32,
+    // Radius of the halo ring as a share of the screen, and its brightness
+    pub halo_radius:     f32,
+    pub halo_intensity:  f32,
+    // Lights to draw glare over, the first `MAX_GLARES` are used
+    pub glares:          Vec<Glare>,
+    params:              UniformBuffer<LensFlareParams>,
+    ghost_pipeline:      wgpu::RenderPipeline,
+    glare_pipeline:      wgpu::RenderPipeline,
+    flare_layout:        wgpu::BindGroupLayout,
+    scene_layout:        wgpu::BindGroupLayout,
+    sampler:             wgpu::Sampler,
+    // Made in `prepare`, since bloom's chain and the depth texture change with the resolution
+    flare_group:         Option<wgpu::BindGroup>,
+    glare_count:         u32,
+    srgb_target:         bool,
+}
+
+impl LensFlare {
+    pub fn new(ctx: &GpuContext) -> Self {
+        let device = &ctx.device;
+
+        // Glare is occlusion tested in the vertex shader, once per sprite rather than per pixel
+        let flare_layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT)
+            .depth_texture(wgpu::ShaderStages::VERTEX)
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .sampler(wgpu::ShaderStages::FRAGMENT)
+            .build(device, "lens_flare_bind_group_layout");
+
+        let scene_layout = bind_group::BindGroupLayoutBuilder::new()
+            .unfilterable_texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .build(device, "lens_flare_scene_bind_group_layout");
+
+        // Ghosts replace the scene with the scene plus themselves, then glare is added on top
+        let ghost_pipeline = create_pipeline(
+            device,
+            &[&flare_layout, &scene_layout],
+            ctx.config.format,
+            wgpu::BlendState::REPLACE,
+            include_str!("lens_flare.wgsl"),
+            "Lens Flare Ghosts",
+        );
+
+        let glare_pipeline = create_pipeline(
+            device,
+            &[&flare_layout],
+            ctx.config.format,
+            wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::One,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation:  wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent::OVER,
+            },
+            include_str!("lens_flare_glare.wgsl"),
+            "Lens Flare Glare",
+        );
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label:          Some("Lens Flare Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter:     wgpu::FilterMode::Linear,
+            min_filter:     wgpu::FilterMode::Linear,
+            mipmap_filter:  wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            ghost_intensity: 0.4,
+            ghosts:          4,
+            ghost_spacing:   0.35,
+            halo_radius:     0.45,
+            halo_intensity:  0.2,
+            glares:          Vec::new(),
+            params:          UniformBuffer::new(device, "Lens Flare Params Buffer"),
+            ghost_pipeline,
+            glare_pipeline,
+            flare_layout,
+            scene_layout,
+            sampler,
+            flare_group:     None,
+            glare_count:     0,
+            srgb_target:     ctx.config.format.describe().srgb,
+        }
+    }
+
+    // Called once a frame before the effect is added, after `bloom` is prepared, with the camera the
+    // scene is drawn from
+    pub fn prepare(&mut self, ctx: &GpuContext, camera: &Camera, bloom: &Bloom) {
+        let bright = match bloom.bright_pass() {
+            Some(bright) => bright,
+            None         => {
+                self.flare_group = None;
+                return;
+            }
+        };
+
+        let view_proj = camera.build_view_projections_matrix();
+        let mut raw   = [GlareRaw { position: [0.0; 4], color: [0.0; 4] }; MAX_GLARES];
+        let mut count = 0;
+
+        for glare in self.glares.iter().take(MAX_GLARES) {
+            let clip = match glare.source {
+                GlareSource::Directional(direction) => view_proj * direction.normalize().extend(0.0),
+                GlareSource::Point(position)        => view_proj * position.to_homogeneous(),
+            };
+
+            // Behind the camera
+            if clip.w <= 0.0 {
+                continue;
+            }
+
+            // A direction ends up at infinity, which is the far plane once clamped
+            let depth = match glare.source {
+                GlareSource::Directional(_) => ctx.depth_mode.far_depth(),
+                GlareSource::Point(_)       => clip.z / clip.w,
+            };
+
+            raw[count] = GlareRaw {
+                position: [clip.x / clip.w, clip.y / clip.w, depth, glare.size],
+                color:    [
+                    glare.color[0] * glare.intensity,
+                    glare.color[1] * glare.intensity,
+                    glare.color[2] * glare.intensity,
+                    1.0,
+                ],
+            };
+            count += 1;
+        }
+
+        self.glare_count = count as u32;
+
+        let size = ctx.render_size();
+
+        self.params.write(&ctx.queue, &LensFlareParams {
+            ghost_intensity: self.ghost_intensity.max(0.0),
+            ghost_spacing:   self.ghost_spacing,
+            halo_radius:     self.halo_radius.max(0.0),
+            halo_intensity:  self.halo_intensity.max(0.0),
+            ghosts:          self.ghosts,
+            glare_count:     self.glare_count,
+            reverse_z:       (ctx.depth_mode == DepthMode::ReverseZ) as u32,
+            srgb_target:     self.srgb_target as u32,
+            aspect:          size.width.max(1) as f32 / size.height.max(1) as f32,
+            _padding:        [0.0; 3],
+            glares:          raw,
+        });
+
+        self.flare_group = Some(
+            bind_group::BindGroupBuilder::new(&self.flare_layout)
+                .uniform(self.params.buffer())
+                .texture(&ctx.depth_texture.view)
+                .texture(bright)
+                .sampler(&self.sampler)
+                .build(&ctx.device, "lens_flare_bind_group")
+        );
+    }
+}
+
+impl PostEffect for LensFlare {
+    fn apply(
+        &self,
+        device:  &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        input:   &wgpu::TextureView,
+        output:  &wgpu::TextureView,
+    ) {
+        let flare_group = match &self.flare_group {
+            Some(group) => group,
+            None        => return,
+        };
+
+        let scene_group = bind_group::BindGroupBuilder::new(&self.scene_layout)
+            .texture(input)
+            .build(device, "lens_flare_scene_bind_group");
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Lens Flare Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view:           output,
+                resolve_target: None,
+                ops:  wgpu::Operations {
+                    load:  wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: true
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        crash_report::set_pipeline(&mut render_pass, &self.ghost_pipeline, "Lens Flare Ghosts");
+        render_pass.set_bind_group(0, flare_group, &[]);
+        render_pass.set_bind_group(1, &scene_group, &[]);
+        render_pass.draw(0..3, 0..1);
+
+        if self.glare_count > 0 {
+            crash_report::set_pipeline(&mut render_pass, &self.glare_pipeline, "Lens Flare Glare");
+            render_pass.draw(0..6, 0..self.glare_count);
+        }
+    }
+}
+
+fn create_pipeline(
+    device:  &wgpu::Device,
+    layouts: &[&wgpu::BindGroupLayout],
+    format:  wgpu::TextureFormat,
+    blend:   wgpu::BlendState,
+    source:  &str,
+    label:   &str,
+) -> wgpu::RenderPipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label:  Some(label),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(label),
+        bind_group_layouts:   layouts,
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label:    Some(label),
+        layout:   Some(&pipeline_layout),
+        vertex:   wgpu::VertexState {
+            module:      &shader,
+            entry_point: "vs_main",
+            buffers:     &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module:      &shader,
+            entry_point: "fs_main",
+            targets:     &[Some(wgpu::ColorTargetState {
+                format,
+                blend:      Some(blend),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive:     wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample:   wgpu::MultisampleState::default(),
+        multiview:     None,
+    })
+}
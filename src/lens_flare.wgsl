@@ -0,0 +1,118 @@
+// Adds ghosts and a halo of the bloom's bright pass over the scene. Each ghost is the bright pass
+// flipped through the middle of the screen and scaled, and the halo is a ring sampled towards the
+// middle, both fading out away from it the way lens reflections do.
+
+struct GlareRaw {
+    position: vec4<f32>,
+    color:    vec4<f32>,
+}
+
+struct LensFlareParams {
+    ghost_intensity: f32,
+    ghost_spacing:   f32,
+    halo_radius:     f32,
+    halo_intensity:  f32,
+    ghosts:          u32,
+    glare_count:     u32,
+    reverse_z:       u32,
+    srgb_target:     u32,
+    aspect:          f32,
+    glares:          array<GlareRaw, 4>,
+}
+
+@group(0) @binding(0)
+var<uniform> params: LensFlareParams;
+@group(0) @binding(2)
+var t_bright: texture_2d<f32>;
+@group(0) @binding(3)
+var s_bright: sampler;
+@group(1) @binding(0)
+var t_scene: texture_2d<f32>;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv:                  vec2<f32>,
+}
+
+// One triangle that covers the screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+
+    out.uv            = uv;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+
+    return out;
+}
+
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+    return select(pow((c + 0.055) / 1.055, vec3<f32>(2.4)), c / 12.92, c <= vec3<f32>(0.04045));
+}
+
+fn linear_to_srgb(c: vec3<f32>) -> vec3<f32> {
+    return select(1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055, c * 12.92, c <= vec3<f32>(0.0031308));
+}
+
+// Brighter near the middle of the screen, nothing at the edges
+fn center_falloff(uv: vec2<f32>) -> f32 {
+    let d = length(uv - 0.5) / 0.7071;
+
+    return pow(clamp(1.0 - d, 0.0, 1.0), 4.0);
+}
+
+// The bright pass is read from a smaller level, which is already soft like a defocused reflection.
+// Each channel is offset a little along the ghost's direction for the fringes of real glass.
+fn sample_bright(uv: vec2<f32>, direction: vec2<f32>) -> vec3<f32> {
+    let offset = direction * 0.004;
+
+    return vec3<f32>(
+        textureSampleLevel(t_bright, s_bright, uv + offset, 1.0).r,
+        textureSampleLevel(t_bright, s_bright, uv, 1.0).g,
+        textureSampleLevel(t_bright, s_bright, uv - offset, 1.0).b,
+    );
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let scene = textureLoad(t_scene, vec2<i32>(in.clip_position.xy), 0);
+
+    var color = scene.rgb;
+
+    if (params.srgb_target == 0u) {
+        color = srgb_to_linear(color);
+    }
+
+    // Ghosts lie on the line from this pixel through the middle, flipped to its other side
+    let flipped   = vec2<f32>(1.0) - in.uv;
+    let to_center = (vec2<f32>(0.5) - flipped) * params.ghost_spacing;
+    let direction = normalize(to_center + vec2<f32>(0.0001));
+
+    var flare = vec3<f32>(0.0);
+
+    for (var i = 0u; i < params.ghosts; i = i + 1u) {
+        let uv = fract(flipped + to_center * f32(i));
+
+        flare = flare + sample_bright(uv, direction) * center_falloff(uv) * params.ghost_intensity;
+    }
+
+    // The halo is a ring around the middle, stretched back to a circle on wide screens
+    var halo_vector = vec2<f32>(0.5) - flipped;
+    halo_vector.x   = halo_vector.x * params.aspect;
+    halo_vector     = normalize(halo_vector + vec2<f32>(0.0001)) * params.halo_radius;
+    halo_vector.x   = halo_vector.x / params.aspect;
+
+    let halo_uv     = flipped + halo_vector;
+    let halo_weight = pow(1.0 - min(length(halo_uv - 0.5) / 0.7071, 1.0), 5.0);
+
+    flare = flare + sample_bright(halo_uv, direction) * halo_weight * params.halo_intensity;
+
+    color = color + flare;
+
+    if (params.srgb_target == 0u) {
+        color = linear_to_srgb(color);
+    }
+
+    return vec4<f32>(color, scene.a);
+}
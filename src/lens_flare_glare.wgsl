@@ -0,0 +1,103 @@
+// Starburst sprites over lights, added on top of the scene. Each sprite's vertices test a grid of
+// depths around the light's pixel, and the share of them nothing was drawn in front of fades the
+// sprite, so it dims smoothly as the light is covered.
+
+struct GlareRaw {
+    // Normalized device coordinates and depth of the light, and the sprite's radius
+    position: vec4<f32>,
+    color:    vec4<f32>,
+}
+
+struct LensFlareParams {
+    ghost_intensity: f32,
+    ghost_spacing:   f32,
+    halo_radius:     f32,
+    halo_intensity:  f32,
+    ghosts:          u32,
+    glare_count:     u32,
+    reverse_z:       u32,
+    srgb_target:     u32,
+    aspect:          f32,
+    glares:          array<GlareRaw, 4>,
+}
+
+@group(0) @binding(0)
+var<uniform> params: LensFlareParams;
+@group(0) @binding(1)
+var t_depth: texture_depth_2d;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // -1 to 1 across the sprite
+    @location(0) local:               vec2<f32>,
+    @location(1) color:               vec3<f32>,
+}
+
+// Share of the depths around the light that aren't in front of it, from a 5x5 grid
+fn visibility(position: vec3<f32>) -> f32 {
+    let size   = vec2<i32>(textureDimensions(t_depth));
+    let center = vec2<i32>((position.xy * vec2<f32>(0.5, -0.5) + 0.5) * vec2<f32>(size));
+
+    var visible = 0.0;
+
+    for (var y = -2; y <= 2; y = y + 1) {
+        for (var x = -2; x <= 2; x = x + 1) {
+            let texel = center + vec2<i32>(x, y) * 4;
+
+            // Off the screen counts as hidden, so lights fade as they leave it
+            if (any(texel < vec2<i32>(0)) || any(texel >= size)) {
+                continue;
+            }
+
+            let depth = textureLoad(t_depth, texel, 0);
+
+            // A little slack so the far plane counts as level with a light at infinity
+            if (params.reverse_z != 0u) {
+                visible = visible + select(0.0, 1.0, depth <= position.z + 0.00001);
+            } else {
+                visible = visible + select(0.0, 1.0, depth >= position.z - 0.00001);
+            }
+        }
+    }
+
+    return visible / 25.0;
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, @builtin(instance_index) instance: u32) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, 1.0),
+    );
+
+    let glare  = params.glares[instance];
+    let corner = corners[index];
+
+    // Square on screen whatever its shape
+    let radius = vec2<f32>(glare.position.w / params.aspect, glare.position.w) * 2.0;
+
+    var out: VertexOutput;
+
+    out.clip_position = vec4<f32>(glare.position.xy + corner * radius, 0.0, 1.0);
+    out.local         = corner;
+    out.color         = glare.color.rgb * visibility(glare.position.xyz);
+
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let d     = length(in.local);
+    let angle = atan2(in.local.y, in.local.x);
+
+    // A soft core with six thin rays that fade towards the edge of the sprite
+    let core = exp(-d * d * 16.0);
+    let rays = pow(abs(cos(angle * 3.0)), 40.0) * max(1.0 - d, 0.0) * 0.6;
+    let edge = 1.0 - smoothstep(0.8, 1.0, d);
+
+    return vec4<f32>(in.color * (core + rays) * edge, 0.0);
+}
@@ -1,662 +1,119 @@
-use std::iter;
-
-use cgmath::prelude::*;
-use wgpu::util::DeviceExt;
-use winit::{
-    event::*,
-    event_loop::{ControlFlow, EventLoop},
-    window::{Window, WindowBuilder}
-};
-
 #[cfg(target_arch="wasm32")]
 use wasm_bindgen::prelude::*;
 
-mod model;
-mod texture;
-mod resources;
-
-use model::{DrawModel, Vertex};
-
-// Translates scene from OpenGL's coordinate system to WGPU's
-#[rustfmt::skip]
-pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
-    1.0, 0.0, 0.0, 0.0,
-    0.0, 1.0, 0.0, 0.0,
-    0.0, 0.0, 0.5, 0.0,
-    0.0, 0.0, 0.5, 1.0,
-);
-
-const CAMERA_SPEED: f32 = 0.2;
-
-const NUM_INSTANCES_PER_ROW: u32 = 10;
-
-struct Camera {
-    eye:    cgmath::Point3<f32>,
-    target: cgmath::Point3<f32>,
-    up:     cgmath::Vector3<f32>,
-    aspect: f32,
-    fovy:   f32,
-    znear:  f32,
-    zfar:   f32,
-}
-
-impl Camera {
-    fn build_view_projections_matrix(&self) -> cgmath::Matrix4<f32> {
-        let view = cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up);
-        let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
-
-        return OPENGL_TO_WGPU_MATRIX * proj * view;
-    }
-}
-
-#[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-struct CameraUniform {
-    // We can't use cgmath with bytemuck directly so we have to convert the Matrix4 into a 4x4 f32 array
-    view_proj: [ [f32; 4]; 4],
-}
-
-impl CameraUniform {
-    fn new() -> Self {
-        use cgmath::SquareMatrix;
-
-        Self {
-            view_proj: cgmath::Matrix4::identity().into(),
-        }
-    }
-
-    fn update_view_proj(&mut self, camera: &Camera) {
-        self.view_proj = (OPENGL_TO_WGPU_MATRIX * camera.build_view_projections_matrix()).into();
-    }
-}
-
-struct CameraController {
-    speed:               f32,
-    is_up_pressed:       bool,
-    is_down_pressed:     bool,
-    is_forward_pressed:  bool,
-    is_backward_pressed: bool,
-    is_left_pressed:     bool,
-    is_right_pressed:    bool,
-}
-
-impl CameraController {
-    fn new(speed: f32) -> Self {
-        Self {
-            speed,
-            is_up_pressed:       false,
-            is_down_pressed:     false,
-            is_forward_pressed:  false,
-            is_backward_pressed: false,
-            is_left_pressed:     false,
-            is_right_pressed:    false,
-        }
-    }
-
-    fn process_events(&mut self, event: &WindowEvent) -> bool {
-        match event {
-            WindowEvent::KeyboardInput {
-                input: KeyboardInput {
-                    state,
-                    virtual_keycode: Some(keycode),
-                    ..
-                },
-                ..
-            } => {
-                let is_pressed = *state == ElementState::Pressed;
-
-                match keycode {
-                    VirtualKeyCode::Space => {
-                        self.is_up_pressed = is_pressed;
-                        true
-                    }
-                    VirtualKeyCode::LShift => {
-                        self.is_down_pressed = is_pressed;
-                        true
-                    }
-                    VirtualKeyCode::W | VirtualKeyCode::Up => {
-                        self.is_forward_pressed = is_pressed;
-                        true
-                    }
-                    VirtualKeyCode::A | VirtualKeyCode::Left => {
-                        self.is_left_pressed = is_pressed;
-                        true
-                    }
-                    VirtualKeyCode::S | VirtualKeyCode::Down => {
-                        self.is_backward_pressed = is_pressed;
-                        true
-                    }
-                    VirtualKeyCode::D | VirtualKeyCode::Right => {
-                        self.is_right_pressed = is_pressed;
-                        true
-                    }
-                    _ => false,
-                }
-            }
-            _ => false
-        }
-    }
-
-    fn update_camera(&self, camera: &mut Camera) {
-        let forward      = camera.target - camera.eye;
-        let forward_norm = forward.normalize();
-        let forward_mag  = forward.magnitude();
-
-        // Prevents glitching when camera gets too close to the center of the screen
-        if self.is_forward_pressed && forward_mag > self.speed {
-            camera.eye += forward_norm * self.speed;
-        }
-        if self.is_backward_pressed {
-            camera.eye -= forward_norm * self.speed;
-        }
-
-        let right = forward_norm.cross(camera.up);
-
-        // Redo radius calculation incase the forward/backward is pressed
-        let forward     = camera.target - camera.eye;
-        let forward_mag = forward.magnitude();
-
-        if self.is_right_pressed {
-            // Rescale the distance between the target and eye so that it doesn't change.
-            // Therefore the eye still lies on the circle made by the target and eye.
-            camera.eye = camera.target - (forward + right * self.speed).normalize() * forward_mag;
-        }
-        if self.is_left_pressed {
-            camera.eye = camera.target - (forward - right * self.speed).normalize() * forward_mag;
-        }
-    }
-}
-
-
-#[repr(C)]
-#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-struct InstanceRaw {
-    model: [[f32; 4]; 4],
-}
-
-impl InstanceRaw {
-    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
-        use std::mem;
-
-        wgpu::VertexBufferLayout {
-            array_stride: mem::size_of::<InstanceRaw>() as wgpu::BufferAddress,
-            step_mode:    wgpu::VertexStepMode::Instance,
-            attributes:   &[
-                wgpu::VertexAttribute {
-                    offset:          0,
-                    shader_location: 5,
-                    format:          wgpu::VertexFormat::Float32x4,
-                },
-                wgpu::VertexAttribute {
-                    offset:          mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
-                    shader_location: 6,
-                    format:          wgpu::VertexFormat::Float32x4,
-                },
-                wgpu::VertexAttribute {
-                    offset:          mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
-                    shader_location: 7,
-                    format:          wgpu::VertexFormat::Float32x4,
-                },
-                wgpu::VertexAttribute {
-                    offset:          mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
-                    shader_location: 8,
-                    format:          wgpu::VertexFormat::Float32x4,
-                },
-            ]
-        }
-    }
-}
-
-struct Instance {
-    position: cgmath::Vector3<f32>,
-    rotation: cgmath::Quaternion<f32>,
-}
-
-impl Instance {
-    fn to_raw(&self) -> InstanceRaw {
-        InstanceRaw {
-            model: (cgmath::Matrix4::from_translation(self.position) * cgmath::Matrix4::from(self.rotation)).into(),
-        }
-    }
-}
-
-struct State {
-    surface:            wgpu::Surface,
-    device:             wgpu::Device,
-    queue:              wgpu::Queue,
-    config:             wgpu::SurfaceConfiguration,
-    size:               winit::dpi::PhysicalSize<u32>,
-    render_pipeline:    wgpu::RenderPipeline,
-    obj_model:          model::Model,
-    camera:             Camera,
-    camera_uniform:     CameraUniform,
-    camera_buffer:      wgpu::Buffer,
-    camera_bind_group:  wgpu::BindGroup,
-    camera_controller:  CameraController,
-    instances:          Vec<Instance>,
-    #[allow(dead_code)]
-    instance_buffer:    wgpu::Buffer,
-    depth_texture:      texture::Texture,
-    window:             Window,
-
-}
-
-impl State {
-    // Creating some of the wgpu types requires async code
-    async fn new(window: Window) -> Self {
-        let size = window.inner_size();
-
-        // The instance is a handle to our GPU
-        let instance = wgpu::Instance::new(wgpu::Backends::all());
-
-        // # Safety
-        //
-        // The surface needs to live as long as the window that created it.
-        // State owns the window so this should be safe.
-        let surface = unsafe { instance.create_surface(&window) };
-        let adapter = instance.request_adapter(
-            &wgpu::RequestAdapterOptions {
-                power_preference:       wgpu::PowerPreference::default(),
-                compatible_surface:     Some(&surface),
-                force_fallback_adapter: false,
-            }
-        ).await.unwrap();
-
-        /*
-         * Enumerator to fall back on if `adapter` returns `None`
-
-         let adapter = instance.enumerate_adapters(wgpu::Backends::all())
-            .filter(|adapter| {
-                !surface.get_supported_formats(&adapter).is_empty()
-            })
-            .next()
-            .unwrap();
-         */
-
-        let (device, queue) = adapter.request_device(
-            &wgpu::DeviceDescriptor {
-                features: wgpu::Features::empty(),
-                // WebGL doesn't support all wgpu's features, so disable some if building for web.
-                limits:   if cfg!(target_arch = "wasm32") {
-                    wgpu::Limits::downlevel_webgl2_defaults()
-                } else {
-                    wgpu::Limits::default()
-                },
-                label:    None,
-            },
-            None, // trace path
-        ).await.unwrap();
-
-        let config = wgpu::SurfaceConfiguration {
-            usage:        wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format:       surface.get_supported_formats(&adapter)[0], // the prefered format is placed at the beginning of the vector
-            width:        size.width,
-            height:       size.height,
-            present_mode: wgpu::PresentMode::Fifo, // VSync, likely supported on all platforms
-            alpha_mode:   wgpu::CompositeAlphaMode::Auto,
-        };
-
-        surface.configure(&device, &config);
-
-        let texture_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding:    0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty:         wgpu::BindingType::Texture {
-                        multisampled:   false,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        sample_type:    wgpu::TextureSampleType::Float { filterable: true },
-                    },
-                    count:      None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding:    1,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    // This should match the filterable field of the corresponding Texture entry about
-                    ty:         wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                    count:      None,
-                },
-            ],
-            label: Some("texture_bind_group_layout"),
-        });
-
-
-        // Cameras
-
-        let camera = Camera {
-            eye:    (0.0, 1.0, 2.0).into(), // position the camera 1 unit up and 2 units back
-            target: (0.0, 0.0, 0.0).into(), // have it look at the origin
-            up:     cgmath::Vector3::unit_y(),
-            aspect: config.width as f32 / config.height as f32,
-            fovy:   45.0,
-            znear:  0.1,
-            zfar:   100.0,
-        };
-
-        let mut camera_uniform = CameraUniform::new();
-        camera_uniform.update_view_proj(&camera);
-
-        let camera_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label:    Some("Camera Buffer"),
-                contents: bytemuck::cast_slice(&[camera_uniform]),
-                usage:    wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            }
-        );
-
-        let camera_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding:    0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty:         wgpu::BindingType::Buffer {
-                        ty:                 wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size:   None,
-                    },
-                    count:      None,
-                }
-            ],
-            label: Some("camera_bind_group_layout"),
-        });
-
-        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout:  &camera_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding:  0,
-                    resource: camera_buffer.as_entire_binding(),
-                }
-            ],
-            label: Some("camera_bind_group")
-        });
-
-        let camera_controller = CameraController::new(CAMERA_SPEED);
-
-        // Instances
-        let obj_model = resources::load_model(
-            "cube.obj",
-            &device,
-            &queue,
-            &texture_bind_group_layout,
-        ).await.unwrap();
-
-        const SPACE_BETWEEN: f32 = 3.0;
-
-        let instances = (0..NUM_INSTANCES_PER_ROW).flat_map(|z| {
-            (0..NUM_INSTANCES_PER_ROW).map(move |x| {
-                let x = SPACE_BETWEEN * (x as f32 - NUM_INSTANCES_PER_ROW as f32 / 2.0);
-                let z = SPACE_BETWEEN * (z as f32 - NUM_INSTANCES_PER_ROW as f32 / 2.0);
-
-                let position = cgmath::Vector3 { x, y: 0.0, z };
-
-                let rotation = if position.is_zero() {
-                    // this is needed so an onject at (0, 0, 0) won't get scaled to 0
-                    cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_z(), cgmath::Deg(0.0))
-                } else {
-                    cgmath::Quaternion::from_axis_angle(position.normalize(), cgmath::Deg(45.0))
-                };
-
-                Instance {
-                    position, rotation,
-                }
-            })
-        }).collect::<Vec<_>>();
-
-        let instance_data   = instances.iter().map(Instance::to_raw).collect::<Vec<_>>();
-        let instance_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label:    Some("Instance Buffer"),
-                contents: bytemuck::cast_slice(&instance_data),
-                usage:    wgpu::BufferUsages::VERTEX,
-            }
-        );
-
-        // Rendering
-
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label:  Some("Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
-        });
-
-        let depth_texture = texture::Texture::create_depth_texture(&device, &config, "depth_texture");
-
-        let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("Render Pipeline Layout"),
-            bind_group_layouts:   &[
-                &texture_bind_group_layout,
-                &camera_bind_group_layout,
-            ],
-            push_constant_ranges: &[],
-        });
-
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label:    Some("Render Pipeline"),
-            layout:   Some(&render_pipeline_layout),
-            vertex:   wgpu::VertexState {
-                module:      &shader,
-                entry_point: "vs_main",
-                buffers:     &[
-                    model::ModelVertex::desc(),
-                    InstanceRaw::desc(),
-                ],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module:      &shader,
-                entry_point: "fs_main",
-                targets:     &[Some(wgpu::ColorTargetState {
-                    format:     config.format,
-                    blend:      Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology:           wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face:         wgpu::FrontFace::Ccw,
-                cull_mode:          Some(wgpu::Face::Back),
-                polygon_mode:       wgpu::PolygonMode::Fill,
-                unclipped_depth:    false,
-                conservative:       false,
-            },
-            depth_stencil:           Some(wgpu::DepthStencilState {
-                format:              texture::Texture::DEPTH_FORMAT,
-                depth_write_enabled: true,
-                depth_compare:       wgpu::CompareFunction::Less, // when to discard a new pixel
-                stencil:             wgpu::StencilState::default(),
-                bias:                wgpu::DepthBiasState::default(),
-            }),
-            multisample:   wgpu::MultisampleState {
-                count: 1,
-                mask:  !0,
-                alpha_to_coverage_enabled: false
-            },
-            multiview: None,
-        });
-
-        Self {
-            surface,
-            device,
-            queue,
-            config,
-            size,
-            render_pipeline,
-            obj_model,
-            camera,
-            camera_controller,
-            camera_buffer,
-            camera_bind_group,
-            camera_uniform,
-            instances,
-            instance_buffer,
-            depth_texture,
-            window,
-        }
-    }
-
-    pub fn window(&self) -> &Window {
-        &self.window
-    }
-
-    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
-        if new_size.width > 0 && new_size.height > 0 {
-            self.camera.aspect = self.config.width as f32 / self.config.height as f32;
-            self.size          = new_size;
-            self.config.width  = new_size.width;
-            self.config.height = new_size.height;
-            self.depth_texture = texture::Texture::create_depth_texture(&self.device, &self.config, "depth texture");
-
-            self.surface.configure(&self.device, &self.config);
-        }
-    }
-
-    fn input(&mut self, event: &WindowEvent) -> bool {
-        self.camera_controller.process_events(event)
-    }
-
-    fn update(&mut self) {
-        self.camera_controller.update_camera(&mut self.camera);
-        self.camera_uniform.update_view_proj(&self.camera);
-        self.queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[self.camera_uniform]));
-    }
-
-    fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let output      = self.surface.get_current_texture()?;
-        let view        = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("Render Encoder"),
-        });
-
-        // `begin_render_pass()` borrows `encoder`. We want to drop any variables when
-        // it leaves scope, thus releasing `encoder` so we can call `.finish()`
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops:  wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.1,
-                            g: 0.2,
-                            b: 0.3,
-                            a: 1.0,
-                        }),
-                        store: true
-                    },
-                })],
-                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view:       &self.depth_texture.view,
-                    depth_ops:  Some(wgpu::Operations {
-                        load:  wgpu::LoadOp::Clear(1.0),
-                        store: true,
-                    }),
-                    stencil_ops: None,
-                }),
-            });
-
-            render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-            render_pass.set_pipeline(&self.render_pipeline);
-
-            render_pass.draw_model_instanced(
-                &self.obj_model,
-                0..self.instances.len() as u32,
-                &self.camera_bind_group
-            );
-        }
-
-        self.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
-
-        Ok(())
-    }
-}
+pub mod ambient;
+pub mod analysis;
+pub mod animation;
+pub mod app;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod asset_cache;
+#[cfg(feature = "audio")]
+pub mod audio;
+pub mod bind_group;
+pub mod bloom;
+pub mod bounds;
+pub mod camera;
+pub mod camera_motion;
+pub mod camera_path;
+pub mod canvas;
+#[cfg(feature = "renderdoc")]
+pub mod capture;
+pub mod color_grading;
+pub mod compute;
+pub mod contact_shadows;
+pub mod crash_report;
+pub mod curves;
+pub mod debug_draw;
+pub mod debug_view;
+pub mod dof;
+pub mod edges;
+#[cfg(feature = "egui")]
+pub mod egui_overlay;
+pub mod editor;
+pub mod environment;
+pub mod exposure;
+pub mod foliage;
+pub mod frame_share;
+pub mod fur;
+pub mod gpu_cull;
+pub mod gpu_stats;
+pub mod hiz;
+pub mod image_filters;
+pub mod in_flight;
+pub mod input;
+pub mod lens_flare;
+pub mod light;
+pub mod light_clusters;
+pub mod light_probes;
+pub mod lightmap;
+#[cfg(feature = "live_control")]
+pub mod live_control;
+pub mod ltc;
+pub mod lod;
+pub mod material_array;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod material_file;
+pub mod minimap;
+pub mod model;
+pub mod morph;
+pub mod motion_blur;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod multi_gpu;
+pub mod nbody;
+pub mod ocean;
+#[cfg(feature = "meshopt")]
+pub mod optimize;
+pub mod outline;
+pub mod packing;
+pub mod pass;
+pub mod pass_timing;
+pub mod planet;
+pub mod plugin;
+pub mod portal;
+pub mod raycast;
+pub mod reflect;
+pub mod reflection;
+pub mod renderer;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod replay;
+pub mod resolution;
+pub mod resources;
+pub mod retro;
+pub mod scene;
+pub mod schedule;
+pub mod shader_code;
+pub mod shader_console;
+pub mod shader_variants;
+pub mod shading;
+pub mod shadow;
+pub mod split_screen;
+pub mod ssr;
+pub mod stereo;
+pub mod texture;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod texture_streaming;
+pub mod thumbnail;
+#[cfg(all(feature = "bc_compress", not(target_arch = "wasm32")))]
+pub mod transcode;
+pub mod transmission;
+pub mod ui;
+pub mod uniform;
+pub mod velocity;
+pub mod voxel;
+pub mod weather;
+#[cfg(all(target_arch = "wasm32", feature = "webxr"))]
+mod webxr;
+pub mod wide_lines;
+#[cfg(feature = "vr")]
+pub mod xr;
+
+mod demo;
+
+pub use app::{run_app, App};
+pub use plugin::{Plugin, Plugins};
+pub use learn_wgpu_derive::VertexLayout;
 
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen(start))]
 pub async fn run() {
-    // Toggle logger based on WASM or desktop
-    cfg_if::cfg_if! {
-        if #[cfg(target_arch = "wasm32")] {
-            std::panic::set_hook(Box::new(console_error_panic_hook::hook));
-            console_log::init_with_level(log::Level::Warn).expect("Couldn't initialize logger");
-        } else {
-            env_logger::init();
-        }
-    }
-
-    // Window setup
-    let event_loop = EventLoop::new();
-    let window     = WindowBuilder::new().build(&event_loop).unwrap();
-
-    // Add a canvas to the HTML document
-    #[cfg(target_arch = "wasm32")]
-    {
-        use winit::dpi::PhysicalSize;
-        use winit::platform::web::WindowExtWebSys;
-
-        window.set_inner_size(PhysicalSize::new(450, 400));
-
-        web_sys::window()
-            .and_then(|win| win.document())
-            .and_then(|doc| {
-                let dst    = doc.get_element_by_id("wasm-example")?;
-                let canvas = websys::Element::from(window.canvas());
-
-                dst.append_child(&canvas).ok()?;
-
-                Some(())
-            })
-            .expect("Couldn't append canvas to document body");
-    }
-
-    // State::new uses async code, so wait to finish
-    let mut state = State::new(window).await;
-
-    // Event loop
-    event_loop.run(move |event, _, control_flow| match event {
-        Event::WindowEvent {
-            ref event,
-            window_id,
-        } if window_id == state.window.id() => {
-            if !state.input(event) {
-                match event {
-                    WindowEvent::CloseRequested | WindowEvent::KeyboardInput {
-                        input: KeyboardInput {
-                            state: ElementState::Pressed,
-                            virtual_keycode: Some(VirtualKeyCode::Escape),
-                            ..
-                        },
-                        ..
-                    } => *control_flow = ControlFlow::Exit,
-                    WindowEvent::Resized(physical_size) => {
-                        state.resize(*physical_size);
-                    }
-                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                        state.resize(**new_inner_size) // dereference it bc it's &&mut
-                    }
-                    _ => {}
-                }
-            }
-        }
-        Event::RedrawRequested(window_id) if window_id == state.window().id() => {
-            state.update();
-            match state.render() {
-                Ok(_) => {},
-                // Reconfigure the surface if lost
-                Err(wgpu::SurfaceError::Lost) => state.resize(state.size),
-                // The system is out of memory--quit
-                Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
-                // All other errors (Outdated, Timeout) should be resolved by the next frame
-                Err(e) => eprintln!("{:?}", e),
-            }
-        }
-        Event::MainEventsCleared => {
-            // RedrawRequested will only trigger once unless we manually retrigger it
-            state.window().request_redraw();
-        }
-        _ => {}
-
-    });
-
+    run_app::<demo::Demo>().await;
 }
@@ -0,0 +1,492 @@
+// Lights shared by every shading model, bound at @group(2). Uniforms rather than a storage buffer
+// since WebGL has no storage buffers, which caps how many lights fit.
+
+use cgmath::prelude::*;
+use image::GenericImageView;
+
+use crate::{
+    ambient::SphericalHarmonics,
+    bind_group::{self, kind},
+    camera::OPENGL_TO_WGPU_MATRIX,
+    contact_shadows::ContactShadowSettings,
+    debug_view::DebugView,
+    light_probes::{ProbeGrid, MAX_PROBES},
+    ltc,
+    packing,
+    renderer::GpuContext,
+    shadow::{self, AtlasTile, ShadowAtlas, ShadowCaster, ShadowQuality, ShadowSettings},
+    texture::Texture,
+    uniform::{Uniform, UniformBuffer},
+};
+
+pub const MAX_SPOT_LIGHTS: usize = 8;
+pub const MAX_RECT_LIGHTS: usize = 4;
+pub const MAX_COOKIES:     u32   = 4;
+// Cookies are resized to this on upload so they fit in one texture array
+pub const COOKIE_SIZE:     u32   = 256;
+
+// Closer than this to the light the cookie and shadow projection is clipped, which isn't noticeable.
+// Also in lights.wgsl.
+const LIGHT_NEAR: f32 = 0.05;
+
+#[derive(Debug, Copy, Clone)]
+pub struct SpotLight {
+    pub position:    cgmath::Point3<f32>,
+    pub direction:   cgmath::Vector3<f32>,
+    pub color:       [f32; 3],
+    pub intensity:   f32,
+    // Light falls off to nothing at this distance
+    pub range:       f32,
+    // Full intensity inside the inner cone, fading out to nothing at the outer one.
+    // Both are half-angles from the direction.
+    pub inner_angle: cgmath::Deg<f32>,
+    pub outer_angle: cgmath::Deg<f32>,
+    // Layer of the cookie texture projected through the cone, if any
+    pub cookie:      Option<u32>,
+    pub shadows:     bool,
+}
+
+impl SpotLight {
+    // Perspective projection along the cone, used to look up the cookie and render the shadow map
+    pub fn view_proj(&self) -> cgmath::Matrix4<f32> {
+        let direction = self.direction.normalize();
+        // Any up vector works as long as it isn't parallel to the direction
+        let up = if direction.y.abs() > 0.99 { cgmath::Vector3::unit_z() } else { cgmath::Vector3::unit_y() };
+
+        let view = cgmath::Matrix4::look_to_rh(self.position, direction, up);
+        let proj = cgmath::perspective(self.outer_angle * 2.0, 1.0, LIGHT_NEAR, self.range.max(LIGHT_NEAR * 2.0));
+
+        OPENGL_TO_WGPU_MATRIX * proj * view
+    }
+
+    // Shadow resolution goes to the lights that are bright and close to the camera
+    fn shadow_priority(&self, eye: cgmath::Point3<f32>) -> f32 {
+        self.intensity / (1.0 + self.position.distance(eye))
+    }
+
+    fn to_raw(self, tile: Option<AtlasTile>) -> SpotLightRaw {
+        SpotLightRaw {
+            view_proj:           self.view_proj().into(),
+            position_range:      [self.position.x, self.position.y, self.position.z, self.range],
+            direction_cos_outer: self.direction.normalize().extend(cgmath::Rad::from(self.outer_angle).0.cos()).into(),
+            color_cos_inner:     [
+                self.color[0] * self.intensity,
+                self.color[1] * self.intensity,
+                self.color[2] * self.intensity,
+                cgmath::Rad::from(self.inner_angle).0.cos(),
+            ],
+            shadow_rect:         tile.map_or([0.0; 4], |tile| tile.uv_rect()),
+            cookie:              self.cookie.map_or(-1, |layer| layer as i32),
+            _padding:            [0; 3],
+        }
+    }
+}
+
+// A glowing rectangle, like a window or a ceiling panel, lit with linearly transformed cosines
+// (see ltc.rs) so its light and highlights soften with its size. It has no shadows.
+#[derive(Debug, Copy, Clone)]
+pub struct RectLight {
+    pub position:  cgmath::Point3<f32>,
+    // Half the width and height, along the rectangle's sides. It shines towards `right.cross(up)`.
+    pub right:     cgmath::Vector3<f32>,
+    pub up:        cgmath::Vector3<f32>,
+    pub color:     [f32; 3],
+    // Brightness of the surface itself, so bigger lights give off more
+    pub intensity: f32,
+    // Shines from the back as well
+    pub two_sided: bool,
+}
+
+impl RectLight {
+    fn to_raw(self) -> RectLightRaw {
+        RectLightRaw {
+            center_two_sided: [self.position.x, self.position.y, self.position.z, self.two_sided as u32 as f32],
+            right:            self.right.extend(0.0).into(),
+            up:               self.up.extend(0.0).into(),
+            color:            [
+                self.color[0] * self.intensity,
+                self.color[1] * self.intensity,
+                self.color[2] * self.intensity,
+                0.0,
+            ],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct SpotLightRaw {
+    view_proj:           [[f32; 4]; 4],
+    position_range:      [f32; 4],
+    direction_cos_outer: [f32; 4],
+    // Color premultiplied by intensity
+    color_cos_inner:     [f32; 4],
+    // Where the light's shadow map is in the atlas, all zero without one
+    shadow_rect:         [f32; 4],
+    cookie:              i32,
+    _padding:            [u32; 3],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct LightsUniform {
+    spot_count:        u32,
+    shadow_kernel:     u32,
+    shadow_pcss:       u32,
+    shadow_light_size: f32,
+    ambient:           [[f32; 4]; 9],
+    spots:             [SpotLightRaw; MAX_SPOT_LIGHTS],
+    probe_origin:      [f32; 3],
+    probe_spacing:     f32,
+    probe_counts:      [u32; 3],
+    // Zero without a probe grid, which leaves `ambient` lighting everything
+    probe_count:       u32,
+    probes:            [ProbeRaw; MAX_PROBES],
+    rect_count:        u32,
+    _padding:          [u32; 3],
+    rects:             [RectLightRaw; MAX_RECT_LIGHTS],
+    // Zero steps turns contact shadows off
+    contact_steps:     u32,
+    contact_length:    f32,
+    contact_thickness: f32,
+    wetness:           f32,
+    // `DebugView::surface_mode`
+    debug_view:        u32,
+    _padding_end:      [u32; 3],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct ProbeRaw {
+    irradiance: [[f32; 4]; 9],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct RectLightRaw {
+    // w is 1.0 for two-sided lights
+    center_two_sided: [f32; 4],
+    right:            [f32; 4],
+    up:               [f32; 4],
+    // Premultiplied by intensity
+    color:            [f32; 4],
+}
+
+pub struct Lights {
+    pub spots:           Vec<SpotLight>,
+    pub rects:           Vec<RectLight>,
+    pub shadow_settings: ShadowSettings,
+    // Light from the surroundings that every surface gets, white by default
+    pub ambient:         SphericalHarmonics,
+    // Takes over from `ambient` where there is one, see light_probes.rs
+    pub probes:          Option<ProbeGrid>,
+    // Needs the depth copied by `ContactShadows`, see `set_scene_depth`
+    pub contact_shadows: Option<ContactShadowSettings>,
+    // How soaked surfaces are from 0.0 to 1.0, darkening and smoothing them, see weather.rs
+    pub wetness:         f32,
+    // Shows what the shading models read in place of the lit color, see debug_view.rs
+    pub debug_view:      DebugView,
+    pub layout:          wgpu::BindGroupLayout,
+    pub bind_group:      wgpu::BindGroup,
+    buffer:              UniformBuffer<LightsUniform>,
+    cookies:             wgpu::Texture,
+    cookie_view:         wgpu::TextureView,
+    cookie_sampler:      wgpu::Sampler,
+    ltc_view:            wgpu::TextureView,
+    // The two area light tables, one per layer
+    ltc_tables:          wgpu::Texture,
+    shadow_atlas:        ShadowAtlas,
+    // Atlas tile of each spot light, from the last `prepare`
+    shadow_tiles:        Vec<Option<AtlasTile>>,
+}
+
+// What each of the lights' bindings holds, in `layout_builder`'s order
+pub type LightsBindings = (kind::Uniform, (kind::Texture, (kind::Sampler, (kind::Texture, (kind::Sampler, (kind::Texture, (kind::Texture, ())))))));
+
+impl Lights {
+    pub fn layout_builder() -> bind_group::BindGroupLayoutBuilder<LightsBindings> {
+        bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::FRAGMENT)
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2Array)
+            .sampler(wgpu::ShaderStages::FRAGMENT)
+            .depth_texture(wgpu::ShaderStages::FRAGMENT)
+            .comparison_sampler(wgpu::ShaderStages::FRAGMENT)
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2Array)
+            .depth_texture(wgpu::ShaderStages::FRAGMENT)
+    }
+
+    // `layout` has `layout_builder`'s entries. `vertex_layouts` are those of the shadow casters,
+    // see `ShadowCaster`.
+    pub fn new(ctx: &GpuContext, layout: wgpu::BindGroupLayout, vertex_layouts: &[wgpu::VertexBufferLayout]) -> Self {
+        let device = &ctx.device;
+
+        let buffer = UniformBuffer::new(device, "Lights Buffer");
+
+        let size = wgpu::Extent3d {
+            width:                 COOKIE_SIZE,
+            height:                COOKIE_SIZE,
+            depth_or_array_layers: MAX_COOKIES,
+        };
+
+        let cookies = device.create_texture(&wgpu::TextureDescriptor {
+            label:           Some("Light Cookies"),
+            size,
+            mip_level_count: 1,
+            sample_count:    1,
+            dimension:       wgpu::TextureDimension::D2,
+            format:          wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage:           wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+
+        let cookie_view = cookies.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+
+        // Past the edge of a cookie the cone has already faded out, so clamp rather than tile
+        let cookie_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label:          Some("Light Cookie Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter:     wgpu::FilterMode::Linear,
+            min_filter:     wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let shadow_atlas = ShadowAtlas::new(device, vertex_layouts);
+
+        // Half floats, since full ones can't be filtered everywhere. Read with the cookie sampler.
+        let ltc_tables = device.create_texture(&wgpu::TextureDescriptor {
+            label:           Some("LTC Tables"),
+            size:            wgpu::Extent3d {
+                width:                 ltc::LUT_SIZE,
+                height:                ltc::LUT_SIZE,
+                depth_or_array_layers: 2,
+            },
+            mip_level_count: 1,
+            sample_count:    1,
+            dimension:       wgpu::TextureDimension::D2,
+            format:          wgpu::TextureFormat::Rgba16Float,
+            usage:           wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+
+        let ltc_view = ltc_tables.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+
+        // Nothing to march through until `set_scene_depth`, and a depth that matches no view
+        let scene_depth = Texture::create_depth_texture(device, 1, 1, "lights_scene_depth_texture");
+
+        let bind_group = create_bind_group(device, &layout, &buffer, &cookie_view, &cookie_sampler, &shadow_atlas, &ltc_view, &scene_depth.view);
+
+        let lights = Self {
+            spots: Vec::new(),
+            rects: Vec::new(),
+            shadow_settings: ShadowQuality::Medium.settings(),
+            ambient: SphericalHarmonics::default(),
+            probes: None,
+            contact_shadows: None,
+            wetness: 0.0,
+            debug_view: DebugView::Off,
+            layout,
+            bind_group,
+            buffer,
+            cookies,
+            cookie_view,
+            cookie_sampler,
+            ltc_view,
+            ltc_tables,
+            shadow_atlas,
+            shadow_tiles: Vec::new(),
+        };
+
+        let (inverse, magnitude) = ltc::approximate_tables();
+        lights.set_ltc_tables(&ctx.queue, &inverse, &magnitude);
+
+        lights
+    }
+
+    // The depth of the opaque scene for contact shadows to march through, copied by
+    // `ContactShadows`. Has to be set again whenever the copy is resized.
+    pub fn set_scene_depth(&mut self, device: &wgpu::Device, depth: &wgpu::TextureView) {
+        self.bind_group = create_bind_group(
+            device,
+            &self.layout,
+            &self.buffer,
+            &self.cookie_view,
+            &self.cookie_sampler,
+            &self.shadow_atlas,
+            &self.ltc_view,
+            depth,
+        );
+    }
+
+    // Replaces the area light tables, each `ltc::LUT_SIZE` squared entries laid out as
+    // `ltc::approximate_tables` describes, e.g. with the fitted GGX tables from the LTC paper
+    pub fn set_ltc_tables(&self, queue: &wgpu::Queue, inverse: &[[f32; 4]], magnitude: &[[f32; 4]]) {
+        let entries = (ltc::LUT_SIZE * ltc::LUT_SIZE) as usize;
+
+        assert!(inverse.len() == entries && magnitude.len() == entries, "LTC tables need {} entries each", entries);
+
+        for (layer, table) in [inverse, magnitude].into_iter().enumerate() {
+            let halves = table.iter().flatten().map(|&value| packing::f16(value)).collect::<Vec<_>>();
+
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    aspect:    wgpu::TextureAspect::All,
+                    texture:   &self.ltc_tables,
+                    mip_level: 0,
+                    origin:    wgpu::Origin3d { x: 0, y: 0, z: layer as u32 },
+                },
+                bytemuck::cast_slice(&halves),
+                wgpu::ImageDataLayout {
+                    offset:         0,
+                    bytes_per_row:  std::num::NonZeroU32::new(8 * ltc::LUT_SIZE),
+                    rows_per_image: std::num::NonZeroU32::new(ltc::LUT_SIZE),
+                },
+                wgpu::Extent3d {
+                    width:                 ltc::LUT_SIZE,
+                    height:                ltc::LUT_SIZE,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+    }
+
+    pub fn set_cookie(&self, queue: &wgpu::Queue, layer: u32, img: &image::DynamicImage) {
+        assert!(layer < MAX_COOKIES, "Cookie layer {} is out of range", layer);
+
+        let img = if img.dimensions() == (COOKIE_SIZE, COOKIE_SIZE) {
+            img.to_rgba8()
+        } else {
+            img.resize_exact(COOKIE_SIZE, COOKIE_SIZE, image::imageops::FilterType::Triangle).to_rgba8()
+        };
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                aspect:    wgpu::TextureAspect::All,
+                texture:   &self.cookies,
+                mip_level: 0,
+                origin:    wgpu::Origin3d { x: 0, y: 0, z: layer },
+            },
+            &img,
+            wgpu::ImageDataLayout {
+                offset:         0,
+                bytes_per_row:  std::num::NonZeroU32::new(4 * COOKIE_SIZE),
+                rows_per_image: std::num::NonZeroU32::new(COOKIE_SIZE),
+            },
+            wgpu::Extent3d {
+                width:                 COOKIE_SIZE,
+                height:                COOKIE_SIZE,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    // Lights past `MAX_SPOT_LIGHTS` and `MAX_RECT_LIGHTS` are left out. `eye` is the camera position,
+    // which decides how the shadow atlas is shared out.
+    pub fn prepare(&mut self, queue: &wgpu::Queue, eye: cgmath::Point3<f32>) {
+        let spots = &self.spots[..self.spots.len().min(MAX_SPOT_LIGHTS)];
+
+        let casters    = (0..spots.len()).filter(|&i| spots[i].shadows).collect::<Vec<_>>();
+        let priorities = casters.iter().map(|&i| spots[i].shadow_priority(eye)).collect::<Vec<_>>();
+
+        self.shadow_tiles = vec![None; spots.len()];
+
+        for (&i, tile) in casters.iter().zip(shadow::allocate_tiles(&priorities)) {
+            self.shadow_tiles[i] = tile;
+        }
+
+        let mut uniform = LightsUniform {
+            spot_count:        spots.len() as u32,
+            shadow_kernel:     self.shadow_settings.kernel_size.max(1),
+            shadow_pcss:       self.shadow_settings.pcss as u32,
+            shadow_light_size: self.shadow_settings.light_size,
+            ambient:           self.ambient.to_raw(),
+            spots:             [bytemuck::Zeroable::zeroed(); MAX_SPOT_LIGHTS],
+            probe_origin:      [0.0; 3],
+            probe_spacing:     1.0,
+            probe_counts:      [0; 3],
+            probe_count:       0,
+            probes:            [bytemuck::Zeroable::zeroed(); MAX_PROBES],
+            rect_count:        0,
+            _padding:          [0; 3],
+            rects:             [bytemuck::Zeroable::zeroed(); MAX_RECT_LIGHTS],
+            contact_steps:     0,
+            contact_length:    0.0,
+            contact_thickness: 0.0,
+            wetness:           self.wetness.clamp(0.0, 1.0),
+            debug_view:        self.debug_view.surface_mode(),
+            _padding_end:      [0; 3],
+        };
+
+        if let Some(contact) = self.contact_shadows {
+            uniform.contact_steps     = contact.steps;
+            uniform.contact_length    = contact.length;
+            uniform.contact_thickness = contact.thickness;
+        }
+
+        for (raw, rect) in uniform.rects.iter_mut().zip(&self.rects) {
+            *raw = rect.to_raw();
+            uniform.rect_count += 1;
+        }
+
+        if let Some(grid) = &self.probes {
+            uniform.probe_origin  = grid.origin.into();
+            uniform.probe_spacing = grid.spacing;
+            uniform.probe_counts  = grid.counts;
+            uniform.probe_count   = grid.probes.len() as u32;
+
+            for (raw, probe) in uniform.probes.iter_mut().zip(&grid.probes) {
+                raw.irradiance = probe.to_raw();
+            }
+        }
+
+        for (slot, (spot, tile)) in spots.iter().zip(&self.shadow_tiles).enumerate() {
+            uniform.spots[slot] = spot.to_raw(*tile);
+
+            if tile.is_some() {
+                self.shadow_atlas.set_view_proj(queue, slot, spot.view_proj());
+            }
+        }
+
+        self.buffer.write(queue, &uniform);
+    }
+
+    // The whole shadow atlas, for debug views
+    pub fn shadow_atlas_view(&self) -> &wgpu::TextureView {
+        &self.shadow_atlas.view
+    }
+
+    // Where the `index`th spot light's shadow map went in the atlas at the last `prepare`, if it got one
+    pub fn shadow_tile(&self, index: usize) -> Option<AtlasTile> {
+        self.shadow_tiles.get(index).copied().flatten()
+    }
+
+    pub fn render_shadows(&self, encoder: &mut wgpu::CommandEncoder, caster: &dyn ShadowCaster) {
+        self.shadow_atlas.render(encoder, &self.shadow_tiles, caster);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn create_bind_group(
+    device:         &wgpu::Device,
+    layout:         &wgpu::BindGroupLayout,
+    buffer:         &UniformBuffer<LightsUniform>,
+    cookie_view:    &wgpu::TextureView,
+    cookie_sampler: &wgpu::Sampler,
+    shadow_atlas:   &ShadowAtlas,
+    ltc_view:       &wgpu::TextureView,
+    scene_depth:    &wgpu::TextureView,
+) -> wgpu::BindGroup {
+    bind_group::BindGroupBuilder::checked(layout, &Lights::layout_builder())
+        .uniform(buffer.buffer())
+        .texture(cookie_view)
+        .sampler(cookie_sampler)
+        .texture(&shadow_atlas.view)
+        .sampler(&shadow_atlas.sampler)
+        .texture(ltc_view)
+        .texture(scene_depth)
+        .build(device, "lights_bind_group")
+}
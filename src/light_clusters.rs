@@ -0,0 +1,278 @@
+// Light culling statistics on a cluster grid, the view frustum cut into cells in x, y and depth the
+// way a clustered renderer would sort its lights. A compute pass counts the spot lights whose range
+// reaches each cell, the counts are read back a frame or so later for `stats`, and a heatmap over
+// the screen shows where the most lights overlap. Shading doesn't use the clusters, this is for
+// finding the hot spots a light list would have.
+//
+// Needs compute shaders and storage buffers, so it isn't available on WebGL.
+
+use crate::{
+    bind_group,
+    camera::Camera,
+    compute::{self, AsyncReadback},
+    crash_report,
+    light::SpotLight,
+    pass::{Drawable, RenderLayer},
+    renderer::GpuContext,
+    uniform::{Uniform, UniformBuffer},
+};
+
+const WORKGROUP_SIZE: u32 = 64;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct ClusterParams {
+    view:         [[f32; 4]; 4],
+    cells:        [u32; 3],
+    light_count:  u32,
+    tan_half_fov: [f32; 2],
+    near:         f32,
+    far:          f32,
+    heat_max:     f32,
+    opacity:      f32,
+    _padding:     [f32; 2],
+}
+
+// What the last counts read back showed
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct ClusterStats {
+    // Most lights reaching one cell, and which cell that was
+    pub max:      u32,
+    pub hottest:  [u32; 3],
+    // Cells at least one light reaches, out of `cells`
+    pub occupied: u32,
+    pub cells:    u32,
+    // Lights per cell, over the occupied cells only
+    pub average:  f32,
+}
+
+impl ClusterStats {
+    // `counts` in the grid's order, x fastest and depth slowest
+    fn from_counts(counts: &[u32], cells: [u32; 3]) -> Self {
+        let mut stats = Self { cells: counts.len() as u32, ..Default::default() };
+        let mut total = 0;
+
+        for (index, &count) in counts.iter().enumerate() {
+            let index = index as u32;
+
+            if count > stats.max {
+                stats.max     = count;
+                stats.hottest = [index % cells[0], index / cells[0] % cells[1], index / (cells[0] * cells[1])];
+            }
+            if count > 0 {
+                stats.occupied += 1;
+                total          += count;
+            }
+        }
+
+        stats.average = if stats.occupied > 0 { total as f32 / stats.occupied as f32 } else { 0.0 };
+        stats
+    }
+}
+
+pub struct LightClusters {
+    // Lights in one cell at which the heatmap is fully red
+    pub heat_max:     u32,
+    pub opacity:      f32,
+    cells:            [u32; 3],
+    params:           UniformBuffer<ClusterParams>,
+    count_pipeline:   wgpu::ComputePipeline,
+    count_layout:     wgpu::BindGroupLayout,
+    count_group:      wgpu::BindGroup,
+    heatmap_pipeline: wgpu::RenderPipeline,
+    heatmap_group:    wgpu::BindGroup,
+    lights:           wgpu::Buffer,
+    light_capacity:   usize,
+    counts:           wgpu::Buffer,
+    readback:         AsyncReadback,
+    stats:            Option<ClusterStats>,
+}
+
+impl LightClusters {
+    // `cells` in x, y and depth
+    pub fn new(ctx: &GpuContext, cells: [u32; 3]) -> Self {
+        let device = &ctx.device;
+        let cells  = cells.map(|n| n.max(1));
+        let size   = (cells.iter().product::<u32>() as usize * std::mem::size_of::<u32>()) as wgpu::BufferAddress;
+
+        let params = UniformBuffer::new(device, "Light Cluster Params Buffer");
+
+        let counts = device.create_buffer(&wgpu::BufferDescriptor {
+            label:              Some("Light Cluster Count Buffer"),
+            size,
+            usage:              wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let light_capacity = 8;
+        let lights         = create_light_buffer(device, light_capacity);
+
+        let count_layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::COMPUTE)
+            .storage(wgpu::ShaderStages::COMPUTE, true)
+            .storage(wgpu::ShaderStages::COMPUTE, false)
+            .build(device, "light_cluster_bind_group_layout");
+
+        let count_group    = create_count_group(device, &count_layout, &params, &lights, &counts);
+        let count_pipeline = compute::create_pipeline(device, &count_layout, include_str!("light_clusters.wgsl"), "Light Cluster Count");
+
+        let heatmap_layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::FRAGMENT)
+            .storage(wgpu::ShaderStages::FRAGMENT, true)
+            .build(device, "light_heatmap_bind_group_layout");
+
+        let heatmap_group = bind_group::BindGroupBuilder::new(&heatmap_layout)
+            .uniform(params.buffer())
+            .storage(&counts)
+            .build(device, "light_heatmap_bind_group");
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Light Heatmap Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("light_heatmap.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Light Heatmap Pipeline Layout"),
+            bind_group_layouts:   &[&heatmap_layout],
+            push_constant_ranges: &[],
+        });
+
+        // Blended over the finished frame in the UI layer, which has no depth attachment
+        let heatmap_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label:    Some("Light Heatmap Pipeline"),
+            layout:   Some(&pipeline_layout),
+            vertex:   wgpu::VertexState {
+                module:      &shader,
+                entry_point: "vs_main",
+                buffers:     &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module:      &shader,
+                entry_point: "fs_main",
+                targets:     &[Some(wgpu::ColorTargetState {
+                    format:     ctx.config.format,
+                    blend:      Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive:     wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample:   wgpu::MultisampleState::default(),
+            multiview:     None,
+        });
+
+        Self {
+            heat_max:       4,
+            opacity:        0.4,
+            cells,
+            params,
+            count_pipeline,
+            count_layout,
+            count_group,
+            heatmap_pipeline,
+            heatmap_group,
+            lights,
+            light_capacity,
+            counts,
+            readback:       AsyncReadback::new(device, size, "Light Cluster Readback Buffer"),
+            stats:          None,
+        }
+    }
+
+    // Counts the lights in each of `camera`'s cells and starts reading the counts back, picking
+    // up an earlier read if it's arrived. Submitted straight away.
+    pub fn update(&mut self, ctx: &GpuContext, camera: &Camera, spots: &[SpotLight]) {
+        let device = &ctx.device;
+        let queue  = &ctx.queue;
+
+        if let Some(counts) = self.readback.try_read::<u32>(device) {
+            self.stats = Some(ClusterStats::from_counts(&counts, self.cells));
+        }
+
+        if spots.len() > self.light_capacity {
+            self.light_capacity = spots.len().next_power_of_two();
+            self.lights         = create_light_buffer(device, self.light_capacity);
+            self.count_group    = create_count_group(device, &self.count_layout, &self.params, &self.lights, &self.counts);
+        }
+
+        let lights = spots.iter()
+            .map(|spot| [spot.position.x, spot.position.y, spot.position.z, spot.range])
+            .collect::<Vec<_>>();
+
+        queue.write_buffer(&self.lights, 0, bytemuck::cast_slice(&lights));
+
+        let tan_y = (cgmath::Rad::from(cgmath::Deg(camera.fovy)).0 / 2.0).tan();
+
+        self.params.write(queue, &ClusterParams {
+            view:         cgmath::Matrix4::look_at_rh(camera.eye, camera.target, camera.up).into(),
+            cells:        self.cells,
+            light_count:  spots.len() as u32,
+            tan_half_fov: [tan_y * camera.aspect, tan_y],
+            near:         camera.znear,
+            far:          camera.zfar,
+            heat_max:     self.heat_max.max(1) as f32,
+            opacity:      self.opacity,
+            _padding:     [0.0; 2],
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Light Cluster Encoder"),
+        });
+
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Light Cluster Pass"),
+            });
+
+            let cell_count = self.cells.iter().product::<u32>();
+
+            crash_report::set_compute_pipeline(&mut compute_pass, &self.count_pipeline, "Light Cluster Count");
+            compute_pass.set_bind_group(0, &self.count_group, &[]);
+            compute_pass.dispatch_workgroups(cell_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+
+        let copied = self.readback.copy(&mut encoder, &self.counts);
+
+        queue.submit(std::iter::once(encoder.finish()));
+
+        if copied {
+            self.readback.submitted();
+        }
+    }
+
+    // From counts a frame or more old, None until the first arrive
+    pub fn stats(&self) -> Option<ClusterStats> {
+        self.stats
+    }
+}
+
+impl Drawable for LightClusters {
+    fn draw<'a>(&'a self, _layer: RenderLayer, render_pass: &mut wgpu::RenderPass<'a>) {
+        crash_report::set_pipeline(render_pass, &self.heatmap_pipeline, "Light Heatmap Pipeline");
+        render_pass.set_bind_group(0, &self.heatmap_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+fn create_light_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label:              Some("Light Cluster Light Buffer"),
+        size:               (capacity * std::mem::size_of::<[f32; 4]>()) as wgpu::BufferAddress,
+        usage:              wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_count_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    params: &UniformBuffer<ClusterParams>,
+    lights: &wgpu::Buffer,
+    counts: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    bind_group::BindGroupBuilder::new(layout)
+        .uniform(params.buffer())
+        .storage(lights)
+        .storage(counts)
+        .build(device, "light_cluster_bind_group")
+}
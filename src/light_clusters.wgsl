@@ -0,0 +1,66 @@
+// Counts the spot lights whose range reaches each cell of the cluster grid, one cell per invocation
+
+struct ClusterParams {
+    view:         mat4x4<f32>,
+    cells:        vec3<u32>,
+    light_count:  u32,
+    tan_half_fov: vec2<f32>,
+    near:         f32,
+    far:          f32,
+    heat_max:     f32,
+    opacity:      f32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: ClusterParams;
+// Position and range of each light, in world space
+@group(0) @binding(1)
+var<storage, read> lights: array<vec4<f32>>;
+@group(0) @binding(2)
+var<storage, read_write> counts: array<u32>;
+
+// Distance in front of the camera where depth slice `k` starts. Slices get exponentially
+// thicker, as in `DebugDraw::cluster_grid`.
+fn slice_depth(k: u32) -> f32 {
+    return params.near * pow(params.far / params.near, f32(k) / f32(params.cells.z));
+}
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+
+    if (index >= params.cells.x * params.cells.y * params.cells.z) {
+        return;
+    }
+
+    // x varies fastest, then y, then depth
+    let i = index % params.cells.x;
+    let j = (index / params.cells.x) % params.cells.y;
+    let k = index / (params.cells.x * params.cells.y);
+
+    let near = slice_depth(k);
+    let far  = slice_depth(k + 1u);
+
+    // Sides of the cell as slopes from the view direction
+    let cells = vec2<f32>(params.cells.xy);
+    let lower = (vec2<f32>(f32(i), f32(j)) / cells * 2.0 - 1.0) * params.tan_half_fov;
+    let upper = (vec2<f32>(f32(i + 1u), f32(j + 1u)) / cells * 2.0 - 1.0) * params.tan_half_fov;
+
+    // The box around the cell in view space, where the camera looks down -z
+    let box_min = vec3<f32>(min(lower * near, lower * far), -far);
+    let box_max = vec3<f32>(max(upper * near, upper * far), -near);
+
+    var count = 0u;
+
+    for (var l = 0u; l < params.light_count; l = l + 1u) {
+        let light  = lights[l];
+        let center = (params.view * vec4<f32>(light.xyz, 1.0)).xyz;
+        let offset = center - clamp(center, box_min, box_max);
+
+        if (dot(offset, offset) <= light.w * light.w) {
+            count = count + 1u;
+        }
+    }
+
+    counts[index] = count;
+}
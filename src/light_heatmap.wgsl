@@ -0,0 +1,70 @@
+// Tints each column of the cluster grid by the most lights reaching any cell in it, from blue
+// through green and yellow to red
+
+struct ClusterParams {
+    view:         mat4x4<f32>,
+    cells:        vec3<u32>,
+    light_count:  u32,
+    tan_half_fov: vec2<f32>,
+    near:         f32,
+    far:          f32,
+    heat_max:     f32,
+    opacity:      f32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: ClusterParams;
+@group(0) @binding(1)
+var<storage, read> counts: array<u32>;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv:                  vec2<f32>,
+}
+
+// One triangle that covers the screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+
+    out.uv            = uv;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+
+    return out;
+}
+
+fn heat(t: f32) -> vec3<f32> {
+    let r = smoothstep(0.33, 0.66, t);
+    let g = smoothstep(0.0, 0.33, t) * (1.0 - smoothstep(0.66, 1.0, t));
+    let b = 1.0 - smoothstep(0.0, 0.33, t);
+
+    return vec3<f32>(r, g, b);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // The grid's rows run bottom to top
+    let cells = params.cells;
+    let i     = min(u32(in.uv.x * f32(cells.x)), cells.x - 1u);
+    let j     = min(u32((1.0 - in.uv.y) * f32(cells.y)), cells.y - 1u);
+
+    var most = 0u;
+
+    for (var k = 0u; k < cells.z; k = k + 1u) {
+        most = max(most, counts[(k * cells.y + j) * cells.x + i]);
+    }
+
+    if (most == 0u) {
+        discard;
+    }
+
+    // Darker along the cell borders so neighbouring columns can be told apart
+    let cell   = fract(in.uv * vec2<f32>(cells.xy));
+    let border = step(0.03, min(min(cell.x, cell.y), min(1.0 - cell.x, 1.0 - cell.y)));
+
+    let color = heat(clamp(f32(most) / params.heat_max, 0.0, 1.0)) * mix(0.5, 1.0, border);
+
+    return vec4<f32>(color, params.opacity);
+}
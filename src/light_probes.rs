@@ -0,0 +1,195 @@
+// A grid of irradiance probes, for moving objects in scenes whose static light is baked. Each probe
+// holds the diffuse light reaching a point as spherical harmonics, like the environment's ambient
+// light, baked by rendering the scene into the six faces of a cube around it. Set on `Lights`, the
+// grid is interpolated per fragment by `probe_lighting` in lights.wgsl, or per object with
+// `sample`.
+
+use anyhow::*;
+use cgmath::prelude::*;
+
+use crate::{
+    ambient::SphericalHarmonics,
+    camera::Camera,
+    renderer::GpuContext,
+    scene::Scene,
+    thumbnail::ThumbnailRenderer,
+};
+
+// As many as fit in the lights' uniform buffer alongside the spot lights. Also in lights.wgsl.
+pub const MAX_PROBES: usize = 64;
+
+// Closer than this and a capture clips through the surfaces around the probe
+const CAPTURE_NEAR: f32 = 0.05;
+
+// Each face's view direction and up, in the order the faces are captured
+const CUBE_FACES: [([f32; 3], [f32; 3]); 6] = [
+    ([ 1.0,  0.0,  0.0], [0.0, 1.0,  0.0]),
+    ([-1.0,  0.0,  0.0], [0.0, 1.0,  0.0]),
+    ([ 0.0,  1.0,  0.0], [0.0, 0.0, -1.0]),
+    ([ 0.0, -1.0,  0.0], [0.0, 0.0,  1.0]),
+    ([ 0.0,  0.0,  1.0], [0.0, 1.0,  0.0]),
+    ([ 0.0,  0.0, -1.0], [0.0, 1.0,  0.0]),
+];
+
+#[derive(Debug, Clone)]
+pub struct ProbeGrid {
+    // Where the first probe is, with the rest `spacing` apart along each axis
+    pub origin:  cgmath::Point3<f32>,
+    pub spacing: f32,
+    // Probes along x, y and z
+    pub counts:  [u32; 3],
+    // x first, then y, then z. Uniform white until baked.
+    pub probes:  Vec<SphericalHarmonics>,
+}
+
+impl ProbeGrid {
+    pub fn new(origin: cgmath::Point3<f32>, spacing: f32, counts: [u32; 3]) -> Self {
+        let counts = counts.map(|count| count.max(1));
+        let len    = counts.iter().product::<u32>() as usize;
+
+        assert!(len <= MAX_PROBES, "A probe grid of {} probes is more than the {} that fit", len, MAX_PROBES);
+
+        Self {
+            origin,
+            spacing,
+            counts,
+            probes: vec![SphericalHarmonics::default(); len],
+        }
+    }
+
+    // The fewest probes `spacing` apart that cover `bounds`, as long as they fit. Otherwise the
+    // spacing grows until they do.
+    pub fn covering(bounds: &crate::bounds::Aabb, spacing: f32) -> Self {
+        let extents     = bounds.extents();
+        let mut spacing = spacing.max(0.001);
+
+        loop {
+            let counts = [extents.x, extents.y, extents.z].map(|extent| (extent / spacing).ceil() as u32 + 1);
+
+            if counts.iter().product::<u32>() as usize <= MAX_PROBES {
+                return Self::new(bounds.min, spacing, counts);
+            }
+
+            spacing *= 1.25;
+        }
+    }
+
+    pub fn position(&self, index: usize) -> cgmath::Point3<f32> {
+        let [x, y, _] = self.counts.map(|count| count as usize);
+        let cell      = cgmath::Vector3::new(index % x, index / x % y, index / (x * y)).cast::<f32>().unwrap();
+
+        self.origin + cell * self.spacing
+    }
+
+    // The light at `position`, blended from the eight probes around it. Outside the grid the
+    // nearest probes on its edge are used.
+    pub fn sample(&self, position: cgmath::Point3<f32>) -> SphericalHarmonics {
+        let cell = (position - self.origin) / self.spacing.max(0.001);
+
+        // The lower corner and how far past it along each axis. Axes with a single probe stay on
+        // it, with nothing past it.
+        let axes = [cell.x, cell.y, cell.z].iter().zip(self.counts)
+            .map(|(&c, count)| {
+                let c    = c.clamp(0.0, (count - 1) as f32);
+                let base = (c.floor() as u32).min(count.saturating_sub(2));
+
+                (base, c - base as f32)
+            })
+            .collect::<Vec<_>>();
+
+        let mut coefficients = [[0.0; 3]; 9];
+
+        for corner in 0..8 {
+            let mut index  = 0;
+            let mut stride = 1;
+            let mut weight = 1.0;
+
+            for (axis, &(base, t)) in axes.iter().enumerate() {
+                let upper = corner & (1 << axis) != 0;
+
+                index  += (base + upper as u32) as usize * stride;
+                stride *= self.counts[axis] as usize;
+                weight *= if upper { t } else { 1.0 - t };
+            }
+
+            // Including every corner past an axis with a single probe, which isn't there to read
+            if weight == 0.0 {
+                continue;
+            }
+
+            for (sum, probe) in coefficients.iter_mut().zip(self.probes[index].coefficients) {
+                for (s, c) in sum.iter_mut().zip(probe) {
+                    *s += c * weight;
+                }
+            }
+        }
+
+        SphericalHarmonics { coefficients }
+    }
+
+    // Captures every probe from `scene`, seeing out to `range`. Whatever lights the scene already
+    // has are captured too, including this grid if it's been set on them, which adds a bounce.
+    pub fn bake(&mut self, ctx: &GpuContext, scene: &dyn Scene, face_size: u32, range: f32) -> Result<()> {
+        let mut renderer = ThumbnailRenderer::new(ctx, face_size, face_size);
+
+        for i in 0..self.probes.len() {
+            self.probes[i] = capture_irradiance(ctx, &mut renderer, scene, self.position(i), face_size, range)?;
+        }
+
+        Ok(())
+    }
+}
+
+// The diffuse light reaching `position`, from `scene` rendered into a cube of `face_size` faces.
+// Post effects aren't applied, so the scene's own colors are what's captured.
+pub fn capture_irradiance(
+    ctx:       &GpuContext,
+    renderer:  &mut ThumbnailRenderer,
+    scene:     &dyn Scene,
+    position:  cgmath::Point3<f32>,
+    face_size: u32,
+    range:     f32,
+) -> Result<SphericalHarmonics> {
+    // Stored colors were encoded for display when the target is sRGB
+    let srgb = ctx.config.format.describe().srgb;
+
+    let faces = CUBE_FACES.iter()
+        .map(|&(forward, up)| {
+            let camera = Camera {
+                eye:        position,
+                target:     position + cgmath::Vector3::from(forward),
+                up:         up.into(),
+                aspect:     1.0,
+                fovy:       90.0,
+                znear:      CAPTURE_NEAR,
+                zfar:       range,
+                depth_mode: ctx.depth_mode,
+            };
+
+            renderer.render_to_image(ctx, scene, &camera, (face_size, face_size))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(SphericalHarmonics::from_radiance(|direction| {
+        // The face the direction points most into, and where it lands on it
+        let (face, &(forward, up)) = CUBE_FACES.iter().enumerate()
+            .max_by(|(_, a), (_, b)| direction.dot(a.0.into()).total_cmp(&direction.dot(b.0.into())))
+            .unwrap();
+
+        let forward = cgmath::Vector3::from(forward);
+        let up      = cgmath::Vector3::from(up);
+        let right   = forward.cross(up);
+        let depth   = direction.dot(forward);
+        let x       = direction.dot(right) / depth;
+        let y       = direction.dot(up) / depth;
+
+        let pixel = |c: f32| ((c * 0.5 + 0.5) * face_size as f32).clamp(0.0, face_size as f32 - 1.0) as u32;
+        let texel = faces[face].get_pixel(pixel(x), pixel(-y));
+
+        [0, 1, 2].map(|i| {
+            let c = texel[i] as f32 / 255.0;
+
+            if srgb { c.powf(2.2) } else { c }
+        })
+    }))
+}
@@ -0,0 +1,147 @@
+// Baking light for static scenes into a texture at the meshes' lightmap coordinates, to be set on
+// their material with `Material::set_lightmap`. Each triangle is rasterized in lightmap space on
+// the CPU, and the caller says how much light reaches each texel's position and normal, e.g. the
+// sky's ambient light scaled by `sky_visibility`. Baking happens once, so it's kept simple over
+// fast.
+
+use cgmath::prelude::*;
+
+use crate::{bounds, model::ModelVertex};
+
+// Texels past a triangle's edge that take the nearest baked color, so bilinear filtering and
+// mip maps along a seam don't pull in black
+const DILATION: u32 = 2;
+
+// The light reaching each texel of a `size` by `size` lightmap, for a mesh placed by `transform`.
+// The light is gamma encoded as the material textures are, so anything brighter than 1.0 is clipped
+// and `lightmap_intensity` has to scale the baked light back up.
+pub fn bake(
+    vertices:   &[ModelVertex],
+    indices:    &[u32],
+    transform:  cgmath::Matrix4<f32>,
+    size:       u32,
+    irradiance: impl Fn(cgmath::Point3<f32>, cgmath::Vector3<f32>) -> [f32; 3],
+) -> image::RgbaImage {
+    let size    = size.max(1);
+    let mut lit = vec![None; (size * size) as usize];
+
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|i| &vertices[i as usize]);
+        let [ta, tb, tc] = [a, b, c].map(|v| cgmath::Vector2::from(v.lightmap_coords) * size as f32);
+
+        let area = edge(ta, tb, tc);
+
+        // Degenerate in lightmap space, so no texel would land inside it
+        if area.abs() < f32::EPSILON {
+            continue;
+        }
+
+        let min_x = ta.x.min(tb.x).min(tc.x).floor().max(0.0) as u32;
+        let min_y = ta.y.min(tb.y).min(tc.y).floor().max(0.0) as u32;
+        let max_x = (ta.x.max(tb.x).max(tc.x).ceil() as u32).min(size);
+        let max_y = (ta.y.max(tb.y).max(tc.y).ceil() as u32).min(size);
+
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let p = cgmath::Vector2::new(x as f32 + 0.5, y as f32 + 0.5);
+                let weights = [edge(tb, tc, p), edge(tc, ta, p), edge(ta, tb, p)].map(|w| w / area);
+
+                if weights.iter().any(|&w| w < 0.0) {
+                    continue;
+                }
+
+                let position = [a, b, c].iter().zip(weights)
+                    .fold(cgmath::Vector3::zero(), |sum, (v, w)| sum + cgmath::Vector3::from(v.position) * w);
+                let normal   = [a, b, c].iter().zip(weights)
+                    .fold(cgmath::Vector3::zero(), |sum, (v, w)| sum + cgmath::Vector3::from(v.normal) * w);
+
+                let position = transform.transform_point(cgmath::Point3::from_vec(position));
+                let normal   = transform.transform_vector(normal).normalize();
+
+                lit[(y * size + x) as usize] = Some(irradiance(position, normal));
+            }
+        }
+    }
+
+    for _ in 0..DILATION {
+        lit = dilate(&lit, size);
+    }
+
+    image::RgbaImage::from_fn(size, size, |x, y| {
+        let encode = |c: f32| (c.clamp(0.0, 1.0).powf(1.0 / 2.2) * 255.0) as u8;
+        let [r, g, b] = lit[(y * size + x) as usize].unwrap_or([0.0; 3]);
+
+        image::Rgba([encode(r), encode(g), encode(b), 255])
+    })
+}
+
+// How much of the sky `position` sees over the hemisphere around `normal`, from 0.0 when
+// surrounded to 1.0 in the open. Rays are spread by the cosine, so the result can scale the light
+// from a uniform sky directly. Anything in `bvh` within `distance` counts as blocking, by its
+// bounds rather than its triangles.
+pub fn sky_visibility(
+    bvh:      &bounds::Bvh,
+    position: cgmath::Point3<f32>,
+    normal:   cgmath::Vector3<f32>,
+    samples:  u32,
+    distance: f32,
+) -> f32 {
+    let samples = samples.max(1);
+
+    // Any two directions perpendicular to the normal and each other
+    let helper    = if normal.y.abs() < 0.99 { cgmath::Vector3::unit_y() } else { cgmath::Vector3::unit_x() };
+    let tangent   = helper.cross(normal).normalize();
+    let bitangent = normal.cross(tangent);
+
+    // Nudged off the surface so rays don't hit what they start from
+    let origin = position + normal * 0.001;
+
+    let open = (0..samples)
+        .filter(|&i| {
+            // A spiral over the disc, projected up onto the hemisphere
+            let radius     = ((i as f32 + 0.5) / samples as f32).sqrt();
+            let angle      = i as f32 * 2.399_963;
+            let (sin, cos) = angle.sin_cos();
+            let up         = (1.0 - radius * radius).max(0.0).sqrt();
+            let direction  = tangent * (radius * cos) + bitangent * (radius * sin) + normal * up;
+
+            bvh.raycast_hit(&bounds::Ray { origin, direction })
+                .is_none_or(|hit| hit.distance > distance)
+        })
+        .count();
+
+    open as f32 / samples as f32
+}
+
+// Twice the signed area of the triangle `a`, `b`, `p`
+fn edge(a: cgmath::Vector2<f32>, b: cgmath::Vector2<f32>, p: cgmath::Vector2<f32>) -> f32 {
+    (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x)
+}
+
+// Fills each unbaked texel next to baked ones with their average
+fn dilate(lit: &[Option<[f32; 3]>], size: u32) -> Vec<Option<[f32; 3]>> {
+    (0..size * size)
+        .map(|i| {
+            if lit[i as usize].is_some() {
+                return lit[i as usize];
+            }
+
+            let (x, y) = ((i % size) as i32, (i / size) as i32);
+
+            let neighbours = [(-1, 0), (1, 0), (0, -1), (0, 1)].iter()
+                .map(|(dx, dy)| (x + dx, y + dy))
+                .filter(|&(nx, ny)| nx >= 0 && ny >= 0 && nx < size as i32 && ny < size as i32)
+                .filter_map(|(nx, ny)| lit[(ny as u32 * size + nx as u32) as usize])
+                .collect::<Vec<_>>();
+
+            if neighbours.is_empty() {
+                return None;
+            }
+
+            let sum = neighbours.iter().fold([0.0; 3], |sum, c| [sum[0] + c[0], sum[1] + c[1], sum[2] + c[2]]);
+            let n   = neighbours.len() as f32;
+
+            Some([sum[0] / n, sum[1] / n, sum[2] / n])
+        })
+        .collect()
+}
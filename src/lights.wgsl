@@ -0,0 +1,528 @@
+// Lights shared by every shading model. Prepended to each shading model's shader, after
+// `camera.wgsl`.
+
+struct SpotLight {
+    view_proj:           mat4x4<f32>,
+    position_range:      vec4<f32>,
+    direction_cos_outer: vec4<f32>,
+    // Color premultiplied by intensity
+    color_cos_inner:     vec4<f32>,
+    // Where the light's shadow map is in the atlas, all zero without one
+    shadow_rect:         vec4<f32>,
+    // Cookie texture layer, or -1 for none
+    cookie:              i32,
+}
+
+struct RectLight {
+    // w is 1.0 for two-sided lights
+    center_two_sided: vec4<f32>,
+    // Half the width and height, shining towards right x up
+    right:            vec4<f32>,
+    up:               vec4<f32>,
+    // Premultiplied by intensity
+    color:            vec4<f32>,
+}
+
+// Spherical harmonics like `Lights.ambient`, see light_probes.rs
+struct LightProbe {
+    irradiance: array<vec4<f32>, 9>,
+}
+
+struct Lights {
+    spot_count:        u32,
+    // Percentage-closer filtering grid width, in samples
+    shadow_kernel:     u32,
+    shadow_pcss:       u32,
+    // Radius of the lights' emitting area in world units, for PCSS
+    shadow_light_size: f32,
+    // Spherical harmonics of the environment's diffuse light, see ambient.rs
+    ambient:           array<vec4<f32>, 9>,
+    spots:             array<SpotLight, 8>,
+    probe_origin:      vec3<f32>,
+    probe_spacing:     f32,
+    probe_counts:      vec3<u32>,
+    // Zero without a probe grid, which leaves `ambient` lighting everything
+    probe_count:       u32,
+    // MAX_PROBES in light_probes.rs
+    probes:            array<LightProbe, 64>,
+    rect_count:        u32,
+    rects:             array<RectLight, 4>,
+    // Zero turns contact shadows off, see contact_shadows.rs
+    contact_steps:     u32,
+    contact_length:    f32,
+    contact_thickness: f32,
+    // From 0.0 for dry to 1.0 for soaked, see weather.rs
+    wetness:           f32,
+    // Non-zero for one of the surface views in debug_view.rs
+    debug_view:        u32,
+}
+
+@group(2) @binding(0)
+var<uniform> lights: Lights;
+@group(2) @binding(1)
+var t_cookies: texture_2d_array<f32>;
+@group(2) @binding(2)
+var s_cookies: sampler;
+@group(2) @binding(3)
+var t_shadow_atlas: texture_depth_2d;
+@group(2) @binding(4)
+var s_shadow: sampler_comparison;
+// Area light tables, see ltc.rs. Read with `s_cookies`, which clamps and filters as they need.
+@group(2) @binding(5)
+var t_ltc: texture_2d_array<f32>;
+// The opaque scene's depth for contact shadows, copied before the opaque layer
+@group(2) @binding(6)
+var t_scene_depth: texture_depth_2d;
+
+// Distance along a light's direction for a depth in its shadow map
+fn linear_shadow_depth(depth: f32, far: f32) -> f32 {
+    // LIGHT_NEAR in light.rs
+    let near = 0.05;
+
+    return near * far / (far - depth * (far - near));
+}
+
+// Position of sample (x, y) in the kernel grid around `center`, `spacing` texels apart. Clamped
+// to the tile so neighbouring lights' shadow maps don't bleed in.
+fn shadow_sample_uv(rect: vec4<f32>, center: vec2<f32>, x: i32, y: i32, spacing: f32) -> vec2<f32> {
+    let texel  = 1.0 / vec2<f32>(vec2<i32>(textureDimensions(t_shadow_atlas)));
+    let half   = f32(i32(lights.shadow_kernel) - 1) * 0.5;
+    let offset = (vec2<f32>(f32(x), f32(y)) - half) * spacing * texel;
+
+    return clamp(center + offset, rect.xy + texel * 0.5, rect.xy + rect.zw - texel * 0.5);
+}
+
+// Average depth of the texels in front of `depth` around `center`, or -1.0 if there are none
+fn shadow_blocker_depth(rect: vec4<f32>, center: vec2<f32>, depth: f32, spacing: f32) -> f32 {
+    let kernel = i32(lights.shadow_kernel);
+    let size   = vec2<f32>(vec2<i32>(textureDimensions(t_shadow_atlas)));
+
+    var total = 0.0;
+    var count = 0.0;
+
+    for (var y = 0; y < kernel; y = y + 1) {
+        for (var x = 0; x < kernel; x = x + 1) {
+            let uv      = shadow_sample_uv(rect, center, x, y, spacing);
+            let blocker = textureLoad(t_shadow_atlas, vec2<i32>(uv * size), 0);
+
+            if (blocker < depth) {
+                total = total + blocker;
+                count = count + 1.0;
+            }
+        }
+    }
+
+    if (count == 0.0) {
+        return -1.0;
+    }
+    return total / count;
+}
+
+// Fraction of the kernel around `center` that's lit
+fn shadow_pcf(rect: vec4<f32>, center: vec2<f32>, depth: f32, spacing: f32) -> f32 {
+    let kernel = i32(lights.shadow_kernel);
+
+    var lit = 0.0;
+
+    for (var y = 0; y < kernel; y = y + 1) {
+        for (var x = 0; x < kernel; x = x + 1) {
+            lit = lit + textureSampleCompareLevel(t_shadow_atlas, s_shadow, shadow_sample_uv(rect, center, x, y, spacing), depth);
+        }
+    }
+
+    return lit / f32(kernel * kernel);
+}
+
+// 1.0 where the light reaches the point at `clip`, 0.0 where something is in the way
+fn spot_shadow(light: SpotLight, clip: vec4<f32>) -> f32 {
+    let rect = light.shadow_rect;
+
+    if (rect.z == 0.0) {
+        return 1.0;
+    }
+
+    let ndc    = clip.xyz / clip.w;
+    let uv     = clamp(ndc.xy * vec2<f32>(0.5, -0.5) + 0.5, vec2<f32>(0.0), vec2<f32>(1.0));
+    let center = rect.xy + uv * rect.zw;
+
+    // PCF samples one texel apart
+    var spacing = 1.0;
+
+    if (lights.shadow_pcss != 0u) {
+        let max_spacing = 4.0;
+        let far         = light.position_range.w;
+        let receiver    = linear_shadow_depth(ndc.z, far);
+
+        // Texels per world unit across the tile at the receiver's distance
+        let cos_outer = light.direction_cos_outer.w;
+        let tan_outer = sqrt(1.0 - cos_outer * cos_outer) / cos_outer;
+        let density   = rect.z * f32(textureDimensions(t_shadow_atlas).x) / (2.0 * receiver * tan_outer);
+
+        // Search the area of the shadow map the light's emitter covers as seen from the receiver
+        let kernel_radius = max(f32(lights.shadow_kernel) - 1.0, 1.0) * 0.5;
+        let search        = clamp(lights.shadow_light_size * density / kernel_radius, 1.0, max_spacing);
+        let blocker       = shadow_blocker_depth(rect, center, ndc.z, search);
+
+        if (blocker < 0.0) {
+            return 1.0;
+        }
+
+        // Similar triangles between the emitter, the blocker and the receiver
+        let occluder = linear_shadow_depth(blocker, far);
+        let penumbra = lights.shadow_light_size * (receiver - occluder) / occluder;
+
+        spacing = clamp(penumbra * density / kernel_radius, 0.5, max_spacing);
+    }
+
+    return shadow_pcf(rect, center, ndc.z, spacing);
+}
+
+// 1.0 unless something on screen is in the way within `contact_length` of the point towards the
+// light. Depths are compared as distances from the camera, which `camera.view_proj` doesn't give
+// directly, so they come from two points projected along the view ray.
+fn contact_shadow(world_position: vec3<f32>, light_dir: vec3<f32>) -> f32 {
+    let steps = lights.contact_steps;
+
+    if (steps == 0u) {
+        return 1.0;
+    }
+
+    // Depth is a + b / w for any perspective projection, so two points along the ray fix a and b
+    let clip0 = camera.view_proj * vec4<f32>(world_position, 1.0);
+    let clip1 = camera.view_proj * vec4<f32>(2.0 * world_position - camera.view_position.xyz, 1.0);
+    let b     = (clip0.z / clip0.w - clip1.z / clip1.w) / (1.0 / clip0.w - 1.0 / clip1.w);
+    let a     = clip0.z / clip0.w - b / clip0.w;
+
+    let size = vec2<f32>(textureDimensions(t_scene_depth));
+
+    // Mirrors and other views see a different scene than the copy was taken from, which shows as
+    // the point not being where the copy has it
+    let own_uv    = clip0.xy / clip0.w * vec2<f32>(0.5, -0.5) + 0.5;
+    let own_pixel = vec2<i32>(clamp(own_uv * size, vec2<f32>(0.0), size - 1.0));
+    let own_depth = b / (textureLoad(t_scene_depth, own_pixel, 0) - a);
+
+    if (abs(own_depth - clip0.w) > 0.02 * clip0.w + 0.01) {
+        return 1.0;
+    }
+
+    let step = light_dir * lights.contact_length / f32(steps);
+
+    for (var i = 1u; i <= steps; i = i + 1u) {
+        let clip = camera.view_proj * vec4<f32>(world_position + step * f32(i), 1.0);
+        let uv   = clip.xy / clip.w * vec2<f32>(0.5, -0.5) + 0.5;
+
+        if (clip.w <= 0.0 || any(uv < vec2<f32>(0.0)) || any(uv >= vec2<f32>(1.0))) {
+            break;
+        }
+
+        let scene  = b / (textureLoad(t_scene_depth, vec2<i32>(uv * size), 0) - a);
+        let behind = clip.w - scene;
+
+        // Past a small bias so the surface doesn't shadow itself
+        if (behind > 0.02 * clip.w && behind < lights.contact_thickness) {
+            return 0.0;
+        }
+    }
+
+    return 1.0;
+}
+
+// Light arriving at a point from a spot light before it meets the surface, after the cone, falloff,
+// shadow and cookie
+fn spot_incoming(light: SpotLight, world_position: vec3<f32>) -> vec3<f32> {
+    let to_light    = light.position_range.xyz - world_position;
+    let dist        = length(to_light);
+    let light_range = light.position_range.w;
+
+    if (dist >= light_range) {
+        return vec3<f32>(0.0);
+    }
+
+    let light_dir = to_light / dist;
+
+    // Smooth fade from the inner cone to the outer one
+    let cos_angle = dot(-light_dir, light.direction_cos_outer.xyz);
+    let cone      = smoothstep(light.direction_cos_outer.w, light.color_cos_inner.w, cos_angle);
+
+    // Inverse square, windowed so it reaches zero at the range instead of trailing off forever
+    let window      = clamp(1.0 - pow(dist / light_range, 4.0), 0.0, 1.0);
+    let attenuation = window * window / (dist * dist + 1.0);
+
+    let clip = light.view_proj * vec4<f32>(world_position, 1.0);
+
+    var shadow = spot_shadow(light, clip);
+
+    // Only for lights with a shadow map, since others are expected to shine through things
+    if (light.shadow_rect.z > 0.0) {
+        shadow = min(shadow, contact_shadow(world_position, light_dir));
+    }
+
+    var color = light.color_cos_inner.rgb * shadow;
+
+    if (light.cookie >= 0) {
+        let uv = clip.xy / clip.w * vec2<f32>(0.5, -0.5) + 0.5;
+
+        // Explicit level since the branch isn't uniform across fragments
+        color = color * textureSampleLevel(t_cookies, s_cookies, uv, light.cookie, 0.0).rgb;
+    }
+
+    return color * cone * attenuation;
+}
+
+fn spot_light(light: SpotLight, world_position: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    let light_dir = normalize(light.position_range.xyz - world_position);
+
+    return spot_incoming(light, world_position) * max(dot(normal, light_dir), 0.0);
+}
+
+// The integral of a cosine over the arc between two directions, as a vector normal to it
+fn ltc_edge(v1: vec3<f32>, v2: vec3<f32>) -> vec3<f32> {
+    let x = dot(v1, v2);
+    let y = abs(x);
+
+    // Fitted theta / sin(theta), from the LTC paper
+    let a = 0.8543985 + (0.4965155 + 0.0145206 * y) * y;
+    let b = 3.4175940 + (4.1616724 + y) * y;
+    let v = a / b;
+
+    let theta_sin_theta = select(0.5 * inverseSqrt(max(1.0 - x * x, 0.0000001)) - v, v, x > 0.0);
+
+    return cross(v1, v2) * theta_sin_theta;
+}
+
+// How much of a cosine lobe around `normal`, transformed by `inverse` in the frame with the view in
+// the xz plane, `light` covers as seen from `world_position`. 1.0 when it fills the hemisphere.
+fn ltc_evaluate(light: RectLight, world_position: vec3<f32>, normal: vec3<f32>, view_dir: vec3<f32>, inverse: mat3x3<f32>) -> f32 {
+    let center = light.center_two_sided.xyz;
+    let facing = cross(light.right.xyz, light.up.xyz);
+
+    if (light.center_two_sided.w == 0.0 && dot(world_position - center, facing) <= 0.0) {
+        return 0.0;
+    }
+
+    // Head on, any tangent will do
+    let along   = view_dir - normal * dot(view_dir, normal);
+    let helper  = select(vec3<f32>(0.0, 1.0, 0.0), vec3<f32>(1.0, 0.0, 0.0), abs(normal.y) > 0.99);
+    let tangent = select(normalize(along), normalize(cross(normal, helper)), dot(along, along) < 0.00000001);
+    let m       = inverse * transpose(mat3x3<f32>(tangent, cross(normal, tangent), normal));
+
+    let p0 = normalize(m * (center - light.right.xyz - light.up.xyz - world_position));
+    let p1 = normalize(m * (center + light.right.xyz - light.up.xyz - world_position));
+    let p2 = normalize(m * (center + light.right.xyz + light.up.xyz - world_position));
+    let p3 = normalize(m * (center - light.right.xyz + light.up.xyz - world_position));
+
+    let sum = (ltc_edge(p0, p1) + ltc_edge(p1, p2) + ltc_edge(p2, p3) + ltc_edge(p3, p0)) / 6.2831853;
+    let len = length(sum);
+
+    if (len <= 0.0) {
+        return 0.0;
+    }
+
+    // Towards the light whichever way round the corners go. The share above the horizon is that
+    // of a sphere covering as much in the same direction, approximated as Blender's Eevee does.
+    let toward = select(-1.0, 1.0, dot(sum, m * (center - world_position)) > 0.0);
+    let z      = sum.z / len * toward;
+
+    return len * max((len * len + z) / (len + 1.0), 0.0);
+}
+
+// Diffuse light reaching a surface from every rect light
+fn rect_lighting(world_position: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    var total = vec3<f32>(0.0);
+
+    // The cosine itself, so any view will do
+    let view_dir = normal;
+    let identity = mat3x3<f32>(vec3<f32>(1.0, 0.0, 0.0), vec3<f32>(0.0, 1.0, 0.0), vec3<f32>(0.0, 0.0, 1.0));
+
+    for (var i = 0u; i < lights.rect_count; i = i + 1u) {
+        let light = lights.rects[i];
+
+        total = total + light.color.rgb * ltc_evaluate(light, world_position, normal, view_dir, identity);
+    }
+
+    return total;
+}
+
+// GGX highlights of `roughness` from every rect light, reflecting 4% head on like `fresnel`
+fn rect_specular(world_position: vec3<f32>, normal: vec3<f32>, view_dir: vec3<f32>, roughness: f32) -> vec3<f32> {
+    // LUT_SIZE in ltc.rs, mapping the table's first and last entries to its edges
+    let size = 64.0;
+    let uv   = vec2<f32>(clamp(roughness, 0.0, 1.0), sqrt(1.0 - clamp(dot(normal, view_dir), 0.0, 1.0))) * (size - 1.0) / size + 0.5 / size;
+
+    // Explicit level since the branch isn't uniform across fragments
+    let t1 = textureSampleLevel(t_ltc, s_cookies, uv, 0, 0.0);
+    let t2 = textureSampleLevel(t_ltc, s_cookies, uv, 1, 0.0);
+
+    let inverse    = mat3x3<f32>(vec3<f32>(t1.x, 0.0, t1.y), vec3<f32>(0.0, 1.0, 0.0), vec3<f32>(t1.z, 0.0, t1.w));
+    let brightness = 0.04 * t2.x + 0.96 * t2.y;
+
+    var total = vec3<f32>(0.0);
+
+    for (var i = 0u; i < lights.rect_count; i = i + 1u) {
+        let light = lights.rects[i];
+
+        total = total + light.color.rgb * ltc_evaluate(light, world_position, normal, view_dir, inverse);
+    }
+
+    return total * brightness;
+}
+
+// Diffuse light reaching a surface from every spot and rect light
+fn spot_lighting(world_position: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    var total = rect_lighting(world_position, normal);
+
+    for (var i = 0u; i < lights.spot_count; i = i + 1u) {
+        total = total + spot_light(lights.spots[i], world_position, normal);
+    }
+
+    return total;
+}
+
+
+// Reflectance of a dielectric, like the base layer and the clear coat, both at 4%
+fn fresnel(cos_theta: f32) -> f32 {
+    return 0.04 + 0.96 * pow(1.0 - clamp(cos_theta, 0.0, 1.0), 5.0);
+}
+
+// How wet a surface facing `normal` is. Faces turned up to the sky catch the most rain.
+fn surface_wetness(normal: vec3<f32>) -> f32 {
+    return lights.wetness * mix(0.3, 1.0, clamp(normal.y, 0.0, 1.0));
+}
+
+// Water soaking into a surface darkens it
+fn wet_albedo(albedo: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    return albedo * mix(1.0, 0.5, surface_wetness(normal));
+}
+
+// ...and a film of it over the top smooths out its highlight
+fn wet_roughness(roughness: f32, normal: vec3<f32>) -> f32 {
+    return mix(roughness, 0.1, surface_wetness(normal));
+}
+
+// Tangent and bitangent along the texture's u and v, worked out from how position and UV change
+// across the pixel since meshes don't carry tangents
+fn tangent_frame(normal: vec3<f32>, world_position: vec3<f32>, tex_coords: vec2<f32>) -> mat3x3<f32> {
+    let dp1  = dpdx(world_position);
+    let dp2  = dpdy(world_position);
+    let duv1 = dpdx(tex_coords);
+    let duv2 = dpdy(tex_coords);
+
+    let dp2_perp = cross(dp2, normal);
+    let dp1_perp = cross(normal, dp1);
+    let tangent  = dp2_perp * duv1.x + dp1_perp * duv2.x;
+    let binormal = dp2_perp * duv1.y + dp1_perp * duv2.y;
+    let scale    = inverseSqrt(max(max(dot(tangent, tangent), dot(binormal, binormal)), 0.000001));
+
+    return mat3x3<f32>(tangent * scale, binormal * scale, normal);
+}
+
+// GGX highlight stretched along `tangent`, as KHR_materials_anisotropy defines it, times n.l.
+// With no anisotropy it's the ordinary isotropic GGX.
+fn anisotropic_specular(
+    frame:      mat3x3<f32>,
+    light_dir:  vec3<f32>,
+    view_dir:   vec3<f32>,
+    roughness:  f32,
+    anisotropy: f32,
+) -> f32 {
+    let tangent  = frame[0];
+    let binormal = frame[1];
+    let normal   = frame[2];
+    let half_dir = normalize(light_dir + view_dir);
+
+    let n_dot_l = max(dot(normal, light_dir), 0.0);
+    let n_dot_v = max(dot(normal, view_dir), 0.0001);
+    let n_dot_h = max(dot(normal, half_dir), 0.0);
+
+    let alpha   = max(roughness * roughness, 0.002);
+    let alpha_t = mix(alpha, 1.0, anisotropy * anisotropy);
+    let alpha_b = alpha;
+
+    let t_dot_h = dot(tangent, half_dir);
+    let b_dot_h = dot(binormal, half_dir);
+    let d_term  = t_dot_h * t_dot_h / (alpha_t * alpha_t) + b_dot_h * b_dot_h / (alpha_b * alpha_b) + n_dot_h * n_dot_h;
+    let d       = 1.0 / (3.14159265 * alpha_t * alpha_b * d_term * d_term);
+
+    let lambda_v = n_dot_l * length(vec3<f32>(alpha_t * dot(tangent, view_dir), alpha_b * dot(binormal, view_dir), n_dot_v));
+    let lambda_l = n_dot_v * length(vec3<f32>(alpha_t * dot(tangent, light_dir), alpha_b * dot(binormal, light_dir), n_dot_l));
+    let v        = 0.5 / max(lambda_v + lambda_l, 0.0001);
+
+    return fresnel(dot(view_dir, half_dir)) * d * v * n_dot_l;
+}
+
+
+// Diffuse light reaching a surface facing `normal` from spherical harmonics `sh`
+fn sh_irradiance(sh: array<vec4<f32>, 9>, normal: vec3<f32>) -> vec3<f32> {
+    let n = normal;
+
+    // Normalization of each basis function, as in ambient.rs
+    let light = sh[0].rgb * 0.282095
+        + (sh[1].rgb * n.y + sh[2].rgb * n.z + sh[3].rgb * n.x) * 0.488603
+        + (sh[4].rgb * n.x * n.y + sh[5].rgb * n.y * n.z + sh[7].rgb * n.x * n.z) * 1.092548
+        + sh[6].rgb * (3.0 * n.z * n.z - 1.0) * 0.315392
+        + sh[8].rgb * (n.x * n.x - n.y * n.y) * 0.546274;
+
+    return max(light, vec3<f32>(0.0));
+}
+
+// Diffuse light from the environment reaching a surface facing `normal`
+fn ambient_lighting(normal: vec3<f32>) -> vec3<f32> {
+    return sh_irradiance(lights.ambient, normal);
+}
+
+// Like `ambient_lighting`, but blended from the eight probes around `position` when there's a
+// probe grid, as `ProbeGrid::sample` does. Outside the grid the nearest probes on its edge are used.
+fn probe_lighting(position: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    if (lights.probe_count == 0u) {
+        return ambient_lighting(normal);
+    }
+
+    let counts = lights.probe_counts;
+    let cell   = clamp((position - lights.probe_origin) / lights.probe_spacing, vec3<f32>(0.0), vec3<f32>(counts - 1u));
+    let base   = min(vec3<u32>(floor(cell)), max(counts, vec3<u32>(2u)) - 2u);
+    let t      = cell - vec3<f32>(base);
+
+    var light = vec3<f32>(0.0);
+
+    for (var corner = 0u; corner < 8u; corner = corner + 1u) {
+        let upper  = vec3<bool>((corner & 1u) != 0u, (corner & 2u) != 0u, (corner & 4u) != 0u);
+        let axes   = select(1.0 - t, t, upper);
+        let weight = axes.x * axes.y * axes.z;
+
+        // Including every corner past an axis with a single probe, which isn't there to read
+        if (weight > 0.0) {
+            let probe = base + select(vec3<u32>(0u), vec3<u32>(1u), upper);
+            let index = probe.x + counts.x * (probe.y + counts.y * probe.z);
+
+            light = light + sh_irradiance(lights.probes[index].irradiance, normal) * weight;
+        }
+    }
+
+    return light;
+}
+
+// What `lights.debug_view` shows in place of the lit color: 1 for normals, 2 for albedo, 3 for
+// roughness in red and specular in green, and 4 for how much contact shadows leave of every spot
+// light
+fn debug_surface(world_position: vec3<f32>, normal: vec3<f32>, albedo: vec3<f32>, roughness: f32, specular: f32) -> vec3<f32> {
+    switch (lights.debug_view) {
+        case 1u: {
+            return normal * 0.5 + 0.5;
+        }
+        case 2u: {
+            return albedo;
+        }
+        case 3u: {
+            return vec3<f32>(roughness, specular, 0.0);
+        }
+        default: {
+            var lit = 1.0;
+
+            for (var i = 0u; i < lights.spot_count; i = i + 1u) {
+                lit = lit * contact_shadow(world_position, normalize(lights.spots[i].position_range.xyz - world_position));
+            }
+
+            return vec3<f32>(lit);
+        }
+    }
+}
@@ -0,0 +1,368 @@
+// Knobs and faders on a MIDI controller, or OSC messages from something like TouchOSC or another
+// program, driving named parameters while the renderer runs. A mapping file names each parameter,
+// where it's controlled from and the range it covers, and each `update` eases the parameters
+// toward what the controls last sent so they glide rather than step.
+//
+// Shaders read the parameters from a uniform through helpers `shader_header` declares, one
+// `param_<name>()` a parameter, and scene code reads them with `value`. Native only, behind the
+// `live_control` feature.
+
+use std::{
+    net::UdpSocket,
+    sync::mpsc,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::{
+    bind_group,
+    renderer::GpuContext,
+    uniform::{Uniform, UniformBuffer},
+};
+
+// Parameters the uniform has room for
+pub const MAX_PARAMS: usize = 64;
+
+// Where a parameter's control messages come from
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControlSource {
+    // A MIDI control change. `channel` is from 1 to 16, or None for any.
+    MidiCc { channel: Option<u8>, controller: u8 },
+    // An OSC message whose first argument is the value, from 0.0 to 1.0
+    Osc { address: String },
+}
+
+#[derive(Debug, Clone)]
+pub struct ControlParam {
+    // Also its shader helper's name, so a WGSL identifier
+    pub name:      String,
+    pub source:    ControlSource,
+    // What the control's lowest and highest settings map to
+    pub min:       f32,
+    pub max:       f32,
+    // Seconds the value takes to get most of the way to a new setting, 0.0 to jump straight there
+    pub smoothing: f32,
+}
+
+// The parsed mapping file. It's made of lines like these, with # starting a comment:
+//
+//   osc_port    9000
+//   midi_device nanoKONTROL
+//   param exposure  midi 1 21    0.0 4.0 0.1
+//   param hue       midi * 22    0.0 1.0 0.0
+//   param zoom      osc /vj/zoom 0.5 8.0 0.25
+//
+// `osc_port` is the UDP port OSC is listened for on, which OSC parameters need, and `midi_device`
+// part of the name of the MIDI input to use, the first one otherwise. A parameter gives its name,
+// its source as `midi <channel> <cc>`, with `*` for any channel, or `osc <address>`, then its
+// minimum, maximum and smoothing. Each kind of input is only opened if a parameter uses it.
+#[derive(Debug, Clone, Default)]
+pub struct ControlMapping {
+    pub osc_port:    Option<u16>,
+    pub midi_device: Option<String>,
+    pub params:      Vec<ControlParam>,
+}
+
+impl ControlMapping {
+    pub fn from_str(text: &str) -> Result<Self> {
+        let mut mapping = Self::default();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            mapping.parse_line(line).with_context(|| format!("Line {} of the control mapping", number + 1))?;
+        }
+
+        if mapping.params.len() > MAX_PARAMS {
+            bail!("The control mapping has {} parameters, at most {} are supported", mapping.params.len(), MAX_PARAMS);
+        }
+
+        Ok(mapping)
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<()> {
+        let mut words = line.split_whitespace();
+        let mut next  = |what: &str| words.next().ok_or_else(|| anyhow!("Missing {} in `{}`", what, line));
+
+        match next("keyword")? {
+            "osc_port"    => self.osc_port = Some(next("port")?.parse()?),
+            "midi_device" => self.midi_device = Some(line["midi_device".len()..].trim().to_string()),
+            "param"       => {
+                let name = next("name")?.to_string();
+
+                if !name.starts_with(|c: char| c.is_ascii_alphabetic()) || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                    bail!("`{}` isn't a valid parameter name, use letters, digits and underscores", name);
+                }
+                if self.params.iter().any(|p| p.name == name) {
+                    bail!("`{}` is mapped twice", name);
+                }
+
+                let source = match next("source")? {
+                    "midi" => {
+                        let channel = match next("MIDI channel")? {
+                            "*"     => None,
+                            channel => Some(channel.parse::<u8>().ok().filter(|c| (1..=16).contains(c))
+                                .ok_or_else(|| anyhow!("MIDI channels are from 1 to 16, or * for any"))?),
+                        };
+                        let controller = next("MIDI controller")?.parse::<u8>().ok().filter(|c| *c < 128)
+                            .ok_or_else(|| anyhow!("MIDI controllers are from 0 to 127"))?;
+
+                        ControlSource::MidiCc { channel, controller }
+                    }
+                    "osc"  => ControlSource::Osc { address: next("OSC address")?.to_string() },
+                    other  => bail!("Unknown source `{}`, expected `midi` or `osc`", other),
+                };
+
+                let min       = next("minimum")?.parse()?;
+                let max       = next("maximum")?.parse()?;
+                let smoothing = next("smoothing")?.parse::<f32>()?.max(0.0);
+
+                self.params.push(ControlParam { name, source, min, max, smoothing });
+            }
+            other => bail!("Unknown keyword `{}`", other),
+        }
+
+        Ok(())
+    }
+
+    pub fn uses_midi(&self) -> bool {
+        self.params.iter().any(|p| matches!(p.source, ControlSource::MidiCc { .. }))
+    }
+
+    pub fn uses_osc(&self) -> bool {
+        self.params.iter().any(|p| matches!(p.source, ControlSource::Osc { .. }))
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct ControlUniform {
+    values: [[f32; 4]; MAX_PARAMS / 4],
+}
+
+// A control message, with the value from 0.0 to 1.0
+enum ControlEvent {
+    Midi { channel: u8, controller: u8, value: f32 },
+    Osc { address: String, value: f32 },
+}
+
+pub struct LiveControl {
+    pub mapping:    ControlMapping,
+    pub layout:     wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+    // Where each parameter is and where it's heading
+    values:         Vec<f32>,
+    targets:        Vec<f32>,
+    events:         mpsc::Receiver<ControlEvent>,
+    uniform:        UniformBuffer<ControlUniform>,
+    // Dropping it closes the MIDI port
+    _midi:          Option<midir::MidiInputConnection<()>>,
+}
+
+impl LiveControl {
+    // Opens the inputs `mapping` needs. Parameters start at their minimum.
+    pub fn new(ctx: &GpuContext, mapping: ControlMapping) -> Result<Self> {
+        let (sender, events) = mpsc::channel();
+
+        let midi = if mapping.uses_midi() {
+            Some(connect_midi(mapping.midi_device.as_deref(), sender.clone())?)
+        } else {
+            None
+        };
+
+        if mapping.uses_osc() {
+            let port = mapping.osc_port.ok_or_else(|| anyhow!("The control mapping has OSC parameters but no osc_port"))?;
+
+            listen_osc(port, sender)?;
+        }
+
+        let device  = &ctx.device;
+        let uniform = UniformBuffer::new(device, "Live Control Uniform Buffer");
+        let stages  = wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE;
+
+        let layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(stages)
+            .build(device, "live_control_bind_group_layout");
+
+        let bind_group = bind_group::BindGroupBuilder::new(&layout)
+            .uniform(uniform.buffer())
+            .build(device, "live_control_bind_group");
+
+        let values = mapping.params.iter().map(|p| p.min).collect::<Vec<_>>();
+
+        Ok(Self {
+            targets: values.clone(),
+            values,
+            mapping,
+            layout,
+            bind_group,
+            events,
+            uniform,
+            _midi: midi,
+        })
+    }
+
+    // The parameter's current value, or None if it isn't in the mapping
+    pub fn value(&self, name: &str) -> Option<f32> {
+        self.index(name).map(|i| self.values[i])
+    }
+
+    // Moves a parameter as if its control had, for keyboard fallbacks and presets. `value` is in
+    // the parameter's own range.
+    pub fn set(&mut self, name: &str, value: f32) {
+        if let Some(i) = self.index(name) {
+            self.targets[i] = value;
+        }
+    }
+
+    // Takes in the messages that have arrived, eases the parameters along and uploads them
+    pub fn update(&mut self, queue: &wgpu::Queue, dt: Duration) {
+        for event in self.events.try_iter() {
+            for (param, target) in self.mapping.params.iter().zip(&mut self.targets) {
+                let value = match (&param.source, &event) {
+                    (ControlSource::MidiCc { channel, controller }, ControlEvent::Midi { channel: c, controller: cc, value })
+                        if cc == controller && channel.map_or(true, |channel| channel == *c) => *value,
+                    (ControlSource::Osc { address }, ControlEvent::Osc { address: a, value })
+                        if a == address => *value,
+                    _ => continue,
+                };
+
+                *target = param.min + (param.max - param.min) * value;
+            }
+
+            match &event {
+                ControlEvent::Midi { channel, controller, value } => log::debug!("MIDI channel {} CC {} = {:.3}", channel, controller, value),
+                ControlEvent::Osc { address, value }              => log::debug!("OSC {} = {:.3}", address, value),
+            }
+        }
+
+        let dt      = dt.as_secs_f32();
+        let mut out = ControlUniform { values: [[0.0; 4]; MAX_PARAMS / 4] };
+
+        for (i, param) in self.mapping.params.iter().enumerate() {
+            let ease = if param.smoothing > 0.0 { 1.0 - (-dt / param.smoothing).exp() } else { 1.0 };
+
+            self.values[i] += (self.targets[i] - self.values[i]) * ease;
+            out.values[i / 4][i % 4] = self.values[i];
+        }
+
+        self.uniform.write(queue, &out);
+    }
+
+    // Declares the parameters' uniform at `group` and a `param_<name>()` for each, to go before the
+    // source of a shader that uses `bind_group` there
+    pub fn shader_header(&self, group: u32) -> String {
+        let mut header = format!(
+            "struct LiveControl {{\n    values: array<vec4<f32>, {}>,\n}}\n\n@group({}) @binding(0)\nvar<uniform> live_control: LiveControl;\n\n",
+            MAX_PARAMS / 4,
+            group,
+        );
+
+        for (i, param) in self.mapping.params.iter().enumerate() {
+            header += &format!("fn param_{}() -> f32 {{\n    return live_control.values[{}][{}];\n}}\n\n", param.name, i / 4, i % 4);
+        }
+
+        header
+    }
+
+    fn index(&self, name: &str) -> Option<usize> {
+        self.mapping.params.iter().position(|p| p.name == name)
+    }
+}
+
+// Passes on control changes from the first MIDI input whose name contains `device`, or the first
+// there is
+fn connect_midi(device: Option<&str>, sender: mpsc::Sender<ControlEvent>) -> Result<midir::MidiInputConnection<()>> {
+    let input = midir::MidiInput::new("learn_wgpu")?;
+    let ports = input.ports();
+
+    let port = ports.iter()
+        .find(|port| device.map_or(true, |device| input.port_name(port).map_or(false, |name| name.contains(device))))
+        .ok_or_else(|| match device {
+            Some(device) => anyhow!("There's no MIDI input named like `{}`", device),
+            None         => anyhow!("There's no MIDI input"),
+        })?;
+
+    let name = input.port_name(port).unwrap_or_default();
+
+    log::info!("Taking control changes from MIDI input {}", name);
+
+    input.connect(
+        port,
+        "learn_wgpu-control",
+        move |_, message, _| {
+            // Control change is status 0xBn for channel n + 1, then the controller and value
+            if let [status, controller, value] = *message {
+                if status & 0xf0 == 0xb0 {
+                    let _ = sender.send(ControlEvent::Midi {
+                        channel: (status & 0x0f) + 1,
+                        controller,
+                        value:   value as f32 / 127.0,
+                    });
+                }
+            }
+        },
+        (),
+    )
+    .map_err(|e| anyhow!("Couldn't connect to MIDI input {}: {}", name, e.kind()))
+}
+
+// Passes on OSC messages arriving on `port` from a thread of its own, which stops once the
+// receiving end is gone
+fn listen_osc(port: u16, sender: mpsc::Sender<ControlEvent>) -> Result<()> {
+    let socket = UdpSocket::bind(("0.0.0.0", port)).with_context(|| format!("Couldn't listen for OSC on port {}", port))?;
+
+    log::info!("Listening for OSC on port {}", port);
+
+    std::thread::Builder::new()
+        .name("osc-listener".to_string())
+        .spawn(move || {
+            let mut buffer = [0; rosc::decoder::MTU];
+
+            loop {
+                let size = match socket.recv_from(&mut buffer) {
+                    Ok((size, _)) => size,
+                    Err(e) => {
+                        log::warn!("OSC listener stopped: {}", e);
+                        return;
+                    }
+                };
+
+                let mut messages = Vec::new();
+
+                match rosc::decoder::decode_udp(&buffer[..size]) {
+                    Ok((_, packet)) => flatten_osc(packet, &mut messages),
+                    Err(e) => log::warn!("Couldn't decode an OSC packet: {:?}", e),
+                }
+
+                for message in messages {
+                    let value = match message.args.first() {
+                        Some(rosc::OscType::Float(v))  => *v,
+                        Some(rosc::OscType::Double(v)) => *v as f32,
+                        Some(rosc::OscType::Int(v))    => *v as f32,
+                        Some(rosc::OscType::Bool(v))   => if *v { 1.0 } else { 0.0 },
+                        _ => continue,
+                    };
+
+                    if sender.send(ControlEvent::Osc { address: message.addr, value }).is_err() {
+                        return;
+                    }
+                }
+            }
+        })?;
+
+    Ok(())
+}
+
+// The messages in a packet, unpacking bundles
+fn flatten_osc(packet: rosc::OscPacket, messages: &mut Vec<rosc::OscMessage>) {
+    match packet {
+        rosc::OscPacket::Message(message) => messages.push(message),
+        rosc::OscPacket::Bundle(bundle)   => bundle.content.into_iter().for_each(|p| flatten_osc(p, messages)),
+    }
+}
@@ -0,0 +1,204 @@
+// Level of detail: coarser versions of a model's meshes, picked per instance by how large the
+// instance is on screen. Levels are either authored, loaded like any other model, or simplified
+// from the full mesh with `resources::load_lod_levels`.
+//
+// Changing level can dither between the two levels over a short time instead of popping. The
+// fade rides along in the instance matrix, see `set_fade`.
+
+use std::ops::Range;
+
+use cgmath::prelude::*;
+
+use crate::{
+    bounds::Aabb,
+    camera::Camera,
+    model::{Instance, InstanceRaw, Mesh, Model},
+};
+
+pub struct LodLevel {
+    pub meshes:      Vec<Mesh>,
+    // Used once an instance's height on screen, as a fraction of the viewport's, drops below this
+    pub screen_size: f32,
+}
+
+pub struct LodChain {
+    // From finest to coarsest, after the model's own meshes which are level 0
+    pub levels:     Vec<LodLevel>,
+    // How far past a threshold, as a fraction of it, the screen size has to go before the level
+    // changes, so instances sitting on a threshold don't flicker between levels
+    pub hysteresis: f32,
+    // Seconds spent dithering from one level to the next, or 0.0 to switch at once
+    pub fade_time:  f32,
+}
+
+impl Default for LodChain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LodChain {
+    pub fn new() -> Self {
+        Self {
+            levels:     Vec::new(),
+            hysteresis: 0.1,
+            fade_time:  0.5,
+        }
+    }
+
+    pub fn level_count(&self) -> usize {
+        self.levels.len() + 1
+    }
+
+    pub fn meshes<'a>(&'a self, model: &'a Model, level: usize) -> &'a [Mesh] {
+        match level {
+            0 => &model.meshes,
+            _ => &self.levels[level - 1].meshes,
+        }
+    }
+
+    // The level for an instance `size` high on screen that's currently at `current`
+    fn select(&self, current: usize, size: f32) -> usize {
+        let mut level = current.min(self.levels.len());
+
+        while level < self.levels.len() && size < self.levels[level].screen_size * (1.0 - self.hysteresis) {
+            level += 1;
+        }
+
+        while level > 0 && size > self.levels[level - 1].screen_size * (1.0 + self.hysteresis) {
+            level -= 1;
+        }
+
+        level
+    }
+}
+
+// Height of `bounds` on screen as a fraction of the viewport's, from its bounding sphere
+pub fn screen_size(camera: &Camera, bounds: &Aabb) -> f32 {
+    let sphere   = bounds.bounding_sphere();
+    let distance = camera.eye.distance(sphere.center);
+
+    if distance <= sphere.radius {
+        return f32::INFINITY;
+    }
+
+    sphere.radius / (distance * (camera.fovy.to_radians() / 2.0).tan())
+}
+
+// The fade is stored in the w of the matrix's first column, which is always 0.0 for the affine
+// transforms instances use, and cleared by the vertex shader. 0.0 draws the instance as usual.
+// A positive fade is the fraction of pixels drawn by an instance fading in, a negative one the
+// fraction already handed over by one fading out, so the two dither patterns never overlap.
+pub fn set_fade(raw: &mut InstanceRaw, fade: f32) {
+    raw.model[0][3] = fade;
+}
+
+#[derive(Debug, Copy, Clone)]
+struct LodState {
+    level:    usize,
+    previous: usize,
+    // 1.0 once the change to `level` has finished
+    progress: f32,
+}
+
+// A level's instances within `LodSelector::instances`. Stable ones come first so passes that
+// can't dither, like a depth prepass, can draw just those.
+#[derive(Debug, Clone)]
+pub struct LodBatch {
+    pub level:  usize,
+    pub stable: Range<u32>,
+    pub fading: Range<u32>,
+}
+
+// Remembers each instance's level from frame to frame, which hysteresis and fading both need
+#[derive(Default)]
+pub struct LodSelector {
+    states:    Vec<LodState>,
+    instances: Vec<InstanceRaw>,
+    batches:   Vec<LodBatch>,
+}
+
+impl LodSelector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Picks levels for the `visible` instances and lays them out by level. `bounds` is the
+    // model's, in model space. Instances that were visible before keep fading while hidden.
+    pub fn update(
+        &mut self,
+        chain:     &LodChain,
+        camera:    &Camera,
+        bounds:    &Aabb,
+        instances: &[Instance],
+        visible:   &[usize],
+        dt:        f32,
+    ) {
+        self.states.resize(instances.len(), LodState { level: 0, previous: 0, progress: 1.0 });
+
+        let step = if chain.fade_time > 0.0 { dt / chain.fade_time } else { 1.0 };
+
+        for state in &mut self.states {
+            state.progress = (state.progress + step).min(1.0);
+        }
+
+        let mut stable = vec![Vec::new(); chain.level_count()];
+        let mut fading = vec![Vec::new(); chain.level_count()];
+
+        for &i in visible {
+            let matrix = instances[i].to_matrix();
+            let size   = screen_size(camera, &bounds.transform(&matrix));
+            let state  = &mut self.states[i];
+            let level  = chain.select(state.level, size);
+
+            // Changing again mid-fade starts over from whichever level was showing more
+            if level != state.level {
+                state.previous = if state.progress >= 0.5 { state.level } else { state.previous };
+                state.level    = level;
+                state.progress = if chain.fade_time > 0.0 { 0.0 } else { 1.0 };
+            }
+
+            let raw = InstanceRaw { model: matrix.into() };
+
+            if state.progress >= 1.0 || state.previous == state.level {
+                stable[state.level].push(raw);
+            } else {
+                // Never exactly 0.0, which would draw the incoming level in full
+                let progress = state.progress.max(0.001);
+
+                let mut incoming = raw;
+                let mut outgoing = raw;
+
+                set_fade(&mut incoming, progress);
+                set_fade(&mut outgoing, -progress);
+
+                fading[state.level].push(incoming);
+                fading[state.previous].push(outgoing);
+            }
+        }
+
+        self.instances.clear();
+        self.batches.clear();
+
+        for (level, (stable, fading)) in stable.into_iter().zip(fading).enumerate() {
+            let start = self.instances.len() as u32;
+            self.instances.extend(stable);
+            let middle = self.instances.len() as u32;
+            self.instances.extend(fading);
+            let end = self.instances.len() as u32;
+
+            if end > start {
+                self.batches.push(LodBatch { level, stable: start..middle, fading: middle..end });
+            }
+        }
+    }
+
+    // Ready to upload, in the order `batches` refers to
+    pub fn instances(&self) -> &[InstanceRaw] {
+        &self.instances
+    }
+
+    pub fn batches(&self) -> &[LodBatch] {
+        &self.batches
+    }
+}
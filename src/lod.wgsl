@@ -0,0 +1,23 @@
+// Dithered cross-fades between levels of detail, see lod.rs. Prepended to each shading model's
+// shader after `lights.wgsl`.
+
+// 4x4 Bayer thresholds, spread evenly over (0, 1)
+fn dither_threshold(pixel: vec2<f32>) -> f32 {
+    var bayer = array<f32, 16>(
+         0.0,  8.0,  2.0, 10.0,
+        12.0,  4.0, 14.0,  6.0,
+         3.0, 11.0,  1.0,  9.0,
+        15.0,  7.0, 13.0,  5.0,
+    );
+    let p = vec2<i32>(pixel) % vec2<i32>(4);
+
+    return (bayer[p.y * 4 + p.x] + 0.5) / 16.0;
+}
+
+// Whether a pixel of an instance with `fade` is left to the other level. A fading in instance
+// draws where the threshold is under its fade, a fading out one everywhere else.
+fn lod_dithered_out(pixel: vec2<f32>, fade: f32) -> bool {
+    let threshold = dither_threshold(pixel);
+
+    return (fade > 0.0 && threshold >= fade) || (fade < 0.0 && threshold < -fade);
+}
@@ -0,0 +1,62 @@
+// Tables for lighting from area lights with linearly transformed cosines (Heitz et al. 2016). The
+// highlight of a surface is approximated by a cosine lobe squeezed and turned by a 3x3 matrix,
+// which turns integrating it over a polygon into integrating a plain cosine, and that has a closed
+// form. The matrix and the highlight's overall brightness depend on roughness and the view angle,
+// so they're looked up from two tables indexed by roughness across and sqrt(1 - n.v) down.
+//
+// The tables here are worked out rather than fitted: the lobe is narrowed by the GGX alpha around
+// the dominant reflection direction, as Frostbite approximates it, and the brightness is Karis'
+// analytic fit of GGX's reflectance. Fitted tables from the paper's code can be uploaded in their
+// place with `Lights::set_ltc_tables`.
+
+// Entries along each side of both tables. Also in lights.wgsl.
+pub const LUT_SIZE: u32 = 64;
+
+// The inverse of each entry's matrix, whose only entries besides the 1.0 in the middle are
+// `[m00, m20, m02, m22]`, and each entry's brightness as `[a, b, 0, 0]`, which is
+// `f0 * a + (1 - f0) * b` for a surface reflecting `f0` head on. The same layout as the paper's
+// tables.
+pub fn approximate_tables() -> (Vec<[f32; 4]>, Vec<[f32; 4]>) {
+    let last = (LUT_SIZE - 1) as f32;
+
+    (0..LUT_SIZE * LUT_SIZE)
+        .map(|i| {
+            let roughness = (i % LUT_SIZE) as f32 / last;
+            let v         = (i / LUT_SIZE) as f32 / last;
+            let cos_theta = (1.0 - v * v).clamp(0.0, 1.0);
+            let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+
+            (inverse_matrix(roughness, cos_theta, sin_theta), magnitude(roughness, cos_theta))
+        })
+        .unzip()
+}
+
+// In the frame with the normal along z and the view in the xz plane. The cosine lobe is narrowed
+// by alpha and then turned from the normal towards the mirror direction, which rough surfaces
+// don't reach. Scaled so the middle entry is 1.0, since the lighting doesn't change with scale.
+fn inverse_matrix(roughness: f32, cos_theta: f32, sin_theta: f32) -> [f32; 4] {
+    let alpha = (roughness * roughness).max(0.002);
+
+    // Between the normal and the mirror of the view, which is (-sin, 0, cos)
+    let share = (1.0 - alpha) * ((1.0 - alpha).sqrt() + alpha);
+    let x     = -sin_theta * share;
+    let z     = 1.0 - share + cos_theta * share;
+    let len   = (x * x + z * z).sqrt();
+    let (sin_beta, cos_beta) = (x / len, z / len);
+
+    [cos_beta, alpha * sin_beta, -sin_beta, alpha * cos_beta]
+}
+
+// The GGX highlight's reflectance over the whole hemisphere, split into what scales with the
+// reflectance facing the surface and what's left at grazing angles
+fn magnitude(roughness: f32, cos_theta: f32) -> [f32; 4] {
+    let c0 = [-1.0, -0.0275, -0.572, 0.022];
+    let c1 = [1.0, 0.0425, 1.04, -0.04];
+    let r  = [0, 1, 2, 3].map(|i| roughness * c0[i] + c1[i]);
+
+    let a004  = (r[0] * r[0]).min((-9.28 * cos_theta).exp2()) * r[0] + r[1];
+    let scale = -1.04 * a004 + r[2];
+    let bias  = 1.04 * a004 + r[3];
+
+    [scale + bias, bias, 0.0, 0.0]
+}
@@ -0,0 +1,59 @@
+// Reduces the luminance histogram to the average luminance of the non-black pixels and clears the
+// bins for the next frame
+
+struct HistogramParams {
+    min_log_luminance: f32,
+    log_range:         f32,
+}
+
+struct Average {
+    luminance: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: HistogramParams;
+@group(0) @binding(1)
+var<storage, read_write> bins: array<u32, 256>;
+@group(0) @binding(2)
+var<storage, read_write> average: Average;
+
+// Pixels weighted by their bin, then counted, summed pairwise
+var<workgroup> weighted: array<f32, 256>;
+var<workgroup> counts:   array<f32, 256>;
+
+@compute @workgroup_size(256)
+fn cs_main(@builtin(local_invocation_index) index: u32) {
+    let count = f32(bins[index]);
+
+    weighted[index] = count * f32(index);
+    counts[index]   = count;
+    bins[index]     = 0u;
+
+    // Bin 0 holds the black pixels
+    if (index == 0u) {
+        counts[index] = 0.0;
+    }
+
+    workgroupBarrier();
+
+    for (var stride = 128u; stride > 0u; stride = stride / 2u) {
+        if (index < stride) {
+            weighted[index] = weighted[index] + weighted[index + stride];
+            counts[index]   = counts[index] + counts[index + stride];
+        }
+
+        workgroupBarrier();
+    }
+
+    if (index == 0u) {
+        if (counts[0] == 0.0) {
+            average.luminance = 0.0;
+            return;
+        }
+
+        // Undoes the binning in luminance_histogram.wgsl
+        let t = (weighted[0] / counts[0] - 1.0) / 254.0;
+
+        average.luminance = exp2(t * params.log_range + params.min_log_luminance);
+    }
+}
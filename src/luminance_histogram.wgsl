@@ -0,0 +1,60 @@
+// Counts the pixels of an image into 256 bins by log2 luminance. Each workgroup builds its own
+// histogram in shared memory first so only one atomic per bin reaches the storage buffer.
+
+struct HistogramParams {
+    min_log_luminance: f32,
+    log_range:         f32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: HistogramParams;
+@group(0) @binding(1)
+var src: texture_2d<f32>;
+@group(0) @binding(2)
+var<storage, read_write> bins: array<atomic<u32>, 256>;
+
+var<workgroup> local_bins: array<atomic<u32>, 256>;
+
+// Bin 0 is kept for black, which would otherwise drag the average down
+fn bin(color: vec3<f32>) -> u32 {
+    let luminance = dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+
+    if (luminance < 0.0001) {
+        return 0u;
+    }
+
+    let t = clamp((log2(luminance) - params.min_log_luminance) / params.log_range, 0.0, 1.0);
+
+    return u32(t * 254.0 + 1.0);
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_main(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(local_invocation_index) index: u32,
+) {
+    let size  = vec2<i32>(textureDimensions(src));
+    let coord = vec2<i32>(id.xy);
+
+    // 64 invocations clear and flush 256 bins, four each
+    for (var i = 0u; i < 4u; i = i + 1u) {
+        atomicStore(&local_bins[index * 4u + i], 0u);
+    }
+
+    workgroupBarrier();
+
+    if (coord.x < size.x && coord.y < size.y) {
+        atomicAdd(&local_bins[bin(textureLoad(src, coord, 0).rgb)], 1u);
+    }
+
+    workgroupBarrier();
+
+    for (var i = 0u; i < 4u; i = i + 1u) {
+        let b     = index * 4u + i;
+        let count = atomicLoad(&local_bins[b]);
+
+        if (count > 0u) {
+            atomicAdd(&bins[b], count);
+        }
+    }
+}
@@ -0,0 +1,56 @@
+// Materials packed into the layers of one texture array. Meshes drawn with any of them share a
+// bind group, so a whole scene of them can go out in one instanced call with each instance
+// picking its layer (see `LayeredInstanceRaw`).
+
+use crate::{bind_group::{self, kind}, texture};
+
+pub struct MaterialArray {
+    pub texture:    texture::Texture,
+    pub bind_group: wgpu::BindGroup,
+    names:          Vec<String>,
+}
+
+impl MaterialArray {
+    pub fn layout_builder() -> bind_group::BindGroupLayoutBuilder<(kind::Texture, (kind::Sampler, ()))> {
+        bind_group::BindGroupLayoutBuilder::new()
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2Array)
+            .sampler(wgpu::ShaderStages::FRAGMENT)
+    }
+
+    // Shader for meshes drawn from an array, with the same bind groups as the shading models
+    // except for the texture at @group(0)
+    pub fn shader_source() -> &'static str {
+        concat!(include_str!("camera.wgsl"), include_str!("lights.wgsl"), include_str!("instance.wgsl"), include_str!("material_array.wgsl"))
+    }
+
+    // Layers are in the order given. Every albedo is resized to the first one's size.
+    pub fn new(
+        device:    &wgpu::Device,
+        queue:     &wgpu::Queue,
+        layout:    &wgpu::BindGroupLayout,
+        materials: &[(&str, image::DynamicImage)],
+        label:     &str,
+    ) -> anyhow::Result<Self> {
+        let images  = materials.iter().map(|(_, img)| img).collect::<Vec<_>>();
+        let texture = texture::Texture::array_from_images(device, queue, &images, Some(label))?;
+
+        let bind_group = bind_group::BindGroupBuilder::checked(layout, &Self::layout_builder())
+            .texture(&texture.view)
+            .sampler(&texture.sampler)
+            .build(device, label);
+
+        Ok(Self {
+            texture,
+            bind_group,
+            names: materials.iter().map(|(name, _)| name.to_string()).collect(),
+        })
+    }
+
+    pub fn layer(&self, name: &str) -> Option<u32> {
+        self.names.iter().position(|n| n == name).map(|i| i as u32)
+    }
+
+    pub fn layer_count(&self) -> u32 {
+        self.names.len() as u32
+    }
+}
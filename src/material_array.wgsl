@@ -0,0 +1,65 @@
+// Vertex shader
+
+struct VertexInput {
+    @location(0) position:   vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) normal:     vec3<f32>,
+    // White where the mesh has no colors of its own
+    @location(3) color:      vec4<f32>,
+}
+
+struct VertexOutput {
+   // Same expression as shader.wgsl so the shared depth prepass lines up
+   @builtin(position) @invariant clip_position: vec4<f32>,
+   @location(0) tex_coords:          vec2<f32>,
+   @location(1) world_normal:        vec3<f32>,
+   @location(2) world_position:      vec3<f32>,
+   @location(3) @interpolate(flat) layer: u32,
+   @location(4) color:               vec4<f32>,
+}
+
+@vertex
+fn vs_main(
+   model:    VertexInput,
+   instance: InstanceInput,
+   // The material's layer in the arrays, from `LayeredInstanceRaw`
+   @location(9) layer: u32,
+) -> VertexOutput {
+    let model_matrix = instance_model_matrix(instance);
+
+    var out: VertexOutput;
+
+    out.tex_coords     = model.tex_coords;
+    out.color          = model.color;
+    out.world_normal   = normal_to_world(model_matrix, model.normal);
+    out.world_position = (model_matrix * vec4<f32>(model.position, 1.0)).xyz;
+    out.layer          = layer;
+    out.clip_position  = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
+
+    return out;
+}
+
+
+// Fragment shader
+
+@group(0) @binding(0)
+var t_diffuse: texture_2d_array<f32>;
+@group(0) @binding(1)
+var s_diffuse: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Sampled before anything's discarded, which would leave the sample out of uniform control flow
+    let albedo = textureSample(t_diffuse, s_diffuse, in.tex_coords, i32(in.layer)) * in.color;
+
+    // Geometry behind a mirror is left out of its reflection
+    if (dot(camera.clip_plane, vec4<f32>(in.world_position, 1.0)) < 0.0) {
+        discard;
+    }
+
+    let normal = normalize(in.world_normal);
+    let spot   = spot_lighting(in.world_position, normal);
+
+    // Lit by the environment, with lights adding on top
+    return vec4<f32>(albedo.rgb * (probe_lighting(in.world_position, normal) + spot), albedo.a);
+}
@@ -0,0 +1,74 @@
+// Reloads a material from its .material file whenever the file changes, for tuning materials
+// without restarting. See `resources::load_material` for the format. Changes are found by polling
+// the file's modified time each frame, so saving a texture it names doesn't count until the file
+// itself is saved again. Native only.
+//
+// The new material replaces the old one in place. Pipelines follow a changed `shading`, `cull` or
+// `alpha` on their own, since they're looked up by the material each draw. A file that fails to
+// load leaves the last good material drawn, and is tried again once it's saved again.
+
+use std::time::SystemTime;
+
+use crate::{model, renderer::GpuContext, resources};
+
+pub struct MaterialFile {
+    file_name: String,
+    // When the file was last loaded, or last failed to
+    modified:  Option<SystemTime>,
+}
+
+impl MaterialFile {
+    // Loads `file_name` for the first time
+    pub async fn load(
+        file_name: &str,
+        ctx:       &GpuContext,
+        layout:    &wgpu::BindGroupLayout,
+    ) -> anyhow::Result<(Self, model::Material)> {
+        let file = Self {
+            file_name: file_name.to_string(),
+            modified:  modified(file_name),
+        };
+
+        let material = resources::load_material(file_name, &ctx.device, &ctx.queue, layout).await?;
+
+        Ok((file, material))
+    }
+
+    pub fn file_name(&self) -> &str {
+        &self.file_name
+    }
+
+    // Whether the file's been saved since it was last loaded
+    pub fn changed(&self) -> bool {
+        let modified = modified(&self.file_name);
+
+        modified.is_some() && modified != self.modified
+    }
+
+    // Reloads `material` if the file's changed, retiring the old one. True if it was replaced.
+    pub fn update(&mut self, ctx: &GpuContext, layout: &wgpu::BindGroupLayout, material: &mut model::Material) -> bool {
+        if !self.changed() {
+            return false;
+        }
+
+        self.modified = modified(&self.file_name);
+
+        match pollster::block_on(resources::load_material(&self.file_name, &ctx.device, &ctx.queue, layout)) {
+            Ok(reloaded) => {
+                log::info!("Reloaded {}", self.file_name);
+                ctx.retire(std::mem::replace(material, reloaded));
+                true
+            }
+            Err(e) => {
+                log::warn!("Couldn't reload {}: {:#}", self.file_name, e);
+                false
+            }
+        }
+    }
+}
+
+fn modified(file_name: &str) -> Option<SystemTime> {
+    std::fs::metadata(resources::resolve_path(file_name))
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
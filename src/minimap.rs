@@ -0,0 +1,190 @@
+// A second camera's view of the scene, rendered into its own texture each frame and shown as a
+// quad in a corner of the window, for minimaps or security camera feeds. Drawn in the UI layer,
+// so it sits over post effects at full resolution.
+
+use cgmath::prelude::*;
+
+use crate::{
+    bind_group,
+    camera::Camera,
+    crash_report,
+    pass::{Drawable, RenderLayer},
+    renderer::{self, GpuContext},
+    scene::Scene,
+    texture::Texture,
+    thumbnail,
+    uniform::{Uniform, UniformBuffer},
+};
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct MinimapUniform {
+    rect:         [f32; 4],
+    border_color: [f32; 4],
+    border:       [f32; 2],
+    _padding:     [f32; 2],
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+pub struct Minimap {
+    pub corner:       Corner,
+    // Gap to the window's edges, in pixels
+    pub margin:       f32,
+    // Frame around the view, in pixels
+    pub border:       f32,
+    pub border_color: [f32; 3],
+    pipeline:         wgpu::RenderPipeline,
+    bind_group:       wgpu::BindGroup,
+    uniform:          UniformBuffer<MinimapUniform>,
+    view:             wgpu::TextureView,
+    depth:            Texture,
+    // Of the texture, which is also the quad's size on screen
+    size:             (u32, u32),
+}
+
+impl Minimap {
+    pub fn new(ctx: &GpuContext, width: u32, height: u32) -> Self {
+        let device = &ctx.device;
+
+        // The surface format, since that's what the scene's pipelines are built for
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label:           Some("Minimap Target"),
+            size:            wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count:    1,
+            dimension:       wgpu::TextureDimension::D2,
+            format:          ctx.config.format,
+            usage:           wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label:          Some("Minimap Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter:     wgpu::FilterMode::Linear,
+            min_filter:     wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let uniform = UniformBuffer::new(device, "Minimap Uniform Buffer");
+
+        let layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT)
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .sampler(wgpu::ShaderStages::FRAGMENT)
+            .build(device, "minimap_bind_group_layout");
+
+        let bind_group = bind_group::BindGroupBuilder::new(&layout)
+            .uniform(uniform.buffer())
+            .texture(&view)
+            .sampler(&sampler)
+            .build(device, "minimap_bind_group");
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Minimap Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("minimap.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Minimap Pipeline Layout"),
+            bind_group_layouts:   &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        // The UI layer has no depth attachment
+        let pipeline = renderer::create_render_pipeline(
+            device,
+            &pipeline_layout,
+            ctx.config.format,
+            None,
+            &[],
+            &shader,
+            "Minimap Pipeline",
+        );
+
+        Self {
+            corner:       Corner::TopRight,
+            margin:       16.0,
+            border:       2.0,
+            border_color: [0.9, 0.9, 0.9],
+            pipeline,
+            bind_group,
+            uniform,
+            view,
+            depth:        Texture::create_depth_texture(device, width, height, "minimap_depth_texture"),
+            size:         (width, height),
+        }
+    }
+
+    pub fn aspect(&self) -> f32 {
+        self.size.0 as f32 / self.size.1 as f32
+    }
+
+    // Renders `scene` from `camera`, whose aspect ratio should match `aspect`, and places the quad
+    // for the window's current size. Submitted straight away, so call it before the frame's own
+    // camera is uploaded.
+    pub fn render(&self, ctx: &GpuContext, scene: &dyn Scene, camera: &Camera) {
+        thumbnail::render_scene(ctx, scene, camera, None, &self.view, &self.depth.view, "Minimap Encoder");
+
+        let window = (ctx.size.width.max(1) as f32, ctx.size.height.max(1) as f32);
+        let (w, h) = (self.size.0 as f32, self.size.1 as f32);
+
+        let left = match self.corner {
+            Corner::TopLeft | Corner::BottomLeft   => self.margin,
+            Corner::TopRight | Corner::BottomRight => window.0 - self.margin - w,
+        };
+        let top = match self.corner {
+            Corner::TopLeft | Corner::TopRight       => self.margin,
+            Corner::BottomLeft | Corner::BottomRight => window.1 - self.margin - h,
+        };
+
+        // Pixels from the top left to normalized device coordinates
+        let ndc = |x: f32, y: f32| [x / window.0 * 2.0 - 1.0, 1.0 - y / window.1 * 2.0];
+        let min = ndc(left, top + h);
+        let max = ndc(left + w, top);
+
+        self.uniform.write(&ctx.queue, &MinimapUniform {
+            rect:         [min[0], min[1], max[0], max[1]],
+            border_color: [self.border_color[0], self.border_color[1], self.border_color[2], 1.0],
+            border:       [self.border / w, self.border / h],
+            _padding:     [0.0; 2],
+        });
+    }
+}
+
+impl Drawable for Minimap {
+    fn draw<'a>(&'a self, _layer: RenderLayer, render_pass: &mut wgpu::RenderPass<'a>) {
+        crash_report::set_pipeline(render_pass, &self.pipeline, "Minimap Pipeline");
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..6, 0..1);
+    }
+}
+
+// Looks straight down on the ground under `camera` from `height` above it, turned so the way it
+// faces is up on the map
+pub fn overhead_camera(camera: &Camera, height: f32, aspect: f32) -> Camera {
+    let forward = camera.target - camera.eye;
+    let heading = cgmath::Vector3::new(forward.x, 0.0, forward.z);
+    let heading = if heading.magnitude2() > 0.0 { heading.normalize() } else { -cgmath::Vector3::unit_z() };
+    let below   = cgmath::Point3::new(camera.eye.x, 0.0, camera.eye.z);
+
+    Camera {
+        eye:        below + cgmath::Vector3::unit_y() * height,
+        target:     below,
+        up:         heading,
+        aspect,
+        fovy:       45.0,
+        znear:      0.1,
+        zfar:       height * 2.0,
+        depth_mode: camera.depth_mode,
+    }
+}
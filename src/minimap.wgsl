@@ -0,0 +1,49 @@
+// A second view of the scene drawn as a quad in a corner of the screen, with a thin frame
+
+struct MinimapUniform {
+    // Left, bottom, right and top in normalized device coordinates
+    rect:         vec4<f32>,
+    border_color: vec4<f32>,
+    // Frame width as a fraction of the quad's size, in x and y
+    border:       vec2<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> minimap: MinimapUniform;
+@group(0) @binding(1)
+var t_view: texture_2d<f32>;
+@group(0) @binding(2)
+var s_view: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // Two triangles covering the quad
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0), vec2<f32>(1.0, 0.0), vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 0.0), vec2<f32>(1.0, 1.0), vec2<f32>(0.0, 1.0),
+    );
+
+    let corner = corners[index];
+
+    var out: VertexOutput;
+
+    out.uv            = vec2<f32>(corner.x, 1.0 - corner.y);
+    out.clip_position = vec4<f32>(mix(minimap.rect.xy, minimap.rect.zw, corner), 0.0, 1.0);
+
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSampleLevel(t_view, s_view, in.uv, 0.0);
+
+    let edge    = min(in.uv, 1.0 - in.uv);
+    let on_edge = any(edge < minimap.border);
+
+    return select(vec4<f32>(color.rgb, 1.0), minimap.border_color, on_edge);
+}
@@ -0,0 +1,58 @@
+// A flat mirror: the planar reflection where it covers the surface, the reflection probe where it
+// doesn't, blended over the mirror's color with Schlick's Fresnel approximation
+
+struct MirrorUniform {
+    view_proj:           mat4x4<f32>,
+    reflected_view_proj: mat4x4<f32>,
+    eye:                 vec4<f32>,
+    // xyz is the normal, w the reflectivity facing the mirror head on
+    normal_reflectivity: vec4<f32>,
+    color:               vec4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> mirror: MirrorUniform;
+@group(0) @binding(1)
+var t_reflection: texture_2d<f32>;
+@group(0) @binding(2)
+var t_probe: texture_2d_array<f32>;
+@group(0) @binding(3)
+var s_reflection: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+}
+
+@vertex
+fn vs_main(@location(0) position: vec3<f32>) -> VertexOutput {
+    var out: VertexOutput;
+
+    out.world_position = position;
+    out.clip_position  = mirror.view_proj * vec4<f32>(position, 1.0);
+
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let normal   = mirror.normal_reflectivity.xyz;
+    let view_dir = normalize(mirror.eye.xyz - in.world_position);
+
+    let clip   = mirror.reflected_view_proj * vec4<f32>(in.world_position, 1.0);
+    let ndc    = clip.xy / clip.w;
+    let uv     = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+    let planar = textureSample(t_reflection, s_reflection, uv).rgb;
+
+    // Sampled at the top level since mirrors are flat, so there's no need for derivatives
+    let probe_coords = probe_uv(reflect(-view_dir, normal));
+    let probe        = textureSampleLevel(t_probe, s_reflection, probe_coords.xy, i32(probe_coords.z), 0.0).rgb;
+
+    let covered    = clip.w > 0.0 && all(uv >= vec2<f32>(0.0)) && all(uv <= vec2<f32>(1.0));
+    let reflection = select(probe, planar, covered);
+
+    let f0      = mirror.normal_reflectivity.w;
+    let fresnel = f0 + (1.0 - f0) * pow(1.0 - max(dot(normal, view_dir), 0.0), 5.0);
+
+    return vec4<f32>(mix(mirror.color.rgb, reflection, fresnel), 1.0);
+}
@@ -1,51 +1,452 @@
 use std::ops::Range;
 
-use crate::texture;
+use learn_wgpu_derive::VertexLayout;
+
+use crate::{
+    bind_group::{self, kind},
+    bounds::Aabb,
+    gpu_stats::{self, Tracked},
+    in_flight::Retire,
+    packing,
+    shading::{AlphaMode, CullMode, DepthBias, MaterialFeatures, PipelineKey, ShadingModel},
+    texture,
+    uniform::{Uniform, UniformBuffer},
+};
 
 pub trait Vertex {
     fn desc<'a>() -> wgpu::VertexBufferLayout<'a>;
 }
 
 #[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
 pub struct ModelVertex {
-    pub position:   [f32; 3],
-    pub tex_coords: [f32; 2],
-    pub normal:     [f32; 3],
+    #[location(0)]
+    pub position:        [f32; 3],
+    #[location(1)]
+    pub tex_coords:      [f32; 2],
+    #[location(2)]
+    pub normal:          [f32; 3],
+    // Multiplies the material's color, white for meshes without vertex colors
+    #[location(3)]
+    pub color:           [f32; 4],
+    // Where the vertex is in the material's lightmap, which usually needs a layout of its own
+    // with no overlaps. The same as `tex_coords` for meshes with one set of UVs.
+    #[location(4)]
+    pub lightmap_coords: [f32; 2],
 }
 
 impl Vertex for ModelVertex {
     fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
-        use std::mem;
-
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<ModelVertex>() as wgpu::BufferAddress, // 24 bytes
-            step_mode:    wgpu::VertexStepMode::Vertex,
-            attributes:   &[
-                wgpu::VertexAttribute {
-                    offset: 0,
-                    shader_location: 0,
-                    format: wgpu::VertexFormat::Float32x3 // vec3<f32>
-                },
-                wgpu::VertexAttribute {
-                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
-                    shader_location: 1,
-                    format: wgpu::VertexFormat::Float32x2
-                },
-                wgpu::VertexAttribute {
-                    offset: std::mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
-                    shader_location: 2,
-                    format: wgpu::VertexFormat::Float32x3
-                },
-            ]
+        Self::layout()
+    }
+}
+
+// What loaded meshes are stored as on the GPU, converted from `ModelVertex` once loading is done:
+// 28 bytes a vertex instead of 56. Positions keep full precision, both sets of UVs get half
+// floats, which are exact to a texel on textures up to 2048 wide, and normals and colors 8 bits a
+// component, plenty once normals are renormalized. Shaders see the same vectors of floats either
+// way.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+pub struct PackedVertex {
+    #[location(0)]
+    pub position:        [f32; 3],
+    #[location(1)]
+    #[format(Float16x2)]
+    pub tex_coords:      [u16; 2],
+    // The w is unused, there being no 3 component 8 bit format
+    #[location(2)]
+    #[format(Snorm8x4)]
+    pub normal:          [i8; 4],
+    #[location(3)]
+    #[format(Unorm8x4)]
+    pub color:           [u8; 4],
+    #[location(4)]
+    #[format(Float16x2)]
+    pub lightmap_coords: [u16; 2],
+}
+
+impl Vertex for PackedVertex {
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        Self::layout()
+    }
+}
+
+impl From<ModelVertex> for PackedVertex {
+    fn from(vertex: ModelVertex) -> Self {
+        let [u, v]    = vertex.tex_coords;
+        let [x, y, z] = vertex.normal;
+
+        Self {
+            position:        vertex.position,
+            tex_coords:      [packing::f16(u), packing::f16(v)],
+            normal:          [packing::snorm8(x), packing::snorm8(y), packing::snorm8(z), 0],
+            color:           vertex.color.map(packing::unorm8),
+            lightmap_coords: vertex.lightmap_coords.map(packing::f16),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+#[vertex(instance)]
+pub struct InstanceRaw {
+    #[location(5)]
+    pub model: [[f32; 4]; 4],
+}
+
+// An instance drawn from a `MaterialArray`, with the layer holding its material
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+#[vertex(instance)]
+pub struct LayeredInstanceRaw {
+    #[location(5)]
+    pub model: [[f32; 4]; 4],
+    #[location(9)]
+    pub layer: u32,
+}
+
+pub struct Instance {
+    pub position: cgmath::Vector3<f32>,
+    pub rotation: cgmath::Quaternion<f32>,
+    pub scale:    cgmath::Vector3<f32>,
+}
+
+impl Instance {
+    pub fn to_matrix(&self) -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::from_translation(self.position)
+            * cgmath::Matrix4::from(self.rotation)
+            * cgmath::Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+
+    pub fn to_raw(&self) -> InstanceRaw {
+        InstanceRaw {
+            model: self.to_matrix().into(),
+        }
+    }
+
+    pub fn to_layered_raw(&self, layer: u32) -> LayeredInstanceRaw {
+        LayeredInstanceRaw {
+            model: self.to_matrix().into(),
+            layer,
+        }
+    }
+}
+
+// Values every shading model reads from @group(0) @binding(2)
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+pub struct MaterialParams {
+    // Multiplies the diffuse texture
+    pub tint:                    [f32; 4],
+    // Light given off whatever the lighting, times the emissive texture. Past 1.0 it's bright
+    // enough to bloom.
+    pub emissive:                [f32; 3],
+    pub emissive_intensity:      f32,
+    // Alpha under which cutout materials are cut away. Uploaded as 0.0 for opaque ones, which the
+    // shaders take to mean no cutting.
+    pub alpha_cutoff:            f32,
+    // The rest is only read by subsurface shading. How far past the terminator light wraps, 0.0
+    // being none.
+    pub subsurface_wrap:         f32,
+    // Strength of light shining through from behind, and how tightly it's focused around the
+    // light
+    pub transmission:            f32,
+    pub transmission_power:      f32,
+    // Color light takes on as it scatters, red for skin and green for leaves
+    pub subsurface_color:        [f32; 3],
+    // How much the normal bends light on its way through
+    pub transmission_distortion: f32,
+    // Strength of the textured model's highlight from the base layer, which it leaves out by
+    // default, and the base layer's roughness
+    pub specular:                f32,
+    pub roughness:               f32,
+    // Stretches the base highlight along the surface, for brushed metal, from 0.0 for none to
+    // 1.0. The direction is along the texture's u, turned by `anisotropy_rotation` radians and by
+    // the anisotropy texture where there is one, as in KHR_materials_anisotropy.
+    pub anisotropy:              f32,
+    pub anisotropy_rotation:     f32,
+    // A glossy layer over everything else, like the lacquer on car paint, as in
+    // KHR_materials_clearcoat. Strength from 0.0 for none to 1.0.
+    pub clearcoat:               f32,
+    pub clearcoat_roughness:     f32,
+    // Share of light passing through the surface rather than being diffusely lit, as in
+    // KHR_materials_transmission. Transmissive materials are drawn as glass over the opaque scene,
+    // which they refract by `ior` as if `thickness` deep. Rough glass blurs what's behind it.
+    pub transmittance:           f32,
+    pub ior:                     f32,
+    pub thickness:               f32,
+    // Above 0.0, the diffuse and emissive textures are projected along the world axes instead of
+    // read at the mesh's UVs, repeating this many times a unit. For terrain and generated meshes
+    // without UVs of their own. `triplanar_sharpness` narrows where the projections blend.
+    pub triplanar_scale:         f32,
+    pub triplanar_sharpness:     f32,
+    // Set by `upload` when there's an anisotropy texture to read
+    pub anisotropy_map:          u32,
+    // Repeats of the detail textures per repeat of the mesh's UVs, over which they're laid up
+    // close. They fade out by `detail_distance` from the camera, and `detail_strength` scales how
+    // much they change the surface.
+    pub detail_scale:            f32,
+    pub detail_distance:         f32,
+    pub detail_strength:         f32,
+    // Set by `upload`, 1 when there's a detail albedo texture to read and 2 for a detail normal
+    // texture
+    pub detail_maps:             u32,
+    // Scales the baked light read from the lightmap, which only the textured model reads
+    pub lightmap_intensity:      f32,
+    // Set by `upload` when there's a lightmap to read
+    pub lightmap_map:            u32,
+    // Above 0.0, fur this long grows out of the surface, drawn as `fur_shells` layers over it with
+    // `fur_density` strands across each repeat of the UVs. `fur_gravity` bends the tips down by
+    // that share of their length. See fur.rs.
+    pub fur_length:              f32,
+    pub fur_density:             f32,
+    pub fur_shells:              u32,
+    pub fur_gravity:             f32,
+    _padding:                    [f32; 2],
+}
+
+impl Default for MaterialParams {
+    fn default() -> Self {
+        Self {
+            tint:                    [1.0; 4],
+            emissive:                [1.0; 3],
+            emissive_intensity:      0.0,
+            alpha_cutoff:            0.5,
+            subsurface_wrap:         0.5,
+            transmission:            0.0,
+            transmission_power:      4.0,
+            subsurface_color:        [1.0, 0.4, 0.25],
+            transmission_distortion: 0.2,
+            specular:                0.0,
+            roughness:               0.5,
+            anisotropy:              0.0,
+            anisotropy_rotation:     0.0,
+            clearcoat:               0.0,
+            clearcoat_roughness:     0.0,
+            transmittance:           0.0,
+            ior:                     1.5,
+            thickness:               0.1,
+            triplanar_scale:         0.0,
+            triplanar_sharpness:     4.0,
+            anisotropy_map:          0,
+            detail_scale:            8.0,
+            detail_distance:         10.0,
+            detail_strength:         1.0,
+            detail_maps:             0,
+            lightmap_intensity:      1.0,
+            lightmap_map:            0,
+            fur_length:              0.0,
+            fur_density:             100.0,
+            fur_shells:              16,
+            fur_gravity:             0.2,
+            _padding:                [0.0; 2],
         }
     }
 }
 
 pub struct Material {
-    pub name:            String,
-    pub diffuse_texture: texture::Texture,
-    pub bind_group:      wgpu::BindGroup,
+    pub name:             String,
+    pub diffuse_texture:  texture::Texture,
+    // Without one the diffuse texture is used, so the material glows in its own colors
+    pub emissive_texture: Option<texture::Texture>,
+    // Directions in red and green and strength in blue, see `set_anisotropy_texture`
+    anisotropy_texture:   Option<texture::Texture>,
+    // See `set_detail_textures`
+    detail_texture:       Option<texture::Texture>,
+    detail_normals:       Option<texture::Texture>,
+    // See `set_lightmap`
+    lightmap:             Option<texture::Texture>,
+    pub bind_group:       wgpu::BindGroup,
+    // Picks the pipeline the material's meshes are drawn with
+    pub shading:          ShadingModel,
+    // Read from a `cull` line in the .mtl file, back faces by default
+    pub cull:             CullMode,
+    // Read from an `alpha` line, opaque by default
+    pub alpha:            AlphaMode,
+    // Read from a `depth_bias` line, unbiased by default
+    pub depth_bias:       DepthBias,
+    // Sent to the GPU by `upload`, so changes can be made any time before it
+    pub params:           MaterialParams,
+    uniform:              UniformBuffer<MaterialParams>,
+    // What `uniform` holds, so unchanged materials aren't uploaded again
+    uploaded:             MaterialParams,
+}
+
+// What each of the material's bindings holds, in `layout_builder`'s order
+pub type MaterialBindings = (kind::Texture, (kind::Sampler, (kind::Uniform, (kind::Texture, (kind::Texture, (kind::Texture, (kind::Texture, (kind::Texture, ()))))))));
+
+impl Material {
+    pub fn layout_builder() -> bind_group::BindGroupLayoutBuilder<MaterialBindings> {
+        bind_group::BindGroupLayoutBuilder::new()
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .sampler(wgpu::ShaderStages::FRAGMENT)
+            // Fur shells are pushed out in the vertex shader
+            .uniform(wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT)
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+    }
+
+    // `layout` is built from `layout_builder`. The other textures share the diffuse sampler.
+    pub fn new(
+        device:           &wgpu::Device,
+        layout:           &wgpu::BindGroupLayout,
+        name:             String,
+        diffuse_texture:  texture::Texture,
+        emissive_texture: Option<texture::Texture>,
+        shading:          ShadingModel,
+    ) -> Self {
+        let params   = MaterialParams::default();
+        // As `upload` would send it for an opaque material
+        let uploaded = MaterialParams { alpha_cutoff: 0.0, ..params };
+        let uniform  = UniformBuffer::with_contents(device, &name, &uploaded);
+
+        let bind_group = create_bind_group(device, layout, &name, &diffuse_texture, emissive_texture.as_ref(), None, None, None, None, &uniform);
+
+        Self {
+            name,
+            diffuse_texture,
+            emissive_texture,
+            anisotropy_texture: None,
+            detail_texture: None,
+            detail_normals: None,
+            lightmap: None,
+            bind_group,
+            shading,
+            cull: CullMode::default(),
+            alpha: AlphaMode::default(),
+            depth_bias: DepthBias::default(),
+            params,
+            uniform,
+            uploaded,
+        }
+    }
+
+    // Drawn as glass in the transparent layer instead of with the shading pipelines
+    pub fn is_transmissive(&self) -> bool {
+        self.params.transmittance > 0.0
+    }
+
+    pub fn has_fur(&self) -> bool {
+        self.params.fur_length > 0.0 && self.params.fur_shells > 0
+    }
+
+    pub fn pipeline_key(&self) -> PipelineKey {
+        PipelineKey {
+            shading:    self.shading,
+            cull:       self.cull,
+            alpha:      self.alpha,
+            depth_bias: self.depth_bias,
+            features:   MaterialFeatures {
+                triplanar:      self.params.triplanar_scale > 0.0,
+                anisotropy_map: self.anisotropy_texture.is_some(),
+                lightmap:       self.lightmap.is_some(),
+            },
+        }
+    }
+
+    // Swaps in another base color texture, like one `TextureStreamer` made with more or fewer
+    // mips, and hands back the old one, which frames in flight may still be drawing with
+    pub fn set_diffuse_texture(&mut self, device: &wgpu::Device, layout: &wgpu::BindGroupLayout, texture: texture::Texture) -> texture::Texture {
+        let old = std::mem::replace(&mut self.diffuse_texture, texture);
+
+        self.rebuild_bind_group(device, layout);
+        old
+    }
+
+    // Directions the highlight is stretched along, in tangent space with u along red and v along
+    // green, each from -1.0 to 1.0 stored as 0.0 to 1.0, and the anisotropy's strength in blue.
+    // Must hold linear data rather than sRGB colors.
+    pub fn set_anisotropy_texture(&mut self, device: &wgpu::Device, layout: &wgpu::BindGroupLayout, texture: texture::Texture) {
+        self.anisotropy_texture = Some(texture);
+        self.rebuild_bind_group(device, layout);
+    }
+
+    // Tiled over the base textures up close, see the detail parameters. Both hold linear data
+    // rather than sRGB colors. `albedo` multiplies the base twice over, so 0.5 leaves it as it is,
+    // lighter brightens it and darker darkens it. `normals` is a tangent space normal map with u
+    // in red and v in green.
+    pub fn set_detail_textures(
+        &mut self,
+        device:  &wgpu::Device,
+        layout:  &wgpu::BindGroupLayout,
+        albedo:  Option<texture::Texture>,
+        normals: Option<texture::Texture>,
+    ) {
+        self.detail_texture = albedo;
+        self.detail_normals = normals;
+        self.rebuild_bind_group(device, layout);
+    }
+
+    // Light baked into a color texture at the meshes' lightmap coordinates, e.g. from Blender or
+    // Bakery or with `lightmap::bake`. The textured model takes it in place of the environment's
+    // light, while spot lights still light the surface as they move. None goes back to the
+    // environment.
+    pub fn set_lightmap(&mut self, device: &wgpu::Device, layout: &wgpu::BindGroupLayout, lightmap: Option<texture::Texture>) {
+        self.lightmap = lightmap;
+        self.rebuild_bind_group(device, layout);
+    }
+
+    fn rebuild_bind_group(&mut self, device: &wgpu::Device, layout: &wgpu::BindGroupLayout) {
+        self.bind_group = create_bind_group(
+            device,
+            layout,
+            &self.name,
+            &self.diffuse_texture,
+            self.emissive_texture.as_ref(),
+            self.anisotropy_texture.as_ref(),
+            self.detail_texture.as_ref(),
+            self.detail_normals.as_ref(),
+            self.lightmap.as_ref(),
+            &self.uniform,
+        );
+    }
+
+    // Writes `params` if they've changed since the last upload
+    pub fn upload(&mut self, queue: &wgpu::Queue) {
+        let params = MaterialParams {
+            alpha_cutoff:   if self.alpha == AlphaMode::Cutout { self.params.alpha_cutoff } else { 0.0 },
+            anisotropy_map: self.anisotropy_texture.is_some() as u32,
+            detail_maps:    self.detail_texture.is_some() as u32 | (self.detail_normals.is_some() as u32) << 1,
+            lightmap_map:   self.lightmap.is_some() as u32,
+            ..self.params
+        };
+
+        if bytemuck::bytes_of(&params) != bytemuck::bytes_of(&self.uploaded) {
+            self.uniform.write(queue, &params);
+            self.uploaded = params;
+        }
+    }
+}
+
+// The diffuse texture stands in for textures a material doesn't have. Emission then takes its
+// colors, while the others skip it, told by the params' `_map` and `_maps` fields.
+#[allow(clippy::too_many_arguments)]
+fn create_bind_group(
+    device:             &wgpu::Device,
+    layout:             &wgpu::BindGroupLayout,
+    name:               &str,
+    diffuse_texture:    &texture::Texture,
+    emissive_texture:   Option<&texture::Texture>,
+    anisotropy_texture: Option<&texture::Texture>,
+    detail_texture:     Option<&texture::Texture>,
+    detail_normals:     Option<&texture::Texture>,
+    lightmap:           Option<&texture::Texture>,
+    uniform:            &UniformBuffer<MaterialParams>,
+) -> wgpu::BindGroup {
+    bind_group::BindGroupBuilder::checked(layout, &Material::layout_builder())
+        .texture(&diffuse_texture.view)
+        .sampler(&diffuse_texture.sampler)
+        .uniform(uniform.buffer())
+        .texture(&emissive_texture.unwrap_or(diffuse_texture).view)
+        .texture(&anisotropy_texture.unwrap_or(diffuse_texture).view)
+        .texture(&detail_texture.unwrap_or(diffuse_texture).view)
+        .texture(&detail_normals.unwrap_or(diffuse_texture).view)
+        .texture(&lightmap.unwrap_or(diffuse_texture).view)
+        .build(device, name)
 }
 
 pub struct Mesh {
@@ -54,6 +455,10 @@ pub struct Mesh {
     pub index_buffer:  wgpu::Buffer,
     pub num_elements:  u32,
     pub material:      usize,
+    // In model space
+    pub bounds:        Aabb,
+    // Both buffers, for the resource inspector, see gpu_stats.rs
+    pub tracked:       Tracked,
 }
 
 pub struct Model {
@@ -61,6 +466,42 @@ pub struct Model {
     pub materials: Vec<Material>,
 }
 
+impl Model {
+    pub fn bounds(&self) -> Aabb {
+        self.meshes.iter().fold(Aabb::EMPTY, |acc, mesh| acc.union(&mesh.bounds))
+    }
+}
+
+// Unloading a model that might still be drawing goes through `GpuContext::retire`
+impl Retire for Mesh {
+    fn release(self: Box<Self>) {
+        self.vertex_buffer.destroy();
+        self.index_buffer.destroy();
+    }
+}
+
+impl Retire for Material {
+    fn release(self: Box<Self>) {
+        let textures = [
+            Some(self.diffuse_texture),
+            self.emissive_texture,
+            self.anisotropy_texture,
+            self.detail_texture,
+            self.detail_normals,
+            self.lightmap,
+        ];
+
+        Box::new(textures.into_iter().flatten().collect::<Vec<_>>()).release();
+    }
+}
+
+impl Retire for Model {
+    fn release(self: Box<Self>) {
+        Box::new(self.meshes).release();
+        Box::new(self.materials).release();
+    }
+}
+
 pub trait DrawModel<'a> {
     fn draw_mesh(
         &mut self,
@@ -115,6 +556,7 @@ where
         self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
         self.set_bind_group(0, &material.bind_group, &[]);
         self.set_bind_group(1, camera_bind_group, &[]);
+        gpu_stats::record_draw(mesh.num_elements, instances.len() as u32);
         self.draw_indexed(0..mesh.num_elements, 0, instances);
     }
 
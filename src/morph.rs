@@ -0,0 +1,205 @@
+// Morph target (blend shape) animation, as glTF characters use for faces. A mesh's targets are
+// stored as per-vertex deltas in a storage buffer and blended in the vertex shader by weights
+// that can be changed every frame. Meshes are loaded with `resources::load_morph_model`.
+//
+// Storage buffers can't be read from vertex shaders on WebGL, so this is native only in practice.
+
+use wgpu::util::DeviceExt;
+
+use crate::{
+    bind_group,
+    crash_report,
+    model::{InstanceRaw, Material, ModelVertex, PackedVertex, Vertex},
+    renderer::{self, GpuContext},
+    texture::Texture,
+};
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct MorphDelta {
+    position: [f32; 4],
+    normal:   [f32; 4],
+}
+
+// Offsets from the base mesh, one per vertex. Either may be empty if the target doesn't move it.
+#[derive(Debug, Clone, Default)]
+pub struct MorphTarget {
+    pub positions: Vec<[f32; 3]>,
+    pub normals:   Vec<[f32; 3]>,
+}
+
+pub struct MorphMesh {
+    pub name:      String,
+    pub material:  usize,
+    // One per target, blended in as they are and all 0.0 to start with. Written to the GPU by
+    // `prepare`.
+    pub weights:   Vec<f32>,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer:  wgpu::Buffer,
+    num_elements:  u32,
+    target_count:  usize,
+    weight_buffer: wgpu::Buffer,
+    bind_group:    wgpu::BindGroup,
+}
+
+impl MorphMesh {
+    // Unlike `resources::load_model` the vertices aren't reordered, as the deltas follow their order
+    pub fn new(
+        device:   &wgpu::Device,
+        layout:   &wgpu::BindGroupLayout,
+        name:     &str,
+        vertices: &[ModelVertex],
+        indices:  &[u32],
+        targets:  &[MorphTarget],
+        material: usize,
+    ) -> Self {
+        let packed = vertices
+            .iter()
+            .map(|&v| PackedVertex::from(v))
+            .collect::<Vec<_>>();
+
+        // A mesh without targets gets one that does nothing, since bindings can't be empty
+        let mut deltas = vec![MorphDelta::default(); vertices.len() * targets.len().max(1)];
+
+        for (t, target) in targets.iter().enumerate() {
+            let deltas = &mut deltas[t * vertices.len()..(t + 1) * vertices.len()];
+
+            for (delta, position) in deltas.iter_mut().zip(&target.positions) {
+                delta.position = [position[0], position[1], position[2], 0.0];
+            }
+            for (delta, normal) in deltas.iter_mut().zip(&target.normals) {
+                delta.normal = [normal[0], normal[1], normal[2], 0.0];
+            }
+        }
+
+        // A padding target's weight stays 0.0 on the GPU, out of sight of `weights`
+        let weights = vec![0.0f32; targets.len().max(1)];
+
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label:    Some(&format!("{:?} Vertex Buffer", name)),
+            contents: bytemuck::cast_slice(&packed),
+            usage:    wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer  = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label:    Some(&format!("{:?} Index Buffer", name)),
+            contents: bytemuck::cast_slice(indices),
+            usage:    wgpu::BufferUsages::INDEX,
+        });
+        let delta_buffer  = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label:    Some(&format!("{:?} Morph Delta Buffer", name)),
+            contents: bytemuck::cast_slice(&deltas),
+            usage:    wgpu::BufferUsages::STORAGE,
+        });
+        let weight_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label:    Some(&format!("{:?} Morph Weight Buffer", name)),
+            contents: bytemuck::cast_slice(&weights),
+            usage:    wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group = bind_group::BindGroupBuilder::new(layout)
+            .storage(&delta_buffer)
+            .storage(&weight_buffer)
+            .build(device, "morph_bind_group");
+
+        Self {
+            name:         name.to_string(),
+            material,
+            weights:      vec![0.0; targets.len()],
+            vertex_buffer,
+            index_buffer,
+            num_elements: indices.len() as u32,
+            target_count: targets.len(),
+            weight_buffer,
+            bind_group,
+        }
+    }
+
+    pub fn prepare(&self, queue: &wgpu::Queue) {
+        let count = self.weights.len().min(self.target_count);
+
+        if count > 0 {
+            queue.write_buffer(&self.weight_buffer, 0, bytemuck::cast_slice(&self.weights[..count]));
+        }
+    }
+}
+
+pub struct MorphModel {
+    pub meshes:    Vec<MorphMesh>,
+    pub materials: Vec<Material>,
+}
+
+impl MorphModel {
+    pub fn prepare(&self, queue: &wgpu::Queue) {
+        for mesh in &self.meshes {
+            mesh.prepare(queue);
+        }
+    }
+}
+
+// The pipeline morph meshes are drawn with, and the layout of their deltas and weights
+pub struct MorphRenderer {
+    pub layout: wgpu::BindGroupLayout,
+    pipeline:   wgpu::RenderPipeline,
+}
+
+impl MorphRenderer {
+    pub fn new(
+        ctx:             &GpuContext,
+        material_layout: &wgpu::BindGroupLayout,
+        camera_layout:   &wgpu::BindGroupLayout,
+        lights_layout:   &wgpu::BindGroupLayout,
+    ) -> Self {
+        let device = &ctx.device;
+
+        let layout = bind_group::BindGroupLayoutBuilder::new()
+            .storage(wgpu::ShaderStages::VERTEX, true)
+            .storage(wgpu::ShaderStages::VERTEX, true)
+            .build(device, "morph_bind_group_layout");
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Morph Shader"),
+            source: wgpu::ShaderSource::Wgsl(concat!(include_str!("camera.wgsl"), include_str!("lights.wgsl"), include_str!("triplanar.wgsl"), include_str!("detail.wgsl"), include_str!("instance.wgsl"), include_str!("morph.wgsl")).into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Morph Pipeline Layout"),
+            bind_group_layouts:   &[material_layout, camera_layout, lights_layout, &layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = renderer::create_render_pipeline(
+            device,
+            &pipeline_layout,
+            ctx.config.format,
+            Some(renderer::depth_state(Texture::DEPTH_FORMAT, ctx.depth_mode.compare(), true)),
+            &[PackedVertex::desc(), InstanceRaw::layout()],
+            &shader,
+            "Morph Pipeline",
+        );
+
+        Self { layout, pipeline }
+    }
+
+    // Draws every mesh of `model` for each instance in `instance_buffer`. Expects the lights to be
+    // bound at group 2 already, as the scene's draws leave them.
+    pub fn draw<'a>(
+        &'a self,
+        render_pass:       &mut wgpu::RenderPass<'a>,
+        model:             &'a MorphModel,
+        instance_buffer:   &'a wgpu::Buffer,
+        instances:         std::ops::Range<u32>,
+        camera_bind_group: &'a wgpu::BindGroup,
+    ) {
+        crash_report::set_pipeline(render_pass, &self.pipeline, "Morph Pipeline");
+        render_pass.set_bind_group(1, camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+
+        for mesh in &model.meshes {
+            render_pass.set_bind_group(0, &model.materials[mesh.material].bind_group, &[]);
+            render_pass.set_bind_group(3, &mesh.bind_group, &[]);
+            render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            render_pass.draw_indexed(0..mesh.num_elements, 0, instances.clone());
+        }
+    }
+}
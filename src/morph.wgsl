@@ -0,0 +1,230 @@
+// Morph target (blend shape) meshes: each vertex is moved by its deltas in every target, scaled
+// by the target's weight, before being transformed like any other mesh. Shaded like the textured
+// model. Gets `camera.wgsl`, `lights.wgsl` and `instance.wgsl` prepended.
+
+struct MorphDelta {
+    position: vec4<f32>,
+    normal:   vec4<f32>,
+}
+
+// Target by target, each with a delta for every vertex of the mesh
+@group(3) @binding(0)
+var<storage, read> deltas: array<MorphDelta>;
+@group(3) @binding(1)
+var<storage, read> weights: array<f32>;
+
+struct VertexInput {
+    @location(0) position:   vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) normal:     vec3<f32>,
+    // White where the mesh has no colors of its own
+    @location(3) color:      vec4<f32>,
+}
+
+struct VertexOutput {
+   @builtin(position) clip_position: vec4<f32>,
+   @location(0) tex_coords:          vec2<f32>,
+   @location(1) world_normal:        vec3<f32>,
+   @location(2) world_position:      vec3<f32>,
+   @location(3) color:               vec4<f32>,
+}
+
+@vertex
+fn vs_main(
+   model:    VertexInput,
+   instance: InstanceInput,
+   @builtin(vertex_index) vertex_index: u32,
+) -> VertexOutput {
+    let model_matrix = instance_model_matrix(instance);
+
+    let target_count = arrayLength(&weights);
+    let vertex_count = arrayLength(&deltas) / target_count;
+
+    var position = model.position;
+    var normal   = model.normal;
+
+    for (var i = 0u; i < target_count; i = i + 1u) {
+        let weight = weights[i];
+
+        if (weight != 0.0) {
+            let delta = deltas[i * vertex_count + vertex_index];
+
+            position = position + delta.position.xyz * weight;
+            normal   = normal + delta.normal.xyz * weight;
+        }
+    }
+
+    var out: VertexOutput;
+
+    out.tex_coords     = model.tex_coords;
+    out.color          = model.color;
+    out.world_normal   = normal_to_world(model_matrix, normal);
+    out.world_position = (model_matrix * vec4<f32>(position, 1.0)).xyz;
+    out.clip_position  = camera.view_proj * vec4<f32>(out.world_position, 1.0);
+
+    return out;
+}
+
+@group(0) @binding(0)
+var t_diffuse: texture_2d<f32>;
+@group(0) @binding(1)
+var s_diffuse: sampler;
+
+struct MaterialParams {
+    tint:                    vec4<f32>,
+    emissive:                vec3<f32>,
+    emissive_intensity:      f32,
+    alpha_cutoff:            f32,
+    subsurface_wrap:         f32,
+    transmission:            f32,
+    transmission_power:      f32,
+    subsurface_color:        vec3<f32>,
+    transmission_distortion: f32,
+    specular:                f32,
+    roughness:               f32,
+    anisotropy:              f32,
+    anisotropy_rotation:     f32,
+    clearcoat:               f32,
+    clearcoat_roughness:     f32,
+    transmittance:           f32,
+    ior:                     f32,
+    thickness:               f32,
+    triplanar_scale:         f32,
+    triplanar_sharpness:     f32,
+    anisotropy_map:          u32,
+    detail_scale:            f32,
+    detail_distance:         f32,
+    detail_strength:         f32,
+    detail_maps:             u32,
+}
+
+@group(0) @binding(2)
+var<uniform> material: MaterialParams;
+@group(0) @binding(3)
+var t_emissive: texture_2d<f32>;
+// Tangent space direction in red and green, strength in blue
+@group(0) @binding(4)
+var t_anisotropy: texture_2d<f32>;
+
+// At the mesh's UVs, or projected along the world axes for triplanar materials
+fn material_texel(t: texture_2d<f32>, tex_coords: vec2<f32>, world_position: vec3<f32>, normal: vec3<f32>) -> vec4<f32> {
+    if (material.triplanar_scale > 0.0) {
+        return triplanar_sample(t, s_diffuse, world_position, normal, material.triplanar_scale, material.triplanar_sharpness);
+    }
+
+    return textureSample(t, s_diffuse, tex_coords);
+}
+
+// The material's detail layer over a surface facing `normal`
+fn material_detail(tex_coords: vec2<f32>, world_position: vec3<f32>, normal: vec3<f32>) -> DetailLayer {
+    return detail_layer(
+        s_diffuse,
+        tex_coords,
+        world_position,
+        normal,
+        camera.view_position.xyz,
+        material.detail_scale,
+        material.detail_distance,
+        material.detail_strength,
+        material.detail_maps,
+    );
+}
+
+// Added after lighting and left unclamped, so bright emission can go past 1.0
+fn emission(tex_coords: vec2<f32>, world_position: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    return material_texel(t_emissive, tex_coords, world_position, normal).rgb * material.emissive * material.emissive_intensity;
+}
+
+// Cutout materials sharpen alpha to about a pixel wide around the cutoff, which alpha to coverage
+// turns into an antialiased edge, and are cut away where it reaches 0.0. Opaque materials have a
+// cutoff of 0.0 and keep their alpha.
+fn cutout(alpha: f32) -> f32 {
+    if (material.alpha_cutoff <= 0.0) {
+        return alpha;
+    }
+
+    let sharpened = clamp((alpha - material.alpha_cutoff) / max(fwidth(alpha), 0.0001) + 0.5, 0.0, 1.0);
+
+    if (sharpened <= 0.0) {
+        discard;
+    }
+
+    return sharpened;
+}
+
+// Highlights from every spot light on the base layer and the clear coat over it. The coat reflects
+// some light before it reaches the base, which is returned in w as the share that gets through.
+fn specular_lighting(
+    world_position: vec3<f32>,
+    normal:         vec3<f32>,
+    view_dir:       vec3<f32>,
+    tex_coords:     vec2<f32>,
+) -> vec4<f32> {
+    let frame = tangent_frame(normal, world_position, tex_coords);
+
+    // Along u unless the texture says otherwise, turned by the rotation either way
+    var direction  = vec2<f32>(1.0, 0.0);
+    var anisotropy = material.anisotropy;
+
+    if (material.anisotropy_map != 0u) {
+        let texel = textureSample(t_anisotropy, s_diffuse, tex_coords).rgb;
+
+        direction  = normalize(texel.rg * 2.0 - 1.0 + vec2<f32>(0.0001, 0.0));
+        anisotropy = anisotropy * texel.b;
+    }
+
+    let c       = cos(material.anisotropy_rotation);
+    let s       = sin(material.anisotropy_rotation);
+    let rotated = vec2<f32>(c * direction.x - s * direction.y, s * direction.x + c * direction.y);
+
+    let tangent     = normalize(frame[0] * rotated.x + frame[1] * rotated.y);
+    let aligned     = mat3x3<f32>(tangent, cross(normal, tangent), normal);
+    let coat_weight = material.clearcoat * fresnel(dot(normal, view_dir));
+
+    var total = vec3<f32>(0.0);
+
+    for (var i = 0u; i < lights.spot_count; i = i + 1u) {
+        let spot      = lights.spots[i];
+        let light_dir = normalize(spot.position_range.xyz - world_position);
+        let incoming  = spot_incoming(spot, world_position);
+
+        // The coat's highlight is the same in every direction, so the frame's turn doesn't matter to it
+        let base = anisotropic_specular(aligned, light_dir, view_dir, material.roughness, anisotropy) * material.specular;
+        let coat = anisotropic_specular(aligned, light_dir, view_dir, material.clearcoat_roughness, 0.0) * material.clearcoat;
+
+        total = total + incoming * (base * (1.0 - coat_weight) + coat);
+    }
+
+    // Area lights' highlights are always round, whatever the anisotropy
+    let rect_base = rect_specular(world_position, normal, view_dir, material.roughness) * material.specular;
+    let rect_coat = rect_specular(world_position, normal, view_dir, material.clearcoat_roughness) * material.clearcoat;
+
+    total = total + rect_base * (1.0 - coat_weight) + rect_coat;
+
+    return vec4<f32>(total, 1.0 - coat_weight);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    if (dot(camera.clip_plane, vec4<f32>(in.world_position, 1.0)) < 0.0) {
+        discard;
+    }
+
+    let detail = material_detail(in.tex_coords, in.world_position, normalize(in.world_normal));
+    let albedo = material_texel(t_diffuse, in.tex_coords, in.world_position, in.world_normal) * material.tint * in.color * vec4<f32>(detail.albedo, 1.0);
+    let alpha  = cutout(albedo.a);
+    let normal = detail.normal;
+    let spot   = spot_lighting(in.world_position, normal);
+
+    var color = albedo.rgb * (probe_lighting(in.world_position, normal) + spot);
+
+    // glTF materials all have a highlight, which imports with `specular` set
+    if (material.specular > 0.0 || material.clearcoat > 0.0) {
+        let view_dir = normalize(camera.view_position.xyz - in.world_position);
+        let specular = specular_lighting(in.world_position, normal, view_dir, in.tex_coords);
+
+        color = color * specular.w + specular.rgb;
+    }
+
+    return vec4<f32>(color + emission(in.tex_coords, in.world_position, in.world_normal), alpha);
+}
@@ -0,0 +1,255 @@
+// Post effect that smears moving things, and everything when the camera turns, along their motion
+// in the velocity buffer. The longest motion in each tile is found first and spread to the tiles
+// around it, which tells every pixel how far to look for things blurring over it. How long the
+// shutter stays open scales the streaks.
+//
+// Blur stands in for the frames in between, so at low frame rates, where each frame is already
+// far from the last, it smears more than it helps. `active` turns it off under `min_fps`.
+
+use std::time::Duration;
+
+use crate::{
+    bind_group,
+    camera::{Camera, DepthMode},
+    crash_report,
+    pass::PostEffect,
+    renderer::{self, GpuContext},
+    uniform::{Uniform, UniformBuffer},
+    velocity::{VelocityBuffer, VELOCITY_FORMAT},
+};
+
+// Pixels a side of the tiles, and so the longest streak. Also caps it in the shaders.
+const TILE_SIZE: u32 = 32;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct MotionBlurParams {
+    shutter:   f32,
+    samples:   u32,
+    tile_size: u32,
+    reverse_z: u32,
+    near:      f32,
+    far:       f32,
+    _padding:  [f32; 2],
+}
+
+pub struct MotionBlur {
+    // Share of the frame the shutter is open, 0.5 being the film look of a 180 degree shutter
+    pub shutter:        f32,
+    // Taps along each pixel's streak
+    pub samples:        u32,
+    // Frame rate under which the blur switches itself off
+    pub min_fps:        f32,
+    params:             UniformBuffer<MotionBlurParams>,
+    tile_pipeline:      wgpu::RenderPipeline,
+    tile_layout:        wgpu::BindGroupLayout,
+    neighbor_pipeline:  wgpu::RenderPipeline,
+    neighbor_layout:    wgpu::BindGroupLayout,
+    blur_pipeline:      wgpu::RenderPipeline,
+    scene_layout:       wgpu::BindGroupLayout,
+    velocity_layout:    wgpu::BindGroupLayout,
+    // Made in `prepare` once the velocity buffer is known
+    tiles:              Option<Tiles>,
+    // Smoothed, so a single slow frame doesn't switch the blur off
+    average_frame_time: f32,
+}
+
+// The tile passes' targets and everything bound to them, remade when the velocity buffer is
+struct Tiles {
+    max:            wgpu::TextureView,
+    neighbors:      wgpu::TextureView,
+    size:           (u32, u32),
+    tile_group:     wgpu::BindGroup,
+    neighbor_group: wgpu::BindGroup,
+    velocity_group: wgpu::BindGroup,
+}
+
+impl MotionBlur {
+    pub fn new(ctx: &GpuContext) -> Self {
+        let device = &ctx.device;
+
+        let tile_layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::FRAGMENT)
+            .unfilterable_texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .build(device, "motion_blur_tile_bind_group_layout");
+
+        let neighbor_layout = bind_group::BindGroupLayoutBuilder::new()
+            .unfilterable_texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .build(device, "motion_blur_neighbor_bind_group_layout");
+
+        let scene_layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::FRAGMENT)
+            .unfilterable_texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .build(device, "motion_blur_bind_group_layout");
+
+        let velocity_layout = bind_group::BindGroupLayoutBuilder::new()
+            .unfilterable_texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .depth_texture(wgpu::ShaderStages::FRAGMENT)
+            .unfilterable_texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .build(device, "motion_blur_velocity_bind_group_layout");
+
+        Self {
+            shutter:            0.5,
+            samples:            12,
+            min_fps:            30.0,
+            params:             UniformBuffer::new(device, "Motion Blur Params Buffer"),
+            tile_pipeline:      create_pipeline(device, &[&tile_layout], VELOCITY_FORMAT, include_str!("motion_blur_tile_max.wgsl"), "Motion Blur Tile Max"),
+            tile_layout,
+            neighbor_pipeline:  create_pipeline(device, &[&neighbor_layout], VELOCITY_FORMAT, include_str!("motion_blur_neighbor_max.wgsl"), "Motion Blur Neighbor Max"),
+            neighbor_layout,
+            blur_pipeline:      create_pipeline(device, &[&scene_layout, &velocity_layout], ctx.config.format, include_str!("motion_blur.wgsl"), "Motion Blur"),
+            scene_layout,
+            velocity_layout,
+            tiles:              None,
+            average_frame_time: 0.0,
+        }
+    }
+
+    // Called every update with the time since the last
+    pub fn update(&mut self, dt: Duration) {
+        let dt = dt.as_secs_f32();
+
+        self.average_frame_time = if self.average_frame_time > 0.0 {
+            self.average_frame_time + (dt - self.average_frame_time) * 0.1
+        } else {
+            dt
+        };
+    }
+
+    // Whether the frame rate is high enough for the blur to be worth applying
+    pub fn active(&self) -> bool {
+        self.shutter > 0.0 && self.average_frame_time * self.min_fps <= 1.0
+    }
+
+    // Called once a frame before the effect is added, after `velocity` is prepared for the same
+    // camera
+    pub fn prepare(&mut self, ctx: &GpuContext, camera: &Camera, velocity: &VelocityBuffer) {
+        let device = &ctx.device;
+        let size   = velocity.size();
+        let tiles  = (size.0.div_ceil(TILE_SIZE), size.1.div_ceil(TILE_SIZE));
+
+        // The velocity buffer is remade along with the render resolution, so follow it
+        if self.tiles.as_ref().is_none_or(|current| current.size != size) {
+            let max       = create_tile_target(device, tiles, "Motion Blur Tile Max");
+            let neighbors = create_tile_target(device, tiles, "Motion Blur Neighbor Max");
+
+            let tile_group = bind_group::BindGroupBuilder::new(&self.tile_layout)
+                .uniform(self.params.buffer())
+                .texture(&velocity.view)
+                .build(device, "motion_blur_tile_bind_group");
+
+            let neighbor_group = bind_group::BindGroupBuilder::new(&self.neighbor_layout)
+                .texture(&max)
+                .build(device, "motion_blur_neighbor_bind_group");
+
+            let velocity_group = bind_group::BindGroupBuilder::new(&self.velocity_layout)
+                .texture(&velocity.view)
+                .texture(&velocity.depth.view)
+                .texture(&neighbors)
+                .build(device, "motion_blur_velocity_bind_group");
+
+            self.tiles = Some(Tiles { max, neighbors, size, tile_group, neighbor_group, velocity_group });
+        }
+
+        self.params.write(&ctx.queue, &MotionBlurParams {
+            shutter:   self.shutter.max(0.0),
+            samples:   self.samples.max(2),
+            tile_size: TILE_SIZE,
+            reverse_z: (ctx.depth_mode == DepthMode::ReverseZ) as u32,
+            near:      camera.znear,
+            far:       camera.zfar,
+            _padding:  [0.0; 2],
+        });
+    }
+}
+
+impl PostEffect for MotionBlur {
+    fn apply(
+        &self,
+        device:  &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        input:   &wgpu::TextureView,
+        output:  &wgpu::TextureView,
+    ) {
+        let tiles = match &self.tiles {
+            Some(tiles) => tiles,
+            None        => return,
+        };
+
+        fullscreen_pass(encoder, &tiles.max, "Motion Blur Tile Max", &self.tile_pipeline, &[&tiles.tile_group]);
+        fullscreen_pass(encoder, &tiles.neighbors, "Motion Blur Neighbor Max", &self.neighbor_pipeline, &[&tiles.neighbor_group]);
+
+        let scene_group = bind_group::BindGroupBuilder::new(&self.scene_layout)
+            .uniform(self.params.buffer())
+            .texture(input)
+            .build(device, "motion_blur_bind_group");
+
+        fullscreen_pass(encoder, output, "Motion Blur", &self.blur_pipeline, &[&scene_group, &tiles.velocity_group]);
+    }
+}
+
+fn create_pipeline(
+    device:  &wgpu::Device,
+    layouts: &[&wgpu::BindGroupLayout],
+    format:  wgpu::TextureFormat,
+    source:  &str,
+    label:   &str,
+) -> wgpu::RenderPipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label:  Some(label),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(label),
+        bind_group_layouts:   layouts,
+        push_constant_ranges: &[],
+    });
+
+    renderer::create_render_pipeline(device, &pipeline_layout, format, None, &[], &shader, label)
+}
+
+fn create_tile_target(device: &wgpu::Device, size: (u32, u32), label: &str) -> wgpu::TextureView {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label:           Some(label),
+        size:            wgpu::Extent3d { width: size.0.max(1), height: size.1.max(1), depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count:    1,
+        dimension:       wgpu::TextureDimension::D2,
+        format:          VELOCITY_FORMAT,
+        usage:           wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+    });
+
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+// One triangle over all of `view`, replacing what was there. `label` names the pipeline, and the
+// pass after it.
+fn fullscreen_pass(
+    encoder:     &mut wgpu::CommandEncoder,
+    view:        &wgpu::TextureView,
+    label:       &str,
+    pipeline:    &wgpu::RenderPipeline,
+    bind_groups: &[&wgpu::BindGroup],
+) {
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some(&format!("{} Pass", label)),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops:  wgpu::Operations {
+                load:  wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                store: true
+            },
+        })],
+        depth_stencil_attachment: None,
+    });
+
+    crash_report::set_pipeline(&mut render_pass, pipeline, label);
+
+    for (index, group) in bind_groups.iter().enumerate() {
+        render_pass.set_bind_group(index as u32, group, &[]);
+    }
+
+    render_pass.draw(0..3, 0..1);
+}
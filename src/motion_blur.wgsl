@@ -0,0 +1,124 @@
+// Smears each pixel along the longest motion around it, gathering samples that move across it.
+// Samples are weighed by which of the two is in front and how far each one's own blur reaches,
+// after McGuire et al.'s reconstruction filter, so fast objects blur over a sharp background and a
+// sharp object stays sharp in front of a blurred one.
+
+struct MotionBlurParams {
+    shutter:   f32,
+    samples:   u32,
+    tile_size: u32,
+    reverse_z: u32,
+    near:      f32,
+    far:       f32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: MotionBlurParams;
+@group(0) @binding(1)
+var t_scene: texture_2d<f32>;
+
+@group(1) @binding(0)
+var t_velocity: texture_2d<f32>;
+@group(1) @binding(1)
+var t_depth: texture_depth_2d;
+@group(1) @binding(2)
+var t_neighbors: texture_2d<f32>;
+
+// One triangle that covers the screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    return vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
+
+// Also in motion_blur_tile_max.wgsl
+fn blur(velocity: vec2<f32>, size: vec2<i32>) -> vec2<f32> {
+    let v      = velocity * vec2<f32>(size) * params.shutter;
+    let length = length(v);
+    let limit  = f32(params.tile_size);
+
+    if (length > limit) {
+        return v * (limit / length);
+    }
+
+    return v;
+}
+
+fn linear_depth(depth: f32) -> f32 {
+    let near = params.near;
+    let far  = params.far;
+
+    if (params.reverse_z != 0u) {
+        return near * far / (near + depth * (far - near));
+    }
+
+    return near * far / (far - depth * (far - near));
+}
+
+// 1.0 when `a` is in front of or level with `b`, fading to 0.0 as it falls half a unit behind
+fn in_front(a: f32, b: f32) -> f32 {
+    return clamp((b - a) / 0.5 + 1.0, 0.0, 1.0);
+}
+
+// How much a blur of `extent` pixels covers a point `distance` away, thinning towards its end
+fn cone(distance: f32, extent: f32) -> f32 {
+    return clamp(1.0 - distance / max(extent, 0.0001), 0.0, 1.0);
+}
+
+// Whether a blur of `extent` reaches `distance` at all
+fn cylinder(distance: f32, extent: f32) -> f32 {
+    return 1.0 - smoothstep(0.95 * extent, 1.05 * extent, distance);
+}
+
+// Offsets where samples land from pixel to pixel, so the steps between them turn into noise
+fn noise(coord: vec2<f32>) -> f32 {
+    return fract(52.982919 * fract(dot(coord, vec2<f32>(0.06711056, 0.00583715))));
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let size  = vec2<i32>(textureDimensions(t_velocity));
+    let coord = vec2<i32>(position.xy);
+    let color = textureLoad(t_scene, coord, 0);
+
+    let tiles   = vec2<i32>(textureDimensions(t_neighbors));
+    let longest = textureLoad(t_neighbors, min(coord / i32(params.tile_size), tiles - 1), 0).xy;
+
+    // Nothing nearby moves far enough to see
+    if (length(longest) < 0.5) {
+        return color;
+    }
+
+    let vx = length(blur(textureLoad(t_velocity, coord, 0).xy, size));
+    let zx = linear_depth(textureLoad(t_depth, coord, 0));
+
+    // The pixel itself, weighed as if it were one sample spread over its own blur
+    var weight = 1.0 / max(vx, 1.0);
+    var sum    = color.rgb * weight;
+
+    let samples = max(params.samples, 2u);
+    let jitter  = noise(position.xy) - 0.5;
+
+    for (var i = 0u; i < samples; i = i + 1u) {
+        // Spread evenly over the blur's length either side of the pixel
+        let t = mix(-0.5, 0.5, (f32(i) + 0.5 + jitter) / f32(samples));
+
+        let y        = clamp(coord + vec2<i32>(round(longest * t)), vec2<i32>(0), size - 1);
+        let distance = length(longest * t);
+
+        let vy = length(blur(textureLoad(t_velocity, y, 0).xy, size));
+        let zy = linear_depth(textureLoad(t_depth, y, 0));
+
+        // A blurred sample in front smearing over the pixel, the pixel's own blur revealing what's
+        // behind it, and both moving together
+        let w = in_front(zy, zx) * cone(distance, vy)
+              + in_front(zx, zy) * cone(distance, vx)
+              + cylinder(distance, vy) * cylinder(distance, vx) * 2.0;
+
+        weight = weight + w;
+        sum    = sum + textureLoad(t_scene, y, 0).rgb * w;
+    }
+
+    return vec4<f32>(sum / weight, color.a);
+}
@@ -0,0 +1,33 @@
+// Spreads each tile's longest velocity to its neighbours, so pixels blurred by something moving in
+// from the next tile over know to look for it
+
+@group(0) @binding(0)
+var t_tiles: texture_2d<f32>;
+
+// One triangle that covers the screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    return vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let size  = vec2<i32>(textureDimensions(t_tiles));
+    let coord = vec2<i32>(position.xy);
+
+    var longest = vec2<f32>(0.0);
+
+    for (var y = -1; y <= 1; y = y + 1) {
+        for (var x = -1; x <= 1; x = x + 1) {
+            let v = textureLoad(t_tiles, clamp(coord + vec2<i32>(x, y), vec2<i32>(0), size - 1), 0).xy;
+
+            if (dot(v, v) > dot(longest, longest)) {
+                longest = v;
+            }
+        }
+    }
+
+    return vec4<f32>(longest, 0.0, 0.0);
+}
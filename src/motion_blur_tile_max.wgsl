@@ -0,0 +1,59 @@
+// Reduces the velocity buffer to the longest blur in each tile, in pixels
+
+struct MotionBlurParams {
+    shutter:   f32,
+    samples:   u32,
+    tile_size: u32,
+    reverse_z: u32,
+    near:      f32,
+    far:       f32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: MotionBlurParams;
+@group(0) @binding(1)
+var t_velocity: texture_2d<f32>;
+
+// One triangle that covers the screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    return vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
+
+// How far the shutter lets a pixel smear, capped at a tile so the neighbours always cover it. Also
+// in motion_blur.wgsl.
+fn blur(velocity: vec2<f32>, size: vec2<i32>) -> vec2<f32> {
+    let v      = velocity * vec2<f32>(size) * params.shutter;
+    let length = length(v);
+    let limit  = f32(params.tile_size);
+
+    if (length > limit) {
+        return v * (limit / length);
+    }
+
+    return v;
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let size   = vec2<i32>(textureDimensions(t_velocity));
+    let tile   = i32(params.tile_size);
+    let origin = vec2<i32>(position.xy) * tile;
+
+    var longest = vec2<f32>(0.0);
+
+    for (var y = 0; y < tile; y = y + 1) {
+        for (var x = 0; x < tile; x = x + 1) {
+            let coord = min(origin + vec2<i32>(x, y), size - 1);
+            let v     = blur(textureLoad(t_velocity, coord, 0).xy, size);
+
+            if (dot(v, v) > dot(longest, longest)) {
+                longest = v;
+            }
+        }
+    }
+
+    return vec4<f32>(longest, 0.0, 0.0);
+}
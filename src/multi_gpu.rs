@@ -0,0 +1,275 @@
+// Secondary devices on other GPUs for offline work like baking assets or rendering probes, so a
+// long precomputation doesn't hold up the GPU drawing the window. Native only, since the web gets
+// one adapter.
+//
+// Devices can't share resources, so anything crossing between them goes through the CPU: read it
+// back into a `TextureData` on one device and upload that on the other. A typical bake runs on a
+// worker thread with `SecondaryGpu::spawn`, returns `TextureData`, and the app uploads it to its
+// own device once `GpuJob::try_take` has it.
+
+use std::sync::{mpsc, Arc};
+
+use anyhow::{bail, Context, Result};
+use wgpu::util::DeviceExt;
+
+use crate::renderer::GpuContext;
+
+// Every GPU wgpu can see, in the order `SecondaryGpu::with_adapter` takes indices
+pub fn adapters() -> Vec<wgpu::AdapterInfo> {
+    wgpu::Instance::new(wgpu::Backends::all())
+        .enumerate_adapters(wgpu::Backends::all())
+        .map(|adapter| adapter.get_info())
+        .collect()
+}
+
+// A device of its own, with no window or surface. Cloning shares the device.
+#[derive(Clone)]
+pub struct SecondaryGpu {
+    pub info:   wgpu::AdapterInfo,
+    pub device: Arc<wgpu::Device>,
+    pub queue:  Arc<wgpu::Queue>,
+}
+
+impl SecondaryGpu {
+    // The first GPU that isn't the one `ctx` draws with, preferring discrete ones, and skipping
+    // software renderers
+    pub fn new(ctx: &GpuContext) -> Result<Self> {
+        let primary = &ctx.adapter_info;
+        let adapter = wgpu::Instance::new(wgpu::Backends::all())
+            .enumerate_adapters(wgpu::Backends::all())
+            .filter(|adapter| {
+                let info = adapter.get_info();
+
+                info.device_type != wgpu::DeviceType::Cpu
+                    && (info.vendor, info.device) != (primary.vendor, primary.device)
+            })
+            .min_by_key(|adapter| adapter.get_info().device_type != wgpu::DeviceType::DiscreteGpu)
+            .context("There's no other GPU")?;
+
+        Self::from_adapter(adapter)
+    }
+
+    // The GPU at `index` in `adapters()`, which can be the display's own
+    pub fn with_adapter(index: usize) -> Result<Self> {
+        let adapter = wgpu::Instance::new(wgpu::Backends::all())
+            .enumerate_adapters(wgpu::Backends::all())
+            .nth(index)
+            .with_context(|| format!("There's no GPU {}", index))?;
+
+        Self::from_adapter(adapter)
+    }
+
+    fn from_adapter(adapter: wgpu::Adapter) -> Result<Self> {
+        let info = adapter.get_info();
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                features: wgpu::Features::empty(),
+                limits:   wgpu::Limits::default(),
+                label:    Some("Secondary Device"),
+            },
+            None,
+        ))?;
+
+        log::info!("Secondary GPU: {} ({:?})", info.name, info.backend);
+
+        Ok(Self {
+            info,
+            device: Arc::new(device),
+            queue:  Arc::new(queue),
+        })
+    }
+
+    // Runs `work` with this device on its own thread
+    pub fn spawn<T, F>(&self, work: F) -> GpuJob<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&wgpu::Device, &wgpu::Queue) -> T + Send + 'static,
+    {
+        let (sender, result) = mpsc::channel();
+        let gpu              = self.clone();
+
+        std::thread::Builder::new()
+            .name(format!("secondary-gpu-{}", gpu.info.name))
+            .spawn(move || {
+                // Nobody's waiting if the job was dropped
+                let _ = sender.send(work(&gpu.device, &gpu.queue));
+            })
+            .expect("Couldn't start a secondary GPU thread");
+
+        GpuJob { result, done: false }
+    }
+}
+
+// Work running on a secondary GPU
+pub struct GpuJob<T> {
+    result: mpsc::Receiver<T>,
+    done:   bool,
+}
+
+impl<T> GpuJob<T> {
+    // The result once the work is done, only returned the first time. Doesn't block.
+    pub fn try_take(&mut self) -> Option<T> {
+        if self.done {
+            return None;
+        }
+
+        let result = self.result.try_recv().ok();
+
+        self.done = result.is_some();
+        result
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    // Blocks until the work is done. None if it panicked.
+    pub fn wait(self) -> Option<T> {
+        self.result.recv().ok()
+    }
+}
+
+// A texture's first mip level in CPU memory, rows tightly packed and layers one after another
+#[derive(Debug, Clone)]
+pub struct TextureData {
+    pub width:  u32,
+    pub height: u32,
+    pub layers: u32,
+    pub format: wgpu::TextureFormat,
+    pub bytes:  Vec<u8>,
+}
+
+impl TextureData {
+    // `texture` needs `COPY_SRC`. Blocks until the copy is back.
+    pub fn read(
+        device:  &wgpu::Device,
+        queue:   &wgpu::Queue,
+        texture: &wgpu::Texture,
+        width:   u32,
+        height:  u32,
+        layers:  u32,
+        format:  wgpu::TextureFormat,
+    ) -> Result<Self> {
+        let info = format.describe();
+
+        if info.block_dimensions != (1, 1) {
+            bail!("Can't read back compressed {:?} textures", format);
+        }
+
+        let row_bytes  = width * info.block_size as u32;
+        let padded_row = padded_bytes_per_row(row_bytes);
+        let staging    = device.create_buffer(&wgpu::BufferDescriptor {
+            label:              Some("Texture Staging Buffer"),
+            size:               (padded_row * height * layers) as wgpu::BufferAddress,
+            usage:              wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Texture Readback Encoder"),
+        });
+
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &staging,
+                layout: wgpu::ImageDataLayout {
+                    offset:         0,
+                    bytes_per_row:  std::num::NonZeroU32::new(padded_row),
+                    rows_per_image: std::num::NonZeroU32::new(height),
+                },
+            },
+            wgpu::Extent3d { width, height, depth_or_array_layers: layers },
+        );
+
+        queue.submit(std::iter::once(encoder.finish()));
+
+        // Rows are padded out to the copy alignment, which isn't wanted on the other side
+        let mapped    = map_read(device, &staging)?;
+        let mut bytes = Vec::with_capacity((row_bytes * height * layers) as usize);
+
+        for row in mapped.chunks(padded_row as usize) {
+            bytes.extend_from_slice(&row[..row_bytes as usize]);
+        }
+
+        Ok(Self { width, height, layers, format, bytes })
+    }
+
+    // A new texture on `device` holding this data, with `COPY_DST` added to `usage`
+    pub fn upload(&self, device: &wgpu::Device, queue: &wgpu::Queue, usage: wgpu::TextureUsages, label: &str) -> wgpu::Texture {
+        device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label:           Some(label),
+                size:            wgpu::Extent3d {
+                    width:                 self.width,
+                    height:                self.height,
+                    depth_or_array_layers: self.layers,
+                },
+                mip_level_count: 1,
+                sample_count:    1,
+                dimension:       wgpu::TextureDimension::D2,
+                format:          self.format,
+                usage:           usage | wgpu::TextureUsages::COPY_DST,
+            },
+            &self.bytes,
+        )
+    }
+}
+
+// The first `size` bytes of `buffer`, which needs `COPY_SRC`. Blocks until the copy is back.
+pub fn read_buffer(device: &wgpu::Device, queue: &wgpu::Queue, buffer: &wgpu::Buffer, size: wgpu::BufferAddress) -> Result<Vec<u8>> {
+    let staging = device.create_buffer(&wgpu::BufferDescriptor {
+        label:              Some("Buffer Staging Buffer"),
+        size,
+        usage:              wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Buffer Readback Encoder"),
+    });
+
+    encoder.copy_buffer_to_buffer(buffer, 0, &staging, 0, size);
+    queue.submit(std::iter::once(encoder.finish()));
+
+    map_read(device, &staging)
+}
+
+// A copy of `buffer`'s first `size` bytes on another device, with `COPY_DST` added to `usage`
+pub fn copy_buffer(
+    from:   (&wgpu::Device, &wgpu::Queue),
+    buffer: &wgpu::Buffer,
+    size:   wgpu::BufferAddress,
+    to:     &wgpu::Device,
+    usage:  wgpu::BufferUsages,
+    label:  &str,
+) -> Result<wgpu::Buffer> {
+    let bytes = read_buffer(from.0, from.1, buffer, size)?;
+
+    Ok(to.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label:    Some(label),
+        contents: &bytes,
+        usage:    usage | wgpu::BufferUsages::COPY_DST,
+    }))
+}
+
+fn map_read(device: &wgpu::Device, staging: &wgpu::Buffer) -> Result<Vec<u8>> {
+    let slice              = staging.slice(..);
+    let (sender, receiver) = mpsc::channel();
+
+    slice.map_async(wgpu::MapMode::Read, move |result| sender.send(result).unwrap());
+    device.poll(wgpu::Maintain::Wait);
+    receiver.recv()??;
+
+    let bytes = slice.get_mapped_range().to_vec();
+
+    staging.unmap();
+    Ok(bytes)
+}
+
+fn padded_bytes_per_row(bytes: u32) -> u32 {
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+    bytes.div_ceil(align) * align
+}
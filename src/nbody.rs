@@ -0,0 +1,338 @@
+// Gravity between tens of thousands of bodies, every one pulled by every other on the GPU each
+// step. Two disk galaxies start out on course to collide, each a heavy core circled by lighter
+// stars. The bodies live in a pair of buffers, each step reading one and writing the other, and
+// the latest are drawn straight from their buffer as glowing sprites that add up where they
+// crowd together.
+//
+// A step is N² interactions, which makes this a good way to see how much compute the GPU has.
+// Needs compute shaders, so it isn't available on WebGL.
+
+use cgmath::prelude::*;
+use learn_wgpu_derive::VertexLayout;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    bind_group,
+    camera::Camera,
+    compute,
+    crash_report,
+    pass::{Drawable, RenderLayer},
+    renderer::{self, GpuContext},
+    texture::Texture,
+    uniform::{Uniform, UniformBuffer},
+};
+
+// Bodies each workgroup loads at a time and shares, also in nbody.wgsl
+const TILE_SIZE: u32 = 256;
+
+// Share of each galaxy's mass in its core, the rest spread over its stars
+const CORE_MASS_SHARE: f32 = 0.8;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+#[vertex(instance)]
+struct Body {
+    // xyz and mass
+    #[location(0)]
+    position: [f32; 4],
+    #[location(1)]
+    velocity: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct SimulationParams {
+    count:     u32,
+    time_step: f32,
+    gravity:   f32,
+    softening: f32,
+    damping:   f32,
+    _padding:  [f32; 3],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct SpriteCamera {
+    view_proj: [[f32; 4]; 4],
+    right:     [f32; 3],
+    size:      f32,
+    up:        [f32; 3],
+    max_speed: f32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct NBodySettings {
+    pub count:       u32,
+    pub gravity:     f32,
+    // Distance under which pulls stop growing, so close passes don't fling bodies off
+    pub softening:   f32,
+    // Share of its velocity a body keeps each step, 1.0 to lose none
+    pub damping:     f32,
+    pub time_step:   f32,
+    // World units across each body's sprite
+    pub sprite_size: f32,
+    // Mass and radius of each galaxy
+    pub galaxy_mass: f32,
+    pub radius:      f32,
+}
+
+impl Default for NBodySettings {
+    fn default() -> Self {
+        Self {
+            count:       16384,
+            gravity:     1.0,
+            softening:   0.5,
+            damping:     1.0,
+            time_step:   0.005,
+            sprite_size: 0.6,
+            galaxy_mass: 2000.0,
+            radius:      30.0,
+        }
+    }
+}
+
+pub struct NBody {
+    // Everything but `count` can change between steps. Changing the galaxies' mass or radius
+    // waits for `reset`.
+    pub settings:      NBodySettings,
+    count:             u32,
+    params:            UniformBuffer<SimulationParams>,
+    camera:            UniformBuffer<SpriteCamera>,
+    bodies:            [wgpu::Buffer; 2],
+    // The first reads the first buffer and writes the second, the other the other way round
+    step_bind_groups:  [wgpu::BindGroup; 2],
+    camera_bind_group: wgpu::BindGroup,
+    step_pipeline:     wgpu::ComputePipeline,
+    render_pipeline:   wgpu::RenderPipeline,
+    // Which buffer holds the latest bodies
+    current:           usize,
+}
+
+impl NBody {
+    pub fn new(ctx: &GpuContext, settings: NBodySettings) -> Self {
+        let device = &ctx.device;
+        let count  = settings.count.max(1);
+        let start  = galaxies(&settings, count);
+
+        let bodies = [0, 1].map(|i| device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label:    Some(if i == 0 { "N-Body Buffer A" } else { "N-Body Buffer B" }),
+            contents: bytemuck::cast_slice(&start),
+            usage:    wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        }));
+
+        let params = UniformBuffer::new(device, "N-Body Params Buffer");
+        let camera = UniformBuffer::new(device, "N-Body Camera Buffer");
+
+        let step_layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::COMPUTE)
+            .storage(wgpu::ShaderStages::COMPUTE, true)
+            .storage(wgpu::ShaderStages::COMPUTE, false)
+            .build(device, "nbody_step_bind_group_layout");
+
+        let step_bind_groups = [(0, 1), (1, 0)].map(|(src, dst)| bind_group::BindGroupBuilder::new(&step_layout)
+            .uniform(params.buffer())
+            .storage(&bodies[src])
+            .storage(&bodies[dst])
+            .build(device, "nbody_step_bind_group"));
+
+        let step_pipeline = compute::create_pipeline(device, &step_layout, include_str!("nbody.wgsl"), "N-Body Step");
+
+        let camera_layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::VERTEX)
+            .build(device, "nbody_camera_bind_group_layout");
+
+        let camera_bind_group = bind_group::BindGroupBuilder::new(&camera_layout)
+            .uniform(camera.buffer())
+            .build(device, "nbody_camera_bind_group");
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("N-Body Sprite Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("nbody_render.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("N-Body Sprite Pipeline Layout"),
+            bind_group_layouts:   &[&camera_layout],
+            push_constant_ranges: &[],
+        });
+
+        // Added together in any order, tested against the scene's depth without adding to it
+        let additive = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation:  wgpu::BlendOperation::Add,
+        };
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label:    Some("N-Body Sprite Pipeline"),
+            layout:   Some(&pipeline_layout),
+            vertex:   wgpu::VertexState {
+                module:      &shader,
+                entry_point: "vs_main",
+                buffers:     &[Body::layout()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module:      &shader,
+                entry_point: "fs_main",
+                targets:     &[Some(wgpu::ColorTargetState {
+                    format:     ctx.config.format,
+                    blend:      Some(wgpu::BlendState { color: additive, alpha: additive }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive:     wgpu::PrimitiveState::default(),
+            depth_stencil: Some(renderer::depth_state(Texture::DEPTH_FORMAT, ctx.depth_mode.compare(), false)),
+            multisample:   wgpu::MultisampleState::default(),
+            multiview:     None,
+        });
+
+        Self {
+            settings,
+            count,
+            params,
+            camera,
+            bodies,
+            step_bind_groups,
+            camera_bind_group,
+            step_pipeline,
+            render_pipeline,
+            current: 0,
+        }
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    // Puts the galaxies back where they started
+    pub fn reset(&mut self, queue: &wgpu::Queue) {
+        queue.write_buffer(&self.bodies[0], 0, bytemuck::cast_slice(&galaxies(&self.settings, self.count)));
+        self.current = 0;
+    }
+
+    // Records `steps` steps of the simulation
+    pub fn step(&mut self, ctx: &GpuContext, encoder: &mut wgpu::CommandEncoder, steps: u32) {
+        let settings = &self.settings;
+
+        self.params.write(&ctx.queue, &SimulationParams {
+            count:     self.count,
+            time_step: settings.time_step,
+            gravity:   settings.gravity,
+            softening: settings.softening.max(f32::EPSILON),
+            damping:   settings.damping,
+            _padding:  [0.0; 3],
+        });
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("N-Body Step Pass"),
+        });
+
+        crash_report::set_compute_pipeline(&mut compute_pass, &self.step_pipeline, "N-Body Step");
+
+        for _ in 0..steps {
+            compute_pass.set_bind_group(0, &self.step_bind_groups[self.current], &[]);
+            compute_pass.dispatch_workgroups(self.count.div_ceil(TILE_SIZE), 1, 1);
+            self.current = 1 - self.current;
+        }
+    }
+
+    // Faces the sprites toward `camera`
+    pub fn prepare(&self, queue: &wgpu::Queue, camera: &Camera) {
+        let forward = (camera.target - camera.eye).normalize();
+        let right   = forward.cross(camera.up).normalize();
+        let up      = right.cross(forward);
+
+        // Roughly the speed of a star on the edge of a galaxy
+        let max_speed = (self.settings.gravity * self.settings.galaxy_mass / self.settings.radius).sqrt() * 2.0;
+
+        self.camera.write(queue, &SpriteCamera {
+            view_proj: camera.build_view_projections_matrix().into(),
+            right:     right.into(),
+            size:      self.settings.sprite_size,
+            up:        up.into(),
+            max_speed: max_speed.max(f32::EPSILON),
+        });
+    }
+}
+
+impl Drawable for NBody {
+    fn draw<'a>(&'a self, layer: RenderLayer, render_pass: &mut wgpu::RenderPass<'a>) {
+        if layer != RenderLayer::WorldTransparent {
+            return;
+        }
+
+        crash_report::set_pipeline(render_pass, &self.render_pipeline, "N-Body Sprite Pipeline");
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.bodies[self.current].slice(..));
+        render_pass.draw(0..6, 0..self.count);
+    }
+}
+
+// A value from 0.0 to 1.0 that's the same for the same `seed`
+fn random(seed: u32) -> f32 {
+    let hash = seed.wrapping_mul(73_856_093) ^ seed.rotate_left(13).wrapping_mul(19_349_663);
+
+    (hash.wrapping_mul(2_654_435_761) >> 8) as f32 / (1 << 24) as f32
+}
+
+// Two galaxies sharing `count` bodies, falling toward each other on a glancing course, their disks
+// tilted against each other
+fn galaxies(settings: &NBodySettings, count: u32) -> Vec<Body> {
+    let separation = settings.radius * 3.0;
+    let approach   = (settings.gravity * settings.galaxy_mass * 2.0 / separation).sqrt() * 0.5;
+
+    let first = count / 2;
+    let mut bodies = Vec::with_capacity(count as usize);
+
+    galaxy(settings, first, 0, cgmath::Vector3::new(-separation * 0.5, 0.0, 0.0), cgmath::Vector3::new(0.0, 0.0, approach), cgmath::Vector3::unit_y(), &mut bodies);
+    galaxy(settings, count - first, first, cgmath::Vector3::new(separation * 0.5, 0.0, 0.0), cgmath::Vector3::new(0.0, 0.0, -approach), cgmath::Vector3::new(0.0, 1.0, 0.6).normalize(), &mut bodies);
+
+    bodies
+}
+
+// A core and `count - 1` stars in a disk around `axis`, on circular orbits around what's inside
+// them. `seed` keeps each galaxy's stars different.
+fn galaxy(
+    settings: &NBodySettings,
+    count:    u32,
+    seed:     u32,
+    center:   cgmath::Vector3<f32>,
+    velocity: cgmath::Vector3<f32>,
+    axis:     cgmath::Vector3<f32>,
+    bodies:   &mut Vec<Body>,
+) {
+    if count == 0 {
+        return;
+    }
+
+    let core_mass = settings.galaxy_mass * CORE_MASS_SHARE;
+    let star_mass = settings.galaxy_mass * (1.0 - CORE_MASS_SHARE) / count.max(2) as f32;
+
+    bodies.push(Body { position: center.extend(core_mass).into(), velocity: velocity.extend(0.0).into() });
+
+    // Two directions across the disk
+    let across = if axis.x.abs() < 0.9 { cgmath::Vector3::unit_x() } else { cgmath::Vector3::unit_z() };
+    let u      = axis.cross(across).normalize();
+    let v      = axis.cross(u);
+    let inner  = settings.radius * 0.1;
+
+    for i in 1..count {
+        let seed  = (seed + i) * 3;
+        // Crowded toward the middle like a real disk
+        let r     = inner + (settings.radius - inner) * random(seed).powi(2);
+        let angle = random(seed + 1) * std::f32::consts::TAU;
+        let dir   = u * angle.cos() + v * angle.sin();
+        let lift  = (random(seed + 2) - 0.5) * settings.radius * 0.04;
+
+        // Stars closer in than this one count toward its pull, as if they sat at the core
+        let inside = core_mass + settings.galaxy_mass * (1.0 - CORE_MASS_SHARE) * ((r - inner) / (settings.radius - inner)).sqrt();
+        let soft   = r * r + settings.softening * settings.softening;
+        let speed  = (settings.gravity * inside * r * r / (soft * soft.sqrt())).sqrt();
+        let orbit  = axis.cross(dir) * speed;
+
+        bodies.push(Body {
+            position: (center + dir * r + axis * lift).extend(star_mass).into(),
+            velocity: (velocity + orbit).extend(0.0).into(),
+        });
+    }
+}
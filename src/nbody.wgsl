@@ -0,0 +1,79 @@
+// One step of the N-body simulation, see nbody.rs. Every body is pulled by every other, so the
+// bodies are read a tile at a time into workgroup memory, where all 256 invocations of a group
+// share them, rather than each invocation reading all of them from the buffer itself.
+
+struct Body {
+    // xyz and mass
+    position: vec4<f32>,
+    velocity: vec4<f32>,
+}
+
+struct SimulationParams {
+    count:     u32,
+    time_step: f32,
+    gravity:   f32,
+    softening: f32,
+    damping:   f32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: SimulationParams;
+@group(0) @binding(1)
+var<storage, read> src: array<Body>;
+@group(0) @binding(2)
+var<storage, read_write> dst: array<Body>;
+
+// Also the workgroup size, and TILE_SIZE in nbody.rs
+var<workgroup> tile: array<vec4<f32>, 256>;
+
+@compute @workgroup_size(256)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>, @builtin(local_invocation_index) local: u32) {
+    let index = id.x;
+    let alive = index < params.count;
+
+    var body: Body;
+
+    if (alive) {
+        body = src[index];
+    }
+
+    let softening    = params.softening * params.softening;
+    var acceleration = vec3<f32>(0.0);
+    let tiles        = (params.count + 255u) / 256u;
+
+    // Every invocation takes part in loading every tile, even past the last body, so the barriers
+    // are reached by the whole group
+    for (var t = 0u; t < tiles; t = t + 1u) {
+        let other = t * 256u + local;
+
+        if (other < params.count) {
+            tile[local] = src[other].position;
+        } else {
+            // Massless, so it pulls on nothing
+            tile[local] = vec4<f32>(0.0);
+        }
+
+        workgroupBarrier();
+
+        for (var i = 0u; i < 256u; i = i + 1u) {
+            let offset = tile[i].xyz - body.position.xyz;
+            // Softened so close passes don't fling bodies away, which also makes a body's pull on
+            // itself zero
+            let distance2 = dot(offset, offset) + softening;
+            let inverse   = inverseSqrt(distance2);
+
+            acceleration = acceleration + offset * (tile[i].w * inverse * inverse * inverse);
+        }
+
+        workgroupBarrier();
+    }
+
+    if (!alive) {
+        return;
+    }
+
+    // Semi-implicit Euler, which keeps orbits from spiraling out
+    let velocity = (body.velocity.xyz + acceleration * params.gravity * params.time_step) * params.damping;
+
+    dst[index] = Body(vec4<f32>(body.position.xyz + velocity * params.time_step, body.position.w), vec4<f32>(velocity, 0.0));
+}
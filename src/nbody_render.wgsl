@@ -0,0 +1,57 @@
+// The N-body simulation's bodies as glowing point sprites, added together so dense clusters burn
+// brighter. Slow bodies are a deep orange and fast ones a pale blue.
+
+struct SpriteCamera {
+    view_proj: mat4x4<f32>,
+    // The camera's axes, so sprites always face it
+    right:     vec3<f32>,
+    // World units across a sprite
+    size:      f32,
+    up:        vec3<f32>,
+    // Speed at which bodies are fully blue
+    max_speed: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> camera: SpriteCamera;
+
+struct BodyInput {
+    @location(0) position: vec4<f32>,
+    @location(1) velocity: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // From -1.0 to 1.0 across the sprite
+    @location(0) corner:              vec2<f32>,
+    @location(1) color:               vec3<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, body: BodyInput) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, -1.0), vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, 1.0), vec2<f32>(-1.0, 1.0),
+    );
+
+    let corner = corners[index];
+    let world  = body.position.xyz + (camera.right * corner.x + camera.up * corner.y) * camera.size * 0.5;
+    let speed  = clamp(length(body.velocity.xyz) / camera.max_speed, 0.0, 1.0);
+
+    var out: VertexOutput;
+
+    out.corner        = corner;
+    out.color         = mix(vec3<f32>(1.0, 0.35, 0.08), vec3<f32>(0.5, 0.7, 1.0), speed) * 0.15;
+    out.clip_position = camera.view_proj * vec4<f32>(world, 1.0);
+
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // A soft Gaussian falloff, cut off at the sprite's edge
+    let r2      = dot(in.corner, in.corner);
+    let falloff = select(exp(-r2 * 4.0), 0.0, r2 > 1.0);
+
+    return vec4<f32>(in.color * falloff, 1.0);
+}
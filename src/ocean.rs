@@ -0,0 +1,403 @@
+// Deep water waves simulated in the frequency domain, after Tessendorf. A square patch's spectrum
+// of waves is drawn once from a Phillips or JONSWAP spectrum for the wind, and every frame each wave
+// is turned on to the current time and an inverse FFT brings the patch back to heights and the
+// horizontal displacement that sharpens the crests. A last pass writes displacement and normal
+// maps from those, with foam where the surface is pinched closest to folding over.
+//
+// The patch tiles seamlessly, so the surface repeats it across a grid as large as the ocean's
+// extent. Nothing is mipped, so far off waves alias rather than smoothing out.
+//
+// Needs compute shaders, so it isn't available on WebGL.
+
+use std::time::Duration;
+
+use learn_wgpu_derive::VertexLayout;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    bind_group,
+    compute::{self, MipChain},
+    crash_report,
+    renderer::{self, GpuContext},
+    texture::Texture,
+    uniform::{Uniform, UniformBuffer},
+};
+
+// Texels along each side of the simulated patch, a power of two for the FFT
+const RESOLUTION: u32 = 256;
+
+// Vertices along each side of the surface's grid
+const SURFACE_RESOLUTION: u32 = 192;
+
+// Holds two complex numbers a texel through the FFT
+const SPECTRUM_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Float;
+
+const MAP_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct OceanParams {
+    origin:     [f32; 3],
+    extent:     f32,
+    wind:       [f32; 2],
+    size:       f32,
+    amplitude:  f32,
+    choppiness: f32,
+    time:       f32,
+    fetch:      f32,
+    spectrum:   u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct FftStage {
+    stage:    u32,
+    vertical: u32,
+    _padding: [u32; 2],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+struct SurfaceVertex {
+    // From 0.0 to 1.0 across the extent
+    #[location(0)]
+    grid: [f32; 2],
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Spectrum {
+    // Fully developed seas, where the wind has blown for long enough over enough water
+    Phillips,
+    // Seas still growing over a limited fetch, with a sharper peak
+    Jonswap,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct OceanSettings {
+    pub spectrum:       Spectrum,
+    // Units a second, with the waves' lengths in the same units
+    pub wind_speed:     f32,
+    // Radians from +x towards +z
+    pub wind_direction: f32,
+    // How far the wind has blown over open water, for JONSWAP
+    pub fetch:          f32,
+    // Scales the spectrum's energy, so heights grow with its square root
+    pub amplitude:      f32,
+    // How far the horizontal displacement pulls points towards the crests
+    pub choppiness:     f32,
+    // Across the simulated patch, which repeats over the surface
+    pub size:           f32,
+    // Middle of the surface at rest, and its width along both x and z
+    pub origin:         cgmath::Point3<f32>,
+    pub extent:         f32,
+}
+
+impl Default for OceanSettings {
+    fn default() -> Self {
+        Self {
+            spectrum:       Spectrum::Phillips,
+            wind_speed:     8.0,
+            wind_direction: 0.0,
+            fetch:          100_000.0,
+            amplitude:      1.0,
+            choppiness:     1.2,
+            size:           64.0,
+            origin:         cgmath::Point3::new(0.0, 0.0, 0.0),
+            extent:         128.0,
+        }
+    }
+}
+
+pub struct Ocean {
+    pub settings:      OceanSettings,
+    time:              f32,
+    params:            UniformBuffer<OceanParams>,
+    spectrum_pipeline: wgpu::ComputePipeline,
+    spectrum_group:    wgpu::BindGroup,
+    waves_pipeline:    wgpu::ComputePipeline,
+    waves_group:       wgpu::BindGroup,
+    fft_pipeline:      wgpu::ComputePipeline,
+    // One per pass, alternating which of the two textures is read
+    fft_groups:        Vec<wgpu::BindGroup>,
+    resolve_pipeline:  wgpu::ComputePipeline,
+    resolve_group:     wgpu::BindGroup,
+    surface_pipeline:  wgpu::RenderPipeline,
+    surface_group:     wgpu::BindGroup,
+    vertices:          wgpu::Buffer,
+    indices:           wgpu::Buffer,
+    index_count:       u32,
+    // What the starting spectrum was drawn from, so it's only drawn again when they change
+    generated:         Option<OceanSettings>,
+    // Kept alive for the bind groups
+    _stages:           Vec<UniformBuffer<FftStage>>,
+    _textures:         Vec<MipChain>,
+}
+
+impl Ocean {
+    // The shader the surface is drawn with. The simulation's are compute shaders of its own.
+    pub fn surface_shader_source() -> &'static str {
+        concat!(include_str!("camera.wgsl"), include_str!("lights.wgsl"), include_str!("ocean.wgsl"), include_str!("ocean_surface.wgsl"))
+    }
+
+    // Drawn with the scene's camera at group 1 and lights at group 2
+    pub fn new(
+        ctx:           &GpuContext,
+        camera_layout: &wgpu::BindGroupLayout,
+        lights_layout: &wgpu::BindGroupLayout,
+        settings:      OceanSettings,
+    ) -> Self {
+        let device = &ctx.device;
+        let params = UniformBuffer::new(device, "Ocean Params Buffer");
+
+        let h0           = MipChain::new(device, SPECTRUM_FORMAT, RESOLUTION, RESOLUTION, 1, "Ocean Spectrum Texture");
+        let waves        = MipChain::new(device, SPECTRUM_FORMAT, RESOLUTION, RESOLUTION, 1, "Ocean Waves Texture");
+        let scratch      = MipChain::new(device, SPECTRUM_FORMAT, RESOLUTION, RESOLUTION, 1, "Ocean FFT Scratch Texture");
+        let displacement = MipChain::new(device, MAP_FORMAT, RESOLUTION, RESOLUTION, 1, "Ocean Displacement Texture");
+        let normals      = MipChain::new(device, MAP_FORMAT, RESOLUTION, RESOLUTION, 1, "Ocean Normal Texture");
+
+        let spectrum_layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::COMPUTE)
+            .storage_texture(wgpu::ShaderStages::COMPUTE, SPECTRUM_FORMAT, wgpu::StorageTextureAccess::WriteOnly, wgpu::TextureViewDimension::D2)
+            .build(device, "ocean_spectrum_bind_group_layout");
+
+        let spectrum_pipeline = compute::create_pipeline(
+            device,
+            &spectrum_layout,
+            concat!(include_str!("ocean.wgsl"), include_str!("ocean_spectrum.wgsl")),
+            "Ocean Spectrum Pass",
+        );
+
+        let spectrum_group = bind_group::BindGroupBuilder::new(&spectrum_layout)
+            .uniform(params.buffer())
+            .texture(&h0.view)
+            .build(device, "ocean_spectrum_bind_group");
+
+        // The waves and FFT passes both read one spectrum texture and write another
+        let step_layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::COMPUTE)
+            .unfilterable_texture(wgpu::ShaderStages::COMPUTE, wgpu::TextureViewDimension::D2)
+            .storage_texture(wgpu::ShaderStages::COMPUTE, SPECTRUM_FORMAT, wgpu::StorageTextureAccess::WriteOnly, wgpu::TextureViewDimension::D2)
+            .build(device, "ocean_step_bind_group_layout");
+
+        let waves_pipeline = compute::create_pipeline(
+            device,
+            &step_layout,
+            concat!(include_str!("ocean.wgsl"), include_str!("ocean_waves.wgsl")),
+            "Ocean Waves Pass",
+        );
+
+        let waves_group = bind_group::BindGroupBuilder::new(&step_layout)
+            .uniform(params.buffer())
+            .texture(&h0.view)
+            .texture(&waves.view)
+            .build(device, "ocean_waves_bind_group");
+
+        let fft_pipeline = compute::create_pipeline(device, &step_layout, include_str!("ocean_fft.wgsl"), "Ocean FFT Pass");
+
+        // Rows first and then columns, a pass for each bit of the resolution. That's an even
+        // number of passes, so the result ends up back in `waves`.
+        let bits   = RESOLUTION.trailing_zeros();
+        let stages = [0, 1]
+            .into_iter()
+            .flat_map(|vertical| (0..bits).map(move |stage| FftStage { stage, vertical, _padding: [0; 2] }))
+            .map(|stage| UniformBuffer::with_contents(device, "Ocean FFT Stage Buffer", &stage))
+            .collect::<Vec<_>>();
+
+        let fft_groups = stages.iter()
+            .enumerate()
+            .map(|(i, stage)| {
+                let (src, dst) = if i % 2 == 0 { (&waves, &scratch) } else { (&scratch, &waves) };
+
+                bind_group::BindGroupBuilder::new(&step_layout)
+                    .uniform(stage.buffer())
+                    .texture(&src.view)
+                    .texture(&dst.view)
+                    .build(device, "ocean_fft_bind_group")
+            })
+            .collect();
+
+        let resolve_layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::COMPUTE)
+            .unfilterable_texture(wgpu::ShaderStages::COMPUTE, wgpu::TextureViewDimension::D2)
+            .storage_texture(wgpu::ShaderStages::COMPUTE, MAP_FORMAT, wgpu::StorageTextureAccess::WriteOnly, wgpu::TextureViewDimension::D2)
+            .storage_texture(wgpu::ShaderStages::COMPUTE, MAP_FORMAT, wgpu::StorageTextureAccess::WriteOnly, wgpu::TextureViewDimension::D2)
+            .build(device, "ocean_resolve_bind_group_layout");
+
+        let resolve_pipeline = compute::create_pipeline(
+            device,
+            &resolve_layout,
+            concat!(include_str!("ocean.wgsl"), include_str!("ocean_resolve.wgsl")),
+            "Ocean Resolve Pass",
+        );
+
+        let resolve_group = bind_group::BindGroupBuilder::new(&resolve_layout)
+            .uniform(params.buffer())
+            .texture(&waves.view)
+            .texture(&displacement.view)
+            .texture(&normals.view)
+            .build(device, "ocean_resolve_bind_group");
+
+        // Repeating, so the patch tiles across the surface
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label:          Some("Ocean Sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            mag_filter:     wgpu::FilterMode::Linear,
+            min_filter:     wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let surface_layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT)
+            .texture(wgpu::ShaderStages::VERTEX, wgpu::TextureViewDimension::D2)
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .sampler(wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT)
+            .build(device, "ocean_surface_bind_group_layout");
+
+        let surface_group = bind_group::BindGroupBuilder::new(&surface_layout)
+            .uniform(params.buffer())
+            .texture(&displacement.view)
+            .texture(&normals.view)
+            .sampler(&sampler)
+            .build(device, "ocean_surface_bind_group");
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Ocean Surface Shader"),
+            source: wgpu::ShaderSource::Wgsl(Self::surface_shader_source().into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Ocean Surface Pipeline Layout"),
+            bind_group_layouts:   &[&surface_layout, camera_layout, lights_layout],
+            push_constant_ranges: &[],
+        });
+
+        let surface_pipeline = renderer::create_render_pipeline(
+            device,
+            &pipeline_layout,
+            ctx.config.format,
+            Some(renderer::depth_state(Texture::DEPTH_FORMAT, ctx.depth_mode.compare(), true)),
+            &[SurfaceVertex::layout()],
+            &shader,
+            "Ocean Surface Pipeline",
+        );
+
+        let (surface_vertices, surface_indices) = surface_mesh();
+
+        let vertices = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label:    Some("Ocean Vertex Buffer"),
+            contents: bytemuck::cast_slice(&surface_vertices),
+            usage:    wgpu::BufferUsages::VERTEX,
+        });
+
+        let indices = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label:    Some("Ocean Index Buffer"),
+            contents: bytemuck::cast_slice(&surface_indices),
+            usage:    wgpu::BufferUsages::INDEX,
+        });
+
+        Self {
+            settings,
+            time: 0.0,
+            params,
+            spectrum_pipeline,
+            spectrum_group,
+            waves_pipeline,
+            waves_group,
+            fft_pipeline,
+            fft_groups,
+            resolve_pipeline,
+            resolve_group,
+            surface_pipeline,
+            surface_group,
+            vertices,
+            indices,
+            index_count: surface_indices.len() as u32,
+            generated:   None,
+            _stages:     stages,
+            _textures:   vec![h0, waves, scratch, displacement, normals],
+        }
+    }
+
+    pub fn update(&mut self, dt: Duration) {
+        self.time += dt.as_secs_f32();
+    }
+
+    // Brings the displacement and normal maps up to the current time, drawing the starting
+    // spectrum again first if the settings have changed
+    pub fn simulate(&mut self, ctx: &GpuContext, encoder: &mut wgpu::CommandEncoder) {
+        let settings = self.settings;
+        let (sin, cos) = settings.wind_direction.sin_cos();
+
+        self.params.write(&ctx.queue, &OceanParams {
+            origin:     settings.origin.into(),
+            extent:     settings.extent,
+            wind:       [cos * settings.wind_speed, sin * settings.wind_speed],
+            size:       settings.size,
+            amplitude:  settings.amplitude,
+            choppiness: settings.choppiness,
+            time:       self.time,
+            fetch:      settings.fetch,
+            spectrum:   match settings.spectrum {
+                Spectrum::Phillips => 0,
+                Spectrum::Jonswap  => 1,
+            },
+        });
+
+        let groups = compute::workgroups(RESOLUTION);
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Ocean Pass"),
+        });
+
+        if self.generated != Some(settings) {
+            crash_report::set_compute_pipeline(&mut compute_pass, &self.spectrum_pipeline, "Ocean Spectrum Pass");
+            compute_pass.set_bind_group(0, &self.spectrum_group, &[]);
+            compute_pass.dispatch_workgroups(groups, groups, 1);
+
+            self.generated = Some(settings);
+        }
+
+        crash_report::set_compute_pipeline(&mut compute_pass, &self.waves_pipeline, "Ocean Waves Pass");
+        compute_pass.set_bind_group(0, &self.waves_group, &[]);
+        compute_pass.dispatch_workgroups(groups, groups, 1);
+
+        crash_report::set_compute_pipeline(&mut compute_pass, &self.fft_pipeline, "Ocean FFT Pass");
+
+        for group in &self.fft_groups {
+            compute_pass.set_bind_group(0, group, &[]);
+            compute_pass.dispatch_workgroups(groups, groups, 1);
+        }
+
+        crash_report::set_compute_pipeline(&mut compute_pass, &self.resolve_pipeline, "Ocean Resolve Pass");
+        compute_pass.set_bind_group(0, &self.resolve_group, &[]);
+        compute_pass.dispatch_workgroups(groups, groups, 1);
+    }
+
+    // Expects the lights to be bound at group 2 already, as the scene's draws leave them
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, camera_bind_group: &'a wgpu::BindGroup) {
+        crash_report::set_pipeline(render_pass, &self.surface_pipeline, "Ocean Surface Pipeline");
+        render_pass.set_bind_group(0, &self.surface_group, &[]);
+        render_pass.set_bind_group(1, camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertices.slice(..));
+        render_pass.set_index_buffer(self.indices.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.draw_indexed(0..self.index_count, 0, 0..1);
+    }
+}
+
+// A grid of `SURFACE_RESOLUTION` vertices a side, with its triangles facing up
+fn surface_mesh() -> (Vec<SurfaceVertex>, Vec<u32>) {
+    let side = SURFACE_RESOLUTION;
+    let last = (side - 1) as f32;
+
+    let vertices = (0..side * side)
+        .map(|i| SurfaceVertex { grid: [(i % side) as f32 / last, (i / side) as f32 / last] })
+        .collect();
+
+    let indices = (0..side - 1)
+        .flat_map(|y| (0..side - 1).map(move |x| y * side + x))
+        .flat_map(|i| [i, i + side + 1, i + 1, i, i + side, i + side + 1])
+        .collect();
+
+    (vertices, indices)
+}
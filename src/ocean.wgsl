@@ -0,0 +1,34 @@
+// Shared by the ocean's passes and its surface, see ocean.rs. Gets the pass's own shader appended.
+
+struct OceanParams {
+    origin:     vec3<f32>,
+    extent:     f32,
+    // Along the wind, as long as its speed
+    wind:       vec2<f32>,
+    size:       f32,
+    amplitude:  f32,
+    choppiness: f32,
+    time:       f32,
+    fetch:      f32,
+    // 0 for Phillips, 1 for JONSWAP
+    spectrum:   u32,
+}
+
+@group(0) @binding(0)
+var<uniform> ocean: OceanParams;
+
+// Which wave a texel of the spectrum holds, in whole waves across the patch. Texels past the
+// middle are the negative frequencies, where the inverse FFT expects them.
+fn wave_number(texel: vec2<u32>, resolution: u32) -> vec2<f32> {
+    let n = vec2<i32>(texel);
+
+    return vec2<f32>(select(n, n - vec2<i32>(i32(resolution)), n >= vec2<i32>(i32(resolution / 2u))));
+}
+
+fn wave_vector(texel: vec2<u32>, resolution: u32) -> vec2<f32> {
+    return wave_number(texel, resolution) * 6.2831853 / ocean.size;
+}
+
+fn complex_mul(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(a.x * b.x - a.y * b.y, a.x * b.y + a.y * b.x);
+}
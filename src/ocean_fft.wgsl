@@ -0,0 +1,61 @@
+// One radix-2 Stockham pass of the ocean's inverse FFT, see ocean.rs. Every texel holds two complex
+// numbers that are transformed alongside each other, along rows or along columns. After a pass for
+// each bit of the resolution the values are back in order, so nothing needs bit reversing.
+
+struct FftStage {
+    stage:    u32,
+    vertical: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> fft: FftStage;
+@group(0) @binding(1)
+var src: texture_2d<f32>;
+@group(0) @binding(2)
+var dst: texture_storage_2d<rgba32float, write>;
+
+fn complex_mul(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(a.x * b.x - a.y * b.y, a.x * b.y + a.y * b.x);
+}
+
+// The texel `i` along row `row`, or down column `row` in vertical passes
+fn texel(i: u32, row: u32) -> vec2<i32> {
+    if (fft.vertical != 0u) {
+        return vec2<i32>(i32(row), i32(i));
+    }
+
+    return vec2<i32>(i32(i), i32(row));
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let resolution = u32(textureDimensions(dst).x);
+
+    if (id.x >= resolution || id.y >= resolution) {
+        return;
+    }
+
+    var i   = id.x;
+    var row = id.y;
+
+    if (fft.vertical != 0u) {
+        i   = id.y;
+        row = id.x;
+    }
+
+    // Pairs of transforms `span` long combine into ones twice as long, each output reading the
+    // same two inputs as its partner half way along
+    let span = 1u << fft.stage;
+    let k    = i & (span - 1u);
+    let j    = (i / (2u * span)) * span + k;
+
+    // Positive for the inverse transform
+    let angle   = 3.14159265 * f32(k) / f32(span);
+    let twiddle = vec2<f32>(cos(angle), sin(angle));
+
+    let a = textureLoad(src, texel(j, row), 0);
+    let b = textureLoad(src, texel(j + resolution / 2u, row), 0);
+    let w = vec4<f32>(complex_mul(twiddle, b.xy), complex_mul(twiddle, b.zw));
+
+    textureStore(dst, texel(i, row), select(a - w, a + w, (i & span) == 0u));
+}
@@ -0,0 +1,50 @@
+// Turns the inverse FFT's output into the textures the surface samples, see ocean.rs: how far
+// each point of the patch moves, and the normal and foam where it ends up.
+
+@group(0) @binding(1)
+var waves: texture_2d<f32>;
+@group(0) @binding(2)
+var displacement: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(3)
+var normals: texture_storage_2d<rgba16float, write>;
+
+// Where the point at a texel moves, wrapping around the patch's edges
+fn displaced(coord: vec2<i32>, resolution: i32) -> vec3<f32> {
+    let value = textureLoad(waves, (coord + vec2<i32>(resolution)) % vec2<i32>(resolution), 0);
+
+    // The height is the real part of the first pair and the x displacement its imaginary part
+    return vec3<f32>(value.y * ocean.choppiness, value.x, value.z * ocean.choppiness);
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let resolution = i32(textureDimensions(waves).x);
+    let coord      = vec2<i32>(id.xy);
+
+    if (coord.x >= resolution || coord.y >= resolution) {
+        return;
+    }
+
+    let spacing = ocean.size / f32(resolution);
+    let left    = displaced(coord - vec2<i32>(1, 0), resolution);
+    let right   = displaced(coord + vec2<i32>(1, 0), resolution);
+    let back    = displaced(coord - vec2<i32>(0, 1), resolution);
+    let front   = displaced(coord + vec2<i32>(0, 1), resolution);
+
+    // Across the displaced surface two texels each way
+    let along_x = right - left + vec3<f32>(2.0 * spacing, 0.0, 0.0);
+    let along_z = front - back + vec3<f32>(0.0, 0.0, 2.0 * spacing);
+    let normal  = normalize(cross(along_z, along_x));
+
+    // How much the displacement squeezes the surface together, down to zero or below where it
+    // folds over itself. Crests that close to folding break into foam.
+    let jxx      = along_x.x / (2.0 * spacing);
+    let jzz      = along_z.z / (2.0 * spacing);
+    let jxz      = along_z.x / (2.0 * spacing);
+    let jzx      = along_x.z / (2.0 * spacing);
+    let jacobian = jxx * jzz - jxz * jzx;
+    let foam     = clamp((0.6 - jacobian) * 2.0, 0.0, 1.0);
+
+    textureStore(displacement, coord, vec4<f32>(displaced(coord, resolution), 0.0));
+    textureStore(normals, coord, vec4<f32>(normal, foam));
+}
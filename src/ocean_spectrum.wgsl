@@ -0,0 +1,92 @@
+// The ocean's starting spectrum, see ocean.rs. Each texel gets a random complex amplitude for its
+// wave, scaled by how much energy the spectrum puts there, along with the conjugate amplitude of
+// the wave heading the opposite way, which the waves pass needs to keep the heights real.
+
+@group(0) @binding(1)
+var h0: texture_storage_2d<rgba32float, write>;
+
+fn spectrum_hash(p: vec2<f32>) -> vec2<f32> {
+    let q = vec2<f32>(dot(p, vec2<f32>(127.1, 311.7)), dot(p, vec2<f32>(269.5, 183.3)));
+
+    return fract(sin(q) * 43758.5453);
+}
+
+// Two independent normally distributed values, from uniform ones by the Box-Muller transform
+fn gaussian(wave: vec2<f32>) -> vec2<f32> {
+    let u      = spectrum_hash(wave + vec2<f32>(0.5, 0.25));
+    let radius = sqrt(-2.0 * log(max(u.x, 0.000001)));
+    let angle  = 6.2831853 * u.y;
+
+    return radius * vec2<f32>(cos(angle), sin(angle));
+}
+
+// How much of the energy travels along `direction`, most of it with the wind and a little against
+// it
+fn spreading(direction: vec2<f32>, wind_direction: vec2<f32>) -> f32 {
+    let c = dot(direction, wind_direction);
+
+    return select(c * c * 0.07, c * c, c > 0.0);
+}
+
+// Fully developed seas, with waves up to as long as the wind can raise and the smallest ripples
+// damped
+fn phillips(k: f32, wind_speed: f32) -> f32 {
+    let longest  = wind_speed * wind_speed / 9.81;
+    let shortest = longest * 0.001;
+    let k2       = k * k;
+
+    return 0.0081 * exp(-1.0 / (k2 * longest * longest)) / (k2 * k2) * exp(-k2 * shortest * shortest);
+}
+
+// Seas still growing over `fetch` of open water, peaking more sharply than Phillips
+fn jonswap(k: f32, wind_speed: f32) -> f32 {
+    let omega = sqrt(9.81 * k);
+    let alpha = 0.076 * pow(wind_speed * wind_speed / (ocean.fetch * 9.81), 0.22);
+    let peak  = 22.0 * pow(9.81 * 9.81 / (wind_speed * ocean.fetch), 1.0 / 3.0);
+    let sigma = select(0.09, 0.07, omega <= peak);
+    let r     = exp(-(omega - peak) * (omega - peak) / (2.0 * sigma * sigma * peak * peak));
+    let s     = alpha * 9.81 * 9.81 / pow(omega, 5.0) * exp(-1.25 * pow(peak / omega, 4.0)) * pow(3.3, r);
+
+    // From energy over frequency to energy over wave vectors, by dω/dk over the circle at k, with
+    // the spreading's share of the circle taken back out
+    return s * (9.81 / (2.0 * omega)) / k * 2.0 / 3.14159265;
+}
+
+// The energy of the wave along `k`, over its cell of the spectrum
+fn energy(k: vec2<f32>) -> f32 {
+    let k_length = length(k);
+
+    if (k_length < 0.0001) {
+        return 0.0;
+    }
+
+    let wind_speed = max(length(ocean.wind), 0.1);
+    let direction  = spreading(k / k_length, ocean.wind / wind_speed);
+    let cell       = 6.2831853 / ocean.size;
+
+    var spectrum = phillips(k_length, wind_speed);
+
+    if (ocean.spectrum == 1u) {
+        spectrum = jonswap(k_length, wind_speed);
+    }
+
+    return spectrum * direction * ocean.amplitude * cell * cell;
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let resolution = u32(textureDimensions(h0).x);
+
+    if (id.x >= resolution || id.y >= resolution) {
+        return;
+    }
+
+    // Random numbers from the wave itself, so the opposite wave is drawn the same way from its own
+    // texel
+    let wave     = wave_number(id.xy, resolution);
+    let k        = wave_vector(id.xy, resolution);
+    let forward  = gaussian(wave) * sqrt(energy(k) / 2.0);
+    let backward = gaussian(-wave) * sqrt(energy(-k) / 2.0);
+
+    textureStore(h0, vec2<i32>(id.xy), vec4<f32>(forward, backward.x, -backward.y));
+}
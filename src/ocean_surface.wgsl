@@ -0,0 +1,68 @@
+// The ocean's surface, see ocean.rs. A flat grid over the ocean's extent, moved by the simulated
+// displacement and shaded with its normals, with the patch tiled across it. Gets `camera.wgsl`,
+// `lights.wgsl` and `ocean.wgsl` prepended.
+
+@group(0) @binding(1)
+var t_displacement: texture_2d<f32>;
+@group(0) @binding(2)
+var t_normals: texture_2d<f32>;
+@group(0) @binding(3)
+var s_ocean: sampler;
+
+struct VertexInput {
+    // From 0.0 to 1.0 across the extent
+    @location(0) grid: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position:      vec3<f32>,
+    @location(1) tex_coords:          vec2<f32>,
+}
+
+@vertex
+fn vs_main(model: VertexInput) -> VertexOutput {
+    let flat       = ocean.origin + vec3<f32>(model.grid.x - 0.5, 0.0, model.grid.y - 0.5) * ocean.extent;
+    let tex_coords = flat.xz / ocean.size;
+
+    var out: VertexOutput;
+
+    out.tex_coords     = tex_coords;
+    out.world_position = flat + textureSampleLevel(t_displacement, s_ocean, tex_coords, 0.0).xyz;
+    out.clip_position  = camera.view_proj * vec4<f32>(out.world_position, 1.0);
+
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Sampled before anything's discarded, which would leave the sample out of uniform control flow
+    let sample = textureSample(t_normals, s_ocean, in.tex_coords);
+
+    if (dot(camera.clip_plane, vec4<f32>(in.world_position, 1.0)) < 0.0) {
+        discard;
+    }
+
+    let normal   = normalize(sample.xyz);
+    let view_dir = normalize(camera.view_position.xyz - in.world_position);
+
+    // The sky along the reflection, as the probes see it, over the deep water's own color lit from
+    // above
+    let sky   = probe_lighting(in.world_position, reflect(-view_dir, normal));
+    let water = vec3<f32>(0.02, 0.08, 0.12) * probe_lighting(in.world_position, vec3<f32>(0.0, 1.0, 0.0));
+
+    var color = mix(water, sky, fresnel(dot(normal, view_dir)));
+
+    // Tight glints of the spot lights off the waves
+    for (var i = 0u; i < lights.spot_count; i = i + 1u) {
+        let spot      = lights.spots[i];
+        let light_dir = normalize(spot.position_range.xyz - in.world_position);
+        let half_dir  = normalize(light_dir + view_dir);
+
+        color = color + spot_incoming(spot, in.world_position) * pow(max(dot(normal, half_dir), 0.0), 256.0);
+    }
+
+    let foam = vec3<f32>(0.9) * (probe_lighting(in.world_position, normal) + spot_lighting(in.world_position, normal));
+
+    return vec4<f32>(mix(color, foam, sample.w), 1.0);
+}
@@ -0,0 +1,34 @@
+// Moves the ocean's spectrum on to the current time, see ocean.rs. Each wave turns at its own
+// frequency, from how fast waves that long travel in deep water, and the spectra of both horizontal
+// displacements follow from the height's.
+
+@group(0) @binding(1)
+var h0: texture_2d<f32>;
+@group(0) @binding(2)
+var waves: texture_storage_2d<rgba32float, write>;
+
+@compute @workgroup_size(8, 8)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let resolution = u32(textureDimensions(waves).x);
+
+    if (id.x >= resolution || id.y >= resolution) {
+        return;
+    }
+
+    let initial  = textureLoad(h0, vec2<i32>(id.xy), 0);
+    let k        = wave_vector(id.xy, resolution);
+    let k_length = length(k);
+    let omega    = sqrt(9.81 * k_length);
+    let phase    = vec2<f32>(cos(omega * ocean.time), sin(omega * ocean.time));
+    let height   = complex_mul(initial.xy, phase) + complex_mul(initial.zw, vec2<f32>(phase.x, -phase.y));
+
+    // Points move towards the crests, a quarter wave out from the height: -i along the wave
+    let direction = select(vec2<f32>(0.0), k / max(k_length, 0.0001), k_length > 0.0001);
+    let sideways  = vec2<f32>(height.y, -height.x);
+    let dx        = sideways * direction.x;
+    let dz        = sideways * direction.y;
+
+    // All three are spectra of real values, so the height and the x displacement share a complex
+    // number as its real and imaginary parts, height + i dx
+    textureStore(waves, vec2<i32>(id.xy), vec4<f32>(height + vec2<f32>(-dx.y, dx.x), dz));
+}
@@ -0,0 +1,44 @@
+// Reorders meshes for the GPU as they're loaded, the way meshoptimizer recommends: duplicate
+// vertices are merged, triangles are ordered to reuse the post-transform vertex cache and then to
+// draw front to back where that costs little cache, and vertices are laid out in the order
+// they're first used. None of it changes what's drawn, only how fast.
+
+use crate::model::ModelVertex;
+
+// How much worse the vertex cache may get, as a factor, for the sake of less overdraw
+const OVERDRAW_THRESHOLD: f32 = 1.05;
+
+// A typical post-transform cache, only used to report how much the reordering helped
+const CACHE_SIZE: u32 = 16;
+
+pub fn optimize_mesh(vertices: &[ModelVertex], indices: &[u32]) -> anyhow::Result<(Vec<ModelVertex>, Vec<u32>)> {
+    let before = meshopt::analyze_vertex_cache(indices, vertices.len(), CACHE_SIZE, 0, 0);
+
+    // The OBJ loader splits vertices per face corner, so shared corners are often exact copies
+    let (vertex_count, remap) = meshopt::generate_vertex_remap(vertices, Some(indices));
+    let indices               = meshopt::remap_index_buffer(Some(indices), vertex_count, &remap);
+    let vertices              = meshopt::remap_vertex_buffer(vertices, vertex_count, &remap);
+
+    let mut indices = meshopt::optimize_vertex_cache(&indices, vertices.len());
+
+    let adapter = meshopt::VertexDataAdapter::new(
+        bytemuck::cast_slice(&vertices),
+        std::mem::size_of::<ModelVertex>(),
+        0,
+    ).map_err(|e| anyhow::anyhow!("{:?}", e))?;
+    // Rewrites `indices` even though it only borrows them shared
+    meshopt::optimize_overdraw_in_place(&indices, &adapter, OVERDRAW_THRESHOLD);
+
+    let vertices = meshopt::optimize_vertex_fetch(&mut indices, &vertices);
+
+    let after = meshopt::analyze_vertex_cache(&indices, vertices.len(), CACHE_SIZE, 0, 0);
+
+    log::debug!(
+        "Optimized mesh: {} vertices, ACMR {:.2} -> {:.2}",
+        vertices.len(),
+        before.acmr,
+        after.acmr,
+    );
+
+    Ok((vertices, indices))
+}
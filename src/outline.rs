@@ -0,0 +1,114 @@
+// Post pass that draws outlines along depth and crease edges, e.g. to go with toon shading
+
+use wgpu::util::DeviceExt;
+
+use crate::{
+    bind_group,
+    camera::DepthMode,
+    crash_report,
+    pass::{Drawable, RenderLayer},
+    renderer::GpuContext,
+    uniform::Uniform,
+};
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct OutlineParams {
+    color:     [f32; 4],
+    threshold: f32,
+    reverse_z: u32,
+    _padding:  [u32; 2],
+}
+
+pub struct OutlinePass {
+    pipeline:   wgpu::RenderPipeline,
+    layout:     wgpu::BindGroupLayout,
+    params:     wgpu::Buffer,
+    // Rebuilt by `prepare` since the depth texture is replaced on resize
+    bind_group: Option<wgpu::BindGroup>,
+}
+
+impl OutlinePass {
+    pub fn new(ctx: &GpuContext, color: [f32; 4], threshold: f32) -> Self {
+        let device = &ctx.device;
+
+        let layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::FRAGMENT)
+            .depth_texture(wgpu::ShaderStages::FRAGMENT)
+            .build(device, "outline_bind_group_layout");
+
+        let params = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label:    Some("Outline Params Buffer"),
+                contents: bytemuck::cast_slice(&[OutlineParams {
+                    color,
+                    threshold,
+                    reverse_z: (ctx.depth_mode == DepthMode::ReverseZ) as u32,
+                    _padding:  [0; 2],
+                }]),
+                usage:    wgpu::BufferUsages::UNIFORM,
+            }
+        );
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Outline Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("outline.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Outline Pipeline Layout"),
+            bind_group_layouts:   &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label:    Some("Outline Pipeline"),
+            layout:   Some(&pipeline_layout),
+            vertex:   wgpu::VertexState {
+                module:      &shader,
+                entry_point: "vs_main",
+                buffers:     &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module:      &shader,
+                entry_point: "fs_main",
+                targets:     &[Some(wgpu::ColorTargetState {
+                    format:     ctx.config.format,
+                    blend:      Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive:     wgpu::PrimitiveState::default(),
+            // Post layers have no depth attachment, which leaves the depth texture free to read
+            depth_stencil: None,
+            multisample:   wgpu::MultisampleState::default(),
+            multiview:     None,
+        });
+
+        Self {
+            pipeline,
+            layout,
+            params,
+            bind_group: None,
+        }
+    }
+
+    pub fn prepare(&mut self, ctx: &GpuContext) {
+        self.bind_group = Some(
+            bind_group::BindGroupBuilder::new(&self.layout)
+                .uniform(&self.params)
+                .texture(&ctx.depth_texture.view)
+                .build(&ctx.device, "outline_bind_group")
+        );
+    }
+}
+
+impl Drawable for OutlinePass {
+    fn draw<'a>(&'a self, layer: RenderLayer, render_pass: &mut wgpu::RenderPass<'a>) {
+        if let (RenderLayer::Post, Some(bind_group)) = (layer, &self.bind_group) {
+            crash_report::set_pipeline(render_pass, &self.pipeline, "Outline Pipeline");
+            render_pass.set_bind_group(0, bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+}
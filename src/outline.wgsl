@@ -0,0 +1,61 @@
+// Screen-space outlines found from the depth buffer
+
+struct OutlineParams {
+    color:     vec4<f32>,
+    // Edge strength, relative to the depth at the pixel, needed to draw an outline
+    threshold: f32,
+    reverse_z: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: OutlineParams;
+@group(0) @binding(1)
+var t_depth: texture_depth_2d;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+}
+
+// One triangle that covers the screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+
+    return out;
+}
+
+// Affine in 1 / view depth and 0.0 at the far plane in both depth modes
+fn inverse_depth(coord: vec2<i32>) -> f32 {
+    let size  = vec2<i32>(textureDimensions(t_depth));
+    let depth = textureLoad(t_depth, clamp(coord, vec2<i32>(0), size - 1), 0);
+
+    if (params.reverse_z != 0u) {
+        return depth;
+    }
+    return 1.0 - depth;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let coord  = vec2<i32>(in.clip_position.xy);
+    let center = inverse_depth(coord);
+    let left   = inverse_depth(coord - vec2<i32>(1, 0));
+    let right  = inverse_depth(coord + vec2<i32>(1, 0));
+    let up     = inverse_depth(coord - vec2<i32>(0, 1));
+    let down   = inverse_depth(coord + vec2<i32>(0, 1));
+
+    // Inverse depth changes linearly across a plane in screen space, so the Laplacian is zero on
+    // flat surfaces and spikes at silhouettes (depth edges) and creases (normal edges)
+    let laplacian = abs(left + right + up + down - 4.0 * center);
+    let nearest   = max(max(center, max(left, right)), max(up, down));
+
+    if (laplacian <= params.threshold * nearest) {
+        discard;
+    }
+
+    return params.color;
+}
@@ -0,0 +1,65 @@
+// Conversions from full precision floats to the packed vertex formats. Normalized values are
+// rounded to the nearest step, and out of range ones clamped.
+
+pub fn unorm8(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+pub fn snorm8(value: f32) -> i8 {
+    (value.clamp(-1.0, 1.0) * 127.0).round() as i8
+}
+
+pub fn unorm16(value: f32) -> u16 {
+    (value.clamp(0.0, 1.0) * 65535.0).round() as u16
+}
+
+pub fn snorm16(value: f32) -> i16 {
+    (value.clamp(-1.0, 1.0) * 32767.0).round() as i16
+}
+
+// The bits of the nearest half float, ties to even. Values too large for one become infinity and
+// those too small flush to subnormals or zero.
+pub fn f16(value: f32) -> u16 {
+    let bits     = value.to_bits();
+    let sign     = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    // Infinity stays infinity, and NaN keeps a mantissa bit so it stays NaN
+    if exponent == 0xff {
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+
+    let exponent = exponent - 127 + 15;
+
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    if exponent <= 0 {
+        // Subnormal: shift the mantissa, with its implicit leading bit, into place
+        if exponent < -10 {
+            return sign;
+        }
+
+        let mantissa = mantissa | 0x80_0000;
+        let shift    = (14 - exponent) as u32;
+
+        return sign | round_shift(mantissa, shift) as u16;
+    }
+
+    // Rounding can carry into the exponent, which is still the right answer
+    sign | (((exponent as u32) << 10) + round_shift(mantissa, 13)) as u16
+}
+
+fn round_shift(value: u32, shift: u32) -> u32 {
+    let half      = 1 << (shift - 1);
+    let remainder = value & ((1 << shift) - 1);
+    let shifted   = value >> shift;
+
+    if remainder > half || remainder == half && shifted & 1 == 1 {
+        shifted + 1
+    } else {
+        shifted
+    }
+}
@@ -0,0 +1,440 @@
+// Helpers for beginning the render passes used by the renderer
+
+use crate::{camera::DepthMode, crash_report, gpu_stats, pass_timing::PassTimer, resolution::SceneTarget};
+
+pub const CLEAR_COLOR: wgpu::Color = wgpu::Color {
+    r: 0.1,
+    g: 0.2,
+    b: 0.3,
+    a: 1.0,
+};
+
+// Clears the color and depth targets and returns a pass ready for the world to be drawn into
+pub fn begin_main_pass<'a>(
+    encoder:    &'a mut wgpu::CommandEncoder,
+    view:       &'a wgpu::TextureView,
+    depth:      &'a wgpu::TextureView,
+    clear:      wgpu::Color,
+    depth_mode: DepthMode,
+) -> wgpu::RenderPass<'a> {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Render Pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops:  wgpu::Operations {
+                load:  wgpu::LoadOp::Clear(clear),
+                store: true
+            },
+        })],
+        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+            view:       depth,
+            depth_ops:  Some(wgpu::Operations {
+                load:  wgpu::LoadOp::Clear(depth_mode.clear_depth()),
+                store: true,
+            }),
+            stencil_ops: None,
+        }),
+    })
+}
+
+// Draws on top of whatever is already in `view`, e.g. for overlays and debug geometry
+pub fn begin_overlay_pass<'a>(
+    encoder: &'a mut wgpu::CommandEncoder,
+    view:    &'a wgpu::TextureView,
+    label:   &str,
+) -> wgpu::RenderPass<'a> {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some(label),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops:  wgpu::Operations {
+                load:  wgpu::LoadOp::Load,
+                store: true
+            },
+        })],
+        depth_stencil_attachment: None,
+    })
+}
+
+fn clear_color(encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView, clear: wgpu::Color) {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Clear Color Pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops:  wgpu::Operations {
+                load:  wgpu::LoadOp::Clear(clear),
+                store: true
+            },
+        })],
+        depth_stencil_attachment: None,
+    });
+}
+
+fn clear_depth(encoder: &mut wgpu::CommandEncoder, depth: &wgpu::TextureView, depth_mode: DepthMode) {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Clear Depth Pass"),
+        color_attachments: &[],
+        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+            view:       depth,
+            depth_ops:  Some(wgpu::Operations {
+                load:  wgpu::LoadOp::Clear(depth_mode.clear_depth()),
+                store: true,
+            }),
+            stencil_ops: None,
+        }),
+    });
+}
+
+// A region of the target to draw into, in pixels from the top left
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Viewport {
+    pub x:      f32,
+    pub y:      f32,
+    pub width:  f32,
+    pub height: f32,
+}
+
+impl Viewport {
+    pub fn aspect(&self) -> f32 {
+        self.width / self.height.max(1.0)
+    }
+}
+
+// Layers are drawn in declaration order, each in its own render pass
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RenderLayer {
+    DepthPrepass,
+    Background,
+    WorldOpaque,
+    WorldTransparent,
+    Post,
+    Ui,
+    Debug,
+}
+
+impl RenderLayer {
+    pub const ALL: [RenderLayer; 7] = [
+        RenderLayer::DepthPrepass,
+        RenderLayer::Background,
+        RenderLayer::WorldOpaque,
+        RenderLayer::WorldTransparent,
+        RenderLayer::Post,
+        RenderLayer::Ui,
+        RenderLayer::Debug,
+    ];
+
+    // The depth prepass only writes depth, so its pipelines have no color targets
+    fn uses_color(&self) -> bool {
+        !matches!(self, RenderLayer::DepthPrepass)
+    }
+
+    // Post-processing and UI draw over the finished scene so they don't test against depth
+    fn uses_depth(&self) -> bool {
+        !matches!(self, RenderLayer::Post | RenderLayer::Ui)
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            RenderLayer::DepthPrepass     => "Depth Prepass Layer",
+            RenderLayer::Background       => "Background Layer",
+            RenderLayer::WorldOpaque      => "World Opaque Layer",
+            RenderLayer::WorldTransparent => "World Transparent Layer",
+            RenderLayer::Post             => "Post Layer",
+            RenderLayer::Ui               => "UI Layer",
+            RenderLayer::Debug            => "Debug Layer",
+        }
+    }
+}
+
+// Anything that records draw calls into a layer's render pass. A drawable registered into
+// several layers is called once per layer.
+pub trait Drawable {
+    fn draw<'a>(&'a self, layer: RenderLayer, render_pass: &mut wgpu::RenderPass<'a>);
+
+    // Names the debug group its draws are wrapped in, which shows up in traces and captures
+    fn label(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+}
+
+// A full-screen filter that reads the finished scene from `input` and writes every pixel of
+// `output`. Both are the scene target's size and the surface's format.
+pub trait PostEffect {
+    fn apply(
+        &self,
+        device:  &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        input:   &wgpu::TextureView,
+        output:  &wgpu::TextureView,
+    );
+
+    // Effects that only look at the scene return false and leave `output` alone, so the next
+    // effect reads the same input
+    fn writes_output(&self) -> bool {
+        true
+    }
+
+    // Names the debug group its passes are wrapped in, which shows up in traces and captures
+    fn label(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+}
+
+// Keeps a copy of the scene as it is partway through, for later layers to read, e.g. the opaque
+// world seen through glass. `scene` is the scene target's size and the surface's format, and
+// `depth` is the depth buffer as the layers before left it.
+pub trait SceneCapture {
+    fn capture(
+        &self,
+        device:  &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        scene:   &wgpu::TextureView,
+        depth:   &wgpu::TextureView,
+    );
+
+    // Names the debug group its passes are wrapped in, which shows up in traces and captures
+    fn label(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+}
+
+// Draws registered for a single frame, grouped by layer
+#[derive(Default)]
+pub struct RenderLayers<'a> {
+    draws:    Vec<(RenderLayer, &'a dyn Drawable)>,
+    effects:  Vec<&'a dyn PostEffect>,
+    captures: Vec<(RenderLayer, &'a dyn SceneCapture)>,
+    timer:    Option<&'a PassTimer>,
+}
+
+impl<'a> RenderLayers<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Times each layer's pass and the effects with `timer`. Only the window's layers are, so
+    // views drawn along the way don't show up as passes of their own.
+    pub fn set_timer(&mut self, timer: &'a PassTimer) {
+        self.timer = Some(timer);
+    }
+
+    pub fn add(&mut self, layer: RenderLayer, drawable: &'a dyn Drawable) {
+        self.draws.push((layer, drawable));
+    }
+
+    // Effects run in the order they're added, after the Post layer
+    pub fn add_effect(&mut self, effect: &'a dyn PostEffect) {
+        self.effects.push(effect);
+    }
+
+    pub fn has_effects(&self) -> bool {
+        !self.effects.is_empty()
+    }
+
+    // Captures run just before `layer`'s pass, so they see every layer before it. The surface
+    // can't be read, so they only run when the scene is drawn offscreen.
+    pub fn add_capture(&mut self, layer: RenderLayer, capture: &'a dyn SceneCapture) {
+        self.captures.push((layer, capture));
+    }
+
+    pub fn has_captures(&self) -> bool {
+        !self.captures.is_empty()
+    }
+
+    // Begins one pass per non-empty layer. Color and depth are each cleared by the first pass
+    // that uses them.
+    pub fn execute(
+        &self,
+        encoder:    &mut wgpu::CommandEncoder,
+        view:       &wgpu::TextureView,
+        depth:      &wgpu::TextureView,
+        clear:      wgpu::Color,
+        depth_mode: DepthMode,
+    ) {
+        self.execute_layers(None, encoder, view, depth, Some(clear), depth_mode, None, |_| true);
+    }
+
+    // Draws every layer into just `viewport` of `view`, leaving the rest as it is. Nothing is
+    // cleared, since clears would wipe the whole target, so clear it once before the first
+    // viewport. Post effects and captures aren't applied.
+    pub fn execute_viewport(
+        &self,
+        encoder:    &mut wgpu::CommandEncoder,
+        view:       &wgpu::TextureView,
+        depth:      &wgpu::TextureView,
+        depth_mode: DepthMode,
+        viewport:   Viewport,
+    ) {
+        self.execute_layers(None, encoder, view, depth, None, depth_mode, Some(viewport), |_| true);
+    }
+
+    // Draws the scene into `target` at the render resolution and runs the post effects over it.
+    // Debug geometry goes on top of the result so it isn't filtered, then it's all upscaled to
+    // `view` and the UI is drawn over it at full resolution.
+    #[allow(clippy::too_many_arguments)]
+    pub fn execute_offscreen(
+        &self,
+        device:     &wgpu::Device,
+        encoder:    &mut wgpu::CommandEncoder,
+        target:     &SceneTarget,
+        view:       &wgpu::TextureView,
+        depth:      &wgpu::TextureView,
+        clear:      wgpu::Color,
+        depth_mode: DepthMode,
+    ) {
+        self.execute_layers(Some(device), encoder, target.view(0), depth, Some(clear), depth_mode, None, |layer| {
+            !matches!(layer, RenderLayer::Ui | RenderLayer::Debug)
+        });
+
+        let mut current = 0;
+
+        if let (Some(timer), true) = (self.timer, self.has_effects()) {
+            timer.begin(encoder, "Post Effects");
+        }
+
+        for effect in &self.effects {
+            crash_report::marker(effect.label());
+            encoder.push_debug_group(effect.label());
+            effect.apply(device, encoder, target.view(current), target.view(1 - current));
+            encoder.pop_debug_group();
+
+            if effect.writes_output() {
+                current = 1 - current;
+            }
+        }
+
+        if let Some(timer) = self.timer {
+            timer.end(encoder);
+        }
+
+        self.execute_layers(None, encoder, target.view(current), depth, None, depth_mode, None, |layer| layer == RenderLayer::Debug);
+
+        crash_report::marker("Upscale");
+        encoder.push_debug_group("Upscale");
+        target.upscale(device, encoder, target.view(current), view);
+        encoder.pop_debug_group();
+
+        self.execute_layers(None, encoder, view, depth, None, depth_mode, None, |layer| layer == RenderLayer::Ui);
+    }
+
+    // Draws the layers accepted by `include` into `view`, or only into `viewport` of it. Without
+    // a clear color, `view` and `depth` are loaded. Captures are run with `device`, when `view`
+    // can be read.
+    #[allow(clippy::too_many_arguments)]
+    fn execute_layers(
+        &self,
+        device:     Option<&wgpu::Device>,
+        encoder:    &mut wgpu::CommandEncoder,
+        view:       &wgpu::TextureView,
+        depth:      &wgpu::TextureView,
+        clear:      Option<wgpu::Color>,
+        depth_mode: DepthMode,
+        viewport:   Option<Viewport>,
+        include:    impl Fn(RenderLayer) -> bool,
+    ) {
+        let mut color_cleared = clear.is_none();
+        let mut depth_cleared = clear.is_none();
+
+        for layer in RenderLayer::ALL.into_iter().filter(|layer| include(*layer)) {
+            let drawables = self.draws.iter()
+                .filter(|(l, _)| *l == layer)
+                .map(|(_, d)| *d)
+                .collect::<Vec<_>>();
+
+            if drawables.is_empty() {
+                continue;
+            }
+
+            if let Some(device) = device {
+                let captures = self.captures.iter().filter(|(l, _)| *l == layer).collect::<Vec<_>>();
+
+                // A capture before anything drew color or depth would read last frame
+                if let (Some(clear), false) = (clear, color_cleared || captures.is_empty()) {
+                    clear_color(encoder, view, clear);
+                    color_cleared = true;
+                }
+
+                if !depth_cleared && !captures.is_empty() {
+                    clear_depth(encoder, depth, depth_mode);
+                    depth_cleared = true;
+                }
+
+                for (_, capture) in captures {
+                    crash_report::marker(capture.label());
+                    encoder.push_debug_group(capture.label());
+                    capture.capture(device, encoder, view, depth);
+                    encoder.pop_debug_group();
+                }
+            }
+
+            let color_attachments = [Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops:  wgpu::Operations {
+                    load:  match clear {
+                        Some(clear) if !color_cleared => wgpu::LoadOp::Clear(clear),
+                        _                             => wgpu::LoadOp::Load,
+                    },
+                    store: true
+                },
+            })];
+
+            if let Some(timer) = self.timer {
+                timer.begin(encoder, layer.label());
+            }
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(layer.label()),
+                color_attachments: if layer.uses_color() { &color_attachments[..] } else { &[] },
+                depth_stencil_attachment: if layer.uses_depth() {
+                    Some(wgpu::RenderPassDepthStencilAttachment {
+                        view:       depth,
+                        depth_ops:  Some(wgpu::Operations {
+                            load:  if depth_cleared { wgpu::LoadOp::Load } else { wgpu::LoadOp::Clear(depth_mode.clear_depth()) },
+                            store: true,
+                        }),
+                        stencil_ops: None,
+                    })
+                } else {
+                    None
+                },
+            });
+
+            if let Some(viewport) = viewport {
+                render_pass.set_viewport(viewport.x, viewport.y, viewport.width, viewport.height, 0.0, 1.0);
+                render_pass.set_scissor_rect(viewport.x as u32, viewport.y as u32, viewport.width as u32, viewport.height as u32);
+            }
+
+            gpu_stats::begin_pass(layer.label());
+            crash_report::marker(layer.label());
+
+            for drawable in drawables {
+                crash_report::marker(drawable.label());
+                render_pass.push_debug_group(drawable.label());
+                drawable.draw(layer, &mut render_pass);
+                render_pass.pop_debug_group();
+            }
+
+            gpu_stats::end_pass();
+            drop(render_pass);
+
+            if let Some(timer) = self.timer {
+                timer.end(encoder);
+            }
+
+            color_cleared |= layer.uses_color();
+            depth_cleared |= layer.uses_depth();
+        }
+
+        // Nothing drew color, but the frame should still be cleared
+        if let (false, Some(clear)) = (color_cleared, clear) {
+            begin_main_pass(encoder, view, depth, clear, depth_mode);
+        } else if !depth_cleared {
+            // Later layers load depth, so it has to be cleared even if nothing here tested against it
+            clear_depth(encoder, depth, depth_mode);
+        }
+    }
+}
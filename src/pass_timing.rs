@@ -0,0 +1,260 @@
+// How long each named pass of a frame takes, on the CPU to record and on the GPU to run. Scopes
+// are opened and closed around the work on the frame's encoder: the runner times each layer pass
+// and the post effects, and apps time their own passes like shadows with `PassTimer::time`. CPU
+// times are measured with `Instant`. GPU times come from timestamps written around each scope,
+// where the adapter supports timestamp queries, and turn up a frame or more later since they're
+// read back without waiting. Frames whose timestamps can't be read back because an earlier
+// frame's are still on their way are skipped.
+//
+// Finished frames go to `gpu_stats`, which the inspector shows them from, and to a CSV file when
+// `RendererOptions::timings_csv` names one.
+
+use std::cell::RefCell;
+
+use crate::{compute::AsyncReadback, gpu_stats};
+
+// Scopes past this many in a frame are still timed on the CPU, but not on the GPU
+const MAX_SCOPES: u32 = 32;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassTiming {
+    pub label:  &'static str,
+    pub cpu_ms: f32,
+    // None without timestamp queries, or past MAX_SCOPES
+    pub gpu_ms: Option<f32>,
+}
+
+struct TimestampQueries {
+    set:      wgpu::QuerySet,
+    // Where the set is resolved to, then copied out of into `readback`
+    resolve:  wgpu::Buffer,
+    readback: AsyncReadback,
+    // Nanoseconds per tick
+    period:   f32,
+    // The frame whose timestamps `readback` is bringing back, and its scopes
+    pending:  Option<(u64, Vec<Scope>)>,
+}
+
+#[derive(Debug, Clone)]
+struct Scope {
+    label:  &'static str,
+    cpu_ms: f32,
+    // Of the timestamp written when it opened, the closing one follows it
+    query:  Option<u32>,
+}
+
+struct TimerState {
+    queries: Option<TimestampQueries>,
+    // This frame's closed scopes, in the order they were opened
+    scopes:  Vec<Scope>,
+    open:    Option<(&'static str, instant::Instant, Option<u32>)>,
+    written: u32,
+    #[cfg(not(target_arch = "wasm32"))]
+    csv:     Option<std::io::BufWriter<std::fs::File>>,
+}
+
+// Kept by the GPU context, see `GpuContext::pass_timer`
+pub struct PassTimer {
+    state: RefCell<TimerState>,
+}
+
+impl PassTimer {
+    // Times on the GPU too if `device` was made with `Features::TIMESTAMP_QUERY`
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let queries = device.features().contains(wgpu::Features::TIMESTAMP_QUERY).then(|| {
+            let size = (MAX_SCOPES * 2) as wgpu::BufferAddress * std::mem::size_of::<u64>() as wgpu::BufferAddress;
+
+            TimestampQueries {
+                set:      device.create_query_set(&wgpu::QuerySetDescriptor {
+                    label: Some("Pass Timestamps"),
+                    ty:    wgpu::QueryType::Timestamp,
+                    count: MAX_SCOPES * 2,
+                }),
+                resolve:  device.create_buffer(&wgpu::BufferDescriptor {
+                    label:              Some("Pass Timestamp Resolve Buffer"),
+                    size,
+                    usage:              wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+                    mapped_at_creation: false,
+                }),
+                readback: AsyncReadback::new(device, size, "Pass Timestamp Readback Buffer"),
+                period:   queue.get_timestamp_period(),
+                pending:  None,
+            }
+        });
+
+        Self {
+            state: RefCell::new(TimerState {
+                queries,
+                scopes:  Vec::new(),
+                open:    None,
+                written: 0,
+                #[cfg(not(target_arch = "wasm32"))]
+                csv:     None,
+            }),
+        }
+    }
+
+    pub fn has_gpu_times(&self) -> bool {
+        self.state.borrow().queries.is_some()
+    }
+
+    // Appends every finished frame to `path` as `frame,pass,cpu_ms,gpu_ms` rows, replacing what
+    // was there
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn log_csv(&self, path: &std::path::Path) -> std::io::Result<()> {
+        use std::io::Write;
+
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+
+        writeln!(file, "frame,pass,cpu_ms,gpu_ms")?;
+        self.state.borrow_mut().csv = Some(file);
+        Ok(())
+    }
+
+    // Opens a scope named `label`, closing any still open
+    pub fn begin(&self, encoder: &mut wgpu::CommandEncoder, label: &'static str) {
+        self.end(encoder);
+
+        let mut state = self.state.borrow_mut();
+        let query     = match &state.queries {
+            Some(queries) if state.written < MAX_SCOPES => {
+                let query = state.written * 2;
+
+                encoder.write_timestamp(&queries.set, query);
+                Some(query)
+            }
+            _ => None,
+        };
+
+        if query.is_some() {
+            state.written += 1;
+        }
+
+        state.open = Some((label, instant::Instant::now(), query));
+    }
+
+    pub fn end(&self, encoder: &mut wgpu::CommandEncoder) {
+        let mut state = self.state.borrow_mut();
+
+        if let Some((label, started, query)) = state.open.take() {
+            if let (Some(queries), Some(query)) = (&state.queries, query) {
+                encoder.write_timestamp(&queries.set, query + 1);
+            }
+
+            state.scopes.push(Scope { label, cpu_ms: started.elapsed().as_secs_f32() * 1000.0, query });
+        }
+    }
+
+    // Records `record` into `encoder` inside a scope named `label`
+    pub fn time<R>(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        label:   &'static str,
+        record:  impl FnOnce(&mut wgpu::CommandEncoder) -> R,
+    ) -> R {
+        self.begin(encoder, label);
+
+        let result = record(encoder);
+
+        self.end(encoder);
+        result
+    }
+
+    // Closes the frame's scopes and resolves their timestamps into `encoder`. Called by
+    // `Frame::present` before the encoder is finished.
+    pub(crate) fn end_frame(&self, encoder: &mut wgpu::CommandEncoder, index: u64) {
+        self.end(encoder);
+
+        let mut state = self.state.borrow_mut();
+        let scopes    = std::mem::take(&mut state.scopes);
+        let written   = std::mem::replace(&mut state.written, 0);
+
+        // Without timestamps there's nothing to wait for
+        if state.queries.is_none() {
+            drop(state);
+            self.publish(index, &scopes, &[], 0.0);
+            return;
+        }
+
+        let queries = match &mut state.queries {
+            Some(queries) if written > 0 => queries,
+            _                            => return,
+        };
+
+        encoder.resolve_query_set(&queries.set, 0..written * 2, &queries.resolve, 0);
+
+        if queries.readback.copy(encoder, &queries.resolve) {
+            queries.pending = Some((index, scopes));
+        }
+    }
+
+    // Starts reading back the timestamps just submitted, and publishes any frame whose have
+    // arrived. Called by `Frame::present` after submitting.
+    pub(crate) fn submitted(&self, device: &wgpu::Device) {
+        let mut state = self.state.borrow_mut();
+
+        let queries = match &mut state.queries {
+            Some(queries) => queries,
+            None          => return,
+        };
+
+        queries.readback.submitted();
+
+        let ticks = match queries.readback.try_read::<u64>(device) {
+            Some(ticks) => ticks,
+            None        => return,
+        };
+
+        let period = queries.period;
+
+        if let Some((index, scopes)) = queries.pending.take() {
+            drop(state);
+            self.publish(index, &scopes, &ticks, period);
+        }
+    }
+
+    fn publish(&self, index: u64, scopes: &[Scope], ticks: &[u64], period: f32) {
+        let timings = scopes.iter()
+            .map(|scope| PassTiming {
+                label:  scope.label,
+                cpu_ms: scope.cpu_ms,
+                gpu_ms: scope.query.and_then(|query| {
+                    let start = *ticks.get(query as usize)?;
+                    let end   = *ticks.get(query as usize + 1)?;
+
+                    Some(end.saturating_sub(start) as f32 * period / 1_000_000.0)
+                }),
+            })
+            .collect::<Vec<_>>();
+
+        #[cfg(not(target_arch = "wasm32"))]
+        self.write_csv(index, &timings);
+        #[cfg(target_arch = "wasm32")]
+        let _ = index;
+
+        gpu_stats::record_timings(timings);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn write_csv(&self, index: u64, timings: &[PassTiming]) {
+        use std::io::Write;
+
+        let mut state = self.state.borrow_mut();
+
+        let file = match &mut state.csv {
+            Some(file) => file,
+            None       => return,
+        };
+
+        let result = timings.iter().try_for_each(|timing| {
+            let gpu_ms = timing.gpu_ms.map(|ms| format!("{:.4}", ms)).unwrap_or_default();
+
+            writeln!(file, "{},{},{:.4},{}", index, timing.label, timing.cpu_ms, gpu_ms)
+        });
+
+        if let Err(e) = result {
+            log::warn!("Stopped logging pass timings: {}", e);
+            state.csv = None;
+        }
+    }
+}
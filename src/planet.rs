@@ -0,0 +1,357 @@
+// A planet drawn as a cube projected onto a sphere, with each of the cube's faces split as a
+// quadtree. Nodes closer to the camera than a few times their own size split into four, so detail
+// follows the camera down to the ground and falls away towards the horizon. Every node is the same
+// grid of vertices instanced over its square of the face, and the vertex shader pushes it out to
+// the sphere and raises it by fractal noise, so the CPU only picks which nodes to draw.
+//
+// Nodes behind the planet's horizon or outside the view are dropped before they're split. Each
+// patch has a skirt hanging down from its edges, hiding the cracks between neighbours of different
+// sizes.
+//
+// Positions are 32 bit floats relative to the world origin, so planets much bigger than a few
+// thousand units across start to shimmer up close.
+
+use cgmath::prelude::*;
+use learn_wgpu_derive::VertexLayout;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    bind_group,
+    bounds::{Frustum, Sphere},
+    camera::Camera,
+    crash_report,
+    renderer::{self, GpuContext},
+    texture::Texture,
+    uniform::{Uniform, UniformBuffer},
+};
+
+// Vertices along each side of a patch, not counting the skirt. Also in planet.wgsl.
+const PATCH_RESOLUTION: u32 = 17;
+
+// Patches the instance buffer holds. Past it, the rest of the quadtree isn't drawn.
+pub const MAX_PATCHES: usize = 2048;
+
+const MAX_DEPTH: u32 = 16;
+
+// Each face's outward normal, and the directions across and up it, with across x up = outward so
+// the grid's triangles wind counter-clockwise from outside
+const CUBE_FACES: [([f32; 3], [f32; 3], [f32; 3]); 6] = [
+    ([ 1.0,  0.0,  0.0], [ 0.0, 0.0, -1.0], [0.0, 1.0,  0.0]),
+    ([-1.0,  0.0,  0.0], [ 0.0, 0.0,  1.0], [0.0, 1.0,  0.0]),
+    ([ 0.0,  1.0,  0.0], [ 1.0, 0.0,  0.0], [0.0, 0.0, -1.0]),
+    ([ 0.0, -1.0,  0.0], [ 1.0, 0.0,  0.0], [0.0, 0.0,  1.0]),
+    ([ 0.0,  0.0,  1.0], [ 1.0, 0.0,  0.0], [0.0, 1.0,  0.0]),
+    ([ 0.0,  0.0, -1.0], [-1.0, 0.0,  0.0], [0.0, 1.0,  0.0]),
+];
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+struct PatchVertex {
+    // From 0.0 to 1.0 across and up the patch
+    #[location(0)]
+    grid:  [f32; 2],
+    // 1.0 for the skirt's lower edge
+    #[location(1)]
+    skirt: f32,
+}
+
+// A node's square of the cube, from `corner` along `across` and `up`
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+#[vertex(instance)]
+struct PatchInstance {
+    #[location(2)]
+    corner: [f32; 3],
+    #[location(3)]
+    across: [f32; 3],
+    #[location(4)]
+    up:     [f32; 3],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct PlanetUniform {
+    center:      [f32; 3],
+    radius:      f32,
+    height:      f32,
+    noise_scale: f32,
+    octaves:     u32,
+    _padding:    f32,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct PlanetSettings {
+    pub center:      cgmath::Point3<f32>,
+    // Of the sea level, with land rising up to `height` above it and seas filling in below
+    pub radius:      f32,
+    pub height:      f32,
+    // Noise features across the planet's radius at the largest octave, each octave after having
+    // twice as many at half the height
+    pub noise_scale: f32,
+    pub octaves:     u32,
+    // Nodes split while the camera is closer than this many times their size
+    pub split:       f32,
+}
+
+impl Default for PlanetSettings {
+    fn default() -> Self {
+        Self {
+            center:      cgmath::Point3::origin(),
+            radius:      100.0,
+            height:      4.0,
+            noise_scale: 2.0,
+            octaves:     6,
+            split:       3.0,
+        }
+    }
+}
+
+pub struct Planet {
+    pub settings:   PlanetSettings,
+    pipeline:       wgpu::RenderPipeline,
+    uniform:        UniformBuffer<PlanetUniform>,
+    bind_group:     wgpu::BindGroup,
+    vertices:       wgpu::Buffer,
+    indices:        wgpu::Buffer,
+    index_count:    u32,
+    instances:      wgpu::Buffer,
+    instance_count: u32,
+}
+
+impl Planet {
+    pub fn shader_source() -> &'static str {
+        concat!(include_str!("camera.wgsl"), include_str!("lights.wgsl"), include_str!("planet.wgsl"))
+    }
+
+    // Drawn with the scene's camera at group 1 and lights at group 2
+    pub fn new(
+        ctx:           &GpuContext,
+        camera_layout: &wgpu::BindGroupLayout,
+        lights_layout: &wgpu::BindGroupLayout,
+        settings:      PlanetSettings,
+    ) -> Self {
+        let device = &ctx.device;
+
+        let (patch_vertices, patch_indices) = patch_mesh();
+
+        let vertices = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label:    Some("Planet Vertex Buffer"),
+            contents: bytemuck::cast_slice(&patch_vertices),
+            usage:    wgpu::BufferUsages::VERTEX,
+        });
+
+        let indices = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label:    Some("Planet Index Buffer"),
+            contents: bytemuck::cast_slice(&patch_indices),
+            usage:    wgpu::BufferUsages::INDEX,
+        });
+
+        let instances = device.create_buffer(&wgpu::BufferDescriptor {
+            label:              Some("Planet Instance Buffer"),
+            size:               (MAX_PATCHES * std::mem::size_of::<PatchInstance>()) as wgpu::BufferAddress,
+            usage:              wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let uniform = UniformBuffer::new(device, "Planet Uniform Buffer");
+
+        let layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT)
+            .build(device, "planet_bind_group_layout");
+
+        let bind_group = bind_group::BindGroupBuilder::new(&layout)
+            .uniform(uniform.buffer())
+            .build(device, "planet_bind_group");
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Planet Shader"),
+            source: wgpu::ShaderSource::Wgsl(Self::shader_source().into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Planet Pipeline Layout"),
+            bind_group_layouts:   &[&layout, camera_layout, lights_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = renderer::create_render_pipeline(
+            device,
+            &pipeline_layout,
+            ctx.config.format,
+            Some(renderer::depth_state(Texture::DEPTH_FORMAT, ctx.depth_mode.compare(), true)),
+            &[PatchVertex::layout(), PatchInstance::layout()],
+            &shader,
+            "Planet Pipeline",
+        );
+
+        Self {
+            settings,
+            pipeline,
+            uniform,
+            bind_group,
+            vertices,
+            indices,
+            index_count:    patch_indices.len() as u32,
+            instances,
+            instance_count: 0,
+        }
+    }
+
+    // Walks the quadtree from `camera`, keeping the nodes in view at the detail they're seen at
+    pub fn update(&mut self, queue: &wgpu::Queue, camera: &Camera) {
+        let settings = self.settings;
+        let frustum  = Frustum::from_view_proj(&camera.build_view_projections_matrix());
+        let horizon  = Horizon::new(&settings, camera.eye);
+
+        let mut patches = Vec::new();
+
+        for (normal, across, up) in CUBE_FACES {
+            let (normal, across, up) = (cgmath::Vector3::from(normal), cgmath::Vector3::from(across), cgmath::Vector3::from(up));
+
+            // The whole face, from -1.0 to 1.0 along both directions
+            let node = PatchInstance {
+                corner: (normal - across - up).into(),
+                across: (across * 2.0).into(),
+                up:     (up * 2.0).into(),
+            };
+
+            select(&settings, camera.eye, &frustum, &horizon, node, 0, &mut patches);
+        }
+
+        self.uniform.write(queue, &PlanetUniform {
+            center:      settings.center.into(),
+            radius:      settings.radius,
+            height:      settings.height,
+            noise_scale: settings.noise_scale,
+            octaves:     settings.octaves,
+            _padding:    0.0,
+        });
+
+        queue.write_buffer(&self.instances, 0, bytemuck::cast_slice(&patches));
+        self.instance_count = patches.len() as u32;
+    }
+
+    // Patches drawn at the last `update`
+    pub fn patch_count(&self) -> u32 {
+        self.instance_count
+    }
+
+    // Expects the lights to be bound at group 2 already, as the scene's draws leave them
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, camera_bind_group: &'a wgpu::BindGroup) {
+        crash_report::set_pipeline(render_pass, &self.pipeline, "Planet Pipeline");
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_bind_group(1, camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertices.slice(..));
+        render_pass.set_vertex_buffer(1, self.instances.slice(..));
+        render_pass.set_index_buffer(self.indices.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.draw_indexed(0..self.index_count, 0, 0..self.instance_count);
+    }
+}
+
+// The sea, which hides whatever is past its horizon from the camera
+struct Horizon {
+    // From the camera to the horizon, or None from inside the sphere, which hides nothing
+    distance: Option<f32>,
+    // From the highest peak to the horizon
+    peak:     f32,
+}
+
+impl Horizon {
+    fn new(settings: &PlanetSettings, eye: cgmath::Point3<f32>) -> Self {
+        let occluder = settings.radius;
+        let camera   = eye.distance(settings.center);
+        let peak     = settings.radius + settings.height;
+
+        Self {
+            distance: (camera > occluder).then(|| (camera * camera - occluder * occluder).sqrt()),
+            peak:     (peak * peak - occluder * occluder).sqrt(),
+        }
+    }
+
+    // Whether any of `bounds` can reach over the horizon into view
+    fn hides(&self, eye: cgmath::Point3<f32>, bounds: &Sphere) -> bool {
+        match self.distance {
+            Some(distance) => eye.distance(bounds.center) - bounds.radius > distance + self.peak,
+            None           => false,
+        }
+    }
+}
+
+// Where a point on the cube ends up at sea level
+fn to_sphere(settings: &PlanetSettings, cube: cgmath::Vector3<f32>) -> cgmath::Point3<f32> {
+    settings.center + cube.normalize() * settings.radius
+}
+
+// Adds the node to `patches`, or its children if the camera is close enough to split it
+fn select(
+    settings: &PlanetSettings,
+    eye:      cgmath::Point3<f32>,
+    frustum:  &Frustum,
+    horizon:  &Horizon,
+    node:     PatchInstance,
+    depth:    u32,
+    patches:  &mut Vec<PatchInstance>,
+) {
+    if patches.len() >= MAX_PATCHES {
+        return;
+    }
+
+    let corner = cgmath::Vector3::from(node.corner);
+    let across = cgmath::Vector3::from(node.across);
+    let up     = cgmath::Vector3::from(node.up);
+
+    // Around the node's corners on the sphere, raised and lowered as far as the terrain goes
+    let center = to_sphere(settings, corner + (across + up) * 0.5);
+    let reach  = [corner, corner + across, corner + up, corner + across + up].iter()
+        .map(|&c| to_sphere(settings, c).distance(center))
+        .fold(0.0, f32::max);
+    let bounds = Sphere { center, radius: reach + settings.height };
+
+    if horizon.hides(eye, &bounds) || !frustum.intersects_sphere(&bounds) {
+        return;
+    }
+
+    if depth >= MAX_DEPTH || eye.distance(center) > reach * 2.0 * settings.split {
+        patches.push(node);
+        return;
+    }
+
+    let (half_across, half_up) = (across * 0.5, up * 0.5);
+
+    for (x, y) in [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)] {
+        let child = PatchInstance {
+            corner: (corner + half_across * x + half_up * y).into(),
+            across: half_across.into(),
+            up:     half_up.into(),
+        };
+
+        select(settings, eye, frustum, horizon, child, depth + 1, patches);
+    }
+}
+
+// A grid of `PATCH_RESOLUTION` vertices a side, with a ring of skirt vertices around it under its
+// edge vertices
+fn patch_mesh() -> (Vec<PatchVertex>, Vec<u32>) {
+    let side = PATCH_RESOLUTION + 2;
+    let last = (PATCH_RESOLUTION - 1) as f32;
+
+    let vertices = (0..side * side)
+        .map(|i| {
+            let (x, y) = (i % side, i / side);
+            let edge   = x == 0 || y == 0 || x == side - 1 || y == side - 1;
+            let grid   = |c: u32| (c.clamp(1, PATCH_RESOLUTION) - 1) as f32 / last;
+
+            PatchVertex {
+                grid:  [grid(x), grid(y)],
+                skirt: if edge { 1.0 } else { 0.0 },
+            }
+        })
+        .collect();
+
+    let indices = (0..side - 1)
+        .flat_map(|y| (0..side - 1).map(move |x| y * side + x))
+        .flat_map(|i| [i, i + 1, i + side + 1, i, i + side + 1, i + side])
+        .collect();
+
+    (vertices, indices)
+}
@@ -0,0 +1,128 @@
+// Quadtree planet patches, see planet.rs. Each patch's grid is laid over its square of the cube,
+// pushed out to the sphere and raised by fractal noise, with seas filling in below sea level. Gets
+// `camera.wgsl` and `lights.wgsl` prepended.
+
+struct PlanetUniform {
+    center:      vec3<f32>,
+    radius:      f32,
+    height:      f32,
+    noise_scale: f32,
+    octaves:     u32,
+}
+
+@group(0) @binding(0)
+var<uniform> planet: PlanetUniform;
+
+struct VertexInput {
+    @location(0) grid:  vec2<f32>,
+    @location(1) skirt: f32,
+}
+
+struct InstanceInput {
+    @location(2) corner: vec3<f32>,
+    @location(3) across: vec3<f32>,
+    @location(4) up:     vec3<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_normal:        vec3<f32>,
+    @location(1) world_position:      vec3<f32>,
+    // From -1.0 at the deepest sea floor to 1.0 at the highest peak
+    @location(2) elevation:           f32,
+}
+
+fn planet_hash(p: vec3<f32>) -> f32 {
+    return fract(sin(dot(p, vec3<f32>(127.1, 311.7, 74.7))) * 43758.5453);
+}
+
+// Smoothly interpolated random values at integer lattice points, from 0.0 to 1.0
+fn value_noise(p: vec3<f32>) -> f32 {
+    let cell = floor(p);
+    let f    = fract(p);
+    let t    = f * f * (3.0 - 2.0 * f);
+
+    let x00 = mix(planet_hash(cell),                            planet_hash(cell + vec3<f32>(1.0, 0.0, 0.0)), t.x);
+    let x10 = mix(planet_hash(cell + vec3<f32>(0.0, 1.0, 0.0)), planet_hash(cell + vec3<f32>(1.0, 1.0, 0.0)), t.x);
+    let x01 = mix(planet_hash(cell + vec3<f32>(0.0, 0.0, 1.0)), planet_hash(cell + vec3<f32>(1.0, 0.0, 1.0)), t.x);
+    let x11 = mix(planet_hash(cell + vec3<f32>(0.0, 1.0, 1.0)), planet_hash(cell + vec3<f32>(1.0, 1.0, 1.0)), t.x);
+
+    return mix(mix(x00, x10, t.y), mix(x01, x11, t.y), t.z);
+}
+
+// Octaves of noise over the unit sphere, each twice as fine at half the height, from -1.0 to 1.0
+fn elevation(direction: vec3<f32>) -> f32 {
+    var sum       = 0.0;
+    var amplitude = 0.5;
+    var frequency = planet.noise_scale;
+
+    for (var i = 0u; i < planet.octaves; i = i + 1u) {
+        sum       = sum + (value_noise(direction * frequency) * 2.0 - 1.0) * amplitude;
+        amplitude = amplitude * 0.5;
+        frequency = frequency * 2.0;
+    }
+
+    return clamp(sum * 2.0, -1.0, 1.0);
+}
+
+// Where a point on the cube lands on the ground, or the sea's surface over it
+fn surface(cube: vec3<f32>) -> vec3<f32> {
+    let direction = normalize(cube);
+
+    return planet.center + direction * (planet.radius + max(elevation(direction), 0.0) * planet.height);
+}
+
+@vertex
+fn vs_main(model: VertexInput, instance: InstanceInput) -> VertexOutput {
+    let cube      = instance.corner + instance.across * model.grid.x + instance.up * model.grid.y;
+    let direction = normalize(cube);
+
+    // One grid step, a side of PATCH_RESOLUTION in planet.rs, for the normal from the surface
+    // around the vertex
+    let step     = 1.0 / 16.0;
+    let position = surface(cube);
+    let right    = surface(cube + instance.across * step) - position;
+    let above    = surface(cube + instance.up * step) - position;
+
+    // Skirts hang a tenth of the patch's width down, deeper than any crack next to it
+    let skirt = model.skirt * length(instance.across) * planet.radius * 0.1;
+
+    var out: VertexOutput;
+
+    out.world_normal   = normalize(cross(right, above));
+    out.world_position = position - direction * skirt;
+    out.elevation      = elevation(direction);
+    out.clip_position  = camera.view_proj * vec4<f32>(out.world_position, 1.0);
+
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    if (dot(camera.clip_plane, vec4<f32>(in.world_position, 1.0)) < 0.0) {
+        discard;
+    }
+
+    let normal = normalize(in.world_normal);
+    let up     = normalize(in.world_position - planet.center);
+    let slope  = 1.0 - dot(normal, up);
+
+    // Sea, then sand along the shore, grass, rock on steep or high ground and snow on the peaks
+    let sea   = mix(vec3<f32>(0.05, 0.15, 0.4), vec3<f32>(0.1, 0.35, 0.55), clamp(in.elevation + 1.0, 0.0, 1.0));
+    let sand  = vec3<f32>(0.76, 0.7, 0.5);
+    let grass = vec3<f32>(0.2, 0.45, 0.15);
+    let rock  = vec3<f32>(0.4, 0.37, 0.35);
+    let snow  = vec3<f32>(0.95, 0.95, 0.97);
+
+    var albedo = mix(sand, grass, smoothstep(0.02, 0.08, in.elevation));
+    albedo     = mix(albedo, rock, max(smoothstep(0.4, 0.6, in.elevation), smoothstep(0.1, 0.3, slope)));
+    albedo     = mix(albedo, snow, smoothstep(0.75, 0.85, in.elevation));
+
+    if (in.elevation <= 0.0) {
+        albedo = sea;
+    }
+
+    let light = probe_lighting(in.world_position, normal) + spot_lighting(in.world_position, normal);
+
+    return vec4<f32>(albedo * light, 1.0);
+}
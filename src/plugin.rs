@@ -0,0 +1,78 @@
+use std::time::Duration;
+
+use winit::event::WindowEvent;
+
+use crate::{
+    input::Input,
+    pass::RenderLayers,
+    renderer::{Frame, GpuContext},
+};
+
+// Optional engine features (overlays, physics, particles, ...) that hook into the runner
+// alongside the app. Disabled plugins still receive `setup` but are skipped every frame.
+pub trait Plugin: 'static {
+    fn name(&self) -> &'static str;
+
+    fn is_enabled(&self) -> bool {
+        true
+    }
+
+    fn setup(&mut self, _ctx: &mut GpuContext) {}
+
+    // Plugins see events before the app. Return true to consume the event.
+    fn input(&mut self, _ctx: &GpuContext, _event: &WindowEvent) -> bool {
+        false
+    }
+
+    fn update(&mut self, _dt: Duration, _input: &Input) {}
+
+    // Record uploads or extra passes into `frame` and register draws into `layers`
+    fn render<'a>(&'a mut self, _frame: &mut Frame, _layers: &mut RenderLayers<'a>) {}
+}
+
+#[derive(Default)]
+pub struct Plugins {
+    plugins: Vec<Box<dyn Plugin>>,
+}
+
+impl Plugins {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add<P: Plugin>(&mut self, plugin: P) -> &mut Self {
+        log::info!("Registered plugin {}", plugin.name());
+        self.plugins.push(Box::new(plugin));
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Plugin> {
+        self.plugins.iter().find(|p| p.name() == name).map(|p| p.as_ref())
+    }
+
+    pub fn setup(&mut self, ctx: &mut GpuContext) {
+        for plugin in &mut self.plugins {
+            plugin.setup(ctx);
+        }
+    }
+
+    pub fn input(&mut self, ctx: &GpuContext, event: &WindowEvent) -> bool {
+        self.plugins.iter_mut()
+            .filter(|p| p.is_enabled())
+            .any(|p| p.input(ctx, event))
+    }
+
+    pub fn update(&mut self, dt: Duration, input: &Input) {
+        for plugin in self.plugins.iter_mut().filter(|p| p.is_enabled()) {
+            plugin.update(dt, input);
+        }
+    }
+
+    pub fn render<'a>(&'a mut self, frame: &mut Frame, layers: &mut RenderLayers<'a>) {
+        for plugin in self.plugins.iter_mut() {
+            if plugin.is_enabled() {
+                plugin.render(frame, layers);
+            }
+        }
+    }
+}
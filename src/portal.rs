@@ -0,0 +1,227 @@
+// Portals: a rectangle that shows the scene as seen through another rectangle somewhere else.
+// Each frame the scene is rendered again from a virtual camera, the main camera carried from the
+// entrance to the exit, with an oblique near plane on the exit so nothing behind it gets in the
+// way. The result is drawn on the entrance, sampled where the main camera sees each pixel.
+//
+// Portals aren't drawn in their own views, so looking through one at another shows the scene
+// behind it rather than recursing.
+
+use cgmath::prelude::*;
+use wgpu::util::DeviceExt;
+
+use learn_wgpu_derive::VertexLayout;
+
+use crate::{
+    bind_group,
+    camera::Camera,
+    crash_report,
+    pass::{Drawable, RenderLayer},
+    renderer::{self, GpuContext},
+    scene::Scene,
+    texture::Texture,
+    thumbnail,
+    uniform::{Uniform, UniformBuffer},
+};
+
+// Where one end of a portal is and which way it faces. Seen from the front, `up` is up.
+#[derive(Debug, Copy, Clone)]
+pub struct PortalFrame {
+    pub center: cgmath::Point3<f32>,
+    pub normal: cgmath::Vector3<f32>,
+    pub up:     cgmath::Vector3<f32>,
+}
+
+impl PortalFrame {
+    // From the frame's own space, where it faces +z with +y up, to world space
+    fn transform(&self) -> cgmath::Matrix4<f32> {
+        let normal = self.normal.normalize();
+        let right  = self.up.cross(normal).normalize();
+        let up     = normal.cross(right);
+
+        cgmath::Matrix4::from_cols(right.extend(0.0), up.extend(0.0), normal.extend(0.0), self.center.to_homogeneous())
+    }
+
+    // The frame's plane, keeping what's in front of it
+    fn plane(&self) -> cgmath::Vector4<f32> {
+        let normal = self.normal.normalize();
+
+        normal.extend(-normal.dot(self.center.to_vec()))
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+struct PortalVertex {
+    #[location(0)]
+    position: [f32; 3],
+    // 0.0 to 1.0 across the quad, for the rim
+    #[location(1)]
+    uv:       [f32; 2],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct PortalUniform {
+    view_proj: [[f32; 4]; 4],
+    rim_color: [f32; 4],
+    rim:       [f32; 2],
+    _padding:  [f32; 2],
+}
+
+pub struct Portal {
+    pub entrance:  PortalFrame,
+    pub exit:      PortalFrame,
+    pub rim_color: [f32; 3],
+    // Width of the glowing edge, in world units
+    pub rim:       f32,
+    half_size:     (f32, f32),
+    pipeline:      wgpu::RenderPipeline,
+    bind_group:    wgpu::BindGroup,
+    uniform:       UniformBuffer<PortalUniform>,
+    vertices:      wgpu::Buffer,
+    view:          wgpu::TextureView,
+    depth:         Texture,
+}
+
+impl Portal {
+    const SIZE: u32 = 1024;
+
+    // `half_size` is the half width and half height of both ends
+    pub fn new(ctx: &GpuContext, entrance: PortalFrame, exit: PortalFrame, half_size: (f32, f32)) -> Self {
+        let device = &ctx.device;
+
+        // Wound counter-clockwise seen from the front, so it's culled from behind
+        let transform = entrance.transform();
+        let corner    = |s: f32, t: f32| PortalVertex {
+            position: cgmath::Point3::from_homogeneous(transform * cgmath::Vector4::new(s * half_size.0, t * half_size.1, 0.0, 1.0)).into(),
+            uv:       [s * 0.5 + 0.5, 0.5 - t * 0.5],
+        };
+        let vertices = [
+            corner(-1.0, -1.0), corner(1.0, -1.0), corner(1.0, 1.0),
+            corner(-1.0, -1.0), corner(1.0, 1.0),  corner(-1.0, 1.0),
+        ];
+
+        let vertices = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label:    Some("Portal Vertex Buffer"),
+            contents: bytemuck::cast_slice(&vertices),
+            usage:    wgpu::BufferUsages::VERTEX,
+        });
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label:           Some("Portal View"),
+            size:            wgpu::Extent3d { width: Self::SIZE, height: Self::SIZE, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count:    1,
+            dimension:       wgpu::TextureDimension::D2,
+            format:          ctx.config.format,
+            usage:           wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label:          Some("Portal Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter:     wgpu::FilterMode::Linear,
+            min_filter:     wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let uniform = UniformBuffer::new(device, "Portal Uniform Buffer");
+
+        let layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT)
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .sampler(wgpu::ShaderStages::FRAGMENT)
+            .build(device, "portal_bind_group_layout");
+
+        let bind_group = bind_group::BindGroupBuilder::new(&layout)
+            .uniform(uniform.buffer())
+            .texture(&view)
+            .sampler(&sampler)
+            .build(device, "portal_bind_group");
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Portal Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("portal.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Portal Pipeline Layout"),
+            bind_group_layouts:   &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        // Passes equal depths so it draws whether or not the depth prepass ran
+        let pipeline = renderer::create_render_pipeline(
+            device,
+            &pipeline_layout,
+            ctx.config.format,
+            Some(renderer::depth_state(Texture::DEPTH_FORMAT, ctx.depth_mode.compare_or_equal(), true)),
+            &[PortalVertex::layout()],
+            &shader,
+            "Portal Pipeline",
+        );
+
+        Self {
+            entrance,
+            exit,
+            rim_color: [0.2, 0.6, 1.0],
+            rim:       0.1,
+            half_size,
+            pipeline,
+            bind_group,
+            uniform,
+            vertices,
+            view,
+            depth:     Texture::create_depth_texture(device, Self::SIZE, Self::SIZE, "portal_depth_texture"),
+        }
+    }
+
+    // Where `camera` would be looking out of the exit. Walking into the entrance's front comes
+    // out of the exit's front, hence the half turn between the two.
+    pub fn virtual_camera(&self, camera: &Camera) -> Camera {
+        let entrance = self.entrance.transform();
+        let through  = self.exit.transform()
+            * cgmath::Matrix4::from_angle_y(cgmath::Deg(180.0))
+            * entrance.invert().unwrap_or_else(cgmath::Matrix4::identity);
+
+        Camera {
+            eye:    cgmath::Point3::from_homogeneous(through * camera.eye.to_homogeneous()),
+            target: cgmath::Point3::from_homogeneous(through * camera.target.to_homogeneous()),
+            up:     through.transform_vector(camera.up),
+            ..camera.clone()
+        }
+    }
+
+    // Renders `scene` as seen through the portal from `camera`. Submitted straight away, so call
+    // it before the frame's own camera is uploaded.
+    pub fn render(&self, ctx: &GpuContext, scene: &dyn Scene, camera: &Camera) {
+        let through = self.virtual_camera(camera);
+
+        self.uniform.write(&ctx.queue, &PortalUniform {
+            view_proj: camera.build_view_projections_matrix().into(),
+            rim_color: [self.rim_color[0], self.rim_color[1], self.rim_color[2], 1.0],
+            rim:       [self.rim / (self.half_size.0 * 2.0), self.rim / (self.half_size.1 * 2.0)],
+            _padding:  [0.0; 2],
+        });
+
+        // Looking at the back of the entrance there's nothing to see, and the virtual camera
+        // would be in front of the exit where the clip plane can't help
+        if self.entrance.plane().dot(camera.eye.to_homogeneous()) <= 0.0 {
+            return;
+        }
+
+        thumbnail::render_scene(ctx, scene, &through, Some(self.exit.plane()), &self.view, &self.depth.view, "Portal Encoder");
+    }
+}
+
+impl Drawable for Portal {
+    fn draw<'a>(&'a self, _layer: RenderLayer, render_pass: &mut wgpu::RenderPass<'a>) {
+        crash_report::set_pipeline(render_pass, &self.pipeline, "Portal Pipeline");
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertices.slice(..));
+        render_pass.draw(0..6, 0..1);
+    }
+}
@@ -0,0 +1,46 @@
+// One end of a portal: the view through it, which was rendered with the same projection as the
+// main camera, so each pixel samples the view where it is on screen, inside a glowing rim
+
+struct PortalUniform {
+    view_proj: mat4x4<f32>,
+    rim_color: vec4<f32>,
+    // Rim width as a fraction of the quad's size, in x and y
+    rim:       vec2<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> portal: PortalUniform;
+@group(0) @binding(1)
+var t_view: texture_2d<f32>;
+@group(0) @binding(2)
+var s_view: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) screen: vec4<f32>,
+    @location(1) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@location(0) position: vec3<f32>, @location(1) uv: vec2<f32>) -> VertexOutput {
+    var out: VertexOutput;
+
+    out.clip_position = portal.view_proj * vec4<f32>(position, 1.0);
+    out.screen        = out.clip_position;
+    out.uv            = uv;
+
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let ndc   = in.screen.xy / in.screen.w;
+    let uv    = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+    let color = textureSampleLevel(t_view, s_view, uv, 0.0).rgb;
+
+    // Brightest right at the edge, fading inwards
+    let edge = min(min(in.uv, 1.0 - in.uv) / portal.rim, vec2<f32>(1.0));
+    let glow = 1.0 - min(edge.x, edge.y);
+
+    return vec4<f32>(mix(color, portal.rim_color.rgb, glow * glow), 1.0);
+}
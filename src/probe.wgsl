@@ -0,0 +1,45 @@
+// Looks up reflection probe faces, which are stored as a 2D array. Prepended to shaders that
+// sample a probe.
+
+// Forward and up of the camera that rendered each probe face, as in reflection.rs
+fn probe_forward(face: i32) -> vec3<f32> {
+    switch (face) {
+        case 0: { return vec3<f32>(1.0, 0.0, 0.0); }
+        case 1: { return vec3<f32>(-1.0, 0.0, 0.0); }
+        case 2: { return vec3<f32>(0.0, 1.0, 0.0); }
+        case 3: { return vec3<f32>(0.0, -1.0, 0.0); }
+        case 4: { return vec3<f32>(0.0, 0.0, 1.0); }
+        default: { return vec3<f32>(0.0, 0.0, -1.0); }
+    }
+}
+
+fn probe_up(face: i32) -> vec3<f32> {
+    switch (face) {
+        case 2: { return vec3<f32>(0.0, 0.0, -1.0); }
+        case 3: { return vec3<f32>(0.0, 0.0, 1.0); }
+        default: { return vec3<f32>(0.0, 1.0, 0.0); }
+    }
+}
+
+// The face a direction lands on and where, as (u, v, face)
+fn probe_uv(dir: vec3<f32>) -> vec3<f32> {
+    let a = abs(dir);
+
+    var face = select(5, 4, dir.z > 0.0);
+    if (a.x >= a.y && a.x >= a.z) {
+        face = select(1, 0, dir.x > 0.0);
+    } else if (a.y >= a.z) {
+        face = select(3, 2, dir.y > 0.0);
+    }
+
+    let forward = probe_forward(face);
+    let up      = probe_up(face);
+    let right   = cross(forward, up);
+    let depth   = dot(dir, forward);
+
+    // A 90 degree projection along the face's camera
+    let ndc = vec2<f32>(dot(dir, right), dot(dir, up)) / depth;
+
+    return vec3<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5, f32(face));
+}
+
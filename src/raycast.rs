@@ -0,0 +1,366 @@
+// Ray queries in bulk on the GPU, for when there are too many to walk the BVH one by one on the
+// CPU, like line-of-sight checks for a crowd. Each invocation walks the same hierarchy as
+// `Bvh::raycast_hit` for one ray and tests the same boxes, so the results match it, which the
+// tests below check when there's a GPU to run on. Reading them back waits on the GPU, and WebGL
+// has no compute shaders, so this is native only in practice.
+
+use anyhow::*;
+use cgmath::prelude::*;
+
+use crate::{
+    bind_group,
+    bounds::{Bvh, Hit, Ray},
+    compute,
+    crash_report,
+    uniform::Uniform,
+};
+
+const WORKGROUP_SIZE: u32 = 64;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+#[uniform(storage)]
+struct NodeRaw {
+    bounds_min: [f32; 4],
+    bounds_max: [f32; 4],
+    // -1 for inner nodes
+    item:       i32,
+    left:       u32,
+    right:      u32,
+    _padding:   u32,
+    // A leaf's item, as its box and the transform into the box's space. All zeros for inner nodes
+    // and items that can't be hit.
+    local_min:  [f32; 4],
+    local_max:  [f32; 4],
+    to_local:   [[f32; 4]; 4],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+#[uniform(storage)]
+struct RayRaw {
+    origin:    [f32; 4],
+    direction: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+#[uniform(storage)]
+struct HitRaw {
+    // w is the distance along the ray
+    position: [f32; 4],
+    normal:   [f32; 3],
+    // -1 for a miss
+    entity:   i32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct RaycastParams {
+    ray_count:  u32,
+    node_count: u32,
+    _padding:   [u32; 2],
+}
+
+pub struct GpuRaycaster {
+    pipeline:      wgpu::ComputePipeline,
+    layout:        wgpu::BindGroupLayout,
+    bind_group:    wgpu::BindGroup,
+    params_buffer: wgpu::Buffer,
+    node_buffer:   wgpu::Buffer,
+    ray_buffer:    wgpu::Buffer,
+    hit_buffer:    wgpu::Buffer,
+    readback:      wgpu::Buffer,
+    node_capacity: usize,
+    ray_capacity:  usize,
+    node_count:    u32,
+}
+
+impl GpuRaycaster {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::COMPUTE)
+            .storage(wgpu::ShaderStages::COMPUTE, true)
+            .storage(wgpu::ShaderStages::COMPUTE, true)
+            .storage(wgpu::ShaderStages::COMPUTE, false)
+            .build(device, "raycast_bind_group_layout");
+
+        let pipeline = compute::create_pipeline(device, &layout, include_str!("raycast.wgsl"), "Raycast Pipeline");
+
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label:              Some("Raycast Params Buffer"),
+            size:               std::mem::size_of::<RaycastParams>() as wgpu::BufferAddress,
+            usage:              wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        // Storage bindings can't be empty, so there's always room for one of each
+        let node_buffer                        = create_node_buffer(device, 1);
+        let (ray_buffer, hit_buffer, readback) = create_ray_buffers(device, 1);
+        let bind_group                         = create_bind_group(device, &layout, &params_buffer, &node_buffer, &ray_buffer, &hit_buffer);
+
+        Self {
+            pipeline,
+            layout,
+            bind_group,
+            params_buffer,
+            node_buffer,
+            ray_buffer,
+            hit_buffer,
+            readback,
+            node_capacity: 1,
+            ray_capacity:  1,
+            node_count:    0,
+        }
+    }
+
+    // Uploads the hierarchy rays are cast against, which is kept until the next call, so only
+    // call it again when the scene has changed
+    pub fn set_bvh(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, bvh: &Bvh) {
+        let nodes = bvh.nodes()
+            .map(|(bounds, item, left, right)| {
+                let shape = item.map(|item| bvh.items()[item])
+                    .and_then(|obb| Some((obb.bounds, obb.transform.invert()?)));
+
+                NodeRaw {
+                    bounds_min: bounds.min.to_homogeneous().into(),
+                    bounds_max: bounds.max.to_homogeneous().into(),
+                    item:       item.map_or(-1, |item| item as i32),
+                    left:       left as u32,
+                    right:      right as u32,
+                    _padding:   0,
+                    local_min:  shape.map_or([0.0; 4], |(local, _)| local.min.to_homogeneous().into()),
+                    local_max:  shape.map_or([0.0; 4], |(local, _)| local.max.to_homogeneous().into()),
+                    to_local:   shape.map_or([[0.0; 4]; 4], |(_, to_local)| to_local.into()),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if nodes.len() > self.node_capacity {
+            self.node_capacity = nodes.len().next_power_of_two();
+            self.node_buffer   = create_node_buffer(device, self.node_capacity);
+            self.rebuild_bind_group(device);
+        }
+
+        queue.write_buffer(&self.node_buffer, 0, bytemuck::cast_slice(&nodes));
+        self.node_count = nodes.len() as u32;
+    }
+
+    // The closest hit along each ray, in the same order. Blocks until the GPU is done.
+    pub fn cast(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, rays: &[Ray]) -> Result<Vec<Option<Hit>>> {
+        if rays.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        if rays.len() > self.ray_capacity {
+            self.ray_capacity = rays.len().next_power_of_two();
+
+            (self.ray_buffer, self.hit_buffer, self.readback) = create_ray_buffers(device, self.ray_capacity);
+            self.rebuild_bind_group(device);
+        }
+
+        let raw = rays.iter()
+            .map(|ray| RayRaw {
+                origin:    ray.origin.to_homogeneous().into(),
+                direction: ray.direction.extend(0.0).into(),
+            })
+            .collect::<Vec<_>>();
+
+        let params = RaycastParams {
+            ray_count:  rays.len() as u32,
+            node_count: self.node_count,
+            _padding:   [0; 2],
+        };
+
+        queue.write_buffer(&self.ray_buffer, 0, bytemuck::cast_slice(&raw));
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[params]));
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Raycast Encoder"),
+        });
+
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Raycast Pass"),
+            });
+
+            crash_report::set_compute_pipeline(&mut compute_pass, &self.pipeline, "Raycast Pipeline");
+            compute_pass.set_bind_group(0, &self.bind_group, &[]);
+            compute_pass.dispatch_workgroups((rays.len() as u32).div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+
+        let size = (rays.len() * std::mem::size_of::<HitRaw>()) as wgpu::BufferAddress;
+
+        encoder.copy_buffer_to_buffer(&self.hit_buffer, 0, &self.readback, 0, size);
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let slice              = self.readback.slice(..size);
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        slice.map_async(wgpu::MapMode::Read, move |result| sender.send(result).unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        receiver.recv()??;
+
+        let hits = {
+            let data = slice.get_mapped_range();
+
+            bytemuck::cast_slice::<u8, HitRaw>(&data)
+                .iter()
+                .map(|hit| (hit.entity >= 0).then(|| Hit {
+                    entity:   hit.entity as usize,
+                    distance: hit.position[3],
+                    position: cgmath::Point3::new(hit.position[0], hit.position[1], hit.position[2]),
+                    normal:   hit.normal.into(),
+                }))
+                .collect()
+        };
+        self.readback.unmap();
+
+        Ok(hits)
+    }
+
+    fn rebuild_bind_group(&mut self, device: &wgpu::Device) {
+        self.bind_group = create_bind_group(device, &self.layout, &self.params_buffer, &self.node_buffer, &self.ray_buffer, &self.hit_buffer);
+    }
+}
+
+fn create_node_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label:              Some("Raycast Node Buffer"),
+        size:               (capacity * std::mem::size_of::<NodeRaw>()) as wgpu::BufferAddress,
+        usage:              wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+// Rays, hits and the buffer hits are read back through
+fn create_ray_buffers(device: &wgpu::Device, capacity: usize) -> (wgpu::Buffer, wgpu::Buffer, wgpu::Buffer) {
+    let create_buffer = |label, size, usage| device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size:  (capacity * size) as wgpu::BufferAddress,
+        usage,
+        mapped_at_creation: false,
+    });
+
+    (
+        create_buffer("Raycast Ray Buffer", std::mem::size_of::<RayRaw>(), wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST),
+        create_buffer("Raycast Hit Buffer", std::mem::size_of::<HitRaw>(), wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC),
+        create_buffer("Raycast Readback Buffer", std::mem::size_of::<HitRaw>(), wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ),
+    )
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    params: &wgpu::Buffer,
+    nodes:  &wgpu::Buffer,
+    rays:   &wgpu::Buffer,
+    hits:   &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    bind_group::BindGroupBuilder::new(layout)
+        .uniform(params)
+        .storage(nodes)
+        .storage(rays)
+        .storage(hits)
+        .build(device, "raycast_bind_group")
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Deg, Matrix4, Point3, Vector3};
+
+    use super::*;
+    use crate::bounds::{Aabb, Obb};
+
+    // A headless device, or none where there's no GPU to run on, like the frame benchmark's
+    fn device() -> Option<(wgpu::Device, wgpu::Queue)> {
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let adapter  = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference:       wgpu::PowerPreference::default(),
+            compatible_surface:     None,
+            force_fallback_adapter: false,
+        }))?;
+
+        pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                features: wgpu::Features::empty(),
+                limits:   wgpu::Limits::default(),
+                label:    Some("Raycast Test Device"),
+            },
+            None,
+        )).ok()
+    }
+
+    // A grid of unit cubes, each turned and stretched differently, and one flattened to nothing
+    fn scene() -> Bvh {
+        let cube      = Aabb { min: Point3::new(-0.5, -0.5, -0.5), max: Point3::new(0.5, 0.5, 0.5) };
+        let mut items = (0..16)
+            .map(|i| {
+                let (x, z)    = ((i % 4) as f32 * 3.0 - 4.5, (i / 4) as f32 * 3.0 - 4.5);
+                let transform = Matrix4::from_translation(Vector3::new(x, 0.0, z))
+                    * Matrix4::from_angle_y(Deg(i as f32 * 23.0))
+                    * Matrix4::from_angle_x(Deg(i as f32 * 11.0))
+                    * Matrix4::from_nonuniform_scale(1.0 + (i % 3) as f32 * 0.5, 1.0, 1.0 + (i % 2) as f32);
+
+                Obb::new(cube, transform)
+            })
+            .collect::<Vec<_>>();
+
+        items.push(Obb::new(cube, Matrix4::from_nonuniform_scale(1.0, 0.0, 1.0)));
+
+        Bvh::build_oriented(&items)
+    }
+
+    // Down onto the grid at a slant from above, spread wider than it so some miss, and one from
+    // inside a cube
+    fn rays() -> Vec<Ray> {
+        let mut rays = (0..32 * 32)
+            .map(|i| Ray {
+                origin:    Point3::new((i % 32) as f32 * 0.41 - 6.5, 8.0, (i / 32) as f32 * 0.43 - 6.7),
+                direction: Vector3::new(0.13, -1.0, 0.07),
+            })
+            .collect::<Vec<_>>();
+
+        rays.push(Ray { origin: Point3::new(-4.5, 0.0, -4.5), direction: Vector3::new(0.3, 0.2, 1.0) });
+
+        rays
+    }
+
+    #[test]
+    fn matches_the_cpu() {
+        let (device, queue) = match device() {
+            Some(device) => device,
+            None         => {
+                eprintln!("No GPU to run on, skipping");
+                return;
+            }
+        };
+
+        let bvh  = scene();
+        let rays = rays();
+
+        let mut raycaster = GpuRaycaster::new(&device);
+        raycaster.set_bvh(&device, &queue, &bvh);
+
+        let gpu = raycaster.cast(&device, &queue, &rays).unwrap();
+
+        assert_eq!(gpu.len(), rays.len());
+        assert!(gpu.iter().filter(|hit| hit.is_some()).count() > rays.len() / 8);
+        assert!(gpu.iter().any(|hit| hit.is_none()));
+
+        for (ray, gpu) in rays.iter().zip(gpu) {
+            let cpu = bvh.raycast_hit(ray);
+
+            match (cpu, gpu) {
+                (Some(cpu), Some(gpu)) => {
+                    assert_eq!(cpu.entity, gpu.entity, "{:?}", ray);
+                    assert!((cpu.distance - gpu.distance).abs() < 1e-3, "{:?}: {} and {}", ray, cpu.distance, gpu.distance);
+                    assert!((cpu.position - gpu.position).magnitude() < 1e-3, "{:?}", ray);
+                    assert!((cpu.normal - gpu.normal).magnitude() < 1e-3, "{:?}: {:?} and {:?}", ray, cpu.normal, gpu.normal);
+                }
+                (None, None)           => {}
+                (cpu, gpu)             => panic!("{:?}: {:?} on the CPU, {:?} on the GPU", ray, cpu, gpu),
+            }
+        }
+    }
+}
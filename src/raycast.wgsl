@@ -0,0 +1,162 @@
+// Casts one ray per invocation against a BVH of axis-aligned boxes, then against the boxes of the
+// items at the leaves it reaches, keeping the closest hit. The traversal and tests are the ones
+// `Bvh::raycast_hit` does on the CPU.
+
+struct Params {
+    ray_count:  u32,
+    node_count: u32,
+}
+
+struct Node {
+    bounds_min: vec4<f32>,
+    bounds_max: vec4<f32>,
+    // -1 for inner nodes
+    item:       i32,
+    left:       u32,
+    right:      u32,
+    // A leaf's item, as its box and the transform into the box's space. All zeros for items that
+    // can't be hit.
+    local_min:  vec4<f32>,
+    local_max:  vec4<f32>,
+    to_local:   mat4x4<f32>,
+}
+
+struct Ray {
+    origin:    vec4<f32>,
+    direction: vec4<f32>,
+}
+
+struct Hit {
+    // w is the distance along the ray
+    position: vec4<f32>,
+    normal:   vec3<f32>,
+    // -1 for a miss
+    entity:   i32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: Params;
+@group(0) @binding(1)
+var<storage, read> nodes: array<Node>;
+@group(0) @binding(2)
+var<storage, read> rays: array<Ray>;
+@group(0) @binding(3)
+var<storage, read_write> hits: array<Hit>;
+
+// Distance to where the ray enters the box, 0.0 starting inside, or -1.0 for a miss
+fn intersect(origin: vec3<f32>, inv_direction: vec3<f32>, bounds_min: vec3<f32>, bounds_max: vec3<f32>) -> f32 {
+    let t0    = (bounds_min - origin) * inv_direction;
+    let t1    = (bounds_max - origin) * inv_direction;
+    let near  = min(t0, t1);
+    let far   = max(t0, t1);
+    let t_min = max(max(near.x, near.y), max(near.z, 0.0));
+    let t_max = min(min(far.x, far.y), far.z);
+
+    return select(-1.0, t_min, t_min <= t_max);
+}
+
+// Distance along the ray to a leaf's item in x, as `intersect` gives it, and the world normal of
+// the face the ray enters through in yzw
+fn intersect_item(origin: vec3<f32>, direction: vec3<f32>, node: Node) -> vec4<f32> {
+    // An affine transform always has 1.0 there, so this is an item that can't be hit
+    if (node.to_local[3][3] == 0.0) {
+        return vec4<f32>(-1.0, 0.0, 0.0, 0.0);
+    }
+
+    let local_origin    = (node.to_local * vec4<f32>(origin, 1.0)).xyz;
+    let local_direction = (node.to_local * vec4<f32>(direction, 0.0)).xyz;
+    let inv_direction   = 1.0 / local_direction;
+    let t               = intersect(local_origin, inv_direction, node.local_min.xyz, node.local_max.xyz);
+
+    if (t <= 0.0) {
+        return vec4<f32>(t, -normalize(direction));
+    }
+
+    // The face entered through is on the axis the entry distance came from
+    let t0   = (node.local_min.xyz - local_origin) * inv_direction;
+    let t1   = (node.local_max.xyz - local_origin) * inv_direction;
+    let near = min(t0, t1);
+
+    var normal: vec3<f32>;
+
+    if (near.x >= near.y && near.x >= near.z) {
+        normal = vec3<f32>(-sign(local_direction.x), 0.0, 0.0);
+    } else if (near.y >= near.z) {
+        normal = vec3<f32>(0.0, -sign(local_direction.y), 0.0);
+    } else {
+        normal = vec3<f32>(0.0, 0.0, -sign(local_direction.z));
+    }
+
+    // Normals go back through the inverse transpose
+    return vec4<f32>(t, normalize((transpose(node.to_local) * vec4<f32>(normal, 0.0)).xyz));
+}
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+
+    if (index >= params.ray_count) {
+        return;
+    }
+
+    let origin        = rays[index].origin.xyz;
+    let direction     = rays[index].direction.xyz;
+    let inv_direction = 1.0 / direction;
+
+    var miss: Hit;
+    miss.entity = -1;
+    hits[index] = miss;
+
+    if (params.node_count == 0u) {
+        return;
+    }
+
+    // A median split halves the items at every level, so this covers billions of them
+    var stack: array<u32, 32>;
+    var top    = 1u;
+    var best   = -1;
+    var best_t = 0.0;
+    var normal = vec3<f32>(0.0);
+
+    stack[0] = 0u;
+
+    loop {
+        if (top == 0u) {
+            break;
+        }
+
+        top = top - 1u;
+
+        let node = nodes[stack[top]];
+        let t    = intersect(origin, inv_direction, node.bounds_min.xyz, node.bounds_max.xyz);
+
+        // Nothing inside can be closer than where the ray enters the node
+        if (t < 0.0 || (best >= 0 && t >= best_t)) {
+            continue;
+        }
+
+        if (node.item >= 0) {
+            let item = intersect_item(origin, direction, node);
+
+            if (item.x >= 0.0 && (best < 0 || item.x < best_t)) {
+                best   = node.item;
+                best_t = item.x;
+                normal = item.yzw;
+            }
+        } else if (top + 2u <= 32u) {
+            stack[top]      = node.left;
+            stack[top + 1u] = node.right;
+            top             = top + 2u;
+        }
+    }
+
+    if (best < 0) {
+        return;
+    }
+
+    var hit: Hit;
+    hit.position = vec4<f32>(origin + direction * best_t, best_t);
+    hit.normal   = normal;
+    hit.entity   = best;
+    hits[index]  = hit;
+}
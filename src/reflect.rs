@@ -0,0 +1,274 @@
+use std::collections::BTreeMap;
+
+use anyhow::*;
+
+// Bind group layouts reflected from a WGSL module's global resource bindings
+pub struct ShaderReflection {
+    groups: BTreeMap<u32, Vec<wgpu::BindGroupLayoutEntry>>,
+}
+
+impl ShaderReflection {
+    pub fn from_wgsl(source: &str) -> Result<Self> {
+        let module = naga::front::wgsl::parse_str(source)
+            .map_err(|e| anyhow!("Couldn't parse shader: {}", e.emit_to_string(source)))?;
+
+        // naga's analysis of each entry point, which counts a global as used when any function it
+        // calls uses it, however deep. Expressions alone don't say, since the WGSL front end gives
+        // every function an expression for every global whether it uses it or not.
+        let info = naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all())
+            .validate(&module)
+            .context("Shader is invalid")?;
+
+        let mut groups: BTreeMap<u32, Vec<wgpu::BindGroupLayoutEntry>> = BTreeMap::new();
+
+        for (handle, var) in module.global_variables.iter() {
+            let binding = match &var.binding {
+                Some(binding) => binding,
+                None          => continue,
+            };
+
+            // Only visible to the stages whose entry points use it. One no entry point reaches is
+            // visible to none, which any layout entry satisfies.
+            let visibility = module.entry_points.iter().enumerate()
+                .filter(|(index, _)| !info.get_entry_point(*index)[handle].is_empty())
+                .fold(wgpu::ShaderStages::NONE, |visibility, (_, entry_point)| visibility | stage_to_wgpu(entry_point.stage));
+
+            let name = var.name.as_deref().unwrap_or("<unnamed>");
+            let ty   = binding_type(&module, var)
+                .with_context(|| format!("Unsupported binding `{}` at @group({}) @binding({})", name, binding.group, binding.binding))?;
+
+            groups.entry(binding.group).or_default().push(wgpu::BindGroupLayoutEntry {
+                binding: binding.binding,
+                visibility,
+                ty,
+                count: None,
+            });
+        }
+
+        for entries in groups.values_mut() {
+            entries.sort_by_key(|entry| entry.binding);
+        }
+
+        Ok(Self { groups })
+    }
+
+    // `group` as every one of `sources` declares it, for a layout shared between shaders. Each
+    // binding is visible to the stages that use it in any of them, and has to be the same kind in
+    // all that declare it.
+    pub fn from_wgsl_shared(sources: &[impl AsRef<str>], group: u32) -> Result<Self> {
+        let mut entries: Vec<wgpu::BindGroupLayoutEntry> = Vec::new();
+
+        for (index, source) in sources.iter().enumerate() {
+            let reflection = Self::from_wgsl(source.as_ref())
+                .with_context(|| format!("Couldn't reflect shader {} of {}", index + 1, sources.len()))?;
+
+            for shader_entry in reflection.entries(group) {
+                match entries.iter_mut().find(|e| e.binding == shader_entry.binding) {
+                    Some(entry) => {
+                        if !same_binding_kind(&entry.ty, &shader_entry.ty) {
+                            bail!(
+                                "@group({}) @binding({}) is {:?} in one shader but {:?} in shader {}",
+                                group, shader_entry.binding, entry.ty, shader_entry.ty, index + 1
+                            );
+                        }
+
+                        entry.visibility |= shader_entry.visibility;
+                    }
+                    None => entries.push(*shader_entry),
+                }
+            }
+        }
+
+        entries.sort_by_key(|entry| entry.binding);
+
+        Ok(Self { groups: BTreeMap::from([(group, entries)]) })
+    }
+
+    pub fn entries(&self, group: u32) -> &[wgpu::BindGroupLayoutEntry] {
+        self.groups.get(&group).map(|e| e.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn create_bind_group_layout(
+        &self,
+        device: &wgpu::Device,
+        group:  u32,
+        label:  &str,
+    ) -> Result<wgpu::BindGroupLayout> {
+        let entries = self.groups.get(&group)
+            .with_context(|| format!("Shader has no bindings in @group({})", group))?;
+
+        Ok(device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries,
+            label: Some(label),
+        }))
+    }
+
+    // Checks hand-written layout entries against what the shader declares for `group`
+    pub fn validate(&self, group: u32, entries: &[wgpu::BindGroupLayoutEntry]) -> Result<()> {
+        let expected = self.entries(group);
+
+        for shader_entry in expected {
+            let rust_entry = entries.iter()
+                .find(|e| e.binding == shader_entry.binding)
+                .with_context(|| format!(
+                    "@group({}) @binding({}) is used by the shader but missing from the layout",
+                    group, shader_entry.binding
+                ))?;
+
+            if !same_binding_kind(&rust_entry.ty, &shader_entry.ty) {
+                bail!(
+                    "@group({}) @binding({}) type mismatch: layout has {:?}, shader expects {:?}",
+                    group, shader_entry.binding, rust_entry.ty, shader_entry.ty
+                );
+            }
+
+            if !rust_entry.visibility.contains(shader_entry.visibility) {
+                bail!(
+                    "@group({}) @binding({}) visibility mismatch: layout has {:?}, shader needs {:?}",
+                    group, shader_entry.binding, rust_entry.visibility, shader_entry.visibility
+                );
+            }
+        }
+
+        for rust_entry in entries {
+            if !expected.iter().any(|e| e.binding == rust_entry.binding) {
+                bail!("@group({}) @binding({}) isn't declared in the shader", group, rust_entry.binding);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn stage_to_wgpu(stage: naga::ShaderStage) -> wgpu::ShaderStages {
+    match stage {
+        naga::ShaderStage::Vertex   => wgpu::ShaderStages::VERTEX,
+        naga::ShaderStage::Fragment => wgpu::ShaderStages::FRAGMENT,
+        naga::ShaderStage::Compute  => wgpu::ShaderStages::COMPUTE,
+    }
+}
+
+fn binding_type(module: &naga::Module, var: &naga::GlobalVariable) -> Result<wgpu::BindingType> {
+    let ty = match var.space {
+        naga::AddressSpace::Uniform => wgpu::BindingType::Buffer {
+            ty:                 wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size:   None,
+        },
+        naga::AddressSpace::Storage { access } => wgpu::BindingType::Buffer {
+            ty:                 wgpu::BufferBindingType::Storage {
+                read_only: !access.contains(naga::StorageAccess::STORE),
+            },
+            has_dynamic_offset: false,
+            min_binding_size:   None,
+        },
+        naga::AddressSpace::Handle => match &module.types[var.ty].inner {
+            naga::TypeInner::Sampler { comparison } => wgpu::BindingType::Sampler(if *comparison {
+                wgpu::SamplerBindingType::Comparison
+            } else {
+                wgpu::SamplerBindingType::Filtering
+            }),
+            naga::TypeInner::Image { dim, arrayed, class } => {
+                let view_dimension = view_dimension(*dim, *arrayed);
+
+                match class {
+                    naga::ImageClass::Sampled { kind, multi } => wgpu::BindingType::Texture {
+                        multisampled: *multi,
+                        view_dimension,
+                        sample_type:  match kind {
+                            naga::ScalarKind::Float => wgpu::TextureSampleType::Float { filterable: !*multi },
+                            naga::ScalarKind::Sint  => wgpu::TextureSampleType::Sint,
+                            naga::ScalarKind::Uint  => wgpu::TextureSampleType::Uint,
+                            naga::ScalarKind::Bool  => bail!("boolean textures aren't supported"),
+                        },
+                    },
+                    naga::ImageClass::Depth { multi } => wgpu::BindingType::Texture {
+                        multisampled: *multi,
+                        view_dimension,
+                        sample_type:  wgpu::TextureSampleType::Depth,
+                    },
+                    naga::ImageClass::Storage { format, access } => wgpu::BindingType::StorageTexture {
+                        access: if access.contains(naga::StorageAccess::LOAD | naga::StorageAccess::STORE) {
+                            wgpu::StorageTextureAccess::ReadWrite
+                        } else if access.contains(naga::StorageAccess::STORE) {
+                            wgpu::StorageTextureAccess::WriteOnly
+                        } else {
+                            wgpu::StorageTextureAccess::ReadOnly
+                        },
+                        format: storage_format(*format)?,
+                        view_dimension,
+                    },
+                }
+            }
+            other => bail!("unexpected handle type {:?}", other),
+        },
+        other => bail!("unexpected address space {:?}", other),
+    };
+
+    Ok(ty)
+}
+
+fn view_dimension(dim: naga::ImageDimension, arrayed: bool) -> wgpu::TextureViewDimension {
+    match (dim, arrayed) {
+        (naga::ImageDimension::D1, _)       => wgpu::TextureViewDimension::D1,
+        (naga::ImageDimension::D2, false)   => wgpu::TextureViewDimension::D2,
+        (naga::ImageDimension::D2, true)    => wgpu::TextureViewDimension::D2Array,
+        (naga::ImageDimension::D3, _)       => wgpu::TextureViewDimension::D3,
+        (naga::ImageDimension::Cube, false) => wgpu::TextureViewDimension::Cube,
+        (naga::ImageDimension::Cube, true)  => wgpu::TextureViewDimension::CubeArray,
+    }
+}
+
+fn storage_format(format: naga::StorageFormat) -> Result<wgpu::TextureFormat> {
+    let format = match format {
+        naga::StorageFormat::R32Float    => wgpu::TextureFormat::R32Float,
+        naga::StorageFormat::R32Uint     => wgpu::TextureFormat::R32Uint,
+        naga::StorageFormat::R32Sint     => wgpu::TextureFormat::R32Sint,
+        naga::StorageFormat::Rg32Float   => wgpu::TextureFormat::Rg32Float,
+        naga::StorageFormat::Rgba8Unorm  => wgpu::TextureFormat::Rgba8Unorm,
+        naga::StorageFormat::Rgba8Snorm  => wgpu::TextureFormat::Rgba8Snorm,
+        naga::StorageFormat::Rgba8Uint   => wgpu::TextureFormat::Rgba8Uint,
+        naga::StorageFormat::Rgba8Sint   => wgpu::TextureFormat::Rgba8Sint,
+        naga::StorageFormat::Rgba16Float => wgpu::TextureFormat::Rgba16Float,
+        naga::StorageFormat::Rgba16Uint  => wgpu::TextureFormat::Rgba16Uint,
+        naga::StorageFormat::Rgba16Sint  => wgpu::TextureFormat::Rgba16Sint,
+        naga::StorageFormat::Rgba32Float => wgpu::TextureFormat::Rgba32Float,
+        naga::StorageFormat::Rgba32Uint  => wgpu::TextureFormat::Rgba32Uint,
+        naga::StorageFormat::Rgba32Sint  => wgpu::TextureFormat::Rgba32Sint,
+        other => bail!("unsupported storage texture format {:?}", other),
+    };
+
+    Ok(format)
+}
+
+// Compares binding kinds while ignoring details the shader can't express (e.g. filterability)
+fn same_binding_kind(a: &wgpu::BindingType, b: &wgpu::BindingType) -> bool {
+    use wgpu::BindingType::*;
+
+    match (a, b) {
+        (Buffer { ty: a, .. }, Buffer { ty: b, .. }) => match (a, b) {
+            (wgpu::BufferBindingType::Uniform, wgpu::BufferBindingType::Uniform) => true,
+            (
+                wgpu::BufferBindingType::Storage { read_only: a },
+                wgpu::BufferBindingType::Storage { read_only: b },
+            ) => a == b,
+            _ => false,
+        },
+        (
+            Texture { sample_type: a_sample, view_dimension: a_dim, multisampled: a_multi },
+            Texture { sample_type: b_sample, view_dimension: b_dim, multisampled: b_multi },
+        ) => {
+            a_dim == b_dim
+                && a_multi == b_multi
+                && std::mem::discriminant(a_sample) == std::mem::discriminant(b_sample)
+        }
+        (Sampler(a), Sampler(b)) => {
+            (*a == wgpu::SamplerBindingType::Comparison) == (*b == wgpu::SamplerBindingType::Comparison)
+        }
+        (
+            StorageTexture { format: a_format, view_dimension: a_dim, .. },
+            StorageTexture { format: b_format, view_dimension: b_dim, .. },
+        ) => a_format == b_format && a_dim == b_dim,
+        _ => false,
+    }
+}
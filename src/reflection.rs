@@ -0,0 +1,287 @@
+// Reflections of the scene: probes that capture the surroundings of a point in all directions, and
+// mirrors that render the scene again from below their plane each frame. Mirrors fall back on a
+// probe where their planar reflection has nothing to show.
+
+use cgmath::prelude::*;
+use wgpu::util::DeviceExt;
+
+use learn_wgpu_derive::VertexLayout;
+
+use crate::{
+    bind_group,
+    camera::Camera,
+    crash_report,
+    pass::{Drawable, RenderLayer},
+    renderer::{self, GpuContext},
+    scene::Scene,
+    texture::Texture,
+    thumbnail,
+    uniform::{Uniform, UniformBuffer},
+};
+
+// Forward and up of the camera rendering each face, in the order +X, -X, +Y, -Y, +Z, -Z.
+// Also in probe.wgsl.
+const PROBE_FACES: [([f32; 3], [f32; 3]); 6] = [
+    ([ 1.0,  0.0,  0.0], [0.0, 1.0,  0.0]),
+    ([-1.0,  0.0,  0.0], [0.0, 1.0,  0.0]),
+    ([ 0.0,  1.0,  0.0], [0.0, 0.0, -1.0]),
+    ([ 0.0, -1.0,  0.0], [0.0, 0.0,  1.0]),
+    ([ 0.0,  0.0,  1.0], [0.0, 1.0,  0.0]),
+    ([ 0.0,  0.0, -1.0], [0.0, 1.0,  0.0]),
+];
+
+const PROBE_NEAR: f32 = 0.1;
+const PROBE_FAR:  f32 = 100.0;
+
+// The scene seen from `position` in every direction. The faces are kept as a 2D array rather than
+// a cube texture so they can be rendered with the scene's ordinary right-handed cameras, and are
+// looked up with `probe_uv` in probe.wgsl.
+pub struct ReflectionProbe {
+    pub position: cgmath::Point3<f32>,
+    pub view:     wgpu::TextureView,
+    faces:        Vec<wgpu::TextureView>,
+    depth:        Texture,
+}
+
+impl ReflectionProbe {
+    pub fn new(ctx: &GpuContext, position: cgmath::Point3<f32>, size: u32) -> Self {
+        let device = &ctx.device;
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label:           Some("Reflection Probe"),
+            size:            wgpu::Extent3d { width: size, height: size, depth_or_array_layers: 6 },
+            mip_level_count: 1,
+            sample_count:    1,
+            dimension:       wgpu::TextureDimension::D2,
+            format:          ctx.config.format,
+            usage:           wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+
+        let faces = (0..6)
+            .map(|face| texture.create_view(&wgpu::TextureViewDescriptor {
+                label:             Some("Reflection Probe Face"),
+                dimension:         Some(wgpu::TextureViewDimension::D2),
+                base_array_layer:  face,
+                array_layer_count: std::num::NonZeroU32::new(1),
+                ..Default::default()
+            }))
+            .collect();
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label:     Some("Reflection Probe View"),
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+
+        Self {
+            position,
+            view,
+            faces,
+            depth: Texture::create_depth_texture(device, size, size, "reflection_probe_depth_texture"),
+        }
+    }
+
+    // Renders all six faces. Each is submitted on its own since the scene's camera buffer can
+    // only hold one view per submission.
+    pub fn capture(&self, ctx: &GpuContext, scene: &dyn Scene) {
+        for (view, (forward, up)) in self.faces.iter().zip(PROBE_FACES) {
+            let camera = Camera {
+                eye:        self.position,
+                target:     self.position + cgmath::Vector3::from(forward),
+                up:         up.into(),
+                aspect:     1.0,
+                fovy:       90.0,
+                znear:      PROBE_NEAR,
+                zfar:       PROBE_FAR,
+                depth_mode: ctx.depth_mode,
+            };
+
+            thumbnail::render_scene(ctx, scene, &camera, None, view, &self.depth.view, "Reflection Probe Encoder");
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+struct MirrorVertex {
+    #[location(0)]
+    position: [f32; 3],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct MirrorUniform {
+    view_proj:           [[f32; 4]; 4],
+    reflected_view_proj: [[f32; 4]; 4],
+    eye:                 [f32; 4],
+    // xyz is the normal, w the reflectivity facing the mirror head on
+    normal_reflectivity: [f32; 4],
+    color:               [f32; 4],
+}
+
+// A flat square that reflects the scene, blended over its own color by a Fresnel term, so it's
+// a near-perfect mirror at grazing angles and mostly `color` looking straight at it. Low
+// reflectivities work for water.
+pub struct Mirror {
+    pub center:       cgmath::Point3<f32>,
+    pub normal:       cgmath::Vector3<f32>,
+    pub color:        [f32; 3],
+    pub reflectivity: f32,
+    pipeline:         wgpu::RenderPipeline,
+    bind_group:       wgpu::BindGroup,
+    uniform:          UniformBuffer<MirrorUniform>,
+    vertices:         wgpu::Buffer,
+    view:             wgpu::TextureView,
+    depth:            Texture,
+}
+
+impl Mirror {
+    const SIZE: u32 = 1024;
+
+    pub fn new(
+        ctx:       &GpuContext,
+        center:    cgmath::Point3<f32>,
+        normal:    cgmath::Vector3<f32>,
+        half_size: f32,
+        probe:     &ReflectionProbe,
+    ) -> Self {
+        let device = &ctx.device;
+        let normal = normal.normalize();
+
+        // Any tangent works for a square
+        let helper    = if normal.y.abs() > 0.99 { cgmath::Vector3::unit_x() } else { cgmath::Vector3::unit_y() };
+        let tangent   = normal.cross(helper).normalize() * half_size;
+        let bitangent = normal.cross(tangent);
+
+        // Wound counter-clockwise seen from the front
+        let corner   = |s: f32, t: f32| MirrorVertex { position: (center + tangent * s + bitangent * t).into() };
+        let vertices = [
+            corner(-1.0, -1.0), corner(1.0, -1.0), corner(1.0, 1.0),
+            corner(-1.0, -1.0), corner(1.0, 1.0),  corner(-1.0, 1.0),
+        ];
+
+        let vertices = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label:    Some("Mirror Vertex Buffer"),
+            contents: bytemuck::cast_slice(&vertices),
+            usage:    wgpu::BufferUsages::VERTEX,
+        });
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label:           Some("Mirror Reflection"),
+            size:            wgpu::Extent3d { width: Self::SIZE, height: Self::SIZE, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count:    1,
+            dimension:       wgpu::TextureDimension::D2,
+            format:          ctx.config.format,
+            usage:           wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label:          Some("Mirror Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter:     wgpu::FilterMode::Linear,
+            min_filter:     wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let uniform = UniformBuffer::new(device, "Mirror Uniform Buffer");
+
+        let layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT)
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2Array)
+            .sampler(wgpu::ShaderStages::FRAGMENT)
+            .build(device, "mirror_bind_group_layout");
+
+        let bind_group = bind_group::BindGroupBuilder::new(&layout)
+            .uniform(uniform.buffer())
+            .texture(&view)
+            .texture(&probe.view)
+            .sampler(&sampler)
+            .build(device, "mirror_bind_group");
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Mirror Shader"),
+            source: wgpu::ShaderSource::Wgsl(concat!(include_str!("probe.wgsl"), include_str!("mirror.wgsl")).into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Mirror Pipeline Layout"),
+            bind_group_layouts:   &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        // Passes equal depths so it draws whether or not the depth prepass ran
+        let pipeline = renderer::create_render_pipeline(
+            device,
+            &pipeline_layout,
+            ctx.config.format,
+            Some(renderer::depth_state(Texture::DEPTH_FORMAT, ctx.depth_mode.compare_or_equal(), true)),
+            &[MirrorVertex::layout()],
+            &shader,
+            "Mirror Pipeline",
+        );
+
+        Self {
+            center,
+            normal,
+            color:        [0.05, 0.05, 0.08],
+            reflectivity: 0.5,
+            pipeline,
+            bind_group,
+            uniform,
+            vertices,
+            view,
+            depth:        Texture::create_depth_texture(device, Self::SIZE, Self::SIZE, "mirror_depth_texture"),
+        }
+    }
+
+    // The plane as (normal, distance), facing `eye`
+    fn plane(&self, eye: cgmath::Point3<f32>) -> cgmath::Vector4<f32> {
+        let plane = self.normal.extend(-self.normal.dot(self.center.to_vec()));
+
+        if plane.dot(eye.to_homogeneous()) < 0.0 { -plane } else { plane }
+    }
+
+    // Renders `scene` as seen in the mirror from `camera`. Submitted straight away, so call it
+    // before the frame's own camera is uploaded.
+    pub fn render_reflection(&self, ctx: &GpuContext, scene: &dyn Scene, camera: &Camera) {
+        let plane   = self.plane(camera.eye);
+        let reflect = |p: cgmath::Point3<f32>| p - plane.truncate() * 2.0 * plane.dot(p.to_homogeneous());
+
+        // Seen from behind the mirror, whatever's at a point on its surface is the reflection
+        // at that point, so projecting the surface with this camera finds the reflected texel
+        let reflected = Camera {
+            eye:        reflect(camera.eye),
+            target:     reflect(camera.target),
+            up:         camera.up - plane.truncate() * 2.0 * plane.truncate().dot(camera.up),
+            aspect:     camera.aspect,
+            fovy:       camera.fovy,
+            znear:      camera.znear,
+            zfar:       camera.zfar,
+            depth_mode: camera.depth_mode,
+        };
+
+        self.uniform.write(&ctx.queue, &MirrorUniform {
+            view_proj:           camera.build_view_projections_matrix().into(),
+            reflected_view_proj: reflected.build_view_projections_matrix().into(),
+            eye:                 camera.eye.to_homogeneous().into(),
+            normal_reflectivity: plane.truncate().extend(self.reflectivity).into(),
+            color:               [self.color[0], self.color[1], self.color[2], 1.0],
+        });
+
+        thumbnail::render_scene(ctx, scene, &reflected, Some(plane), &self.view, &self.depth.view, "Mirror Encoder");
+    }
+}
+
+impl Drawable for Mirror {
+    fn draw<'a>(&'a self, _layer: RenderLayer, render_pass: &mut wgpu::RenderPass<'a>) {
+        crash_report::set_pipeline(render_pass, &self.pipeline, "Mirror Pipeline");
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertices.slice(..));
+        render_pass.draw(0..6, 0..1);
+    }
+}
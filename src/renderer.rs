@@ -0,0 +1,537 @@
+use std::cell::{Cell, RefCell};
+
+use winit::window::{CursorGrabMode, Window};
+
+use crate::{
+    camera::DepthMode,
+    crash_report,
+    gpu_stats,
+    in_flight::{InFlight, Retire, FRAMES_IN_FLIGHT},
+    pass_timing::PassTimer,
+    resolution::{DynamicResolution, SceneTarget},
+    schedule::{ComputeScheduler, WorkFence},
+    texture,
+};
+
+// Choices fixed when the GPU context is created
+#[derive(Debug, Copy, Clone)]
+pub struct RendererOptions {
+    pub depth_mode:         DepthMode,
+    // Draws the scene at a resolution that tracks frame time, then upscales it
+    pub dynamic_resolution: Option<DynamicResolution>,
+    // Directory to record a wgpu API trace into, for attaching to bug reports. The
+    // `LEARN_WGPU_TRACE` environment variable sets it too. Only recorded when built with the
+    // `trace` feature.
+    pub trace_path:         Option<&'static str>,
+    // File to log each frame's pass timings to as CSV, see pass_timing.rs. The
+    // `LEARN_WGPU_TIMINGS` environment variable sets it too. Native only.
+    pub timings_csv:        Option<&'static str>,
+    // File to write a report to on a panic or wgpu validation error, see crash_report.rs. The
+    // `LEARN_WGPU_CRASH_REPORT` environment variable sets it too, and it's crash_report.txt in
+    // the working directory otherwise. Reports go to the browser console on the web.
+    pub crash_report:       Option<&'static str>,
+    // Renders to an OpenXR headset as well as the window, falling back to just the window when
+    // there's no runtime or headset. Only with the `vr` feature, see xr.rs.
+    pub vr:                 bool,
+}
+
+impl Default for RendererOptions {
+    fn default() -> Self {
+        Self {
+            depth_mode:         DepthMode::ReverseZ,
+            dynamic_resolution: None,
+            trace_path:         None,
+            timings_csv:        None,
+            crash_report:       None,
+            vr:                 false,
+        }
+    }
+}
+
+// Owns the window's surface and the GPU device, and hands out a `Frame` to draw into each redraw
+pub struct GpuContext {
+    pub surface:        wgpu::Surface,
+    pub device:         wgpu::Device,
+    pub queue:          wgpu::Queue,
+    pub config:         wgpu::SurfaceConfiguration,
+    pub size:           winit::dpi::PhysicalSize<u32>,
+    // Which GPU the device is on, so offline work can pick a different one, see multi_gpu.rs
+    pub adapter_info:   wgpu::AdapterInfo,
+    // Sized to the render resolution, which only differs from `size` with dynamic resolution
+    pub depth_texture:  texture::Texture,
+    pub depth_mode:     DepthMode,
+    // Where the scene is drawn when it's scaled or has post effects
+    pub scene_target:   SceneTarget,
+    dynamic_resolution: Option<DynamicResolution>,
+    cursor_grabbed:     Cell<bool>,
+    // Counts frames begun, for labelling them in traces and captures
+    frame_index:        Cell<u64>,
+    in_flight:          RefCell<InFlight>,
+    pass_timer:         PassTimer,
+    window:             Window,
+}
+
+// Everything needed to record one frame. Submitted and presented by `Frame::present`.
+pub struct Frame<'a> {
+    pub ctx:     &'a GpuContext,
+    pub view:    wgpu::TextureView,
+    pub encoder: wgpu::CommandEncoder,
+    compute:     ComputeScheduler,
+    index:       u64,
+    fence:       WorkFence,
+    output:      wgpu::SurfaceTexture,
+}
+
+impl<'a> Frame<'a> {
+    pub fn depth_view(&self) -> &'a wgpu::TextureView {
+        &self.ctx.depth_texture.view
+    }
+
+    // Records compute work that doesn't depend on anything drawn this frame into a submission of
+    // its own named `label`, which goes to the GPU ahead of the frame's encoder, see schedule.rs
+    pub fn compute(&mut self, label: &'static str, work: impl FnOnce(&GpuContext, &mut wgpu::CommandEncoder)) -> WorkFence {
+        self.compute.record(self.ctx, label, work)
+    }
+
+    // Sends the compute work recorded so far. The runner calls this between the app registering
+    // its draws and executing them.
+    pub fn submit_compute(&mut self) {
+        self.compute.submit(&self.ctx.queue);
+    }
+
+    // Counts up from 0 with every frame begun
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    // Which of a `PerFrame`'s copies belong to this frame
+    pub fn slot(&self) -> usize {
+        (self.index % FRAMES_IN_FLIGHT as u64) as usize
+    }
+
+    // Done once the GPU has finished everything submitted with this frame
+    pub fn fence(&self) -> WorkFence {
+        self.fence.clone()
+    }
+
+    pub fn present(mut self) {
+        self.submit_compute();
+        self.ctx.pass_timer.end_frame(&mut self.encoder, self.index);
+
+        let submission = self.ctx.queue.submit(std::iter::once(self.encoder.finish()));
+
+        self.fence.signal_when_done(&self.ctx.queue);
+        self.ctx.in_flight.borrow_mut().submitted(self.index, submission, self.fence);
+        self.output.present();
+
+        self.ctx.pass_timer.submitted(&self.ctx.device);
+        gpu_stats::end_frame();
+    }
+}
+
+impl GpuContext {
+    // Creating some of the wgpu types requires async code
+    pub async fn new(window: Window, options: RendererOptions) -> Self {
+        // The instance is a handle to our GPU
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+
+        // # Safety
+        //
+        // The surface needs to live as long as the window that created it.
+        // GpuContext owns the window so this should be safe.
+        let surface = unsafe { instance.create_surface(&window) };
+        let adapter = instance.request_adapter(
+            &wgpu::RequestAdapterOptions {
+                power_preference:       wgpu::PowerPreference::default(),
+                compatible_surface:     Some(&surface),
+                force_fallback_adapter: false,
+            }
+        ).await.unwrap();
+
+        /*
+         * Enumerator to fall back on if `adapter` returns `None`
+
+         let adapter = instance.enumerate_adapters(wgpu::Backends::all())
+            .filter(|adapter| {
+                !surface.get_supported_formats(&adapter).is_empty()
+            })
+            .next()
+            .unwrap();
+         */
+
+        let trace_path = options.trace_path
+            .map(std::path::PathBuf::from)
+            .or_else(|| std::env::var_os("LEARN_WGPU_TRACE").map(std::path::PathBuf::from));
+
+        if let Some(path) = &trace_path {
+            std::fs::create_dir_all(path).unwrap();
+            log::info!("Recording a wgpu trace to {}", path.display());
+        }
+
+        let (device, queue) = adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                features: adapter.features() & wanted_features(),
+                // WebGL doesn't support all wgpu's features, so disable some if building for web.
+                limits:   if cfg!(target_arch = "wasm32") {
+                    wgpu::Limits::downlevel_webgl2_defaults()
+                } else {
+                    wgpu::Limits::default()
+                },
+                label:    Some("Device"),
+            },
+            trace_path.as_deref(),
+        ).await.unwrap();
+
+        Self::from_parts(window, surface, &adapter, device, queue, options)
+    }
+
+    // Wraps a device made some other way, like by an OpenXR runtime. `surface` has to come from the
+    // same instance as `adapter`.
+    pub fn from_parts(
+        window:  Window,
+        surface: wgpu::Surface,
+        adapter: &wgpu::Adapter,
+        device:  wgpu::Device,
+        queue:   wgpu::Queue,
+        options: RendererOptions,
+    ) -> Self {
+        let size = window.inner_size();
+
+        let config = wgpu::SurfaceConfiguration {
+            usage:        wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format:       surface.get_supported_formats(adapter)[0], // the prefered format is placed at the beginning of the vector
+            width:        size.width,
+            height:       size.height,
+            present_mode: wgpu::PresentMode::Fifo, // VSync, likely supported on all platforms
+            alpha_mode:   wgpu::CompositeAlphaMode::Auto,
+        };
+
+        surface.configure(&device, &config);
+
+        let depth_texture = texture::Texture::create_depth_texture(&device, config.width, config.height, "depth_texture");
+        let scene_target  = SceneTarget::new(&device, config.format, config.width, config.height);
+        let pass_timer    = PassTimer::new(&device, &queue);
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let timings_csv = options.timings_csv
+                .map(std::path::PathBuf::from)
+                .or_else(|| std::env::var_os("LEARN_WGPU_TIMINGS").map(std::path::PathBuf::from));
+
+            if let Some(path) = timings_csv {
+                match pass_timer.log_csv(&path) {
+                    Ok(()) => log::info!("Logging pass timings to {}", path.display()),
+                    Err(e) => log::warn!("Couldn't log pass timings to {}: {}", path.display(), e),
+                }
+            }
+        }
+
+        let ctx = Self {
+            surface,
+            device,
+            queue,
+            config,
+            size,
+            adapter_info:       adapter.get_info(),
+            depth_texture,
+            depth_mode:         options.depth_mode,
+            scene_target,
+            dynamic_resolution: options.dynamic_resolution,
+            cursor_grabbed:     Cell::new(false),
+            frame_index:        Cell::new(0),
+            in_flight:          RefCell::new(InFlight::new()),
+            pass_timer,
+            window,
+        };
+
+        crash_report::install(&ctx, &options);
+        ctx
+    }
+
+    pub fn window(&self) -> &Window {
+        &self.window
+    }
+
+    // Times the passes of each frame, see pass_timing.rs
+    pub fn pass_timer(&self) -> &PassTimer {
+        &self.pass_timer
+    }
+
+    // Confines and hides the cursor for mouse-look. Uses pointer lock on the web.
+    pub fn set_cursor_grab(&self, grab: bool) {
+        if grab == self.cursor_grabbed.get() {
+            return;
+        }
+
+        let result = if grab {
+            // Confined isn't supported on macOS or the web, and Locked isn't supported on Windows
+            self.window.set_cursor_grab(CursorGrabMode::Confined)
+                .or_else(|_| self.window.set_cursor_grab(CursorGrabMode::Locked))
+        } else {
+            self.window.set_cursor_grab(CursorGrabMode::None)
+        };
+
+        match result {
+            Ok(_) => {
+                self.window.set_cursor_visible(!grab);
+                self.cursor_grabbed.set(grab);
+            }
+            Err(e) => log::warn!("Couldn't {} cursor: {:?}", if grab { "grab" } else { "release" }, e),
+        }
+    }
+
+    pub fn is_cursor_grabbed(&self) -> bool {
+        self.cursor_grabbed.get()
+    }
+
+    // Frees `resource` once every frame submitted so far, including the one being recorded, has
+    // finished on the GPU. For anything dropped while a frame might still be drawing with it.
+    pub fn retire(&self, resource: impl Retire) {
+        let index = self.frame_index.get().saturating_sub(1);
+
+        self.in_flight.borrow_mut().retire(index, Box::new(resource));
+    }
+
+    // The latest frame the GPU has finished, by `Frame::index`
+    pub fn completed_frame(&self) -> Option<u64> {
+        self.in_flight.borrow().completed()
+    }
+
+    pub fn aspect(&self) -> f32 {
+        self.config.width as f32 / self.config.height as f32
+    }
+
+    pub fn render_scale(&self) -> f32 {
+        self.dynamic_resolution.map_or(1.0, |d| d.scale())
+    }
+
+    // Size the scene is drawn at
+    pub fn render_size(&self) -> winit::dpi::PhysicalSize<u32> {
+        let scale = self.render_scale();
+
+        winit::dpi::PhysicalSize::new(
+            ((self.config.width as f32 * scale).round() as u32).max(1),
+            ((self.config.height as f32 * scale).round() as u32).max(1),
+        )
+    }
+
+    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+        if new_size.width > 0 && new_size.height > 0 {
+            self.size          = new_size;
+            self.config.width  = new_size.width;
+            self.config.height = new_size.height;
+
+            self.surface.configure(&self.device, &self.config);
+            self.create_render_targets();
+
+            crash_report::set_setting("Window size", format!("{}x{}", new_size.width, new_size.height));
+        }
+    }
+
+    // Feeds the last frame time to dynamic resolution. Returns true if the render size changed.
+    pub fn update_render_scale(&mut self, frame_time: std::time::Duration) -> bool {
+        let changed = match &mut self.dynamic_resolution {
+            Some(dynamic_resolution) => dynamic_resolution.update(frame_time),
+            None                     => false,
+        };
+
+        if changed {
+            log::debug!("Render scale is now {:.2}", self.render_scale());
+            self.create_render_targets();
+        }
+
+        changed
+    }
+
+    fn create_render_targets(&mut self) {
+        let size = self.render_size();
+
+        self.depth_texture = texture::Texture::create_depth_texture(&self.device, size.width, size.height, "depth texture");
+
+        self.scene_target.resize(&self.device, size.width, size.height);
+    }
+
+    // Waits first if FRAMES_IN_FLIGHT frames are still on the GPU, see in_flight.rs
+    pub fn begin_frame(&self) -> Result<Frame<'_>, wgpu::SurfaceError> {
+        self.in_flight.borrow_mut().begin(&self.device);
+
+        let output  = self.surface.get_current_texture()?;
+        let view    = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Render Encoder"),
+        });
+
+        let index = self.frame_index.get();
+        self.frame_index.set(index + 1);
+
+        // Marks where each frame starts when stepping through a trace or capture
+        let marker = format!("Frame {}", index);
+
+        encoder.insert_debug_marker(&marker);
+        crash_report::marker(&marker);
+
+        Ok(Frame {
+            ctx:     self,
+            view,
+            encoder,
+            compute: ComputeScheduler::new(),
+            index,
+            fence:   WorkFence::default(),
+            output,
+        })
+    }
+}
+
+// Optional features the device is asked for, of which it gets what the adapter has. BC textures
+// take less memory when load-time compression is on, see transcode.rs. Passes are timed on the
+// GPU where it can be, see pass_timing.rs. SPIR-V shaders skip translation where they can, see
+// shader_code.rs.
+fn wanted_features() -> wgpu::Features {
+    let mut features = wgpu::Features::TIMESTAMP_QUERY;
+
+    if cfg!(feature = "bc_compress") {
+        features |= wgpu::Features::TEXTURE_COMPRESSION_BC;
+    }
+
+    if cfg!(feature = "spirv") {
+        features |= wgpu::Features::SPIRV_SHADER_PASSTHROUGH;
+    }
+
+    features
+}
+
+pub fn create_render_pipeline(
+    device:         &wgpu::Device,
+    layout:         &wgpu::PipelineLayout,
+    color_format:   wgpu::TextureFormat,
+    depth_stencil:  Option<wgpu::DepthStencilState>,
+    vertex_layouts: &[wgpu::VertexBufferLayout],
+    shader:         &wgpu::ShaderModule,
+    label:          &str,
+) -> wgpu::RenderPipeline {
+    create_render_pipeline_with_raster(device, layout, color_format, depth_stencil, vertex_layouts, shader, RasterState::default(), label)
+}
+
+// Rasterizer settings that differ between pipelines drawing the same shader
+#[derive(Debug, Copy, Clone)]
+pub struct RasterState {
+    pub cull_mode:         Option<wgpu::Face>,
+    pub sample_count:      u32,
+    // Turns the fragment's alpha into sample coverage, only useful with more than one sample
+    pub alpha_to_coverage: bool,
+    // Replaces the depth state's bias. Constant and slope-scaled offsets added to each fragment's
+    // depth, with `clamp` capping their sum when it's not 0.0.
+    pub depth_bias:        wgpu::DepthBiasState,
+}
+
+impl Default for RasterState {
+    fn default() -> Self {
+        Self {
+            cull_mode:         Some(wgpu::Face::Back),
+            sample_count:      1,
+            alpha_to_coverage: false,
+            depth_bias:        wgpu::DepthBiasState::default(),
+        }
+    }
+}
+
+// Like `create_render_pipeline` with `raster` in place of the default rasterizer settings
+#[allow(clippy::too_many_arguments)]
+pub fn create_render_pipeline_with_raster(
+    device:         &wgpu::Device,
+    layout:         &wgpu::PipelineLayout,
+    color_format:   wgpu::TextureFormat,
+    depth_stencil:  Option<wgpu::DepthStencilState>,
+    vertex_layouts: &[wgpu::VertexBufferLayout],
+    shader:         &wgpu::ShaderModule,
+    raster:         RasterState,
+    label:          &str,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label:    Some(label),
+        layout:   Some(layout),
+        vertex:   wgpu::VertexState {
+            module:      shader,
+            entry_point: "vs_main",
+            buffers:     vertex_layouts,
+        },
+        fragment: Some(wgpu::FragmentState {
+            module:      shader,
+            entry_point: "fs_main",
+            targets:     &[Some(wgpu::ColorTargetState {
+                format:     color_format,
+                blend:      Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology:           wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face:         wgpu::FrontFace::Ccw,
+            cull_mode:          raster.cull_mode,
+            polygon_mode:       wgpu::PolygonMode::Fill,
+            unclipped_depth:    false,
+            conservative:       false,
+        },
+        depth_stencil: depth_stencil.map(|state| wgpu::DepthStencilState { bias: raster.depth_bias, ..state }),
+        multisample:   wgpu::MultisampleState {
+            count: raster.sample_count,
+            mask:  !0,
+            alpha_to_coverage_enabled: raster.alpha_to_coverage
+        },
+        multiview: None,
+    })
+}
+
+pub fn depth_state(
+    format:        wgpu::TextureFormat,
+    depth_compare: wgpu::CompareFunction, // when to discard a new pixel
+    write_enabled: bool,
+) -> wgpu::DepthStencilState {
+    wgpu::DepthStencilState {
+        format,
+        depth_write_enabled: write_enabled,
+        depth_compare,
+        stencil:             wgpu::StencilState::default(),
+        bias:                wgpu::DepthBiasState::default(),
+    }
+}
+
+// Vertex-only pipeline that lays down depth so the main pass can shade each pixel once. `raster`
+// should match the pipelines it's laying depth down for. Also used for shadow maps, whose bias
+// keeps surfaces from shadowing themselves.
+pub fn create_depth_prepass_pipeline(
+    device:         &wgpu::Device,
+    layout:         &wgpu::PipelineLayout,
+    depth_stencil:  wgpu::DepthStencilState,
+    vertex_layouts: &[wgpu::VertexBufferLayout],
+    shader:         &wgpu::ShaderModule,
+    raster:         RasterState,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label:    Some("Depth Prepass Pipeline"),
+        layout:   Some(layout),
+        vertex:   wgpu::VertexState {
+            module:      shader,
+            entry_point: "vs_main",
+            buffers:     vertex_layouts,
+        },
+        fragment: None,
+        primitive: wgpu::PrimitiveState {
+            topology:           wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face:         wgpu::FrontFace::Ccw,
+            cull_mode:          raster.cull_mode,
+            polygon_mode:       wgpu::PolygonMode::Fill,
+            unclipped_depth:    false,
+            conservative:       false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState { bias: raster.depth_bias, ..depth_stencil }),
+        multisample:   wgpu::MultisampleState {
+            count: raster.sample_count,
+            mask:  !0,
+            alpha_to_coverage_enabled: false
+        },
+        multiview: None,
+    })
+}
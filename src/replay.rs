@@ -0,0 +1,523 @@
+// Records what the user does to a file and plays it back the same way every time, so a bug seen
+// once can be reproduced, and golden-image tests can drive the interactive paths. Setting
+// `LEARN_WGPU_RECORD` to a path records to it and `LEARN_WGPU_REPLAY` plays it back.
+//
+// Recordings hold the window events that reach `Input` and the app, and raw mouse motion, grouped
+// by the frame whose update they came before. Frames are stepped by a fixed `FIXED_STEP` while
+// recording rather than by the wall clock, so replaying doesn't depend on how fast either machine
+// was. The window is resized to the recorded size, and `Input::seed` is the recorded seed, so apps
+// that randomize from it pick the same numbers. While replaying, the user's input is ignored
+// until the recording runs out. Gamepads, MIDI and audio input aren't recorded, and neither is
+// anything timed by the wall clock on its own, like auto exposure. Native only.
+//
+// The file is text, a header then one line per event and a `frame` line ending each frame:
+//
+//   learn_wgpu replay 1
+//   seed 1234
+//   size 800 600
+//   key pressed W
+//   cursor 412.5 300
+//   frame 16667
+
+use std::{
+    collections::VecDeque,
+    fmt::Write as _,
+    io::Write as _,
+    path::Path,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use winit::{dpi::PhysicalPosition, dpi::PhysicalSize, event::*};
+
+const HEADER: &str = "learn_wgpu replay 1";
+
+pub const FIXED_STEP: Duration = Duration::from_micros(16_667);
+
+// Every key winit knows, which are written by their names
+const KEYS: [VirtualKeyCode; 163] = {
+    use VirtualKeyCode::*;
+
+    [
+        Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0, A, B, C, D, E, F, G, H, I, J, K, L,
+        M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z, Escape, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11,
+        F12, F13, F14, F15, F16, F17, F18, F19, F20, F21, F22, F23, F24, Snapshot, Scroll, Pause,
+        Insert, Home, Delete, End, PageDown, PageUp, Left, Up, Right, Down, Back, Return, Space,
+        Compose, Caret, Numlock, Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7,
+        Numpad8, Numpad9, NumpadAdd, NumpadDivide, NumpadDecimal, NumpadComma, NumpadEnter,
+        NumpadEquals, NumpadMultiply, NumpadSubtract, AbntC1, AbntC2, Apostrophe, Apps, Asterisk, At,
+        Ax, Backslash, Calculator, Capital, Colon, Comma, Convert, Equals, Grave, Kana, Kanji, LAlt,
+        LBracket, LControl, LShift, LWin, Mail, MediaSelect, MediaStop, Minus, Mute, MyComputer,
+        NavigateForward, NavigateBackward, NextTrack, NoConvert, OEM102, Period, PlayPause, Plus, Power,
+        PrevTrack, RAlt, RBracket, RControl, RShift, RWin, Semicolon, Slash, Sleep, Stop, Sysrq, Tab,
+        Underline, Unlabeled, VolumeDown, VolumeUp, Wake, WebBack, WebFavorites, WebForward, WebHome,
+        WebRefresh, WebSearch, WebStop, Yen, Copy, Paste, Cut,
+    ]
+};
+
+// One thing the user did, as recorded
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ReplayEvent {
+    Key { key: VirtualKeyCode, pressed: bool },
+    Button { button: MouseButton, pressed: bool },
+    Cursor { x: f64, y: f64 },
+    // In lines, or in pixels when `pixels`
+    Wheel { x: f64, y: f64, pixels: bool },
+    Modifiers(ModifiersState),
+    Focused(bool),
+    // Raw mouse movement, which arrives as a device event rather than a window one
+    Motion { dx: f64, dy: f64 },
+}
+
+impl ReplayEvent {
+    // None for window events that aren't input, like resizes, which are left to happen live
+    pub fn from_window_event(event: &WindowEvent) -> Option<Self> {
+        let pressed = |state: &ElementState| *state == ElementState::Pressed;
+
+        match event {
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state,
+                    virtual_keycode: Some(key),
+                    ..
+                },
+                ..
+            } => Some(ReplayEvent::Key { key: *key, pressed: pressed(state) }),
+            WindowEvent::MouseInput { state, button, .. } => Some(ReplayEvent::Button { button: *button, pressed: pressed(state) }),
+            WindowEvent::CursorMoved { position, .. } => Some(ReplayEvent::Cursor { x: position.x, y: position.y }),
+            WindowEvent::MouseWheel { delta, .. } => Some(match delta {
+                MouseScrollDelta::LineDelta(x, y) => ReplayEvent::Wheel { x: *x as f64, y: *y as f64, pixels: false },
+                MouseScrollDelta::PixelDelta(pos) => ReplayEvent::Wheel { x: pos.x, y: pos.y, pixels: true },
+            }),
+            WindowEvent::ModifiersChanged(modifiers) => Some(ReplayEvent::Modifiers(*modifiers)),
+            WindowEvent::Focused(focused)            => Some(ReplayEvent::Focused(*focused)),
+            _ => None,
+        }
+    }
+
+    pub fn from_device_event(event: &DeviceEvent) -> Option<Self> {
+        match event {
+            DeviceEvent::MouseMotion { delta } => Some(ReplayEvent::Motion { dx: delta.0, dy: delta.1 }),
+            _ => None,
+        }
+    }
+
+    // None for `Motion`, see `to_device_event`
+    #[allow(deprecated)]
+    pub fn to_window_event(&self) -> Option<WindowEvent<'static>> {
+        // Safe in that it's only ever compared against, and nothing here tells devices apart
+        let device_id = unsafe { DeviceId::dummy() };
+        let modifiers = ModifiersState::empty();
+        let state     = |pressed: bool| if pressed { ElementState::Pressed } else { ElementState::Released };
+
+        Some(match *self {
+            ReplayEvent::Key { key, pressed } => WindowEvent::KeyboardInput {
+                device_id,
+                input: KeyboardInput {
+                    scancode:        0,
+                    state:           state(pressed),
+                    virtual_keycode: Some(key),
+                    modifiers,
+                },
+                is_synthetic: false,
+            },
+            ReplayEvent::Button { button, pressed } => WindowEvent::MouseInput { device_id, state: state(pressed), button, modifiers },
+            ReplayEvent::Cursor { x, y } => WindowEvent::CursorMoved { device_id, position: PhysicalPosition::new(x, y), modifiers },
+            ReplayEvent::Wheel { x, y, pixels } => WindowEvent::MouseWheel {
+                device_id,
+                delta: if pixels {
+                    MouseScrollDelta::PixelDelta(PhysicalPosition::new(x, y))
+                } else {
+                    MouseScrollDelta::LineDelta(x as f32, y as f32)
+                },
+                phase: TouchPhase::Moved,
+                modifiers,
+            },
+            ReplayEvent::Modifiers(modifiers) => WindowEvent::ModifiersChanged(modifiers),
+            ReplayEvent::Focused(focused)     => WindowEvent::Focused(focused),
+            ReplayEvent::Motion { .. }        => return None,
+        })
+    }
+
+    pub fn to_device_event(&self) -> Option<DeviceEvent> {
+        match *self {
+            ReplayEvent::Motion { dx, dy } => Some(DeviceEvent::MouseMotion { delta: (dx, dy) }),
+            _ => None,
+        }
+    }
+
+    fn write(&self, out: &mut String) {
+        let state = |pressed: bool| if pressed { "pressed" } else { "released" };
+
+        // Writing to a String can't fail
+        let _ = match *self {
+            ReplayEvent::Key { key, pressed } => writeln!(out, "key {} {:?}", state(pressed), key),
+            ReplayEvent::Button { button, pressed } => match button {
+                MouseButton::Left     => writeln!(out, "button {} left", state(pressed)),
+                MouseButton::Right    => writeln!(out, "button {} right", state(pressed)),
+                MouseButton::Middle   => writeln!(out, "button {} middle", state(pressed)),
+                MouseButton::Other(n) => writeln!(out, "button {} {}", state(pressed), n),
+            },
+            ReplayEvent::Cursor { x, y }        => writeln!(out, "cursor {} {}", x, y),
+            ReplayEvent::Wheel { x, y, pixels } => writeln!(out, "wheel {} {} {}", if pixels { "pixels" } else { "lines" }, x, y),
+            ReplayEvent::Modifiers(modifiers)   => writeln!(out, "modifiers {}", modifiers.bits()),
+            ReplayEvent::Focused(focused)       => writeln!(out, "focused {}", focused),
+            ReplayEvent::Motion { dx, dy }      => writeln!(out, "motion {} {}", dx, dy),
+        };
+    }
+
+    fn parse(line: &str) -> Result<Self> {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+
+        let pressed = |word: &str| match word {
+            "pressed"  => Ok(true),
+            "released" => Ok(false),
+            _          => Err(anyhow!("Expected pressed or released, found {:?}", word)),
+        };
+        let number = |word: &str| word.parse::<f64>().with_context(|| format!("Expected a number, found {:?}", word));
+
+        Ok(match words[..] {
+            ["key", state, name] => ReplayEvent::Key {
+                key:     *KEYS.iter()
+                    .find(|key| format!("{:?}", key) == name)
+                    .ok_or_else(|| anyhow!("Unknown key {:?}", name))?,
+                pressed: pressed(state)?,
+            },
+            ["button", state, button] => ReplayEvent::Button {
+                button:  match button {
+                    "left"   => MouseButton::Left,
+                    "right"  => MouseButton::Right,
+                    "middle" => MouseButton::Middle,
+                    other    => MouseButton::Other(other.parse().with_context(|| format!("Unknown button {:?}", other))?),
+                },
+                pressed: pressed(state)?,
+            },
+            ["cursor", x, y]           => ReplayEvent::Cursor { x: number(x)?, y: number(y)? },
+            ["wheel", "lines", x, y]   => ReplayEvent::Wheel { x: number(x)?, y: number(y)?, pixels: false },
+            ["wheel", "pixels", x, y]  => ReplayEvent::Wheel { x: number(x)?, y: number(y)?, pixels: true },
+            ["modifiers", bits]        => ReplayEvent::Modifiers(
+                ModifiersState::from_bits(bits.parse()?).ok_or_else(|| anyhow!("Unknown modifiers {:?}", bits))?
+            ),
+            ["focused", focused]       => ReplayEvent::Focused(focused.parse()?),
+            ["motion", dx, dy]         => ReplayEvent::Motion { dx: number(dx)?, dy: number(dy)? },
+            _ => bail!("Unknown event {:?}", line),
+        })
+    }
+}
+
+// Writes a recording as it's made, a frame at a time
+pub struct Recorder {
+    file:  std::io::BufWriter<std::fs::File>,
+    // This frame's events so far
+    frame: String,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, seed: u64, size: PhysicalSize<u32>) -> Result<Self> {
+        let file = std::fs::File::create(path)
+            .with_context(|| format!("Couldn't create the recording {}", path.display()))?;
+        let mut file = std::io::BufWriter::new(file);
+
+        writeln!(file, "{}\nseed {}\nsize {} {}", HEADER, seed, size.width, size.height)?;
+
+        Ok(Self { file, frame: String::new() })
+    }
+
+    pub fn event(&mut self, event: &ReplayEvent) {
+        event.write(&mut self.frame);
+    }
+
+    // Ends the frame, which is stepped by `FIXED_STEP`. Flushed every frame, since the app can
+    // exit without anything being dropped.
+    pub fn end_frame(&mut self) -> Result<()> {
+        let _ = writeln!(self.frame, "frame {}", FIXED_STEP.as_micros());
+
+        self.file.write_all(self.frame.as_bytes())?;
+        self.file.flush()?;
+        self.frame.clear();
+        Ok(())
+    }
+}
+
+// The events to handle before one frame's update, and the time to step it by
+#[derive(Debug, Clone)]
+pub struct ReplayFrame {
+    pub events: Vec<ReplayEvent>,
+    pub dt:     Duration,
+}
+
+// A recording being played back
+pub struct Replay {
+    seed:   u64,
+    size:   PhysicalSize<u32>,
+    frames: VecDeque<ReplayFrame>,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Couldn't read the recording {}", path.display()))?;
+
+        Self::parse(&text).with_context(|| format!("Couldn't parse the recording {}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut lines = text.lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty());
+
+        let mut next = |what: &str| lines.next().ok_or_else(|| anyhow!("Expected {}, found the end of the file", what));
+
+        let (_, header) = next("the header")?;
+
+        if header != HEADER {
+            bail!("Expected {:?}, found {:?}", HEADER, header);
+        }
+
+        let seed = match next("the seed")? {
+            (_, line) if line.starts_with("seed ") => line["seed ".len()..].parse::<u64>()
+                .with_context(|| format!("Bad seed {:?}", line))?,
+            (n, line) => bail!("Line {}: expected the seed, found {:?}", n, line),
+        };
+
+        let size = match next("the window size")?.1.split_whitespace().collect::<Vec<_>>()[..] {
+            ["size", width, height] => PhysicalSize::new(width.parse()?, height.parse()?),
+            ref words               => bail!("Expected the window size, found {:?}", words.join(" ")),
+        };
+
+        let mut frames = VecDeque::new();
+        let mut events = Vec::new();
+
+        for (n, line) in lines {
+            if let Some(micros) = line.strip_prefix("frame ") {
+                let micros = micros.parse::<u64>().with_context(|| format!("Line {}: bad frame time {:?}", n, micros))?;
+
+                frames.push_back(ReplayFrame { events: std::mem::take(&mut events), dt: Duration::from_micros(micros) });
+            } else {
+                events.push(ReplayEvent::parse(line).with_context(|| format!("Line {}", n))?);
+            }
+        }
+
+        // Anything after the last frame line never made it into an update, so it's left out
+
+        Ok(Self { seed, size, frames })
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn window_size(&self) -> PhysicalSize<u32> {
+        self.size
+    }
+
+    // Frames left to play back
+    pub fn remaining(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn next_frame(&mut self) -> Option<ReplayFrame> {
+        self.frames.pop_front()
+    }
+}
+
+// Whether the runner is taking input from the user, recording it, or playing a recording back
+pub enum ReplayMode {
+    Live,
+    Recording(Recorder),
+    Replaying(Replay),
+}
+
+impl ReplayMode {
+    // Replays `LEARN_WGPU_REPLAY` if it's set, otherwise records to `LEARN_WGPU_RECORD` if that
+    // is, with `seed` and the window's size. Falls back to live input if either fails.
+    pub fn from_env(seed: u64, size: PhysicalSize<u32>) -> Self {
+        if let Some(path) = std::env::var_os("LEARN_WGPU_REPLAY") {
+            return match Replay::load(Path::new(&path)) {
+                Ok(replay) => {
+                    log::info!("Replaying {} frames from {}", replay.remaining(), Path::new(&path).display());
+                    ReplayMode::Replaying(replay)
+                }
+                Err(e) => {
+                    log::error!("{:#}", e);
+                    ReplayMode::Live
+                }
+            };
+        }
+
+        if let Some(path) = std::env::var_os("LEARN_WGPU_RECORD") {
+            return match Recorder::create(Path::new(&path), seed, size) {
+                Ok(recorder) => {
+                    log::info!("Recording input to {}", Path::new(&path).display());
+                    ReplayMode::Recording(recorder)
+                }
+                Err(e) => {
+                    log::error!("{:#}", e);
+                    ReplayMode::Live
+                }
+            };
+        }
+
+        ReplayMode::Live
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        matches!(self, ReplayMode::Replaying(_))
+    }
+
+    // Whether input from the user should be handled, recording it if so. It's ignored while
+    // replaying so it can't change what happens.
+    pub fn live_event(&mut self, event: &ReplayEvent) -> bool {
+        match self {
+            ReplayMode::Live                => true,
+            ReplayMode::Recording(recorder) => {
+                recorder.event(event);
+                true
+            }
+            ReplayMode::Replaying(_)        => false,
+        }
+    }
+
+    // Called before each update with the wall clock time since the last. Returns the time to step
+    // by, and the recorded events to handle first when replaying.
+    pub fn next_frame(&mut self, elapsed: Duration) -> (Duration, Vec<ReplayEvent>) {
+        match self {
+            ReplayMode::Live => (elapsed, Vec::new()),
+            ReplayMode::Recording(recorder) => {
+                if let Err(e) = recorder.end_frame() {
+                    log::error!("Stopped recording: {:#}", e);
+                    *self = ReplayMode::Live;
+                }
+
+                (FIXED_STEP, Vec::new())
+            }
+            ReplayMode::Replaying(replay) => match replay.next_frame() {
+                Some(frame) => (frame.dt, frame.events),
+                None => {
+                    log::info!("Replay finished, back to live input");
+                    *self = ReplayMode::Live;
+                    (elapsed, Vec::new())
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One of every kind of event, with the awkward cases of each
+    fn events() -> Vec<ReplayEvent> {
+        vec![
+            ReplayEvent::Key { key: VirtualKeyCode::W, pressed: true },
+            ReplayEvent::Key { key: VirtualKeyCode::NumpadEnter, pressed: false },
+            ReplayEvent::Button { button: MouseButton::Left, pressed: true },
+            ReplayEvent::Button { button: MouseButton::Right, pressed: false },
+            ReplayEvent::Button { button: MouseButton::Middle, pressed: true },
+            ReplayEvent::Button { button: MouseButton::Other(7), pressed: false },
+            ReplayEvent::Cursor { x: 412.5, y: 0.1 + 0.2 },
+            ReplayEvent::Wheel { x: 0.0, y: -1.0, pixels: false },
+            ReplayEvent::Wheel { x: -3.25, y: 120.0, pixels: true },
+            ReplayEvent::Modifiers(ModifiersState::SHIFT | ModifiersState::LOGO),
+            ReplayEvent::Modifiers(ModifiersState::empty()),
+            ReplayEvent::Focused(true),
+            ReplayEvent::Focused(false),
+            ReplayEvent::Motion { dx: -1e-7, dy: 12345.678 },
+        ]
+    }
+
+    #[test]
+    fn events_survive_the_text_format() {
+        for event in events() {
+            let mut line = String::new();
+            event.write(&mut line);
+
+            assert_eq!(ReplayEvent::parse(&line).unwrap(), event, "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn every_key_survives_the_text_format() {
+        for key in KEYS {
+            let event    = ReplayEvent::Key { key, pressed: true };
+            let mut line = String::new();
+            event.write(&mut line);
+
+            assert_eq!(ReplayEvent::parse(&line).unwrap(), event, "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn events_survive_winit() {
+        for event in events() {
+            let back = match event.to_window_event() {
+                Some(window_event) => ReplayEvent::from_window_event(&window_event),
+                None               => ReplayEvent::from_device_event(&event.to_device_event().unwrap()),
+            };
+
+            assert_eq!(back, Some(event));
+        }
+    }
+
+    #[test]
+    fn bad_recordings_are_errors() {
+        assert!(Replay::parse("").is_err());
+        assert!(Replay::parse("learn_wgpu replay 0\nseed 1\nsize 1 1\n").is_err());
+        assert!(Replay::parse(&format!("{}\nsize 1 1\nseed 1\n", HEADER)).is_err());
+        assert!(Replay::parse(&format!("{}\nseed 1\nsize 1 1\nkey pressed NotAKey\nframe 1\n", HEADER)).is_err());
+        assert!(Replay::parse(&format!("{}\nseed 1\nsize 1 1\nframe soon\n", HEADER)).is_err());
+    }
+
+    #[test]
+    fn recordings_replay_what_was_recorded() {
+        let path = std::env::temp_dir().join(format!("learn_wgpu_replay_test_{}.txt", std::process::id()));
+        let seed = 0xdead_beef_u64;
+        let size = PhysicalSize::new(800, 600);
+
+        // A frame for each event, then an empty one, then them all in one frame
+        let mut recorded = events().into_iter().map(|event| vec![event]).collect::<Vec<_>>();
+        recorded.push(Vec::new());
+        recorded.push(events());
+
+        {
+            let mut mode = ReplayMode::Recording(Recorder::create(&path, seed, size).unwrap());
+
+            for frame in &recorded {
+                for event in frame {
+                    assert!(mode.live_event(event));
+                }
+
+                // Stepped by the fixed step whatever the wall clock says
+                assert_eq!(mode.next_frame(Duration::from_secs(1)), (FIXED_STEP, Vec::new()));
+            }
+
+            // Never reaches an update, so it isn't replayed
+            mode.live_event(&ReplayEvent::Focused(false));
+        }
+
+        let replay = Replay::load(&path);
+        let _      = std::fs::remove_file(&path);
+        let replay = replay.unwrap();
+
+        assert_eq!(replay.seed(), seed);
+        assert_eq!(replay.window_size(), size);
+        assert_eq!(replay.remaining(), recorded.len());
+
+        let mut mode = ReplayMode::Replaying(replay);
+
+        assert!(mode.is_replaying());
+        assert!(!mode.live_event(&ReplayEvent::Focused(true)));
+
+        for frame in &recorded {
+            assert_eq!(mode.next_frame(Duration::from_secs(1)), (FIXED_STEP, frame.clone()));
+        }
+
+        // Back to live input once it runs out
+        let elapsed = Duration::from_millis(5);
+
+        assert_eq!(mode.next_frame(elapsed), (elapsed, Vec::new()));
+        assert!(!mode.is_replaying());
+    }
+}
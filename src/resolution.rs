@@ -0,0 +1,214 @@
+// Dynamic resolution: the scene is drawn into an internal target whose size follows recent frame
+// times, then upscaled to the surface. The same target feeds post effects.
+
+use std::time::Duration;
+
+use crate::{bind_group, crash_report};
+
+// Scales snap to multiples of this so the targets aren't reallocated every frame
+const SCALE_STEP: f32 = 0.05;
+// How far under the target frame time counts as having room to scale back up
+const HEADROOM: f32 = 0.05;
+const SMOOTHING: f32 = 0.1;
+// Frames to wait after a change so the average reflects the new scale
+const COOLDOWN_FRAMES: u32 = 30;
+
+// Picks the render scale from whole-frame times. GPU timestamp queries aren't available on WebGL,
+// so the time between redraws stands in for GPU time. With vsync the frame time can't drop below
+// the refresh interval, so the scale is nudged up whenever frames are on target and backs off
+// again if that turns out to be too much.
+#[derive(Debug, Copy, Clone)]
+pub struct DynamicResolution {
+    pub target_frame_time: Duration,
+    pub min_scale:         f32,
+    pub max_scale:         f32,
+    scale:                 f32,
+    average:               f32,
+    cooldown:              u32,
+}
+
+impl DynamicResolution {
+    pub fn new(target_frame_time: Duration) -> Self {
+        Self {
+            target_frame_time,
+            min_scale: 0.5,
+            max_scale: 1.0,
+            scale:     1.0,
+            average:   target_frame_time.as_secs_f32(),
+            cooldown:  COOLDOWN_FRAMES,
+        }
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    // Returns true when the scale changed
+    pub fn update(&mut self, frame_time: Duration) -> bool {
+        self.average += (frame_time.as_secs_f32() - self.average) * SMOOTHING;
+
+        if self.cooldown > 0 {
+            self.cooldown -= 1;
+            return false;
+        }
+
+        let ratio = self.target_frame_time.as_secs_f32() / self.average;
+
+        // Pixel cost goes with area, so each axis scales by the square root of the time ratio
+        let ideal = if ratio >= 1.0 - HEADROOM {
+            self.scale + SCALE_STEP
+        } else {
+            self.scale * ratio.sqrt()
+        };
+
+        let scale = ((ideal / SCALE_STEP).round() * SCALE_STEP).clamp(self.min_scale, self.max_scale);
+
+        if (scale - self.scale).abs() < SCALE_STEP / 2.0 {
+            return false;
+        }
+
+        self.scale    = scale;
+        self.cooldown = COOLDOWN_FRAMES;
+        true
+    }
+}
+
+// Color targets the scene is drawn into at the internal resolution. The scene goes into the first
+// and post effects ping-pong between the two. Depth lives in the context's depth texture, which is
+// sized to match.
+pub struct SceneTarget {
+    pub width:  u32,
+    pub height: u32,
+    views:      [wgpu::TextureView; 2],
+    format:     wgpu::TextureFormat,
+    pipeline:   wgpu::RenderPipeline,
+    layout:     wgpu::BindGroupLayout,
+    sampler:    wgpu::Sampler,
+}
+
+impl SceneTarget {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, width: u32, height: u32) -> Self {
+        let layout = bind_group::BindGroupLayoutBuilder::new()
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .sampler(wgpu::ShaderStages::FRAGMENT)
+            .build(device, "upscale_bind_group_layout");
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Upscale Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("upscale.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Upscale Pipeline Layout"),
+            bind_group_layouts:   &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label:    Some("Upscale Pipeline"),
+            layout:   Some(&pipeline_layout),
+            vertex:   wgpu::VertexState {
+                module:      &shader,
+                entry_point: "vs_main",
+                buffers:     &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module:      &shader,
+                entry_point: "fs_main",
+                targets:     &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend:      Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive:     wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample:   wgpu::MultisampleState::default(),
+            multiview:     None,
+        });
+
+        // Bilinear filtering does the upscaling
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label:          Some("Upscale Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter:     wgpu::FilterMode::Linear,
+            min_filter:     wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            width,
+            height,
+            views: create_views(device, format, width, height),
+            format,
+            pipeline,
+            layout,
+            sampler,
+        }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.views  = create_views(device, self.format, width, height);
+        self.width  = width;
+        self.height = height;
+    }
+
+    pub fn view(&self, index: usize) -> &wgpu::TextureView {
+        &self.views[index]
+    }
+
+    // Draws `input`, one of this target's views, over all of `output`
+    pub fn upscale(
+        &self,
+        device:  &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        input:   &wgpu::TextureView,
+        output:  &wgpu::TextureView,
+    ) {
+        // Which view holds the final image changes with the number of post effects, so the bind
+        // group is made per frame
+        let bind_group = bind_group::BindGroupBuilder::new(&self.layout)
+            .texture(input)
+            .sampler(&self.sampler)
+            .build(device, "upscale_bind_group");
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Upscale Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view:           output,
+                resolve_target: None,
+                ops:  wgpu::Operations {
+                    load:  wgpu::LoadOp::Load,
+                    store: true
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        crash_report::set_pipeline(&mut render_pass, &self.pipeline, "Upscale Pipeline");
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+fn create_views(
+    device: &wgpu::Device,
+    format: wgpu::TextureFormat,
+    width:  u32,
+    height: u32,
+) -> [wgpu::TextureView; 2] {
+    [0, 1].map(|_| {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label:           Some("Scene Target"),
+            size:            wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count:    1,
+            dimension:       wgpu::TextureDimension::D2,
+            format,
+            usage:           wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+
+        texture.create_view(&wgpu::TextureViewDescriptor::default())
+    })
+}
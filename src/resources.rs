@@ -1,9 +1,19 @@
-use std::io::{BufReader, Cursor};
+use std::{
+    collections::HashMap,
+    hash::BuildHasher,
+    io::{BufReader, Cursor},
+};
 
 use cfg_if::cfg_if;
 use wgpu::util::DeviceExt;
 
-use crate::{model, texture};
+#[cfg(feature = "meshopt")]
+use crate::{lod, optimize};
+#[cfg(feature = "gltf")]
+use crate::morph;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::asset_cache;
+use crate::{bounds, color_grading, gpu_stats, model, shader_code::ShaderCode, shading::{AlphaMode, CullMode, DepthBias, ShadingModel}, texture};
 
 #[cfg(target_arch = "wasm32")]
 fn format_url(file_name: &str) -> reqwest::Url {
@@ -18,6 +28,26 @@ fn format_url(file_name: &str) -> reqwest::Url {
     base.join(file_name).unwrap()
 }
 
+// Absolute paths (e.g. dropped files) are read as-is, everything else comes from `res/`
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn resolve_path(file_name: &str) -> std::path::PathBuf {
+    let path = std::path::Path::new(file_name);
+
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::path::Path::new(env!("OUT_DIR")).join("res").join(path)
+    }
+}
+
+// Materials and textures are named relative to the file that references them
+fn relative_to(file_name: &str, name: &str) -> String {
+    match std::path::Path::new(file_name).parent() {
+        Some(dir) => dir.join(name).to_string_lossy().into_owned(),
+        None      => name.to_string(),
+    }
+}
+
 pub async fn load_string(file_name: &str) -> anyhow::Result<String> {
     cfg_if! {
         if #[cfg(target_arch = "wasm32")] {
@@ -27,10 +57,8 @@ pub async fn load_string(file_name: &str) -> anyhow::Result<String> {
                 .text()
                 .await?;
         } else {
-            let path = std::path::Path::new(env!("OUT_DIR"))
-                .join("res")
-                .join(file_name);
-            let txt = std::fs::read_to_string(path)?;
+            let path = resolve_path(file_name);
+            let txt  = std::fs::read_to_string(path)?;
         }
     }
 
@@ -47,9 +75,7 @@ pub async fn load_binary(file_name: &str) -> anyhow::Result<Vec<u8>> {
                 .await?
                 .to_vec();
         } else {
-            let path = std::path::Path::new(env!("OUT_DIR"))
-                .join("res")
-                .join(file_name);
+            let path = resolve_path(file_name);
             let data = std::fs::read(path)?;
         }
     }
@@ -64,7 +90,44 @@ pub async fn load_texture(
 ) -> anyhow::Result<texture::Texture> {
     let data = load_binary(file_name).await?;
 
-    texture::Texture::from_bytes(device, queue, &data, file_name)
+    decode_texture(device, queue, &data, file_name, wgpu::TextureFormat::Rgba8UnormSrgb)
+}
+
+// For data rather than colors, see `Texture::from_linear_bytes`
+pub async fn load_linear_texture(
+    file_name: &str,
+    device:    &wgpu::Device,
+    queue:     &wgpu::Queue,
+) -> anyhow::Result<texture::Texture> {
+    let data = load_binary(file_name).await?;
+
+    decode_texture(device, queue, &data, file_name, wgpu::TextureFormat::Rgba8Unorm)
+}
+
+pub async fn load_hdr_texture(
+    file_name: &str,
+    device:    &wgpu::Device,
+    queue:     &wgpu::Queue,
+) -> anyhow::Result<texture::Texture> {
+    let data = load_binary(file_name).await?;
+
+    texture::Texture::from_hdr_bytes(device, queue, &data, file_name)
+}
+
+// `.cube` files are read as text, anything else as a strip image
+pub async fn load_lut(file_name: &str) -> anyhow::Result<color_grading::Lut> {
+    if file_name.to_lowercase().ends_with(".cube") {
+        color_grading::Lut::from_cube(&load_string(file_name).await?)
+    } else {
+        color_grading::Lut::from_strip_image(&load_binary(file_name).await?)
+    }
+}
+
+// WGSL, GLSL or SPIR-V by the file's extension, see shader_code.rs
+pub async fn load_shader(file_name: &str, device: &wgpu::Device) -> anyhow::Result<wgpu::ShaderModule> {
+    let data = load_binary(file_name).await?;
+
+    ShaderCode::from_file(file_name, data)?.create_module(device, file_name)
 }
 
 pub async fn load_model(
@@ -73,90 +136,734 @@ pub async fn load_model(
     queue:     &wgpu::Queue,
     layout:    &wgpu::BindGroupLayout,
 ) -> anyhow::Result<model::Model> {
+    let (models, obj_materials) = load_obj(file_name).await?;
+
+    let mut materials = Vec::new();
+
+    for m in obj_materials? {
+        let desc = MaterialDesc::from_mtl(&m)?;
+
+        materials.push(build_material(file_name, m.name.clone(), &desc, device, queue, layout).await?)
+    }
+
+    let meshes = models
+        .into_iter()
+        .map(|m| create_mesh(device, file_name, &obj_vertices(&m.mesh), &m.mesh.indices, m.mesh.material_id.unwrap_or(0)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    Ok(model::Model { meshes, materials })
+}
+
+// What a material is made of past its diffuse texture, read from a .material file or gathered
+// from the lines of a .mtl file. Anything left out keeps `MaterialParams`'s default. The modes
+// are named as `ShadingModel::from_name` and its neighbours take them.
+#[derive(Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MaterialDesc {
+    // Defaults to the file's name
+    pub name:                Option<String>,
+    pub textures:            MaterialTextures,
+    #[serde(alias = "shader")]
+    pub shading:             Option<String>,
+    pub cull:                Option<String>,
+    pub alpha:               Option<String>,
+    pub depth_bias:          Option<String>,
+    pub alpha_cutoff:        Option<f32>,
+    pub emissive:            Option<[f32; 3]>,
+    // Only subsurface shading reads these, e.g. a `subsurface` of `(0.3, 0.8, 0.2)` and a
+    // `transmission` of 1.0 for leaves
+    pub subsurface:          Option<[f32; 3]>,
+    pub subsurface_wrap:     Option<f32>,
+    pub transmission:        Option<f32>,
+    // A roughness or anisotropy turns on the base highlight. The rotation is in turns, 0.0 to 1.0.
+    pub roughness:           Option<f32>,
+    pub anisotropy:          Option<f32>,
+    pub anisotropy_rotation: Option<f32>,
+    pub clearcoat:           Option<f32>,
+    pub clearcoat_roughness: Option<f32>,
+    // Glass, e.g. a `transmittance` of 1.0 with an `ior` of 1.5. Nothing shows through without a
+    // transmittance, whatever the index of refraction.
+    pub transmittance:       Option<f32>,
+    pub ior:                 Option<f32>,
+    pub thickness:           Option<f32>,
+    pub detail_scale:        Option<f32>,
+    pub detail_distance:     Option<f32>,
+    pub detail_strength:     Option<f32>,
+    // Above 1.0 for light clipped to fit the lightmap
+    pub lightmap_intensity:  Option<f32>,
+    // World space texturing for meshes without UVs, e.g. 0.5 to repeat every two units
+    pub triplanar:           Option<f32>,
+    pub triplanar_sharpness: Option<f32>,
+    // Shell fur, e.g. a `fur` length of 0.05 with a `fur_density` of 200 strands across the texture
+    pub fur:                 Option<f32>,
+    pub fur_density:         Option<f32>,
+    pub fur_shells:          Option<u32>,
+    pub fur_gravity:         Option<f32>,
+}
+
+// Named relative to the file that names them. The detail textures hold data rather than colors.
+#[derive(Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MaterialTextures {
+    pub diffuse:       String,
+    pub emissive:      Option<String>,
+    pub detail:        Option<String>,
+    pub detail_normal: Option<String>,
+    pub lightmap:      Option<String>,
+}
+
+impl MaterialDesc {
+    // From a .mtl material, whose lines past what tobj knows are keyed by their first word, e.g.
+    // `shading toon`, `Pc 1.0` for car paint or `map_detail grain.png`
+    fn from_mtl(m: &tobj::Material) -> anyhow::Result<Self> {
+        let params = &m.unknown_param;
+        let float  = |key: &str| float_param(params, key, &m.name);
+
+        Ok(Self {
+            name:                Some(m.name.clone()),
+            textures:            MaterialTextures {
+                diffuse:       m.diffuse_texture.clone(),
+                emissive:      params.get("map_Ke").cloned(),
+                detail:        params.get("map_detail").cloned(),
+                detail_normal: params.get("map_detail_normal").cloned(),
+                lightmap:      params.get("map_lightmap").cloned(),
+            },
+            shading:             params.get("shading").cloned(),
+            cull:                params.get("cull").cloned(),
+            alpha:               params.get("alpha").cloned(),
+            depth_bias:          params.get("depth_bias").cloned(),
+            alpha_cutoff:        float("alpha_cutoff")?,
+            // tobj leaves emission to the unknown parameters
+            emissive:            color_param(params, "Ke", &m.name)?,
+            subsurface:          color_param(params, "subsurface", &m.name)?,
+            subsurface_wrap:     float("subsurface_wrap")?,
+            transmission:        float("transmission")?,
+            roughness:           float("Pr")?,
+            anisotropy:          float("aniso")?,
+            anisotropy_rotation: float("anisor")?,
+            clearcoat:           float("Pc")?,
+            clearcoat_roughness: float("Pcr")?,
+            transmittance:       float("transmittance")?,
+            // Below 1.0 when there's no `Ni` line
+            ior:                 Some(m.optical_density).filter(|ior| *ior >= 1.0),
+            thickness:           float("thickness")?,
+            detail_scale:        float("detail_scale")?,
+            detail_distance:     float("detail_distance")?,
+            detail_strength:     float("detail_strength")?,
+            lightmap_intensity:  float("lightmap_intensity")?,
+            triplanar:           float("triplanar")?,
+            triplanar_sharpness: float("triplanar_sharpness")?,
+            fur:                 float("fur")?,
+            fur_density:         float("fur_density")?,
+            fur_shells:          params.get("fur_shells")
+                .map(|shells| shells.trim().parse().map_err(|_| anyhow::anyhow!("Invalid `fur_shells {}` in {}", shells, m.name)))
+                .transpose()?,
+            fur_gravity:         float("fur_gravity")?,
+        })
+    }
+}
+
+// Builds the material `desc` describes, named `name`. Textures are named relative to `file_name`.
+async fn build_material(
+    file_name: &str,
+    name:      String,
+    desc:      &MaterialDesc,
+    device:    &wgpu::Device,
+    queue:     &wgpu::Queue,
+    layout:    &wgpu::BindGroupLayout,
+) -> anyhow::Result<model::Material> {
+    let textures        = &desc.textures;
+    let diffuse_texture = load_texture(&relative_to(file_name, &textures.diffuse), device, queue).await?;
+
+    let emissive_texture = match &textures.emissive {
+        Some(texture) => Some(load_texture(&relative_to(file_name, texture), device, queue).await?),
+        None          => None,
+    };
+
+    let shading = match &desc.shading {
+        Some(mode) => ShadingModel::from_name(mode)
+            .ok_or_else(|| anyhow::anyhow!("Unknown shading model `{}` in {}", mode, name))?,
+        None       => ShadingModel::default(),
+    };
+
+    let cull = match &desc.cull {
+        Some(mode) => CullMode::from_name(mode)
+            .ok_or_else(|| anyhow::anyhow!("Unknown cull mode `{}` in {}", mode, name))?,
+        None       => CullMode::default(),
+    };
+
+    let alpha = match &desc.alpha {
+        Some(mode) => AlphaMode::from_name(mode)
+            .ok_or_else(|| anyhow::anyhow!("Unknown alpha mode `{}` in {}", mode, name))?,
+        None       => AlphaMode::default(),
+    };
+
+    let depth_bias = match &desc.depth_bias {
+        Some(mode) => DepthBias::from_name(mode)
+            .ok_or_else(|| anyhow::anyhow!("Unknown depth bias `{}` in {}", mode, name))?,
+        None       => DepthBias::default(),
+    };
+
+    let mut material = model::Material::new(device, layout, name, diffuse_texture, emissive_texture, shading);
+
+    material.cull       = cull;
+    material.alpha      = alpha;
+    material.depth_bias = depth_bias;
+
+    let params = &mut material.params;
+
+    if let Some(cutoff) = desc.alpha_cutoff {
+        params.alpha_cutoff = cutoff;
+    }
+
+    if let Some(emissive) = desc.emissive {
+        params.emissive           = emissive;
+        params.emissive_intensity = 1.0;
+    }
+
+    if let Some(color) = desc.subsurface {
+        params.subsurface_color = color;
+    }
+
+    if let Some(wrap) = desc.subsurface_wrap {
+        params.subsurface_wrap = wrap;
+    }
+
+    if let Some(transmission) = desc.transmission {
+        params.transmission = transmission;
+    }
+
+    if let Some(roughness) = desc.roughness {
+        params.specular  = 1.0;
+        params.roughness = roughness;
+    }
+
+    if let Some(anisotropy) = desc.anisotropy {
+        params.specular   = 1.0;
+        params.anisotropy = anisotropy;
+    }
+
+    if let Some(rotation) = desc.anisotropy_rotation {
+        params.anisotropy_rotation = rotation * std::f32::consts::TAU;
+    }
+
+    if let Some(clearcoat) = desc.clearcoat {
+        params.clearcoat = clearcoat;
+    }
+
+    if let Some(roughness) = desc.clearcoat_roughness {
+        params.clearcoat_roughness = roughness;
+    }
+
+    if let Some(transmittance) = desc.transmittance {
+        params.transmittance = transmittance;
+    }
+
+    if let Some(ior) = desc.ior {
+        params.ior = ior;
+    }
+
+    if let Some(thickness) = desc.thickness {
+        params.thickness = thickness;
+    }
+
+    if let Some(scale) = desc.detail_scale {
+        params.detail_scale = scale;
+    }
+
+    if let Some(distance) = desc.detail_distance {
+        params.detail_distance = distance;
+    }
+
+    if let Some(strength) = desc.detail_strength {
+        params.detail_strength = strength;
+    }
+
+    if let Some(intensity) = desc.lightmap_intensity {
+        params.lightmap_intensity = intensity;
+    }
+
+    if let Some(scale) = desc.triplanar {
+        params.triplanar_scale = scale;
+    }
+
+    if let Some(sharpness) = desc.triplanar_sharpness {
+        params.triplanar_sharpness = sharpness;
+    }
+
+    if let Some(length) = desc.fur {
+        params.fur_length = length;
+    }
+
+    if let Some(density) = desc.fur_density {
+        params.fur_density = density;
+    }
+
+    if let Some(shells) = desc.fur_shells {
+        params.fur_shells = shells;
+    }
+
+    if let Some(gravity) = desc.fur_gravity {
+        params.fur_gravity = gravity;
+    }
+
+    let detail_albedo = match &textures.detail {
+        Some(texture) => Some(load_linear_texture(&relative_to(file_name, texture), device, queue).await?),
+        None          => None,
+    };
+
+    let detail_normals = match &textures.detail_normal {
+        Some(texture) => Some(load_linear_texture(&relative_to(file_name, texture), device, queue).await?),
+        None          => None,
+    };
+
+    if detail_albedo.is_some() || detail_normals.is_some() {
+        material.set_detail_textures(device, layout, detail_albedo, detail_normals);
+    }
+
+    if let Some(texture) = &textures.lightmap {
+        let lightmap = load_texture(&relative_to(file_name, texture), device, queue).await?;
+
+        material.set_lightmap(device, layout, Some(lightmap));
+    }
+
+    Ok(material)
+}
+
+// A material on its own, declared in a .material file as a `MaterialDesc` in RON, e.g.
+//
+//   // Varnished floorboards
+//   (
+//       textures:  (diffuse: "floor.png"),
+//       shading:   "toon",
+//       cull:      "none",
+//       clearcoat: 1.0,
+//   )
+//
+// Only the diffuse texture is needed, and optional fields can leave out their `Some`. The name
+// defaults to the file's up to its first dot. Natively, `material_file::MaterialFile` loads it
+// again when it changes.
+pub async fn load_material(
+    file_name: &str,
+    device:    &wgpu::Device,
+    queue:     &wgpu::Queue,
+    layout:    &wgpu::BindGroupLayout,
+) -> anyhow::Result<model::Material> {
+    let text = load_string(file_name).await?;
+
+    let desc: MaterialDesc = ron::Options::default()
+        .with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME)
+        .from_str(&text)
+        .map_err(|e| anyhow::anyhow!("Couldn't read {}: {}", file_name, e))?;
+
+    let name = desc.name.clone().unwrap_or_else(|| {
+        std::path::Path::new(file_name)
+            .file_name()
+            .and_then(|name| name.to_string_lossy().split('.').next().map(str::to_string))
+            .unwrap_or_else(|| file_name.to_string())
+    });
+
+    build_material(file_name, name, &desc, device, queue, layout).await
+}
+
+// Coarser levels of detail for a model loaded with `load_model`, simplified from its meshes. Each
+// `(ratio, screen_size)` keeps about `ratio` of the triangles and is used below `screen_size`.
+// Meshes that can't be simplified that far stop where they can.
+#[cfg(feature = "meshopt")]
+pub async fn load_lod_levels(
+    file_name: &str,
+    device:    &wgpu::Device,
+    levels:    &[(f32, f32)],
+) -> anyhow::Result<Vec<lod::LodLevel>> {
+    let (models, _) = load_obj(file_name).await?;
+
+    let meshes = models
+        .iter()
+        .map(|m| (obj_vertices(&m.mesh), m))
+        .collect::<Vec<_>>();
+
+    let mut lod_levels = Vec::new();
+
+    for &(ratio, screen_size) in levels {
+        let mut level_meshes = Vec::new();
+
+        for (vertices, m) in &meshes {
+            let adapter = meshopt::VertexDataAdapter::new(
+                bytemuck::cast_slice(vertices),
+                std::mem::size_of::<model::ModelVertex>(),
+                0,
+            ).map_err(|e| anyhow::anyhow!("{:?}", e))?;
+
+            // Whole triangles, within 1% of the mesh's size of the original surface
+            let target  = (m.mesh.indices.len() as f32 * ratio) as usize / 3 * 3;
+            let indices = meshopt::simplify(&m.mesh.indices, &adapter, target, 0.01);
+
+            level_meshes.push(create_mesh(device, file_name, vertices, &indices, m.mesh.material_id.unwrap_or(0))?);
+        }
+
+        lod_levels.push(lod::LodLevel { meshes: level_meshes, screen_size });
+    }
+
+    Ok(lod_levels)
+}
+
+// Loads the meshes of a glTF file along with their morph targets and default weights. Node
+// transforms, skins and animations are left out, and only base color textures are used. Buffers
+// and images can be in the .glb or in files beside it, but not in data URIs.
+#[cfg(feature = "gltf")]
+pub async fn load_morph_model(
+    file_name:    &str,
+    device:       &wgpu::Device,
+    queue:        &wgpu::Queue,
+    layout:       &wgpu::BindGroupLayout,
+    morph_layout: &wgpu::BindGroupLayout,
+) -> anyhow::Result<morph::MorphModel> {
+    let gltf = gltf::Gltf::from_slice(&load_binary(file_name).await?)?;
+
+    let mut buffers = Vec::new();
+
+    for buffer in gltf.buffers() {
+        let data = match buffer.source() {
+            gltf::buffer::Source::Bin => gltf.blob.clone()
+                .ok_or_else(|| anyhow::anyhow!("{} has no binary chunk", file_name))?,
+            gltf::buffer::Source::Uri(uri) if uri.starts_with("data:") => {
+                anyhow::bail!("Data URIs aren't supported, in {}", file_name)
+            }
+            gltf::buffer::Source::Uri(uri) => load_binary(&relative_to(file_name, uri)).await?,
+        };
+
+        buffers.push(data);
+    }
+
+    let mut materials = Vec::new();
+
+    for m in gltf.materials() {
+        let name = m.name().unwrap_or("glTF Material").to_string();
+
+        let diffuse_texture = match m.pbr_metallic_roughness().base_color_texture() {
+            Some(info) => gltf_texture(info.texture(), &buffers, file_name, device, queue, &name, true).await?,
+            None       => white_texture(device, queue, &name)?,
+        };
+
+        let mut material = material(device, layout, name, diffuse_texture);
+
+        // Metals aren't told apart, everything reflects like a dielectric
+        material.params.specular  = 1.0;
+        material.params.roughness = m.pbr_metallic_roughness().roughness_factor();
+
+        // None of these extensions are known to the gltf crate, so they're read from the JSON. Only
+        // their factors are used, except for the anisotropy direction texture.
+        let factor = |extension: &gltf::json::Value, key: &str, default: f32| {
+            extension.get(key).and_then(gltf::json::Value::as_f64).map_or(default, |value| value as f32)
+        };
+
+        if let Some(clearcoat) = m.extension_value("KHR_materials_clearcoat") {
+            material.params.clearcoat           = factor(clearcoat, "clearcoatFactor", 0.0);
+            material.params.clearcoat_roughness = factor(clearcoat, "clearcoatRoughnessFactor", 0.0);
+        }
+
+        if let Some(anisotropy) = m.extension_value("KHR_materials_anisotropy") {
+            material.params.anisotropy          = factor(anisotropy, "anisotropyStrength", 0.0);
+            material.params.anisotropy_rotation = factor(anisotropy, "anisotropyRotation", 0.0);
+
+            let index = anisotropy.get("anisotropyTexture")
+                .and_then(|info| info.get("index"))
+                .and_then(gltf::json::Value::as_u64);
+
+            if let Some(texture) = index.and_then(|index| gltf.textures().nth(index as usize)) {
+                let texture = gltf_texture(texture, &buffers, file_name, device, queue, &material.name, false).await?;
+
+                material.set_anisotropy_texture(device, layout, texture);
+            }
+        }
+
+        if let Some(transmission) = m.extension_value("KHR_materials_transmission") {
+            material.params.transmittance = factor(transmission, "transmissionFactor", 0.0);
+        }
+
+        if let Some(ior) = m.extension_value("KHR_materials_ior") {
+            material.params.ior = factor(ior, "ior", 1.5);
+        }
+
+        // Thin-walled without a volume, which bends light in and straight back out
+        material.params.thickness = m.extension_value("KHR_materials_volume")
+            .map_or(0.0, |volume| factor(volume, "thicknessFactor", 0.0));
+
+        materials.push(material);
+    }
+
+    // For primitives without a material of their own
+    let default_material = materials.len();
+    materials.push(material(device, layout, "Default Material".to_string(), white_texture(device, queue, "Default Material")?));
+
+    let mut meshes = Vec::new();
+
+    for mesh in gltf.meshes() {
+        let name = mesh.name().unwrap_or(file_name);
+
+        for primitive in mesh.primitives() {
+            let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| &data[..]));
+
+            let positions = reader.read_positions()
+                .ok_or_else(|| anyhow::anyhow!("Mesh {} has no positions", name))?
+                .collect::<Vec<_>>();
+            let normals   = reader.read_normals()
+                .map(|normals| normals.collect())
+                .unwrap_or_else(|| vec![[0.0, 1.0, 0.0]; positions.len()]);
+            let uvs       = reader.read_tex_coords(0)
+                .map(|uvs| uvs.into_f32().collect())
+                .unwrap_or_else(|| vec![[0.0, 0.0]; positions.len()]);
+            // Exporters put lightmap UVs in the second set, falling back to the first
+            let lightmap  = reader.read_tex_coords(1)
+                .map(|uvs| uvs.into_f32().collect())
+                .unwrap_or_else(|| uvs.clone());
+            let colors    = reader.read_colors(0)
+                .map(|colors| colors.into_rgba_f32().collect())
+                .unwrap_or_else(|| vec![[1.0; 4]; positions.len()]);
+            let indices   = reader.read_indices()
+                .map(|indices| indices.into_u32().collect())
+                .unwrap_or_else(|| (0..positions.len() as u32).collect::<Vec<_>>());
+
+            let vertices = positions.iter().zip(&normals).zip(&uvs).zip(&colors).zip(&lightmap)
+                .map(|((((&position, &normal), &tex_coords), &color), &lightmap_coords)| model::ModelVertex {
+                    position,
+                    tex_coords,
+                    normal,
+                    color,
+                    lightmap_coords,
+                })
+                .collect::<Vec<_>>();
+
+            let targets = reader.read_morph_targets()
+                .map(|(positions, normals, _)| morph::MorphTarget {
+                    positions: positions.map(|p| p.collect()).unwrap_or_default(),
+                    normals:   normals.map(|n| n.collect()).unwrap_or_default(),
+                })
+                .collect::<Vec<_>>();
+
+            let material = primitive.material().index().unwrap_or(default_material);
+
+            let mut morph_mesh = morph::MorphMesh::new(device, morph_layout, name, &vertices, &indices, &targets, material);
+
+            if let Some(weights) = mesh.weights() {
+                for (weight, &default) in morph_mesh.weights.iter_mut().zip(weights) {
+                    *weight = default;
+                }
+            }
+
+            meshes.push(morph_mesh);
+        }
+    }
+
+    Ok(morph::MorphModel { meshes, materials })
+}
+
+// Colors are decoded from sRGB, data like directions are left linear
+#[cfg(feature = "gltf")]
+async fn gltf_texture(
+    texture:   gltf::Texture<'_>,
+    buffers:   &[Vec<u8>],
+    file_name: &str,
+    device:    &wgpu::Device,
+    queue:     &wgpu::Queue,
+    label:     &str,
+    color:     bool,
+) -> anyhow::Result<texture::Texture> {
+    let data = match texture.source().source() {
+        gltf::image::Source::View { view, .. } => {
+            let start = view.offset();
+
+            buffers[view.buffer().index()][start..start + view.length()].to_vec()
+        }
+        gltf::image::Source::Uri { uri, .. } => load_binary(&relative_to(file_name, uri)).await?,
+    };
+
+    let format = if color { wgpu::TextureFormat::Rgba8UnormSrgb } else { wgpu::TextureFormat::Rgba8Unorm };
+
+    decode_texture(device, queue, &data, label, format)
+}
+
+#[cfg(feature = "gltf")]
+fn white_texture(device: &wgpu::Device, queue: &wgpu::Queue, label: &str) -> anyhow::Result<texture::Texture> {
+    let image = image::RgbaImage::from_pixel(1, 1, image::Rgba([255; 4]));
+
+    texture::Texture::from_image(device, queue, &image::DynamicImage::ImageRgba8(image), Some(label))
+}
+
+#[cfg(feature = "gltf")]
+fn material(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, name: String, diffuse_texture: texture::Texture) -> model::Material {
+    model::Material::new(device, layout, name, diffuse_texture, None, ShadingModel::default())
+}
+
+fn float_param<S: BuildHasher>(params: &HashMap<String, String, S>, key: &str, material: &str) -> anyhow::Result<Option<f32>> {
+    params.get(key)
+        .map(|value| value.trim().parse().map_err(|_| anyhow::anyhow!("Invalid `{} {}` in {}", key, value, material)))
+        .transpose()
+}
+
+// Three floats, e.g. `Ke 1.0 0.5 0.0`
+fn color_param<S: BuildHasher>(params: &HashMap<String, String, S>, key: &str, material: &str) -> anyhow::Result<Option<[f32; 3]>> {
+    params.get(key)
+        .map(|value| {
+            value.split_whitespace().map(str::parse).collect::<Result<Vec<f32>, _>>()
+                .ok()
+                .filter(|color| color.len() == 3)
+                .map(|color| [color[0], color[1], color[2]])
+                .ok_or_else(|| anyhow::anyhow!("Couldn't read `{} {}` in {}", key, value, material))
+        })
+        .transpose()
+}
+
+async fn load_obj(file_name: &str) -> anyhow::Result<(Vec<tobj::Model>, Result<Vec<tobj::Material>, tobj::LoadError>)> {
     let obj_text       = load_string(file_name).await?;
     let obj_cursor     = Cursor::new(obj_text);
     let mut obj_reader = BufReader::new(obj_cursor);
 
-    let (models, obj_materials) = tobj::load_obj_buf_async(
+    let loaded = tobj::load_obj_buf_async(
         &mut obj_reader,
         &tobj::LoadOptions {
             triangulate: true,
             single_index: true,
             ..Default::default()
         },
-        |p| async move {
-            let mat_text = load_string(&p).await.unwrap();
-            tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(mat_text)))
+        |p| {
+            let mat_path = relative_to(file_name, &p);
+
+            async move {
+                let mat_text = load_string(&mat_path).await.unwrap();
+                tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(mat_text)))
+            }
         },
     )
     .await?;
 
-    let mut materials = Vec::new();
+    Ok(loaded)
+}
 
-    for m in obj_materials? {
-        let diffuse_texture = load_texture(&m.diffuse_texture, device, queue).await?;
-        let bind_group      = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                },
+fn obj_vertices(mesh: &tobj::Mesh) -> Vec<model::ModelVertex> {
+    (0..mesh.positions.len() / 3)
+        .map(|i| model::ModelVertex {
+            position: [
+                mesh.positions[i * 3],
+                mesh.positions[i * 3 + 1],
+                mesh.positions[i * 3 + 2],
             ],
-            label: None,
-        });
+            tex_coords: [
+                mesh.texcoords[i * 2],
+                mesh.texcoords[i * 2 + 1],
+            ],
+            // OBJ has one set of UVs, so a lightmap has to share it
+            lightmap_coords: [
+                mesh.texcoords[i * 2],
+                mesh.texcoords[i * 2 + 1],
+            ],
+            normal: [
+                mesh.normals[i * 3],
+                mesh.normals[i * 3 + 1],
+                mesh.normals[i * 3 + 2],
+            ],
+            // From `v x y z r g b` lines, which tobj reads when any vertex has them
+            color: match mesh.vertex_color.get(i * 3..i * 3 + 3) {
+                Some(&[r, g, b]) => [r, g, b, 1.0],
+                _                => [1.0; 4],
+            },
+        }).collect()
+}
 
-        materials.push(model::Material {
-            name: m.name,
-            diffuse_texture,
-            bind_group,
-        })
-    }
+// Decoded like `Texture::from_bytes` into an 8 bit RGBA `format`. Natively the decoded, and maybe
+// transcoded, texture is kept in the asset cache, see asset_cache.rs.
+fn decode_texture(
+    device: &wgpu::Device,
+    queue:  &wgpu::Queue,
+    data:   &[u8],
+    label:  &str,
+    format: wgpu::TextureFormat,
+) -> anyhow::Result<texture::Texture> {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(cache) = asset_cache::AssetCache::from_env() {
+        // Whether it's compressed depends on the device as well as the image
+        let compress = cfg!(feature = "bc_compress") && device.features().contains(wgpu::Features::TEXTURE_COMPRESSION_BC);
+        let key      = asset_cache::key(&format!("texture {:?} {}", format, compress), &[data]);
 
-    let meshes = models
-        .into_iter()
-        .map(|m| {
-            let vertices = (0..m.mesh.positions.len() / 3)
-                .map(|i| model::ModelVertex {
-                    position: [
-                        m.mesh.positions[i * 3],
-                        m.mesh.positions[i * 3 + 1],
-                        m.mesh.positions[i * 3 + 2],
-                    ],
-                    tex_coords: [
-                        m.mesh.texcoords[i * 2],
-                        m.mesh.texcoords[i * 2 + 1],
-                    ],
-                    normal: [
-                        m.mesh.normals[i * 3],
-                        m.mesh.normals[i * 3 + 1],
-                        m.mesh.normals[i * 3 + 2],
-                    ]
-                }).collect::<Vec<_>>();
-
-            let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label:    Some(&format!("{:?} Vertex Buffer", file_name)),
-                contents: bytemuck::cast_slice(&vertices),
-                usage:    wgpu::BufferUsages::VERTEX,
-            });
-            let index_buffer  = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label:    Some(&format!("{:?} Index Buffer", file_name)),
-                contents: bytemuck::cast_slice(&m.mesh.indices),
-                usage:    wgpu::BufferUsages::INDEX,
-            });
-
-            model::Mesh {
-                name: file_name.to_string(),
-                vertex_buffer,
-                index_buffer,
-                num_elements: m.mesh.indices.len() as u32,
-                material: m.mesh.material_id.unwrap_or(0),
+        let cached = match cache.load_texture(key) {
+            Some(cached) => cached,
+            None         => {
+                let image          = image::load_from_memory(data)?;
+                let (format, data) = texture::Texture::encode(device, &image, format);
+                let cached         = asset_cache::CachedTexture { format, width: image.width(), height: image.height(), data };
+
+                cache.store_texture(key, &cached);
+                cached
             }
-        }).collect::<Vec<_>>();
+        };
 
-    Ok(model::Model { meshes, materials })
+        return Ok(texture::Texture::from_data(device, queue, &cached.data, cached.width, cached.height, cached.format, Some(label)));
+    }
+
+    texture::Texture::from_image_with_format(device, queue, &image::load_from_memory(data)?, Some(label), format)
+}
+
+// See optimize.rs. Natively the result is kept in the asset cache, see asset_cache.rs.
+#[cfg(feature = "meshopt")]
+fn optimize_mesh(vertices: &[model::ModelVertex], indices: &[u32]) -> anyhow::Result<(Vec<model::ModelVertex>, Vec<u32>)> {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(cache) = asset_cache::AssetCache::from_env() {
+        let key = asset_cache::key("optimized mesh", &[bytemuck::cast_slice(vertices), bytemuck::cast_slice(indices)]);
+
+        if let Some(mesh) = cache.load_mesh(key) {
+            return Ok(mesh);
+        }
+
+        let mesh = optimize::optimize_mesh(vertices, indices)?;
+
+        cache.store_mesh(key, &mesh.0, &mesh.1);
+        return Ok(mesh);
+    }
+
+    optimize::optimize_mesh(vertices, indices)
+}
+
+// Reordered for the GPU first when built with meshopt, see optimize.rs, then packed into
+// `PackedVertex`es
+fn create_mesh(
+    device:    &wgpu::Device,
+    file_name: &str,
+    vertices:  &[model::ModelVertex],
+    indices:   &[u32],
+    material:  usize,
+) -> anyhow::Result<model::Mesh> {
+    #[cfg(feature = "meshopt")]
+    let optimized = optimize_mesh(vertices, indices)?;
+    #[cfg(feature = "meshopt")]
+    let (vertices, indices) = (&optimized.0[..], &optimized.1[..]);
+
+    let bounds = bounds::Aabb::from_points(vertices.iter().map(|v| v.position.into()));
+
+    let packed = vertices
+        .iter()
+        .map(|&v| model::PackedVertex::from(v))
+        .collect::<Vec<_>>();
+
+    let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label:    Some(&format!("{:?} Vertex Buffer", file_name)),
+        contents: bytemuck::cast_slice(&packed),
+        usage:    wgpu::BufferUsages::VERTEX,
+    });
+    let index_buffer  = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label:    Some(&format!("{:?} Index Buffer", file_name)),
+        contents: bytemuck::cast_slice(indices),
+        usage:    wgpu::BufferUsages::INDEX,
+    });
+
+    let tracked = gpu_stats::Tracked::buffer(Some(file_name), (packed.len() * std::mem::size_of::<model::PackedVertex>() + indices.len() * 4) as u64);
+
+    Ok(model::Mesh {
+        name: file_name.to_string(),
+        vertex_buffer,
+        index_buffer,
+        num_elements: indices.len() as u32,
+        material,
+        bounds,
+        tracked,
+    })
 }
@@ -0,0 +1,153 @@
+// Post effect for a low-resolution CRT look: chunky pixels, scanlines, a curved screen and an
+// optional fixed palette
+
+use crate::{bind_group, crash_report, pass::PostEffect, renderer::GpuContext, uniform::Uniform};
+
+pub const MAX_PALETTE_SIZE: usize = 16;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct RetroParams {
+    pixel_size:        f32,
+    scanline_strength: f32,
+    curvature:         f32,
+    palette_size:      u32,
+    srgb_target:       u32,
+    _padding:          [u32; 3],
+    palette:           [[f32; 4]; MAX_PALETTE_SIZE],
+}
+
+pub struct RetroFilter {
+    // Size of one virtual pixel in scene pixels. The scene is point-sampled once per virtual pixel,
+    // which is the same as drawing it that much smaller and upscaling with nearest filtering.
+    pub pixel_size:        u32,
+    // 0.0 (off) to 1.0 (black between lines)
+    pub scanline_strength: f32,
+    // 0.0 for a flat screen
+    pub curvature:         f32,
+    palette:               Vec<[u8; 3]>,
+    pipeline:              wgpu::RenderPipeline,
+    layout:                wgpu::BindGroupLayout,
+    params:                wgpu::Buffer,
+    srgb_target:           bool,
+}
+
+impl RetroFilter {
+    pub fn new(ctx: &GpuContext) -> Self {
+        let device = &ctx.device;
+
+        let layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::FRAGMENT)
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .build(device, "retro_bind_group_layout");
+
+        let params = device.create_buffer(&wgpu::BufferDescriptor {
+            label:              Some("Retro Params Buffer"),
+            size:               std::mem::size_of::<RetroParams>() as wgpu::BufferAddress,
+            usage:              wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Retro Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("retro.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Retro Pipeline Layout"),
+            bind_group_layouts:   &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label:    Some("Retro Pipeline"),
+            layout:   Some(&pipeline_layout),
+            vertex:   wgpu::VertexState {
+                module:      &shader,
+                entry_point: "vs_main",
+                buffers:     &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module:      &shader,
+                entry_point: "fs_main",
+                targets:     &[Some(wgpu::ColorTargetState {
+                    format:     ctx.config.format,
+                    blend:      Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive:     wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample:   wgpu::MultisampleState::default(),
+            multiview:     None,
+        });
+
+        Self {
+            pixel_size:        4,
+            scanline_strength: 0.3,
+            curvature:         0.1,
+            palette:           Vec::new(),
+            pipeline,
+            layout,
+            params,
+            srgb_target:       ctx.config.format.describe().srgb,
+        }
+    }
+
+    // Snaps every pixel to the closest of these sRGB colors. An empty palette turns it off.
+    pub fn set_palette(&mut self, palette: &[[u8; 3]]) {
+        assert!(palette.len() <= MAX_PALETTE_SIZE, "Palettes can have at most {} colors", MAX_PALETTE_SIZE);
+
+        self.palette = palette.to_vec();
+    }
+
+    pub fn prepare(&self, queue: &wgpu::Queue) {
+        let mut palette = [[0.0; 4]; MAX_PALETTE_SIZE];
+
+        for (slot, color) in palette.iter_mut().zip(&self.palette) {
+            *slot = [color[0] as f32 / 255.0, color[1] as f32 / 255.0, color[2] as f32 / 255.0, 1.0];
+        }
+
+        queue.write_buffer(&self.params, 0, bytemuck::cast_slice(&[RetroParams {
+            pixel_size:        self.pixel_size.max(1) as f32,
+            scanline_strength: self.scanline_strength,
+            curvature:         self.curvature,
+            palette_size:      self.palette.len() as u32,
+            srgb_target:       self.srgb_target as u32,
+            _padding:          [0; 3],
+            palette,
+        }]));
+    }
+}
+
+impl PostEffect for RetroFilter {
+    fn apply(
+        &self,
+        device:  &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        input:   &wgpu::TextureView,
+        output:  &wgpu::TextureView,
+    ) {
+        let bind_group = bind_group::BindGroupBuilder::new(&self.layout)
+            .uniform(&self.params)
+            .texture(input)
+            .build(device, "retro_bind_group");
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Retro Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view:           output,
+                resolve_target: None,
+                ops:  wgpu::Operations {
+                    load:  wgpu::LoadOp::Load,
+                    store: true
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        crash_report::set_pipeline(&mut render_pass, &self.pipeline, "Retro Pipeline");
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,103 @@
+// Chunky pixels, scanlines and a curved screen, with an optional fixed palette
+
+struct RetroParams {
+    pixel_size:        f32,
+    scanline_strength: f32,
+    curvature:         f32,
+    palette_size:      u32,
+    // The target encodes to sRGB on write, so the input was linear and the output must be too
+    srgb_target:       u32,
+    palette:           array<vec4<f32>, 16>,
+}
+
+@group(0) @binding(0)
+var<uniform> params: RetroParams;
+@group(0) @binding(1)
+var t_scene: texture_2d<f32>;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv:                  vec2<f32>,
+}
+
+// One triangle that covers the screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+
+    out.uv            = uv;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+
+    return out;
+}
+
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+    return select(pow((c + 0.055) / 1.055, vec3<f32>(2.4)), c / 12.92, c <= vec3<f32>(0.04045));
+}
+
+fn linear_to_srgb(c: vec3<f32>) -> vec3<f32> {
+    return select(1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055, c * 12.92, c <= vec3<f32>(0.0031308));
+}
+
+// Palette colors are sRGB, so they're compared against the display-encoded color
+fn quantize(color: vec3<f32>) -> vec3<f32> {
+    var nearest = color;
+    var best    = 1e9;
+
+    for (var i = 0u; i < params.palette_size; i = i + 1u) {
+        let candidate = params.palette[i].rgb;
+        let offset    = candidate - color;
+        let dist      = dot(offset, offset);
+
+        if (dist < best) {
+            best    = dist;
+            nearest = candidate;
+        }
+    }
+
+    return nearest;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = vec2<f32>(vec2<i32>(textureDimensions(t_scene)));
+
+    // Barrel distortion around the center, anything pushed off the tube is black
+    let centered = in.uv * 2.0 - 1.0;
+    let curved   = centered * (1.0 + params.curvature * dot(centered, centered));
+    let uv       = curved * 0.5 + 0.5;
+
+    if (any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0))) {
+        return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    }
+
+    // Sample the center of the virtual pixel this fragment falls in
+    let position = uv * size;
+    let cell     = floor(position / params.pixel_size);
+    let center   = min((cell + 0.5) * params.pixel_size, size - 1.0);
+    let scene    = textureLoad(t_scene, vec2<i32>(center), 0);
+
+    var color = scene.rgb;
+
+    if (params.srgb_target != 0u) {
+        color = linear_to_srgb(clamp(color, vec3<f32>(0.0), vec3<f32>(1.0)));
+    }
+
+    if (params.palette_size > 0u) {
+        color = quantize(color);
+    }
+
+    // One dark line through the middle of each row of virtual pixels
+    let row      = fract(position.y / params.pixel_size);
+    let scanline = 1.0 - params.scanline_strength * (0.5 + 0.5 * cos(row * 6.2831853));
+
+    color = color * scanline;
+
+    if (params.srgb_target != 0u) {
+        color = srgb_to_linear(color);
+    }
+
+    return vec4<f32>(color, scene.a);
+}
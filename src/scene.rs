@@ -0,0 +1,24 @@
+// What the renderer's views draw: the main camera, split-screen players, reflections, portals, the
+// minimap, thumbnails and headsets all register a scene's draws through `render_view`, and picking
+// asks it what's under the cursor through `raycast`.
+
+use crate::{bounds::Hit, camera::Camera, pass::RenderLayers};
+
+// Anything that can be drawn from a camera other than the one it normally uses
+pub trait Scene {
+    // Upload `camera` to wherever the scene's draws read it from, then register those draws.
+    // Fragments behind `clip_plane` should be discarded, see `CameraUniform::set_clip_plane`.
+    fn render_view<'a>(
+        &'a self,
+        queue:      &wgpu::Queue,
+        camera:     &Camera,
+        clip_plane: Option<cgmath::Vector4<f32>>,
+        layers:     &mut RenderLayers<'a>,
+    );
+
+    // The closest thing along the ray, for picking, line of sight and the like. Scenes that can't
+    // be queried never report a hit.
+    fn raycast(&self, _origin: cgmath::Point3<f32>, _direction: cgmath::Vector3<f32>) -> Option<Hit> {
+        None
+    }
+}
@@ -0,0 +1,89 @@
+// Independent compute work like culling, simulations and histograms, recorded into command
+// buffers of its own and submitted ahead of the frame's render work, so the GPU starts on it while
+// the CPU is still recording passes. wgpu has a single queue, so this is about ordering and early
+// submission rather than running on a separate compute queue, and the frame still sees the
+// results of anything submitted before it.
+//
+// Work is grouped by label, each group becoming one submission. Submissions are tracked with
+// `Queue::on_submitted_work_done`, and the `WorkFence` handed back says when the GPU has finished,
+// e.g. before mapping a buffer the work wrote, without blocking on it.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use crate::{crash_report, renderer::GpuContext};
+
+// Set once the GPU has finished a submission. Cloning shares it.
+#[derive(Debug, Clone, Default)]
+pub struct WorkFence {
+    done: Arc<AtomicBool>,
+}
+
+impl WorkFence {
+    pub fn is_done(&self) -> bool {
+        self.done.load(Ordering::Acquire)
+    }
+
+    // Signals the fence once everything submitted to `queue` so far is finished
+    pub(crate) fn signal_when_done(&self, queue: &wgpu::Queue) {
+        let done = self.done.clone();
+
+        queue.on_submitted_work_done(move || done.store(true, Ordering::Release));
+    }
+}
+
+struct Batch {
+    label:   &'static str,
+    encoder: wgpu::CommandEncoder,
+    fence:   WorkFence,
+}
+
+// The frame's compute work waiting to be submitted, see `Frame::compute`
+#[derive(Default)]
+pub struct ComputeScheduler {
+    batches: Vec<Batch>,
+}
+
+impl ComputeScheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Records work into the batch named `label`, starting one if needed
+    pub fn record(&mut self, ctx: &GpuContext, label: &'static str, work: impl FnOnce(&GpuContext, &mut wgpu::CommandEncoder)) -> WorkFence {
+        let index = match self.batches.iter().position(|batch| batch.label == label) {
+            Some(index) => index,
+            None => {
+                self.batches.push(Batch {
+                    label,
+                    encoder: ctx.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                        label: Some(label),
+                    }),
+                    fence:   WorkFence::default(),
+                });
+
+                self.batches.len() - 1
+            }
+        };
+
+        let batch = &mut self.batches[index];
+
+        crash_report::marker(label);
+        work(ctx, &mut batch.encoder);
+        batch.fence.clone()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.batches.is_empty()
+    }
+
+    // Submits each batch on its own, in the order they were started
+    pub fn submit(&mut self, queue: &wgpu::Queue) {
+        for batch in self.batches.drain(..) {
+            queue.submit(std::iter::once(batch.encoder.finish()));
+            batch.fence.signal_when_done(queue);
+        }
+    }
+}
@@ -0,0 +1,141 @@
+// Shader code in any of the languages the crate accepts, for porting shaders written elsewhere.
+// The crate's own shaders are WGSL, which always works. GLSL, with the `glsl` feature, is one
+// stage per source and translated by naga's front end. Compiled SPIR-V, with the `spirv`
+// feature, is translated the same way, or handed to the driver untouched where the device has
+// `Features::SPIRV_SHADER_PASSTHROUGH`.
+//
+// Everything that's translated is parsed and validated by naga first, so mistakes come back as
+// errors rather than the fatal ones wgpu raises for a bad module. Passed through SPIR-V isn't
+// checked by anything, and since wgpu can't reflect it either, pipelines using it need explicit
+// layouts, which every pipeline in the crate has anyway.
+
+use std::borrow::Cow;
+
+use anyhow::*;
+
+pub enum ShaderCode<'a> {
+    Wgsl(Cow<'a, str>),
+    #[cfg(feature = "glsl")]
+    Glsl {
+        source:  Cow<'a, str>,
+        stage:   naga::ShaderStage,
+        // Like `#define name value` at the top of the source
+        defines: Vec<(String, String)>,
+    },
+    // Bytes as a .spv file holds them
+    #[cfg(feature = "spirv")]
+    SpirV(Cow<'a, [u8]>),
+}
+
+impl ShaderCode<'static> {
+    // Picks the language from `file_name`'s extension: .wgsl, .vert, .frag and .comp for GLSL's
+    // stages, and .spv for SPIR-V
+    pub fn from_file(file_name: &str, data: Vec<u8>) -> Result<Self> {
+        let extension = std::path::Path::new(file_name)
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        let text = |data: Vec<u8>| String::from_utf8(data).with_context(|| format!("{} isn't UTF-8", file_name));
+
+        #[cfg(feature = "glsl")]
+        let glsl = |source: String, stage| ShaderCode::Glsl { source: Cow::Owned(source), stage, defines: Vec::new() };
+
+        Ok(match extension.as_str() {
+            "wgsl" => ShaderCode::Wgsl(Cow::Owned(text(data)?)),
+            #[cfg(feature = "glsl")]
+            "vert" => glsl(text(data)?, naga::ShaderStage::Vertex),
+            #[cfg(feature = "glsl")]
+            "frag" => glsl(text(data)?, naga::ShaderStage::Fragment),
+            #[cfg(feature = "glsl")]
+            "comp" => glsl(text(data)?, naga::ShaderStage::Compute),
+            #[cfg(not(feature = "glsl"))]
+            "vert" | "frag" | "comp" => bail!("{} is GLSL, which needs the `glsl` feature", file_name),
+            #[cfg(feature = "spirv")]
+            "spv"  => ShaderCode::SpirV(Cow::Owned(data)),
+            #[cfg(not(feature = "spirv"))]
+            "spv"  => bail!("{} is SPIR-V, which needs the `spirv` feature", file_name),
+            _      => bail!("Don't know what language {} is in", file_name),
+        })
+    }
+}
+
+impl<'a> ShaderCode<'a> {
+    pub fn create_module(&self, device: &wgpu::Device, label: &str) -> Result<wgpu::ShaderModule> {
+        let source = match self {
+            ShaderCode::Wgsl(source) => {
+                let module = naga::front::wgsl::parse_str(source)
+                    .map_err(|e| anyhow!("Couldn't parse {}: {}", label, e.emit_to_string(source)))?;
+
+                validate(&module, label)?;
+                wgpu::ShaderSource::Wgsl(Cow::Borrowed(source))
+            }
+            #[cfg(feature = "glsl")]
+            ShaderCode::Glsl { source, stage, defines } => {
+                let options = naga::front::glsl::Options {
+                    stage:   *stage,
+                    defines: defines.iter().cloned().collect(),
+                };
+
+                let module = naga::front::glsl::Parser::default()
+                    .parse(&options, source)
+                    .map_err(|errors| {
+                        let errors = errors.iter()
+                            .map(|e| format!("line {}: {}", e.meta.location(source).line_number, e))
+                            .collect::<Vec<_>>();
+
+                        anyhow!("Couldn't parse {}:\n{}", label, errors.join("\n"))
+                    })?;
+
+                validate(&module, label)?;
+                wgpu::ShaderSource::Glsl { shader: Cow::Borrowed(source), stage: options.stage, defines: options.defines }
+            }
+            #[cfg(feature = "spirv")]
+            ShaderCode::SpirV(data) => {
+                if data.len() % 4 != 0 {
+                    bail!("{} isn't SPIR-V, its length isn't a whole number of words", label);
+                }
+
+                let words = data.chunks_exact(4)
+                    .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+                    .collect::<Vec<_>>();
+
+                if words.first() != Some(&0x0723_0203) {
+                    bail!("{} isn't SPIR-V, it doesn't start with the magic number", label);
+                }
+
+                if device.features().contains(wgpu::Features::SPIRV_SHADER_PASSTHROUGH) {
+                    // # Safety
+                    //
+                    // The driver is trusted with whatever the module holds, see the top of the
+                    // file
+                    return Ok(unsafe {
+                        device.create_shader_module_spirv(&wgpu::ShaderModuleDescriptorSpirV {
+                            label:  Some(label),
+                            source: Cow::Owned(words),
+                        })
+                    });
+                }
+
+                let module = naga::front::spv::parse_u8_slice(data, &naga::front::spv::Options::default())
+                    .with_context(|| format!("Couldn't parse {}", label))?;
+
+                validate(&module, label)?;
+                wgpu::ShaderSource::SpirV(Cow::Owned(words))
+            }
+        };
+
+        Ok(device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(label),
+            source,
+        }))
+    }
+}
+
+fn validate(module: &naga::Module, label: &str) -> Result<()> {
+    naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all())
+        .validate(module)
+        .with_context(|| format!("{} is invalid", label))?;
+
+    Ok(())
+}
@@ -0,0 +1,435 @@
+// A post effect whose fragment shader is typed into a text box on screen, for trying things out
+// without restarting. The snippet defines `effect`, which shader_console.wgsl describes, and is
+// compiled again after every edit. Whatever naga finds wrong with it is shown under the text with
+// line numbers counted from the snippet's start, and the last snippet that compiled keeps running
+// until it's fixed.
+//
+// Snippets are checked with naga before wgpu sees them, since wgpu treats a bad shader as a fatal
+// error. They can't declare bindings of their own, because the layout is fixed. Apps pass window
+// events to `input` while it's open, and add the console as an effect whether it's open or not.
+
+use std::error::Error;
+
+use winit::event::*;
+
+use crate::{
+    bind_group,
+    crash_report,
+    pass::PostEffect,
+    renderer::{self, GpuContext},
+    ui::{Anchor, BitmapFont, Ui, UiId, UiKind, UiNode},
+    uniform::{Uniform, UniformBuffer},
+};
+
+const PRELUDE: &str = include_str!("shader_console.wgsl");
+const MAIN:    &str = include_str!("shader_console_main.wgsl");
+
+// How many bindings the prelude declares
+const BINDINGS: usize = 3;
+
+const DEFAULT_SNIPPET: &str = "\
+fn effect(uv: vec2<f32>, color: vec4<f32>) -> vec4<f32> {
+    return color;
+}
+";
+
+const ERROR_COLOR: [f32; 4] = [1.0, 0.4, 0.4, 1.0];
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct ConsoleUniform {
+    resolution: [f32; 2],
+    time:       f32,
+    _padding:   f32,
+}
+
+pub struct ShaderConsole {
+    source:          String,
+    // Byte offset into `source`
+    caret:           usize,
+    open:            bool,
+    // Edited since it was last compiled
+    dirty:           bool,
+    // Changed since the text was last laid out
+    shown:           bool,
+    // Of the latest edit, when it didn't compile
+    error:           Option<String>,
+    // Of the last snippet that compiled
+    pipeline:        wgpu::RenderPipeline,
+    pipeline_layout: wgpu::PipelineLayout,
+    layout:          wgpu::BindGroupLayout,
+    uniform:         UniformBuffer<ConsoleUniform>,
+    sampler:         wgpu::Sampler,
+    format:          wgpu::TextureFormat,
+    started:         instant::Instant,
+    root:            UiId,
+    text:            UiId,
+    errors:          UiId,
+}
+
+impl ShaderConsole {
+    // Starts closed, with a snippet that passes the scene through
+    pub fn new(ctx: &GpuContext, ui: &mut Ui, font: BitmapFont, text_size: f32) -> Self {
+        let device = &ctx.device;
+
+        let layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::FRAGMENT)
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .sampler(wgpu::ShaderStages::FRAGMENT)
+            .build(device, "shader_console_bind_group_layout");
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Shader Console Pipeline Layout"),
+            bind_group_layouts:   &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label:          Some("Shader Console Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter:     wgpu::FilterMode::Linear,
+            min_filter:     wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let pipeline = create_pipeline(device, &pipeline_layout, ctx.config.format, DEFAULT_SNIPPET)
+            .expect("The default snippet should compile");
+
+        let root   = ui.add(UiNode::new(Anchor::TopRight, [-8.0, 8.0], [0.0, 0.0], UiKind::Panel { color: [0.0, 0.0, 0.0, 0.75] }));
+        let text   = ui.add(UiNode::new(Anchor::TopLeft, [6.0, 6.0], [0.0, 0.0], UiKind::Text {
+            font,
+            text:  String::new(),
+            size:  text_size,
+            color: [1.0; 4],
+        }).with_parent(root));
+        let errors = ui.add(UiNode::new(Anchor::TopLeft, [6.0, 6.0], [0.0, 0.0], UiKind::Text {
+            font,
+            text:  String::new(),
+            size:  text_size,
+            color: ERROR_COLOR,
+        }).with_parent(root));
+
+        ui.node_mut(root).unwrap().visible = false;
+
+        Self {
+            source:  DEFAULT_SNIPPET.to_string(),
+            caret:   0,
+            open:    false,
+            dirty:   false,
+            shown:   true,
+            error:   None,
+            pipeline,
+            pipeline_layout,
+            layout,
+            uniform: UniformBuffer::new(device, "Shader Console Buffer"),
+            sampler,
+            format:  ctx.config.format,
+            started: instant::Instant::now(),
+            root,
+            text,
+            errors,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn set_open(&mut self, open: bool) {
+        self.open  = open;
+        self.shown = false;
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    // Replaces the snippet, which is compiled on the next `update`
+    pub fn set_source(&mut self, source: &str) {
+        self.source = source.to_string();
+        self.caret  = self.source.len();
+        self.edited();
+    }
+
+    // What the latest edit got wrong, None when it compiled
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    // Edits the snippet while the console is open. Returns true if the event was used, which is
+    // every key while it's open, so typing doesn't also move the camera. Escape closes it.
+    pub fn input(&mut self, event: &WindowEvent) -> bool {
+        if !self.open {
+            return false;
+        }
+
+        match event {
+            // Control characters come through as keys as well, and are handled below
+            WindowEvent::ReceivedCharacter(c) if !c.is_control() => self.insert(&c.to_string()),
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(key),
+                    ..
+                },
+                ..
+            } => match key {
+                VirtualKeyCode::Escape => self.set_open(false),
+                VirtualKeyCode::Return => {
+                    // Keeps the line's indent
+                    let start  = self.line_start(self.caret);
+                    let indent = self.source[start..].chars().take_while(|c| *c == ' ').count();
+
+                    self.insert(&format!("\n{}", " ".repeat(indent)));
+                }
+                VirtualKeyCode::Tab => self.insert("    "),
+                VirtualKeyCode::Back => {
+                    if let Some(previous) = self.previous_char() {
+                        self.source.replace_range(previous..self.caret, "");
+                        self.caret = previous;
+                        self.edited();
+                    }
+                }
+                VirtualKeyCode::Delete => {
+                    if let Some(next) = self.next_char() {
+                        self.source.replace_range(self.caret..next, "");
+                        self.edited();
+                    }
+                }
+                VirtualKeyCode::Left  => self.move_caret(self.previous_char()),
+                VirtualKeyCode::Right => self.move_caret(self.next_char()),
+                VirtualKeyCode::Home  => self.move_caret(Some(self.line_start(self.caret))),
+                VirtualKeyCode::End   => self.move_caret(Some(self.line_end(self.caret))),
+                VirtualKeyCode::Up    => {
+                    let start = self.line_start(self.caret);
+
+                    self.move_caret(Some(if start == 0 {
+                        0
+                    } else {
+                        self.column_in_line(self.line_start(start - 1), self.column())
+                    }));
+                }
+                VirtualKeyCode::Down  => {
+                    let end = self.line_end(self.caret);
+
+                    self.move_caret(Some(if end == self.source.len() {
+                        end
+                    } else {
+                        self.column_in_line(end + 1, self.column())
+                    }));
+                }
+                _ => {}
+            },
+            WindowEvent::ReceivedCharacter(_) | WindowEvent::KeyboardInput { .. } => {}
+            _ => return false,
+        }
+
+        true
+    }
+
+    // Compiles the latest edit, and shows the snippet while the console is open
+    pub fn update(&mut self, ctx: &GpuContext, ui: &mut Ui) {
+        if self.dirty {
+            self.dirty = false;
+
+            match create_pipeline(&ctx.device, &self.pipeline_layout, self.format, &self.source) {
+                Ok(pipeline) => {
+                    self.pipeline = pipeline;
+                    self.error    = None;
+                }
+                Err(e) => self.error = Some(e),
+            }
+        }
+
+        let size = ctx.render_size();
+
+        self.uniform.write(&ctx.queue, &ConsoleUniform {
+            resolution: [size.width as f32, size.height as f32],
+            time:       self.started.elapsed().as_secs_f32(),
+            _padding:   0.0,
+        });
+
+        if !self.shown {
+            self.shown = true;
+            self.show(ui);
+        }
+    }
+
+    fn show(&self, ui: &mut Ui) {
+        if let Some(root) = ui.node_mut(self.root) {
+            root.visible = self.open;
+        }
+
+        if !self.open {
+            return;
+        }
+
+        let (font, text_size) = match ui.node(self.text) {
+            Some(UiNode { kind: UiKind::Text { font, size, .. }, .. }) => (*font, *size),
+            _ => return,
+        };
+
+        // Numbered lines with a bar for the caret
+        let mut source = self.source.clone();
+
+        source.insert(self.caret, '|');
+
+        let text = source.split('\n')
+            .enumerate()
+            .map(|(i, line)| format!("{:>3} {}", i + 1, line))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let errors = self.error.clone().unwrap_or_default();
+
+        let text_size_px  = ui.text_size(&font, &text, text_size);
+        let error_size_px = ui.text_size(&font, &errors, text_size);
+
+        if let Some(UiNode { kind: UiKind::Text { text: shown, .. }, size, .. }) = ui.node_mut(self.text) {
+            *shown = text;
+            *size  = text_size_px;
+        }
+
+        if let Some(UiNode { kind: UiKind::Text { text: shown, .. }, size, offset, .. }) = ui.node_mut(self.errors) {
+            *shown  = errors;
+            *size   = error_size_px;
+            *offset = [6.0, 12.0 + text_size_px[1]];
+        }
+
+        if let Some(root) = ui.node_mut(self.root) {
+            root.size = [
+                text_size_px[0].max(error_size_px[0]) + 12.0,
+                text_size_px[1] + error_size_px[1] + 18.0,
+            ];
+        }
+    }
+
+    fn edited(&mut self) {
+        self.dirty = true;
+        self.shown = false;
+    }
+
+    fn insert(&mut self, text: &str) {
+        self.source.insert_str(self.caret, text);
+        self.caret += text.len();
+        self.edited();
+    }
+
+    fn move_caret(&mut self, caret: Option<usize>) {
+        if let Some(caret) = caret {
+            self.caret = caret;
+            self.shown = false;
+        }
+    }
+
+    fn previous_char(&self) -> Option<usize> {
+        self.source[..self.caret].chars().next_back().map(|c| self.caret - c.len_utf8())
+    }
+
+    fn next_char(&self) -> Option<usize> {
+        self.source[self.caret..].chars().next().map(|c| self.caret + c.len_utf8())
+    }
+
+    fn line_start(&self, at: usize) -> usize {
+        self.source[..at].rfind('\n').map_or(0, |i| i + 1)
+    }
+
+    fn line_end(&self, at: usize) -> usize {
+        self.source[at..].find('\n').map_or(self.source.len(), |i| at + i)
+    }
+
+    // Of the caret in its line, in characters
+    fn column(&self) -> usize {
+        self.source[self.line_start(self.caret)..self.caret].chars().count()
+    }
+
+    // The offset `column` characters into the line starting at `start`, or its end if it's shorter
+    fn column_in_line(&self, start: usize, column: usize) -> usize {
+        let end = self.line_end(start);
+
+        self.source[start..end].char_indices().nth(column).map_or(end, |(i, _)| start + i)
+    }
+}
+
+impl PostEffect for ShaderConsole {
+    fn apply(
+        &self,
+        device:  &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        input:   &wgpu::TextureView,
+        output:  &wgpu::TextureView,
+    ) {
+        let bind_group = bind_group::BindGroupBuilder::new(&self.layout)
+            .uniform(self.uniform.buffer())
+            .texture(input)
+            .sampler(&self.sampler)
+            .build(device, "shader_console_bind_group");
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Shader Console Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view:           output,
+                resolve_target: None,
+                ops:  wgpu::Operations {
+                    load:  wgpu::LoadOp::Load,
+                    store: true
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        crash_report::set_pipeline(&mut render_pass, &self.pipeline, "Shader Console Pipeline");
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+// Checks `snippet` between the prelude and main with naga first, so wgpu only ever sees shaders
+// it'll accept
+fn create_pipeline(
+    device:  &wgpu::Device,
+    layout:  &wgpu::PipelineLayout,
+    format:  wgpu::TextureFormat,
+    snippet: &str,
+) -> Result<wgpu::RenderPipeline, String> {
+    let source = format!("{}{}{}", PRELUDE, snippet, MAIN);
+
+    // Lines before the snippet's first
+    let offset = PRELUDE.lines().count() as u32;
+
+    let describe = |message: String, location: Option<naga::SourceLocation>| match location {
+        Some(location) if location.line_number > offset => format!("Line {}: {}", location.line_number - offset, message),
+        _ => message,
+    };
+
+    let module = naga::front::wgsl::parse_str(&source)
+        .map_err(|e| describe(e.message().to_string(), e.location(&source)))?;
+
+    if module.global_variables.iter().filter(|(_, var)| var.binding.is_some()).count() != BINDINGS {
+        return Err("Snippets can't declare bindings of their own".to_string());
+    }
+
+    naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::empty())
+        .validate(&module)
+        .map_err(|e| describe(error_chain(&e), e.location(&source)))?;
+
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label:  Some("Shader Console Shader"),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    });
+
+    Ok(renderer::create_render_pipeline(device, layout, format, None, &[], &shader, "Shader Console Pipeline"))
+}
+
+// Validation errors say where they went wrong in their sources, from the outside in
+fn error_chain(error: &dyn Error) -> String {
+    let mut message = error.to_string();
+    let mut source  = error.source();
+
+    while let Some(error) = source {
+        message += &format!(": {}", error);
+        source   = error.source();
+    }
+
+    message
+}
@@ -0,0 +1,41 @@
+// Goes before the shader console's snippet, which defines
+//
+//   fn effect(uv: vec2<f32>, color: vec4<f32>) -> vec4<f32>
+//
+// to turn the scene's color at `uv` into the output's, and can read anything declared here. See
+// shader_console.rs.
+
+struct Console {
+    // Of the scene, in pixels
+    resolution: vec2<f32>,
+    // Seconds since the console was made
+    time:       f32,
+    _padding:   f32,
+}
+
+@group(0) @binding(0)
+var<uniform> console: Console;
+@group(0) @binding(1)
+var t_scene: texture_2d<f32>;
+@group(0) @binding(2)
+var s_scene: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv:                  vec2<f32>,
+}
+
+// One triangle that covers the screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+
+    out.uv            = uv;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+
+    return out;
+}
+
+// The snippet follows
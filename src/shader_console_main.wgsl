@@ -0,0 +1,8 @@
+
+// Goes after the shader console's snippet, since functions have to be declared before they're
+// called
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return effect(in.uv, textureSample(t_scene, s_scene, in.uv));
+}
@@ -0,0 +1,280 @@
+// Variants of one WGSL source, picked by defines rather than branching on uniforms at run time.
+// A `VariantKey` names the defines, like `HAS_LIGHTMAP` or `NUM_CASCADES = 4`, and
+// `ShaderVariants` compiles the source for each key the first time it's asked for and keeps the
+// module after.
+//
+// Sources mark what depends on a define with lines of their own:
+//
+//   #ifdef NAME     kept if NAME is defined
+//   #ifndef NAME    kept if it isn't
+//   #if NAME        kept if it's defined and not 0
+//   #else
+//   #endif
+//
+// and they nest. Anything more on a directive's line is an error rather than ignored. Defined
+// names elsewhere in kept lines are replaced by their values, so a `NUM_CASCADES` can size an
+// array. Lines left out become blank, so naga's errors point at the same line of the source
+// either way.
+
+use std::{borrow::Cow, collections::{BTreeMap, HashMap}};
+
+use anyhow::*;
+
+use crate::shader_code::ShaderCode;
+
+// The defines a variant is compiled with, in name order so equal keys hash the same
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct VariantKey {
+    defines: BTreeMap<&'static str, u32>,
+}
+
+impl VariantKey {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Defines `name` as 1
+    pub fn define(self, name: &'static str) -> Self {
+        self.set(name, 1)
+    }
+
+    // Defines `name` if `enabled`, for building keys from flags
+    pub fn define_if(self, name: &'static str, enabled: bool) -> Self {
+        if enabled { self.define(name) } else { self }
+    }
+
+    pub fn set(mut self, name: &'static str, value: u32) -> Self {
+        self.defines.insert(name, value);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<u32> {
+        self.defines.get(name).copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.defines.is_empty()
+    }
+
+    // For labels, e.g. `HAS_LIGHTMAP NUM_CASCADES=4`
+    pub fn label(&self) -> String {
+        self.defines.iter()
+            .map(|(name, value)| if *value == 1 { name.to_string() } else { format!("{}={}", name, value) })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+// `source` as `key` has it
+pub fn preprocess(source: &str, key: &VariantKey) -> Result<String> {
+    // For each open block, whether its lines are kept, and whether it's had its `#else`
+    let mut blocks: Vec<(bool, bool)> = Vec::new();
+    let mut output = String::with_capacity(source.len());
+
+    for (number, line) in source.lines().enumerate() {
+        let number  = number + 1;
+        let keeping = !matches!(blocks.last(), Some((false, _)));
+        let trimmed = line.trim();
+
+        if let Some(directive) = trimmed.strip_prefix('#') {
+            let mut words = directive.split_whitespace();
+            let name      = words.next().unwrap_or("");
+            let argument  = if matches!(name, "else" | "endif") { None } else { words.next() };
+
+            if let Some(extra) = words.next() {
+                bail!("line {}: unexpected `{}` after `#{}`", number, extra, name);
+            }
+
+            let defined = |argument: Option<&str>| {
+                argument
+                    .map(|argument| key.get(argument))
+                    .ok_or_else(|| anyhow!("line {}: `#{}` needs a name", number, name))
+            };
+
+            match name {
+                "ifdef"  => blocks.push((keeping && defined(argument)?.is_some(), false)),
+                "ifndef" => blocks.push((keeping && defined(argument)?.is_none(), false)),
+                "if"     => blocks.push((keeping && defined(argument)?.unwrap_or(0) != 0, false)),
+                "else"   => {
+                    let outer = blocks.len() < 2 || blocks[blocks.len() - 2].0;
+
+                    match blocks.last_mut() {
+                        Some((_, true))        => bail!("line {}: a second `#else` for the same block", number),
+                        Some((kept, has_else)) => {
+                            *kept     = outer && !*kept;
+                            *has_else = true;
+                        }
+                        None                   => bail!("line {}: `#else` without an `#if`", number),
+                    }
+                }
+                "endif"  => {
+                    blocks.pop().ok_or_else(|| anyhow!("line {}: `#endif` without an `#if`", number))?;
+                }
+                _        => bail!("line {}: unknown directive `#{}`", number, name),
+            }
+        } else if keeping {
+            substitute(line, key, &mut output);
+        }
+
+        output.push('\n');
+    }
+
+    if !blocks.is_empty() {
+        bail!("{} `#if` block(s) left open at the end", blocks.len());
+    }
+
+    Ok(output)
+}
+
+// Copies `line` to `output` with every defined name replaced by its value
+fn substitute(line: &str, key: &VariantKey, output: &mut String) {
+    if key.is_empty() {
+        output.push_str(line);
+        return;
+    }
+
+    let mut rest = line;
+
+    while let Some(start) = rest.find(|c: char| c.is_ascii_alphanumeric() || c == '_') {
+        let end  = rest[start..]
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .map_or(rest.len(), |end| start + end);
+        let word = &rest[start..end];
+
+        output.push_str(&rest[..start]);
+
+        match key.get(word) {
+            Some(value) => output.push_str(&value.to_string()),
+            None        => output.push_str(word),
+        }
+
+        rest = &rest[end..];
+    }
+
+    output.push_str(rest);
+}
+
+// One source's compiled variants
+pub struct ShaderVariants {
+    source:  Cow<'static, str>,
+    label:   String,
+    modules: HashMap<VariantKey, wgpu::ShaderModule>,
+}
+
+impl ShaderVariants {
+    pub fn new(source: impl Into<Cow<'static, str>>, label: &str) -> Self {
+        Self {
+            source:  source.into(),
+            label:   label.to_string(),
+            modules: HashMap::new(),
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    // The module for `key`, compiled now if it hasn't been yet. Errors are the preprocessor's or
+    // naga's, and aren't kept, so asking again tries again.
+    pub fn get(&mut self, device: &wgpu::Device, key: &VariantKey) -> Result<&wgpu::ShaderModule> {
+        if !self.modules.contains_key(key) {
+            let label  = if key.is_empty() { self.label.clone() } else { format!("{} ({})", self.label, key.label()) };
+            let source = preprocess(&self.source, key).with_context(|| format!("Couldn't preprocess {}", label))?;
+            let module = ShaderCode::Wgsl(Cow::Owned(source)).create_module(device, &label)?;
+
+            self.modules.insert(key.clone(), module);
+        }
+
+        Ok(&self.modules[key])
+    }
+
+    // How many variants have been compiled
+    pub fn len(&self) -> usize {
+        self.modules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.modules.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The kept lines, without the blanks left for the rest
+    fn kept(source: &str, key: &VariantKey) -> Vec<String> {
+        preprocess(source, key).unwrap()
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn keeps_line_numbers() {
+        let source = "a\n#ifdef X\nb\n#endif\nc\n";
+
+        assert_eq!(preprocess(source, &VariantKey::new()).unwrap(), "a\n\n\n\nc\n");
+    }
+
+    #[test]
+    fn else_flips_the_block() {
+        let source = "#ifdef X\nx\n#else\nnot x\n#endif";
+
+        assert_eq!(kept(source, &VariantKey::new().define("X")), ["x"]);
+        assert_eq!(kept(source, &VariantKey::new()), ["not x"]);
+    }
+
+    #[test]
+    fn if_and_ifndef() {
+        let source = "#if X\nx\n#endif\n#ifndef X\nno x\n#endif";
+
+        assert_eq!(kept(source, &VariantKey::new().set("X", 2)), ["x"]);
+        assert_eq!(kept(source, &VariantKey::new().set("X", 0)), Vec::<String>::new());
+        assert_eq!(kept(source, &VariantKey::new()), ["no x"]);
+    }
+
+    #[test]
+    fn nested_blocks_follow_the_outer_one() {
+        let source = "#ifdef A\n#ifdef B\nab\n#else\na\n#endif\n#else\n#ifdef B\nb\n#endif\nneither\n#endif";
+
+        assert_eq!(kept(source, &VariantKey::new().define("A").define("B")), ["ab"]);
+        assert_eq!(kept(source, &VariantKey::new().define("A")), ["a"]);
+        assert_eq!(kept(source, &VariantKey::new().define("B")), ["b", "neither"]);
+        assert_eq!(kept(source, &VariantKey::new()), ["neither"]);
+    }
+
+    #[test]
+    fn substitutes_whole_names() {
+        let key = VariantKey::new().set("NUM_CASCADES", 4);
+
+        assert_eq!(kept("array<f32, NUM_CASCADES> NUM_CASCADES_MAX", &key), ["array<f32, 4> NUM_CASCADES_MAX"]);
+    }
+
+    #[test]
+    fn unknown_defines_are_undefined() {
+        let source = "#ifdef UNKNOWN\nx\n#endif\n#if UNKNOWN\ny\n#endif\nz";
+
+        assert_eq!(kept(source, &VariantKey::new().define("OTHER")), ["z"]);
+    }
+
+    #[test]
+    fn rejects_malformed_directives() {
+        let key = VariantKey::new();
+
+        for source in [
+            "#ifdef X\nx",
+            "#endif",
+            "#else",
+            "#ifdef X\n#else\n#else\n#endif",
+            "#ifdef\n#endif",
+            "#ifdef X Y\n#endif",
+            "#ifdef X\n#endif X",
+            "#ifdef X\n#else junk\n#endif",
+            "#define X",
+        ] {
+            assert!(preprocess(source, &key).is_err(), "{:?} should have failed", source);
+        }
+    }
+}
@@ -0,0 +1,328 @@
+// Shading models a material can pick from. Each model is a shader with the same bind groups and
+// vertex layouts, so adding one means adding a variant, its shader and a pipeline here. Every
+// shader gets `camera.wgsl`, `lights.wgsl`, `lod.wgsl`, `triplanar.wgsl`, `detail.wgsl` and
+// `instance.wgsl` prepended.
+//
+// Materials also pick which faces they cull, how they use alpha and how their depth is biased,
+// and the textures they have pick a variant of their model's shader, see `MaterialFeatures`. So
+// there's a pipeline for every combination, looked up by `PipelineKey` and built the first time a
+// material needs it.
+
+use std::collections::HashMap;
+
+use crate::{camera::DepthMode, renderer, shader_variants::{self, ShaderVariants, VariantKey}};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum ShadingModel {
+    // The diffuse texture as-is
+    #[default]
+    Textured,
+    // Banded diffuse lighting with a rim light
+    Toon,
+    // Light wraps round and shines through, for skin, wax and leaves. Tuned by the material's
+    // subsurface parameters.
+    Subsurface,
+}
+
+impl ShadingModel {
+    pub const ALL: [ShadingModel; 3] = [ShadingModel::Textured, ShadingModel::Toon, ShadingModel::Subsurface];
+
+    // Read from a `shading` line in the .mtl file, e.g. `shading toon`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "textured"   => Some(ShadingModel::Textured),
+            "toon"       => Some(ShadingModel::Toon),
+            "subsurface" => Some(ShadingModel::Subsurface),
+            _            => None,
+        }
+    }
+
+    // With every variant's lines, see `variant_source`
+    pub fn shader_source(&self) -> &'static str {
+        match self {
+            ShadingModel::Textured   => concat!(include_str!("camera.wgsl"), include_str!("lights.wgsl"), include_str!("lod.wgsl"), include_str!("triplanar.wgsl"), include_str!("detail.wgsl"), include_str!("instance.wgsl"), include_str!("shader.wgsl")),
+            ShadingModel::Toon       => concat!(include_str!("camera.wgsl"), include_str!("lights.wgsl"), include_str!("lod.wgsl"), include_str!("triplanar.wgsl"), include_str!("detail.wgsl"), include_str!("instance.wgsl"), include_str!("toon.wgsl")),
+            ShadingModel::Subsurface => concat!(include_str!("camera.wgsl"), include_str!("lights.wgsl"), include_str!("lod.wgsl"), include_str!("triplanar.wgsl"), include_str!("detail.wgsl"), include_str!("instance.wgsl"), include_str!("subsurface.wgsl")),
+        }
+    }
+
+    // The shader as materials with `features` are drawn with
+    pub fn variant_source(&self, features: MaterialFeatures) -> anyhow::Result<String> {
+        shader_variants::preprocess(self.shader_source(), &features.variant_key())
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            ShadingModel::Textured   => "Textured",
+            ShadingModel::Toon       => "Toon",
+            ShadingModel::Subsurface => "Subsurface",
+        }
+    }
+}
+
+// Which faces of a material's meshes aren't drawn
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum CullMode {
+    #[default]
+    Back,
+    // For effects drawn from the inside, like skies or inverted hull outlines
+    Front,
+    // Both sides drawn, for thin surfaces like leaves, cloth and paper
+    TwoSided,
+}
+
+impl CullMode {
+    pub const ALL: [CullMode; 3] = [CullMode::Back, CullMode::Front, CullMode::TwoSided];
+
+    // Read from a `cull` line in the .mtl file, e.g. `cull none`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "back"               => Some(CullMode::Back),
+            "front"              => Some(CullMode::Front),
+            "none" | "two_sided" => Some(CullMode::TwoSided),
+            _                    => None,
+        }
+    }
+
+    pub fn face(&self) -> Option<wgpu::Face> {
+        match self {
+            CullMode::Back     => Some(wgpu::Face::Back),
+            CullMode::Front    => Some(wgpu::Face::Front),
+            CullMode::TwoSided => None,
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            CullMode::Back     => "Back Culled",
+            CullMode::Front    => "Front Culled",
+            CullMode::TwoSided => "Two-Sided",
+        }
+    }
+}
+
+// How a material's alpha is used. Both draw in the opaque pass.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum AlphaMode {
+    // Alpha is ignored
+    #[default]
+    Opaque,
+    // Cut away where alpha is under the material's `alpha_cutoff`, for foliage and fences. With
+    // multisampling the edge is antialiased by alpha to coverage.
+    Cutout,
+}
+
+impl AlphaMode {
+    pub const ALL: [AlphaMode; 2] = [AlphaMode::Opaque, AlphaMode::Cutout];
+
+    // Read from an `alpha` line in the .mtl file, e.g. `alpha cutout`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "opaque"          => Some(AlphaMode::Opaque),
+            "cutout" | "mask" => Some(AlphaMode::Cutout),
+            _                 => None,
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            AlphaMode::Opaque => "Opaque",
+            AlphaMode::Cutout => "Cutout",
+        }
+    }
+}
+
+// Offsets a material's depth so it wins against surfaces it's drawn flush with
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum DepthBias {
+    #[default]
+    None,
+    // Decals and overlays laid on other geometry, pulled towards the camera so they don't z-fight
+    Decal,
+}
+
+impl DepthBias {
+    pub const ALL: [DepthBias; 2] = [DepthBias::None, DepthBias::Decal];
+
+    // Read from a `depth_bias` line in the .mtl file, e.g. `depth_bias decal`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "none"  => Some(DepthBias::None),
+            "decal" => Some(DepthBias::Decal),
+            _       => None,
+        }
+    }
+
+    pub fn state(&self, depth_mode: DepthMode) -> wgpu::DepthBiasState {
+        match self {
+            DepthBias::None  => wgpu::DepthBiasState::default(),
+            // The slope term covers decals seen at grazing angles, where a constant offset alone
+            // falls short
+            DepthBias::Decal => depth_mode.bias_toward_camera(wgpu::DepthBiasState {
+                constant:    4,
+                slope_scale: 1.5,
+                clamp:       0.0,
+            }),
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            DepthBias::None  => "Unbiased",
+            DepthBias::Decal => "Decal Biased",
+        }
+    }
+}
+
+// What a material has that its shader would otherwise check for on every pixel. Each is a define
+// in the shading models' shaders.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct MaterialFeatures {
+    // `TRIPLANAR`, a triplanar scale above 0.0
+    pub triplanar:      bool,
+    // `HAS_ANISOTROPY_MAP`
+    pub anisotropy_map: bool,
+    // `HAS_LIGHTMAP`
+    pub lightmap:       bool,
+}
+
+impl MaterialFeatures {
+    // Every combination, for what has to hold whichever variant a material ends up with
+    pub fn all() -> impl Iterator<Item = MaterialFeatures> {
+        (0..8).map(|bits| MaterialFeatures {
+            triplanar:      bits & 1 != 0,
+            anisotropy_map: bits & 2 != 0,
+            lightmap:       bits & 4 != 0,
+        })
+    }
+
+    pub fn variant_key(&self) -> VariantKey {
+        VariantKey::new()
+            .define_if("TRIPLANAR", self.triplanar)
+            .define_if("HAS_ANISOTROPY_MAP", self.anisotropy_map)
+            .define_if("HAS_LIGHTMAP", self.lightmap)
+    }
+}
+
+// Everything about a material that picks its pipeline
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct PipelineKey {
+    pub shading:    ShadingModel,
+    pub cull:       CullMode,
+    pub alpha:      AlphaMode,
+    pub depth_bias: DepthBias,
+    pub features:   MaterialFeatures,
+}
+
+// One render pipeline per pipeline key, sharing a layout and depth state. They're built by
+// `prepare`, so only the combinations materials use are compiled.
+pub struct ShadingPipelines {
+    layout:         wgpu::PipelineLayout,
+    color_format:   wgpu::TextureFormat,
+    depth_stencil:  wgpu::DepthStencilState,
+    depth_mode:     DepthMode,
+    vertex_layouts: Vec<wgpu::VertexBufferLayout<'static>>,
+    sample_count:   u32,
+    label:          String,
+    shaders:        HashMap<ShadingModel, ShaderVariants>,
+    // With the label each was made with
+    pipelines:      HashMap<PipelineKey, (wgpu::RenderPipeline, String)>,
+}
+
+impl ShadingPipelines {
+    // Cutout pipelines turn on alpha to coverage when `sample_count` is more than one. Biases
+    // point towards the camera in `depth_mode`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device:             &wgpu::Device,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+        color_format:       wgpu::TextureFormat,
+        depth_stencil:      wgpu::DepthStencilState,
+        depth_mode:         DepthMode,
+        vertex_layouts:     &[wgpu::VertexBufferLayout<'static>],
+        sample_count:       u32,
+        label:              &str,
+    ) -> Self {
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label:                Some(&format!("{} Layout", label)),
+            bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+
+        let shaders = ShadingModel::ALL.iter()
+            .map(|shading| (*shading, ShaderVariants::new(shading.shader_source(), shading.label())))
+            .collect();
+
+        Self {
+            layout,
+            color_format,
+            depth_stencil,
+            depth_mode,
+            vertex_layouts: vertex_layouts.to_vec(),
+            sample_count,
+            label:          label.to_string(),
+            shaders,
+            pipelines:      HashMap::new(),
+        }
+    }
+
+    // Builds `key`'s pipeline if it hasn't been yet. Called for every material before drawing.
+    pub fn prepare(&mut self, device: &wgpu::Device, key: PipelineKey) {
+        if self.pipelines.contains_key(&key) {
+            return;
+        }
+
+        let variant = key.features.variant_key();
+
+        let mut label = format!("{} {} {} {} {}", key.shading.label(), key.cull.label(), key.alpha.label(), key.depth_bias.label(), self.label);
+
+        if !variant.is_empty() {
+            label = format!("{} ({})", label, variant.label());
+        }
+
+        // The shaders are the crate's own, so one that doesn't build is a bug
+        let shader = self.shaders.get_mut(&key.shading)
+            .expect("every shading model has a shader")
+            .get(device, &variant)
+            .unwrap_or_else(|e| panic!("{:#}", e));
+
+        let raster = renderer::RasterState {
+            cull_mode:         key.cull.face(),
+            sample_count:      self.sample_count,
+            alpha_to_coverage: key.alpha == AlphaMode::Cutout && self.sample_count > 1,
+            depth_bias:        key.depth_bias.state(self.depth_mode),
+        };
+
+        let pipeline = renderer::create_render_pipeline_with_raster(
+            device,
+            &self.layout,
+            self.color_format,
+            Some(self.depth_stencil.clone()),
+            &self.vertex_layouts,
+            shader,
+            raster,
+            &label,
+        );
+
+        self.pipelines.insert(key, (pipeline, label));
+    }
+
+    // `key`'s pipeline, which must have been `prepare`d
+    pub fn get(&self, key: PipelineKey) -> &wgpu::RenderPipeline {
+        &self.pipelines[&key].0
+    }
+
+    // The label `key`'s pipeline was made with, for `crash_report::set_pipeline`
+    pub fn label(&self, key: PipelineKey) -> &str {
+        &self.pipelines[&key].1
+    }
+
+    // How many pipelines have been built
+    pub fn len(&self) -> usize {
+        self.pipelines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pipelines.is_empty()
+    }
+}
@@ -0,0 +1,270 @@
+// Shadow maps for every shadow-casting light, packed into one depth atlas so the number of
+// textures doesn't grow with the number of lights
+
+use crate::{bind_group, crash_report, light::MAX_SPOT_LIGHTS, renderer};
+
+pub const ATLAS_SIZE: u32 = 2048;
+pub const MAX_TILE:   u32 = 1024;
+pub const MIN_TILE:   u32 = 128;
+
+pub const SHADOW_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+// How shadow edges are filtered
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ShadowSettings {
+    // Width of the grid of percentage-closer filtering samples. 1 is a single tap, which the
+    // comparison sampler still smooths over 2x2 texels.
+    pub kernel_size: u32,
+    // Percentage-closer soft shadows: searches for the blockers around each sample and widens the
+    // filter with their distance, so shadows are sharp at contact and soften further away
+    pub pcss:        bool,
+    // Radius of the light's emitting area in world units, which sets how soft PCSS shadows get
+    pub light_size:  f32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ShadowQuality {
+    Hard,
+    Low,
+    Medium,
+    High,
+}
+
+impl ShadowQuality {
+    pub const ALL: [ShadowQuality; 4] = [
+        ShadowQuality::Hard,
+        ShadowQuality::Low,
+        ShadowQuality::Medium,
+        ShadowQuality::High,
+    ];
+
+    pub fn settings(&self) -> ShadowSettings {
+        match self {
+            ShadowQuality::Hard   => ShadowSettings { kernel_size: 1, pcss: false, light_size: 0.0 },
+            ShadowQuality::Low    => ShadowSettings { kernel_size: 3, pcss: false, light_size: 0.0 },
+            ShadowQuality::Medium => ShadowSettings { kernel_size: 5, pcss: false, light_size: 0.0 },
+            ShadowQuality::High   => ShadowSettings { kernel_size: 5, pcss: true,  light_size: 0.5 },
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ShadowQuality::Hard   => "hard",
+            ShadowQuality::Low    => "low",
+            ShadowQuality::Medium => "medium",
+            ShadowQuality::High   => "high",
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        Self::ALL[(*self as usize + 1) % Self::ALL.len()]
+    }
+}
+
+// A square region of the atlas, in texels
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AtlasTile {
+    pub x:    u32,
+    pub y:    u32,
+    pub size: u32,
+}
+
+impl AtlasTile {
+    // Offset and scale in atlas UVs
+    pub fn uv_rect(&self) -> [f32; 4] {
+        let atlas = ATLAS_SIZE as f32;
+
+        [self.x as f32 / atlas, self.y as f32 / atlas, self.size as f32 / atlas, self.size as f32 / atlas]
+    }
+}
+
+// Hands out tiles by priority, highest first. Each light gets the biggest tile that still leaves
+// room for everyone after it to get the smallest, so low priority lights lose resolution before
+// anyone loses their shadow. Lights that don't fit at all get `None`.
+pub fn allocate_tiles(priorities: &[f32]) -> Vec<Option<AtlasTile>> {
+    let mut order = (0..priorities.len()).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| priorities[b].total_cmp(&priorities[a]));
+
+    // Measured in MIN_TILE cells
+    let cells = |size: u32| (size / MIN_TILE) * (size / MIN_TILE);
+    let total = cells(ATLAS_SIZE);
+
+    let mut tiles = vec![None; priorities.len()];
+    let mut used  = 0;
+    let mut size  = MAX_TILE;
+
+    for (rank, &light) in order.iter().enumerate() {
+        let after = (order.len() - rank - 1) as u32;
+
+        // Sizes only ever shrink, which is what keeps the Z-order packing free of gaps
+        while size > MIN_TILE && used + cells(size) + after > total {
+            size /= 2;
+        }
+
+        if used + cells(size) > total {
+            break;
+        }
+
+        // Every earlier tile was at least this big, so `used` is a multiple of this tile's area
+        // and its Z-order position is aligned to it
+        tiles[light] = Some(AtlasTile {
+            x: deinterleave(used) * MIN_TILE,
+            y: deinterleave(used >> 1) * MIN_TILE,
+            size,
+        });
+
+        used += cells(size);
+    }
+
+    tiles
+}
+
+// Every other bit of `v`, packed together
+fn deinterleave(v: u32) -> u32 {
+    let mut v = v & 0x5555_5555;
+
+    v = (v | (v >> 1)) & 0x3333_3333;
+    v = (v | (v >> 2)) & 0x0f0f_0f0f;
+    v = (v | (v >> 4)) & 0x00ff_00ff;
+    v = (v | (v >> 8)) & 0x0000_ffff;
+
+    v
+}
+
+// Anything that draws depth into the shadow atlas. The pipeline and the light's view-projection at
+// @group(0) are set beforehand, and vertex buffers are laid out as in `shadow.wgsl`.
+pub trait ShadowCaster {
+    fn draw_shadow<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>);
+}
+
+pub struct ShadowAtlas {
+    pub view:    wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+    pipeline:    wgpu::RenderPipeline,
+    // One view-projection per light slot
+    buffers:     Vec<wgpu::Buffer>,
+    bind_groups: Vec<wgpu::BindGroup>,
+}
+
+impl ShadowAtlas {
+    pub fn new(device: &wgpu::Device, vertex_layouts: &[wgpu::VertexBufferLayout]) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label:           Some("Shadow Atlas"),
+            size:            wgpu::Extent3d { width: ATLAS_SIZE, height: ATLAS_SIZE, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count:    1,
+            dimension:       wgpu::TextureDimension::D2,
+            format:          SHADOW_FORMAT,
+            usage:           wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+
+        let view    = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label:          Some("Shadow Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter:     wgpu::FilterMode::Linear,
+            min_filter:     wgpu::FilterMode::Linear,
+            compare:        Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        let layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::VERTEX)
+            .build(device, "shadow_bind_group_layout");
+
+        let buffers = (0..MAX_SPOT_LIGHTS)
+            .map(|_| device.create_buffer(&wgpu::BufferDescriptor {
+                label:              Some("Shadow View Projection Buffer"),
+                size:               std::mem::size_of::<[[f32; 4]; 4]>() as wgpu::BufferAddress,
+                usage:              wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }))
+            .collect::<Vec<_>>();
+
+        let bind_groups = buffers.iter()
+            .map(|buffer| {
+                bind_group::BindGroupBuilder::new(&layout)
+                    .uniform(buffer)
+                    .build(device, "shadow_bind_group")
+            })
+            .collect();
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Shadow Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shadow.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Shadow Pipeline Layout"),
+            bind_group_layouts:   &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        // Slope-scaled bias keeps surfaces from shadowing themselves at grazing angles
+        let raster = renderer::RasterState {
+            depth_bias: wgpu::DepthBiasState {
+                constant:    2,
+                slope_scale: 2.0,
+                clamp:       0.0,
+            },
+            ..Default::default()
+        };
+
+        let pipeline = renderer::create_depth_prepass_pipeline(
+            device,
+            &pipeline_layout,
+            renderer::depth_state(SHADOW_FORMAT, wgpu::CompareFunction::LessEqual, true),
+            vertex_layouts,
+            &shader,
+            raster,
+        );
+
+        Self {
+            view,
+            sampler,
+            pipeline,
+            buffers,
+            bind_groups,
+        }
+    }
+
+    pub fn set_view_proj(&self, queue: &wgpu::Queue, slot: usize, view_proj: cgmath::Matrix4<f32>) {
+        let view_proj: [[f32; 4]; 4] = view_proj.into();
+
+        queue.write_buffer(&self.buffers[slot], 0, bytemuck::cast_slice(&[view_proj]));
+    }
+
+    // Clears the atlas and draws the casters into each light slot's tile
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        tiles:   &[Option<AtlasTile>],
+        caster:  &dyn ShadowCaster,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Shadow Pass"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view:       &self.view,
+                depth_ops:  Some(wgpu::Operations {
+                    load:  wgpu::LoadOp::Clear(1.0),
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+
+        crash_report::set_pipeline(&mut render_pass, &self.pipeline, "Depth Prepass Pipeline");
+
+        for (slot, tile) in tiles.iter().enumerate().take(MAX_SPOT_LIGHTS) {
+            if let Some(tile) = tile {
+                let size = tile.size as f32;
+
+                render_pass.set_viewport(tile.x as f32, tile.y as f32, size, size, 0.0, 1.0);
+                render_pass.set_bind_group(0, &self.bind_groups[slot], &[]);
+                caster.draw_shadow(&mut render_pass);
+            }
+        }
+    }
+}
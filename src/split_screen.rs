@@ -0,0 +1,365 @@
+// Split-screen for up to four players. Each player has a camera and an input source, and their
+// views are drawn side by side into one target, each restricted to its region with a viewport and
+// scissor rect. The target is then shown in place of the main view, so post effects still apply.
+//
+// Views are submitted one after another since the scene's camera buffer only holds one view per
+// submission, the same way reflection probes capture their faces.
+
+use std::time::Duration;
+
+use cgmath::prelude::*;
+use winit::event::VirtualKeyCode;
+
+use crate::{
+    bind_group,
+    camera::Camera,
+    crash_report,
+    input::Input,
+    pass::{self, Drawable, RenderLayer, RenderLayers, Viewport},
+    renderer::{self, GpuContext},
+    scene::Scene,
+    texture::Texture,
+};
+
+pub const MAX_PLAYERS: usize = 4;
+
+// Degrees per second at full turn
+const TURN_SPEED: f32 = 90.0;
+// Stick deflection ignored around the center
+#[cfg(feature = "gamepad")]
+const DEAD_ZONE: f32 = 0.15;
+
+// Keys that move one player around
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct KeyBindings {
+    pub forward:    VirtualKeyCode,
+    pub back:       VirtualKeyCode,
+    pub left:       VirtualKeyCode,
+    pub right:      VirtualKeyCode,
+    pub turn_left:  VirtualKeyCode,
+    pub turn_right: VirtualKeyCode,
+}
+
+impl KeyBindings {
+    pub const WASD: Self = Self {
+        forward:    VirtualKeyCode::W,
+        back:       VirtualKeyCode::S,
+        left:       VirtualKeyCode::A,
+        right:      VirtualKeyCode::D,
+        turn_left:  VirtualKeyCode::Q,
+        turn_right: VirtualKeyCode::E,
+    };
+
+    pub const IJKL: Self = Self {
+        forward:    VirtualKeyCode::I,
+        back:       VirtualKeyCode::K,
+        left:       VirtualKeyCode::J,
+        right:      VirtualKeyCode::L,
+        turn_left:  VirtualKeyCode::U,
+        turn_right: VirtualKeyCode::O,
+    };
+}
+
+// Where a player's input comes from. Several players can share a keyboard with different keys.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InputSource {
+    Keyboard(KeyBindings),
+    // The nth connected gamepad. Only read when built with the `gamepad` feature.
+    Gamepad(usize),
+}
+
+// What a player asked for this frame, each from -1.0 to 1.0
+#[derive(Debug, Copy, Clone, Default)]
+pub struct PlayerIntent {
+    pub forward: f32,
+    pub right:   f32,
+    pub turn:    f32,
+}
+
+// Gamepads polled once a frame, so every player reads the same state
+pub struct Gamepads {
+    #[cfg(feature = "gamepad")]
+    gilrs: Option<gilrs::Gilrs>,
+}
+
+impl Default for Gamepads {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Gamepads {
+    pub fn new() -> Self {
+        Self {
+            #[cfg(feature = "gamepad")]
+            gilrs: gilrs::Gilrs::new()
+                .map_err(|e| log::warn!("Gamepads are unavailable: {}", e))
+                .ok(),
+        }
+    }
+
+    // Takes in the events since the last frame, which is what updates each gamepad's state
+    pub fn update(&mut self) {
+        #[cfg(feature = "gamepad")]
+        if let Some(gilrs) = &mut self.gilrs {
+            while gilrs.next_event().is_some() {}
+        }
+    }
+
+    #[cfg(feature = "gamepad")]
+    fn intent(&self, index: usize) -> PlayerIntent {
+        use gilrs::Axis;
+
+        let gamepad = match self.gilrs.as_ref().and_then(|gilrs| gilrs.gamepads().nth(index)) {
+            Some((_, gamepad)) => gamepad,
+            None               => return PlayerIntent::default(),
+        };
+
+        let axis = |axis| {
+            let value = gamepad.value(axis);
+            if value.abs() < DEAD_ZONE { 0.0 } else { value }
+        };
+
+        PlayerIntent {
+            forward: axis(Axis::LeftStickY),
+            right:   axis(Axis::LeftStickX),
+            turn:    axis(Axis::RightStickX),
+        }
+    }
+
+    #[cfg(not(feature = "gamepad"))]
+    fn intent(&self, _index: usize) -> PlayerIntent {
+        PlayerIntent::default()
+    }
+}
+
+pub struct Player {
+    pub camera: Camera,
+    pub input:  InputSource,
+    // World units per second
+    pub speed:  f32,
+}
+
+impl Player {
+    pub fn intent(&self, input: &Input, gamepads: &Gamepads) -> PlayerIntent {
+        match self.input {
+            InputSource::Keyboard(keys) => {
+                let axis = |negative, positive| {
+                    input.is_key_held(positive) as i32 as f32 - input.is_key_held(negative) as i32 as f32
+                };
+
+                PlayerIntent {
+                    forward: axis(keys.back, keys.forward),
+                    right:   axis(keys.left, keys.right),
+                    turn:    axis(keys.turn_left, keys.turn_right),
+                }
+            }
+            InputSource::Gamepad(index) => gamepads.intent(index),
+        }
+    }
+
+    // Walks the camera over the ground and turns it about the vertical
+    pub fn update(&mut self, intent: PlayerIntent, dt: Duration) {
+        let dt      = dt.as_secs_f32();
+        let camera  = &mut self.camera;
+        let turn    = cgmath::Quaternion::from_angle_y(cgmath::Deg(-intent.turn * TURN_SPEED * dt));
+        let look    = turn.rotate_vector(camera.target - camera.eye);
+        let forward = cgmath::Vector3::new(look.x, 0.0, look.z);
+
+        if forward.magnitude2() == 0.0 {
+            return;
+        }
+
+        let forward = forward.normalize();
+        let right   = forward.cross(cgmath::Vector3::unit_y());
+        let step    = (forward * intent.forward + right * intent.right) * self.speed * dt;
+
+        camera.eye   += step;
+        camera.target = camera.eye + look;
+    }
+}
+
+// The players' regions of a `width` by `height` target: side by side for two, one across the top
+// and two below for three, and quarters for four
+pub fn layout(players: usize, width: f32, height: f32) -> Vec<Viewport> {
+    let (half_width, half_height) = (width / 2.0, height / 2.0);
+    let region = |x, y, width, height| Viewport { x, y, width, height };
+
+    match players {
+        0 | 1 => vec![region(0.0, 0.0, width, height)],
+        2     => vec![region(0.0, 0.0, half_width, height), region(half_width, 0.0, half_width, height)],
+        3     => vec![
+            region(0.0, 0.0, width, half_height),
+            region(0.0, half_height, half_width, half_height),
+            region(half_width, half_height, half_width, half_height),
+        ],
+        _     => (0..4).map(|i| region(
+            (i % 2) as f32 * half_width,
+            (i / 2) as f32 * half_height,
+            half_width,
+            half_height,
+        )).collect(),
+    }
+}
+
+pub struct SplitScreen {
+    pub players: Vec<Player>,
+    gamepads:    Gamepads,
+    pipeline:    wgpu::RenderPipeline,
+    layout:      wgpu::BindGroupLayout,
+    sampler:     wgpu::Sampler,
+    // Rebuilt by `resize` along with the target
+    bind_group:  wgpu::BindGroup,
+    view:        wgpu::TextureView,
+    depth:       Texture,
+    size:        (u32, u32),
+}
+
+impl SplitScreen {
+    pub fn new(ctx: &GpuContext) -> Self {
+        let device = &ctx.device;
+
+        let layout = bind_group::BindGroupLayoutBuilder::new()
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .sampler(wgpu::ShaderStages::FRAGMENT)
+            .build(device, "split_screen_bind_group_layout");
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label:          Some("Split Screen Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter:     wgpu::FilterMode::Linear,
+            min_filter:     wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Split Screen Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("split_screen.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Split Screen Pipeline Layout"),
+            bind_group_layouts:   &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        // Drawn in the background layer, which has depth, but behind everything and without
+        // writing it
+        let pipeline = renderer::create_render_pipeline(
+            device,
+            &pipeline_layout,
+            ctx.config.format,
+            Some(renderer::depth_state(Texture::DEPTH_FORMAT, wgpu::CompareFunction::Always, false)),
+            &[],
+            &shader,
+            "Split Screen Pipeline",
+        );
+
+        let (view, depth) = create_target(ctx, ctx.size.width, ctx.size.height);
+        let bind_group    = bind_group::BindGroupBuilder::new(&layout)
+            .texture(&view)
+            .sampler(&sampler)
+            .build(device, "split_screen_bind_group");
+
+        Self {
+            players:  Vec::new(),
+            gamepads: Gamepads::new(),
+            pipeline,
+            layout,
+            sampler,
+            bind_group,
+            view,
+            depth,
+            size:     (ctx.size.width, ctx.size.height),
+        }
+    }
+
+    // The target matches the window, so call this after it's resized
+    pub fn resize(&mut self, ctx: &GpuContext) {
+        let size = (ctx.size.width.max(1), ctx.size.height.max(1));
+
+        if size == self.size {
+            return;
+        }
+
+        let (view, depth) = create_target(ctx, size.0, size.1);
+
+        self.bind_group = bind_group::BindGroupBuilder::new(&self.layout)
+            .texture(&view)
+            .sampler(&self.sampler)
+            .build(&ctx.device, "split_screen_bind_group");
+        self.view  = view;
+        self.depth = depth;
+        self.size  = size;
+    }
+
+    pub fn viewports(&self) -> Vec<Viewport> {
+        layout(self.players.len(), self.size.0 as f32, self.size.1 as f32)
+    }
+
+    // Moves each player by their own input, and keeps their cameras' aspect ratios matching
+    // their regions
+    pub fn update(&mut self, input: &Input, dt: Duration) {
+        self.gamepads.update();
+
+        let viewports = self.viewports();
+
+        for (player, viewport) in self.players.iter_mut().zip(viewports) {
+            let intent = player.intent(input, &self.gamepads);
+
+            player.update(intent, dt);
+            player.camera.aspect = viewport.aspect();
+        }
+    }
+
+    // Draws every player's view into their region of the target. Submitted straight away, so
+    // call it before the frame's own camera is uploaded.
+    pub fn render(&self, ctx: &GpuContext, scene: &dyn Scene) {
+        for (i, (player, viewport)) in self.players.iter().zip(self.viewports()).enumerate() {
+            let mut encoder = ctx.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Split Screen Encoder"),
+            });
+
+            if i == 0 {
+                pass::begin_main_pass(&mut encoder, &self.view, &self.depth.view, pass::CLEAR_COLOR, ctx.depth_mode);
+            }
+
+            {
+                let mut layers = RenderLayers::new();
+
+                scene.render_view(&ctx.queue, &player.camera, None, &mut layers);
+                layers.execute_viewport(&mut encoder, &self.view, &self.depth.view, ctx.depth_mode, viewport);
+            }
+
+            ctx.queue.submit(std::iter::once(encoder.finish()));
+        }
+    }
+}
+
+// Shows the players' views in place of the scene, so register it on its own in the background
+// layer
+impl Drawable for SplitScreen {
+    fn draw<'a>(&'a self, _layer: RenderLayer, render_pass: &mut wgpu::RenderPass<'a>) {
+        crash_report::set_pipeline(render_pass, &self.pipeline, "Split Screen Pipeline");
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+fn create_target(ctx: &GpuContext, width: u32, height: u32) -> (wgpu::TextureView, Texture) {
+    let texture = ctx.device.create_texture(&wgpu::TextureDescriptor {
+        label:           Some("Split Screen Target"),
+        size:            wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count:    1,
+        dimension:       wgpu::TextureDimension::D2,
+        format:          ctx.config.format,
+        usage:           wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+    });
+
+    (
+        texture.create_view(&wgpu::TextureViewDescriptor::default()),
+        Texture::create_depth_texture(&ctx.device, width, height, "split_screen_depth_texture"),
+    )
+}
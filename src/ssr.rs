@@ -0,0 +1,190 @@
+// Post effect that adds screen-space reflections to the finished scene, tracing each pixel's
+// reflection through the depth buffer. What's off screen or hidden can't be traced, so those
+// rays fall back on a reflection probe. Surfaces all share one roughness and reflectivity since
+// materials don't carry their own.
+
+use cgmath::prelude::*;
+
+use crate::{
+    bind_group,
+    camera::{Camera, DepthMode},
+    crash_report,
+    pass::PostEffect,
+    reflection::ReflectionProbe,
+    renderer::GpuContext,
+    uniform::{Uniform, UniformBuffer},
+};
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct SsrUniform {
+    view_proj:     [[f32; 4]; 4],
+    inv_view_proj: [[f32; 4]; 4],
+    eye:           [f32; 4],
+    max_distance:  f32,
+    thickness:     f32,
+    roughness:     f32,
+    reflectivity:  f32,
+    steps:         u32,
+    reverse_z:     u32,
+    _padding:      [u32; 2],
+}
+
+pub struct ScreenSpaceReflections {
+    // World units a ray travels before falling back on the probe
+    pub max_distance: f32,
+    // How far behind the depth buffer a ray can pass and still hit it. Too thin and rays slip
+    // between steps, too thick and they hit the backs of objects.
+    pub thickness:    f32,
+    // 0.0 is a sharp mirror, 1.0 a blurred and dimmed reflection
+    pub roughness:    f32,
+    // Reflectivity facing a surface head on, rising to 1.0 at grazing angles
+    pub reflectivity: f32,
+    pub steps:        u32,
+    pipeline:         wgpu::RenderPipeline,
+    uniform:          UniformBuffer<SsrUniform>,
+    layout:           wgpu::BindGroupLayout,
+    scene_layout:     wgpu::BindGroupLayout,
+    sampler:          wgpu::Sampler,
+    // Rebuilt by `prepare` since the depth texture is replaced on resize
+    bind_group:       Option<wgpu::BindGroup>,
+}
+
+impl ScreenSpaceReflections {
+    pub fn new(ctx: &GpuContext) -> Self {
+        let device = &ctx.device;
+
+        let layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::FRAGMENT)
+            .depth_texture(wgpu::ShaderStages::FRAGMENT)
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2Array)
+            .sampler(wgpu::ShaderStages::FRAGMENT)
+            .build(device, "ssr_bind_group_layout");
+
+        // The scene is bound on its own since it changes from effect to effect
+        let scene_layout = bind_group::BindGroupLayoutBuilder::new()
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .build(device, "ssr_scene_bind_group_layout");
+
+        let uniform = UniformBuffer::new(device, "SSR Uniform Buffer");
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label:          Some("SSR Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter:     wgpu::FilterMode::Linear,
+            min_filter:     wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("SSR Shader"),
+            source: wgpu::ShaderSource::Wgsl(concat!(include_str!("probe.wgsl"), include_str!("ssr.wgsl")).into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("SSR Pipeline Layout"),
+            bind_group_layouts:   &[&layout, &scene_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label:    Some("SSR Pipeline"),
+            layout:   Some(&pipeline_layout),
+            vertex:   wgpu::VertexState {
+                module:      &shader,
+                entry_point: "vs_main",
+                buffers:     &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module:      &shader,
+                entry_point: "fs_main",
+                targets:     &[Some(wgpu::ColorTargetState {
+                    format:     ctx.config.format,
+                    blend:      Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive:     wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample:   wgpu::MultisampleState::default(),
+            multiview:     None,
+        });
+
+        Self {
+            max_distance: 20.0,
+            thickness:    0.5,
+            roughness:    0.2,
+            reflectivity: 0.1,
+            steps:        48,
+            pipeline,
+            uniform,
+            layout,
+            scene_layout,
+            sampler,
+            bind_group:   None,
+        }
+    }
+
+    // Called once a frame before the effect is added, with the camera the scene was drawn from
+    pub fn prepare(&mut self, ctx: &GpuContext, camera: &Camera, probe: &ReflectionProbe) {
+        let view_proj = camera.build_view_projections_matrix();
+
+        self.uniform.write(&ctx.queue, &SsrUniform {
+            view_proj:     view_proj.into(),
+            inv_view_proj: view_proj.invert().unwrap_or_else(cgmath::Matrix4::identity).into(),
+            eye:           camera.eye.to_homogeneous().into(),
+            max_distance:  self.max_distance,
+            thickness:     self.thickness,
+            roughness:     self.roughness.clamp(0.0, 1.0),
+            reflectivity:  self.reflectivity,
+            steps:         self.steps.max(1),
+            reverse_z:     (ctx.depth_mode == DepthMode::ReverseZ) as u32,
+            _padding:      [0; 2],
+        });
+
+        self.bind_group = Some(
+            bind_group::BindGroupBuilder::new(&self.layout)
+                .uniform(self.uniform.buffer())
+                .texture(&ctx.depth_texture.view)
+                .texture(&probe.view)
+                .sampler(&self.sampler)
+                .build(&ctx.device, "ssr_bind_group")
+        );
+    }
+}
+
+impl PostEffect for ScreenSpaceReflections {
+    fn apply(
+        &self,
+        device:  &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        input:   &wgpu::TextureView,
+        output:  &wgpu::TextureView,
+    ) {
+        // Every pixel of `output` has to be written, so there's no skipping the pass without it
+        let bind_group = self.bind_group.as_ref().expect("SSR needs to be prepared before it's applied");
+
+        let scene_group = bind_group::BindGroupBuilder::new(&self.scene_layout)
+            .texture(input)
+            .build(device, "ssr_scene_bind_group");
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("SSR Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view:           output,
+                resolve_target: None,
+                ops:  wgpu::Operations {
+                    load:  wgpu::LoadOp::Load,
+                    store: true
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        crash_report::set_pipeline(&mut render_pass, &self.pipeline, "SSR Pipeline");
+        render_pass.set_bind_group(0, bind_group, &[]);
+        render_pass.set_bind_group(1, &scene_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
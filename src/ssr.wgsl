@@ -0,0 +1,213 @@
+// Screen-space reflections: rays are marched through the depth buffer and pick up the finished
+// scene wherever they hit. Rays that leave the screen or pass behind everything fall back on the
+// reflection probe. Needs `probe.wgsl` prepended.
+
+struct SsrUniform {
+    view_proj:     mat4x4<f32>,
+    inv_view_proj: mat4x4<f32>,
+    eye:           vec4<f32>,
+    // World units a ray travels before giving up
+    max_distance:  f32,
+    // How far behind the depth buffer a ray can be and still count as hitting it
+    thickness:     f32,
+    roughness:     f32,
+    reflectivity:  f32,
+    steps:         u32,
+    reverse_z:     u32,
+}
+
+@group(0) @binding(0)
+var<uniform> ssr: SsrUniform;
+@group(0) @binding(1)
+var t_depth: texture_depth_2d;
+@group(0) @binding(2)
+var t_probe: texture_2d_array<f32>;
+@group(0) @binding(3)
+var s_linear: sampler;
+
+@group(1) @binding(0)
+var t_scene: texture_2d<f32>;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+}
+
+// One triangle that covers the screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+
+    return out;
+}
+
+fn depth_size() -> vec2<i32> {
+    return vec2<i32>(textureDimensions(t_depth));
+}
+
+fn load_depth(coord: vec2<i32>) -> f32 {
+    return textureLoad(t_depth, clamp(coord, vec2<i32>(0), depth_size() - 1), 0);
+}
+
+// Nothing was drawn there, only the clear color
+fn is_background(depth: f32) -> bool {
+    if (ssr.reverse_z != 0u) {
+        return depth <= 0.0;
+    }
+    return depth >= 1.0;
+}
+
+fn world_position(coord: vec2<i32>, depth: f32) -> vec3<f32> {
+    let uv    = (vec2<f32>(coord) + 0.5) / vec2<f32>(depth_size());
+    let world = ssr.inv_view_proj * vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+
+    return world.xyz / world.w;
+}
+
+// There's no normal buffer, so normals come from the positions of neighbouring pixels. The
+// smaller difference on each axis is used so silhouettes don't bend the normal.
+fn reconstruct_normal(coord: vec2<i32>, center: vec3<f32>) -> vec3<f32> {
+    let left  = world_position(coord - vec2<i32>(1, 0), load_depth(coord - vec2<i32>(1, 0)));
+    let right = world_position(coord + vec2<i32>(1, 0), load_depth(coord + vec2<i32>(1, 0)));
+    let up    = world_position(coord - vec2<i32>(0, 1), load_depth(coord - vec2<i32>(0, 1)));
+    let down  = world_position(coord + vec2<i32>(0, 1), load_depth(coord + vec2<i32>(0, 1)));
+
+    let dx = select(center - left, right - center, length(right - center) < length(center - left));
+    let dy = select(center - up, down - center, length(down - center) < length(center - up));
+
+    let normal = normalize(cross(dx, dy));
+
+    return select(-normal, normal, dot(normal, ssr.eye.xyz - center) > 0.0);
+}
+
+// Where a point lands on screen as (u, v, ndc distance from the center), with z above 1.0 when
+// it's off screen or behind the camera
+fn project(p: vec3<f32>) -> vec3<f32> {
+    let clip = ssr.view_proj * vec4<f32>(p, 1.0);
+    let ndc  = clip.xy / clip.w;
+
+    return vec3<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5, select(2.0, max(abs(ndc.x), abs(ndc.y)), clip.w > 0.0));
+}
+
+// How far a point is behind whatever the depth buffer holds at its pixel, negative in front of it
+// and for points that can't hit anything
+fn depth_behind(p: vec3<f32>) -> f32 {
+    let screen = project(p);
+
+    if (screen.z > 1.0) {
+        return -1.0e9;
+    }
+
+    let coord = vec2<i32>(screen.xy * vec2<f32>(depth_size()));
+    let depth = load_depth(coord);
+
+    if (is_background(depth)) {
+        return -1.0e9;
+    }
+
+    return distance(ssr.eye.xyz, p) - distance(ssr.eye.xyz, world_position(coord, depth));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let coord = vec2<i32>(in.clip_position.xy);
+    let scene = textureLoad(t_scene, coord, 0);
+    let depth = load_depth(coord);
+
+    if (is_background(depth)) {
+        return scene;
+    }
+
+    let position = world_position(coord, depth);
+    let normal   = reconstruct_normal(coord, position);
+    let view_dir = normalize(ssr.eye.xyz - position);
+    let ray      = reflect(-view_dir, normal);
+
+    // Starts a little off the surface so the ray doesn't hit the pixel it came from
+    let origin    = position + normal * 0.01;
+    let step_size = ssr.max_distance / f32(ssr.steps);
+
+    var hit      = false;
+    var previous = 0.0;
+    var t        = 0.0;
+
+    for (var i = 1u; i <= ssr.steps; i = i + 1u) {
+        t = step_size * f32(i);
+
+        let p = origin + ray * t;
+
+        if (project(p).z > 1.0) {
+            break;
+        }
+
+        let behind = depth_behind(p);
+
+        if (behind > 0.0 && behind < ssr.thickness) {
+            hit = true;
+            break;
+        }
+
+        previous = t;
+    }
+
+    // Narrow the hit down between the last step in front of the surface and the first behind it
+    if (hit) {
+        var near = previous;
+        var far  = t;
+
+        for (var i = 0; i < 5; i = i + 1) {
+            let mid = (near + far) * 0.5;
+
+            if (depth_behind(origin + ray * mid) > 0.0) {
+                far = mid;
+            } else {
+                near = mid;
+            }
+        }
+
+        t = far;
+    }
+
+    let screen = project(origin + ray * t);
+
+    // Rougher surfaces blur the reflection more, and more so the further the ray travelled, like
+    // a cone of rays spreading out from the surface
+    let radius  = ssr.roughness * (0.002 + 0.05 * t / ssr.max_distance);
+    var offsets = array<vec2<f32>, 8>(
+        vec2<f32>(-0.613, 0.617),
+        vec2<f32>(0.170, -0.040),
+        vec2<f32>(-0.299, 0.791),
+        vec2<f32>(0.645, 0.493),
+        vec2<f32>(-0.651, -0.717),
+        vec2<f32>(0.421, 0.027),
+        vec2<f32>(-0.817, -0.271),
+        vec2<f32>(-0.705, -0.668),
+    );
+
+    var traced = textureSampleLevel(t_scene, s_linear, screen.xy, 0.0).rgb;
+    for (var i = 0; i < 8; i = i + 1) {
+        traced = traced + textureSampleLevel(t_scene, s_linear, screen.xy + offsets[i] * radius, 0.0).rgb;
+    }
+    traced = traced / 9.0;
+
+    let probe_coords = probe_uv(ray);
+    let probe        = textureSampleLevel(t_probe, s_linear, probe_coords.xy, i32(probe_coords.z), 0.0).rgb;
+
+    // Hits fade out towards the edge of the screen and the end of the ray, so the fallback doesn't
+    // pop in where they stop
+    let edge       = 1.0 - smoothstep(0.8, 1.0, screen.z);
+    let travel     = 1.0 - smoothstep(0.7, 1.0, t / ssr.max_distance);
+    let confidence = select(0.0, edge * travel, hit);
+    let reflection = mix(probe, traced, confidence);
+
+    let f0      = ssr.reflectivity;
+    let fresnel = f0 + (1.0 - f0) * pow(1.0 - max(dot(normal, view_dir), 0.0), 5.0);
+
+    // Rough surfaces scatter their reflection, which dims it as well as blurring it
+    let strength = fresnel * (1.0 - 0.5 * ssr.roughness);
+
+    return vec4<f32>(mix(scene.rgb, reflection, strength), scene.a);
+}
@@ -0,0 +1,101 @@
+// What the headset backends have in common: the app's side of it, and placing the eyes the
+// headset tracks in the world. OpenXR is in xr.rs and WebXR in webxr.rs.
+//
+// Headsets report asymmetric fields of view, which `Camera` can't express, so each eye is drawn
+// with the smallest symmetric field of view around the real one. How the extra is cut off again is
+// up to the backend.
+
+use cgmath::prelude::*;
+
+use crate::{camera::Camera, scene::Scene};
+
+// What an app shows in the headset
+pub trait XrScene: Scene {
+    // Where the player stands and which way they face. Only the camera's eye, its heading around
+    // the vertical and its clip planes are used, the headset decides the rest.
+    fn xr_camera(&self) -> &Camera;
+
+    // Called with a camera between the eyes each frame, e.g. to have the window follow the
+    // headset
+    fn head_moved(&mut self, _head: &Camera) {}
+}
+
+// Where the headset's tracking space is in the world: its origin at the app camera's eye, turned
+// to the camera's heading
+#[derive(Debug, Copy, Clone)]
+pub struct TrackingSpace {
+    pub origin:   cgmath::Point3<f32>,
+    pub rotation: cgmath::Quaternion<f32>,
+}
+
+impl TrackingSpace {
+    pub fn new(camera: &Camera) -> Self {
+        let forward = camera.target - camera.eye;
+
+        Self {
+            origin:   camera.eye,
+            rotation: cgmath::Quaternion::from_angle_y(cgmath::Rad((-forward.x).atan2(-forward.z))),
+        }
+    }
+
+    pub fn point(&self, position: cgmath::Point3<f32>) -> cgmath::Point3<f32> {
+        self.origin + self.rotation.rotate_vector(position.to_vec())
+    }
+
+    pub fn orientation(&self, orientation: cgmath::Quaternion<f32>) -> cgmath::Quaternion<f32> {
+        self.rotation * orientation
+    }
+}
+
+// Tangents of the angles from an eye's view direction out to each edge of what it sees, all
+// positive when the edge is on that side
+#[derive(Debug, Copy, Clone)]
+pub struct EyeFov {
+    pub left:  f32,
+    pub right: f32,
+    pub up:    f32,
+    pub down:  f32,
+}
+
+impl EyeFov {
+    // Tangents of the half angles of the symmetric field of view around this one
+    pub fn symmetric(&self) -> (f32, f32) {
+        (self.left.max(self.right), self.up.max(self.down))
+    }
+}
+
+// The camera for an eye the headset placed at `position` facing `orientation`, both in the
+// tracking space, drawing the symmetric field of view around `fov`. Clip planes come from
+// `origin`, the app's camera.
+pub fn eye_camera(
+    space:       &TrackingSpace,
+    origin:      &Camera,
+    position:    cgmath::Point3<f32>,
+    orientation: cgmath::Quaternion<f32>,
+    fov:         &EyeFov,
+) -> Camera {
+    let orientation    = space.orientation(orientation);
+    let eye            = space.point(position);
+    let (tan_x, tan_y) = fov.symmetric();
+
+    Camera {
+        eye,
+        target:     eye + orientation.rotate_vector(-cgmath::Vector3::unit_z()),
+        up:         orientation.rotate_vector(cgmath::Vector3::unit_y()),
+        aspect:     tan_x / tan_y,
+        fovy:       cgmath::Deg::from(cgmath::Rad(tan_y.atan() * 2.0)).0,
+        znear:      origin.znear,
+        zfar:       origin.zfar,
+        depth_mode: origin.depth_mode,
+    }
+}
+
+// A camera between two eyes' cameras, for the head
+pub fn head_camera(left: &Camera, right: &Camera) -> Camera {
+    let mut head = left.clone();
+    let middle   = left.eye.midpoint(right.eye);
+
+    head.target += middle - head.eye;
+    head.eye     = middle;
+    head
+}
@@ -0,0 +1,195 @@
+// Skin, wax and leaves: light wraps round past the terminator tinted by the subsurface color, and
+// lights behind thin parts shine through them. A cheap stand-in for scattering under the surface
+// that needs no extra passes.
+
+// Vertex shader
+
+struct VertexInput {
+    @location(0) position:   vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) normal:     vec3<f32>,
+    // White where the mesh has no colors of its own
+    @location(3) color:      vec4<f32>,
+}
+
+struct VertexOutput {
+   // Invariant so the depth prepass and main pass produce identical depths
+   @builtin(position) @invariant clip_position: vec4<f32>,
+   @location(0) tex_coords:          vec2<f32>,
+   @location(1) world_normal:        vec3<f32>,
+   @location(2) world_position:      vec3<f32>,
+   @location(3) fade:                f32,
+   @location(4) color:               vec4<f32>,
+}
+
+@vertex
+fn vs_main(
+   model:    VertexInput,
+   instance: InstanceInput,
+) -> VertexOutput {
+    let model_matrix = instance_model_matrix(instance);
+
+    var out: VertexOutput;
+
+    out.tex_coords     = model.tex_coords;
+    out.color          = model.color;
+    out.fade           = instance.model_matrix_0.w;
+    out.world_normal   = normal_to_world(model_matrix, model.normal);
+    out.world_position = (model_matrix * vec4<f32>(model.position, 1.0)).xyz;
+    out.clip_position  = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
+
+    return out;
+}
+
+
+// Fragment shader
+
+@group(0) @binding(0)
+var t_diffuse: texture_2d<f32>;
+@group(0) @binding(1)
+var s_diffuse: sampler;
+
+struct MaterialParams {
+    tint:                    vec4<f32>,
+    emissive:                vec3<f32>,
+    emissive_intensity:      f32,
+    alpha_cutoff:            f32,
+    subsurface_wrap:         f32,
+    transmission:            f32,
+    transmission_power:      f32,
+    subsurface_color:        vec3<f32>,
+    transmission_distortion: f32,
+    specular:                f32,
+    roughness:               f32,
+    anisotropy:              f32,
+    anisotropy_rotation:     f32,
+    clearcoat:               f32,
+    clearcoat_roughness:     f32,
+    transmittance:           f32,
+    ior:                     f32,
+    thickness:               f32,
+    triplanar_scale:         f32,
+    triplanar_sharpness:     f32,
+    anisotropy_map:          u32,
+    detail_scale:            f32,
+    detail_distance:         f32,
+    detail_strength:         f32,
+    detail_maps:             u32,
+}
+
+@group(0) @binding(2)
+var<uniform> material: MaterialParams;
+@group(0) @binding(3)
+var t_emissive: texture_2d<f32>;
+// Part of every shading model's layout, but only the textured model reads them
+@group(0) @binding(4)
+var t_anisotropy: texture_2d<f32>;
+@group(0) @binding(7)
+var t_lightmap: texture_2d<f32>;
+
+// At the mesh's UVs, or projected along the world axes for triplanar materials
+fn material_texel(t: texture_2d<f32>, tex_coords: vec2<f32>, world_position: vec3<f32>, normal: vec3<f32>) -> vec4<f32> {
+#ifdef TRIPLANAR
+    return triplanar_sample(t, s_diffuse, world_position, normal, material.triplanar_scale, material.triplanar_sharpness);
+#else
+    return textureSample(t, s_diffuse, tex_coords);
+#endif
+}
+
+// The material's detail layer over a surface facing `normal`
+fn material_detail(tex_coords: vec2<f32>, world_position: vec3<f32>, normal: vec3<f32>) -> DetailLayer {
+    return detail_layer(
+        s_diffuse,
+        tex_coords,
+        world_position,
+        normal,
+        camera.view_position.xyz,
+        material.detail_scale,
+        material.detail_distance,
+        material.detail_strength,
+        material.detail_maps,
+    );
+}
+
+// Added after lighting and left unclamped, so bright emission can go past 1.0
+fn emission(tex_coords: vec2<f32>, world_position: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    return material_texel(t_emissive, tex_coords, world_position, normal).rgb * material.emissive * material.emissive_intensity;
+}
+
+// Cutout materials sharpen alpha to about a pixel wide around the cutoff, which alpha to coverage
+// turns into an antialiased edge, and are cut away where it reaches 0.0. Opaque materials have a
+// cutoff of 0.0 and keep their alpha.
+fn cutout(alpha: f32) -> f32 {
+    if (material.alpha_cutoff <= 0.0) {
+        return alpha;
+    }
+
+    let sharpened = clamp((alpha - material.alpha_cutoff) / max(fwidth(alpha), 0.0001) + 0.5, 0.0, 1.0);
+
+    if (sharpened <= 0.0) {
+        discard;
+    }
+
+    return sharpened;
+}
+
+// Diffuse light from a spot light that wraps past where the surface turns away from it, as light
+// scattered under the surface comes back out further round. The extra light takes the color of
+// what it scattered through. Spread wider, the same light is divided down so the total stays about
+// the same.
+fn wrapped_diffuse(n_dot_l: f32) -> vec3<f32> {
+    let wrap    = material.subsurface_wrap;
+    let lambert = max(n_dot_l, 0.0);
+    let wrapped = max((n_dot_l + wrap) / (1.0 + wrap), 0.0) / (1.0 + wrap);
+
+    return vec3<f32>(lambert) + (wrapped - lambert) * material.subsurface_color;
+}
+
+// Light from behind that made it through to the viewer's side, brightest looking straight into
+// the light through the surface. Distortion bends the light along the normal on its way through.
+fn transmitted(light_dir: vec3<f32>, normal: vec3<f32>, view_dir: vec3<f32>) -> vec3<f32> {
+    let through = normalize(light_dir + normal * material.transmission_distortion);
+    let amount  = pow(max(dot(view_dir, -through), 0.0), max(material.transmission_power, 1.0));
+
+    return material.subsurface_color * amount * material.transmission;
+}
+
+@fragment
+fn fs_main(in: VertexOutput, @builtin(front_facing) front_facing: bool) -> @location(0) vec4<f32> {
+    // Geometry behind a mirror is left out of its reflection
+    if (dot(camera.clip_plane, vec4<f32>(in.world_position, 1.0)) < 0.0) {
+        discard;
+    }
+
+    if (lod_dithered_out(in.clip_position.xy, in.fade)) {
+        discard;
+    }
+
+    // Back faces of two-sided materials are lit from their own side
+    let facing   = select(-1.0, 1.0, front_facing) * normalize(in.world_normal);
+    let detail   = material_detail(in.tex_coords, in.world_position, facing);
+    let albedo   = material_texel(t_diffuse, in.tex_coords, in.world_position, in.world_normal) * material.tint * in.color * vec4<f32>(detail.albedo, 1.0);
+    let alpha    = cutout(albedo.a);
+    let normal   = detail.normal;
+    let view_dir = normalize(camera.view_position.xyz - in.world_position);
+
+    if (lights.debug_view != 0u) {
+        return vec4<f32>(debug_surface(in.world_position, normal, albedo.rgb, material.roughness, material.specular), alpha);
+    }
+
+    // Area lights aren't wrapped or shone through, only spot lights are
+    var spot = rect_lighting(in.world_position, normal);
+
+    for (var i = 0u; i < lights.spot_count; i = i + 1u) {
+        let light     = lights.spots[i];
+        let light_dir = normalize(light.position_range.xyz - in.world_position);
+        let incoming  = spot_incoming(light, in.world_position);
+
+        spot = spot + incoming * (wrapped_diffuse(dot(normal, light_dir)) + transmitted(light_dir, normal, view_dir));
+    }
+
+    // Some of the environment behind comes through as well
+    let ambient = probe_lighting(in.world_position, normal) + probe_lighting(in.world_position, -normal) * material.subsurface_color * material.transmission * 0.5;
+
+    return vec4<f32>(albedo.rgb * (ambient + spot) + emission(in.tex_coords, in.world_position, in.world_normal), alpha);
+}
@@ -1,19 +1,23 @@
 use image::GenericImageView;
 use anyhow::*;
 
+use crate::gpu_stats::{self, Tracked};
+
 pub struct Texture {
     pub texture: wgpu::Texture,
     pub view:    wgpu::TextureView,
     pub sampler: wgpu::Sampler,
+    // Lists the texture in the resource inspector, see gpu_stats.rs
+    pub tracked: Tracked,
 }
 
 impl Texture {
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
 
-    pub fn create_depth_texture(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, label: &str) -> Self {
+    pub fn create_depth_texture(device: &wgpu::Device, width: u32, height: u32, label: &str) -> Self {
         let size = wgpu::Extent3d {
-            width:  config.width,
-            height: config.height,
+            width,
+            height,
             depth_or_array_layers: 1,
         };
         let desc = wgpu::TextureDescriptor {
@@ -26,9 +30,9 @@ impl Texture {
             usage:           wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
         };
 
-        let texture = device.create_texture(&desc);
-        let view    = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = device.create_sampler(
+        let (texture, tracked) = gpu_stats::create_texture(device, &desc);
+        let view               = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler            = device.create_sampler(
             &wgpu::SamplerDescriptor {
                 address_mode_u: wgpu::AddressMode::ClampToEdge,
                 address_mode_v: wgpu::AddressMode::ClampToEdge,
@@ -46,7 +50,8 @@ impl Texture {
         Self {
             texture,
             view,
-            sampler
+            sampler,
+            tracked,
         }
     }
 
@@ -61,31 +66,118 @@ impl Texture {
         Self::from_image(device, queue, &img, Some(label))
     }
 
+    // For data rather than colors, like direction maps, which mustn't be decoded from sRGB when
+    // sampled
+    pub fn from_linear_bytes(
+        device: &wgpu::Device,
+        queue:  &wgpu::Queue,
+        bytes:  &[u8],
+        label:  &str
+    ) -> Result<Self> {
+        let img = image::load_from_memory(bytes)?;
+
+        Self::from_linear_image(device, queue, &img, Some(label))
+    }
+
+    pub fn from_linear_image(
+        device: &wgpu::Device,
+        queue:  &wgpu::Queue,
+        img:    &image::DynamicImage,
+        label:  Option<&str>
+    ) -> Result<Self> {
+        Self::from_image_with_format(device, queue, img, label, wgpu::TextureFormat::Rgba8Unorm)
+    }
+
+    // Tone maps a Radiance HDR image down to an 8-bit texture that can be shown like any other
+    pub fn from_hdr_bytes(
+        device: &wgpu::Device,
+        queue:  &wgpu::Queue,
+        bytes:  &[u8],
+        label:  &str
+    ) -> Result<Self> {
+        let hdr = image::load_from_memory_with_format(bytes, image::ImageFormat::Hdr)?.to_rgba32f();
+        let ldr = image::RgbaImage::from_fn(hdr.width(), hdr.height(), |x, y| {
+            let p = hdr.get_pixel(x, y).0;
+            // Reinhard, then gamma encode since the texture is sampled as sRGB
+            let map = |c: f32| ((c / (1.0 + c)).clamp(0.0, 1.0).powf(1.0 / 2.2) * 255.0) as u8;
+
+            image::Rgba([map(p[0]), map(p[1]), map(p[2]), 255])
+        });
+
+        Self::from_image(device, queue, &image::DynamicImage::ImageRgba8(ldr), Some(label))
+    }
+
     pub fn from_image(
         device: &wgpu::Device,
         queue:  &wgpu::Queue,
         img:    &image::DynamicImage,
         label:  Option<&str>
     ) -> Result<Self> {
-        let rgba       = img.to_rgba8(); // JPEGs don't have an alpha channel so would panic for `as_rgba8()`
-        let dimensions = img.dimensions();
-        let size       = wgpu::Extent3d {
-            width:  dimensions.0,
-            height: dimensions.1,
+        Self::from_image_with_format(device, queue, img, label, wgpu::TextureFormat::Rgba8UnormSrgb)
+    }
+
+    // `format` is an 8 bit RGBA format
+    pub(crate) fn from_image_with_format(
+        device: &wgpu::Device,
+        queue:  &wgpu::Queue,
+        img:    &image::DynamicImage,
+        label:  Option<&str>,
+        format: wgpu::TextureFormat,
+    ) -> Result<Self> {
+        let (width, height) = img.dimensions();
+        let (format, data)  = Self::encode(device, img, format);
+
+        Ok(Self::from_data(device, queue, &data, width, height, format, label))
+    }
+
+    // What `from_image` uploads for `img` in `format`: BC7 blocks when it can be compressed, see
+    // transcode.rs, otherwise RGBA8 pixels. Kept apart so the result can be cached, see
+    // asset_cache.rs. `device` is only asked whether it supports BC formats.
+    #[allow(unused_variables)]
+    pub(crate) fn encode(device: &wgpu::Device, img: &image::DynamicImage, format: wgpu::TextureFormat) -> (wgpu::TextureFormat, Vec<u8>) {
+        let rgba = img.to_rgba8(); // JPEGs don't have an alpha channel so would panic for `as_rgba8()`
+
+        #[cfg(all(feature = "bc_compress", not(target_arch = "wasm32")))]
+        if let Some(compressed) = crate::transcode::compressed_format(device, format, rgba.width(), rgba.height()) {
+            return (compressed, crate::transcode::compress_bc7(&rgba));
+        }
+
+        (format, rgba.into_raw())
+    }
+
+    // One mip of `data` already in `format`, sampled the same way as `from_image`'s textures
+    pub(crate) fn from_data(
+        device: &wgpu::Device,
+        queue:  &wgpu::Queue,
+        data:   &[u8],
+        width:  u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        label:  Option<&str>,
+    ) -> Self {
+        let size = wgpu::Extent3d {
+            width,
+            height,
             depth_or_array_layers: 1,
         };
-        let texture = device.create_texture(
+
+        let (texture, tracked) = gpu_stats::create_texture(
+            device,
             &wgpu::TextureDescriptor {
                 label,
                 size,
                 mip_level_count: 1,
                 sample_count:    1,
                 dimension:       wgpu::TextureDimension::D2,
-                format:          wgpu::TextureFormat::Rgba8UnormSrgb,
+                format,
                 usage:           wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
             }
         );
 
+        // Rows are of blocks for compressed formats, e.g. 4x4 at 16 bytes each for BC7
+        let info                        = format.describe();
+        let (block_width, block_height) = (info.block_dimensions.0 as u32, info.block_dimensions.1 as u32);
+
         queue.write_texture(
             wgpu::ImageCopyTexture {
                 aspect:    wgpu::TextureAspect::All,
@@ -93,11 +185,11 @@ impl Texture {
                 mip_level: 0,
                 origin:    wgpu::Origin3d::ZERO,
             },
-            &rgba,
+            data,
             wgpu::ImageDataLayout {
                 offset:         0,
-                bytes_per_row:  std::num::NonZeroU32::new(4 * dimensions.0),
-                rows_per_image: std::num::NonZeroU32::new(dimensions.1),
+                bytes_per_row:  std::num::NonZeroU32::new(width / block_width * info.block_size as u32),
+                rows_per_image: std::num::NonZeroU32::new(height / block_height),
             },
             size
         );
@@ -115,6 +207,225 @@ impl Texture {
             }
         );
 
-        Ok(Self { texture, view, sampler })
+        Self { texture, view, sampler, tracked }
+    }
+
+    // One layer per image, all resized to the first image's size
+    pub fn array_from_images(
+        device: &wgpu::Device,
+        queue:  &wgpu::Queue,
+        images: &[&image::DynamicImage],
+        label:  Option<&str>
+    ) -> Result<Self> {
+        let (width, height) = images.first()
+            .ok_or_else(|| anyhow!("A texture array needs at least one image"))?
+            .dimensions();
+
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: images.len() as u32,
+        };
+        let (texture, tracked) = gpu_stats::create_texture(
+            device,
+            &wgpu::TextureDescriptor {
+                label,
+                size,
+                mip_level_count: 1,
+                sample_count:    1,
+                dimension:       wgpu::TextureDimension::D2,
+                format:          wgpu::TextureFormat::Rgba8UnormSrgb,
+                usage:           wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            }
+        );
+
+        for (layer, img) in images.iter().enumerate() {
+            let rgba = if img.dimensions() == (width, height) {
+                img.to_rgba8()
+            } else {
+                img.resize_exact(width, height, image::imageops::FilterType::Triangle).to_rgba8()
+            };
+
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    aspect:    wgpu::TextureAspect::All,
+                    texture:   &texture,
+                    mip_level: 0,
+                    origin:    wgpu::Origin3d { x: 0, y: 0, z: layer as u32 },
+                },
+                &rgba,
+                wgpu::ImageDataLayout {
+                    offset:         0,
+                    bytes_per_row:  std::num::NonZeroU32::new(4 * width),
+                    rows_per_image: std::num::NonZeroU32::new(height),
+                },
+                wgpu::Extent3d { width, height, depth_or_array_layers: 1 }
+            );
+        }
+
+        let view    = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(
+            &wgpu::SamplerDescriptor {
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                address_mode_w: wgpu::AddressMode::ClampToEdge,
+                mag_filter:     wgpu::FilterMode::Linear,
+                min_filter:     wgpu::FilterMode::Nearest,
+                mipmap_filter:  wgpu::FilterMode::Nearest,
+                ..Default::default()
+            }
+        );
+
+        Ok(Self { texture, view, sampler, tracked })
+    }
+
+    // A volume from raw texel data, laid out slice by slice
+    pub fn from_volume(
+        device:       &wgpu::Device,
+        queue:        &wgpu::Queue,
+        data:         &[u8],
+        size:         wgpu::Extent3d,
+        format:       wgpu::TextureFormat,
+        address_mode: wgpu::AddressMode,
+        label:        Option<&str>
+    ) -> Self {
+        let (texture, tracked) = create_volume(device, size, format, wgpu::TextureUsages::COPY_DST, label);
+        let bytes              = format.describe().block_size as u32;
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                aspect:    wgpu::TextureAspect::All,
+                texture:   &texture,
+                mip_level: 0,
+                origin:    wgpu::Origin3d::ZERO,
+            },
+            data,
+            wgpu::ImageDataLayout {
+                offset:         0,
+                bytes_per_row:  std::num::NonZeroU32::new(bytes * size.width),
+                rows_per_image: std::num::NonZeroU32::new(size.height),
+            },
+            size
+        );
+
+        Self::from_volume_texture(device, texture, tracked, address_mode)
+    }
+
+    // A volume with one slice per image, all resized to the first image's size. Linear RGBA, since
+    // volumes usually hold data rather than colors.
+    pub fn from_slices(
+        device:       &wgpu::Device,
+        queue:        &wgpu::Queue,
+        slices:       &[&image::DynamicImage],
+        address_mode: wgpu::AddressMode,
+        label:        Option<&str>
+    ) -> Result<Self> {
+        let (width, height) = slices.first()
+            .ok_or_else(|| anyhow!("A volume needs at least one slice"))?
+            .dimensions();
+
+        let data = slices.iter()
+            .flat_map(|img| {
+                if img.dimensions() == (width, height) {
+                    img.to_rgba8().into_raw()
+                } else {
+                    img.resize_exact(width, height, image::imageops::FilterType::Triangle).to_rgba8().into_raw()
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: slices.len() as u32,
+        };
+
+        Ok(Self::from_volume(device, queue, &data, size, wgpu::TextureFormat::Rgba8Unorm, address_mode, label))
+    }
+
+    // A volume filled in by a compute shader, e.g. for noise. `source` has to declare
+    // `@group(0) @binding(0) var volume: texture_storage_3d<FORMAT, write>;` and a `cs_main` with a
+    // workgroup size of (4, 4, 4). Not available on WebGL, which has no compute shaders.
+    pub fn bake_volume(
+        device:       &wgpu::Device,
+        queue:        &wgpu::Queue,
+        size:         wgpu::Extent3d,
+        format:       wgpu::TextureFormat,
+        source:       &str,
+        address_mode: wgpu::AddressMode,
+        label:        Option<&str>
+    ) -> Self {
+        let (texture, tracked) = create_volume(device, size, format, wgpu::TextureUsages::STORAGE_BINDING, label);
+        let view               = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let layout = crate::bind_group::BindGroupLayoutBuilder::new()
+            .storage_texture(wgpu::ShaderStages::COMPUTE, format, wgpu::StorageTextureAccess::WriteOnly, wgpu::TextureViewDimension::D3)
+            .build(device, "bake_volume_bind_group_layout");
+
+        let bind_group = crate::bind_group::BindGroupBuilder::new(&layout)
+            .texture(&view)
+            .build(device, "bake_volume_bind_group");
+
+        let pipeline = crate::compute::create_pipeline(device, &layout, source, "Bake Volume");
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Bake Volume Encoder"),
+        });
+
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Bake Volume Pass"),
+            });
+
+            crate::crash_report::set_compute_pipeline(&mut compute_pass, &pipeline, "Bake Volume");
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            compute_pass.dispatch_workgroups(
+                size.width.div_ceil(4),
+                size.height.div_ceil(4),
+                size.depth_or_array_layers.div_ceil(4),
+            );
+        }
+
+        queue.submit(std::iter::once(encoder.finish()));
+
+        Self::from_volume_texture(device, texture, tracked, address_mode)
+    }
+
+    fn from_volume_texture(device: &wgpu::Device, texture: wgpu::Texture, tracked: Tracked, address_mode: wgpu::AddressMode) -> Self {
+        let view    = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(
+            &wgpu::SamplerDescriptor {
+                address_mode_u: address_mode,
+                address_mode_v: address_mode,
+                address_mode_w: address_mode,
+                mag_filter:     wgpu::FilterMode::Linear,
+                min_filter:     wgpu::FilterMode::Linear,
+                mipmap_filter:  wgpu::FilterMode::Nearest,
+                ..Default::default()
+            }
+        );
+
+        Self { texture, view, sampler, tracked }
     }
 }
+
+fn create_volume(
+    device: &wgpu::Device,
+    size:   wgpu::Extent3d,
+    format: wgpu::TextureFormat,
+    usage:  wgpu::TextureUsages,
+    label:  Option<&str>
+) -> (wgpu::Texture, Tracked) {
+    gpu_stats::create_texture(device, &wgpu::TextureDescriptor {
+        label,
+        size,
+        mip_level_count: 1,
+        sample_count:    1,
+        dimension:       wgpu::TextureDimension::D3,
+        format,
+        usage:           wgpu::TextureUsages::TEXTURE_BINDING | usage,
+    })
+}
@@ -0,0 +1,331 @@
+// Streaming the detailed mips of big textures in and out, so only what's near the camera takes up
+// GPU memory. The small mips at the end of each chain stay resident. Each frame the app says
+// roughly how many pixels tall each texture is drawn with `request`, and `update` works out which
+// mip every texture should start from within the memory budget, on-screen textures first and then
+// the biggest.
+//
+// Images are read and their mips made on worker threads. A finished load becomes a new texture
+// holding the mips from its first level down, written a mip at a time with `write_texture`, which
+// `update` hands back for the app to swap in, e.g. with `Material::set_diffuse_texture` and then
+// `GpuContext::retire` for the old one. Textures keep whatever they were drawn with before until
+// their first load arrives. Dropping mips goes through a worker too, which reads the image again,
+// but only when the budget's exceeded. Native only.
+
+use std::sync::{mpsc, Arc, Mutex};
+
+use anyhow::{Context, Result};
+
+use crate::{camera::Camera, gpu_stats, renderer::GpuContext, resources, texture};
+
+// Mips this size and smaller are always resident
+const TAIL_SIZE:    u32 = 64;
+// Loads in progress at once, so a burst of requests doesn't queue up work that's stale by the
+// time it's done
+const MAX_PENDING:  usize = 4;
+const WORKER_COUNT: usize = 2;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct StreamedId(usize);
+
+struct StreamedTexture {
+    path:      String,
+    // Data rather than colors, see `Texture::from_linear_bytes`
+    linear:    bool,
+    width:     u32,
+    height:    u32,
+    mip_count: u32,
+    // Where the mips on the GPU start, None before the first load arrives
+    resident:  Option<u32>,
+    pending:   bool,
+    // From this frame's requests
+    pixels:    f32,
+    on_screen: bool,
+}
+
+impl StreamedTexture {
+    fn mip_size(&self, level: u32) -> (u32, u32) {
+        ((self.width >> level).max(1), (self.height >> level).max(1))
+    }
+
+    // The first mip small enough to always be resident
+    fn tail(&self) -> u32 {
+        (0..self.mip_count)
+            .find(|&level| {
+                let (width, height) = self.mip_size(level);
+
+                width.max(height) <= TAIL_SIZE
+            })
+            .unwrap_or(self.mip_count - 1)
+    }
+
+    // Bytes the chain from `first` down takes up
+    fn bytes(&self, first: u32) -> u64 {
+        (first..self.mip_count)
+            .map(|level| {
+                let (width, height) = self.mip_size(level);
+
+                width as u64 * height as u64 * 4
+            })
+            .sum()
+    }
+
+    // The mip whose texels come out about one to a pixel at the requested size
+    fn wanted(&self) -> u32 {
+        let texels = self.width.max(self.height) as f32 / self.pixels.max(1.0);
+
+        (texels.log2().floor().max(0.0) as u32).min(self.tail())
+    }
+}
+
+struct LoadJob {
+    id:    StreamedId,
+    path:  String,
+    first: u32,
+}
+
+struct LoadResult {
+    id:    StreamedId,
+    first: u32,
+    mips:  Result<Vec<image::RgbaImage>>,
+}
+
+pub struct TextureStreamer {
+    // GPU memory the streamed textures may take up, in bytes
+    pub budget: u64,
+    textures:   Vec<StreamedTexture>,
+    jobs:       mpsc::Sender<LoadJob>,
+    results:    mpsc::Receiver<LoadResult>,
+}
+
+impl TextureStreamer {
+    pub fn new(budget: u64) -> Self {
+        let (done, results) = mpsc::channel();
+
+        Self {
+            budget,
+            textures: Vec::new(),
+            jobs:     spawn_workers(done),
+            results,
+        }
+    }
+
+    // Streams the image at `file_name`, under `res/` like other resources. Only its header is
+    // read here, the smallest mips are loaded by the next `update`.
+    pub fn add(&mut self, file_name: &str, linear: bool) -> Result<StreamedId> {
+        let path            = resources::resolve_path(file_name).to_string_lossy().into_owned();
+        let (width, height) = image::image_dimensions(&path).with_context(|| format!("Couldn't read {}", file_name))?;
+
+        self.textures.push(StreamedTexture {
+            path,
+            linear,
+            width,
+            height,
+            mip_count: 32 - width.max(height).leading_zeros(),
+            resident:  None,
+            pending:   false,
+            pixels:    0.0,
+            on_screen: false,
+        });
+
+        Ok(StreamedId(self.textures.len() - 1))
+    }
+
+    // Says how many pixels across the texture is drawn this frame, e.g. from `projected_size`.
+    // Asking more than once a frame keeps the biggest.
+    pub fn request(&mut self, id: StreamedId, pixels: f32, on_screen: bool) {
+        let texture = &mut self.textures[id.0];
+
+        texture.pixels    = texture.pixels.max(pixels);
+        texture.on_screen = texture.on_screen || on_screen;
+    }
+
+    // GPU memory the resident mips take up
+    pub fn resident_bytes(&self) -> u64 {
+        self.textures.iter()
+            .filter_map(|texture| texture.resident.map(|first| texture.bytes(first)))
+            .sum()
+    }
+
+    // Starts loads for textures that should change, and returns the ones that finished to be
+    // swapped in
+    pub fn update(&mut self, ctx: &GpuContext) -> Vec<(StreamedId, texture::Texture)> {
+        let mut finished = Vec::new();
+
+        while let Ok(result) = self.results.try_recv() {
+            let texture = &mut self.textures[result.id.0];
+
+            texture.pending = false;
+
+            match result.mips {
+                Ok(mips) => {
+                    texture.resident = Some(result.first);
+                    finished.push((result.id, upload(ctx, texture, &mips)));
+                }
+                Err(e) => log::warn!("Couldn't stream {}: {:?}", texture.path, e),
+            }
+        }
+
+        let targets  = self.targets();
+        let over     = self.resident_bytes() > self.budget;
+        let mut busy = self.textures.iter().filter(|texture| texture.pending).count();
+
+        for (i, first) in targets {
+            if busy >= MAX_PENDING {
+                break;
+            }
+
+            let texture = &mut self.textures[i];
+            let load    = match texture.resident {
+                None           => true,
+                Some(resident) => first < resident || (over && first > resident),
+            };
+
+            if load && !texture.pending {
+                texture.pending = true;
+                busy += 1;
+
+                // Only fails once the workers are gone, which they don't do while this is alive
+                let _ = self.jobs.send(LoadJob { id: StreamedId(i), path: texture.path.clone(), first });
+            }
+        }
+
+        for texture in &mut self.textures {
+            texture.pixels    = 0.0;
+            texture.on_screen = false;
+        }
+
+        finished
+    }
+
+    // The first mip each texture should have, in priority order. Every tail fits before any
+    // detail is handed out.
+    fn targets(&self) -> Vec<(usize, u32)> {
+        let mut order = (0..self.textures.len()).collect::<Vec<_>>();
+
+        order.sort_by(|&a, &b| {
+            let (a, b) = (&self.textures[a], &self.textures[b]);
+
+            b.on_screen.cmp(&a.on_screen).then(b.pixels.total_cmp(&a.pixels))
+        });
+
+        let mut used = self.textures.iter().map(|texture| texture.bytes(texture.tail())).sum::<u64>();
+
+        order.into_iter()
+            .map(|i| {
+                let texture = &self.textures[i];
+                let tail    = texture.bytes(texture.tail());
+                let first   = (texture.wanted()..texture.tail())
+                    .find(|&level| used + texture.bytes(level) - tail <= self.budget)
+                    .unwrap_or(texture.tail());
+
+                used += texture.bytes(first) - tail;
+                (i, first)
+            })
+            .collect()
+    }
+}
+
+// Roughly how many pixels tall something `radius` across around `center` is drawn by `camera` on
+// a screen `screen_height` pixels tall
+pub fn projected_size(camera: &Camera, screen_height: f32, center: cgmath::Point3<f32>, radius: f32) -> f32 {
+    use cgmath::MetricSpace;
+
+    let distance = camera.eye.distance(center).max(camera.znear);
+    let half_fov = cgmath::Rad::from(cgmath::Deg(camera.fovy / 2.0)).0;
+
+    radius / (distance * half_fov.tan()) * screen_height
+}
+
+fn upload(ctx: &GpuContext, streamed: &StreamedTexture, mips: &[image::RgbaImage]) -> texture::Texture {
+    let (width, height)    = (mips[0].width(), mips[0].height());
+    let (texture, tracked) = gpu_stats::create_texture(&ctx.device, &wgpu::TextureDescriptor {
+        label:           Some(&streamed.path),
+        size:            wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        mip_level_count: mips.len() as u32,
+        sample_count:    1,
+        dimension:       wgpu::TextureDimension::D2,
+        format:          if streamed.linear { wgpu::TextureFormat::Rgba8Unorm } else { wgpu::TextureFormat::Rgba8UnormSrgb },
+        usage:           wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+    });
+
+    for (level, mip) in mips.iter().enumerate() {
+        ctx.queue.write_texture(
+            wgpu::ImageCopyTexture {
+                aspect:    wgpu::TextureAspect::All,
+                texture:   &texture,
+                mip_level: level as u32,
+                origin:    wgpu::Origin3d::ZERO,
+            },
+            mip,
+            wgpu::ImageDataLayout {
+                offset:         0,
+                bytes_per_row:  std::num::NonZeroU32::new(4 * mip.width()),
+                rows_per_image: std::num::NonZeroU32::new(mip.height()),
+            },
+            wgpu::Extent3d { width: mip.width(), height: mip.height(), depth_or_array_layers: 1 },
+        );
+    }
+
+    let view    = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let sampler = ctx.device.create_sampler(&wgpu::SamplerDescriptor {
+        address_mode_u: wgpu::AddressMode::Repeat,
+        address_mode_v: wgpu::AddressMode::Repeat,
+        mag_filter:     wgpu::FilterMode::Linear,
+        min_filter:     wgpu::FilterMode::Linear,
+        mipmap_filter:  wgpu::FilterMode::Linear,
+        ..Default::default()
+    });
+
+    texture::Texture { texture, view, sampler, tracked }
+}
+
+// Reads the image and halves it down to 1x1, keeping the mips from `first` on
+fn load_mips(path: &str, first: u32) -> Result<Vec<image::RgbaImage>> {
+    let mut mip   = image::open(path)?.to_rgba8();
+    let mut mips  = Vec::new();
+    let mut level = 0;
+
+    loop {
+        if level >= first {
+            mips.push(mip.clone());
+        }
+
+        if mip.width() == 1 && mip.height() == 1 {
+            return Ok(mips);
+        }
+
+        let (width, height) = ((mip.width() / 2).max(1), (mip.height() / 2).max(1));
+
+        mip    = image::imageops::resize(&mip, width, height, image::imageops::FilterType::Triangle);
+        level += 1;
+    }
+}
+
+// Workers take jobs in turn until the sender is dropped
+fn spawn_workers(done: mpsc::Sender<LoadResult>) -> mpsc::Sender<LoadJob> {
+    let (jobs, receiver) = mpsc::channel::<LoadJob>();
+    let receiver         = Arc::new(Mutex::new(receiver));
+
+    for i in 0..WORKER_COUNT {
+        let receiver = receiver.clone();
+        let done     = done.clone();
+
+        std::thread::Builder::new()
+            .name(format!("texture-streamer-{}", i))
+            .spawn(move || loop {
+                let job = match receiver.lock().unwrap().recv() {
+                    Ok(job) => job,
+                    Err(_)  => return,
+                };
+
+                let mips = load_mips(&job.path, job.first);
+
+                if done.send(LoadResult { id: job.id, first: job.first, mips }).is_err() {
+                    return;
+                }
+            })
+            .expect("Couldn't start a texture streaming thread");
+    }
+
+    jobs
+}
@@ -0,0 +1,161 @@
+// Renders a scene somewhere other than the window: into textures other passes sample, like
+// reflections and the minimap, or into an image for asset thumbnails and documentation figures.
+// Images wait on the GPU to be read back, so they aren't available on the web.
+
+use anyhow::*;
+
+use crate::{
+    camera::Camera,
+    pass::{self, RenderLayers},
+    renderer::GpuContext,
+    scene::Scene,
+    texture::Texture,
+};
+
+// Draws `scene` from `camera` into `view` and submits it straight away, so it has to happen before
+// the frame's own camera is uploaded
+pub fn render_scene(
+    ctx:        &GpuContext,
+    scene:      &dyn Scene,
+    camera:     &Camera,
+    clip_plane: Option<cgmath::Vector4<f32>>,
+    view:       &wgpu::TextureView,
+    depth:      &wgpu::TextureView,
+    label:      &str,
+) {
+    let mut encoder = ctx.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some(label),
+    });
+
+    {
+        let mut layers = RenderLayers::new();
+
+        scene.render_view(&ctx.queue, camera, clip_plane, &mut layers);
+        layers.execute(&mut encoder, view, depth, pass::CLEAR_COLOR, ctx.depth_mode);
+    }
+
+    ctx.queue.submit(std::iter::once(encoder.finish()));
+}
+
+// Keeps its color and depth targets between calls and only recreates them when the size changes
+pub struct ThumbnailRenderer {
+    color:    wgpu::Texture,
+    view:     wgpu::TextureView,
+    depth:    Texture,
+    readback: wgpu::Buffer,
+    size:     (u32, u32),
+}
+
+impl ThumbnailRenderer {
+    pub fn new(ctx: &GpuContext, width: u32, height: u32) -> Self {
+        let device = &ctx.device;
+
+        // The surface format, since that's what the scene's pipelines are built for
+        let color = device.create_texture(&wgpu::TextureDescriptor {
+            label:           Some("Thumbnail Target"),
+            size:            wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count:    1,
+            dimension:       wgpu::TextureDimension::D2,
+            format:          ctx.config.format,
+            usage:           wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        });
+
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label:              Some("Thumbnail Readback Buffer"),
+            size:               (padded_bytes_per_row(width) * height) as wgpu::BufferAddress,
+            usage:              wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            view:  color.create_view(&wgpu::TextureViewDescriptor::default()),
+            color,
+            depth: Texture::create_depth_texture(device, width, height, "thumbnail_depth_texture"),
+            readback,
+            size:  (width, height),
+        }
+    }
+
+    // Draws `scene` from `camera` at `size` and reads it back. The camera's aspect ratio is left
+    // as it is, so it should match `size`. Post effects aren't applied.
+    pub fn render_to_image(
+        &mut self,
+        ctx:    &GpuContext,
+        scene:  &dyn Scene,
+        camera: &Camera,
+        size:   (u32, u32),
+    ) -> Result<image::RgbaImage> {
+        if size != self.size {
+            *self = Self::new(ctx, size.0, size.1);
+        }
+
+        let device = &ctx.device;
+        let (width, height) = size;
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Thumbnail Encoder"),
+        });
+
+        {
+            let mut layers = RenderLayers::new();
+
+            scene.render_view(&ctx.queue, camera, None, &mut layers);
+            layers.execute(&mut encoder, &self.view, &self.depth.view, pass::CLEAR_COLOR, ctx.depth_mode);
+        }
+
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                aspect:    wgpu::TextureAspect::All,
+                texture:   &self.color,
+                mip_level: 0,
+                origin:    wgpu::Origin3d::ZERO,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &self.readback,
+                layout: wgpu::ImageDataLayout {
+                    offset:         0,
+                    bytes_per_row:  std::num::NonZeroU32::new(padded_bytes_per_row(width)),
+                    rows_per_image: std::num::NonZeroU32::new(height),
+                },
+            },
+            wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        );
+
+        ctx.queue.submit(std::iter::once(encoder.finish()));
+
+        let slice              = self.readback.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        slice.map_async(wgpu::MapMode::Read, move |result| sender.send(result).unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        receiver.recv()??;
+
+        let bgra = matches!(ctx.config.format, wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb);
+
+        // Rows are padded out to the copy alignment, which the image doesn't want
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+        {
+            let data = slice.get_mapped_range();
+
+            for row in data.chunks(padded_bytes_per_row(width) as usize) {
+                for texel in row[..(width * 4) as usize].chunks(4) {
+                    if bgra {
+                        pixels.extend([texel[2], texel[1], texel[0], texel[3]]);
+                    } else {
+                        pixels.extend_from_slice(texel);
+                    }
+                }
+            }
+        }
+        self.readback.unmap();
+
+        image::RgbaImage::from_raw(width, height, pixels).ok_or_else(|| anyhow!("Thumbnail readback was the wrong size"))
+    }
+}
+
+fn padded_bytes_per_row(width: u32) -> u32 {
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+    (width * 4).div_ceil(align) * align
+}
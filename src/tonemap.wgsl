@@ -0,0 +1,67 @@
+// Applies the adapted exposure, then compresses highlights with a fitted ACES curve
+
+struct TonemapParams {
+    // In stops
+    exposure:    f32,
+    // The target encodes to sRGB on write, so the input was linear and the output must be too
+    srgb_target: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: TonemapParams;
+@group(0) @binding(1)
+var t_scene: texture_2d<f32>;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+}
+
+// One triangle that covers the screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+
+    return out;
+}
+
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+    return select(pow((c + 0.055) / 1.055, vec3<f32>(2.4)), c / 12.92, c <= vec3<f32>(0.04045));
+}
+
+fn linear_to_srgb(c: vec3<f32>) -> vec3<f32> {
+    return select(1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055, c * 12.92, c <= vec3<f32>(0.0031308));
+}
+
+// Krzysztof Narkowicz's fit of the ACES filmic curve
+fn aces(x: vec3<f32>) -> vec3<f32> {
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+
+    return clamp((x * (a * x + b)) / (x * (c * x + d) + e), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let scene = textureLoad(t_scene, vec2<i32>(in.clip_position.xy), 0);
+
+    var color = scene.rgb;
+
+    if (params.srgb_target == 0u) {
+        color = srgb_to_linear(color);
+    }
+
+    color = aces(color * exp2(params.exposure));
+
+    if (params.srgb_target == 0u) {
+        color = linear_to_srgb(color);
+    }
+
+    return vec4<f32>(color, scene.a);
+}
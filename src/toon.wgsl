@@ -0,0 +1,174 @@
+// Vertex shader
+
+struct VertexInput {
+    @location(0) position:   vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) normal:     vec3<f32>,
+    // White where the mesh has no colors of its own
+    @location(3) color:      vec4<f32>,
+}
+
+struct VertexOutput {
+   // Same expression as shader.wgsl so the shared depth prepass lines up
+   @builtin(position) @invariant clip_position: vec4<f32>,
+   @location(0) tex_coords:          vec2<f32>,
+   @location(1) world_normal:        vec3<f32>,
+   @location(2) world_position:      vec3<f32>,
+   @location(3) fade:                f32,
+   @location(4) color:               vec4<f32>,
+}
+
+@vertex
+fn vs_main(
+   model:    VertexInput,
+   instance: InstanceInput,
+) -> VertexOutput {
+    let model_matrix = instance_model_matrix(instance);
+
+    var out: VertexOutput;
+
+    out.tex_coords     = model.tex_coords;
+    out.color          = model.color;
+    out.fade           = instance.model_matrix_0.w;
+    out.world_normal   = normal_to_world(model_matrix, model.normal);
+    out.world_position = (model_matrix * vec4<f32>(model.position, 1.0)).xyz;
+    out.clip_position  = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
+
+    return out;
+}
+
+
+// Fragment shader
+
+@group(0) @binding(0)
+var t_diffuse: texture_2d<f32>;
+@group(0) @binding(1)
+var s_diffuse: sampler;
+
+struct MaterialParams {
+    tint:                    vec4<f32>,
+    emissive:                vec3<f32>,
+    emissive_intensity:      f32,
+    alpha_cutoff:            f32,
+    subsurface_wrap:         f32,
+    transmission:            f32,
+    transmission_power:      f32,
+    subsurface_color:        vec3<f32>,
+    transmission_distortion: f32,
+    specular:                f32,
+    roughness:               f32,
+    anisotropy:              f32,
+    anisotropy_rotation:     f32,
+    clearcoat:               f32,
+    clearcoat_roughness:     f32,
+    transmittance:           f32,
+    ior:                     f32,
+    thickness:               f32,
+    triplanar_scale:         f32,
+    triplanar_sharpness:     f32,
+    anisotropy_map:          u32,
+    detail_scale:            f32,
+    detail_distance:         f32,
+    detail_strength:         f32,
+    detail_maps:             u32,
+}
+
+@group(0) @binding(2)
+var<uniform> material: MaterialParams;
+@group(0) @binding(3)
+var t_emissive: texture_2d<f32>;
+// Part of every shading model's layout, but only the textured model reads them
+@group(0) @binding(4)
+var t_anisotropy: texture_2d<f32>;
+@group(0) @binding(7)
+var t_lightmap: texture_2d<f32>;
+
+// At the mesh's UVs, or projected along the world axes for triplanar materials
+fn material_texel(t: texture_2d<f32>, tex_coords: vec2<f32>, world_position: vec3<f32>, normal: vec3<f32>) -> vec4<f32> {
+    if (material.triplanar_scale > 0.0) {
+        return triplanar_sample(t, s_diffuse, world_position, normal, material.triplanar_scale, material.triplanar_sharpness);
+    }
+
+    return textureSample(t, s_diffuse, tex_coords);
+}
+
+// The material's detail layer over a surface facing `normal`
+fn material_detail(tex_coords: vec2<f32>, world_position: vec3<f32>, normal: vec3<f32>) -> DetailLayer {
+    return detail_layer(
+        s_diffuse,
+        tex_coords,
+        world_position,
+        normal,
+        camera.view_position.xyz,
+        material.detail_scale,
+        material.detail_distance,
+        material.detail_strength,
+        material.detail_maps,
+    );
+}
+
+// Added after lighting and left unclamped, so bright emission can go past 1.0
+fn emission(tex_coords: vec2<f32>, world_position: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    return material_texel(t_emissive, tex_coords, world_position, normal).rgb * material.emissive * material.emissive_intensity;
+}
+
+// Cutout materials sharpen alpha to about a pixel wide around the cutoff, which alpha to coverage
+// turns into an antialiased edge, and are cut away where it reaches 0.0. Opaque materials have a
+// cutoff of 0.0 and keep their alpha.
+fn cutout(alpha: f32) -> f32 {
+    if (material.alpha_cutoff <= 0.0) {
+        return alpha;
+    }
+
+    return clamp((alpha - material.alpha_cutoff) / max(fwidth(alpha), 0.0001) + 0.5, 0.0, 1.0);
+}
+
+// Whether a fragment is left out: behind a mirror's clip plane, dithered out between LODs, or
+// where a cutout is clear
+fn discarded(world_position: vec3<f32>, clip_position: vec4<f32>, fade: f32, alpha: f32) -> bool {
+    return dot(camera.clip_plane, vec4<f32>(world_position, 1.0)) < 0.0
+        || lod_dithered_out(clip_position.xy, fade)
+        || (material.alpha_cutoff > 0.0 && alpha <= 0.0);
+}
+
+@fragment
+fn fs_main(in: VertexOutput, @builtin(front_facing) front_facing: bool) -> @location(0) vec4<f32> {
+    // Back faces of two-sided materials are lit from their own side
+    let facing    = select(-1.0, 1.0, front_facing) * normalize(in.world_normal);
+    let detail    = material_detail(in.tex_coords, in.world_position, facing);
+    let albedo    = material_texel(t_diffuse, in.tex_coords, in.world_position, in.world_normal) * material.tint * in.color * vec4<f32>(detail.albedo, 1.0);
+    let alpha     = cutout(albedo.a);
+    let light_dir = normalize(vec3<f32>(0.5, 1.0, 0.3));
+    let normal    = detail.normal;
+    let view_dir  = normalize(camera.view_position.xyz - in.world_position);
+
+    var color: vec3<f32>;
+
+    if (lights.debug_view != 0u) {
+        color = debug_surface(in.world_position, normal, albedo.rgb, material.roughness, material.specular);
+    } else {
+        // Hard-edged bands instead of a smooth falloff
+        let bands   = 3.0;
+        let diffuse = ceil(max(dot(normal, light_dir), 0.0) * bands) / bands;
+
+        // Bright edge where the surface turns away from the viewer, only on the lit side
+        let rim = smoothstep(0.7, 0.72, 1.0 - max(dot(normal, view_dir), 0.0)) * step(0.0, dot(normal, light_dir));
+
+        // Spot lights are banded the same way
+        let spot = ceil(spot_lighting(in.world_position, normal) * bands) / bands;
+
+        // The banded key light is tinted by the environment
+        let ambient = 0.3;
+        let sky     = probe_lighting(in.world_position, normal);
+
+        color = albedo.rgb * ((ambient + diffuse * (1.0 - ambient)) * sky + spot) + vec3<f32>(rim * 0.4);
+        color = color + emission(in.tex_coords, in.world_position, in.world_normal);
+    }
+
+    // Only once everything's sampled, since samples after a discard aren't in uniform control flow
+    if (discarded(in.world_position, in.clip_position, in.fade, alpha)) {
+        discard;
+    }
+
+    return vec4<f32>(color, alpha);
+}
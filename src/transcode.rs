@@ -0,0 +1,42 @@
+// Compressing PNG and JPEG textures to BC7 as they're loaded, with the `bc_compress` feature, when
+// the GPU can sample BC formats. BC7 takes a quarter of the memory of RGBA8 at close to the same
+// quality, so art that wasn't compressed ahead of time doesn't fill up VRAM. The device asks for
+// BC support when the adapter has it, see `GpuContext::new`.
+//
+// Blocks are 4x4, so only images with sides a multiple of 4 are compressed, the rest load as they
+// always have. Encoding uses intel_tex's fastest settings, which still take a moment on big
+// images. Native only.
+
+use image::RgbaImage;
+
+// The BC7 format standing in for `format`, if textures of it can be compressed on this device
+pub fn compressed_format(device: &wgpu::Device, format: wgpu::TextureFormat, width: u32, height: u32) -> Option<wgpu::TextureFormat> {
+    if !device.features().contains(wgpu::Features::TEXTURE_COMPRESSION_BC) || width % 4 != 0 || height % 4 != 0 {
+        return None;
+    }
+
+    match format {
+        wgpu::TextureFormat::Rgba8UnormSrgb => Some(wgpu::TextureFormat::Bc7RgbaUnormSrgb),
+        wgpu::TextureFormat::Rgba8Unorm     => Some(wgpu::TextureFormat::Bc7RgbaUnorm),
+        _ => None,
+    }
+}
+
+// 16 bytes per 4x4 block, rows of blocks top to bottom
+pub fn compress_bc7(image: &RgbaImage) -> Vec<u8> {
+    let surface = intel_tex_2::RgbaSurface {
+        data:   image.as_raw(),
+        width:  image.width(),
+        height: image.height(),
+        stride: image.width() * 4,
+    };
+
+    // Opaque images get the whole block for color
+    let settings = if image.pixels().all(|pixel| pixel[3] == 255) {
+        intel_tex_2::bc7::opaque_ultra_fast_settings()
+    } else {
+        intel_tex_2::bc7::alpha_ultra_fast_settings()
+    };
+
+    intel_tex_2::bc7::compress_blocks(&settings, &surface)
+}
@@ -0,0 +1,259 @@
+// Glass and other transmissive materials. The opaque scene is copied just before the transparent
+// layer, then halved down a chain of mips with render passes, so it works without compute shaders.
+// Transmissive surfaces read the copy where their refracted view ray comes out, from blurrier
+// levels the rougher they are, as KHR_materials_transmission and KHR_materials_ior describe.
+//
+// Only what was drawn before the capture shows through, so glass doesn't see other glass.
+
+use crate::{
+    bind_group,
+    crash_report,
+    pass::SceneCapture,
+    renderer::{self, GpuContext},
+    shading::CullMode,
+    texture,
+};
+
+// Enough to blur the roughest glass without reading a handful of pixels. Also in glass.wgsl.
+const MAX_LEVELS: u32 = 6;
+
+pub struct Transmission {
+    // One per cull mode, in `CullMode::ALL` order
+    pipelines:    Vec<wgpu::RenderPipeline>,
+    blit:         wgpu::RenderPipeline,
+    blit_layout:  wgpu::BindGroupLayout,
+    glass_layout: wgpu::BindGroupLayout,
+    sampler:      wgpu::Sampler,
+    format:       wgpu::TextureFormat,
+    // Made in `resize` along with the copy, since they're all its size
+    levels:       Vec<wgpu::TextureView>,
+    // Each level's bind group reads the one above it, so the first level has none
+    level_groups: Vec<wgpu::BindGroup>,
+    glass_group:  wgpu::BindGroup,
+}
+
+impl Transmission {
+    pub fn glass_shader_source() -> &'static str {
+        concat!(include_str!("camera.wgsl"), include_str!("lights.wgsl"), include_str!("lod.wgsl"), include_str!("triplanar.wgsl"), include_str!("detail.wgsl"), include_str!("instance.wgsl"), include_str!("glass.wgsl"))
+    }
+
+    // Glass is drawn with the scene's material, camera and lights layouts at groups 0 to 2, and
+    // the scene's vertex layouts
+    pub fn new(
+        ctx:             &GpuContext,
+        material_layout: &wgpu::BindGroupLayout,
+        camera_layout:   &wgpu::BindGroupLayout,
+        lights_layout:   &wgpu::BindGroupLayout,
+        vertex_layouts:  &[wgpu::VertexBufferLayout],
+    ) -> Self {
+        let device = &ctx.device;
+        let format = ctx.config.format;
+
+        let blit_layout = bind_group::BindGroupLayoutBuilder::new()
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .sampler(wgpu::ShaderStages::FRAGMENT)
+            .build(device, "transmission_blit_bind_group_layout");
+
+        let glass_layout = bind_group::BindGroupLayoutBuilder::new()
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .sampler(wgpu::ShaderStages::FRAGMENT)
+            .build(device, "transmission_bind_group_layout");
+
+        // Bilinear filtering at half size averages each 2x2 block, which is all the blur needs
+        let blit_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Transmission Blit Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("upscale.wgsl").into()),
+        });
+
+        let blit_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Transmission Blit Pipeline Layout"),
+            bind_group_layouts:   &[&blit_layout],
+            push_constant_ranges: &[],
+        });
+
+        let blit = renderer::create_render_pipeline_with_raster(
+            device,
+            &blit_pipeline_layout,
+            format,
+            None,
+            &[],
+            &blit_shader,
+            renderer::RasterState { cull_mode: None, ..Default::default() },
+            "Transmission Blit Pipeline",
+        );
+
+        let glass_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Glass Shader"),
+            source: wgpu::ShaderSource::Wgsl(Self::glass_shader_source().into()),
+        });
+
+        let glass_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Glass Pipeline Layout"),
+            bind_group_layouts:   &[material_layout, camera_layout, lights_layout, &glass_layout],
+            push_constant_ranges: &[],
+        });
+
+        // Tested against the opaque scene's depth without adding to it, so glass behind glass
+        // still draws
+        let pipelines = CullMode::ALL.iter()
+            .map(|cull| renderer::create_render_pipeline_with_raster(
+                device,
+                &glass_pipeline_layout,
+                format,
+                Some(renderer::depth_state(texture::Texture::DEPTH_FORMAT, ctx.depth_mode.compare(), false)),
+                vertex_layouts,
+                &glass_shader,
+                renderer::RasterState { cull_mode: cull.face(), ..Default::default() },
+                "Glass Pipeline",
+            ))
+            .collect();
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label:          Some("Transmission Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter:     wgpu::FilterMode::Linear,
+            min_filter:     wgpu::FilterMode::Linear,
+            mipmap_filter:  wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let (levels, level_groups, glass_group) = create_copy(
+            device,
+            format,
+            &blit_layout,
+            &glass_layout,
+            &sampler,
+            ctx.scene_target.width,
+            ctx.scene_target.height,
+        );
+
+        Self {
+            pipelines,
+            blit,
+            blit_layout,
+            glass_layout,
+            sampler,
+            format,
+            levels,
+            level_groups,
+            glass_group,
+        }
+    }
+
+    // Matches the copy to the scene target, which follows the render resolution
+    pub fn resize(&mut self, ctx: &GpuContext) {
+        let (levels, level_groups, glass_group) = create_copy(
+            &ctx.device,
+            self.format,
+            &self.blit_layout,
+            &self.glass_layout,
+            &self.sampler,
+            ctx.scene_target.width,
+            ctx.scene_target.height,
+        );
+
+        self.levels       = levels;
+        self.level_groups = level_groups;
+        self.glass_group  = glass_group;
+    }
+
+    pub fn pipeline(&self, cull: CullMode) -> &wgpu::RenderPipeline {
+        &self.pipelines[cull as usize]
+    }
+
+    // The copy, for group 3 of the glass pipelines
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.glass_group
+    }
+
+    fn blit(&self, encoder: &mut wgpu::CommandEncoder, group: &wgpu::BindGroup, output: &wgpu::TextureView) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Transmission Blit Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view:           output,
+                resolve_target: None,
+                ops:  wgpu::Operations {
+                    load:  wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: true
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        crash_report::set_pipeline(&mut render_pass, &self.blit, "Transmission Blit Pipeline");
+        render_pass.set_bind_group(0, group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+impl SceneCapture for Transmission {
+    fn capture(
+        &self,
+        device:  &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        scene:   &wgpu::TextureView,
+        _depth:  &wgpu::TextureView,
+    ) {
+        // Which view holds the scene isn't known ahead of time, so its bind group is made per frame
+        let scene_group = bind_group::BindGroupBuilder::new(&self.blit_layout)
+            .texture(scene)
+            .sampler(&self.sampler)
+            .build(device, "transmission_scene_bind_group");
+
+        self.blit(encoder, &scene_group, &self.levels[0]);
+
+        for (group, level) in self.level_groups.iter().zip(&self.levels[1..]) {
+            self.blit(encoder, group, level);
+        }
+    }
+}
+
+// The copy's levels, a bind group reading each level after the first from the one above it, and
+// the glass pipelines' bind group reading them all
+fn create_copy(
+    device:       &wgpu::Device,
+    format:       wgpu::TextureFormat,
+    blit_layout:  &wgpu::BindGroupLayout,
+    glass_layout: &wgpu::BindGroupLayout,
+    sampler:      &wgpu::Sampler,
+    width:        u32,
+    height:       u32,
+) -> (Vec<wgpu::TextureView>, Vec<wgpu::BindGroup>, wgpu::BindGroup) {
+    let width     = width.max(1);
+    let height    = height.max(1);
+    let mip_count = MAX_LEVELS.min(32 - width.max(height).leading_zeros());
+
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label:           Some("Transmission Texture"),
+        size:            wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        mip_level_count: mip_count,
+        sample_count:    1,
+        dimension:       wgpu::TextureDimension::D2,
+        format,
+        usage:           wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+    });
+
+    let levels = (0..mip_count)
+        .map(|level| texture.create_view(&wgpu::TextureViewDescriptor {
+            label:           Some("Transmission Level"),
+            base_mip_level:  level,
+            mip_level_count: std::num::NonZeroU32::new(1),
+            ..Default::default()
+        }))
+        .collect::<Vec<_>>();
+
+    let level_groups = levels[..levels.len() - 1].iter()
+        .map(|level| bind_group::BindGroupBuilder::new(blit_layout)
+            .texture(level)
+            .sampler(sampler)
+            .build(device, "transmission_level_bind_group"))
+        .collect();
+
+    let glass_group = bind_group::BindGroupBuilder::new(glass_layout)
+        .texture(&texture.create_view(&wgpu::TextureViewDescriptor::default()))
+        .sampler(sampler)
+        .build(device, "transmission_bind_group");
+
+    (levels, level_groups, glass_group)
+}
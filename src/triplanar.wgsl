@@ -0,0 +1,28 @@
+// Texturing by world position instead of UVs, for terrain and generated meshes that have none
+// worth using. Prepended to every shader that reads materials, just before it.
+
+// One projection. Material samplers clamp, so the coordinates are wrapped here, with gradients
+// taken before wrapping so the mip level doesn't jump at the seam.
+fn planar_sample(t: texture_2d<f32>, s: sampler, uv: vec2<f32>) -> vec4<f32> {
+    return textureSampleGrad(t, s, fract(uv), dpdx(uv), dpdy(uv));
+}
+
+// `t` projected along each world axis, repeating every 1 / `scale` units, and blended by how
+// squarely `normal` faces that axis. Raising the blend to `sharpness` narrows the band where
+// projections mix.
+fn triplanar_sample(
+    t:              texture_2d<f32>,
+    s:              sampler,
+    world_position: vec3<f32>,
+    normal:         vec3<f32>,
+    scale:          f32,
+    sharpness:      f32,
+) -> vec4<f32> {
+    let blend   = pow(abs(normal), vec3<f32>(max(sharpness, 1.0)));
+    let weights = blend / max(blend.x + blend.y + blend.z, 0.0001);
+    let p       = world_position * scale;
+
+    return planar_sample(t, s, p.zy) * weights.x
+        + planar_sample(t, s, p.xz) * weights.y
+        + planar_sample(t, s, p.xy) * weights.z;
+}
@@ -0,0 +1,472 @@
+// A small retained UI for HUDs: panels of flat color, images, nine-slice frames and text, each a
+// node that stays put until it's changed or removed. Nodes are laid out in reference pixels and
+// anchored to a corner, edge or the middle of the screen or of their parent, and `UiScale` decides
+// how reference pixels grow or shrink with the window, so a layout made for one resolution holds
+// at others. Only what changed is laid out again, and everything is drawn as instanced quads in
+// the UI layer, one draw per run of nodes showing the same image.
+//
+// The tree has no font rasterizer, so text comes from bitmap fonts: an image holding a grid of
+// equally sized glyphs in character order.
+
+use std::ops::Range;
+
+use image::GenericImageView;
+use learn_wgpu_derive::VertexLayout;
+
+use crate::{
+    bind_group,
+    crash_report,
+    gpu_stats,
+    pass::{Drawable, RenderLayer},
+    renderer::GpuContext,
+    texture::Texture,
+    uniform::{Uniform, UniformBuffer},
+};
+
+const INITIAL_CAPACITY: usize = 256;
+
+// Where a node sits in its parent, and the point of the node that sits there
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    // As fractions of the width and height from the top left
+    fn fraction(self) -> [f32; 2] {
+        match self {
+            Anchor::TopLeft     => [0.0, 0.0],
+            Anchor::Top         => [0.5, 0.0],
+            Anchor::TopRight    => [1.0, 0.0],
+            Anchor::Left        => [0.0, 0.5],
+            Anchor::Center      => [0.5, 0.5],
+            Anchor::Right       => [1.0, 0.5],
+            Anchor::BottomLeft  => [0.0, 1.0],
+            Anchor::Bottom      => [0.5, 1.0],
+            Anchor::BottomRight => [1.0, 1.0],
+        }
+    }
+}
+
+// How many pixels a reference pixel covers at a given window size
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum UiScale {
+    // One, whatever the window's size
+    Pixels,
+    // Grows with the window's height, matching pixels when it's `height` tall
+    Height(f32),
+    // The largest that fits a `width` by `height` layout inside the window
+    Fit { width: f32, height: f32 },
+}
+
+impl UiScale {
+    fn factor(self, screen: [f32; 2]) -> f32 {
+        match self {
+            UiScale::Pixels                => 1.0,
+            UiScale::Height(height)        => screen[1] / height,
+            UiScale::Fit { width, height } => (screen[0] / width).min(screen[1] / height),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct UiId(usize);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ImageId(usize);
+
+// A grid of equally sized glyphs, left to right and top to bottom, starting from `first`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BitmapFont {
+    pub image:   ImageId,
+    pub columns: u32,
+    pub rows:    u32,
+    pub first:   char,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UiKind {
+    Panel { color: [f32; 4] },
+    Image { image: ImageId, tint: [f32; 4] },
+    // The image's edges, `border` texels in from the left, top, right and bottom, keep their size
+    // in reference pixels around the node while the middle stretches to fill it
+    NineSlice { image: ImageId, border: [f32; 4], tint: [f32; 4] },
+    // Lines of `size` reference pixel tall glyphs from the node's top left, as wide as the font's
+    // glyphs are for that height
+    Text { font: BitmapFont, text: String, size: f32, color: [f32; 4] },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UiNode {
+    pub anchor:  Anchor,
+    // From the anchor, in reference pixels with y down
+    pub offset:  [f32; 2],
+    pub size:    [f32; 2],
+    // Anchored within the parent rather than the screen, and hidden with it
+    pub parent:  Option<UiId>,
+    pub visible: bool,
+    pub kind:    UiKind,
+}
+
+impl UiNode {
+    pub fn new(anchor: Anchor, offset: [f32; 2], size: [f32; 2], kind: UiKind) -> Self {
+        Self { anchor, offset, size, parent: None, visible: true, kind }
+    }
+
+    pub fn with_parent(mut self, parent: UiId) -> Self {
+        self.parent = Some(parent);
+        self
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+#[vertex(instance)]
+struct UiQuad {
+    #[location(0)]
+    rect:  [f32; 4],
+    #[location(1)]
+    uv:    [f32; 4],
+    #[location(2)]
+    color: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct UiUniform {
+    screen:   [f32; 2],
+    _padding: [f32; 2],
+}
+
+struct UiImage {
+    bind_group: wgpu::BindGroup,
+    size:       [f32; 2],
+}
+
+pub struct Ui {
+    scale:        UiScale,
+    // Removed nodes leave an empty slot so the ids of the rest stay the same
+    nodes:        Vec<Option<UiNode>>,
+    // The first is a single white texel, which panels are drawn with
+    images:       Vec<UiImage>,
+    pipeline:     wgpu::RenderPipeline,
+    image_layout: wgpu::BindGroupLayout,
+    uniform:      UniformBuffer<UiUniform>,
+    bind_group:   wgpu::BindGroup,
+    quad_buffer:  wgpu::Buffer,
+    capacity:     usize,
+    // The image each run of quads shows
+    batches:      Vec<(ImageId, Range<u32>)>,
+    // Laid out for this window size, and nothing's changed since
+    laid_out:     Option<[u32; 2]>,
+}
+
+impl Ui {
+    pub fn new(ctx: &GpuContext, scale: UiScale) -> Self {
+        let device  = &ctx.device;
+        let uniform = UniformBuffer::new(device, "UI Uniform Buffer");
+
+        let layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::VERTEX)
+            .build(device, "ui_bind_group_layout");
+
+        let image_layout = bind_group::BindGroupLayoutBuilder::new()
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .sampler(wgpu::ShaderStages::FRAGMENT)
+            .build(device, "ui_image_bind_group_layout");
+
+        let bind_group = bind_group::BindGroupBuilder::new(&layout)
+            .uniform(uniform.buffer())
+            .build(device, "ui_bind_group");
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("UI Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("ui.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("UI Pipeline Layout"),
+            bind_group_layouts:   &[&layout, &image_layout],
+            push_constant_ranges: &[],
+        });
+
+        // Blended over the finished frame. The UI layer has no depth attachment.
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label:    Some("UI Pipeline"),
+            layout:   Some(&pipeline_layout),
+            vertex:   wgpu::VertexState {
+                module:      &shader,
+                entry_point: "vs_main",
+                buffers:     &[UiQuad::layout()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module:      &shader,
+                entry_point: "fs_main",
+                targets:     &[Some(wgpu::ColorTargetState {
+                    format:     ctx.config.format,
+                    blend:      Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive:     wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample:   wgpu::MultisampleState::default(),
+            multiview:     None,
+        });
+
+        let mut ui = Self {
+            scale,
+            nodes:       Vec::new(),
+            images:      Vec::new(),
+            pipeline,
+            image_layout,
+            uniform,
+            bind_group,
+            quad_buffer: create_quad_buffer(device, INITIAL_CAPACITY),
+            capacity:    INITIAL_CAPACITY,
+            batches:     Vec::new(),
+            laid_out:    None,
+        };
+
+        let white = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba([255; 4])));
+        ui.add_image(ctx, &white, "ui_white_texture").expect("a 1x1 image always makes a texture");
+
+        ui
+    }
+
+    // Uploads `image` for nodes to show
+    pub fn add_image(&mut self, ctx: &GpuContext, image: &image::DynamicImage, label: &str) -> anyhow::Result<ImageId> {
+        let texture = Texture::from_image(&ctx.device, &ctx.queue, image, Some(label))?;
+
+        let bind_group = bind_group::BindGroupBuilder::new(&self.image_layout)
+            .texture(&texture.view)
+            .sampler(&texture.sampler)
+            .build(&ctx.device, label);
+
+        let (width, height) = image.dimensions();
+
+        self.images.push(UiImage { bind_group, size: [width as f32, height as f32] });
+
+        Ok(ImageId(self.images.len() - 1))
+    }
+
+    // Parents have to be added before their children
+    pub fn add(&mut self, node: UiNode) -> UiId {
+        self.nodes.push(Some(node));
+        self.laid_out = None;
+
+        UiId(self.nodes.len() - 1)
+    }
+
+    pub fn node(&self, id: UiId) -> Option<&UiNode> {
+        self.nodes.get(id.0).and_then(Option::as_ref)
+    }
+
+    // Lays everything out again on the next `prepare`, so only borrow nodes to change them
+    pub fn node_mut(&mut self, id: UiId) -> Option<&mut UiNode> {
+        self.laid_out = None;
+        self.nodes.get_mut(id.0).and_then(Option::as_mut)
+    }
+
+    // Children of a removed node are no longer shown
+    pub fn remove(&mut self, id: UiId) -> Option<UiNode> {
+        self.laid_out = None;
+        self.nodes.get_mut(id.0).and_then(Option::take)
+    }
+
+    // How big `text` is in reference pixels as a `UiKind::Text` of `size` in `font`
+    pub fn text_size(&self, font: &BitmapFont, text: &str, size: f32) -> [f32; 2] {
+        let texels  = self.images[font.image.0].size;
+        let width   = size * (texels[0] / font.columns as f32) / (texels[1] / font.rows as f32);
+        let columns = text.lines().map(|line| line.chars().count()).max().unwrap_or(0);
+
+        [width * columns as f32, size * text.lines().count() as f32]
+    }
+
+    pub fn set_scale(&mut self, scale: UiScale) {
+        self.scale    = scale;
+        self.laid_out = None;
+    }
+
+    // Lays the nodes out for the window's current size if it or they have changed since last time
+    pub fn prepare(&mut self, ctx: &GpuContext) {
+        let size = [ctx.size.width.max(1), ctx.size.height.max(1)];
+
+        if self.laid_out == Some(size) {
+            return;
+        }
+
+        let screen = [size[0] as f32, size[1] as f32];
+        let scale  = self.scale.factor(screen);
+
+        // Each node's rect in pixels as left, top, right and bottom, or None when it isn't shown.
+        // Parents come before their children so are always placed first.
+        let mut rects: Vec<Option<[f32; 4]>> = Vec::with_capacity(self.nodes.len());
+        let mut quads = Vec::new();
+
+        self.batches.clear();
+
+        for (i, node) in self.nodes.iter().enumerate() {
+            let parent = match node {
+                Some(UiNode { parent: Some(parent), .. }) if parent.0 < i => rects[parent.0],
+                Some(UiNode { parent: Some(_), .. })                     => None,
+                Some(_)                                                  => Some([0.0, 0.0, screen[0], screen[1]]),
+                None                                                     => None,
+            };
+
+            let (node, parent) = match (node, parent) {
+                (Some(node), Some(parent)) if node.visible => (node, parent),
+                _ => {
+                    rects.push(None);
+                    continue;
+                }
+            };
+
+            let anchor = node.anchor.fraction();
+            let size   = [node.size[0] * scale, node.size[1] * scale];
+            let left   = parent[0] + (parent[2] - parent[0]) * anchor[0] - size[0] * anchor[0] + node.offset[0] * scale;
+            let top    = parent[1] + (parent[3] - parent[1]) * anchor[1] - size[1] * anchor[1] + node.offset[1] * scale;
+
+            // Whole pixels keep edges and borders crisp
+            let rect = [left.round(), top.round(), (left + size[0]).round(), (top + size[1]).round()];
+
+            rects.push(Some(rect));
+
+            let start = quads.len() as u32;
+            let image = self.node_quads(node, rect, scale, &mut quads);
+
+            push_batch(&mut self.batches, image, start..quads.len() as u32);
+        }
+
+        if quads.len() > self.capacity {
+            self.capacity    = quads.len().next_power_of_two();
+            self.quad_buffer = create_quad_buffer(&ctx.device, self.capacity);
+        }
+
+        ctx.queue.write_buffer(&self.quad_buffer, 0, bytemuck::cast_slice(&quads));
+        self.uniform.write(&ctx.queue, &UiUniform { screen, _padding: [0.0; 2] });
+
+        self.laid_out = Some(size);
+    }
+
+    // Adds the quads showing `node` placed at `rect`, returning the image they show
+    fn node_quads(&self, node: &UiNode, rect: [f32; 4], scale: f32, quads: &mut Vec<UiQuad>) -> ImageId {
+        match &node.kind {
+            UiKind::Panel { color } => {
+                quads.push(UiQuad { rect, uv: [0.0, 0.0, 1.0, 1.0], color: *color });
+                ImageId(0)
+            }
+            UiKind::Image { image, tint } => {
+                quads.push(UiQuad { rect, uv: [0.0, 0.0, 1.0, 1.0], color: *tint });
+                *image
+            }
+            UiKind::NineSlice { image, border, tint } => {
+                let texels = self.images[image.0].size;
+
+                // Where the middle starts and ends across the node and across the image, clamped
+                // so opposite borders never overlap
+                let inset = |min: f32, max: f32, near: f32, far: f32| {
+                    let middle = ((min + max) * 0.5).round();
+                    ((min + near * scale).round().min(middle), (max - far * scale).round().max(middle))
+                };
+                let (x1, x2) = inset(rect[0], rect[2], border[0], border[2]);
+                let (y1, y2) = inset(rect[1], rect[3], border[1], border[3]);
+
+                let xs = [rect[0], x1, x2, rect[2]];
+                let ys = [rect[1], y1, y2, rect[3]];
+                let us = [0.0, border[0] / texels[0], 1.0 - border[2] / texels[0], 1.0];
+                let vs = [0.0, border[1] / texels[1], 1.0 - border[3] / texels[1], 1.0];
+
+                for row in 0..3 {
+                    for column in 0..3 {
+                        quads.push(UiQuad {
+                            rect:  [xs[column], ys[row], xs[column + 1], ys[row + 1]],
+                            uv:    [us[column], vs[row], us[column + 1], vs[row + 1]],
+                            color: *tint,
+                        });
+                    }
+                }
+
+                *image
+            }
+            UiKind::Text { font, text, size, color } => {
+                let texels = self.images[font.image.0].size;
+                let cell   = [texels[0] / font.columns as f32, texels[1] / font.rows as f32];
+                let height = size * scale;
+                let width  = height * cell[0] / cell[1];
+
+                for (line, characters) in text.lines().enumerate() {
+                    let top = (rect[1] + height * line as f32).round();
+
+                    for (column, character) in characters.chars().enumerate() {
+                        let index = (character as u32).wrapping_sub(font.first as u32);
+
+                        // Glyphs the font doesn't have are left as gaps
+                        if index >= font.columns * font.rows {
+                            continue;
+                        }
+
+                        let left = (rect[0] + width * column as f32).round();
+                        let u    = (index % font.columns) as f32 / font.columns as f32;
+                        let v    = (index / font.columns) as f32 / font.rows as f32;
+
+                        quads.push(UiQuad {
+                            rect:  [left, top, (left + width).round(), (top + height).round()],
+                            uv:    [u, v, u + 1.0 / font.columns as f32, v + 1.0 / font.rows as f32],
+                            color: *color,
+                        });
+                    }
+                }
+
+                font.image
+            }
+        }
+    }
+}
+
+// Extends the last batch when it shows the same image
+fn push_batch(batches: &mut Vec<(ImageId, Range<u32>)>, image: ImageId, quads: Range<u32>) {
+    if quads.is_empty() {
+        return;
+    }
+
+    match batches.last_mut() {
+        Some((last, range)) if *last == image && range.end == quads.start => range.end = quads.end,
+        _ => batches.push((image, quads)),
+    }
+}
+
+fn create_quad_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label:              Some("UI Quad Buffer"),
+        size:               (capacity * std::mem::size_of::<UiQuad>()) as wgpu::BufferAddress,
+        usage:              wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+impl Drawable for Ui {
+    fn draw<'a>(&'a self, layer: RenderLayer, render_pass: &mut wgpu::RenderPass<'a>) {
+        if layer != RenderLayer::Ui || self.batches.is_empty() {
+            return;
+        }
+
+        crash_report::set_pipeline(render_pass, &self.pipeline, "UI Pipeline");
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.quad_buffer.slice(..));
+
+        for (image, quads) in &self.batches {
+            render_pass.set_bind_group(1, &self.images[image.0].bind_group, &[]);
+            render_pass.draw(0..6, quads.clone());
+            gpu_stats::record_draw(6, quads.len() as u32);
+        }
+    }
+}
@@ -0,0 +1,53 @@
+// Textured quads for the UI, see ui.rs. Each instance is one quad in pixels from the top left of
+// the screen, showing part of an image tinted by its color.
+
+struct UiUniform {
+    screen: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> ui: UiUniform;
+
+@group(1) @binding(0)
+var t_image: texture_2d<f32>;
+@group(1) @binding(1)
+var s_image: sampler;
+
+struct QuadInput {
+    // Left, top, right and bottom in pixels
+    @location(0) rect:  vec4<f32>,
+    // The same edges in texture coordinates
+    @location(1) uv:    vec4<f32>,
+    @location(2) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv:                  vec2<f32>,
+    @location(1) color:               vec4<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, quad: QuadInput) -> VertexOutput {
+    // Two triangles covering the quad
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0), vec2<f32>(1.0, 0.0), vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 0.0), vec2<f32>(1.0, 1.0), vec2<f32>(0.0, 1.0),
+    );
+
+    let corner = corners[index];
+    let pixel  = mix(quad.rect.xy, quad.rect.zw, corner);
+
+    var out: VertexOutput;
+
+    out.uv            = mix(quad.uv.xy, quad.uv.zw, corner);
+    out.color         = quad.color;
+    out.clip_position = vec4<f32>(pixel.x / ui.screen.x * 2.0 - 1.0, 1.0 - pixel.y / ui.screen.y * 2.0, 0.0, 1.0);
+
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSampleLevel(t_image, s_image, in.uv, 0.0) * in.color;
+}
@@ -0,0 +1,47 @@
+// Typed uniform buffers. Structs shared with WGSL derive `Uniform`, which checks at compile time
+// that their fields line up with WGSL's layout rules, so a missing padding field fails the build
+// instead of silently shifting everything after it on the GPU.
+
+use std::marker::PhantomData;
+
+use crate::gpu_stats::{self, Tracked};
+
+pub use learn_wgpu_derive::Uniform;
+
+// Implemented by `#[derive(Uniform)]`, which is where the layout checks come from
+pub trait Uniform: bytemuck::Pod {
+    // What WGSL aligns the struct to, which decides where it can go in other structs and arrays
+    const ALIGN: usize;
+}
+
+// A buffer holding one `T`
+pub struct UniformBuffer<T: Uniform> {
+    buffer:   wgpu::Buffer,
+    _tracked: Tracked,
+    _marker:  PhantomData<T>,
+}
+
+impl<T: Uniform> UniformBuffer<T> {
+    // Zeroed until the first `write`
+    pub fn new(device: &wgpu::Device, label: &str) -> Self {
+        Self::with_contents(device, label, &T::zeroed())
+    }
+
+    pub fn with_contents(device: &wgpu::Device, label: &str, value: &T) -> Self {
+        let (buffer, tracked) = gpu_stats::create_buffer_init(device, &wgpu::util::BufferInitDescriptor {
+            label:    Some(label),
+            contents: bytemuck::bytes_of(value),
+            usage:    wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        Self { buffer, _tracked: tracked, _marker: PhantomData }
+    }
+
+    pub fn write(&self, queue: &wgpu::Queue, value: &T) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(value));
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+}
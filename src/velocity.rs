@@ -0,0 +1,288 @@
+// Screen-space motion of every opaque pixel since the last frame, for temporal antialiasing and
+// motion blur to reproject with. Casters are drawn in a pass of their own, like the shadows, so
+// the shading pipelines keep a single color target: each vertex goes through this frame's model
+// matrix and camera and last frame's, and the difference is written out.
+//
+// Velocity is in UV units, current minus previous, so subtracting it from a pixel's UV finds where
+// that surface was a frame ago. Pixels nothing was drawn to get the camera's motion alone, as if
+// they were at the far plane, and only whole instances move: morphs and vertex animation aren't
+// followed.
+
+use learn_wgpu_derive::VertexLayout;
+
+use cgmath::prelude::*;
+
+use crate::{
+    bind_group,
+    camera::{Camera, DepthMode},
+    crash_report,
+    model::InstanceRaw,
+    pass::{Drawable, RenderLayer},
+    renderer::{self, GpuContext},
+    texture::Texture,
+    uniform::{Uniform, UniformBuffer},
+};
+
+pub const VELOCITY_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg16Float;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct MotionUniform {
+    view_proj:          [[f32; 4]; 4],
+    previous_view_proj: [[f32; 4]; 4],
+    inv_view_proj:      [[f32; 4]; 4],
+    far_depth:          f32,
+    _padding:           [f32; 3],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct VelocityDebug {
+    full_speed: f32,
+    _padding:   [f32; 3],
+}
+
+// An instance's model matrix from the last frame, bound next to its `InstanceRaw`. Instances that
+// can't move can bind the same buffer for both.
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+#[vertex(instance)]
+pub struct PreviousInstanceRaw {
+    #[location(10)]
+    pub model: [[f32; 4]; 4],
+}
+
+// Anything that draws into the velocity buffer. The pipeline and the cameras at @group(0) are set
+// beforehand, and vertex buffers are laid out as in `velocity.wgsl`: the mesh at 0, this frame's
+// instances at 1 and last frame's at 2.
+pub trait MotionCaster {
+    fn draw_motion<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>);
+}
+
+pub struct VelocityBuffer {
+    pub view:           wgpu::TextureView,
+    // Depth of the casters, for telling what's in front of what
+    pub depth:          Texture,
+    // Speed in UV units a frame that the debug view shows at full brightness
+    pub full_speed:     f32,
+    pipeline:           wgpu::RenderPipeline,
+    background:         wgpu::RenderPipeline,
+    motion:             UniformBuffer<MotionUniform>,
+    motion_group:       wgpu::BindGroup,
+    debug:              UniformBuffer<VelocityDebug>,
+    debug_pipeline:     wgpu::RenderPipeline,
+    debug_layout:       wgpu::BindGroupLayout,
+    debug_group:        wgpu::BindGroup,
+    size:               (u32, u32),
+    // Of the camera as it was last prepared, None until the first frame
+    previous_view_proj: Option<cgmath::Matrix4<f32>>,
+}
+
+impl VelocityBuffer {
+    // `mesh_layout` is how the casters' meshes are stored, only the position at location 0 is read
+    pub fn new(ctx: &GpuContext, mesh_layout: wgpu::VertexBufferLayout) -> Self {
+        let device = &ctx.device;
+        let size   = ctx.render_size();
+        let size   = (size.width.max(1), size.height.max(1));
+
+        let motion_layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT)
+            .build(device, "velocity_bind_group_layout");
+
+        let motion = UniformBuffer::new(device, "Velocity Motion Buffer");
+
+        let motion_group = bind_group::BindGroupBuilder::new(&motion_layout)
+            .uniform(motion.buffer())
+            .build(device, "velocity_bind_group");
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Velocity Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("velocity.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Velocity Pipeline Layout"),
+            bind_group_layouts:   &[&motion_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = renderer::create_render_pipeline(
+            device,
+            &pipeline_layout,
+            VELOCITY_FORMAT,
+            Some(renderer::depth_state(Texture::DEPTH_FORMAT, ctx.depth_mode.compare(), true)),
+            &[mesh_layout, InstanceRaw::layout(), PreviousInstanceRaw::layout()],
+            &shader,
+            "Velocity Pipeline",
+        );
+
+        let background_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Velocity Background Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("velocity_background.wgsl").into()),
+        });
+
+        // Leaves depth alone so the casters after it all pass
+        let background = renderer::create_render_pipeline(
+            device,
+            &pipeline_layout,
+            VELOCITY_FORMAT,
+            Some(renderer::depth_state(Texture::DEPTH_FORMAT, wgpu::CompareFunction::Always, false)),
+            &[],
+            &background_shader,
+            "Velocity Background Pipeline",
+        );
+
+        let debug_layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::FRAGMENT)
+            .unfilterable_texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .build(device, "velocity_debug_bind_group_layout");
+
+        let debug_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Velocity Debug Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("velocity_debug.wgsl").into()),
+        });
+
+        let debug_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Velocity Debug Pipeline Layout"),
+            bind_group_layouts:   &[&debug_layout],
+            push_constant_ranges: &[],
+        });
+
+        // Drawn over everything in the UI layer, which has no depth attachment
+        let debug_pipeline = renderer::create_render_pipeline(
+            device,
+            &debug_pipeline_layout,
+            ctx.config.format,
+            None,
+            &[],
+            &debug_shader,
+            "Velocity Debug Pipeline",
+        );
+
+        let debug       = UniformBuffer::new(device, "Velocity Debug Buffer");
+        let view        = create_target(device, size);
+        let debug_group = create_debug_group(device, &debug_layout, &debug, &view);
+
+        Self {
+            view,
+            depth:              Texture::create_depth_texture(device, size.0, size.1, "Velocity Depth Texture"),
+            full_speed:         0.02,
+            pipeline,
+            background,
+            motion,
+            motion_group,
+            debug,
+            debug_pipeline,
+            debug_layout,
+            debug_group,
+            size,
+            previous_view_proj: None,
+        }
+    }
+
+    // Called once a frame before `render`, with the camera the scene is drawn from. Follows the
+    // render resolution, and starts over without motion when it changes.
+    pub fn prepare(&mut self, ctx: &GpuContext, camera: &Camera) {
+        let size = ctx.render_size();
+        let size = (size.width.max(1), size.height.max(1));
+
+        if size != self.size {
+            self.view               = create_target(&ctx.device, size);
+            self.depth              = Texture::create_depth_texture(&ctx.device, size.0, size.1, "Velocity Depth Texture");
+            self.debug_group        = create_debug_group(&ctx.device, &self.debug_layout, &self.debug, &self.view);
+            self.size               = size;
+            self.previous_view_proj = None;
+        }
+
+        let view_proj = camera.build_view_projections_matrix();
+
+        self.motion.write(&ctx.queue, &MotionUniform {
+            view_proj:          view_proj.into(),
+            previous_view_proj: self.previous_view_proj.unwrap_or(view_proj).into(),
+            inv_view_proj:      view_proj.invert().unwrap_or_else(cgmath::Matrix4::identity).into(),
+            far_depth:          ctx.depth_mode.far_depth(),
+            _padding:           [0.0; 3],
+        });
+
+        self.debug.write(&ctx.queue, &VelocityDebug {
+            full_speed: self.full_speed.max(f32::EPSILON),
+            _padding:   [0.0; 3],
+        });
+
+        self.previous_view_proj = Some(view_proj);
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    // Forgets the last camera, so the next frame doesn't count everything since as motion
+    pub fn reset(&mut self) {
+        self.previous_view_proj = None;
+    }
+
+    // Clears the buffer and draws the casters' motion into it
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, caster: &dyn MotionCaster, depth_mode: DepthMode) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Velocity Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view:           &self.view,
+                resolve_target: None,
+                ops:  wgpu::Operations {
+                    load:  wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: true
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view:       &self.depth.view,
+                depth_ops:  Some(wgpu::Operations {
+                    load:  wgpu::LoadOp::Clear(depth_mode.clear_depth()),
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+
+        render_pass.set_bind_group(0, &self.motion_group, &[]);
+        crash_report::set_pipeline(&mut render_pass, &self.background, "Velocity Background Pipeline");
+        render_pass.draw(0..3, 0..1);
+
+        crash_report::set_pipeline(&mut render_pass, &self.pipeline, "Velocity Pipeline");
+        caster.draw_motion(&mut render_pass);
+    }
+}
+
+// The debug view, filling the screen
+impl Drawable for VelocityBuffer {
+    fn draw<'a>(&'a self, _layer: RenderLayer, render_pass: &mut wgpu::RenderPass<'a>) {
+        crash_report::set_pipeline(render_pass, &self.debug_pipeline, "Velocity Debug Pipeline");
+        render_pass.set_bind_group(0, &self.debug_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+fn create_target(device: &wgpu::Device, size: (u32, u32)) -> wgpu::TextureView {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label:           Some("Velocity Buffer"),
+        size:            wgpu::Extent3d { width: size.0, height: size.1, depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count:    1,
+        dimension:       wgpu::TextureDimension::D2,
+        format:          VELOCITY_FORMAT,
+        usage:           wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+    });
+
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+fn create_debug_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    debug:  &UniformBuffer<VelocityDebug>,
+    view:   &wgpu::TextureView,
+) -> wgpu::BindGroup {
+    bind_group::BindGroupBuilder::new(layout)
+        .uniform(debug.buffer())
+        .texture(view)
+        .build(device, "velocity_debug_bind_group")
+}
@@ -0,0 +1,71 @@
+// Draws how far each opaque pixel moved on screen since the last frame, from the model matrices and
+// camera of both frames
+
+struct MotionUniform {
+    view_proj:          mat4x4<f32>,
+    previous_view_proj: mat4x4<f32>,
+    inv_view_proj:      mat4x4<f32>,
+    far_depth:          f32,
+}
+
+@group(0) @binding(0)
+var<uniform> motion: MotionUniform;
+
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+};
+
+// The same instance a frame ago
+struct PreviousInstanceInput {
+    @location(10) model_matrix_0: vec4<f32>,
+    @location(11) model_matrix_1: vec4<f32>,
+    @location(12) model_matrix_2: vec4<f32>,
+    @location(13) model_matrix_3: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // Divided per fragment, since dividing per vertex wouldn't interpolate correctly
+    @location(0) current:             vec4<f32>,
+    @location(1) previous:            vec4<f32>,
+}
+
+@vertex
+fn vs_main(
+    @location(0) position: vec3<f32>,
+    instance:              InstanceInput,
+    previous:              PreviousInstanceInput,
+) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    let previous_model_matrix = mat4x4<f32>(
+        previous.model_matrix_0,
+        previous.model_matrix_1,
+        previous.model_matrix_2,
+        previous.model_matrix_3,
+    );
+
+    var out: VertexOutput;
+
+    out.current       = motion.view_proj * model_matrix * vec4<f32>(position, 1.0);
+    out.previous      = motion.previous_view_proj * previous_model_matrix * vec4<f32>(position, 1.0);
+    out.clip_position = out.current;
+
+    return out;
+}
+
+// In UV units, where y runs down the screen
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let current  = in.current.xy / in.current.w;
+    let previous = in.previous.xy / in.previous.w;
+
+    return vec4<f32>((current - previous) * vec2<f32>(0.5, -0.5), 0.0, 0.0);
+}
@@ -0,0 +1,39 @@
+// Fills the velocity buffer with the camera's own motion, taking every pixel to be at the far
+// plane. Drawn before the casters, which replace it wherever they cover.
+
+struct MotionUniform {
+    view_proj:          mat4x4<f32>,
+    previous_view_proj: mat4x4<f32>,
+    inv_view_proj:      mat4x4<f32>,
+    far_depth:          f32,
+}
+
+@group(0) @binding(0)
+var<uniform> motion: MotionUniform;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc:                 vec2<f32>,
+}
+
+// One triangle that covers the screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv  = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    let ndc = vec2<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+
+    var out: VertexOutput;
+
+    out.ndc           = ndc;
+    out.clip_position = vec4<f32>(ndc, motion.far_depth, 1.0);
+
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let world    = motion.inv_view_proj * vec4<f32>(in.ndc, motion.far_depth, 1.0);
+    let previous = motion.previous_view_proj * vec4<f32>(world.xyz / world.w, 1.0);
+
+    return vec4<f32>((in.ndc - previous.xy / previous.w) * vec2<f32>(0.5, -0.5), 0.0, 0.0);
+}
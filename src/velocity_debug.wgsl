@@ -0,0 +1,49 @@
+// Shows the velocity buffer over the screen: direction as hue and speed as brightness, black
+// where nothing moved
+
+struct VelocityDebug {
+    // Speed in UV units a frame that shows at full brightness
+    full_speed: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> debug: VelocityDebug;
+@group(0) @binding(1)
+var t_velocity: texture_2d<f32>;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv:                  vec2<f32>,
+}
+
+// One triangle that covers the screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+
+    out.uv            = uv;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+
+    return out;
+}
+
+fn hue(h: f32) -> vec3<f32> {
+    let k = fract(vec3<f32>(h) + vec3<f32>(0.0, 2.0 / 3.0, 1.0 / 3.0)) * 6.0 - 3.0;
+
+    return clamp(abs(k) - 1.0, vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Loaded rather than sampled, since the buffer may be smaller than the window
+    let size     = vec2<f32>(textureDimensions(t_velocity));
+    let coord    = min(vec2<i32>(in.uv * size), vec2<i32>(size) - 1);
+    let velocity = textureLoad(t_velocity, coord, 0).xy;
+
+    let speed = clamp(length(velocity) / debug.full_speed, 0.0, 1.0);
+    let angle = atan2(velocity.y, velocity.x) / 6.283185 + 0.5;
+
+    return vec4<f32>(hue(angle) * speed, 1.0);
+}
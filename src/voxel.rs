@@ -0,0 +1,524 @@
+// Block worlds split into chunks of `CHUNK_SIZE` cubed blocks, each meshed on its own. Meshing is
+// greedy: faces between solid blocks and air are merged into the largest rectangles of the same
+// texture, which cuts flat ground down to a handful of quads. It runs on worker threads, so edits
+// don't stall the frame, and an edit only remeshes its own chunk and any neighbour it borders.
+// Until the new mesh comes back the old one is drawn.
+//
+// Block textures are layers of one texture array, repeated across merged quads in the shader. The
+// web has no threads, so there chunks are meshed as they're submitted.
+
+use std::{
+    collections::HashMap,
+    sync::{mpsc, Arc},
+};
+
+use cgmath::prelude::*;
+use learn_wgpu_derive::VertexLayout;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    crash_report,
+    material_array::MaterialArray,
+    renderer::{self, GpuContext},
+    texture::Texture,
+};
+
+// Blocks along each side of a chunk
+pub const CHUNK_SIZE: i32 = 16;
+
+// A chunk and the blocks bordering it in its neighbours, which hide the faces against them
+const PADDED_SIZE: i32 = CHUNK_SIZE + 2;
+
+// What's at each position, an index into the block kinds given to `VoxelWorld::new` counting from
+// 1, with 0 for nothing
+pub type Block = u8;
+
+pub const AIR: Block = 0;
+
+// Which texture array layer each side of a block kind shows
+#[derive(Debug, Copy, Clone)]
+pub struct BlockFaces {
+    pub top:    u32,
+    pub side:   u32,
+    pub bottom: u32,
+}
+
+impl BlockFaces {
+    pub fn all(layer: u32) -> Self {
+        Self { top: layer, side: layer, bottom: layer }
+    }
+
+    fn layer(&self, axis: usize, positive: bool) -> u32 {
+        match (axis, positive) {
+            (1, true)  => self.top,
+            (1, false) => self.bottom,
+            _          => self.side,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+struct VoxelVertex {
+    #[location(0)]
+    position:   [f32; 3],
+    // In blocks, so textures repeat once per block across merged quads
+    #[location(1)]
+    tex_coords: [f32; 2],
+    #[location(2)]
+    normal:     [f32; 3],
+    #[location(3)]
+    layer:      u32,
+}
+
+// Where a ray first met a solid block, and the face it came in through
+#[derive(Debug, Copy, Clone)]
+pub struct VoxelHit {
+    pub block:    cgmath::Point3<i32>,
+    pub normal:   cgmath::Vector3<i32>,
+    pub distance: f32,
+}
+
+#[derive(Clone)]
+struct Chunk {
+    blocks: Vec<Block>,
+}
+
+impl Chunk {
+    fn new() -> Self {
+        Self { blocks: vec![AIR; (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize] }
+    }
+
+    fn index(local: [i32; 3]) -> usize {
+        (local[0] + (local[1] + local[2] * CHUNK_SIZE) * CHUNK_SIZE) as usize
+    }
+}
+
+struct ChunkMesh {
+    vertices:    wgpu::Buffer,
+    indices:     wgpu::Buffer,
+    index_count: u32,
+}
+
+struct ChunkEntry {
+    chunk:      Chunk,
+    // None until the first mesh comes back, and for chunks with nothing to show
+    mesh:       Option<ChunkMesh>,
+    // Counts edits, so meshes of what the chunk held before the last one are thrown away
+    generation: u32,
+    // Edited since it was last sent to be meshed
+    dirty:      bool,
+}
+
+struct MeshJob {
+    key:        [i32; 3],
+    generation: u32,
+    // Of the chunk's corner, in world space
+    origin:     [f32; 3],
+    // `PADDED_SIZE` cubed, x first
+    blocks:     Vec<Block>,
+    kinds:      Arc<Vec<BlockFaces>>,
+}
+
+struct MeshResult {
+    key:        [i32; 3],
+    generation: u32,
+    vertices:   Vec<VoxelVertex>,
+    indices:    Vec<u32>,
+}
+
+// Hands jobs to the worker threads and collects what they finish
+struct Mesher {
+    // None on the web, where jobs are meshed as they're submitted
+    jobs:    Option<mpsc::Sender<MeshJob>>,
+    done:    mpsc::Sender<MeshResult>,
+    results: mpsc::Receiver<MeshResult>,
+}
+
+impl Mesher {
+    fn new() -> Self {
+        let (done, results) = mpsc::channel();
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let jobs = Some(spawn_workers(done.clone()));
+        #[cfg(target_arch = "wasm32")]
+        let jobs = None;
+
+        Self { jobs, done, results }
+    }
+
+    fn submit(&self, job: MeshJob) {
+        // Sending only fails once the receiving end is gone, when nobody wants the mesh anyway
+        match &self.jobs {
+            Some(jobs) => { let _ = jobs.send(job); }
+            None       => { let _ = self.done.send(greedy_mesh(job)); }
+        }
+    }
+}
+
+// One worker per core beyond the one rendering, taking jobs in turn until the sender is dropped
+#[cfg(not(target_arch = "wasm32"))]
+fn spawn_workers(done: mpsc::Sender<MeshResult>) -> mpsc::Sender<MeshJob> {
+    let (jobs, receiver) = mpsc::channel::<MeshJob>();
+    let receiver         = Arc::new(std::sync::Mutex::new(receiver));
+    let count            = std::thread::available_parallelism().map_or(1, |n| n.get().saturating_sub(1)).max(1);
+
+    for i in 0..count {
+        let receiver = receiver.clone();
+        let done     = done.clone();
+
+        std::thread::Builder::new()
+            .name(format!("voxel-mesher-{}", i))
+            .spawn(move || loop {
+                // Only one worker waits on the queue at a time, letting go as soon as it has a job
+                let job = match receiver.lock().unwrap().recv() {
+                    Ok(job) => job,
+                    Err(_)  => return,
+                };
+
+                if done.send(greedy_mesh(job)).is_err() {
+                    return;
+                }
+            })
+            .expect("Couldn't start a voxel meshing thread");
+    }
+
+    jobs
+}
+
+pub struct VoxelWorld {
+    // Where block (0, 0, 0)'s lower corner is. Blocks are a unit across.
+    pub origin: cgmath::Point3<f32>,
+    chunks:     HashMap<[i32; 3], ChunkEntry>,
+    kinds:      Arc<Vec<BlockFaces>>,
+    mesher:     Mesher,
+    textures:   MaterialArray,
+    pipeline:   wgpu::RenderPipeline,
+}
+
+impl VoxelWorld {
+    pub fn shader_source() -> &'static str {
+        concat!(include_str!("camera.wgsl"), include_str!("lights.wgsl"), include_str!("voxel.wgsl"))
+    }
+
+    // Drawn with the scene's camera at group 1 and lights at group 2. `textures` become the layers
+    // `kinds` refer to, the first kind being block 1.
+    pub fn new(
+        ctx:           &GpuContext,
+        camera_layout: &wgpu::BindGroupLayout,
+        lights_layout: &wgpu::BindGroupLayout,
+        textures:      &[(&str, image::DynamicImage)],
+        kinds:         Vec<BlockFaces>,
+    ) -> anyhow::Result<Self> {
+        let device = &ctx.device;
+
+        assert!(kinds.len() <= Block::MAX as usize, "At most {} block kinds fit in a block", Block::MAX);
+
+        let textures_layout = MaterialArray::layout_builder().build(device, "voxel_bind_group_layout");
+        let textures        = MaterialArray::new(device, &ctx.queue, &textures_layout, textures, "voxel_bind_group")?;
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Voxel Shader"),
+            source: wgpu::ShaderSource::Wgsl(Self::shader_source().into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Voxel Pipeline Layout"),
+            bind_group_layouts:   &[&textures_layout, camera_layout, lights_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = renderer::create_render_pipeline(
+            device,
+            &pipeline_layout,
+            ctx.config.format,
+            Some(renderer::depth_state(Texture::DEPTH_FORMAT, ctx.depth_mode.compare(), true)),
+            &[VoxelVertex::layout()],
+            &shader,
+            "Voxel Pipeline",
+        );
+
+        Ok(Self {
+            origin:   cgmath::Point3::origin(),
+            chunks:   HashMap::new(),
+            kinds:    Arc::new(kinds),
+            mesher:   Mesher::new(),
+            textures,
+            pipeline,
+        })
+    }
+
+    pub fn block(&self, position: cgmath::Point3<i32>) -> Block {
+        let (key, local) = split(position);
+
+        self.chunks.get(&key).map_or(AIR, |entry| entry.chunk.blocks[Chunk::index(local)])
+    }
+
+    // Marks the block's chunk for remeshing, along with the neighbours whose faces it borders
+    pub fn set_block(&mut self, position: cgmath::Point3<i32>, block: Block) {
+        let (key, local) = split(position);
+
+        if self.block(position) == block {
+            return;
+        }
+
+        let entry = self.chunks.entry(key).or_insert_with(|| ChunkEntry {
+            chunk:      Chunk::new(),
+            mesh:       None,
+            generation: 0,
+            dirty:      false,
+        });
+
+        entry.chunk.blocks[Chunk::index(local)] = block;
+        entry.generation += 1;
+        entry.dirty       = true;
+
+        for (axis, &l) in local.iter().enumerate() {
+            let neighbour = match l {
+                0                        => -1,
+                l if l == CHUNK_SIZE - 1 => 1,
+                _                        => continue,
+            };
+
+            let mut key = key;
+            key[axis] += neighbour;
+
+            if let Some(entry) = self.chunks.get_mut(&key) {
+                entry.generation += 1;
+                entry.dirty       = true;
+            }
+        }
+    }
+
+    // The first solid block along the ray within `max_distance`, stepping block by block
+    pub fn raycast(&self, origin: cgmath::Point3<f32>, direction: cgmath::Vector3<f32>, max_distance: f32) -> Option<VoxelHit> {
+        let start     = origin - self.origin.to_vec();
+        let direction = direction.normalize();
+
+        let mut block = start.map(|c| c.floor() as i32);
+        let step      = direction.map(|c| if c > 0.0 { 1 } else { -1 });
+        let delta     = direction.map(|c| (1.0 / c).abs());
+
+        // Distance along the ray to the next boundary on each axis
+        let mut next = cgmath::Vector3::new(0.0, 0.0, 0.0);
+
+        for axis in 0..3 {
+            let edge = if step[axis] > 0 { block[axis] as f32 + 1.0 - start[axis] } else { start[axis] - block[axis] as f32 };
+
+            next[axis] = edge * delta[axis];
+        }
+
+        let mut normal   = cgmath::Vector3::zero();
+        let mut distance = 0.0;
+
+        while distance <= max_distance {
+            if self.block(block) != AIR {
+                return Some(VoxelHit { block, normal, distance });
+            }
+
+            let axis = if next.x < next.y && next.x < next.z { 0 } else if next.y < next.z { 1 } else { 2 };
+
+            distance     = next[axis];
+            block[axis] += step[axis];
+            next[axis]  += delta[axis];
+            normal       = cgmath::Vector3::zero();
+            normal[axis] = -step[axis];
+        }
+
+        None
+    }
+
+    // Sends edited chunks off to be meshed and uploads whatever meshes have come back
+    pub fn update(&mut self, device: &wgpu::Device) {
+        let dirty = self.chunks.iter()
+            .filter(|(_, entry)| entry.dirty)
+            .map(|(&key, _)| key)
+            .collect::<Vec<_>>();
+
+        for key in dirty {
+            let job = MeshJob {
+                key,
+                generation: self.chunks[&key].generation,
+                origin:     (self.origin + cgmath::Vector3::from(key).cast::<f32>().unwrap() * CHUNK_SIZE as f32).into(),
+                blocks:     self.padded_blocks(key),
+                kinds:      self.kinds.clone(),
+            };
+
+            self.chunks.get_mut(&key).unwrap().dirty = false;
+            self.mesher.submit(job);
+        }
+
+        while let Ok(result) = self.mesher.results.try_recv() {
+            let entry = match self.chunks.get_mut(&result.key) {
+                Some(entry) if entry.generation == result.generation => entry,
+                _ => continue,
+            };
+
+            entry.mesh = (!result.indices.is_empty()).then(|| ChunkMesh {
+                vertices:    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label:    Some("Voxel Vertex Buffer"),
+                    contents: bytemuck::cast_slice(&result.vertices),
+                    usage:    wgpu::BufferUsages::VERTEX,
+                }),
+                indices:     device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label:    Some("Voxel Index Buffer"),
+                    contents: bytemuck::cast_slice(&result.indices),
+                    usage:    wgpu::BufferUsages::INDEX,
+                }),
+                index_count: result.indices.len() as u32,
+            });
+        }
+    }
+
+    // Chunks with any blocks ever set in them
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    // Expects the lights to be bound at group 2 already, as the scene's draws leave them
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, camera_bind_group: &'a wgpu::BindGroup) {
+        crash_report::set_pipeline(render_pass, &self.pipeline, "Voxel Pipeline");
+        render_pass.set_bind_group(0, &self.textures.bind_group, &[]);
+        render_pass.set_bind_group(1, camera_bind_group, &[]);
+
+        for mesh in self.chunks.values().filter_map(|entry| entry.mesh.as_ref()) {
+            render_pass.set_vertex_buffer(0, mesh.vertices.slice(..));
+            render_pass.set_index_buffer(mesh.indices.slice(..), wgpu::IndexFormat::Uint32);
+            render_pass.draw_indexed(0..mesh.index_count, 0, 0..1);
+        }
+    }
+
+    // The chunk's blocks with a border of its neighbours' around them
+    fn padded_blocks(&self, key: [i32; 3]) -> Vec<Block> {
+        let corner = cgmath::Point3::from(key) * CHUNK_SIZE - cgmath::Vector3::new(1, 1, 1);
+
+        (0..PADDED_SIZE.pow(3))
+            .map(|i| {
+                let offset = cgmath::Vector3::new(i % PADDED_SIZE, i / PADDED_SIZE % PADDED_SIZE, i / (PADDED_SIZE * PADDED_SIZE));
+
+                self.block(corner + offset)
+            })
+            .collect()
+    }
+}
+
+// The chunk a block is in and where it is in that chunk
+fn split(position: cgmath::Point3<i32>) -> ([i32; 3], [i32; 3]) {
+    let position: [i32; 3] = position.into();
+
+    (position.map(|c| c.div_euclid(CHUNK_SIZE)), position.map(|c| c.rem_euclid(CHUNK_SIZE)))
+}
+
+// Faces between solid blocks and air, merged into rectangles of the same layer. Each slice of the
+// chunk across each axis is masked with the faces it shows in one direction, then rectangles are
+// grown from each unmerged face along the slice's rows and then down them.
+fn greedy_mesh(job: MeshJob) -> MeshResult {
+    let size  = CHUNK_SIZE as usize;
+    let block_at = |p: [i32; 3]| job.blocks[((p[0] + 1) + ((p[1] + 1) + (p[2] + 1) * PADDED_SIZE) * PADDED_SIZE) as usize];
+
+    let mut vertices = Vec::new();
+    let mut indices  = Vec::new();
+    let mut mask     = vec![None; size * size];
+
+    for axis in 0..3 {
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+
+        for positive in [false, true] {
+            let facing = if positive { 1 } else { -1 };
+
+            for slice in 0..CHUNK_SIZE {
+                for j in 0..CHUNK_SIZE {
+                    for i in 0..CHUNK_SIZE {
+                        let mut p = [0; 3];
+                        p[axis] = slice;
+                        p[u]    = i;
+                        p[v]    = j;
+
+                        let mut beyond = p;
+                        beyond[axis] += facing;
+
+                        let block = block_at(p);
+
+                        mask[(i + j * CHUNK_SIZE) as usize] = (block != AIR && block_at(beyond) == AIR)
+                            .then(|| job.kinds[block as usize - 1].layer(axis, positive));
+                    }
+                }
+
+                for j in 0..size {
+                    let mut i = 0;
+
+                    while i < size {
+                        let layer = match mask[i + j * size] {
+                            Some(layer) => layer,
+                            None        => { i += 1; continue; }
+                        };
+
+                        let width  = (i..size).take_while(|&x| mask[x + j * size] == Some(layer)).count();
+                        let height = (j..size)
+                            .take_while(|&y| (i..i + width).all(|x| mask[x + y * size] == Some(layer)))
+                            .count();
+
+                        for y in j..j + height {
+                            for x in i..i + width {
+                                mask[x + y * size] = None;
+                            }
+                        }
+
+                        let mut corner = [0.0; 3];
+                        corner[axis] = (slice + positive as i32) as f32;
+                        corner[u]    = i as f32;
+                        corner[v]    = j as f32;
+
+                        let mut du = [0.0; 3];
+                        du[u] = width as f32;
+
+                        let mut dv = [0.0; 3];
+                        dv[v] = height as f32;
+
+                        let mut normal = [0.0; 3];
+                        normal[axis] = facing as f32;
+
+                        let base = vertices.len() as u32;
+
+                        for (su, sv) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+                            let local = [0, 1, 2].map(|c| corner[c] + du[c] * su + dv[c] * sv);
+
+                            vertices.push(VoxelVertex {
+                                position:   [0, 1, 2].map(|c| job.origin[c] + local[c]),
+                                tex_coords: block_tex_coords(axis, local),
+                                normal,
+                                layer,
+                            });
+                        }
+
+                        // u cross v is along the axis, so the corners go counter-clockwise seen
+                        // from the positive side
+                        if positive {
+                            indices.extend([base, base + 1, base + 2, base, base + 2, base + 3]);
+                        } else {
+                            indices.extend([base, base + 2, base + 1, base, base + 3, base + 2]);
+                        }
+
+                        i += width;
+                    }
+                }
+            }
+        }
+    }
+
+    MeshResult {
+        key:        job.key,
+        generation: job.generation,
+        vertices,
+        indices,
+    }
+}
+
+// Tops and bottoms are textured in the xz plane, and sides with the texture upright
+fn block_tex_coords(axis: usize, local: [f32; 3]) -> [f32; 2] {
+    match axis {
+        0 => [local[2], -local[1]],
+        1 => [local[0], local[2]],
+        _ => [local[0], -local[1]],
+    }
+}
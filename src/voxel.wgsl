@@ -0,0 +1,57 @@
+// Voxel chunks, see voxel.rs. Vertices are already in world space, and merged quads repeat their
+// block's texture once per block. Gets `camera.wgsl` and `lights.wgsl` prepended.
+
+struct VertexInput {
+    @location(0) position:   vec3<f32>,
+    // In blocks
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) normal:     vec3<f32>,
+    @location(3) layer:      u32,
+}
+
+struct VertexOutput {
+   @builtin(position) clip_position: vec4<f32>,
+   @location(0) tex_coords:              vec2<f32>,
+   @location(1) world_normal:            vec3<f32>,
+   @location(2) world_position:          vec3<f32>,
+   @location(3) @interpolate(flat) layer: u32,
+}
+
+@vertex
+fn vs_main(model: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+
+    out.tex_coords     = model.tex_coords;
+    out.world_normal   = model.normal;
+    out.world_position = model.position;
+    out.layer          = model.layer;
+    out.clip_position  = camera.view_proj * vec4<f32>(model.position, 1.0);
+
+    return out;
+}
+
+
+// Fragment shader
+
+@group(0) @binding(0)
+var t_blocks: texture_2d_array<f32>;
+@group(0) @binding(1)
+var s_blocks: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Gradients from the unwrapped coordinates, so filtering doesn't see the jump where each
+    // block's repeat starts over. Taken before anything's discarded, which would leave them out of
+    // uniform control flow.
+    let albedo = textureSampleGrad(t_blocks, s_blocks, fract(in.tex_coords), i32(in.layer), dpdx(in.tex_coords), dpdy(in.tex_coords));
+
+    // Geometry behind a mirror is left out of its reflection
+    if (dot(camera.clip_plane, vec4<f32>(in.world_position, 1.0)) < 0.0) {
+        discard;
+    }
+
+    let normal = in.world_normal;
+    let spot   = spot_lighting(in.world_position, normal);
+
+    return vec4<f32>(albedo.rgb * (probe_lighting(in.world_position, normal) + spot), 1.0);
+}
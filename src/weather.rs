@@ -0,0 +1,308 @@
+// Rain and snow. Falling particles fill two boxes around the camera: a dense near one and a sparser
+// far one. The particles wrap around inside the boxes rather than being spawned, so the whole
+// layer is just one instanced draw. While it rains, surfaces get wetter, which darkens them and
+// makes them shinier through the lights' `wetness`, and drops run down the lens as a post effect.
+// They dry off again slowly once the rain stops.
+//
+// The scene has no sky or time of day of its own, only its ambient light. Cloud cover dims that
+// light, and `sky_light` says by how much.
+
+use std::time::Duration;
+
+use crate::{
+    bind_group,
+    crash_report,
+    pass::PostEffect,
+    renderer::{self, GpuContext},
+    texture::Texture,
+    uniform::{Uniform, UniformBuffer},
+};
+
+// Particles in each box at full intensity, which draws all of them
+const NEAR_PARTICLES: u32 = 6000;
+const FAR_PARTICLES:  u32 = 10000;
+
+// Widths of the boxes around the camera
+const NEAR_SIZE: f32 = 16.0;
+const FAR_SIZE:  f32 = 48.0;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Precipitation {
+    Clear,
+    Rain,
+    Snow,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct WeatherUniform {
+    velocity:   [f32; 3],
+    time:       f32,
+    near_size:  f32,
+    far_size:   f32,
+    near_count: u32,
+    kind:       u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct DropletParams {
+    time:      f32,
+    intensity: f32,
+    _padding:  [f32; 2],
+}
+
+pub struct Weather {
+    pub precipitation: Precipitation,
+    // From 0.0 to 1.0, the share of particles drawn, how fast things get wet and how much the
+    // clouds dim the sky
+    pub intensity:     f32,
+    // Blows rain and snow sideways as they fall
+    pub wind:          cgmath::Vector3<f32>,
+    // Wetness gained a second in full rain, and lost a second after it stops
+    pub soak_rate:     f32,
+    pub dry_rate:      f32,
+    pub droplets:      LensDroplets,
+    wetness:           f32,
+    time:              f32,
+    pipeline:          wgpu::RenderPipeline,
+    uniform:           UniformBuffer<WeatherUniform>,
+    bind_group:        wgpu::BindGroup,
+}
+
+impl Weather {
+    pub fn shader_source() -> &'static str {
+        concat!(include_str!("camera.wgsl"), include_str!("lights.wgsl"), include_str!("weather.wgsl"))
+    }
+
+    // Drawn with the scene's camera at group 1 and lights at group 2
+    pub fn new(ctx: &GpuContext, camera_layout: &wgpu::BindGroupLayout, lights_layout: &wgpu::BindGroupLayout) -> Self {
+        let device  = &ctx.device;
+        let uniform = UniformBuffer::new(device, "Weather Uniform Buffer");
+
+        let layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT)
+            .build(device, "weather_bind_group_layout");
+
+        let bind_group = bind_group::BindGroupBuilder::new(&layout)
+            .uniform(uniform.buffer())
+            .build(device, "weather_bind_group");
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Weather Shader"),
+            source: wgpu::ShaderSource::Wgsl(Self::shader_source().into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Weather Pipeline Layout"),
+            bind_group_layouts:   &[&layout, camera_layout, lights_layout],
+            push_constant_ranges: &[],
+        });
+
+        // Blended over the scene, tested against its depth without adding to it
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label:    Some("Weather Pipeline"),
+            layout:   Some(&pipeline_layout),
+            vertex:   wgpu::VertexState {
+                module:      &shader,
+                entry_point: "vs_main",
+                buffers:     &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module:      &shader,
+                entry_point: "fs_main",
+                targets:     &[Some(wgpu::ColorTargetState {
+                    format:     ctx.config.format,
+                    blend:      Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive:     wgpu::PrimitiveState::default(),
+            depth_stencil: Some(renderer::depth_state(Texture::DEPTH_FORMAT, ctx.depth_mode.compare(), false)),
+            multisample:   wgpu::MultisampleState::default(),
+            multiview:     None,
+        });
+
+        Self {
+            precipitation: Precipitation::Clear,
+            intensity:     1.0,
+            wind:          cgmath::Vector3::new(1.5, 0.0, 0.5),
+            soak_rate:     0.2,
+            dry_rate:      0.02,
+            droplets:      LensDroplets::new(ctx),
+            wetness:       0.0,
+            time:          0.0,
+            pipeline,
+            uniform,
+            bind_group,
+        }
+    }
+
+    pub fn update(&mut self, dt: Duration) {
+        let dt = dt.as_secs_f32();
+
+        self.time += dt;
+
+        if self.precipitation == Precipitation::Rain {
+            self.wetness = (self.wetness + self.soak_rate * self.intensity * dt).min(self.intensity);
+        } else {
+            self.wetness = (self.wetness - self.dry_rate * dt).max(0.0);
+        }
+    }
+
+    // How soaked surfaces are, for `Lights::wetness`
+    pub fn wetness(&self) -> f32 {
+        self.wetness
+    }
+
+    // How much of the sky's light gets through the clouds, from 1.0 under a clear sky
+    pub fn sky_light(&self) -> f32 {
+        match self.precipitation {
+            Precipitation::Clear => 1.0,
+            _                    => 1.0 - 0.6 * self.intensity.clamp(0.0, 1.0),
+        }
+    }
+
+    pub fn is_falling(&self) -> bool {
+        self.precipitation != Precipitation::Clear && self.intensity > 0.0
+    }
+
+    // Drops on the lens only come with rain
+    pub fn shows_droplets(&self) -> bool {
+        self.precipitation == Precipitation::Rain && self.intensity > 0.0
+    }
+
+    pub fn prepare(&self, queue: &wgpu::Queue) {
+        // Snow drifts down slowly and the wind pushes it around more
+        let (fall, kind, wind) = match self.precipitation {
+            Precipitation::Snow => (-1.0, 2, self.wind * 0.6),
+            _                   => (-9.0, 1, self.wind),
+        };
+
+        self.uniform.write(queue, &WeatherUniform {
+            velocity:   [wind.x, wind.y + fall, wind.z],
+            time:       self.time,
+            near_size:  NEAR_SIZE,
+            far_size:   FAR_SIZE,
+            near_count: NEAR_PARTICLES,
+            kind,
+        });
+
+        self.droplets.prepare(queue, self.time, self.intensity);
+    }
+
+    // Expects the lights to be bound at group 2 already, as the scene's draws leave them
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, camera_bind_group: &'a wgpu::BindGroup) {
+        if !self.is_falling() {
+            return;
+        }
+
+        let share = self.intensity.clamp(0.0, 1.0);
+        let near  = (NEAR_PARTICLES as f32 * share) as u32;
+        let far   = (FAR_PARTICLES as f32 * share) as u32;
+
+        crash_report::set_pipeline(render_pass, &self.pipeline, "Weather Pipeline");
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_bind_group(1, camera_bind_group, &[]);
+        render_pass.draw(0..6, 0..near);
+        render_pass.draw(0..6, NEAR_PARTICLES..NEAR_PARTICLES + far);
+    }
+}
+
+// Post effect of raindrops landing on the lens and running down it
+pub struct LensDroplets {
+    pipeline: wgpu::RenderPipeline,
+    layout:   wgpu::BindGroupLayout,
+    params:   UniformBuffer<DropletParams>,
+}
+
+impl LensDroplets {
+    fn new(ctx: &GpuContext) -> Self {
+        let device = &ctx.device;
+
+        let layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::FRAGMENT)
+            .texture(wgpu::ShaderStages::FRAGMENT, wgpu::TextureViewDimension::D2)
+            .build(device, "lens_droplets_bind_group_layout");
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Lens Droplets Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("weather_droplets.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Lens Droplets Pipeline Layout"),
+            bind_group_layouts:   &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label:    Some("Lens Droplets Pipeline"),
+            layout:   Some(&pipeline_layout),
+            vertex:   wgpu::VertexState {
+                module:      &shader,
+                entry_point: "vs_main",
+                buffers:     &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module:      &shader,
+                entry_point: "fs_main",
+                targets:     &[Some(wgpu::ColorTargetState {
+                    format:     ctx.config.format,
+                    blend:      Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive:     wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample:   wgpu::MultisampleState::default(),
+            multiview:     None,
+        });
+
+        Self {
+            pipeline,
+            layout,
+            params: UniformBuffer::new(device, "Lens Droplets Params Buffer"),
+        }
+    }
+
+    fn prepare(&self, queue: &wgpu::Queue, time: f32, intensity: f32) {
+        self.params.write(queue, &DropletParams {
+            time,
+            intensity: intensity.clamp(0.0, 1.0),
+            _padding:  [0.0; 2],
+        });
+    }
+}
+
+impl PostEffect for LensDroplets {
+    fn apply(
+        &self,
+        device:  &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        input:   &wgpu::TextureView,
+        output:  &wgpu::TextureView,
+    ) {
+        let bind_group = bind_group::BindGroupBuilder::new(&self.layout)
+            .uniform(self.params.buffer())
+            .texture(input)
+            .build(device, "lens_droplets_bind_group");
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Lens Droplets Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view:           output,
+                resolve_target: None,
+                ops:  wgpu::Operations {
+                    load:  wgpu::LoadOp::Load,
+                    store: true
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        crash_report::set_pipeline(&mut render_pass, &self.pipeline, "Lens Droplets Pipeline");
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
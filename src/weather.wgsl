@@ -0,0 +1,129 @@
+// Rain and snow, see weather.rs. Every particle is a quad made up in the vertex shader from its
+// instance index alone: a random spot in a box, carried along by the fall and wrapped around the
+// camera, so the weather always surrounds the camera without anything being spawned. Gets
+// `camera.wgsl` and `lights.wgsl` prepended.
+
+struct WeatherUniform {
+    velocity:   vec3<f32>,
+    time:       f32,
+    near_size:  f32,
+    far_size:   f32,
+    // Instances before it are in the near box, the rest in the far one
+    near_count: u32,
+    // 1 for rain, 2 for snow
+    kind:       u32,
+}
+
+@group(0) @binding(0)
+var<uniform> weather: WeatherUniform;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position:      vec3<f32>,
+    // From -1.0 to 1.0 across the quad
+    @location(1) corner:              vec2<f32>,
+    @location(2) fade:                f32,
+}
+
+fn weather_pcg(n: u32) -> u32 {
+    let state = n * 747796405u + 2891336453u;
+    let word  = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+
+    return (word >> 22u) ^ word;
+}
+
+// Three values in [0, 1) for a particle
+fn weather_hash(n: u32) -> vec3<f32> {
+    let a = weather_pcg(n);
+    let b = weather_pcg(a);
+    let c = weather_pcg(b);
+
+    return vec3<f32>(vec3<u32>(a, b, c)) / 4294967296.0;
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, @builtin(instance_index) instance: u32) -> VertexOutput {
+    let snow = weather.kind == 2u;
+    let size = select(weather.far_size, weather.near_size, instance < weather.near_count);
+    let seed = weather_hash(instance);
+
+    // Flakes fall at their own pace and sway from side to side as they go
+    var velocity = weather.velocity;
+    var sway     = vec3<f32>(0.0);
+
+    if (snow) {
+        let phase = weather.time * mix(1.0, 2.0, seed.y) + seed.z * 6.2831853;
+
+        velocity = velocity * mix(0.7, 1.3, seed.x);
+        sway     = vec3<f32>(sin(phase), 0.0, cos(phase * 0.7)) * 0.3;
+    }
+
+    let eye    = camera.view_position.xyz;
+    let moved  = seed * size + velocity * weather.time;
+    let local  = (fract((moved - eye) / size) - 0.5) * size;
+    let center = eye + local + sway;
+
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>( 1.0, -1.0),
+        vec2<f32>( 1.0,  1.0),
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>( 1.0,  1.0),
+        vec2<f32>(-1.0,  1.0),
+    );
+
+    let corner   = corners[index];
+    let view_dir = normalize(eye - center);
+
+    var right: vec3<f32>;
+    var up:    vec3<f32>;
+
+    if (snow) {
+        // Flakes face the camera
+        let side = cross(vec3<f32>(0.0, 1.0, 0.0), view_dir);
+
+        right = side / max(length(side), 0.0001) * 0.03;
+        up    = cross(view_dir, right);
+    } else {
+        // Drops are streaks along their fall, turned to face the camera as far as they can
+        let fall = normalize(velocity);
+        let side = cross(fall, view_dir);
+
+        right = side / max(length(side), 0.0001) * 0.006;
+        up    = fall * 0.25;
+    }
+
+    // Faded out towards the sides of the box, where they wrap around, and right in front of the
+    // camera
+    let edge = max(abs(local.x), max(abs(local.y), abs(local.z))) / (size * 0.5);
+
+    var out: VertexOutput;
+
+    out.corner         = corner;
+    out.fade           = (1.0 - smoothstep(0.7, 1.0, edge)) * smoothstep(0.2, 0.6, length(local));
+    out.world_position = center + right * corner.x + up * corner.y;
+    out.clip_position  = camera.view_proj * vec4<f32>(out.world_position, 1.0);
+
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    if (dot(camera.clip_plane, vec4<f32>(in.world_position, 1.0)) < 0.0) {
+        discard;
+    }
+
+    // Round soft flakes, or thin drops that taper off at both ends
+    var color = vec3<f32>(0.45, 0.5, 0.55);
+    var alpha = (1.0 - abs(in.corner.x)) * (1.0 - in.corner.y * in.corner.y) * 0.35;
+
+    if (weather.kind == 2u) {
+        color = vec3<f32>(0.95);
+        alpha = (1.0 - smoothstep(0.4, 1.0, length(in.corner))) * 0.9;
+    }
+
+    let view_dir = normalize(camera.view_position.xyz - in.world_position);
+    let light    = probe_lighting(in.world_position, vec3<f32>(0.0, 1.0, 0.0)) + spot_lighting(in.world_position, view_dir);
+
+    return vec4<f32>(color * light, alpha * in.fade);
+}
@@ -0,0 +1,76 @@
+// Raindrops on the lens, see weather.rs. The screen is split into cells, each with a drop that
+// lands somewhere random, slides a little way down and dries up before the next one lands. Drops
+// bend the scene behind them like tiny lenses.
+
+struct DropletParams {
+    time:      f32,
+    // Fewer cells get a drop in lighter rain
+    intensity: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: DropletParams;
+@group(0) @binding(1)
+var t_scene: texture_2d<f32>;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv:                  vec2<f32>,
+}
+
+// One triangle that covers the screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+
+    out.uv            = uv;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+
+    return out;
+}
+
+fn droplet_hash(cell: vec2<f32>) -> vec3<f32> {
+    let p = vec3<f32>(dot(cell, vec2<f32>(127.1, 311.7)), dot(cell, vec2<f32>(269.5, 183.3)), dot(cell, vec2<f32>(419.2, 371.9)));
+
+    return fract(sin(p) * 43758.5453);
+}
+
+// How far a layer of drops `cells` high bends the view at `uv` in xy, and how much of the pixel
+// is under a drop in z
+fn droplet_layer(uv: vec2<f32>, aspect: f32, cells: f32, seed: f32) -> vec3<f32> {
+    let grid   = uv * vec2<f32>(aspect, 1.0) * cells;
+    let random = droplet_hash(floor(grid) + seed);
+
+    if (random.y > params.intensity) {
+        return vec3<f32>(0.0);
+    }
+
+    // Each drop lasts a few seconds, starting at its own time
+    let life   = fract(params.time / mix(2.0, 4.0, random.z) + random.x);
+    let center = vec2<f32>(mix(0.25, 0.75, random.x), mix(0.2, 0.45, random.z) + life * 0.3);
+    let radius = mix(0.12, 0.22, random.z) * (1.0 - life * 0.5);
+    let offset = (fract(grid) - center) / radius;
+    let dist   = length(offset);
+
+    if (dist > 1.0) {
+        return vec3<f32>(0.0);
+    }
+
+    let strength = 1.0 - life;
+
+    return vec3<f32>(-offset * 0.04 / cells, 1.0 - smoothstep(0.8, 1.0, dist)) * strength;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size   = vec2<f32>(textureDimensions(t_scene));
+    let aspect = size.x / size.y;
+    let drops  = droplet_layer(in.uv, aspect, 6.0, 0.0) + droplet_layer(in.uv, aspect, 11.0, 17.0);
+
+    let scene     = textureLoad(t_scene, vec2<i32>(in.uv * size), 0);
+    let refracted = textureLoad(t_scene, clamp(vec2<i32>((in.uv + drops.xy) * size), vec2<i32>(0), vec2<i32>(size) - 1), 0);
+
+    return vec4<f32>(mix(scene.rgb, refracted.rgb, clamp(drops.z, 0.0, 1.0)), 1.0);
+}
@@ -0,0 +1,417 @@
+// Headsets from the browser through WebXR, for the wasm build with `--features webxr`. web-sys only
+// has the WebXR bindings with `RUSTFLAGS=--cfg=web_sys_unstable_apis`.
+//
+// wgpu draws through WebGL2 on the web, so the session's layer is an XRWebGLLayer on wgpu's own
+// canvas context. Browsers only start a session from a user gesture, so with `RendererOptions::vr`
+// clicking the canvas starts one, and while it's presenting the session's frames take the place
+// of the window's. Each frame the eyes are drawn side by side into the canvas, the way split-screen
+// draws its views, then copied into the layer's framebuffer with a GL blit, since wgpu can't draw
+// into a framebuffer it didn't create.
+//
+// Each eye is drawn with the symmetric field of view around the headset's (see stereo.rs), so its
+// image is a bit bigger than its viewport, and the blit copies out just the part the headset's
+// projection covers. Controllers are tracked into `Input::xr_controller`.
+
+use std::{cell::RefCell, rc::Rc};
+
+use wasm_bindgen::{prelude::*, JsCast};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    GamepadButton, WebGl2RenderingContext as Gl, XrFrame, XrHandedness, XrReferenceSpace,
+    XrReferenceSpaceType, XrRenderStateInit, XrRigidTransform, XrSession, XrSessionInit,
+    XrSessionMode, XrView, XrWebGlLayer,
+};
+use winit::{
+    dpi::PhysicalSize,
+    event::{ElementState, Event, MouseButton, WindowEvent},
+    platform::web::WindowExtWebSys,
+};
+
+use crate::{
+    app::{App, Runner},
+    camera::Camera,
+    input::{Hand, Input, XrController},
+    pass::{self, RenderLayers, Viewport},
+    scene::Scene,
+    stereo::{self, EyeFov, TrackingSpace},
+};
+
+// The runner's WebXR state
+pub(crate) struct WebXr {
+    requested:  bool,
+    presenting: Option<Presenting>,
+}
+
+struct Presenting {
+    session:     XrSession,
+    space:       XrReferenceSpace,
+    gl:          Gl,
+    // What the canvas was before it was resized for the eyes, put back when the session ends
+    window_size: PhysicalSize<u32>,
+}
+
+// One of the headset's views this frame
+struct Eye {
+    view:     XrView,
+    fov:      EyeFov,
+    // Size of the symmetric image drawn for the eye
+    width:    u32,
+    height:   u32,
+    // Where the headset's projection sits in that image, in pixels from its bottom left
+    source:   [i32; 4],
+    // Where the eye goes in the layer's framebuffer, from its bottom left
+    viewport: [i32; 4],
+}
+
+impl WebXr {
+    pub(crate) fn new() -> Self {
+        Self {
+            requested:  false,
+            presenting: None,
+        }
+    }
+
+    pub(crate) fn is_presenting(&self) -> bool {
+        self.presenting.is_some()
+    }
+}
+
+// Starts a session when the canvas is clicked
+pub(crate) fn handle_event<A: App>(shared: &Rc<RefCell<Runner<A>>>, event: &Event<'_, ()>) {
+    if let Event::WindowEvent {
+        event: WindowEvent::MouseInput {
+            state:  ElementState::Pressed,
+            button: MouseButton::Left,
+            ..
+        },
+        ..
+    } = event {
+        if !A::renderer_options().vr || shared.borrow().web_xr.requested {
+            return;
+        }
+
+        let xr = match web_sys::window() {
+            Some(window) => window.navigator().xr(),
+            None         => return,
+        };
+
+        // Asked for here rather than in the future below, while the click still counts as a gesture
+        let mut init = XrSessionInit::new();
+        init.optional_features(&js_sys::Array::of1(&"local-floor".into()));
+
+        let request = xr.request_session_with_options(XrSessionMode::ImmersiveVr, &init);
+        let shared  = shared.clone();
+
+        shared.borrow_mut().web_xr.requested = true;
+
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Err(e) = start(&shared, request).await {
+                log::warn!("Couldn't start a WebXR session: {:?}", e);
+                shared.borrow_mut().web_xr.requested = false;
+            }
+        });
+    }
+}
+
+async fn start<A: App>(shared: &Rc<RefCell<Runner<A>>>, request: js_sys::Promise) -> Result<(), JsValue> {
+    let session: XrSession = JsFuture::from(request).await?.dyn_into()?;
+
+    // wgpu's context, which the layer has to share to see what wgpu drew
+    let gl: Gl = shared.borrow().ctx.window().canvas()
+        .get_context("webgl2")?
+        .ok_or("The canvas has no WebGL2 context")?
+        .dyn_into()?;
+
+    JsFuture::from(gl.make_xr_compatible()).await?;
+
+    let layer     = XrWebGlLayer::new_with_web_gl2_rendering_context(&session, &gl)?;
+    let mut state = XrRenderStateInit::new();
+
+    state.base_layer(Some(&layer));
+    session.update_render_state_with_state(&state);
+
+    // Floor level when the headset knows where the floor is, the starting head position otherwise
+    let space = match JsFuture::from(session.request_reference_space(XrReferenceSpaceType::LocalFloor)).await {
+        Ok(space) => space,
+        Err(_)    => JsFuture::from(session.request_reference_space(XrReferenceSpaceType::Local)).await?,
+    };
+
+    // Each frame asks for the next while the session runs
+    let callback: Rc<RefCell<Option<Closure<dyn FnMut(f64, XrFrame)>>>> = Rc::new(RefCell::new(None));
+    let next   = callback.clone();
+    let runner = shared.clone();
+
+    *callback.borrow_mut() = Some(Closure::wrap(Box::new(move |_time: f64, xr_frame: XrFrame| {
+        frame(&mut runner.borrow_mut(), &xr_frame);
+
+        if let Some(next) = next.borrow().as_ref() {
+            xr_frame.session().request_animation_frame(next.as_ref().unchecked_ref());
+        }
+    }) as Box<dyn FnMut(f64, XrFrame)>));
+
+    let runner = shared.clone();
+    let frames = callback.clone();
+    let on_end = Closure::once_into_js(move || {
+        frames.borrow_mut().take();
+        end(&mut runner.borrow_mut());
+    });
+
+    session.set_onend(Some(on_end.unchecked_ref()));
+    session.request_animation_frame(callback.borrow().as_ref().unwrap().as_ref().unchecked_ref());
+
+    let mut runner  = shared.borrow_mut();
+    let window_size = runner.ctx.size;
+
+    runner.web_xr.presenting = Some(Presenting {
+        session,
+        space: space.dyn_into()?,
+        gl,
+        window_size,
+    });
+
+    Ok(())
+}
+
+fn end<A: App>(runner: &mut Runner<A>) {
+    runner.web_xr.requested = false;
+
+    if let Some(presenting) = runner.web_xr.presenting.take() {
+        resize_canvas(runner, presenting.window_size);
+    }
+
+    for hand in [Hand::Left, Hand::Right] {
+        runner.input.set_xr_controller(hand, None);
+    }
+}
+
+// Sets the size the canvas draws at, leaving the size it's shown at on the page alone
+fn resize_canvas<A: App>(runner: &mut Runner<A>, size: PhysicalSize<u32>) {
+    let canvas = runner.ctx.window().canvas();
+
+    canvas.set_width(size.width);
+    canvas.set_height(size.height);
+    runner.ctx.resize(size);
+    runner.app.resize(&runner.ctx);
+}
+
+fn frame<A: App>(runner: &mut Runner<A>, xr_frame: &XrFrame) {
+    let eyes = match &runner.web_xr.presenting {
+        Some(presenting) => match eyes(presenting, xr_frame) {
+            Some(eyes) => eyes,
+            None       => return,
+        },
+        None => return,
+    };
+
+    let size = PhysicalSize::new(
+        eyes.iter().map(|eye| eye.width).sum(),
+        eyes.iter().map(|eye| eye.height).max().unwrap_or(1),
+    );
+
+    if size != runner.ctx.size {
+        resize_canvas(runner, size);
+    }
+
+    let space = match runner.app.xr_scene() {
+        Some(scene) => TrackingSpace::new(scene.xr_camera()),
+        None        => return,
+    };
+
+    if let Some(presenting) = &runner.web_xr.presenting {
+        track_controllers(presenting, xr_frame, &space, &mut runner.input);
+    }
+
+    runner.update();
+    render(runner, &eyes);
+    runner.input.end_frame();
+}
+
+// The headset's views this frame and where each is drawn
+fn eyes(presenting: &Presenting, xr_frame: &XrFrame) -> Option<Vec<Eye>> {
+    let pose  = xr_frame.get_viewer_pose(&presenting.space)?;
+    let layer = presenting.session.render_state().base_layer()?;
+
+    let eyes = pose.views().iter()
+        .filter_map(|view| {
+            let view: XrView = view.dyn_into().ok()?;
+            let viewport     = layer.get_viewport(&view)?;
+
+            // Column major, so the tangents come from the diagonal and the third column
+            let p   = view.projection_matrix();
+            let fov = EyeFov {
+                left:  (1.0 - p[8]) / p[0],
+                right: (1.0 + p[8]) / p[0],
+                up:    (1.0 + p[9]) / p[5],
+                down:  (1.0 - p[9]) / p[5],
+            };
+
+            let (tan_x, tan_y) = fov.symmetric();
+            let per_x          = viewport.width() as f32 / (fov.left + fov.right);
+            let per_y          = viewport.height() as f32 / (fov.up + fov.down);
+            let x              = ((tan_x - fov.left) * per_x).round() as i32;
+            let y              = ((tan_y - fov.down) * per_y).round() as i32;
+
+            Some(Eye {
+                fov,
+                width:    (tan_x * 2.0 * per_x).ceil() as u32,
+                height:   (tan_y * 2.0 * per_y).ceil() as u32,
+                source:   [x, y, x + viewport.width(), y + viewport.height()],
+                viewport: [
+                    viewport.x(),
+                    viewport.y(),
+                    viewport.x() + viewport.width(),
+                    viewport.y() + viewport.height(),
+                ],
+                view,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    (!eyes.is_empty()).then(|| eyes)
+}
+
+fn track_controllers(presenting: &Presenting, xr_frame: &XrFrame, space: &TrackingSpace, input: &mut Input) {
+    let mut controllers = [None, None];
+    let sources         = presenting.session.input_sources();
+
+    for i in 0..sources.length() {
+        let source = match sources.get(i) {
+            Some(source) => source,
+            None         => continue,
+        };
+
+        let hand = match source.handedness() {
+            XrHandedness::Left  => Hand::Left,
+            XrHandedness::Right => Hand::Right,
+            _ => continue,
+        };
+
+        let pose = match source.grip_space().and_then(|grip| xr_frame.get_pose(&grip, &presenting.space)) {
+            Some(pose) => pose,
+            None       => continue,
+        };
+
+        // Laid out by the "xr-standard" gamepad mapping
+        let gamepad = source.gamepad();
+        let button  = |i: u32| gamepad.as_ref()
+            .and_then(|gamepad| gamepad.buttons().get(i).dyn_into::<GamepadButton>().ok())
+            .map_or(0.0, |button| button.value() as f32);
+        let axis    = |i: u32| gamepad.as_ref()
+            .and_then(|gamepad| gamepad.axes().get(i).as_f64())
+            .unwrap_or(0.0) as f32;
+
+        let (position, orientation) = rigid_transform(&pose.transform());
+
+        controllers[hand as usize] = Some(XrController {
+            position:    space.point(position),
+            orientation: space.orientation(orientation),
+            trigger:     button(0),
+            squeeze:     button(1),
+            // The gamepad's up is negative
+            thumbstick:  (axis(2), -axis(3)),
+        });
+    }
+
+    input.set_xr_controller(Hand::Left, controllers[0]);
+    input.set_xr_controller(Hand::Right, controllers[1]);
+}
+
+fn render<A: App>(runner: &mut Runner<A>, eyes: &[Eye]) {
+    let Runner { ctx, app, web_xr, .. } = runner;
+
+    let (presenting, scene) = match (&web_xr.presenting, app.xr_scene()) {
+        (Some(presenting), Some(scene)) => (presenting, scene),
+        _ => return,
+    };
+
+    let origin  = scene.xr_camera().clone();
+    let space   = TrackingSpace::new(&origin);
+    let cameras = eyes.iter()
+        .map(|eye| {
+            let (position, orientation) = rigid_transform(&eye.view.transform());
+
+            stereo::eye_camera(&space, &origin, position, orientation, &eye.fov)
+        })
+        .collect::<Vec<Camera>>();
+
+    let frame = match ctx.begin_frame() {
+        Ok(frame) => frame,
+        Err(e)    => {
+            log::warn!("Skipping a WebXR frame: {:?}", e);
+            return;
+        }
+    };
+
+    let depth_view = frame.depth_view();
+    let mut x      = 0;
+
+    // Submitted an eye at a time, since both draw through the scene's one camera buffer
+    for (i, (eye, camera)) in eyes.iter().zip(&cameras).enumerate() {
+        let mut encoder = ctx.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("WebXR Eye Encoder"),
+        });
+
+        if i == 0 {
+            pass::begin_main_pass(&mut encoder, &frame.view, depth_view, pass::CLEAR_COLOR, ctx.depth_mode);
+        }
+
+        {
+            let mut layers = RenderLayers::new();
+            let viewport   = Viewport {
+                x:      x as f32,
+                y:      0.0,
+                width:  eye.width as f32,
+                height: eye.height as f32,
+            };
+
+            scene.render_view(&ctx.queue, camera, None, &mut layers);
+            layers.execute_viewport(&mut encoder, &frame.view, depth_view, ctx.depth_mode, viewport);
+        }
+
+        ctx.queue.submit(std::iter::once(encoder.finish()));
+        x += eye.width;
+    }
+
+    // Presenting copies the frame into the canvas's own framebuffer, which the blit reads from
+    frame.present();
+
+    if let Some(layer) = presenting.session.render_state().base_layer() {
+        let gl     = &presenting.gl;
+        let height = ctx.size.height as i32;
+        let mut x  = 0;
+
+        gl.bind_framebuffer(Gl::READ_FRAMEBUFFER, None);
+        gl.bind_framebuffer(Gl::DRAW_FRAMEBUFFER, layer.framebuffer().as_ref());
+
+        for eye in eyes {
+            // The eyes are drawn along the top of the canvas, and GL counts up from the bottom
+            let bottom = height - eye.height as i32;
+            let [x0, y0, x1, y1] = eye.source;
+            let [dx0, dy0, dx1, dy1] = eye.viewport;
+
+            gl.blit_framebuffer(
+                x + x0, bottom + y0, x + x1, bottom + y1,
+                dx0, dy0, dx1, dy1,
+                Gl::COLOR_BUFFER_BIT,
+                Gl::LINEAR,
+            );
+            x += eye.width as i32;
+        }
+
+        gl.bind_framebuffer(Gl::FRAMEBUFFER, None);
+    }
+
+    if let [left, right] = cameras.as_slice() {
+        scene.head_moved(&stereo::head_camera(left, right));
+    }
+}
+
+fn rigid_transform(transform: &XrRigidTransform) -> (cgmath::Point3<f32>, cgmath::Quaternion<f32>) {
+    let p = transform.position();
+    let o = transform.orientation();
+
+    (
+        cgmath::Point3::new(p.x() as f32, p.y() as f32, p.z() as f32),
+        cgmath::Quaternion::new(o.w() as f32, o.x() as f32, o.y() as f32, o.z() as f32),
+    )
+}
@@ -0,0 +1,175 @@
+// Immediate-mode lines of any width, for paths, roads, rails and camera tracks. Widths are either
+// in pixels, staying the same on screen at any distance, or in world units, narrowing with
+// distance like the scene around them. Like `DebugDraw`, lines are queued during the frame,
+// uploaded by `prepare` and drawn in the debug layer, hidden behind geometry without hiding
+// anything themselves.
+
+use learn_wgpu_derive::VertexLayout;
+
+use crate::{
+    bind_group,
+    camera::Camera,
+    crash_report,
+    curves::{Curve, Tolerance},
+    pass::{Drawable, RenderLayer},
+    renderer::{self, GpuContext},
+    texture::Texture,
+    uniform::{Uniform, UniformBuffer},
+};
+
+const INITIAL_CAPACITY: usize = 1024;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LineWidth {
+    Pixels(f32),
+    World(f32),
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+#[vertex(instance)]
+struct Segment {
+    #[location(0)]
+    start: [f32; 3],
+    #[location(1)]
+    end:   [f32; 3],
+    #[location(2)]
+    color: [f32; 3],
+    #[location(3)]
+    width: f32,
+    #[location(4)]
+    world: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Uniform)]
+struct LineCamera {
+    view_proj: [[f32; 4]; 4],
+    viewport:  [f32; 2],
+    focal:     f32,
+    near:      f32,
+}
+
+pub struct WideLines {
+    pipeline:       wgpu::RenderPipeline,
+    camera:         UniformBuffer<LineCamera>,
+    bind_group:     wgpu::BindGroup,
+    segment_buffer: wgpu::Buffer,
+    capacity:       usize,
+    segments:       Vec<Segment>,
+    segment_count:  u32,
+}
+
+impl WideLines {
+    pub fn new(ctx: &GpuContext) -> Self {
+        let device = &ctx.device;
+        let camera = UniformBuffer::new(device, "Wide Line Camera Buffer");
+
+        let layout = bind_group::BindGroupLayoutBuilder::new()
+            .uniform(wgpu::ShaderStages::VERTEX)
+            .build(device, "wide_line_bind_group_layout");
+
+        let bind_group = bind_group::BindGroupBuilder::new(&layout)
+            .uniform(camera.buffer())
+            .build(device, "wide_line_bind_group");
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label:  Some("Wide Line Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("wide_lines.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Wide Line Pipeline Layout"),
+            bind_group_layouts:   &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        // Quads face whichever way the segment runs across the screen, so nothing is culled
+        let pipeline = renderer::create_render_pipeline_with_raster(
+            device,
+            &pipeline_layout,
+            ctx.config.format,
+            Some(renderer::depth_state(Texture::DEPTH_FORMAT, ctx.depth_mode.compare(), false)),
+            &[Segment::layout()],
+            &shader,
+            renderer::RasterState { cull_mode: None, ..Default::default() },
+            "Wide Line Pipeline",
+        );
+
+        Self {
+            pipeline,
+            camera,
+            bind_group,
+            segment_buffer: create_segment_buffer(device, INITIAL_CAPACITY),
+            capacity:       INITIAL_CAPACITY,
+            segments:       Vec::new(),
+            segment_count:  0,
+        }
+    }
+
+    pub fn line(&mut self, a: cgmath::Point3<f32>, b: cgmath::Point3<f32>, width: LineWidth, color: [f32; 3]) {
+        let (width, world) = match width {
+            LineWidth::Pixels(width) => (width, 0),
+            LineWidth::World(width)  => (width, 1),
+        };
+
+        self.segments.push(Segment { start: a.into(), end: b.into(), color, width, world });
+    }
+
+    // Joins each point to the next
+    pub fn polyline(&mut self, points: &[cgmath::Point3<f32>], width: LineWidth, color: [f32; 3]) {
+        for pair in points.windows(2) {
+            self.line(pair[0], pair[1], width, color);
+        }
+    }
+
+    // `curve` flattened to within `tolerance`, see curves.rs
+    pub fn curve(&mut self, curve: &Curve, tolerance: &Tolerance, width: LineWidth, color: [f32; 3]) {
+        self.polyline(&curve.flatten(tolerance), width, color);
+    }
+
+    // Uploads the lines queued since the last call as seen by `camera`, growing the buffer if needed
+    pub fn prepare(&mut self, ctx: &GpuContext, camera: &Camera) {
+        let (width, height) = (ctx.scene_target.width as f32, ctx.scene_target.height as f32);
+        let tan_half_fovy   = (cgmath::Rad::from(cgmath::Deg(camera.fovy)).0 / 2.0).tan();
+
+        self.camera.write(&ctx.queue, &LineCamera {
+            view_proj: camera.build_view_projections_matrix().into(),
+            viewport:  [width, height],
+            focal:     height * 0.5 / tan_half_fovy,
+            near:      camera.znear,
+        });
+
+        if self.segments.len() > self.capacity {
+            self.capacity       = self.segments.len().next_power_of_two();
+            self.segment_buffer = create_segment_buffer(&ctx.device, self.capacity);
+        }
+
+        ctx.queue.write_buffer(&self.segment_buffer, 0, bytemuck::cast_slice(&self.segments));
+
+        self.segment_count = self.segments.len() as u32;
+        self.segments.clear();
+    }
+}
+
+fn create_segment_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label:              Some("Wide Line Segment Buffer"),
+        size:               (capacity * std::mem::size_of::<Segment>()) as wgpu::BufferAddress,
+        usage:              wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+impl Drawable for WideLines {
+    fn draw<'a>(&'a self, layer: RenderLayer, render_pass: &mut wgpu::RenderPass<'a>) {
+        if layer != RenderLayer::Debug || self.segment_count == 0 {
+            return;
+        }
+
+        crash_report::set_pipeline(render_pass, &self.pipeline, "Wide Line Pipeline");
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.segment_buffer.slice(..));
+        render_pass.draw(0..6, 0..self.segment_count);
+    }
+}
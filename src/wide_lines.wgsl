@@ -0,0 +1,92 @@
+// Lines wider than a pixel, see wide_lines.rs. Each segment is a quad made up in the vertex shader,
+// pushed out sideways from the segment as it lands on screen and past its ends by the same
+// amount, so neighbouring segments of a polyline overlap at their joins.
+
+struct LineCamera {
+    view_proj: mat4x4<f32>,
+    viewport:  vec2<f32>,
+    // Pixels a world unit covers one unit in front of the camera
+    focal:     f32,
+    near:      f32,
+}
+
+@group(0) @binding(0)
+var<uniform> camera: LineCamera;
+
+struct SegmentInput {
+    @location(0) start: vec3<f32>,
+    @location(1) end:   vec3<f32>,
+    @location(2) color: vec3<f32>,
+    @location(3) width: f32,
+    // 1 when `width` is in world units rather than pixels
+    @location(4) world: u32,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color:               vec3<f32>,
+}
+
+// Half the line's width at a point `w` in front of the camera, in pixels
+fn half_width(segment: SegmentInput, w: f32) -> f32 {
+    if (segment.world != 0u) {
+        return segment.width * 0.5 * camera.focal / w;
+    }
+
+    return segment.width * 0.5;
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, segment: SegmentInput) -> VertexOutput {
+    var a = camera.view_proj * vec4<f32>(segment.start, 1.0);
+    var b = camera.view_proj * vec4<f32>(segment.end, 1.0);
+
+    var out: VertexOutput;
+
+    out.color = segment.color;
+
+    // Entirely behind the camera, so collapsed to nothing
+    if (a.w < camera.near && b.w < camera.near) {
+        out.clip_position = vec4<f32>(0.0, 0.0, 0.0, 1.0);
+        return out;
+    }
+
+    // Cut back to the near plane, past which the screen positions would flip
+    if (a.w < camera.near) {
+        a = mix(a, b, (camera.near - a.w) / (b.w - a.w));
+    }
+    if (b.w < camera.near) {
+        b = mix(b, a, (camera.near - b.w) / (a.w - b.w));
+    }
+
+    let half_viewport = camera.viewport * 0.5;
+    let screen_a      = a.xy / a.w * half_viewport;
+    let screen_b      = b.xy / b.w * half_viewport;
+    let along         = screen_b - screen_a;
+    let direction     = select(vec2<f32>(1.0, 0.0), along / length(along), length(along) > 0.0001);
+    let across        = vec2<f32>(-direction.y, direction.x);
+
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0,  1.0),
+        vec2<f32>(0.0, -1.0),
+        vec2<f32>(1.0,  1.0),
+        vec2<f32>(0.0,  1.0),
+    );
+
+    // Which end, and which side of the line
+    let corner = corners[index];
+    let end    = select(a, b, corner.x > 0.5);
+    let half   = half_width(segment, end.w);
+    let offset = (across * corner.y + direction * (corner.x * 2.0 - 1.0)) * half;
+
+    out.clip_position = end + vec4<f32>(offset / half_viewport * end.w, 0.0, 0.0);
+
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(in.color, 1.0);
+}
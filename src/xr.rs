@@ -0,0 +1,506 @@
+// Stereo rendering to a headset through OpenXR. The runtime has to create the Vulkan instance and
+// device itself, so with `RendererOptions::vr` the GPU context is built on the ones it makes, and
+// wgpu is handed them through wgpu-hal. The window keeps showing the app as usual, as a mirror.
+//
+// Each frame the headset's pose places one camera per eye relative to the app's own camera, and
+// the app's `Scene` is drawn from each into a layer of a two layer swapchain texture, which goes
+// back to the runtime for display. Every pipeline in the renderer is built for a single view, so
+// the eyes are separate passes rather than one multiview pass, and post effects aren't applied.
+//
+// Each eye is drawn with the symmetric field of view around the runtime's (see stereo.rs) and the
+// compositor is told that's what was drawn. It fits the image to the display, at the cost of a few
+// pixels outside what the eye can see.
+
+use std::ffi::{c_void, CString};
+
+use anyhow::{anyhow, bail, Context, Result};
+use ash::vk::{self, Handle};
+use openxr as xr;
+use wgpu_hal::api::Vulkan;
+use winit::window::Window;
+
+use crate::{
+    app::App,
+    camera::Camera,
+    pass::{self, RenderLayers},
+    renderer::{GpuContext, RendererOptions},
+    scene::Scene,
+    stereo::{self, EyeFov, TrackingSpace},
+    texture::Texture,
+};
+
+const VIEW_TYPE:   xr::ViewConfigurationType = xr::ViewConfigurationType::PRIMARY_STEREO;
+const VIEW_COUNT:  u32 = 2;
+// wgpu needs at least Vulkan 1.1
+const VK_VERSION:  u32 = vk::API_VERSION_1_1;
+
+// Builds the GPU context on an OpenXR runtime's device when `options.vr` is set and one's there,
+// and the usual way otherwise
+pub async fn create_context(window: Window, options: RendererOptions) -> (GpuContext, Option<XrSession>) {
+    if !options.vr {
+        return (GpuContext::new(window, options).await, None);
+    }
+
+    let gpu = match XrGpu::new() {
+        Ok(gpu) => gpu,
+        Err(e)  => {
+            log::warn!("Not rendering to a headset: {:#}", e);
+            return (GpuContext::new(window, options).await, None);
+        }
+    };
+
+    // # Safety
+    //
+    // The surface needs to live as long as the window that created it. GpuContext owns the window
+    // so this should be safe.
+    let surface = unsafe { gpu.instance.create_surface(&window) };
+    let ctx     = GpuContext::from_parts(window, surface, &gpu.adapter, gpu.device, gpu.queue, options);
+
+    match XrSession::new(&ctx, gpu.xr) {
+        Ok(session) => (ctx, Some(session)),
+        Err(e)      => {
+            log::warn!("Couldn't start an OpenXR session: {:#}", e);
+            (ctx, None)
+        }
+    }
+}
+
+// The OpenXR instance and the Vulkan objects it made, before there's a session
+struct XrVulkan {
+    instance:           xr::Instance,
+    system:             xr::SystemId,
+    blend_mode:         xr::EnvironmentBlendMode,
+    vk_instance:        ash::Instance,
+    vk_physical_device: vk::PhysicalDevice,
+    vk_device:          vk::Device,
+    queue_family:       u32,
+}
+
+struct XrGpu {
+    xr:       XrVulkan,
+    instance: wgpu::Instance,
+    adapter:  wgpu::Adapter,
+    device:   wgpu::Device,
+    queue:    wgpu::Queue,
+}
+
+impl XrGpu {
+    fn new() -> Result<Self> {
+        let entry = unsafe { xr::Entry::load() }.context("Couldn't load the OpenXR loader")?;
+
+        if !entry.enumerate_extensions()?.khr_vulkan_enable2 {
+            bail!("The OpenXR runtime doesn't support Vulkan");
+        }
+
+        let mut extensions = xr::ExtensionSet::default();
+        extensions.khr_vulkan_enable2 = true;
+
+        let instance = entry.create_instance(
+            &xr::ApplicationInfo {
+                application_name:    "learn_wgpu",
+                application_version: 0,
+                engine_name:         "learn_wgpu",
+                engine_version:      0,
+            },
+            &extensions,
+            &[],
+        )?;
+
+        let system     = instance.system(xr::FormFactor::HEAD_MOUNTED_DISPLAY).context("There's no headset")?;
+        let blend_mode = instance.enumerate_environment_blend_modes(system, VIEW_TYPE)?[0];
+
+        // Has to be asked before the runtime makes a Vulkan instance
+        let requirements = instance.graphics_requirements::<xr::Vulkan>(system)?;
+        let version      = xr::Version::new(vk::api_version_major(VK_VERSION) as u16, vk::api_version_minor(VK_VERSION) as u16, 0);
+
+        if version < requirements.min_api_version_supported || version.major() > requirements.max_api_version_supported.major() {
+            bail!("The OpenXR runtime needs Vulkan {} to {}", requirements.min_api_version_supported, requirements.max_api_version_supported);
+        }
+
+        let vk_entry   = unsafe { ash::Entry::load() }?;
+        let flags      = wgpu_hal::InstanceFlags::empty();
+        let extensions = wgpu_hal::vulkan::Instance::required_extensions(&vk_entry, VK_VERSION, flags)?;
+
+        let vk_instance = unsafe {
+            let names    = extensions.iter().map(|e| e.as_ptr()).collect::<Vec<_>>();
+            let app_name = CString::new("learn_wgpu")?;
+            let app_info = vk::ApplicationInfo::builder()
+                .application_name(&app_name)
+                .engine_name(&app_name)
+                .api_version(VK_VERSION);
+            let info     = vk::InstanceCreateInfo::builder()
+                .application_info(&app_info)
+                .enabled_extension_names(&names);
+
+            let raw = instance.create_vulkan_instance(
+                system,
+                std::mem::transmute(vk_entry.static_fn().get_instance_proc_addr),
+                &*info as *const _ as *const _,
+            )?.map_err(vk::Result::from_raw)?;
+
+            ash::Instance::load(vk_entry.static_fn(), vk::Instance::from_raw(raw as _))
+        };
+
+        let vk_physical_device = vk::PhysicalDevice::from_raw(
+            unsafe { instance.vulkan_graphics_device(system, vk_instance.handle().as_raw() as _) }? as _
+        );
+
+        let hal_instance = unsafe {
+            wgpu_hal::vulkan::Instance::from_raw(vk_entry.clone(), vk_instance.clone(), VK_VERSION, 0, extensions, flags, false, None)
+        }?;
+        let exposed = hal_instance.expose_adapter(vk_physical_device)
+            .ok_or_else(|| anyhow!("wgpu can't use the headset's GPU"))?;
+
+        let queue_family = unsafe { vk_instance.get_physical_device_queue_family_properties(vk_physical_device) }
+            .iter()
+            .position(|family| family.queue_flags.contains(vk::QueueFlags::GRAPHICS))
+            .ok_or_else(|| anyhow!("The headset's GPU has no graphics queue"))? as u32;
+
+        let features    = wgpu::Features::empty();
+        let uab_types   = wgpu_hal::vulkan::UpdateAfterBindTypes::empty();
+        let device_exts = exposed.adapter.required_device_extensions(features);
+
+        let (open_device, vk_device) = unsafe {
+            let names        = device_exts.iter().map(|e| e.as_ptr()).collect::<Vec<_>>();
+            let mut physical = exposed.adapter.physical_device_features(&device_exts, features, uab_types);
+            let queues       = [vk::DeviceQueueCreateInfo::builder()
+                .queue_family_index(queue_family)
+                .queue_priorities(&[1.0])
+                .build()];
+            let info         = physical.add_to_device_create_builder(
+                vk::DeviceCreateInfo::builder()
+                    .queue_create_infos(&queues)
+                    .enabled_extension_names(&names),
+            );
+
+            let raw = instance.create_vulkan_device(
+                system,
+                std::mem::transmute(vk_entry.static_fn().get_instance_proc_addr),
+                vk_physical_device.as_raw() as _,
+                &*info as *const _ as *const _,
+            )?.map_err(vk::Result::from_raw)?;
+
+            let vk_device   = ash::Device::load(vk_instance.fp_v1_0(), vk::Device::from_raw(raw as _));
+            let handle      = vk_device.handle();
+            let open_device = exposed.adapter.device_from_raw(vk_device, true, &device_exts, features, uab_types, queue_family, 0)?;
+
+            (open_device, handle)
+        };
+
+        let wgpu_instance     = unsafe { wgpu::Instance::from_hal::<Vulkan>(hal_instance) };
+        let adapter           = unsafe { wgpu_instance.create_adapter_from_hal(exposed) };
+        let (device, queue)   = unsafe {
+            adapter.create_device_from_hal(
+                open_device,
+                &wgpu::DeviceDescriptor {
+                    features,
+                    limits:   wgpu::Limits::default(),
+                    label:    Some("XR Device"),
+                },
+                None,
+            )
+        }?;
+
+        log::info!("Rendering to {} through {}", instance.system_properties(system)?.system_name, instance.properties()?.runtime_name);
+
+        Ok(Self {
+            xr: XrVulkan {
+                instance,
+                system,
+                blend_mode,
+                vk_instance,
+                vk_physical_device,
+                vk_device,
+                queue_family,
+            },
+            instance: wgpu_instance,
+            adapter,
+            device,
+            queue,
+        })
+    }
+}
+
+// One view of the swapchain's image for each eye
+struct SwapchainImage {
+    eyes: Vec<wgpu::TextureView>,
+    // Keeps the wrapped image alive, the runtime owns the memory
+    _texture: wgpu::Texture,
+}
+
+pub struct XrSession {
+    instance:   xr::Instance,
+    blend_mode: xr::EnvironmentBlendMode,
+    session:    xr::Session<xr::Vulkan>,
+    waiter:     xr::FrameWaiter,
+    stream:     xr::FrameStream<xr::Vulkan>,
+    stage:      xr::Space,
+    swapchain:  xr::Swapchain<xr::Vulkan>,
+    images:     Vec<SwapchainImage>,
+    // Layers of a depth texture array, one per eye
+    depth:      Vec<wgpu::TextureView>,
+    size:       (u32, u32),
+    events:     xr::EventDataBuffer,
+    // Between the runtime saying it's ready and saying it's stopping
+    running:    bool,
+}
+
+impl XrSession {
+    fn new(ctx: &GpuContext, xr: XrVulkan) -> Result<Self> {
+        let (session, waiter, stream) = unsafe {
+            xr.instance.create_session::<xr::Vulkan>(xr.system, &xr::vulkan::SessionCreateInfo {
+                instance:           xr.vk_instance.handle().as_raw() as *const c_void,
+                physical_device:    xr.vk_physical_device.as_raw() as *const c_void,
+                device:             xr.vk_device.as_raw() as *const c_void,
+                queue_family_index: xr.queue_family,
+                queue_index:        0,
+            })
+        }?;
+
+        let stage = session.create_reference_space(xr::ReferenceSpaceType::STAGE, xr::Posef::IDENTITY)
+            .or_else(|_| session.create_reference_space(xr::ReferenceSpaceType::LOCAL, xr::Posef::IDENTITY))?;
+
+        let views  = xr.instance.enumerate_view_configuration_views(xr.system, VIEW_TYPE)?;
+        let width  = views[0].recommended_image_rect_width;
+        let height = views[0].recommended_image_rect_height;
+
+        // The scene's pipelines are built for the surface format, so the swapchain has to match it
+        let format = vk_format(ctx.config.format)
+            .filter(|format| session.enumerate_swapchain_formats().map_or(false, |formats| formats.contains(&(format.as_raw() as u32))))
+            .ok_or_else(|| anyhow!("The OpenXR runtime can't display {:?}", ctx.config.format))?;
+
+        let swapchain = session.create_swapchain(&xr::SwapchainCreateInfo {
+            create_flags: xr::SwapchainCreateFlags::EMPTY,
+            usage_flags:  xr::SwapchainUsageFlags::COLOR_ATTACHMENT | xr::SwapchainUsageFlags::SAMPLED,
+            format:       format.as_raw() as u32,
+            sample_count: 1,
+            width,
+            height,
+            face_count:   1,
+            array_size:   VIEW_COUNT,
+            mip_count:    1,
+        })?;
+
+        let size = wgpu::Extent3d { width, height, depth_or_array_layers: VIEW_COUNT };
+
+        let images = swapchain.enumerate_images()?
+            .into_iter()
+            .map(|image| {
+                let texture = unsafe {
+                    let raw = wgpu_hal::vulkan::Device::texture_from_raw(
+                        vk::Image::from_raw(image),
+                        &wgpu_hal::TextureDescriptor {
+                            label:           Some("XR Swapchain Image"),
+                            size,
+                            mip_level_count: 1,
+                            sample_count:    1,
+                            dimension:       wgpu::TextureDimension::D2,
+                            format:          ctx.config.format,
+                            usage:           wgpu_hal::TextureUses::COLOR_TARGET,
+                            memory_flags:    wgpu_hal::MemoryFlags::empty(),
+                        },
+                        None,
+                    );
+
+                    ctx.device.create_texture_from_hal::<Vulkan>(raw, &wgpu::TextureDescriptor {
+                        label:           Some("XR Swapchain Image"),
+                        size,
+                        mip_level_count: 1,
+                        sample_count:    1,
+                        dimension:       wgpu::TextureDimension::D2,
+                        format:          ctx.config.format,
+                        usage:           wgpu::TextureUsages::RENDER_ATTACHMENT,
+                    })
+                };
+
+                SwapchainImage {
+                    eyes:     eye_views(&texture),
+                    _texture: texture,
+                }
+            })
+            .collect();
+
+        let depth = ctx.device.create_texture(&wgpu::TextureDescriptor {
+            label:           Some("XR Depth Texture"),
+            size,
+            mip_level_count: 1,
+            sample_count:    1,
+            dimension:       wgpu::TextureDimension::D2,
+            format:          Texture::DEPTH_FORMAT,
+            usage:           wgpu::TextureUsages::RENDER_ATTACHMENT,
+        });
+
+        log::info!("Rendering {}x{} per eye", width, height);
+
+        Ok(Self {
+            instance:   xr.instance,
+            blend_mode: xr.blend_mode,
+            session,
+            waiter,
+            stream,
+            stage,
+            swapchain,
+            images,
+            depth:      eye_views(&depth),
+            size:       (width, height),
+            events:     xr::EventDataBuffer::new(),
+            running:    false,
+        })
+    }
+
+    // Handles the runtime's events and, while the session runs, draws `app`'s scene for both eyes.
+    // Returns false once the runtime wants the app to quit.
+    pub fn update<A: App>(&mut self, ctx: &GpuContext, app: &mut A) -> bool {
+        match self.try_update(ctx, app) {
+            Ok(keep_running) => keep_running,
+            Err(e) => {
+                log::error!("OpenXR frame failed: {:#}", e);
+                true
+            }
+        }
+    }
+
+    fn try_update<A: App>(&mut self, ctx: &GpuContext, app: &mut A) -> Result<bool> {
+        while let Some(event) = self.instance.poll_event(&mut self.events)? {
+            match event {
+                xr::Event::SessionStateChanged(change) => match change.state() {
+                    xr::SessionState::READY => {
+                        self.session.begin(VIEW_TYPE)?;
+                        self.running = true;
+                    }
+                    xr::SessionState::STOPPING => {
+                        self.session.end()?;
+                        self.running = false;
+                    }
+                    xr::SessionState::EXITING | xr::SessionState::LOSS_PENDING => return Ok(false),
+                    _ => {}
+                },
+                xr::Event::InstanceLossPending(_) => return Ok(false),
+                _ => {}
+            }
+        }
+
+        if !self.running {
+            return Ok(true);
+        }
+
+        // Blocks until the runtime wants the next frame, which paces the loop to the headset
+        let state = self.waiter.wait()?;
+        self.stream.begin()?;
+
+        let scene = match app.xr_scene() {
+            Some(scene) if state.should_render => scene,
+            _ => {
+                self.stream.end(state.predicted_display_time, self.blend_mode, &[])?;
+                return Ok(true);
+            }
+        };
+
+        let (_, views) = self.session.locate_views(VIEW_TYPE, state.predicted_display_time, &self.stage)?;
+
+        let image = self.swapchain.acquire_image()? as usize;
+        self.swapchain.wait_image(xr::Duration::INFINITE)?;
+
+        let origin  = scene.xr_camera().clone();
+        let space   = TrackingSpace::new(&origin);
+        let cameras = views.iter().map(|view| eye_camera(&space, &origin, view)).collect::<Vec<_>>();
+
+        // Submitted an eye at a time, since both draw through the scene's one camera buffer
+        for (eye, (camera, _)) in cameras.iter().enumerate() {
+            let mut encoder = ctx.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("XR Eye Encoder"),
+            });
+
+            {
+                let mut layers = RenderLayers::new();
+
+                scene.render_view(&ctx.queue, camera, None, &mut layers);
+                layers.execute(&mut encoder, &self.images[image].eyes[eye], &self.depth[eye], pass::CLEAR_COLOR, ctx.depth_mode);
+            }
+
+            ctx.queue.submit(std::iter::once(encoder.finish()));
+        }
+
+        self.swapchain.release_image()?;
+
+        scene.head_moved(&stereo::head_camera(&cameras[0].0, &cameras[1].0));
+
+        let rect = xr::Rect2Di {
+            offset: xr::Offset2Di { x: 0, y: 0 },
+            extent: xr::Extent2Di { width: self.size.0 as i32, height: self.size.1 as i32 },
+        };
+
+        let projection_views = views.iter().zip(&cameras).enumerate()
+            .map(|(eye, (view, (_, fov)))| {
+                xr::CompositionLayerProjectionView::new()
+                    .pose(view.pose)
+                    .fov(*fov)
+                    .sub_image(xr::SwapchainSubImage::new()
+                        .swapchain(&self.swapchain)
+                        .image_array_index(eye as u32)
+                        .image_rect(rect))
+            })
+            .collect::<Vec<_>>();
+
+        self.stream.end(
+            state.predicted_display_time,
+            self.blend_mode,
+            &[&xr::CompositionLayerProjection::new().space(&self.stage).views(&projection_views)],
+        )?;
+
+        Ok(true)
+    }
+}
+
+// A view of each layer of a texture array, for drawing one eye at a time
+fn eye_views(texture: &wgpu::Texture) -> Vec<wgpu::TextureView> {
+    (0..VIEW_COUNT)
+        .map(|eye| texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension:         Some(wgpu::TextureViewDimension::D2),
+            base_array_layer:  eye,
+            array_layer_count: std::num::NonZeroU32::new(1),
+            ..Default::default()
+        }))
+        .collect()
+}
+
+// The camera for an eye the runtime placed at `view`, and the field of view that camera actually
+// covers
+fn eye_camera(space: &TrackingSpace, origin: &Camera, view: &xr::View) -> (Camera, xr::Fovf) {
+    let o   = view.pose.orientation;
+    let p   = view.pose.position;
+    let fov = EyeFov {
+        left:  (-view.fov.angle_left).tan(),
+        right: view.fov.angle_right.tan(),
+        up:    view.fov.angle_up.tan(),
+        down:  (-view.fov.angle_down).tan(),
+    };
+
+    let camera = stereo::eye_camera(
+        space,
+        origin,
+        cgmath::Point3::new(p.x, p.y, p.z),
+        cgmath::Quaternion::new(o.w, o.x, o.y, o.z),
+        &fov,
+    );
+
+    let (tan_x, tan_y) = fov.symmetric();
+    let fov            = xr::Fovf {
+        angle_left:  -tan_x.atan(),
+        angle_right: tan_x.atan(),
+        angle_up:    tan_y.atan(),
+        angle_down:  -tan_y.atan(),
+    };
+
+    (camera, fov)
+}
+
+fn vk_format(format: wgpu::TextureFormat) -> Option<vk::Format> {
+    match format {
+        wgpu::TextureFormat::Bgra8UnormSrgb => Some(vk::Format::B8G8R8A8_SRGB),
+        wgpu::TextureFormat::Bgra8Unorm     => Some(vk::Format::B8G8R8A8_UNORM),
+        wgpu::TextureFormat::Rgba8UnormSrgb => Some(vk::Format::R8G8B8A8_SRGB),
+        wgpu::TextureFormat::Rgba8Unorm     => Some(vk::Format::R8G8B8A8_UNORM),
+        _ => None,
+    }
+}